@@ -0,0 +1,34 @@
+#!/bin/bash
+## on push branch~=gh-readonly-queue/main/.*
+## on pull_request
+
+set -euo pipefail
+
+export RUSTUP_HOME=/ci/cache/rustup
+export CARGO_HOME=/ci/cache/cargo
+export CARGO_TARGET_DIR=/ci/cache/target
+
+# The interrupt handlers of the embassy-nrf UARTE, SPIM and WDT drivers are shared with the
+# replay tests, which run them on a fake register block. On hardware they must stay inlined into
+# the interrupt vectors with direct register access, so no out-of-line copy may be left.
+cd examples/nrf52840
+cargo build --release --bin uart --bin spim --bin wdt_callback
+
+out=$CARGO_TARGET_DIR/thumbv7em-none-eabi/release
+check() {
+    local bin=$1 vector=$2
+    local symbols
+    symbols=$(nm "$out/$bin")
+    # The handler must be bound, or the check below would pass vacuously.
+    if [[ $(grep -w "$vector" <<< "$symbols" | cut -d' ' -f1) == $(grep -w DefaultHandler <<< "$symbols" | cut -d' ' -f1) ]]; then
+        echo "$bin: no handler bound to $vector"
+        exit 1
+    fi
+    if grep -E 'embassy_nrf(5uarte|4spim|3wdt)12on_interrupt' <<< "$symbols"; then
+        echo "$bin: an embassy-nrf interrupt handler isn't inlined"
+        exit 1
+    fi
+}
+check uart UARTE0
+check spim SPIM3
+check wdt_callback WDT
@@ -13,9 +13,11 @@ export CARGO_TARGET_DIR=/ci/cache/target
 export CARGO_NET_GIT_FETCH_WITH_CLI=true
 
 cargo test --manifest-path ./embassy-executor/Cargo.toml --features metadata-name
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features trace-stats --test trace_stats
 cargo test --manifest-path ./embassy-futures/Cargo.toml
 cargo test --manifest-path ./embassy-sync/Cargo.toml
 cargo test --manifest-path ./embassy-embedded-hal/Cargo.toml
+cargo test --manifest-path ./embassy-embedded-hal/Cargo.toml --features time
 cargo test --manifest-path ./embassy-hal-internal/Cargo.toml
 cargo test --manifest-path ./embassy-time/Cargo.toml --features mock-driver,embassy-time-queue-utils/generic-queue-8
 cargo test --manifest-path ./embassy-time-driver/Cargo.toml
@@ -24,6 +26,9 @@ cargo test --manifest-path ./embassy-boot/Cargo.toml
 cargo test --manifest-path ./embassy-boot/Cargo.toml --features ed25519-dalek
 cargo test --manifest-path ./embassy-boot/Cargo.toml --features ed25519-salty
 
+cargo test --manifest-path ./embassy-usb/Cargo.toml --features msc
+cargo test --manifest-path ./embassy-usb-dfu/Cargo.toml --features dfu
+
 cargo test --manifest-path ./embassy-nrf/Cargo.toml --no-default-features --features nrf52840,time-driver-rtc1,gpiote
 
 cargo test --manifest-path ./embassy-rp/Cargo.toml --no-default-features --features time-driver,rp2040,_test
@@ -35,4 +40,5 @@ cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --fe
 cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --features stm32f769ni,time-driver-any,exti,single-bank
 cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --features stm32f769ni,time-driver-any,exti,dual-bank
 
+cargo test --manifest-path ./embassy-net/Cargo.toml --features dhcpv4-server,proto-ipv4,medium-ethernet
 cargo test --manifest-path ./embassy-net-adin1110/Cargo.toml
@@ -4,16 +4,21 @@
 #![doc = include_str!("../README.md")]
 mod fmt;
 
+use core::future::Future;
+
 pub use embassy_boot::{
-    AlignedBuffer, BlockingFirmwareState, BlockingFirmwareUpdater, BootError, BootLoaderConfig, FirmwareState,
-    FirmwareUpdater, FirmwareUpdaterConfig,
+    AlignedBuffer, BlockingFirmwareState, BlockingFirmwareUpdater, BootError, BootInfo, BootLoaderConfig,
+    FirmwareState, FirmwareUpdater, FirmwareUpdaterConfig, FirmwareUpdaterError, SelfTestGate, SelfTestOutcome, State,
+    SwapProgress,
 };
 use embassy_nrf::nvmc::PAGE_SIZE;
 use embassy_nrf::{Peri, wdt};
 use embedded_storage::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
 
 /// A bootloader for nRF devices.
-pub struct BootLoader<const BUFFER_SIZE: usize = PAGE_SIZE>;
+pub struct BootLoader<const BUFFER_SIZE: usize = PAGE_SIZE> {
+    info: BootInfo,
+}
 
 impl<const BUFFER_SIZE: usize> BootLoader<BUFFER_SIZE> {
     /// Inspect the bootloader state and perform actions required before booting, such as swapping firmware
@@ -35,8 +40,24 @@ impl<const BUFFER_SIZE: usize> BootLoader<BUFFER_SIZE> {
     ) -> Result<Self, BootError> {
         let mut aligned_buf = AlignedBuffer([0; BUFFER_SIZE]);
         let mut boot = embassy_boot::BootLoader::new(config);
-        let _state = boot.prepare_boot(aligned_buf.as_mut())?;
-        Ok(Self)
+        let state = boot.prepare_boot(aligned_buf.as_mut())?;
+        Ok(Self {
+            // The bootloader version and active offset are given to `Self::boot_info`.
+            info: boot.boot_info(state, 0, 0),
+        })
+    }
+
+    /// Get the [`BootInfo`] block describing this boot, to be handed over to the application
+    /// with [`BootInfo::store`].
+    ///
+    /// `bootloader_version` is the version of the bootloader binary, and `active_offset` the
+    /// address of the active partition, which is also the address given to [`BootLoader::load`].
+    pub fn boot_info(&self, bootloader_version: u32, active_offset: u32) -> BootInfo {
+        BootInfo {
+            bootloader_version,
+            active_offset,
+            ..self.info
+        }
     }
 
     /// Boots the application without softdevice mechanisms.
@@ -106,15 +127,31 @@ impl<const BUFFER_SIZE: usize> BootLoader<BUFFER_SIZE> {
 }
 
 /// A flash implementation that wraps any flash and will pet a watchdog when touching flash.
-pub struct WatchdogFlash<FLASH> {
+///
+/// `N` is the number of watchdog handles, all of which are pet.
+pub struct WatchdogFlash<FLASH, const N: usize = 1> {
     flash: FLASH,
-    wdt: wdt::WatchdogHandle,
+    wdt: [wdt::WatchdogHandle; N],
 }
 
 impl<FLASH> WatchdogFlash<FLASH> {
     /// Start a new watchdog with a given flash and WDT peripheral and a timeout
     pub fn start(flash: FLASH, wdt: Peri<'static, impl wdt::Instance>, config: wdt::Config) -> Self {
-        let (_wdt, [wdt]) = match wdt::Watchdog::try_new(wdt, config) {
+        Self::start_inner(flash, wdt::Watchdog::try_new(wdt, config))
+    }
+}
+
+impl<FLASH, const N: usize> WatchdogFlash<FLASH, N> {
+    /// Start a new watchdog with a given flash and WDT peripheral and a configuration shared
+    /// with the application.
+    ///
+    /// `N` must be the handle count of `shared`, see [`wdt::SharedConfig`].
+    pub fn start_shared(flash: FLASH, wdt: Peri<'static, impl wdt::Instance>, shared: &wdt::SharedConfig) -> Self {
+        Self::start_inner(flash, wdt::Watchdog::try_new_shared(wdt, shared))
+    }
+
+    fn start_inner<T>(flash: FLASH, wdt: Result<(wdt::Watchdog, [wdt::WatchdogHandle; N]), T>) -> Self {
+        let (_wdt, wdt) = match wdt {
             Ok(x) => x,
             Err(_) => {
                 // In case the watchdog is already running, just spin and let it expire, since
@@ -126,33 +163,246 @@ impl<FLASH> WatchdogFlash<FLASH> {
         };
         Self { flash, wdt }
     }
+
+    fn pet(&mut self) {
+        for wdt in &mut self.wdt {
+            wdt.pet();
+        }
+    }
 }
 
-impl<FLASH: ErrorType> ErrorType for WatchdogFlash<FLASH> {
+impl<FLASH: ErrorType, const N: usize> ErrorType for WatchdogFlash<FLASH, N> {
     type Error = FLASH::Error;
 }
 
-impl<FLASH: NorFlash> NorFlash for WatchdogFlash<FLASH> {
+impl<FLASH: NorFlash, const N: usize> NorFlash for WatchdogFlash<FLASH, N> {
     const WRITE_SIZE: usize = FLASH::WRITE_SIZE;
     const ERASE_SIZE: usize = FLASH::ERASE_SIZE;
 
     fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
-        self.wdt.pet();
+        self.pet();
         self.flash.erase(from, to)
     }
     fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error> {
-        self.wdt.pet();
+        self.pet();
         self.flash.write(offset, data)
     }
 }
 
-impl<FLASH: ReadNorFlash> ReadNorFlash for WatchdogFlash<FLASH> {
+impl<FLASH: ReadNorFlash, const N: usize> ReadNorFlash for WatchdogFlash<FLASH, N> {
     const READ_SIZE: usize = FLASH::READ_SIZE;
     fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), Self::Error> {
-        self.wdt.pet();
+        self.pet();
         self.flash.read(offset, data)
     }
     fn capacity(&self) -> usize {
         self.flash.capacity()
     }
 }
+
+/// A flash implementation that wraps any flash and pets a watchdog that is already running.
+///
+/// Unlike [`WatchdogFlash`], this never starts the watchdog: it keeps alive a watchdog started
+/// by the previous firmware, which can't be stopped, for example while the bootloader swaps a
+/// large image. Erases are split into single pages, petting the watchdog before each.
+pub struct RunningWatchdogFlash<FLASH> {
+    flash: FLASH,
+    wdt: Option<wdt::WatchdogHandle>,
+}
+
+impl<FLASH> RunningWatchdogFlash<FLASH> {
+    /// Wrap a flash, petting `handle` on every access.
+    pub fn new(flash: FLASH, handle: wdt::WatchdogHandle) -> Self {
+        Self {
+            flash,
+            wdt: Some(handle),
+        }
+    }
+
+    /// Wrap a flash, petting the first handle of `wdt` on every access if it is running.
+    ///
+    /// If the watchdog is not running, the flash is used as is. The other handles are not pet,
+    /// so this is meant for a watchdog with a single handle.
+    pub fn steal<T: wdt::Instance>(flash: FLASH, _wdt: &Peri<'_, T>) -> Self {
+        // Safety: a running watchdog has at least one handle.
+        let wdt = wdt::is_running::<T>().then(|| unsafe { wdt::WatchdogHandle::steal::<T>(0) });
+        Self { flash, wdt }
+    }
+
+    /// Whether a watchdog is pet on every access.
+    pub fn is_petting(&self) -> bool {
+        self.wdt.is_some()
+    }
+
+    fn pet(&mut self) {
+        if let Some(wdt) = &mut self.wdt {
+            wdt.pet();
+        }
+    }
+}
+
+impl<FLASH: ErrorType> ErrorType for RunningWatchdogFlash<FLASH> {
+    type Error = FLASH::Error;
+}
+
+impl<FLASH: NorFlash> NorFlash for RunningWatchdogFlash<FLASH> {
+    const WRITE_SIZE: usize = FLASH::WRITE_SIZE;
+    const ERASE_SIZE: usize = FLASH::ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let mut from = from;
+        while from < to {
+            let end = to.min(from + FLASH::ERASE_SIZE as u32);
+            self.pet();
+            self.flash.erase(from, end)?;
+            from = end;
+        }
+        Ok(())
+    }
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error> {
+        self.pet();
+        self.flash.write(offset, data)
+    }
+}
+
+impl<FLASH: ReadNorFlash> ReadNorFlash for RunningWatchdogFlash<FLASH> {
+    const READ_SIZE: usize = FLASH::READ_SIZE;
+    fn read(&mut self, offset: u32, data: &mut [u8]) -> Result<(), Self::Error> {
+        self.pet();
+        self.flash.read(offset, data)
+    }
+    fn capacity(&self) -> usize {
+        self.flash.capacity()
+    }
+}
+
+/// Error starting a [`BootWatchdog`].
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootWatchdogError {
+    /// Reading the firmware state failed.
+    State(FirmwareUpdaterError),
+    /// The watchdog is already running with another configuration.
+    ///
+    /// It can't be reconfigured nor pet, so it resets the device when it expires. For an image
+    /// that isn't confirmed, this reverts it. Resetting the device earlier doesn't help, as the
+    /// watchdog keeps running across a soft reset.
+    WrongConfig,
+}
+
+impl From<FirmwareUpdaterError> for BootWatchdogError {
+    fn from(e: FirmwareUpdaterError) -> Self {
+        Self::State(e)
+    }
+}
+
+/// A watchdog guarding the first boot of an updated application.
+///
+/// After an update, the application must confirm the new image with
+/// [`mark_booted`](Self::mark_booted) before the watchdog expires. If it hangs before that, the
+/// watchdog resets the device and the bootloader reverts to the previous image.
+///
+/// The watchdog is started when the image isn't confirmed yet. It is also taken over when it is
+/// already running, for example because the bootloader used a [`WatchdogFlash`], as it can't be
+/// stopped. Otherwise a confirmed image runs without a watchdog.
+///
+/// ```rust,ignore
+/// let mut wdt = BootWatchdog::start(&mut state, p.WDT, wdt_config).await?;
+/// // Initialize the application. A hang here reverts the update.
+/// wdt.mark_booted(&mut state).await?;
+/// wdt.keep_alive(|| Timer::after_millis(500)).await;
+/// ```
+pub struct BootWatchdog<const N: usize = 1> {
+    wdt: Option<[wdt::WatchdogHandle; N]>,
+}
+
+impl BootWatchdog {
+    /// Start the watchdog with `config` if the current image isn't confirmed, or if it is
+    /// already running.
+    pub async fn start<STATE: embedded_storage_async::nor_flash::NorFlash, T: wdt::Instance>(
+        state: &mut FirmwareState<'_, STATE>,
+        wdt: Peri<'static, T>,
+        config: wdt::Config,
+    ) -> Result<Self, BootWatchdogError> {
+        let armed = Self::should_arm::<_, T>(state).await?;
+        Self::start_inner(armed.then(|| wdt::Watchdog::try_new(wdt, config)))
+    }
+}
+
+impl<const N: usize> BootWatchdog<N> {
+    /// Start the watchdog with a configuration shared with the bootloader, if the current image
+    /// isn't confirmed, or if it is already running.
+    ///
+    /// `N` must be the handle count of `shared`, see [`wdt::SharedConfig`].
+    pub async fn start_shared<STATE: embedded_storage_async::nor_flash::NorFlash, T: wdt::Instance>(
+        state: &mut FirmwareState<'_, STATE>,
+        wdt: Peri<'static, T>,
+        shared: &wdt::SharedConfig,
+    ) -> Result<Self, BootWatchdogError> {
+        let armed = Self::should_arm::<_, T>(state).await?;
+        Self::start_inner(armed.then(|| wdt::Watchdog::try_new_shared(wdt, shared)))
+    }
+
+    async fn should_arm<STATE: embedded_storage_async::nor_flash::NorFlash, T: wdt::Instance>(
+        state: &mut FirmwareState<'_, STATE>,
+    ) -> Result<bool, FirmwareUpdaterError> {
+        Ok(state.get_state().await? == State::Swap || wdt::is_running::<T>())
+    }
+
+    fn start_inner<E>(
+        wdt: Option<Result<(wdt::Watchdog, [wdt::WatchdogHandle; N]), E>>,
+    ) -> Result<Self, BootWatchdogError> {
+        let wdt = match wdt {
+            Some(Ok((_wdt, handles))) => Some(handles),
+            Some(Err(_)) => {
+                warn!("Watchdog already active with wrong config");
+                return Err(BootWatchdogError::WrongConfig);
+            }
+            None => None,
+        };
+        Ok(Self { wdt })
+    }
+
+    /// Whether the watchdog is running, and must be pet.
+    pub fn is_armed(&self) -> bool {
+        self.wdt.is_some()
+    }
+
+    /// Pet the watchdog, if it is running.
+    pub fn pet(&mut self) {
+        if let Some(wdt) = &mut self.wdt {
+            for handle in wdt {
+                handle.pet();
+            }
+        }
+    }
+
+    /// Confirm the current image, so that the bootloader keeps it, and pet the watchdog.
+    ///
+    /// From then on, the watchdog must be pet regularly, for example with
+    /// [`keep_alive`](Self::keep_alive).
+    pub async fn mark_booted<STATE: embedded_storage_async::nor_flash::NorFlash>(
+        &mut self,
+        state: &mut FirmwareState<'_, STATE>,
+    ) -> Result<(), FirmwareUpdaterError> {
+        state.mark_booted().await?;
+        self.pet();
+        Ok(())
+    }
+
+    /// Pet the watchdog forever, waiting for `period()` between pets.
+    ///
+    /// `period` is typically `|| Timer::after(period)`, with a period shorter than the watchdog
+    /// timeout. Only call this once the image is confirmed, as a hang elsewhere in the
+    /// application no longer resets the device. If the watchdog isn't running, this never
+    /// completes, without doing anything.
+    pub async fn keep_alive<F: Future<Output = ()>>(mut self, mut period: impl FnMut() -> F) -> ! {
+        if !self.is_armed() {
+            core::future::pending::<()>().await;
+        }
+        loop {
+            self.pet();
+            period().await;
+        }
+    }
+}
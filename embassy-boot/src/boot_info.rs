@@ -0,0 +1,181 @@
+use crate::State;
+use crate::crc::crc32;
+
+const BOOT_INFO_MAGIC: u32 = 0xEB00_71F0;
+const BOOT_INFO_VERSION: u8 = 1;
+
+/// Information handed over from the bootloader to the application.
+///
+/// The bootloader stores this block in a memory region reserved in the linkerfile of both the
+/// bootloader and the application, right before jumping to the application. The block is
+/// protected by a magic value and a CRC, so the application can tell whether it was booted
+/// by a bootloader that provides it.
+///
+/// The region is defined by the `__bootloader_info_start` and `__bootloader_info_end` symbols and
+/// must be at least [`BootInfo::SIZE`] bytes. It must be placed outside of the RAM region used by
+/// either binary, so that it is not cleared by the runtime on startup:
+///
+/// ```text
+/// MEMORY
+/// {
+///   RAM                         (rwx) : ORIGIN = 0x20000000, LENGTH = 32K - 64
+///   BOOTLOADER_INFO             (rw)  : ORIGIN = 0x20007FC0, LENGTH = 64
+/// }
+///
+/// __bootloader_info_start = ORIGIN(BOOTLOADER_INFO);
+/// __bootloader_info_end = ORIGIN(BOOTLOADER_INFO) + LENGTH(BOOTLOADER_INFO);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BootInfo {
+    /// The bootloader state observed before booting, telling whether a swap or revert just happened.
+    pub state: State,
+    /// Version of the bootloader, as provided by the bootloader binary.
+    pub bootloader_version: u32,
+    /// Address of the image that was booted.
+    pub active_offset: u32,
+    /// Length of the booted image.
+    ///
+    /// Bootloaders that do not validate the image report the size of the active partition.
+    pub image_len: u32,
+}
+
+impl BootInfo {
+    /// Size of the encoded boot info block in bytes.
+    pub const SIZE: usize = 24;
+
+    /// Encode the boot info block, including magic and CRC.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        buf[0..4].copy_from_slice(&BOOT_INFO_MAGIC.to_le_bytes());
+        buf[4] = BOOT_INFO_VERSION;
+        buf[5] = state_to_u8(&self.state);
+        buf[8..12].copy_from_slice(&self.bootloader_version.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.active_offset.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.image_len.to_le_bytes());
+        let crc = crc32(&buf[..20]);
+        buf[20..24].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    /// Decode and validate a boot info block.
+    ///
+    /// Returns `None` if the magic, version or CRC do not match.
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
+        }
+        let word = |offset: usize| u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]]);
+
+        if word(0) != BOOT_INFO_MAGIC || buf[4] != BOOT_INFO_VERSION || word(20) != crc32(&buf[..20]) {
+            return None;
+        }
+
+        Some(Self {
+            state: state_from_u8(buf[5])?,
+            bootloader_version: word(8),
+            active_offset: word(12),
+            image_len: word(16),
+        })
+    }
+}
+
+#[cfg(target_os = "none")]
+impl BootInfo {
+    /// Read and validate the boot info block from the region defined in the linkerfile.
+    ///
+    /// Returns `None` if the bootloader did not store a valid block.
+    pub fn read() -> Option<Self> {
+        let (start, len) = Self::region();
+        assert!(len >= Self::SIZE);
+
+        let mut buf = [0; Self::SIZE];
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = unsafe { core::ptr::read_volatile(start.add(i)) };
+        }
+        Self::from_bytes(&buf)
+    }
+
+    /// Store the boot info block in the region defined in the linkerfile.
+    ///
+    /// This is meant to be called by the bootloader right before booting the application.
+    ///
+    /// # Safety
+    ///
+    /// The region defined by the `__bootloader_info_start` and `__bootloader_info_end` symbols must
+    /// be valid, writable memory that is not used for anything else.
+    pub unsafe fn store(&self) {
+        let (start, len) = Self::region();
+        assert!(len >= Self::SIZE);
+
+        for (i, b) in self.to_bytes().iter().enumerate() {
+            core::ptr::write_volatile(start.add(i), *b);
+        }
+    }
+
+    fn region() -> (*mut u8, usize) {
+        unsafe extern "C" {
+            static __bootloader_info_start: u32;
+            static __bootloader_info_end: u32;
+        }
+
+        unsafe {
+            let start = &__bootloader_info_start as *const u32 as u32;
+            let end = &__bootloader_info_end as *const u32 as u32;
+            trace!("INFO: 0x{:x} - 0x{:x}", start, end);
+
+            (start as *mut u8, (end - start) as usize)
+        }
+    }
+}
+
+fn state_to_u8(state: &State) -> u8 {
+    match state {
+        State::Boot => 0,
+        State::Swap => 1,
+        State::Revert => 2,
+        State::DfuDetach => 3,
+    }
+}
+
+fn state_from_u8(value: u8) -> Option<State> {
+    match value {
+        0 => Some(State::Boot),
+        1 => Some(State::Swap),
+        2 => Some(State::Revert),
+        3 => Some(State::DfuDetach),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO: BootInfo = BootInfo {
+        state: State::Swap,
+        bootloader_version: 0x0001_0203,
+        active_offset: 0x7000,
+        image_len: 65536,
+    };
+
+    #[test]
+    fn round_trip() {
+        let buf = INFO.to_bytes();
+        assert_eq!(Some(INFO), BootInfo::from_bytes(&buf));
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let mut buf = INFO.to_bytes();
+        buf[12] ^= 0x01;
+        assert_eq!(None, BootInfo::from_bytes(&buf));
+    }
+
+    #[test]
+    fn rejects_uninitialized() {
+        assert_eq!(None, BootInfo::from_bytes(&[0; BootInfo::SIZE]));
+        assert_eq!(None, BootInfo::from_bytes(&[0xFF; BootInfo::SIZE]));
+        assert_eq!(None, BootInfo::from_bytes(&INFO.to_bytes()[..BootInfo::SIZE - 1]));
+    }
+}
@@ -5,7 +5,9 @@ use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embedded_storage::nor_flash::{NorFlash, NorFlashError, NorFlashErrorKind};
 
-use crate::{DFU_DETACH_MAGIC, REVERT_MAGIC, STATE_ERASE_VALUE, SWAP_MAGIC, State};
+#[cfg(feature = "verify-swap")]
+use crate::crc::crc32_update;
+use crate::{BootInfo, DFU_DETACH_MAGIC, REVERT_MAGIC, STATE_ERASE_VALUE, SWAP_MAGIC, State};
 
 /// Errors returned by bootloader
 #[derive(PartialEq, Eq, Debug)]
@@ -14,6 +16,8 @@ pub enum BootError {
     Flash(NorFlashErrorKind),
     /// Invalid bootloader magic
     BadMagic,
+    /// A page copied while swapping or reverting read back differently, see the `verify-swap` feature.
+    PageMismatch,
 }
 
 #[cfg(feature = "defmt")]
@@ -22,6 +26,7 @@ impl defmt::Format for BootError {
         match self {
             BootError::Flash(_) => defmt::write!(fmt, "BootError::Flash(_)"),
             BootError::BadMagic => defmt::write!(fmt, "BootError::BadMagic"),
+            BootError::PageMismatch => defmt::write!(fmt, "BootError::PageMismatch"),
         }
     }
 }
@@ -146,17 +151,29 @@ pub struct BootLoader<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> {
 
 impl<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> BootLoader<ACTIVE, DFU, STATE> {
     /// Get the page size which is the "unit of operation" within the bootloader.
-    const PAGE_SIZE: u32 = if ACTIVE::ERASE_SIZE > DFU::ERASE_SIZE {
-        ACTIVE::ERASE_SIZE as u32
-    } else {
-        DFU::ERASE_SIZE as u32
-    };
+    ///
+    /// This is the least common multiple of the erase and write sizes of the active and DFU
+    /// flashes, so that pages are erased and written whole on both, even if they have different
+    /// geometries, such as internal flash for the active partition and an external SPI NOR flash
+    /// for the DFU partition.
+    ///
+    /// It is not necessarily a power of two: sectors of 2048 and 3072 bytes give pages of 6144
+    /// bytes, which a buffer of 4096 bytes can't be used for.
+    const PAGE_SIZE: u32 = page_size(
+        (ACTIVE::ERASE_SIZE, ACTIVE::WRITE_SIZE),
+        (DFU::ERASE_SIZE, DFU::WRITE_SIZE),
+    );
 
     /// Create a new instance of a bootloader with the flash partitions.
     ///
-    /// - All partitions must be aligned with the PAGE_SIZE const generic parameter.
-    /// - The dfu partition must be at least PAGE_SIZE bigger than the active partition.
+    /// - The active and dfu partitions must be multiples of the page size, the least common
+    ///   multiple of their erase and write sizes.
+    /// - The dfu partition must be at least one page bigger than the active partition.
+    /// - The state partition must be large enough to track the progress of every page.
+    ///
+    /// This panics, with a description of the problem, if the partitions don't comply.
     pub fn new(config: BootLoaderConfig<ACTIVE, DFU, STATE>) -> Self {
+        assert_partitions(&config.active, &config.dfu, &config.state, Self::PAGE_SIZE);
         Self {
             active: config.active,
             dfu: config.dfu,
@@ -170,7 +187,8 @@ impl<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> BootLoader<ACTIVE, DFU, S
     /// algorithm to work correctly.
     ///
     /// The provided aligned_buf argument must satisfy any alignment requirements
-    /// given by the partition flashes. All flash operations will use this buffer.
+    /// given by the partition flashes. All flash operations will use this buffer. Its length must
+    /// divide the page size, which isn't necessarily a power of two, see [`BootLoader::new`].
     ///
     /// ## SWAPPING
     ///
@@ -236,23 +254,26 @@ impl<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> BootLoader<ACTIVE, DFU, S
     /// |    Active |            3 |      1 |      2 |      3 |      - |
     /// |       DFU |            3 |      4 |      5 |      6 |      3 |
     ///
+    /// ## VERIFYING
+    ///
+    /// With the `verify-swap` feature, every copied page is read back and its CRC compared with
+    /// the one of the data written. On mismatch, [`BootError::PageMismatch`] is returned before the
+    /// progress of the copy is recorded, leaving the magic in place: the next call redoes the copy,
+    /// as it would after a power failure.
+    ///
     pub fn prepare_boot(&mut self, aligned_buf: &mut [u8]) -> Result<State, BootError> {
-        const {
-            core::assert!(Self::PAGE_SIZE % ACTIVE::WRITE_SIZE as u32 == 0);
-            core::assert!(Self::PAGE_SIZE % ACTIVE::ERASE_SIZE as u32 == 0);
-            core::assert!(Self::PAGE_SIZE % DFU::WRITE_SIZE as u32 == 0);
-            core::assert!(Self::PAGE_SIZE % DFU::ERASE_SIZE as u32 == 0);
-        }
-
         // Ensure we have enough progress pages to store copy progress
-        assert_eq!(0, Self::PAGE_SIZE % aligned_buf.len() as u32);
+        assert_eq!(
+            0,
+            Self::PAGE_SIZE % aligned_buf.len() as u32,
+            "aligned_buf length {} doesn't divide the page size {}",
+            aligned_buf.len(),
+            Self::PAGE_SIZE
+        );
         assert!(aligned_buf.len() >= STATE::WRITE_SIZE);
         assert_eq!(0, aligned_buf.len() % ACTIVE::WRITE_SIZE);
         assert_eq!(0, aligned_buf.len() % DFU::WRITE_SIZE);
 
-        // Ensure our partitions are able to handle boot operations
-        assert_partitions(&self.active, &self.dfu, &self.state, Self::PAGE_SIZE);
-
         // Copy contents from partition N to active
         let state = self.read_state(aligned_buf)?;
         if state == State::Swap {
@@ -285,6 +306,18 @@ impl<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> BootLoader<ACTIVE, DFU, S
         Ok(state)
     }
 
+    /// Create the [`BootInfo`] block to hand over to the application, given the `state`
+    /// returned by [`BootLoader::prepare_boot`], the version of the bootloader binary and the
+    /// address of the active partition.
+    pub fn boot_info(&self, state: State, bootloader_version: u32, active_offset: u32) -> BootInfo {
+        BootInfo {
+            state,
+            bootloader_version,
+            active_offset,
+            image_len: self.active.capacity() as u32,
+        }
+    }
+
     /// Read the magic state from flash
     pub fn read_state(&mut self, aligned_buf: &mut [u8]) -> Result<State, BootError> {
         let state_word = &mut aligned_buf[..STATE::WRITE_SIZE];
@@ -349,11 +382,20 @@ impl<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> BootLoader<ACTIVE, DFU, S
 
             self.active.erase(to_offset, to_offset + page_size)?;
 
+            #[cfg(feature = "verify-swap")]
+            let mut crc = 0xFFFF_FFFF;
             for offset_in_page in (0..page_size).step_by(aligned_buf.len()) {
                 self.dfu.read(from_offset + offset_in_page as u32, aligned_buf)?;
                 self.active.write(to_offset + offset_in_page as u32, aligned_buf)?;
+                #[cfg(feature = "verify-swap")]
+                {
+                    crc = crc32_update(crc, aligned_buf);
+                }
             }
 
+            #[cfg(feature = "verify-swap")]
+            verify_page(&mut self.active, to_offset, page_size, crc, aligned_buf)?;
+
             self.update_progress(progress_index, aligned_buf)?;
         }
         Ok(())
@@ -371,11 +413,20 @@ impl<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> BootLoader<ACTIVE, DFU, S
 
             self.dfu.erase(to_offset as u32, to_offset + page_size)?;
 
+            #[cfg(feature = "verify-swap")]
+            let mut crc = 0xFFFF_FFFF;
             for offset_in_page in (0..page_size).step_by(aligned_buf.len()) {
                 self.active.read(from_offset + offset_in_page as u32, aligned_buf)?;
                 self.dfu.write(to_offset + offset_in_page as u32, aligned_buf)?;
+                #[cfg(feature = "verify-swap")]
+                {
+                    crc = crc32_update(crc, aligned_buf);
+                }
             }
 
+            #[cfg(feature = "verify-swap")]
+            verify_page(&mut self.dfu, to_offset, page_size, crc, aligned_buf)?;
+
             self.update_progress(progress_index, aligned_buf)?;
         }
         Ok(())
@@ -422,17 +473,85 @@ impl<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash> BootLoader<ACTIVE, DFU, S
     }
 }
 
+/// Read back the page copied at `offset`, and compare its CRC with `crc`, the one of the data written.
+///
+/// On mismatch, the progress of the copy isn't recorded, so it is done again on the next boot, as if
+/// the power failed while copying.
+#[cfg(feature = "verify-swap")]
+fn verify_page<F: NorFlash>(
+    flash: &mut F,
+    offset: u32,
+    page_size: u32,
+    crc: u32,
+    aligned_buf: &mut [u8],
+) -> Result<(), BootError> {
+    let mut read_back = 0xFFFF_FFFF;
+    for offset_in_page in (0..page_size).step_by(aligned_buf.len()) {
+        flash.read(offset + offset_in_page, aligned_buf)?;
+        read_back = crc32_update(read_back, aligned_buf);
+    }
+    if read_back != crc {
+        warn!("Page at {} read back differently, aborting", offset);
+        return Err(BootError::PageMismatch);
+    }
+    Ok(())
+}
+
 fn assert_partitions<ACTIVE: NorFlash, DFU: NorFlash, STATE: NorFlash>(
     active: &ACTIVE,
     dfu: &DFU,
     state: &STATE,
     page_size: u32,
 ) {
-    assert_eq!(active.capacity() as u32 % page_size, 0);
-    assert_eq!(dfu.capacity() as u32 % page_size, 0);
+    let active = active.capacity() as u32;
+    let dfu = dfu.capacity() as u32;
+    assert!(
+        active.is_multiple_of(page_size),
+        "active partition size {} is not a multiple of the page size {}",
+        active,
+        page_size
+    );
+    assert!(
+        dfu.is_multiple_of(page_size),
+        "dfu partition size {} is not a multiple of the page size {}",
+        dfu,
+        page_size
+    );
     // DFU partition has to be bigger than ACTIVE partition to handle swap algorithm
-    assert!(dfu.capacity() as u32 - active.capacity() as u32 >= page_size);
-    assert!(2 + 4 * (active.capacity() as u32 / page_size) <= state.capacity() as u32 / STATE::WRITE_SIZE as u32);
+    assert!(
+        dfu >= active + page_size,
+        "dfu partition size {} must be at least one page of {} bigger than the active partition size {}",
+        dfu,
+        page_size,
+        active
+    );
+    let words = state.capacity() as u32 / STATE::WRITE_SIZE as u32;
+    assert!(
+        2 + 4 * (active / page_size) <= words,
+        "state partition holds {} words, fewer than the {} needed to track the progress of {} pages",
+        words,
+        2 + 4 * (active / page_size),
+        active / page_size
+    );
+}
+
+/// Greatest common divisor of `a` and `b`.
+const fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Least common multiple of `a` and `b`.
+const fn lcm(a: u32, b: u32) -> u32 {
+    a / gcd(a, b) * b
+}
+
+/// Page size of the bootloader for active and DFU flashes of the given `(erase, write)` sizes,
+/// see `BootLoader::PAGE_SIZE`.
+pub(crate) const fn page_size(active: (usize, usize), dfu: (usize, usize)) -> u32 {
+    lcm(lcm(active.0 as u32, dfu.0 as u32), lcm(active.1 as u32, dfu.1 as u32))
 }
 
 #[cfg(test)]
@@ -451,4 +570,51 @@ mod tests {
         static STATE: MemFlash<STATE_SIZE, 4, 4> = MemFlash::new(0xFF);
         assert_partitions(&ACTIVE, &DFU, &STATE, 4096);
     }
+
+    #[test]
+    #[should_panic(expected = "dfu partition size 16384 is not a multiple of the page size 6144")]
+    fn test_unaligned_dfu_partition() {
+        static ACTIVE: MemFlash<12288, 2048, 4> = MemFlash::new(0xFF);
+        static DFU: MemFlash<16384, 3072, 256> = MemFlash::new(0xFF);
+        static STATE: MemFlash<4096, 4, 4> = MemFlash::new(0xFF);
+        assert_partitions(&ACTIVE, &DFU, &STATE, 6144);
+    }
+
+    #[test]
+    #[cfg(feature = "verify-swap")]
+    fn test_swap_retried_after_page_mismatch() {
+        let mut active = MemFlash::<8192, 4096, 4>::new(0x55);
+        // A worn byte of the last active page, which is swapped first, doesn't program bit 0.
+        active.stuck_bits = Some((4100, 0x01));
+        let dfu = MemFlash::<12288, 4096, 4>::new(0xAA);
+        let mut state = MemFlash::<4096, 4096, 4>::default();
+        state.mem[..4].fill(SWAP_MAGIC);
+        let mut bootloader = BootLoader::new(BootLoaderConfig { active, dfu, state });
+
+        let mut page = [0; 1024];
+        assert_eq!(Err(BootError::PageMismatch), bootloader.prepare_boot(&mut page));
+        assert_eq!(State::Swap, bootloader.read_state(&mut page).unwrap());
+
+        // The copy is done again on the next boot.
+        bootloader.active.stuck_bits = None;
+        assert_eq!(State::Swap, bootloader.prepare_boot(&mut page).unwrap());
+        assert!(bootloader.active.mem.iter().all(|&b| b == 0xAA));
+        assert!(bootloader.dfu.mem[4096..].iter().all(|&b| b == 0x55));
+    }
+
+    #[test]
+    fn test_page_size() {
+        assert_eq!(
+            4096,
+            BootLoader::<MemFlash<0, 4096, 4>, MemFlash<0, 4096, 256>, MemFlash<0, 4, 4>>::PAGE_SIZE
+        );
+        assert_eq!(
+            6144,
+            BootLoader::<MemFlash<0, 2048, 4>, MemFlash<0, 3072, 256>, MemFlash<0, 4, 4>>::PAGE_SIZE
+        );
+        assert_eq!(
+            1024,
+            BootLoader::<MemFlash<0, 4, 4>, MemFlash<0, 256, 1024>, MemFlash<0, 4, 4>>::PAGE_SIZE
+        );
+    }
 }
@@ -0,0 +1,40 @@
+/// CRC-32 (IEEE 802.3) of `data`, as used by zlib and Ethernet.
+pub(crate) const fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0xFFFF_FFFF, data) ^ 0xFFFF_FFFF
+}
+
+/// Feed `data` into a running CRC-32 value that has not been finalized yet.
+pub(crate) const fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    let mut i = 0;
+    while i < data.len() {
+        crc ^= data[i] as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        i += 1;
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn incremental() {
+        let crc = crc32_update(0xFFFF_FFFF, b"1234");
+        let crc = crc32_update(crc, b"56789");
+        assert_eq!(0xCBF4_3926, crc ^ 0xFFFF_FFFF);
+    }
+}
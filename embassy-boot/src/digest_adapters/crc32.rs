@@ -0,0 +1,55 @@
+use digest::typenum::U4;
+use digest::{FixedOutput, HashMarker, OutputSizeUser, Reset, Update};
+
+use crate::crc::crc32_update;
+
+/// CRC-32 (IEEE 802.3) as a [`Digest`](digest::Digest), for
+/// [`FirmwareUpdater::verify_digest`](crate::FirmwareUpdater::verify_digest).
+///
+/// The output is the CRC in big endian, as printed by e.g. `crc32` or `rhash --crc32`.
+/// A CRC only detects accidental corruption: use a cryptographic hash, or a signature, if the
+/// update could have been tampered with.
+#[derive(Clone)]
+pub struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+}
+
+impl Update for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32_update(self.0, data);
+    }
+}
+
+impl FixedOutput for Crc32 {
+    fn finalize_into(self, out: &mut digest::Output<Self>) {
+        out.copy_from_slice(&(self.0 ^ 0xFFFF_FFFF).to_be_bytes());
+    }
+}
+
+impl OutputSizeUser for Crc32 {
+    type OutputSize = U4;
+}
+
+impl Reset for Crc32 {
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl HashMarker for Crc32 {}
+
+#[cfg(test)]
+mod tests {
+    use digest::Digest;
+
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!([0xCB, 0xF4, 0x39, 0x26], Crc32::digest(b"123456789").as_slice());
+    }
+}
@@ -1,3 +1,6 @@
+mod crc32;
+pub use crc32::Crc32;
+
 #[cfg(feature = "ed25519-dalek")]
 pub(crate) mod ed25519_dalek;
 
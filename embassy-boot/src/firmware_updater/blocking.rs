@@ -5,7 +5,9 @@ use embassy_embedded_hal::flash::partition::BlockingPartition;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embedded_storage::nor_flash::NorFlash;
 
-use super::FirmwareUpdaterConfig;
+use super::progress::{Layout, RECORD_LEN, Scan, encode, fill_unit};
+use super::{BlockingRollbackCounter, FirmwareUpdaterConfig, SwapProgress};
+use crate::boot_loader::page_size;
 use crate::{BOOT_MAGIC, DFU_DETACH_MAGIC, FirmwareUpdaterError, STATE_ERASE_VALUE, SWAP_MAGIC, State};
 
 /// Blocking FirmwareUpdater is an application API for interacting with the BootLoader without the ability to
@@ -111,8 +113,13 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
     ///
     /// Mark to trigger firmware swap on next boot if verify succeeds.
     ///
-    /// If the "ed25519-salty" feature is set (or another similar feature) then the signature is expected to have
-    /// been generated from a SHA-512 digest of the firmware bytes.
+    /// The signature is an ed25519 signature of the 64 byte SHA-512 digest of the first `update_len`
+    /// bytes of the DFU partition, and nothing else: there is no length prefix, the length is only
+    /// covered through the digest. This is the digest of the firmware file as written, as computed by
+    /// e.g. `shasum -a 512 -b`.
+    ///
+    /// If verification fails, the state partition is not written, so the current firmware keeps
+    /// running after the next reset.
     ///
     /// If no signature feature is set then this method will always return a
     /// signature error.
@@ -186,14 +193,40 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
         chunk_buf: &mut [u8],
         output: &mut [u8],
     ) -> Result<(), FirmwareUpdaterError> {
+        let digest = self.digest::<D>(update_len, chunk_buf)?;
+        output.copy_from_slice(digest.finalize().as_slice());
+        Ok(())
+    }
+
+    /// Verify the first `update_len` bytes of the DFU partition against the `expected` digest.
+    ///
+    /// `D` is any [`Digest`], e.g. `sha2::Sha256` or [`Crc32`](crate::Crc32). The partition is read
+    /// in chunks of `chunk_buf.len()` bytes, and the digests are compared in constant time.
+    ///
+    /// Returns [`FirmwareUpdaterError::DigestMismatch`] if they differ, in which case the update
+    /// must not be marked.
+    pub fn verify_digest<D: Digest>(
+        &mut self,
+        update_len: u32,
+        expected: &[u8],
+        chunk_buf: &mut [u8],
+    ) -> Result<(), FirmwareUpdaterError> {
+        let digest = self.digest::<D>(update_len, chunk_buf)?;
+        if super::digest_matches(digest, expected) {
+            Ok(())
+        } else {
+            Err(FirmwareUpdaterError::DigestMismatch)
+        }
+    }
+
+    fn digest<D: Digest>(&mut self, update_len: u32, chunk_buf: &mut [u8]) -> Result<D, FirmwareUpdaterError> {
         let mut digest = D::new();
         for offset in (0..update_len).step_by(chunk_buf.len()) {
             self.dfu.read(offset, chunk_buf)?;
             let len = core::cmp::min((update_len - offset) as usize, chunk_buf.len());
             digest.update(&chunk_buf[..len]);
         }
-        output.copy_from_slice(digest.finalize().as_slice());
-        Ok(())
+        Ok(digest)
     }
 
     /// Read a slice of data from the DFU storage peripheral, starting the read
@@ -213,6 +246,40 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
         self.state.mark_updated()
     }
 
+    /// Get the number of bytes of the current download written to the DFU partition.
+    ///
+    /// The length is recorded in the state partition once the bytes are written: when an erase
+    /// sector of the DFU partition is completed by [`write_firmware`](Self::write_firmware) or a
+    /// [`BlockingFirmwareWriter`], and when the writer is finished. It survives a reset, and is
+    /// cleared by a write at offset 0, by [`prepare_update`](Self::prepare_update), and by marking
+    /// an update.
+    pub fn written_len(&mut self) -> Result<u32, FirmwareUpdaterError> {
+        Ok(self.state.scan_written_len()?.len)
+    }
+
+    /// Get the offset to resume an interrupted download at.
+    ///
+    /// This is [`written_len`](Self::written_len) rounded down to an erase sector of the DFU
+    /// partition. Pass the rest of the image from this offset to
+    /// [`write_firmware`](Self::write_firmware), which erases the sector again before writing it.
+    pub fn resume_at(&mut self) -> Result<u32, FirmwareUpdaterError> {
+        let len = self.written_len()?;
+        Ok(len - len % DFU::ERASE_SIZE as u32)
+    }
+
+    /// Read how far the bootloader got in applying an update, without writing to flash.
+    ///
+    /// `active` is the active partition, only used for its geometry: the number of pages swapped
+    /// is derived from it, with the same page size as the bootloader.
+    pub fn swap_progress<ACTIVE: NorFlash>(&mut self, active: &ACTIVE) -> Result<SwapProgress, FirmwareUpdaterError> {
+        let page_count = active.capacity() as u32
+            / page_size(
+                (ACTIVE::ERASE_SIZE, ACTIVE::WRITE_SIZE),
+                (DFU::ERASE_SIZE, DFU::WRITE_SIZE),
+            );
+        self.state.swap_progress(page_count)
+    }
+
     /// Mark to trigger USB DFU device on next boot.
     pub fn mark_dfu(&mut self) -> Result<(), FirmwareUpdaterError> {
         self.state.verify_booted()?;
@@ -224,13 +291,29 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
         self.state.mark_booted()
     }
 
+    /// Mark firmware boot successful, then raise the version floor of `counter` to `version`.
+    ///
+    /// `version` must be the version of the running image. The floor is only raised once the image
+    /// is confirmed, so an image the bootloader reverts never raises it. If the device resets in
+    /// between, the floor is raised by the next call.
+    pub fn mark_booted_with_version<VERSION: NorFlash>(
+        &mut self,
+        counter: &mut BlockingRollbackCounter<'_, VERSION>,
+        version: u32,
+    ) -> Result<(), FirmwareUpdaterError> {
+        self.state.mark_booted()?;
+        counter.advance(version)?;
+        Ok(())
+    }
+
     /// Writes firmware data to the device.
     ///
     /// This function writes the given data to the firmware area starting at the specified offset.
     /// It handles sector erasures and data writes while verifying the device is in a proper state
     /// for firmware updates. The function ensures that only unerased sectors are erased before
     /// writing and efficiently handles the writing process across sector boundaries and in
-    /// various configurations (data size, sector size, etc.).
+    /// various configurations (data size, sector size, etc.). A write at offset 0 starts a new
+    /// image, so an interrupted update can be restarted from the beginning.
     ///
     /// # Arguments
     ///
@@ -252,6 +335,16 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
     pub fn write_firmware(&mut self, offset: usize, data: &[u8]) -> Result<(), FirmwareUpdaterError> {
         // Make sure we are running a booted firmware to avoid reverting to a bad state.
         self.state.verify_booted()?;
+        self.write_dfu(offset, data)
+    }
+
+    fn write_dfu(&mut self, offset: usize, data: &[u8]) -> Result<(), FirmwareUpdaterError> {
+        // A write at the start of the partition starts a new image, for instance after an interrupted
+        // transfer: the sectors written before must be erased again.
+        if offset == 0 {
+            self.last_erased_dfu_sector_index = None;
+            self.state.clear_written_len()?;
+        }
 
         // Initialize variables to keep track of the remaining data and the current offset.
         let mut remaining_data = data;
@@ -270,7 +363,9 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
 
             // If the sector needs to be erased, erase it and update the last erased sector index.
             if need_erase {
-                self.dfu.erase(sector_start as u32, sector_end as u32)?;
+                self.dfu
+                    .erase(sector_start as u32, sector_end as u32)
+                    .map_err(FirmwareUpdaterError::erase)?;
                 self.last_erased_dfu_sector_index = Some(current_sector);
             }
 
@@ -282,6 +377,11 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
             // Write the current data chunk.
             self.dfu.write(offset as u32, data_chunk)?;
 
+            // Record the sector once it is completely written, so a download can be resumed after it.
+            if offset + write_size == sector_end {
+                self.state.advance_written_len(sector_start as u32, sector_end as u32)?;
+            }
+
             // Update the offset and remaining data for the next iteration.
             remaining_data = rest;
             offset += write_size;
@@ -297,10 +397,121 @@ impl<'d, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareUpdater<'d, DFU, STATE>
     /// exchange for added complexity.
     pub fn prepare_update(&mut self) -> Result<&mut DFU, FirmwareUpdaterError> {
         self.state.verify_booted()?;
-        self.dfu.erase(0, self.dfu.capacity() as u32)?;
+        self.state.clear_written_len()?;
+        self.dfu
+            .erase(0, self.dfu.capacity() as u32)
+            .map_err(FirmwareUpdaterError::erase)?;
 
         Ok(&mut self.dfu)
     }
+
+    /// Create a streaming writer for the DFU partition, starting at offset 0.
+    ///
+    /// The writer accepts chunks of any size and alignment, buffers them in `page`
+    /// and only writes to flash once the buffer is full, erasing sectors lazily
+    /// as they are reached. Call [`BlockingFirmwareWriter::finish`] once the whole image has been
+    /// written to flush the remaining bytes.
+    ///
+    /// # Safety
+    ///
+    /// The `page` buffer length must be a non-zero multiple of DFU::WRITE_SIZE, and follow the
+    /// alignment rules for the flash being written to. Using DFU::ERASE_SIZE is recommended.
+    pub fn writer<'a>(
+        &'a mut self,
+        page: &'a mut [u8],
+    ) -> Result<BlockingFirmwareWriter<'a, 'd, DFU, STATE>, FirmwareUpdaterError> {
+        assert!(!page.is_empty() && page.len().is_multiple_of(DFU::WRITE_SIZE));
+        self.state.verify_booted()?;
+
+        Ok(BlockingFirmwareWriter {
+            updater: self,
+            page,
+            buffered: 0,
+            offset: 0,
+            written: 0,
+        })
+    }
+}
+
+/// Blocking streaming writer for the DFU partition, created by [`BlockingFirmwareUpdater::writer`].
+///
+/// Buffered data that has not been flushed is lost if the writer is dropped without
+/// calling [`BlockingFirmwareWriter::finish`].
+pub struct BlockingFirmwareWriter<'a, 'd, DFU: NorFlash, STATE: NorFlash> {
+    updater: &'a mut BlockingFirmwareUpdater<'d, DFU, STATE>,
+    page: &'a mut [u8],
+    // Number of bytes currently held in `page`.
+    buffered: usize,
+    // DFU offset of the first byte in `page`.
+    offset: usize,
+    written: usize,
+}
+
+impl<'a, 'd, DFU: NorFlash, STATE: NorFlash> BlockingFirmwareWriter<'a, 'd, DFU, STATE> {
+    /// Total number of bytes accepted by the writer so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Write all buffered bytes that fill a complete flash word, keeping the remainder buffered.
+    fn write_buffered(&mut self) -> Result<(), FirmwareUpdaterError> {
+        let aligned = self.buffered - self.buffered % DFU::WRITE_SIZE;
+        if aligned > 0 {
+            self.updater.write_dfu(self.offset, &self.page[..aligned])?;
+            self.page.copy_within(aligned..self.buffered, 0);
+            self.buffered -= aligned;
+            self.offset += aligned;
+        }
+        Ok(())
+    }
+
+    /// Flush all remaining data to the DFU partition and return the total number of bytes written.
+    ///
+    /// If the image does not end on a flash word boundary, the last word is padded with the
+    /// erase value (`0xFF`). The returned length excludes this padding and can be passed to
+    /// [`BlockingFirmwareUpdater::hash`] or `verify_and_mark_updated`.
+    pub fn finish(mut self) -> Result<u32, FirmwareUpdaterError> {
+        self.write_buffered()?;
+        if self.buffered > 0 {
+            self.page[self.buffered..DFU::WRITE_SIZE].fill(STATE_ERASE_VALUE);
+            self.updater.write_dfu(self.offset, &self.page[..DFU::WRITE_SIZE])?;
+            self.offset += DFU::WRITE_SIZE;
+            self.buffered = 0;
+        }
+        let written = self.written as u32;
+        self.updater
+            .state
+            .advance_written_len(written - written % DFU::ERASE_SIZE as u32, written)?;
+        Ok(written)
+    }
+}
+
+impl<DFU: NorFlash, STATE: NorFlash> embedded_io::ErrorType for BlockingFirmwareWriter<'_, '_, DFU, STATE> {
+    type Error = FirmwareUpdaterError;
+}
+
+impl<DFU: NorFlash, STATE: NorFlash> embedded_io::Write for BlockingFirmwareWriter<'_, '_, DFU, STATE> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let n = core::cmp::min(buf.len(), self.page.len() - self.buffered);
+        self.page[self.buffered..self.buffered + n].copy_from_slice(&buf[..n]);
+        self.buffered += n;
+        self.written += n;
+
+        if self.buffered == self.page.len() {
+            self.write_buffered()?;
+        }
+        Ok(n)
+    }
+
+    /// Writes all buffered complete flash words. A trailing partial word stays buffered
+    /// until more data arrives or [`BlockingFirmwareWriter::finish`] is called.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.write_buffered()
+    }
 }
 
 /// Manages the state partition of the firmware update.
@@ -368,6 +579,108 @@ impl<'d, STATE: NorFlash> BlockingFirmwareState<'d, STATE> {
         self.set_magic(BOOT_MAGIC)
     }
 
+    /// Read how far the bootloader got in applying an update, without writing to flash.
+    ///
+    /// `page_count` is the number of pages of the active partition, a page being the least common
+    /// multiple of the erase and write sizes of the active and DFU partitions.
+    pub fn swap_progress(&mut self, page_count: u32) -> Result<SwapProgress, FirmwareUpdaterError> {
+        self.state.read(0, self.aligned)?;
+        let magic = &self.aligned[..STATE::WRITE_SIZE];
+        if State::from(magic) != State::Swap {
+            return Ok(SwapProgress::from_magic(magic));
+        }
+
+        if self.read_word(1)?.iter().any(|&b| b != STATE_ERASE_VALUE) {
+            return Ok(SwapProgress::from_progress(None, page_count));
+        }
+
+        // The progress words follow the magic and the progress validity word.
+        let words = ((self.state.capacity() / STATE::WRITE_SIZE).saturating_sub(3) as u32).min(4 * page_count);
+        let mut written = 0;
+        while written < words && SwapProgress::is_written(self.read_word(2 + written)?) {
+            written += 1;
+        }
+        Ok(SwapProgress::from_progress(Some(written), page_count))
+    }
+
+    /// Read the `index`-th word of `STATE::WRITE_SIZE` bytes of the state partition.
+    fn read_word(&mut self, index: u32) -> Result<&[u8], FirmwareUpdaterError> {
+        let offset = index * STATE::WRITE_SIZE as u32;
+        let start = offset - offset % self.aligned.len() as u32;
+        self.state.read(start, self.aligned)?;
+        let at = (offset - start) as usize;
+        Ok(&self.aligned[at..at + STATE::WRITE_SIZE])
+    }
+
+    fn scan_written_len(&mut self) -> Result<Scan, FirmwareUpdaterError> {
+        let layout = Layout::new(self.state.capacity(), STATE::WRITE_SIZE, self.aligned.len());
+        let mut scan = Scan::default();
+        for offset in layout.records() {
+            let mut record = [0; RECORD_LEN];
+            for index in 0..layout.units() {
+                let (unit, range) = layout.unit_at(offset, index);
+                self.state.read(unit, self.aligned)?;
+                record[range.clone()].copy_from_slice(&self.aligned[..range.len()]);
+            }
+            if !scan.record(offset, &record) {
+                break;
+            }
+        }
+        Ok(scan)
+    }
+
+    fn clear_written_len(&mut self) -> Result<(), FirmwareUpdaterError> {
+        let scan = self.scan_written_len()?;
+        if scan.len != 0 {
+            self.write_written_len(scan.free, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Raise the written length to `to`, if the bytes from the current length are the ones written
+    /// since `from`.
+    fn advance_written_len(&mut self, from: u32, to: u32) -> Result<(), FirmwareUpdaterError> {
+        let scan = self.scan_written_len()?;
+        if (from..to).contains(&scan.len) {
+            self.write_written_len(scan.free, to)?;
+        }
+        Ok(())
+    }
+
+    fn write_written_len(&mut self, free: Option<u32>, len: u32) -> Result<(), FirmwareUpdaterError> {
+        let layout = Layout::new(self.state.capacity(), STATE::WRITE_SIZE, self.aligned.len());
+        let Some(first) = layout.first() else {
+            return Ok(());
+        };
+
+        let offset = match free {
+            Some(offset) => offset,
+            None => {
+                // All records are used: erase them along with the magic, and write the magic back.
+                self.state.read(0, self.aligned)?;
+                self.state.erase(0, self.state.capacity() as u32)?;
+                if self.aligned[..STATE::WRITE_SIZE]
+                    .iter()
+                    .any(|&b| b != STATE_ERASE_VALUE)
+                {
+                    self.state.write(0, &self.aligned[..STATE::WRITE_SIZE])?;
+                }
+                if len == 0 {
+                    return Ok(());
+                }
+                first
+            }
+        };
+
+        let record = encode(len);
+        for index in 0..layout.units() {
+            let (unit, range) = layout.unit_at(offset, index);
+            fill_unit(self.aligned, &record, range);
+            self.state.write(unit, self.aligned)?;
+        }
+        Ok(())
+    }
+
     fn set_magic(&mut self, magic: u8) -> Result<(), FirmwareUpdaterError> {
         self.state.read(0, &mut self.aligned)?;
 
@@ -401,6 +714,7 @@ mod tests {
     use embassy_embedded_hal::flash::partition::BlockingPartition;
     use embassy_sync::blocking_mutex::Mutex;
     use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embedded_storage::nor_flash::NorFlashErrorKind;
     use sha1::{Digest, Sha1};
 
     use super::*;
@@ -428,6 +742,109 @@ mod tests {
         assert_eq!(Sha1::digest(update).as_slice(), hash);
     }
 
+    #[test]
+    fn can_verify_digest() {
+        let flash = Mutex::<NoopRawMutex, _>::new(RefCell::new(MemFlash::<131072, 4096, 8>::default()));
+        let state = BlockingPartition::new(&flash, 0, 4096);
+        let dfu = BlockingPartition::new(&flash, 65536, 65536);
+        let mut aligned = [0; 8];
+
+        let update = *b"123456789";
+        let mut to_write = [0; 4096];
+        to_write[..9].copy_from_slice(update.as_slice());
+
+        let mut updater = BlockingFirmwareUpdater::new(FirmwareUpdaterConfig { dfu, state }, &mut aligned);
+        updater.write_firmware(0, to_write.as_slice()).unwrap();
+        let mut chunk_buf = [0; 4];
+        let len = update.len() as u32;
+
+        updater
+            .verify_digest::<Sha1>(len, Sha1::digest(update).as_slice(), &mut chunk_buf)
+            .unwrap();
+        updater
+            .verify_digest::<crate::Crc32>(len, &[0xCB, 0xF4, 0x39, 0x26], &mut chunk_buf)
+            .unwrap();
+        assert!(matches!(
+            updater.verify_digest::<crate::Crc32>(len, &[0xCB, 0xF4, 0x39, 0x27], &mut chunk_buf),
+            Err(FirmwareUpdaterError::DigestMismatch)
+        ));
+        assert!(matches!(
+            updater.verify_digest::<crate::Crc32>(len - 1, &[0xCB, 0xF4, 0x39, 0x26], &mut chunk_buf),
+            Err(FirmwareUpdaterError::DigestMismatch)
+        ));
+    }
+
+    #[test]
+    fn restart_interrupted_update() {
+        let flash = Mutex::<NoopRawMutex, _>::new(RefCell::new(MemFlash::<131072, 4096, 8>::default()));
+        let state = BlockingPartition::new(&flash, 0, 4096);
+        let dfu = BlockingPartition::new(&flash, 65536, 65536);
+        let mut aligned = [0; 8];
+
+        let mut updater = BlockingFirmwareUpdater::new(FirmwareUpdaterConfig { dfu, state }, &mut aligned);
+        updater.write_firmware(0, &[0x11; 1024]).unwrap();
+
+        // Starting over in the same sector erases it again.
+        updater.write_firmware(0, &[0x22; 1024]).unwrap();
+        updater.write_firmware(1024, &[0x33; 1024]).unwrap();
+
+        let mut read = [0; 2048];
+        updater.read_dfu(0, &mut read).unwrap();
+        assert!(read[..1024].iter().all(|&b| b == 0x22));
+        assert!(read[1024..].iter().all(|&b| b == 0x33));
+    }
+
+    #[test]
+    fn tells_erase_and_write_failures_apart() {
+        let flash = Mutex::<NoopRawMutex, _>::new(RefCell::new(MemFlash::<131072, 4096, 8>::default()));
+        let state = BlockingPartition::new(&flash, 0, 4096);
+        let dfu = BlockingPartition::new(&flash, 65536, 65536);
+        let mut aligned = [0; 8];
+
+        let mut updater = BlockingFirmwareUpdater::new(FirmwareUpdaterConfig { dfu, state }, &mut aligned);
+        assert!(matches!(
+            updater.write_firmware(65536, &[0x11; 8]),
+            Err(FirmwareUpdaterError::Erase(NorFlashErrorKind::OutOfBounds))
+        ));
+
+        flash.lock(|flash| flash.borrow_mut().pending_write_successes = Some(0));
+        assert!(matches!(
+            updater.write_firmware(4096, &[0x11; 8]),
+            Err(FirmwareUpdaterError::Flash(_))
+        ));
+    }
+
+    #[test]
+    fn resume_interrupted_update() {
+        let flash = Mutex::<NoopRawMutex, _>::new(RefCell::new(MemFlash::<131072, 4096, 8>::default()));
+        let mut aligned = [0; 8];
+
+        let config = FirmwareUpdaterConfig {
+            dfu: BlockingPartition::new(&flash, 65536, 65536),
+            state: BlockingPartition::new(&flash, 0, 4096),
+        };
+        let mut updater = BlockingFirmwareUpdater::new(config, &mut aligned);
+        updater.write_firmware(0, &[0x11; 4096]).unwrap();
+        updater.write_firmware(4096, &[0x22; 1024]).unwrap();
+        assert_eq!(4096, updater.written_len().unwrap());
+
+        // After a reset, the partially written sector is erased and written again.
+        let config = FirmwareUpdaterConfig {
+            dfu: BlockingPartition::new(&flash, 65536, 65536),
+            state: BlockingPartition::new(&flash, 0, 4096),
+        };
+        let mut updater = BlockingFirmwareUpdater::new(config, &mut aligned);
+        let resume_at = updater.resume_at().unwrap();
+        assert_eq!(4096, resume_at);
+        updater.write_firmware(resume_at as usize, &[0x33; 4096]).unwrap();
+        assert_eq!(8192, updater.written_len().unwrap());
+
+        let mut read = [0; 8192];
+        updater.read_dfu(0, &mut read).unwrap();
+        assert!(read[..4096].iter().all(|&b| b == 0x11));
+        assert!(read[4096..].iter().all(|&b| b == 0x33));
+    }
+
     #[test]
     fn can_verify_sha1_sector_bigger_than_chunk() {
         let flash = Mutex::<NoopRawMutex, _>::new(RefCell::new(MemFlash::<131072, 4096, 8>::default()));
@@ -505,4 +922,44 @@ mod tests {
 
         assert_eq!(Sha1::digest(update).as_slice(), hash);
     }
+
+    #[test]
+    fn can_stream_unaligned_chunks() {
+        use embedded_io::Write;
+
+        let flash = Mutex::<NoopRawMutex, _>::new(RefCell::new(MemFlash::<131072, 1024, 8>::default()));
+        let state = BlockingPartition::new(&flash, 0, 4096);
+        let dfu = BlockingPartition::new(&flash, 65536, 65536);
+        let mut aligned = [0; 8];
+
+        let mut update = [0; 3003];
+        for (i, b) in update.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+
+        let mut updater = BlockingFirmwareUpdater::new(FirmwareUpdaterConfig { dfu, state }, &mut aligned);
+        let mut page = [0; 1024];
+        let mut writer = updater.writer(&mut page).unwrap();
+        for chunk in update.chunks(63) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(update.len() as u32, writer.finish().unwrap());
+
+        let mut read_buf = [0; 3003];
+        updater.read_dfu(0, &mut read_buf).unwrap();
+        assert_eq!(update, read_buf);
+
+        // Final word is padded with the erase value
+        let mut tail = [0; 8];
+        updater.read_dfu(3000, &mut tail).unwrap();
+        assert_eq!([0xB8, 0xB9, 0xBA, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], tail);
+
+        let mut chunk_buf = [0; 2];
+        let mut hash = [0; 20];
+        updater
+            .hash::<Sha1>(update.len() as u32, &mut chunk_buf, &mut hash)
+            .unwrap();
+        assert_eq!(Sha1::digest(update).as_slice(), hash);
+    }
 }
@@ -1,9 +1,16 @@
 mod asynch;
 mod blocking;
+mod progress;
+mod rollback;
+mod self_test;
+mod swap_progress;
 
-pub use asynch::{FirmwareState, FirmwareUpdater};
-pub use blocking::{BlockingFirmwareState, BlockingFirmwareUpdater};
+pub use asynch::{FirmwareState, FirmwareUpdater, FirmwareWriter};
+pub use blocking::{BlockingFirmwareState, BlockingFirmwareUpdater, BlockingFirmwareWriter};
 use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
+pub use rollback::{BlockingRollbackCounter, RollbackCounter, RollbackError};
+pub use self_test::{SelfTestGate, SelfTestOutcome};
+pub use swap_progress::SwapProgress;
 
 /// Firmware updater flash configuration holding the two flashes used by the updater
 ///
@@ -17,15 +24,25 @@ pub struct FirmwareUpdaterConfig<DFU, STATE> {
     pub state: STATE,
 }
 
+/// Compare the finalized `digest` with `expected`, in a time independent of where they differ.
+fn digest_matches<D: digest::Digest>(digest: D, expected: &[u8]) -> bool {
+    let output = digest.finalize();
+    output.len() == expected.len() && output.iter().zip(expected).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
 /// Errors returned by FirmwareUpdater
 #[derive(Debug)]
 pub enum FirmwareUpdaterError {
     /// Error from flash.
     Flash(NorFlashErrorKind),
+    /// Error from flash while erasing the DFU partition.
+    Erase(NorFlashErrorKind),
     /// Signature errors.
     Signature(signature::Error),
     /// Bad state.
     BadState,
+    /// The digest of the update doesn't match the expected one.
+    DigestMismatch,
 }
 
 #[cfg(feature = "defmt")]
@@ -33,12 +50,40 @@ impl defmt::Format for FirmwareUpdaterError {
     fn format(&self, fmt: defmt::Formatter) {
         match self {
             FirmwareUpdaterError::Flash(_) => defmt::write!(fmt, "FirmwareUpdaterError::Flash(_)"),
+            FirmwareUpdaterError::Erase(_) => defmt::write!(fmt, "FirmwareUpdaterError::Erase(_)"),
             FirmwareUpdaterError::Signature(_) => defmt::write!(fmt, "FirmwareUpdaterError::Signature(_)"),
             FirmwareUpdaterError::BadState => defmt::write!(fmt, "FirmwareUpdaterError::BadState"),
+            FirmwareUpdaterError::DigestMismatch => defmt::write!(fmt, "FirmwareUpdaterError::DigestMismatch"),
+        }
+    }
+}
+
+impl core::fmt::Display for FirmwareUpdaterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FirmwareUpdaterError::Flash(_) => f.write_str("Flash"),
+            FirmwareUpdaterError::Erase(_) => f.write_str("Erase"),
+            FirmwareUpdaterError::Signature(_) => f.write_str("Signature"),
+            FirmwareUpdaterError::BadState => f.write_str("BadState"),
+            FirmwareUpdaterError::DigestMismatch => f.write_str("DigestMismatch"),
         }
     }
 }
 
+impl core::error::Error for FirmwareUpdaterError {}
+
+impl embedded_io::Error for FirmwareUpdaterError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+impl FirmwareUpdaterError {
+    fn erase<E: NorFlashError>(error: E) -> Self {
+        FirmwareUpdaterError::Erase(error.kind())
+    }
+}
+
 impl<E> From<E> for FirmwareUpdaterError
 where
     E: NorFlashError,
@@ -0,0 +1,101 @@
+//! High-water mark of the DFU download, stored in the state partition.
+//!
+//! The mark is a list of records appended after the magic and the progress validity word of the
+//! state partition, the last valid record holding the mark. The bootloader only uses this area
+//! for the swap progress, and the state partition is erased whenever its magic changes, so the
+//! mark is cleared by marking an update, and never read as swap progress.
+//!
+//! A record is the length and its complement, padded with the erase value to a multiple of the
+//! unit the state partition is accessed with.
+
+use core::iter::StepBy;
+use core::ops::Range;
+
+use crate::STATE_ERASE_VALUE;
+
+/// Size of the encoded part of a record.
+pub(crate) const RECORD_LEN: usize = 8;
+
+/// Location of the records in the state partition.
+pub(crate) struct Layout {
+    /// Size of the reads and writes, the length of the `aligned` buffer.
+    unit: usize,
+    first: u32,
+    end: u32,
+}
+
+impl Layout {
+    pub(crate) fn new(capacity: usize, write_size: usize, unit: usize) -> Self {
+        let record_size = RECORD_LEN.div_ceil(unit) * unit;
+        let first = (2 * write_size).next_multiple_of(unit);
+        // Only use whole records.
+        let count = capacity.saturating_sub(first) / record_size;
+        Self {
+            unit,
+            first: first as u32,
+            end: (first + count * record_size) as u32,
+        }
+    }
+
+    /// Offsets of the records.
+    pub(crate) fn records(&self) -> StepBy<Range<u32>> {
+        (self.first..self.end).step_by(RECORD_LEN.div_ceil(self.unit) * self.unit)
+    }
+
+    /// Offset of the first record, or `None` if the state partition has no room for records.
+    pub(crate) fn first(&self) -> Option<u32> {
+        (self.first < self.end).then_some(self.first)
+    }
+
+    /// Flash offset of the `index`-th unit of the record at `record`, and the part of the
+    /// encoded record it holds.
+    pub(crate) fn unit_at(&self, record: u32, index: usize) -> (u32, Range<usize>) {
+        let start = index * self.unit;
+        let end = (start + self.unit).min(RECORD_LEN);
+        (record + start as u32, start..end)
+    }
+
+    /// Number of units of a record.
+    pub(crate) fn units(&self) -> usize {
+        RECORD_LEN.div_ceil(self.unit)
+    }
+}
+
+pub(crate) fn encode(len: u32) -> [u8; RECORD_LEN] {
+    let mut record = [0; RECORD_LEN];
+    record[..4].copy_from_slice(&len.to_le_bytes());
+    record[4..].copy_from_slice(&(!len).to_le_bytes());
+    record
+}
+
+/// Copy the part `range` of an encoded record into `aligned`, padded with the erase value.
+pub(crate) fn fill_unit(aligned: &mut [u8], record: &[u8; RECORD_LEN], range: Range<usize>) {
+    aligned.fill(STATE_ERASE_VALUE);
+    aligned[..range.len()].copy_from_slice(&record[range]);
+}
+
+/// High-water mark and first free record found by scanning the records.
+#[derive(Default)]
+pub(crate) struct Scan {
+    pub(crate) len: u32,
+    pub(crate) free: Option<u32>,
+}
+
+impl Scan {
+    /// Scan the record at `offset`. Returns `false` once an erased record is found, which ends
+    /// the list.
+    pub(crate) fn record(&mut self, offset: u32, record: &[u8; RECORD_LEN]) -> bool {
+        if record.iter().all(|&b| b == STATE_ERASE_VALUE) {
+            self.free = Some(offset);
+            return false;
+        }
+
+        let len = u32::from_le_bytes(record[..4].try_into().unwrap());
+        let complement = u32::from_le_bytes(record[4..].try_into().unwrap());
+        // A record whose write was interrupted doesn't match its complement, and is skipped.
+        if complement == !len {
+            self.len = len;
+        }
+        true
+    }
+}
@@ -0,0 +1,380 @@
+use embedded_storage::nor_flash::{NorFlash as BlockingNorFlash, NorFlashError, NorFlashErrorKind};
+use embedded_storage_async::nor_flash::NorFlash;
+
+use super::FirmwareUpdaterError;
+use crate::STATE_ERASE_VALUE;
+
+/// Errors returned by [`RollbackCounter`] and [`BlockingRollbackCounter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackError {
+    /// Error from flash.
+    Flash(NorFlashErrorKind),
+    /// The incoming image is older than the version floor.
+    Rollback {
+        /// The lowest version that is accepted.
+        floor: u32,
+        /// The version of the rejected image.
+        incoming: u32,
+    },
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for RollbackError {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            RollbackError::Flash(_) => defmt::write!(fmt, "RollbackError::Flash(_)"),
+            RollbackError::Rollback { floor, incoming } => {
+                defmt::write!(
+                    fmt,
+                    "RollbackError::Rollback {{ floor: {}, incoming: {} }}",
+                    floor,
+                    incoming
+                )
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for RollbackError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RollbackError::Flash(_) => f.write_str("Flash"),
+            RollbackError::Rollback { floor, incoming } => {
+                write!(f, "Rollback from version {} to {}", floor, incoming)
+            }
+        }
+    }
+}
+
+impl core::error::Error for RollbackError {}
+
+impl<E> From<E> for RollbackError
+where
+    E: NorFlashError,
+{
+    fn from(error: E) -> Self {
+        RollbackError::Flash(error.kind())
+    }
+}
+
+impl From<RollbackError> for FirmwareUpdaterError {
+    fn from(error: RollbackError) -> Self {
+        match error {
+            RollbackError::Flash(kind) => FirmwareUpdaterError::Flash(kind),
+            RollbackError::Rollback { .. } => FirmwareUpdaterError::BadState,
+        }
+    }
+}
+
+/// Size of a version record: the version and its complement, padded to the write size.
+const fn record_size(write_size: usize) -> usize {
+    8usize.div_ceil(write_size) * write_size
+}
+
+fn check_partition(capacity: usize, erase_size: usize, write_size: usize, aligned: &[u8]) {
+    assert_eq!(aligned.len(), record_size(write_size));
+    assert!(capacity > 0 && capacity.is_multiple_of(2 * erase_size));
+    assert!(record_size(write_size) <= erase_size);
+}
+
+fn encode(version: u32, record: &mut [u8]) {
+    record.fill(STATE_ERASE_VALUE);
+    record[..4].copy_from_slice(&version.to_le_bytes());
+    record[4..8].copy_from_slice(&(!version).to_le_bytes());
+}
+
+/// Version floor and free slots found by scanning the counter partition.
+#[derive(Default)]
+struct Scan {
+    floor: Option<u32>,
+    floor_half: usize,
+    /// Offset of the first erased record after the last written one, per half.
+    free: [Option<u32>; 2],
+}
+
+impl Scan {
+    fn record(&mut self, half: usize, offset: u32, record: &[u8]) {
+        if record.iter().all(|&b| b == STATE_ERASE_VALUE) {
+            self.free[half].get_or_insert(offset);
+            return;
+        }
+
+        self.free[half] = None;
+        let version = u32::from_le_bytes(record[..4].try_into().unwrap());
+        let complement = u32::from_le_bytes(record[4..8].try_into().unwrap());
+        // A record whose write was interrupted doesn't match its complement, and is skipped.
+        if complement == !version && self.floor.is_none_or(|floor| version > floor) {
+            self.floor = Some(version);
+            self.floor_half = half;
+        }
+    }
+
+    /// Where to write a record raising the floor, and whether its half must be erased first.
+    fn next_slot(&self, half_size: u32) -> (u32, bool) {
+        let other = 1 - self.floor_half;
+        match (self.free[self.floor_half], self.free[other]) {
+            (Some(offset), _) | (None, Some(offset)) => (offset, false),
+            // Both halves are full: start over in the half that doesn't hold the floor, so the
+            // floor survives a reset during the erase.
+            (None, None) => (other as u32 * half_size, true),
+        }
+    }
+}
+
+/// Monotonic version counter preventing firmware rollback (downgrade) attacks.
+///
+/// The counter holds a version floor: [`check_version`](Self::check_version) rejects images
+/// older than the floor, and [`FirmwareUpdater::mark_booted_with_version`](crate::FirmwareUpdater::mark_booted_with_version)
+/// raises the floor to the version of the running image once it is confirmed.
+///
+/// The floor is stored in a dedicated partition, separate from the state partition which is erased
+/// by every swap. The partition must span an even number of erase sectors, split in two halves.
+/// Raising the floor appends a record to a half, and a half is only erased when both are full and
+/// the other half holds the floor: a reset at any point leaves either the previous or the new floor.
+pub struct RollbackCounter<'d, VERSION> {
+    version: VERSION,
+    aligned: &'d mut [u8],
+}
+
+impl<'d, VERSION: NorFlash> RollbackCounter<'d, VERSION> {
+    /// Create a rollback counter stored in the `version` partition.
+    ///
+    /// # Safety
+    ///
+    /// The `aligned` buffer must have a size of 8 bytes rounded up to a multiple of VERSION::WRITE_SIZE,
+    /// and follow the alignment rules for the flash being read from and written to.
+    pub fn new(version: VERSION, aligned: &'d mut [u8]) -> Self {
+        check_partition(version.capacity(), VERSION::ERASE_SIZE, VERSION::WRITE_SIZE, aligned);
+        Self { version, aligned }
+    }
+
+    async fn scan(&mut self) -> Result<Scan, RollbackError> {
+        let half_size = self.version.capacity() as u32 / 2;
+        let mut scan = Scan::default();
+        for half in 0..2 {
+            for offset in (half as u32 * half_size..(half as u32 + 1) * half_size).step_by(self.aligned.len()) {
+                self.version.read(offset, self.aligned).await?;
+                scan.record(half, offset, self.aligned);
+            }
+        }
+        Ok(scan)
+    }
+
+    /// Get the version floor, which is 0 until the first call to [`advance`](Self::advance).
+    pub async fn floor(&mut self) -> Result<u32, RollbackError> {
+        Ok(self.scan().await?.floor.unwrap_or(0))
+    }
+
+    /// Check that an image with version `incoming` may be staged.
+    ///
+    /// Returns [`RollbackError::Rollback`] if `incoming` is below the floor. Call this before writing
+    /// an update, with the version embedded in (and covered by the signature of) the incoming image.
+    pub async fn check_version(&mut self, incoming: u32) -> Result<(), RollbackError> {
+        let floor = self.floor().await?;
+        if incoming < floor {
+            warn!("Rejecting version {} below floor {}", incoming, floor);
+            return Err(RollbackError::Rollback { floor, incoming });
+        }
+        Ok(())
+    }
+
+    /// Raise the floor to `version`.
+    ///
+    /// Does nothing if the floor is already at or above `version`. This should only be called by a
+    /// confirmed image with its own version, see
+    /// [`FirmwareUpdater::mark_booted_with_version`](crate::FirmwareUpdater::mark_booted_with_version).
+    pub async fn advance(&mut self, version: u32) -> Result<(), RollbackError> {
+        let scan = self.scan().await?;
+        if scan.floor.is_some_and(|floor| floor >= version) {
+            return Ok(());
+        }
+
+        let half_size = self.version.capacity() as u32 / 2;
+        let (offset, erase) = scan.next_slot(half_size);
+        if erase {
+            self.version.erase(offset, offset + half_size).await?;
+        }
+        encode(version, self.aligned);
+        self.version.write(offset, self.aligned).await?;
+        Ok(())
+    }
+}
+
+/// Blocking monotonic version counter preventing firmware rollback (downgrade) attacks.
+///
+/// See [`RollbackCounter`] for details.
+pub struct BlockingRollbackCounter<'d, VERSION> {
+    version: VERSION,
+    aligned: &'d mut [u8],
+}
+
+impl<'d, VERSION: BlockingNorFlash> BlockingRollbackCounter<'d, VERSION> {
+    /// Create a rollback counter stored in the `version` partition.
+    ///
+    /// # Safety
+    ///
+    /// The `aligned` buffer must have a size of 8 bytes rounded up to a multiple of VERSION::WRITE_SIZE,
+    /// and follow the alignment rules for the flash being read from and written to.
+    pub fn new(version: VERSION, aligned: &'d mut [u8]) -> Self {
+        check_partition(version.capacity(), VERSION::ERASE_SIZE, VERSION::WRITE_SIZE, aligned);
+        Self { version, aligned }
+    }
+
+    fn scan(&mut self) -> Result<Scan, RollbackError> {
+        let half_size = self.version.capacity() as u32 / 2;
+        let mut scan = Scan::default();
+        for half in 0..2 {
+            for offset in (half as u32 * half_size..(half as u32 + 1) * half_size).step_by(self.aligned.len()) {
+                self.version.read(offset, self.aligned)?;
+                scan.record(half, offset, self.aligned);
+            }
+        }
+        Ok(scan)
+    }
+
+    /// Get the version floor, which is 0 until the first call to [`advance`](Self::advance).
+    pub fn floor(&mut self) -> Result<u32, RollbackError> {
+        Ok(self.scan()?.floor.unwrap_or(0))
+    }
+
+    /// Check that an image with version `incoming` may be staged.
+    ///
+    /// Returns [`RollbackError::Rollback`] if `incoming` is below the floor. Call this before writing
+    /// an update, with the version embedded in (and covered by the signature of) the incoming image.
+    pub fn check_version(&mut self, incoming: u32) -> Result<(), RollbackError> {
+        let floor = self.floor()?;
+        if incoming < floor {
+            warn!("Rejecting version {} below floor {}", incoming, floor);
+            return Err(RollbackError::Rollback { floor, incoming });
+        }
+        Ok(())
+    }
+
+    /// Raise the floor to `version`.
+    ///
+    /// Does nothing if the floor is already at or above `version`. This should only be called by a
+    /// confirmed image with its own version, see
+    /// [`BlockingFirmwareUpdater::mark_booted_with_version`](crate::BlockingFirmwareUpdater::mark_booted_with_version).
+    pub fn advance(&mut self, version: u32) -> Result<(), RollbackError> {
+        let scan = self.scan()?;
+        if scan.floor.is_some_and(|floor| floor >= version) {
+            return Ok(());
+        }
+
+        let half_size = self.version.capacity() as u32 / 2;
+        let (offset, erase) = scan.next_slot(half_size);
+        if erase {
+            self.version.erase(offset, offset + half_size)?;
+        }
+        encode(version, self.aligned);
+        self.version.write(offset, self.aligned)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_storage::nor_flash::ReadNorFlash;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::mem_flash::MemFlash;
+
+    #[test]
+    fn check_and_advance() {
+        let mut flash = MemFlash::<8192, 4096, 4>::default();
+        let mut aligned = [0; 8];
+        let mut counter = BlockingRollbackCounter::new(&mut flash, &mut aligned);
+
+        assert_eq!(0, counter.floor().unwrap());
+        counter.check_version(0).unwrap();
+
+        counter.advance(3).unwrap();
+        assert_eq!(3, counter.floor().unwrap());
+        assert_eq!(
+            Err(RollbackError::Rollback { floor: 3, incoming: 2 }),
+            counter.check_version(2)
+        );
+        counter.check_version(3).unwrap();
+        counter.check_version(4).unwrap();
+
+        // The floor never goes down.
+        counter.advance(2).unwrap();
+        assert_eq!(3, counter.floor().unwrap());
+    }
+
+    #[test]
+    fn wraps_around_halves() {
+        // 4 records per half.
+        let mut flash = MemFlash::<64, 32, 4>::default();
+        let mut aligned = [0; 8];
+        let mut counter = BlockingRollbackCounter::new(&mut flash, &mut aligned);
+
+        for version in 1..=8 {
+            counter.advance(version).unwrap();
+            assert_eq!(version, counter.floor().unwrap());
+        }
+
+        // The second half holds the floor, so the first one is erased.
+        counter.advance(9).unwrap();
+        let mut record = [0; 4];
+        flash.read(56, &mut record).unwrap();
+        assert_eq!(8, u32::from_le_bytes(record));
+
+        let mut counter = BlockingRollbackCounter::new(&mut flash, &mut aligned);
+        for version in 10..=20 {
+            counter.advance(version).unwrap();
+            assert_eq!(version, counter.floor().unwrap());
+        }
+    }
+
+    #[test]
+    fn interrupted_write_is_ignored() {
+        let mut flash = MemFlash::<64, 32, 4>::default();
+        let mut aligned = [0; 8];
+        BlockingRollbackCounter::new(&mut flash, &mut aligned)
+            .advance(5)
+            .unwrap();
+
+        // Only the version of the next record was written when the device reset.
+        flash.program(8, &9u32.to_le_bytes()).unwrap();
+
+        let mut counter = BlockingRollbackCounter::new(&mut flash, &mut aligned);
+        assert_eq!(5, counter.floor().unwrap());
+        counter.advance(6).unwrap();
+        assert_eq!(6, counter.floor().unwrap());
+    }
+
+    #[test]
+    fn interrupted_erase_keeps_floor() {
+        let mut flash = MemFlash::<64, 32, 4>::default();
+        let mut aligned = [0; 8];
+        let mut counter = BlockingRollbackCounter::new(&mut flash, &mut aligned);
+        for version in 1..=8 {
+            counter.advance(version).unwrap();
+        }
+
+        // Both halves are full, and the device resets while erasing the half not holding the floor.
+        BlockingNorFlash::erase(&mut flash, 0, 32).unwrap();
+        let mut counter = BlockingRollbackCounter::new(&mut flash, &mut aligned);
+        assert_eq!(8, counter.floor().unwrap());
+
+        counter.advance(9).unwrap();
+        assert_eq!(9, counter.floor().unwrap());
+        let mut record = [0; 4];
+        flash.read(0, &mut record).unwrap();
+        assert_eq!(9, u32::from_le_bytes(record));
+    }
+
+    #[test]
+    fn async_check_and_advance() {
+        let mut flash = MemFlash::<64, 32, 4>::default();
+        let mut aligned = [0; 8];
+        let mut counter = RollbackCounter::new(&mut flash, &mut aligned);
+
+        block_on(counter.advance(7)).unwrap();
+        assert_eq!(7, block_on(counter.floor()).unwrap());
+        assert!(block_on(counter.check_version(6)).is_err());
+        block_on(counter.check_version(7)).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use core::future::Future;
+
+use embassy_futures::select::{Either, select};
+use embedded_storage_async::nor_flash::NorFlash;
+
+use super::FirmwareState;
+use crate::{FirmwareUpdaterError, State};
+
+/// Result of [`SelfTestGate::run`] when the application is allowed to keep running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SelfTestOutcome {
+    /// The image was already confirmed, the self-tests were not run.
+    AlreadyConfirmed,
+    /// The image was unconfirmed, the self-tests passed and the image has been marked as booted.
+    Passed,
+}
+
+/// Gate that only confirms a freshly swapped image after it passed its self-tests.
+///
+/// The recommended flow after an update is to boot, run self-tests, and only then call
+/// `mark_booted`. If the application resets before that, the bootloader reverts to the
+/// previous image.
+///
+/// The self-tests are bounded by a deadline, and by the watchdog that was started by the bootloader:
+/// the gate pets the watchdog once before running the tests and not again until they pass, so
+/// a self-test that blocks the CPU still causes a reset (and thus a revert) when the watchdog
+/// expires.
+pub struct SelfTestGate<'a, 'd, STATE, P> {
+    state: &'a mut FirmwareState<'d, STATE>,
+    pet: P,
+    reset: fn() -> !,
+}
+
+impl<'a, 'd, STATE, P> SelfTestGate<'a, 'd, STATE, P>
+where
+    STATE: NorFlash,
+    P: FnMut(),
+{
+    /// Create a self-test gate.
+    ///
+    /// `pet` must pet the watchdog guarding the application, and `reset` must reset the device
+    /// immediately.
+    pub fn new(state: &'a mut FirmwareState<'d, STATE>, pet: P, reset: fn() -> !) -> Self {
+        Self { state, pet, reset }
+    }
+
+    /// Run the self-tests if the current image has not been confirmed yet.
+    ///
+    /// If the bootloader just swapped in a new image, `tests` is run until it completes or
+    /// `deadline` completes, whichever comes first. If the tests return `true`, the image is
+    /// marked as booted. If they return `false` or the deadline expires, the device is reset,
+    /// which makes the bootloader revert to the previous image.
+    ///
+    /// If the image is already confirmed, the tests are not run.
+    pub async fn run<T, D>(mut self, tests: T, deadline: D) -> Result<SelfTestOutcome, FirmwareUpdaterError>
+    where
+        T: Future<Output = bool>,
+        D: Future<Output = ()>,
+    {
+        if self.state.get_state().await? != State::Swap {
+            return Ok(SelfTestOutcome::AlreadyConfirmed);
+        }
+
+        // Give the tests a full watchdog period.
+        (self.pet)();
+
+        match select(tests, deadline).await {
+            Either::First(true) => {
+                self.state.mark_booted().await?;
+                (self.pet)();
+                Ok(SelfTestOutcome::Passed)
+            }
+            Either::First(false) => {
+                warn!("Self-tests failed, resetting");
+                (self.reset)()
+            }
+            Either::Second(()) => {
+                warn!("Self-tests timed out, resetting");
+                (self.reset)()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::future::{pending, ready};
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    use embassy_embedded_hal::flash::partition::Partition;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_sync::mutex::Mutex;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::SWAP_MAGIC;
+    use crate::mem_flash::MemFlash;
+
+    #[derive(Default)]
+    struct MockWdt {
+        pets: usize,
+    }
+
+    impl MockWdt {
+        fn pet(&mut self) {
+            self.pets += 1;
+        }
+    }
+
+    fn reset() -> ! {
+        panic!("reset")
+    }
+
+    fn swapped_flash() -> Mutex<NoopRawMutex, MemFlash<4096, 4096, 4>> {
+        let mut flash = MemFlash::<4096, 4096, 4>::default();
+        flash.program(0, &[SWAP_MAGIC; 4]).unwrap();
+        Mutex::new(flash)
+    }
+
+    fn read_state(flash: &Mutex<NoopRawMutex, MemFlash<4096, 4096, 4>>) -> State {
+        let mut aligned = [0; 4];
+        let mut state = FirmwareState::new(Partition::new(flash, 0, 4096), &mut aligned);
+        block_on(state.get_state()).unwrap()
+    }
+
+    #[test]
+    fn pass_marks_booted() {
+        let flash = swapped_flash();
+        let mut wdt = MockWdt::default();
+
+        let mut aligned = [0; 4];
+        let mut state = FirmwareState::new(Partition::new(&flash, 0, 4096), &mut aligned);
+        let gate = SelfTestGate::new(&mut state, || wdt.pet(), reset);
+        let outcome = block_on(gate.run(ready(true), pending())).unwrap();
+
+        assert_eq!(SelfTestOutcome::Passed, outcome);
+        assert_eq!(2, wdt.pets);
+        assert_eq!(State::Boot, read_state(&flash));
+    }
+
+    #[test]
+    fn fail_resets_without_marking_booted() {
+        let flash = swapped_flash();
+        let mut wdt = MockWdt::default();
+
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            let mut aligned = [0; 4];
+            let mut state = FirmwareState::new(Partition::new(&flash, 0, 4096), &mut aligned);
+            let gate = SelfTestGate::new(&mut state, || wdt.pet(), reset);
+            block_on(gate.run(ready(false), pending()))
+        }));
+
+        assert!(res.is_err());
+        assert_eq!(1, wdt.pets);
+        assert_eq!(State::Swap, read_state(&flash));
+    }
+
+    #[test]
+    fn hang_resets_at_deadline() {
+        let flash = swapped_flash();
+        let mut wdt = MockWdt::default();
+
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            let mut aligned = [0; 4];
+            let mut state = FirmwareState::new(Partition::new(&flash, 0, 4096), &mut aligned);
+            let gate = SelfTestGate::new(&mut state, || wdt.pet(), reset);
+            block_on(gate.run(pending(), ready(())))
+        }));
+
+        assert!(res.is_err());
+        assert_eq!(1, wdt.pets);
+        assert_eq!(State::Swap, read_state(&flash));
+    }
+
+    #[test]
+    fn confirmed_image_skips_tests() {
+        let flash = Mutex::<NoopRawMutex, _>::new(MemFlash::<4096, 4096, 4>::default());
+        let mut wdt = MockWdt::default();
+
+        let mut aligned = [0; 4];
+        let mut state = FirmwareState::new(Partition::new(&flash, 0, 4096), &mut aligned);
+        let gate = SelfTestGate::new(&mut state, || wdt.pet(), reset);
+        let outcome = block_on(gate.run(async { unreachable!() }, pending())).unwrap();
+
+        assert_eq!(SelfTestOutcome::AlreadyConfirmed, outcome);
+        assert_eq!(0, wdt.pets);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{BOOT_MAGIC, REVERT_MAGIC, STATE_ERASE_VALUE};
+
+/// How far the bootloader got in applying an update, as recorded in the state partition.
+///
+/// This tells an update that was never started from one whose swap was interrupted, for
+/// diagnostics. The swap and the revert proceed by pages, a page being the least common multiple
+/// of the erase and write sizes of the active and DFU partitions.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SwapProgress {
+    /// No update is pending, and no image was marked booted.
+    Idle,
+    /// An update is marked, and the bootloader swapped `page` pages of it into the active
+    /// partition. This is `0` until the bootloader starts the swap.
+    Swapping {
+        /// Number of pages swapped.
+        page: u32,
+    },
+    /// The update was swapped in, and runs on trial until it is marked booted.
+    Swapped,
+    /// The update reset before being marked booted, and the bootloader restored `page` pages of
+    /// the previous image.
+    Reverting {
+        /// Number of pages reverted.
+        page: u32,
+    },
+    /// The previous image was restored after the update failed to be marked booted.
+    Reverted,
+    /// The running image was marked booted.
+    Confirmed,
+}
+
+impl SwapProgress {
+    /// Progress of a state partition whose magic isn't the swap magic.
+    pub(crate) fn from_magic(magic: &[u8]) -> Self {
+        if magic.iter().all(|&b| b == BOOT_MAGIC) {
+            Self::Confirmed
+        } else if magic.iter().all(|&b| b == REVERT_MAGIC) {
+            Self::Reverted
+        } else {
+            Self::Idle
+        }
+    }
+
+    /// Progress of a state partition with the swap magic, given the number of progress words
+    /// written, or `None` if the progress was invalidated.
+    ///
+    /// The swap writes two words per page, then the revert two more per page. The bootloader
+    /// invalidates the progress once the revert is done, before writing the revert magic.
+    pub(crate) fn from_progress(written: Option<u32>, page_count: u32) -> Self {
+        let swap_words = 2 * page_count;
+        match written {
+            None => Self::Reverting { page: page_count },
+            Some(written) if written < swap_words => Self::Swapping { page: written / 2 },
+            Some(written) if written == swap_words => Self::Swapped,
+            Some(written) => Self::Reverting {
+                page: ((written - swap_words) / 2).min(page_count),
+            },
+        }
+    }
+
+    /// Whether a progress word is written.
+    pub(crate) fn is_written(word: &[u8]) -> bool {
+        !word.contains(&STATE_ERASE_VALUE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_storage::nor_flash::{NorFlash as _, ReadNorFlash as _};
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::mem_flash::MemFlash;
+    use crate::test_flash::{AsyncTestFlash, BlockingTestFlash};
+    use crate::{
+        BlockingFirmwareState, BlockingFirmwareUpdater, BootLoader, BootLoaderConfig, FirmwareState, FirmwareUpdater,
+        FirmwareUpdaterConfig, State,
+    };
+
+    // Two pages of active partition, the DFU partition being one page larger.
+    type Active = MemFlash<8192, 4096, 4>;
+    type Dfu = MemFlash<12288, 4096, 4>;
+    type StateFlash = MemFlash<4096, 4096, 4>;
+
+    fn test_flash() -> BlockingTestFlash<Active, Dfu, StateFlash> {
+        BlockingTestFlash::new(BootLoaderConfig {
+            active: Active::default(),
+            dfu: Dfu::default(),
+            state: StateFlash::default(),
+        })
+    }
+
+    fn prepare_boot(flash: &BlockingTestFlash<Active, Dfu, StateFlash>) -> State {
+        let mut page = [0; 4096];
+        BootLoader::new(BootLoaderConfig {
+            active: flash.active(),
+            dfu: flash.dfu(),
+            state: flash.state(),
+        })
+        .prepare_boot(&mut page)
+        .unwrap()
+    }
+
+    fn progress(flash: &BlockingTestFlash<Active, Dfu, StateFlash>) -> SwapProgress {
+        let mut aligned = [0; 4];
+        BlockingFirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu: flash.dfu(),
+                state: flash.state(),
+            },
+            &mut aligned,
+        )
+        .swap_progress(&flash.active())
+        .unwrap()
+    }
+
+    /// Mark an update, and write `words` progress words as an interrupted bootloader would.
+    fn interrupted(words: u32) -> BlockingTestFlash<Active, Dfu, StateFlash> {
+        let flash = test_flash();
+        let mut aligned = [0; 4];
+        BlockingFirmwareState::new(flash.state(), &mut aligned)
+            .mark_updated()
+            .unwrap();
+        for index in 0..words {
+            flash.state().write((2 + index) * 4, &[!STATE_ERASE_VALUE; 4]).unwrap();
+        }
+        flash
+    }
+
+    #[test]
+    fn follows_update_lifecycle() {
+        let flash = test_flash();
+        assert_eq!(SwapProgress::Idle, progress(&flash));
+
+        let mut aligned = [0; 4];
+        let mut updater = BlockingFirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu: flash.dfu(),
+                state: flash.state(),
+            },
+            &mut aligned,
+        );
+        updater.write_firmware(0, &[0xAA; 8192]).unwrap();
+        let mut aligned = [0; 4];
+        BlockingFirmwareState::new(flash.state(), &mut aligned)
+            .mark_updated()
+            .unwrap();
+        assert_eq!(SwapProgress::Swapping { page: 0 }, progress(&flash));
+
+        assert_eq!(State::Swap, prepare_boot(&flash));
+        assert_eq!(SwapProgress::Swapped, progress(&flash));
+
+        // The update resets before being marked booted.
+        assert_eq!(State::Swap, prepare_boot(&flash));
+        assert_eq!(SwapProgress::Reverted, progress(&flash));
+
+        let mut aligned = [0; 4];
+        BlockingFirmwareState::new(flash.state(), &mut aligned)
+            .mark_booted()
+            .unwrap();
+        assert_eq!(SwapProgress::Confirmed, progress(&flash));
+    }
+
+    #[test]
+    fn reports_interrupted_swap_and_revert() {
+        assert_eq!(SwapProgress::Swapping { page: 1 }, progress(&interrupted(3)));
+        assert_eq!(SwapProgress::Swapped, progress(&interrupted(4)));
+        assert_eq!(SwapProgress::Reverting { page: 0 }, progress(&interrupted(5)));
+        assert_eq!(SwapProgress::Reverting { page: 1 }, progress(&interrupted(7)));
+        assert_eq!(SwapProgress::Reverting { page: 2 }, progress(&interrupted(8)));
+    }
+
+    #[test]
+    fn counts_pages_of_the_bootloader() {
+        // Pages of 6144 bytes, the least common multiple of the sector sizes: two in the active
+        // partition, though the DFU partition has six sectors.
+        type Active = MemFlash<12288, 2048, 4>;
+        type Dfu = MemFlash<18432, 3072, 4>;
+        let flash = BlockingTestFlash::new(BootLoaderConfig {
+            active: Active::default(),
+            dfu: Dfu::default(),
+            state: StateFlash::default(),
+        });
+        let mut aligned = [0; 4];
+        BlockingFirmwareState::new(flash.state(), &mut aligned)
+            .mark_updated()
+            .unwrap();
+        for index in 0..4 {
+            flash.state().write((2 + index) * 4, &[!STATE_ERASE_VALUE; 4]).unwrap();
+        }
+
+        let mut aligned = [0; 4];
+        let progress = BlockingFirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu: flash.dfu(),
+                state: flash.state(),
+            },
+            &mut aligned,
+        )
+        .swap_progress(&flash.active())
+        .unwrap();
+        assert_eq!(SwapProgress::Swapped, progress);
+    }
+
+    #[test]
+    fn does_not_write_flash() {
+        let flash = interrupted(3);
+        let mut before = [0; 4096];
+        flash.state().read(0, &mut before).unwrap();
+
+        progress(&flash);
+
+        let mut after = [0; 4096];
+        flash.state().read(0, &mut after).unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn async_matches_blocking() {
+        let flash = interrupted(3).into_async();
+        let mut aligned = [0; 4];
+        let mut updater = FirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu: flash.dfu(),
+                state: flash.state(),
+            },
+            &mut aligned,
+        );
+        assert_eq!(
+            SwapProgress::Swapping { page: 1 },
+            block_on(updater.swap_progress(&flash.active())).unwrap()
+        );
+
+        let flash = AsyncTestFlash::new(BootLoaderConfig {
+            active: Active::default(),
+            dfu: Dfu::default(),
+            state: StateFlash::default(),
+        });
+        let mut aligned = [0; 4];
+        let mut state = FirmwareState::new(flash.state(), &mut aligned);
+        block_on(state.mark_booted()).unwrap();
+        assert_eq!(SwapProgress::Confirmed, block_on(state.swap_progress(2)).unwrap());
+    }
+}
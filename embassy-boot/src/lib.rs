@@ -9,7 +9,9 @@
 
 mod fmt;
 
+mod boot_info;
 mod boot_loader;
+mod crc;
 mod digest_adapters;
 mod firmware_updater;
 #[cfg(test)]
@@ -24,10 +26,13 @@ pub(crate) const STATE_ERASE_VALUE: u8 = 0xFF;
 #[cfg(feature = "flash-erase-zero")]
 pub(crate) const STATE_ERASE_VALUE: u8 = 0x00;
 
+pub use boot_info::BootInfo;
 pub use boot_loader::{BootError, BootLoader, BootLoaderConfig};
+pub use digest_adapters::Crc32;
 pub use firmware_updater::{
-    BlockingFirmwareState, BlockingFirmwareUpdater, FirmwareState, FirmwareUpdater, FirmwareUpdaterConfig,
-    FirmwareUpdaterError,
+    BlockingFirmwareState, BlockingFirmwareUpdater, BlockingFirmwareWriter, BlockingRollbackCounter, FirmwareState,
+    FirmwareUpdater, FirmwareUpdaterConfig, FirmwareUpdaterError, FirmwareWriter, RollbackCounter, RollbackError,
+    SelfTestGate, SelfTestOutcome, SwapProgress,
 };
 
 pub(crate) const REVERT_MAGIC: u8 = 0xC0;
@@ -36,7 +41,7 @@ pub(crate) const SWAP_MAGIC: u8 = 0xF0;
 pub(crate) const DFU_DETACH_MAGIC: u8 = 0xE0;
 
 /// The state of the bootloader after running prepare.
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum State {
     /// Bootloader is ready to boot the active partition.
@@ -132,6 +137,50 @@ mod tests {
         assert_eq!(State::Boot, bootloader.prepare_boot(&mut page).unwrap());
     }
 
+    #[test]
+    fn test_async_state_read_by_bootloader() {
+        let flash = AsyncTestFlash::new(BootLoaderConfig {
+            active: MemFlash::<8192, 4096, 4>::default(),
+            dfu: MemFlash::<12288, 4096, 4>::default(),
+            state: MemFlash::<4096, 4096, 4>::default(),
+        });
+
+        // The async state is written with the same encoding the blocking bootloader reads.
+        let mut aligned = [0; 4];
+        block_on(FirmwareState::new(flash.state(), &mut aligned).mark_dfu()).unwrap();
+
+        let flash = flash.into_blocking();
+        let mut page = [0; 4096];
+        let mut bootloader = BootLoader::new(BootLoaderConfig {
+            active: flash.active(),
+            dfu: flash.dfu(),
+            state: flash.state(),
+        });
+        assert_eq!(State::DfuDetach, bootloader.prepare_boot(&mut page).unwrap());
+
+        let flash = flash.into_async();
+        let mut state = FirmwareState::new(flash.state(), &mut aligned);
+        block_on(state.mark_updated()).unwrap();
+        assert_eq!(State::Swap, block_on(state.get_state()).unwrap());
+
+        let flash = flash.into_blocking();
+        let mut bootloader = BootLoader::new(BootLoaderConfig {
+            active: flash.active(),
+            dfu: flash.dfu(),
+            state: flash.state(),
+        });
+        assert_eq!(State::Swap, bootloader.prepare_boot(&mut page).unwrap());
+
+        // And the other way around.
+        let mut aligned = [0; 4];
+        let mut blocking_state = BlockingFirmwareState::new(flash.state(), &mut aligned);
+        blocking_state.mark_booted().unwrap();
+        let flash = flash.into_async();
+        let mut aligned = [0; 4];
+        let mut state = FirmwareState::new(flash.state(), &mut aligned);
+        assert_eq!(State::Boot, block_on(state.get_state()).unwrap());
+    }
+
     #[test]
     #[cfg(not(feature = "_verify"))]
     fn test_swap_state() {
@@ -302,6 +351,77 @@ mod tests {
         assert_eq!(ORIGINAL, read_buf);
     }
 
+    #[test]
+    #[cfg(not(feature = "_verify"))]
+    fn test_swap_and_revert_different_geometry() {
+        // Internal flash with 2K pages for the active partition, and an external flash with
+        // 3K sectors and 256 byte pages for the DFU partition: the bootloader works with 6K pages.
+        const FIRMWARE_SIZE: usize = 12288;
+        let flash = AsyncTestFlash::new(BootLoaderConfig {
+            active: MemFlash::<FIRMWARE_SIZE, 2048, 4>::random(),
+            dfu: MemFlash::<18432, 3072, 256>::random(),
+            state: MemFlash::<4096, 4096, 4>::random(),
+        });
+
+        let mut original = [0; FIRMWARE_SIZE];
+        let mut update = [0; FIRMWARE_SIZE];
+        for (i, (o, u)) in original.iter_mut().zip(update.iter_mut()).enumerate() {
+            *o = i as u8;
+            *u = !(i as u8) ^ (i >> 8) as u8;
+        }
+        let mut aligned = [0; 4];
+
+        block_on(flash.active().erase(0, original.len() as u32)).unwrap();
+        block_on(flash.active().write(0, &original)).unwrap();
+
+        let mut updater = FirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu: flash.dfu(),
+                state: flash.state(),
+            },
+            &mut aligned,
+        );
+        block_on(updater.write_firmware(0, &update)).unwrap();
+        block_on(updater.mark_updated()).unwrap();
+
+        let flash = flash.into_blocking();
+        let mut bootloader = BootLoader::new(BootLoaderConfig {
+            active: flash.active(),
+            dfu: flash.dfu(),
+            state: flash.state(),
+        });
+
+        let mut page = [0; 3072];
+        assert_eq!(State::Swap, bootloader.prepare_boot(&mut page).unwrap());
+
+        let mut read_buf = [0; FIRMWARE_SIZE];
+        flash.active().read(0, &mut read_buf).unwrap();
+        assert_eq!(update, read_buf);
+        // First DFU page is untouched
+        flash.dfu().read(6144, &mut read_buf).unwrap();
+        assert_eq!(original, read_buf);
+
+        // Running again should cause a revert
+        assert_eq!(State::Swap, bootloader.prepare_boot(&mut page).unwrap());
+        assert_eq!(State::Revert, bootloader.prepare_boot(&mut page).unwrap());
+
+        flash.active().read(0, &mut read_buf).unwrap();
+        assert_eq!(original, read_buf);
+        // Last DFU page is untouched
+        flash.dfu().read(0, &mut read_buf).unwrap();
+        assert_eq!(update, read_buf);
+    }
+
+    #[test]
+    #[should_panic(expected = "dfu partition size 12288 must be at least one page of 6144 bigger")]
+    fn test_dfu_partition_too_small() {
+        BootLoader::new(BootLoaderConfig {
+            active: MemFlash::<12288, 2048, 4>::default(),
+            dfu: MemFlash::<12288, 3072, 256>::default(),
+            state: MemFlash::<4096, 4096, 4>::default(),
+        });
+    }
+
     #[test]
     #[cfg(feature = "_verify")]
     fn test_verify() {
@@ -355,4 +475,59 @@ mod tests {
             .is_ok()
         );
     }
+
+    #[test]
+    #[cfg(feature = "_verify")]
+    fn test_verify_failure_keeps_state() {
+        use ed25519_dalek::{Digest, Sha512, Signature, Signer, SigningKey};
+        use embedded_storage_async::nor_flash::ReadNorFlash as AsyncReadNorFlash;
+
+        let keypair = SigningKey::from_bytes(&[7; 32]);
+
+        // The signature covers the digest of the firmware bytes only, without a length prefix.
+        let firmware: &[u8] = b"This are bytes that would otherwise be firmware bytes for DFU.";
+        let signature: Signature = keypair.sign(&Sha512::digest(firmware));
+        let public_key = keypair.verifying_key().to_bytes();
+
+        let flash = BlockingTestFlash::new(BootLoaderConfig {
+            active: MemFlash::<0, 0, 0>::default(),
+            dfu: MemFlash::<4096, 4096, 4>::default(),
+            state: MemFlash::<4096, 4096, 4>::default(),
+        });
+        let mut write_buf = [0; 4096];
+        write_buf[..firmware.len()].copy_from_slice(firmware);
+        flash.dfu().write(0, &write_buf).unwrap();
+
+        let flash = flash.into_async();
+        let mut aligned = [0; 4];
+        let mut updater = FirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu: flash.dfu(),
+                state: flash.state(),
+            },
+            &mut aligned,
+        );
+
+        // A tampered signature, or a length other than the signed one, is rejected.
+        let mut tampered = signature.to_bytes();
+        tampered[0] ^= 1;
+        for (signature, len) in [
+            (tampered, firmware.len()),
+            (signature.to_bytes(), firmware.len() - 1),
+            (signature.to_bytes(), firmware.len() + 1),
+        ] {
+            assert!(matches!(
+                block_on(updater.verify_and_mark_updated(&public_key, &signature, len as u32)),
+                Err(FirmwareUpdaterError::Signature(_))
+            ));
+        }
+        assert_eq!(State::Boot, block_on(updater.get_state()).unwrap());
+
+        let mut state = [0; 4096];
+        block_on(flash.state().read(0, &mut state)).unwrap();
+        assert!(state.iter().all(|&b| b == 0xFF));
+
+        block_on(updater.verify_and_mark_updated(&public_key, &signature.to_bytes(), firmware.len() as u32)).unwrap();
+        assert_eq!(State::Swap, block_on(updater.get_state()).unwrap());
+    }
 }
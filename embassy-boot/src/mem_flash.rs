@@ -8,6 +8,8 @@ use embedded_storage_async::nor_flash::{NorFlash as AsyncNorFlash, ReadNorFlash
 pub struct MemFlash<const SIZE: usize, const ERASE_SIZE: usize, const WRITE_SIZE: usize> {
     pub mem: [u8; SIZE],
     pub pending_write_successes: Option<usize>,
+    /// Offset of a worn byte, and the bits stuck at 1 in it.
+    pub stuck_bits: Option<(usize, u8)>,
 }
 
 #[derive(Debug)]
@@ -18,6 +20,7 @@ impl<const SIZE: usize, const ERASE_SIZE: usize, const WRITE_SIZE: usize> MemFla
         Self {
             mem: [fill; SIZE],
             pending_write_successes: None,
+            stuck_bits: None,
         }
     }
 
@@ -30,6 +33,7 @@ impl<const SIZE: usize, const ERASE_SIZE: usize, const WRITE_SIZE: usize> MemFla
         Self {
             mem,
             pending_write_successes: None,
+            stuck_bits: None,
         }
     }
 
@@ -65,6 +69,12 @@ impl<const SIZE: usize, const ERASE_SIZE: usize, const WRITE_SIZE: usize> MemFla
             *mem_byte = *new_byte;
         }
 
+        if let Some((stuck_offset, mask)) = self.stuck_bits
+            && (offset..offset + bytes.len()).contains(&stuck_offset)
+        {
+            self.mem[stuck_offset] |= mask;
+        }
+
         Ok(())
     }
 
@@ -0,0 +1,689 @@
+//! Bit-banged I2C master
+//!
+//! The pins emulate open drain outputs: setting a pin high must release the line rather than
+//! drive it, and reading it must return the level of the line. With an external or internal
+//! pull-up on both lines, the bus idles high.
+//!
+//! Devices may hold the clock low to slow the transfer down (clock stretching). Whenever the
+//! master releases the clock, it waits for the line to go high, up to
+//! [`Config::stretch_timeout`].
+//!
+//! Only 7-bit addresses are supported. A single master is expected on the bus, but losing
+//! arbitration to another master is detected.
+//!
+//! # Example (nrf52)
+//!
+//! ```rust,ignore
+//! use embassy_embedded_hal::bitbang::i2c::{Config, I2c};
+//!
+//! let mut sda = Flex::new(p.P0_03);
+//! sda.set_high();
+//! sda.set_as_input_output(Pull::Up, OutputDrive::Standard0Disconnect1);
+//! let mut scl = Flex::new(p.P0_04);
+//! scl.set_high();
+//! scl.set_as_input_output(Pull::Up, OutputDrive::Standard0Disconnect1);
+//! let mut i2c = I2c::new(sda, scl, Config::default());
+//!
+//! let mut id = [0];
+//! i2c.write_read(0x76, &[0xD0], &mut id).await?;
+//! ```
+
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_1::digital::{InputPin, OutputPin, PinState};
+use embedded_hal_async::i2c::{self, Operation, SevenBitAddress};
+
+use super::Clock;
+use crate::SetConfig;
+
+/// I2C error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The address was not acknowledged.
+    AddressNack,
+    /// A data byte was not acknowledged.
+    DataNack,
+    /// Another master pulled SDA low while this master released it.
+    ArbitrationLoss,
+    /// SDA is held low and clocking SCL doesn't release it.
+    Bus,
+    /// SCL was held low for longer than [`Config::stretch_timeout`].
+    Timeout,
+    /// Setting or reading a pin failed.
+    Pin,
+}
+
+impl i2c::Error for Error {
+    fn kind(&self) -> i2c::ErrorKind {
+        match *self {
+            Self::AddressNack => i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address),
+            Self::DataNack => i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Data),
+            Self::ArbitrationLoss => i2c::ErrorKind::ArbitrationLoss,
+            Self::Bus => i2c::ErrorKind::Bus,
+            Self::Timeout | Self::Pin => i2c::ErrorKind::Other,
+        }
+    }
+}
+
+/// Configuration of [`I2c`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// Clock frequency in Hz, see the [module documentation](super#clock-frequency) for the
+    /// frequencies that can be reached.
+    pub frequency: u32,
+    /// Longest time a device may hold SCL low.
+    pub stretch_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frequency: 100_000,
+            stretch_timeout: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Bit-banged I2C master.
+pub struct I2c<SDA, SCL> {
+    sda: SDA,
+    scl: SCL,
+    clock: Clock,
+    stretch_timeout: Duration,
+}
+
+impl<SDA, SCL> I2c<SDA, SCL>
+where
+    SDA: InputPin + OutputPin,
+    SCL: InputPin + OutputPin,
+{
+    /// Create a new I2C master.
+    ///
+    /// Both pins must be configured as open drain outputs with their input enabled.
+    ///
+    /// Panics if `config.frequency` is 0.
+    pub fn new(sda: SDA, scl: SCL, config: Config) -> Self {
+        Self {
+            sda,
+            scl,
+            clock: Clock::new(config.frequency),
+            stretch_timeout: config.stretch_timeout,
+        }
+    }
+
+    /// Release the pins.
+    pub fn release(self) -> (SDA, SCL) {
+        (self.sda, self.scl)
+    }
+
+    fn set_sda(&mut self, high: bool) -> Result<(), Error> {
+        self.sda.set_state(PinState::from(high)).map_err(|_| Error::Pin)
+    }
+
+    fn sda_is_high(&mut self) -> Result<bool, Error> {
+        self.sda.is_high().map_err(|_| Error::Pin)
+    }
+
+    fn scl_low(&mut self) -> Result<(), Error> {
+        self.scl.set_low().map_err(|_| Error::Pin)
+    }
+
+    /// Release SCL and wait for it to go high, for as long as a device stretches the clock.
+    async fn scl_release(&mut self) -> Result<(), Error> {
+        self.scl.set_high().map_err(|_| Error::Pin)?;
+        let deadline = Instant::now() + self.stretch_timeout;
+        while self.scl.is_low().map_err(|_| Error::Pin)? {
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+            Timer::after_ticks(1).await;
+        }
+        Ok(())
+    }
+
+    /// Release both lines, then free SDA if a device still holds it low, such as after a
+    /// cancelled read.
+    async fn prepare(&mut self) -> Result<(), Error> {
+        self.set_sda(true)?;
+        self.scl_release().await?;
+        self.clock.sync().await;
+        if self.sda_is_high()? {
+            return Ok(());
+        }
+
+        // The device finishes shifting out its byte on the clock pulses, then sees a NACK.
+        for _ in 0..9 {
+            self.scl_low()?;
+            self.clock.half().await;
+            self.scl_release().await?;
+            self.clock.half().await;
+            if self.sda_is_high()? {
+                return self.stop().await;
+            }
+        }
+        Err(Error::Bus)
+    }
+
+    /// Send a start or, with SCL low after a previous byte, a repeated start.
+    async fn start(&mut self) -> Result<(), Error> {
+        self.set_sda(true)?;
+        self.clock.half().await;
+        self.scl_release().await?;
+        if !self.sda_is_high()? {
+            return Err(Error::ArbitrationLoss);
+        }
+        self.clock.half().await;
+        self.set_sda(false)?;
+        self.clock.half().await;
+        self.scl_low()
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.scl_low()?;
+        self.set_sda(false)?;
+        self.clock.half().await;
+        self.scl_release().await?;
+        self.clock.half().await;
+        self.set_sda(true)?;
+        self.clock.half().await;
+        Ok(())
+    }
+
+    async fn write_bit(&mut self, high: bool) -> Result<(), Error> {
+        self.set_sda(high)?;
+        self.clock.half().await;
+        self.scl_release().await?;
+        if high && !self.sda_is_high()? {
+            return Err(Error::ArbitrationLoss);
+        }
+        self.clock.half().await;
+        self.scl_low()
+    }
+
+    async fn read_bit(&mut self) -> Result<bool, Error> {
+        self.set_sda(true)?;
+        self.clock.half().await;
+        self.scl_release().await?;
+        let high = self.sda_is_high()?;
+        self.clock.half().await;
+        self.scl_low()?;
+        Ok(high)
+    }
+
+    /// Write a byte, returning whether it was acknowledged.
+    async fn write_byte(&mut self, byte: u8) -> Result<bool, Error> {
+        for bit in (0..8).rev() {
+            self.write_bit(byte >> bit & 1 != 0).await?;
+        }
+        Ok(!self.read_bit().await?)
+    }
+
+    async fn read_byte(&mut self, ack: bool) -> Result<u8, Error> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = byte << 1 | self.read_bit().await? as u8;
+        }
+        self.write_bit(!ack).await?;
+        Ok(byte)
+    }
+
+    async fn transaction_inner(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        // Whether the previous operation was a read, to merge consecutive operations of the
+        // same kind.
+        let mut reading = None;
+        for i in 0..operations.len() {
+            let next_is_read = matches!(operations.get(i + 1), Some(Operation::Read(_)));
+            match &mut operations[i] {
+                Operation::Write(buf) => {
+                    if reading != Some(false) {
+                        self.start().await?;
+                        if !self.write_byte(address << 1).await? {
+                            return Err(Error::AddressNack);
+                        }
+                    }
+                    for &byte in buf.iter() {
+                        if !self.write_byte(byte).await? {
+                            return Err(Error::DataNack);
+                        }
+                    }
+                    reading = Some(false);
+                }
+                Operation::Read(buf) => {
+                    if reading != Some(true) {
+                        self.start().await?;
+                        if !self.write_byte(address << 1 | 1).await? {
+                            return Err(Error::AddressNack);
+                        }
+                    }
+                    // The last byte before a stop or a repeated start is not acknowledged.
+                    let len = buf.len();
+                    for (j, byte) in buf.iter_mut().enumerate() {
+                        *byte = self.read_byte(j + 1 < len || next_is_read).await?;
+                    }
+                    reading = Some(true);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<SDA, SCL> i2c::ErrorType for I2c<SDA, SCL> {
+    type Error = Error;
+}
+
+impl<SDA, SCL> i2c::I2c<SevenBitAddress> for I2c<SDA, SCL>
+where
+    SDA: InputPin + OutputPin,
+    SCL: InputPin + OutputPin,
+{
+    /// Run the operations, merging consecutive operations of the same kind.
+    ///
+    /// An empty read can't be ended, as the device may be holding SDA low. It is freed by the
+    /// next transaction.
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        self.prepare().await?;
+        match self.transaction_inner(address, operations).await {
+            Ok(()) => self.stop().await,
+            // The bus belongs to another master, or is stuck.
+            Err(e @ (Error::ArbitrationLoss | Error::Timeout | Error::Pin)) => {
+                self.sda.set_high().ok();
+                self.scl.set_high().ok();
+                Err(e)
+            }
+            Err(e) => {
+                self.stop().await?;
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<SDA, SCL> SetConfig for I2c<SDA, SCL> {
+    type Config = Config;
+    type ConfigError = ();
+
+    /// Fails if `config.frequency` is 0.
+    fn set_config(&mut self, config: &Config) -> Result<(), ()> {
+        if config.frequency == 0 {
+            return Err(());
+        }
+        self.clock = Clock::new(config.frequency);
+        self.stretch_timeout = config.stretch_timeout;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::{Cell, RefCell};
+    use core::convert::Infallible;
+    use core::pin::pin;
+    use core::task::{Context, Waker};
+
+    use embassy_time::MockDriver;
+    use embedded_hal_1::digital::ErrorType;
+    use embedded_hal_async::i2c::I2c as _;
+
+    use super::*;
+    use crate::bitbang::tests::run;
+
+    const ADDRESS: u8 = 0x42;
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    enum State {
+        /// Not addressed, waiting for a start.
+        Idle,
+        /// Receiving a byte.
+        Rx { byte: u8, bits: u8 },
+        /// Acknowledging a received byte.
+        AckOut,
+        /// Sending a byte.
+        Tx { byte: u8, bits: u8 },
+        /// Waiting for the master to acknowledge a sent byte.
+        AckIn { ack: bool },
+    }
+
+    /// Register-based device. The first byte written sets the register pointer, the next ones
+    /// are written to the registers. Reads start at the register pointer.
+    struct Device {
+        state: State,
+        /// The next received byte is the address.
+        address: bool,
+        /// The next written byte sets the register pointer.
+        pointer: bool,
+        reading: bool,
+        regs: [u8; 8],
+        ptr: usize,
+        /// Level driven on SDA, high when released.
+        sda: bool,
+        /// Time until which SCL is held low after acknowledging a byte.
+        stretch: Duration,
+        stretch_until: Instant,
+        /// Line levels last seen.
+        lines: (bool, bool),
+        starts: usize,
+    }
+
+    impl Device {
+        fn new() -> Self {
+            Self {
+                state: State::Idle,
+                address: false,
+                pointer: false,
+                reading: false,
+                regs: [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17],
+                ptr: 0,
+                sda: true,
+                stretch: Duration::from_ticks(0),
+                stretch_until: Instant::from_ticks(0),
+                lines: (true, true),
+                starts: 0,
+            }
+        }
+
+        fn scl(&self) -> bool {
+            Instant::now() >= self.stretch_until
+        }
+
+        fn send(&mut self) {
+            let byte = self.regs[self.ptr % self.regs.len()];
+            self.ptr += 1;
+            self.sda = byte & 0x80 != 0;
+            self.state = State::Tx { byte, bits: 1 };
+        }
+
+        fn scl_rise(&mut self, sda: bool) {
+            match self.state {
+                State::Rx { byte, bits } => {
+                    self.state = State::Rx {
+                        byte: byte << 1 | sda as u8,
+                        bits: bits + 1,
+                    }
+                }
+                State::AckIn { .. } => self.state = State::AckIn { ack: !sda },
+                _ => {}
+            }
+        }
+
+        fn scl_fall(&mut self) {
+            match self.state {
+                State::Rx { byte, bits: 8 } => {
+                    let ack = if self.address {
+                        self.address = false;
+                        self.reading = byte & 1 != 0;
+                        byte >> 1 == ADDRESS
+                    } else {
+                        if self.pointer {
+                            self.pointer = false;
+                            self.ptr = byte as usize;
+                        } else {
+                            self.regs[self.ptr % self.regs.len()] = byte;
+                            self.ptr += 1;
+                        }
+                        true
+                    };
+                    if ack {
+                        self.sda = false;
+                        self.state = State::AckOut;
+                        self.stretch_until = Instant::now() + self.stretch;
+                    } else {
+                        self.state = State::Idle;
+                    }
+                }
+                State::AckOut => {
+                    self.sda = true;
+                    if self.reading {
+                        self.send();
+                    } else {
+                        self.state = State::Rx { byte: 0, bits: 0 };
+                    }
+                }
+                State::Tx { bits: 8, .. } => {
+                    self.sda = true;
+                    self.state = State::AckIn { ack: false };
+                }
+                State::Tx { byte, bits } => {
+                    self.sda = byte << bits & 0x80 != 0;
+                    self.state = State::Tx { byte, bits: bits + 1 };
+                }
+                State::AckIn { ack: true } => self.send(),
+                State::AckIn { ack: false } => self.state = State::Idle,
+                _ => {}
+            }
+        }
+    }
+
+    /// Wired-AND bus between the master pins and a [`Device`].
+    struct Bus {
+        sda: Cell<bool>,
+        scl: Cell<bool>,
+        device: RefCell<Device>,
+    }
+
+    impl Bus {
+        fn new() -> Self {
+            Self {
+                sda: Cell::new(true),
+                scl: Cell::new(true),
+                device: RefCell::new(Device::new()),
+            }
+        }
+
+        /// Line levels, after letting the device react to their changes.
+        fn lines(&self) -> (bool, bool) {
+            let d = &mut *self.device.borrow_mut();
+            loop {
+                let sda = self.sda.get() && d.sda;
+                let scl = self.scl.get() && d.scl();
+                let (last_sda, last_scl) = d.lines;
+                if (sda, scl) == (last_sda, last_scl) {
+                    return (sda, scl);
+                }
+                d.lines = (sda, scl);
+
+                if scl && last_scl {
+                    if !sda {
+                        d.starts += 1;
+                        d.state = State::Rx { byte: 0, bits: 0 };
+                        d.address = true;
+                        d.pointer = true;
+                    } else {
+                        d.state = State::Idle;
+                    }
+                    d.sda = true;
+                } else if scl && !last_scl {
+                    d.scl_rise(sda);
+                } else if !scl && last_scl {
+                    d.scl_fall();
+                }
+            }
+        }
+    }
+
+    struct Pin<'a> {
+        bus: &'a Bus,
+        sda: bool,
+    }
+
+    impl ErrorType for Pin<'_> {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin<'_> {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.set_state(PinState::Low)
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.set_state(PinState::High)
+        }
+
+        fn set_state(&mut self, state: PinState) -> Result<(), Infallible> {
+            let line = if self.sda { &self.bus.sda } else { &self.bus.scl };
+            line.set(state == PinState::High);
+            self.bus.lines();
+            Ok(())
+        }
+    }
+
+    impl InputPin for Pin<'_> {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            let (sda, scl) = self.bus.lines();
+            Ok(if self.sda { sda } else { scl })
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    fn i2c(bus: &Bus, config: Config) -> I2c<Pin<'_>, Pin<'_>> {
+        I2c::new(Pin { bus, sda: true }, Pin { bus, sda: false }, config)
+    }
+
+    fn assert_idle(bus: &Bus) {
+        assert_eq!(bus.lines(), (true, true));
+        assert_eq!(bus.device.borrow().state, State::Idle);
+    }
+
+    #[test]
+    fn write_read() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        let bus = Bus::new();
+        let mut i2c = i2c(&bus, Config::default());
+
+        let mut buf = [0; 3];
+        run(i2c.write_read(ADDRESS, &[2], &mut buf)).0.unwrap();
+        assert_eq!(buf, [0x12, 0x13, 0x14]);
+        assert_eq!(bus.device.borrow().starts, 2);
+        assert_idle(&bus);
+
+        run(i2c.write(ADDRESS, &[1, 0xAA, 0xBB])).0.unwrap();
+        assert_eq!(bus.device.borrow().regs[1..4], [0xAA, 0xBB, 0x13]);
+        assert_idle(&bus);
+
+        // Reads start at the register pointer left by the previous transaction.
+        let mut buf = [0; 2];
+        run(i2c.read(ADDRESS, &mut buf)).0.unwrap();
+        assert_eq!(buf, [0x13, 0x14]);
+        assert_idle(&bus);
+    }
+
+    #[test]
+    fn merged_operations() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        let bus = Bus::new();
+        let mut i2c = i2c(&bus, Config::default());
+
+        let mut a = [0; 1];
+        let mut b = [0; 2];
+        let mut ops = [
+            Operation::Write(&[5]),
+            Operation::Write(&[0x55]),
+            Operation::Read(&mut a),
+            Operation::Read(&mut b),
+        ];
+        run(i2c.transaction(ADDRESS, &mut ops)).0.unwrap();
+        // Written to register 5, then read from 6 on: a start and a repeated start.
+        assert_eq!((a, b), ([0x16], [0x17, 0x10]));
+        assert_eq!(bus.device.borrow().regs[5], 0x55);
+        assert_eq!(bus.device.borrow().starts, 2);
+        assert_idle(&bus);
+    }
+
+    #[test]
+    fn address_nack() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        let bus = Bus::new();
+        let mut i2c = i2c(&bus, Config::default());
+
+        assert_eq!(run(i2c.write(ADDRESS + 1, &[1, 2])).0, Err(Error::AddressNack));
+        assert_idle(&bus);
+
+        // Probing with an empty write.
+        assert_eq!(run(i2c.write(ADDRESS + 1, &[])).0, Err(Error::AddressNack));
+        run(i2c.write(ADDRESS, &[])).0.unwrap();
+        assert_idle(&bus);
+    }
+
+    #[test]
+    fn clock_stretching() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        let bus = Bus::new();
+        let config = Config {
+            frequency: 100_000,
+            stretch_timeout: Duration::from_micros(500),
+        };
+        let mut i2c = i2c(&bus, config);
+
+        let (res, plain) = run(i2c.write(ADDRESS, &[1, 2]));
+        res.unwrap();
+
+        // Stretched after each of the 3 acknowledged bytes.
+        bus.device.borrow_mut().stretch = Duration::from_micros(200);
+        let (res, stretched) = run(i2c.write(ADDRESS, &[1, 3]));
+        res.unwrap();
+        assert_eq!(bus.device.borrow().regs[1], 3);
+        assert!(stretched >= plain + 3 * 190, "{} {}", plain, stretched);
+        assert_idle(&bus);
+
+        bus.device.borrow_mut().stretch = Duration::from_micros(600);
+        assert_eq!(run(i2c.write(ADDRESS, &[1, 4])).0, Err(Error::Timeout));
+        // Released by the device after the stretch, which the next transaction waits for.
+        bus.device.borrow_mut().stretch = Duration::from_ticks(0);
+        run(i2c.write(ADDRESS, &[1, 5])).0.unwrap();
+        assert_eq!(bus.device.borrow().regs[1], 5);
+        assert_idle(&bus);
+    }
+
+    #[test]
+    fn recover_after_cancel() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        let bus = Bus::new();
+        bus.device.borrow_mut().regs = [0; 8];
+        let mut i2c = i2c(&bus, Config::default());
+
+        // Cancel a read while the device drives a 0 bit.
+        {
+            let mut buf = [0; 2];
+            let mut fut = pin!(i2c.read(ADDRESS, &mut buf));
+            let mut cx = Context::from_waker(Waker::noop());
+            let driver = MockDriver::get();
+            while !matches!(bus.device.borrow().state, State::Tx { bits: 3, .. }) {
+                assert!(fut.as_mut().poll(&mut cx).is_pending());
+                driver.advance(Duration::from_ticks(1));
+            }
+        }
+        assert!(!bus.lines().0);
+
+        bus.device.borrow_mut().regs[0] = 0x99;
+        let mut buf = [0; 1];
+        run(i2c.write_read(ADDRESS, &[0], &mut buf)).0.unwrap();
+        assert_eq!(buf, [0x99]);
+        assert_idle(&bus);
+    }
+
+    #[test]
+    fn timing() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        let bus = Bus::new();
+        let mut i2c = i2c(&bus, Config::default());
+
+        // Synchronizing, then 3 half periods for the start, 2 per bit and 3 for the stop, at 5
+        // ticks per half period.
+        let (res, ticks) = run(i2c.write(ADDRESS, &[0]));
+        res.unwrap();
+        assert_eq!(ticks, 1 + 5 * (3 + 2 * 9 * 2 + 3));
+
+        let config = Config {
+            frequency: 0,
+            ..Config::default()
+        };
+        assert_eq!(i2c.set_config(&config), Err(()));
+    }
+}
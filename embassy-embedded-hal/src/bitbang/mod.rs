@@ -0,0 +1,128 @@
+//! Bit-banged buses
+//!
+//! Software [`spi::Spi`] and [`i2c::I2c`] masters for pins that can't reach a hardware
+//! peripheral, such as pins of a GPIO expander or pins left over by the board routing. They
+//! implement the async `embedded-hal` bus traits and [`SetConfig`](crate::SetConfig), so they
+//! can be shared with the device wrappers of [`shared_bus`](crate::shared_bus) like any
+//! hardware bus.
+//!
+//! Every half clock period is an `embassy-time` timer, so other tasks run while a transfer is
+//! in progress instead of the executor being blocked.
+//!
+//! # Clock frequency
+//!
+//! A half period is a whole number of time driver ticks, rounded up so the clock is never
+//! faster than the configured frequency. The highest frequency is therefore half the tick rate,
+//! and the frequencies in between are quantized. The nominal frequencies for two common tick
+//! rates:
+//!
+//! | Configured | 32 768 Hz ticks (nRF RTC) | 1 MHz ticks         |
+//! |------------|---------------------------|---------------------|
+//! | 100 Hz     | 99.9 Hz (164 ticks)       | 100 Hz (5000 ticks) |
+//! | 1 kHz      | 963.8 Hz (17 ticks)       | 1 kHz (500 ticks)   |
+//! | 5 kHz      | 4 096 Hz (4 ticks)        | 5 kHz (100 ticks)   |
+//! | 10 kHz     | 8 192 Hz (2 ticks)        | 10 kHz (50 ticks)   |
+//! | 100 kHz    | 16 384 Hz (1 tick)        | 100 kHz (5 ticks)   |
+//! | 400 kHz    | 16 384 Hz (1 tick)        | 250 kHz (2 ticks)   |
+//! | 1 MHz      | 16 384 Hz (1 tick)        | 500 kHz (1 tick)    |
+//!
+//! The ticks are those of a half period.
+//!
+//! The actual clock is slower than nominal, never faster. After each timer expires, the time
+//! driver interrupt and the executor have to run before the task sets the next edge, so edges
+//! land on tick boundaries, late by this wake-up latency. The latency varies with what else is
+//! running: a task that doesn't yield, or a higher priority interrupt, stretches the half period
+//! it happens in. The buses tolerate this, as SPI and I2C devices only have minimum timings.
+//! Each transfer starts by waiting for the next tick, so its first half period isn't cut short
+//! by starting mid-tick.
+//!
+//! For faster clocks, use a time driver with a higher tick rate, or a hardware peripheral.
+
+pub mod i2c;
+pub mod spi;
+
+use embassy_time::{Duration, TICK_HZ, Timer};
+
+/// Ticks in half a clock period at `frequency` Hz, with `tick_hz` ticks per second.
+///
+/// Rounded up, so the clock is never faster than `frequency`, and at least one tick.
+const fn half_period_ticks(tick_hz: u64, frequency: u32) -> u64 {
+    let ticks = tick_hz.div_ceil(2 * frequency as u64);
+    if ticks == 0 { 1 } else { ticks }
+}
+
+/// Clock timing shared by the masters.
+#[derive(Clone, Copy)]
+struct Clock {
+    half_period: Duration,
+}
+
+impl Clock {
+    /// Panics if `frequency` is 0.
+    fn new(frequency: u32) -> Self {
+        assert!(frequency > 0, "clock frequency must not be 0");
+        Self {
+            half_period: Duration::from_ticks(half_period_ticks(TICK_HZ, frequency)),
+        }
+    }
+
+    /// Wait for the start of the next tick, so the first half period isn't cut short.
+    async fn sync(&self) {
+        Timer::after_ticks(1).await;
+    }
+
+    /// Wait for half a clock period.
+    async fn half(&self) {
+        Timer::after(self.half_period).await;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+
+    use embassy_time::MockDriver;
+
+    use super::*;
+
+    /// Poll `fut` to completion, advancing the mock time by a tick whenever it is pending.
+    /// Returns its output and the number of ticks it took.
+    ///
+    /// The caller must hold [`MOCK_TIME`](crate::MOCK_TIME). The time isn't reset, so that it
+    /// keeps increasing across the calls of a test.
+    pub(crate) fn run<F: Future>(fut: F) -> (F::Output, u64) {
+        let driver = MockDriver::get();
+
+        let mut fut = pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut ticks = 0;
+        loop {
+            if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                return (output, ticks);
+            }
+            driver.advance(Duration::from_ticks(1));
+            ticks += 1;
+        }
+    }
+
+    #[test]
+    fn frequency_table() {
+        // The rows of the table in the module documentation.
+        let rows = [
+            (100, 164, 5000),
+            (1_000, 17, 500),
+            (5_000, 4, 100),
+            (10_000, 2, 50),
+            (100_000, 1, 5),
+            (400_000, 1, 2),
+            (1_000_000, 1, 1),
+        ];
+        for (frequency, rtc, mhz) in rows {
+            assert_eq!(half_period_ticks(32_768, frequency), rtc, "{} Hz", frequency);
+            assert_eq!(half_period_ticks(1_000_000, frequency), mhz, "{} Hz", frequency);
+        }
+        assert_eq!(half_period_ticks(1_000_000, u32::MAX), 1);
+    }
+}
@@ -0,0 +1,407 @@
+//! Bit-banged SPI master
+//!
+//! # Example (nrf52)
+//!
+//! ```rust,ignore
+//! use embassy_embedded_hal::bitbang::spi::{Config, Spi};
+//! use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice;
+//!
+//! let sck = Output::new(p.P0_03, Level::Low, OutputDrive::Standard);
+//! let mosi = Output::new(p.P0_04, Level::Low, OutputDrive::Standard);
+//! let miso = Input::new(p.P0_28, Pull::None);
+//! let spi = Mutex::<NoopRawMutex, _>::new(Spi::new(sck, mosi, miso, Config::default()));
+//!
+//! let cs = Output::new(p.P0_29, Level::High, OutputDrive::Standard);
+//! let mut device = SpiDevice::new(&spi, cs);
+//! ```
+
+use embedded_hal_1::digital::{InputPin, OutputPin, PinState};
+use embedded_hal_1::spi::{MODE_0, Mode, Phase, Polarity};
+use embedded_hal_async::spi;
+
+use super::Clock;
+use crate::SetConfig;
+
+/// SPI error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Setting or reading a pin failed.
+    Pin,
+}
+
+impl spi::Error for Error {
+    fn kind(&self) -> spi::ErrorKind {
+        spi::ErrorKind::Other
+    }
+}
+
+/// Configuration of [`Spi`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// Clock frequency in Hz, see the [module documentation](super#clock-frequency) for the
+    /// frequencies that can be reached.
+    pub frequency: u32,
+    /// Clock polarity and phase.
+    pub mode: Mode,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frequency: 1_000_000,
+            mode: MODE_0,
+        }
+    }
+}
+
+/// Bit-banged SPI master, sending the most significant bit first.
+pub struct Spi<SCK, MOSI, MISO> {
+    sck: SCK,
+    mosi: MOSI,
+    miso: MISO,
+    clock: Clock,
+    mode: Mode,
+}
+
+impl<SCK, MOSI, MISO> Spi<SCK, MOSI, MISO>
+where
+    SCK: OutputPin,
+    MOSI: OutputPin,
+    MISO: InputPin,
+{
+    /// Create a new SPI master.
+    ///
+    /// `sck` should already be at the idle level of `config.mode`, low for modes 0 and 1.
+    /// Otherwise it is only set to it by the first transfer, after the device's chip select
+    /// may have been asserted.
+    ///
+    /// Panics if `config.frequency` is 0.
+    pub fn new(sck: SCK, mosi: MOSI, miso: MISO, config: Config) -> Self {
+        Self {
+            sck,
+            mosi,
+            miso,
+            clock: Clock::new(config.frequency),
+            mode: config.mode,
+        }
+    }
+
+    /// Release the pins.
+    pub fn release(self) -> (SCK, MOSI, MISO) {
+        (self.sck, self.mosi, self.miso)
+    }
+
+    fn set_sck(&mut self, active: bool) -> Result<(), Error> {
+        let idle_high = self.mode.polarity == Polarity::IdleHigh;
+        self.sck
+            .set_state(PinState::from(active != idle_high))
+            .map_err(|_| Error::Pin)
+    }
+
+    /// Return the clock to its idle level, in case a transfer was cancelled, and wait for the
+    /// start of a tick.
+    async fn start(&mut self) -> Result<(), Error> {
+        self.set_sck(false)?;
+        self.clock.sync().await;
+        Ok(())
+    }
+
+    async fn transfer_byte(&mut self, out: u8) -> Result<u8, Error> {
+        let mut input = 0;
+        for bit in (0..8).rev() {
+            // The data is set up half a period before the sampling edge: on the leading edge
+            // in phase 1, while the clock is idle in phase 0.
+            if self.mode.phase == Phase::CaptureOnSecondTransition {
+                self.set_sck(true)?;
+            }
+            self.mosi
+                .set_state(PinState::from(out >> bit & 1 != 0))
+                .map_err(|_| Error::Pin)?;
+            self.clock.half().await;
+
+            input = input << 1 | self.miso.is_high().map_err(|_| Error::Pin)? as u8;
+            self.set_sck(self.mode.phase == Phase::CaptureOnFirstTransition)?;
+            self.clock.half().await;
+
+            if self.mode.phase == Phase::CaptureOnFirstTransition {
+                self.set_sck(false)?;
+            }
+        }
+        Ok(input)
+    }
+}
+
+impl<SCK, MOSI, MISO> spi::ErrorType for Spi<SCK, MOSI, MISO> {
+    type Error = Error;
+}
+
+impl<SCK, MOSI, MISO> spi::SpiBus<u8> for Spi<SCK, MOSI, MISO>
+where
+    SCK: OutputPin,
+    MOSI: OutputPin,
+    MISO: InputPin,
+{
+    async fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        self.transfer(words, &[]).await
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        self.transfer(&mut [], words).await
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let len = read.len().max(write.len());
+        if len == 0 {
+            return Ok(());
+        }
+
+        self.start().await?;
+        for i in 0..len {
+            let input = self.transfer_byte(write.get(i).copied().unwrap_or(0)).await?;
+            if let Some(word) = read.get_mut(i) {
+                *word = input;
+            }
+        }
+        Ok(())
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        if words.is_empty() {
+            return Ok(());
+        }
+
+        self.start().await?;
+        for word in words {
+            *word = self.transfer_byte(*word).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<SCK, MOSI, MISO> SetConfig for Spi<SCK, MOSI, MISO>
+where
+    SCK: OutputPin,
+{
+    type Config = Config;
+    type ConfigError = ();
+
+    /// Fails if `config.frequency` is 0, or if setting the clock to its new idle level fails.
+    fn set_config(&mut self, config: &Config) -> Result<(), ()> {
+        if config.frequency == 0 {
+            return Err(());
+        }
+        self.clock = Clock::new(config.frequency);
+        self.mode = config.mode;
+        let idle_high = self.mode.polarity == Polarity::IdleHigh;
+        self.sck.set_state(PinState::from(idle_high)).map_err(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::{Cell, RefCell};
+    use core::convert::Infallible;
+
+    use embedded_hal_1::digital::ErrorType;
+    use embedded_hal_1::spi::{MODE_1, MODE_2, MODE_3};
+    use embedded_hal_async::spi::SpiBus;
+
+    use super::*;
+    use crate::bitbang::tests::run;
+
+    struct MockOut<'a>(&'a Cell<bool>);
+
+    impl ErrorType for MockOut<'_> {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for MockOut<'_> {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0.set(false);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0.set(true);
+            Ok(())
+        }
+    }
+
+    struct MockIn<'a>(&'a Cell<bool>);
+
+    impl ErrorType for MockIn<'_> {
+        type Error = Infallible;
+    }
+
+    impl InputPin for MockIn<'_> {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0.get())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0.get())
+        }
+    }
+
+    /// Device shifting a byte in and out on the edges of its SPI mode.
+    struct Device<'a> {
+        mode: Mode,
+        mosi: &'a Cell<bool>,
+        miso: &'a Cell<bool>,
+        sck: bool,
+        tx: u8,
+        rx: u8,
+        edges: usize,
+    }
+
+    impl Device<'_> {
+        fn shift_out(&mut self) {
+            self.miso.set(self.tx & 0x80 != 0);
+            self.tx <<= 1;
+        }
+    }
+
+    /// Clock pin driving a [`Device`].
+    struct DeviceClock<'a, 'b>(&'a RefCell<Device<'b>>);
+
+    impl ErrorType for DeviceClock<'_, '_> {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for DeviceClock<'_, '_> {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.set_state(PinState::Low)
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.set_state(PinState::High)
+        }
+
+        fn set_state(&mut self, state: PinState) -> Result<(), Infallible> {
+            let d = &mut *self.0.borrow_mut();
+            let high = state == PinState::High;
+            if high == d.sck {
+                return Ok(());
+            }
+            d.sck = high;
+            d.edges += 1;
+
+            let leading = high != (d.mode.polarity == Polarity::IdleHigh);
+            let capture = leading == (d.mode.phase == Phase::CaptureOnFirstTransition);
+            if capture {
+                d.rx = d.rx << 1 | d.mosi.get() as u8;
+            } else if d.mode.phase == Phase::CaptureOnSecondTransition || d.edges < 16 {
+                // In phase 0, nothing is shifted out after the last bit.
+                d.shift_out();
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn loopback() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+
+        for mode in [MODE_0, MODE_1, MODE_2, MODE_3] {
+            let sck = Cell::new(mode.polarity == Polarity::IdleHigh);
+            let data = Cell::new(false);
+            let config = Config {
+                frequency: 100_000,
+                mode,
+            };
+            let mut spi = Spi::new(MockOut(&sck), MockOut(&data), MockIn(&data), config);
+
+            let mut buf = [0xA5, 0x3C, 0xFF, 0x00, 0x81];
+            let (res, _) = run(spi.transfer_in_place(&mut buf));
+            res.unwrap();
+            assert_eq!(buf, [0xA5, 0x3C, 0xFF, 0x00, 0x81], "{:?}", mode);
+
+            let mut read = [0; 3];
+            let (res, _) = run(spi.transfer(&mut read, &[0x12, 0x34]));
+            res.unwrap();
+            assert_eq!(read, [0x12, 0x34, 0x00], "{:?}", mode);
+
+            assert_eq!(sck.get(), mode.polarity == Polarity::IdleHigh, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn modes() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+
+        for mode in [MODE_0, MODE_1, MODE_2, MODE_3] {
+            let mosi = Cell::new(false);
+            let miso = Cell::new(false);
+            let device = RefCell::new(Device {
+                mode,
+                mosi: &mosi,
+                miso: &miso,
+                sck: mode.polarity == Polarity::IdleHigh,
+                tx: 0x5A,
+                rx: 0,
+                edges: 0,
+            });
+            // In phase 0, the first bit is shifted out when the chip select is asserted.
+            if mode.phase == Phase::CaptureOnFirstTransition {
+                device.borrow_mut().shift_out();
+            }
+
+            let config = Config {
+                frequency: 100_000,
+                mode,
+            };
+            let mut spi = Spi::new(DeviceClock(&device), MockOut(&mosi), MockIn(&miso), config);
+            let mut buf = [0x96];
+            let (res, _) = run(spi.transfer_in_place(&mut buf));
+            res.unwrap();
+
+            let device = device.borrow();
+            assert_eq!(buf, [0x5A], "{:?}", mode);
+            assert_eq!(device.rx, 0x96, "{:?}", mode);
+            assert_eq!(device.edges, 16, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn timing() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+
+        let sck = Cell::new(false);
+        let data = Cell::new(false);
+        let config = Config {
+            frequency: 100_000,
+            mode: MODE_0,
+        };
+        let mut spi = Spi::new(MockOut(&sck), MockOut(&data), MockIn(&data), config);
+
+        // One tick to synchronize, then 5 ticks per half period at 1 MHz ticks.
+        let (res, ticks) = run(spi.write(&[0; 2]));
+        res.unwrap();
+        assert_eq!(ticks, 1 + 2 * 16 * 5);
+
+        // Requests above half the tick rate are clamped to it.
+        let config = Config {
+            frequency: 2_000_000,
+            mode: MODE_0,
+        };
+        spi.set_config(&config).unwrap();
+        let (res, ticks) = run(spi.write(&[0]));
+        res.unwrap();
+        assert_eq!(ticks, 1 + 16);
+
+        let config = Config {
+            frequency: 0,
+            mode: MODE_0,
+        };
+        assert_eq!(spi.set_config(&config), Err(()));
+    }
+}
@@ -0,0 +1,419 @@
+//! Button gestures
+//!
+//! [`ButtonEvents`] debounces a push button and classifies its presses into [`ButtonEvent`]s:
+//! clicks, double clicks, long presses and hold-repeats. It only needs the [`InputPin`] and
+//! [`Wait`] traits, so it works with HAL pins as well as with pins of GPIO expanders.
+//!
+//! # Example (nrf52)
+//!
+//! ```rust,ignore
+//! use embassy_embedded_hal::button::{ButtonConfig, ButtonEvent, ButtonEvents};
+//!
+//! let mut config = ButtonConfig::default();
+//! config.repeat = Some(Duration::from_millis(200));
+//! let mut button = ButtonEvents::new(Input::new(p.P0_11, Pull::Up), config);
+//!
+//! loop {
+//!     match button.next().await.unwrap() {
+//!         ButtonEvent::Click => info!("click"),
+//!         ButtonEvent::DoubleClick => info!("double click"),
+//!         ButtonEvent::LongPress => info!("long press"),
+//!         ButtonEvent::Repeat => info!("repeat"),
+//!     }
+//! }
+//! ```
+
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_1::digital::InputPin;
+use embedded_hal_async::digital::Wait;
+
+/// Classified button event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ButtonEvent {
+    /// The button was pressed and released, and not pressed again within the double click window.
+    Click,
+    /// The button was clicked twice, the second press starting within the double click window.
+    DoubleClick,
+    /// The button has been held down for the long press time.
+    LongPress,
+    /// The button is still held down after a long press. Emitted every repeat interval.
+    Repeat,
+}
+
+/// Pin level while the button is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ActiveLevel {
+    /// The pin is low while pressed, such as for a button to ground with a pull-up.
+    Low,
+    /// The pin is high while pressed.
+    High,
+}
+
+/// Configuration of [`ButtonEvents`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ButtonConfig {
+    /// Pin level while the button is pressed.
+    pub active_level: ActiveLevel,
+    /// Time the pin level must be stable for an edge to count.
+    pub debounce: Duration,
+    /// Time the button must be held down for a [`ButtonEvent::LongPress`].
+    ///
+    /// A press that lasts exactly this long is a long press.
+    pub long_press: Duration,
+    /// Time after a release in which a second press makes a [`ButtonEvent::DoubleClick`].
+    ///
+    /// A second press exactly this long after the release starts a new gesture.
+    pub double_click: Duration,
+    /// Interval of [`ButtonEvent::Repeat`] while the button is held down after a long press, or
+    /// `None` to not repeat.
+    pub repeat: Option<Duration>,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            active_level: ActiveLevel::Low,
+            debounce: Duration::from_millis(20),
+            long_press: Duration::from_secs(1),
+            double_click: Duration::from_millis(300),
+            repeat: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Idle,
+    /// Pressed at `at`, `second` if it is the second press of a double click.
+    Pressed {
+        at: Instant,
+        second: bool,
+    },
+    /// Released at `at` after a short press.
+    Released {
+        at: Instant,
+    },
+    /// Held down after a long press, the next repeat is due at `next` if repeating.
+    Held {
+        next: Option<Instant>,
+    },
+}
+
+/// Push button producing classified [`ButtonEvent`]s.
+///
+/// Every gesture produces exactly one event, except that a long press keeps producing
+/// [`ButtonEvent::Repeat`] while held if configured. In particular a long press doesn't also
+/// produce a click, and a double click doesn't also produce clicks. If the second press of a
+/// double click turns into a long press, a [`ButtonEvent::Click`] for the first press is produced
+/// followed by a [`ButtonEvent::LongPress`].
+///
+/// Events are only produced while [`next`](Self::next) is awaited.
+pub struct ButtonEvents<P> {
+    pin: P,
+    config: ButtonConfig,
+    state: State,
+    pending: Option<ButtonEvent>,
+}
+
+impl<P: InputPin + Wait> ButtonEvents<P> {
+    /// Create a new `ButtonEvents`.
+    pub fn new(pin: P, config: ButtonConfig) -> Self {
+        Self {
+            pin,
+            config,
+            state: State::Idle,
+            pending: None,
+        }
+    }
+
+    /// Consume the `ButtonEvents`, returning the pin.
+    pub fn into_inner(self) -> P {
+        self.pin
+    }
+
+    /// Wait for the next button event.
+    pub async fn next(&mut self) -> Result<ButtonEvent, P::Error> {
+        if let Some(event) = self.pending.take() {
+            return Ok(event);
+        }
+
+        loop {
+            match self.state {
+                State::Idle => {
+                    if let Some(at) = self.wait_for(true, Instant::MAX).await? {
+                        self.state = State::Pressed { at, second: false };
+                    }
+                }
+                State::Pressed { at, second } => match self.wait_for(false, at + self.config.long_press).await? {
+                    Some(_) if second => {
+                        self.state = State::Idle;
+                        return Ok(ButtonEvent::DoubleClick);
+                    }
+                    Some(released) => self.state = State::Released { at: released },
+                    None => {
+                        self.state = State::Held {
+                            next: self.config.repeat.map(|r| at + self.config.long_press + r),
+                        };
+                        if second {
+                            self.pending = Some(ButtonEvent::LongPress);
+                            return Ok(ButtonEvent::Click);
+                        }
+                        return Ok(ButtonEvent::LongPress);
+                    }
+                },
+                State::Released { at } => match self.wait_for(true, at + self.config.double_click).await? {
+                    Some(pressed) => {
+                        self.state = State::Pressed {
+                            at: pressed,
+                            second: true,
+                        }
+                    }
+                    None => {
+                        self.state = State::Idle;
+                        return Ok(ButtonEvent::Click);
+                    }
+                },
+                State::Held { next } => match self.wait_for(false, next.unwrap_or(Instant::MAX)).await? {
+                    Some(_) => self.state = State::Idle,
+                    None => {
+                        self.state = State::Held {
+                            next: next.zip(self.config.repeat).map(|(next, r)| next + r),
+                        };
+                        return Ok(ButtonEvent::Repeat);
+                    }
+                },
+            }
+        }
+    }
+
+    fn is_pressed(&mut self) -> Result<bool, P::Error> {
+        match self.config.active_level {
+            ActiveLevel::Low => self.pin.is_low(),
+            ActiveLevel::High => self.pin.is_high(),
+        }
+    }
+
+    /// Wait until the button is stably `pressed` (or released), returning when the edge happened.
+    ///
+    /// Returns `None` if no edge happened before `deadline`. An edge exactly at the deadline is
+    /// too late.
+    async fn wait_for(&mut self, pressed: bool, deadline: Instant) -> Result<Option<Instant>, P::Error> {
+        loop {
+            if self.is_pressed()? != pressed {
+                match select(Timer::at(deadline), self.pin.wait_for_any_edge()).await {
+                    Either::First(()) => return Ok(None),
+                    Either::Second(res) => res?,
+                }
+            }
+
+            // Only the edge has to happen before the deadline, debouncing may take longer.
+            let at = Instant::now();
+            Timer::after(self.config.debounce).await;
+            if self.is_pressed()? == pressed {
+                return Ok(Some(at));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use core::future::{Future, poll_fn};
+    use core::task::{Context, Poll, Waker};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use embassy_time::MockDriver;
+    use embedded_hal_1::digital::ErrorType;
+
+    use super::*;
+
+    /// Pin whose level is set by the test. Edges are detected by polling.
+    struct MockPin<'a>(&'a Cell<bool>);
+
+    impl MockPin<'_> {
+        async fn wait_until(&mut self, f: impl Fn(bool, bool) -> bool) -> Result<(), Infallible> {
+            let start = self.0.get();
+            poll_fn(|_| {
+                if f(start, self.0.get()) {
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    impl ErrorType for MockPin<'_> {
+        type Error = Infallible;
+    }
+
+    impl InputPin for MockPin<'_> {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            Ok(self.0.get())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0.get())
+        }
+    }
+
+    impl Wait for MockPin<'_> {
+        async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+            self.wait_until(|_, now| now).await
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+            self.wait_until(|_, now| !now).await
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+            self.wait_until(|start, now| !start && now).await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+            self.wait_until(|start, now| start && !now).await
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+            self.wait_until(|start, now| start != now).await
+        }
+    }
+
+    /// Run the button for 3 s of mock time, pressing and releasing it at the given milliseconds.
+    /// Returns the events with the millisecond they were produced at.
+    fn run(config: ButtonConfig, presses: &[(u64, u64)]) -> Vec<(u64, ButtonEvent)> {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        let driver = MockDriver::get();
+        driver.reset();
+
+        let active_high = config.active_level == ActiveLevel::High;
+        let level = Cell::new(!active_high);
+        let mut button = ButtonEvents::new(MockPin(&level), config);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut events = Vec::new();
+        let mut next = Box::pin(button.next());
+
+        for ms in 0..3000 {
+            for &(press, release) in presses {
+                if ms == press {
+                    level.set(active_high);
+                }
+                if ms == release {
+                    level.set(!active_high);
+                }
+            }
+
+            while let Poll::Ready(event) = next.as_mut().poll(&mut cx) {
+                events.push((ms, event.unwrap()));
+                drop(next);
+                next = Box::pin(button.next());
+            }
+
+            driver.advance(Duration::from_millis(1));
+        }
+
+        events
+    }
+
+    fn run_default(presses: &[(u64, u64)]) -> Vec<(u64, ButtonEvent)> {
+        run(ButtonConfig::default(), presses)
+    }
+
+    #[test]
+    fn click() {
+        // Produced when the double click window after the release expires.
+        assert_eq!(&[(410, ButtonEvent::Click)], &run_default(&[(10, 110)])[..]);
+    }
+
+    #[test]
+    fn click_active_high() {
+        let config = ButtonConfig {
+            active_level: ActiveLevel::High,
+            ..Default::default()
+        };
+        assert_eq!(&[(410, ButtonEvent::Click)], &run(config, &[(10, 110)])[..]);
+    }
+
+    #[test]
+    fn release_just_before_long_press() {
+        assert_eq!(&[(1309, ButtonEvent::Click)], &run_default(&[(10, 1009)])[..]);
+    }
+
+    #[test]
+    fn release_exactly_at_long_press() {
+        assert_eq!(&[(1010, ButtonEvent::LongPress)], &run_default(&[(10, 1010)])[..]);
+    }
+
+    #[test]
+    fn long_press_without_click() {
+        assert_eq!(&[(1010, ButtonEvent::LongPress)], &run_default(&[(10, 2000)])[..]);
+    }
+
+    #[test]
+    fn double_click_just_inside_window() {
+        // Produced once the second release is debounced.
+        assert_eq!(
+            &[(520, ButtonEvent::DoubleClick)],
+            &run_default(&[(10, 110), (409, 500)])[..]
+        );
+    }
+
+    #[test]
+    fn second_press_exactly_at_window_edge() {
+        assert_eq!(
+            &[(410, ButtonEvent::Click), (800, ButtonEvent::Click)],
+            &run_default(&[(10, 110), (410, 500)])[..]
+        );
+    }
+
+    #[test]
+    fn click_then_long_press() {
+        assert_eq!(
+            &[(1200, ButtonEvent::Click), (1200, ButtonEvent::LongPress)],
+            &run_default(&[(10, 110), (200, 2000)])[..]
+        );
+    }
+
+    #[test]
+    fn repeat_while_held() {
+        let config = ButtonConfig {
+            repeat: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+
+        // Released exactly when the second repeat is due.
+        assert_eq!(
+            &[
+                (1010, ButtonEvent::LongPress),
+                (1210, ButtonEvent::Repeat),
+                (1410, ButtonEvent::Repeat)
+            ],
+            &run(config, &[(10, 1410)])[..]
+        );
+        assert_eq!(
+            &[(1010, ButtonEvent::LongPress), (1210, ButtonEvent::Repeat)],
+            &run(config, &[(10, 1409)])[..]
+        );
+    }
+
+    #[test]
+    fn bounces_are_ignored() {
+        // Glitch shorter than the debounce time.
+        assert_eq!(&[] as &[(u64, ButtonEvent)], &run_default(&[(10, 15)])[..]);
+        // Bouncing contacts on press and release.
+        assert_eq!(
+            &[(500, ButtonEvent::Click)],
+            &run_default(&[(10, 12), (14, 200), (202, 204)])[..]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use aligned::{A4, Aligned};
+use block_device_driver::{BlockDevice, blocks_to_slice, blocks_to_slice_mut};
+use embedded_storage_async::nor_flash::NorFlash;
+
+const BLOCK_SIZE: usize = 512;
+
+/// A block device with 512 byte blocks, stored in a flash
+///
+/// Writing a block erases it first. The flash must be able to erase and write 512 byte blocks,
+/// so a flash with larger sectors, such as a QSPI NOR flash with 4K sectors, must be wrapped in a
+/// [`RmwPartition`](crate::flash::partition::RmwPartition) with an erase size of 512 bytes:
+///
+/// ```rust,ignore
+/// let mut sector = [0; 4096];
+/// let flash = RmwPartition::<_, 512>::new(qspi, &mut sector);
+/// let device = FlashBlockDevice::new(flash);
+/// ```
+pub struct FlashBlockDevice<F: NorFlash> {
+    flash: F,
+}
+
+impl<F: NorFlash> FlashBlockDevice<F> {
+    /// Create a new block device stored in `flash`
+    ///
+    /// The erase, write and read sizes of `flash` must divide 512.
+    pub fn new(flash: F) -> Self {
+        assert!(
+            BLOCK_SIZE.is_multiple_of(F::ERASE_SIZE)
+                && BLOCK_SIZE.is_multiple_of(F::WRITE_SIZE)
+                && BLOCK_SIZE.is_multiple_of(F::READ_SIZE),
+            "Flash erase, write and read sizes must divide the block size"
+        );
+        Self { flash }
+    }
+
+    /// Release the underlying flash
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+}
+
+impl<F: NorFlash> BlockDevice<BLOCK_SIZE> for FlashBlockDevice<F> {
+    type Error = F::Error;
+    type Align = A4;
+
+    async fn read(&mut self, block_address: u32, data: &mut [Aligned<A4, [u8; BLOCK_SIZE]>]) -> Result<(), F::Error> {
+        let offset = block_address * BLOCK_SIZE as u32;
+        self.flash.read(offset, blocks_to_slice_mut(data)).await
+    }
+
+    async fn write(&mut self, block_address: u32, data: &[Aligned<A4, [u8; BLOCK_SIZE]>]) -> Result<(), F::Error> {
+        let offset = block_address * BLOCK_SIZE as u32;
+        let data = blocks_to_slice(data);
+        self.flash.erase(offset, offset + data.len() as u32).await?;
+        self.flash.write(offset, data).await
+    }
+
+    async fn size(&mut self) -> Result<u64, F::Error> {
+        Ok(self.flash.capacity() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_sync::mutex::Mutex;
+
+    use super::*;
+    use crate::flash::mem_flash::MemFlash;
+    use crate::flash::partition::{Partition, RmwPartition};
+
+    #[futures_test::test]
+    async fn writes_blocks_of_larger_sectors() {
+        let flash = Mutex::<NoopRawMutex, _>::new(MemFlash::<8192, 4096, 4>::new(0x00));
+        let mut sector = [0; 4096];
+        let mut device = FlashBlockDevice::new(RmwPartition::<_, 512>::new(
+            Partition::new(&flash, 0, 8192),
+            &mut sector,
+        ));
+        assert_eq!(8192, device.size().await.unwrap());
+
+        let blocks = [Aligned([0xAA; 512]), Aligned([0x55; 512])];
+        device.write(7, &blocks).await.unwrap();
+
+        let mut read = [Aligned([0; 512]); 3];
+        device.read(6, &mut read).await.unwrap();
+        assert!(read[0].iter().all(|&b| b == 0x00));
+        assert!(read[1].iter().all(|&b| b == 0xAA));
+        assert!(read[2].iter().all(|&b| b == 0x55));
+    }
+}
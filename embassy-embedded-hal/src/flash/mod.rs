@@ -1,8 +1,15 @@
 //! Utilities related to flash.
 
+#[cfg(feature = "block-device-driver")]
+mod block_device;
 mod concat_flash;
 #[cfg(test)]
 pub(crate) mod mem_flash;
 pub mod partition;
+#[cfg(feature = "time")]
+pub mod scheduler;
+pub mod spi_nor;
 
+#[cfg(feature = "block-device-driver")]
+pub use block_device::FlashBlockDevice;
 pub use concat_flash::ConcatFlash;
@@ -3,7 +3,7 @@ use embassy_sync::mutex::Mutex;
 use embedded_storage::nor_flash::ErrorType;
 use embedded_storage_async::nor_flash::{MultiwriteNorFlash, NorFlash, ReadNorFlash};
 
-use super::Error;
+use super::{Error, check_area, check_erase, dirty_runs, sectors};
 
 /// A logical partition of an underlying shared flash
 ///
@@ -60,9 +60,7 @@ impl<M: RawMutex, T: NorFlash> ReadNorFlash for Partition<'_, M, T> {
     const READ_SIZE: usize = T::READ_SIZE;
 
     async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
-        if offset + bytes.len() as u32 > self.size {
-            return Err(Error::OutOfBounds);
-        }
+        check_area(self.size, T::READ_SIZE, offset, bytes.len())?;
 
         let mut flash = self.flash.lock().await;
         flash.read(self.offset + offset, bytes).await.map_err(Error::Flash)
@@ -78,18 +76,14 @@ impl<M: RawMutex, T: NorFlash> NorFlash for Partition<'_, M, T> {
     const ERASE_SIZE: usize = T::ERASE_SIZE;
 
     async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
-        if offset + bytes.len() as u32 > self.size {
-            return Err(Error::OutOfBounds);
-        }
+        check_area(self.size, T::WRITE_SIZE, offset, bytes.len())?;
 
         let mut flash = self.flash.lock().await;
         flash.write(self.offset + offset, bytes).await.map_err(Error::Flash)
     }
 
     async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
-        if to > self.size {
-            return Err(Error::OutOfBounds);
-        }
+        check_erase(self.size, T::ERASE_SIZE, from, to)?;
 
         let mut flash = self.flash.lock().await;
         flash
@@ -101,6 +95,112 @@ impl<M: RawMutex, T: NorFlash> NorFlash for Partition<'_, M, T> {
 
 impl<M: RawMutex, T: MultiwriteNorFlash> MultiwriteNorFlash for Partition<'_, M, T> {}
 
+/// A flash with a logical erase size smaller than the erase size of the underlying flash
+///
+/// Erasing part of a sector of the underlying flash reads the whole sector into a scratch
+/// buffer, erases it, and writes back the data that must be kept. This lets storage built
+/// for small sectors use a flash with larger ones, at the cost of extra wear.
+///
+/// The erase of a partial sector is not atomic: the data kept in the sector is lost if
+/// power fails before it is written back.
+///
+/// `ERASE_VALUE` is the value of the bytes of an erased sector of `flash`, `0xFF` for most NOR
+/// flashes. It must be set for flashes that erase to another value, such as `0x00`.
+pub struct RmwPartition<'a, F: NorFlash, const ERASE_SIZE: usize, const ERASE_VALUE: u8 = 0xFF> {
+    flash: F,
+    buf: &'a mut [u8],
+}
+
+impl<'a, F: NorFlash, const ERASE_SIZE: usize, const ERASE_VALUE: u8> RmwPartition<'a, F, ERASE_SIZE, ERASE_VALUE> {
+    /// Create a new partition, using `buf` to hold a sector of `flash`
+    ///
+    /// `buf` must be at least as large as the erase size of `flash`, and `ERASE_SIZE` must divide
+    /// the erase size of `flash` and be a multiple of its write size.
+    pub fn new(flash: F, buf: &'a mut [u8]) -> Self {
+        assert!(
+            ERASE_SIZE != 0 && F::ERASE_SIZE.is_multiple_of(ERASE_SIZE) && ERASE_SIZE.is_multiple_of(F::WRITE_SIZE),
+            "Erase size must divide the flash erase size, and be a multiple of its write size"
+        );
+        assert!(
+            buf.len() >= F::ERASE_SIZE,
+            "Buffer must be at least as large as the flash erase size"
+        );
+        Self {
+            flash,
+            buf: &mut buf[..F::ERASE_SIZE],
+        }
+    }
+
+    /// Release the underlying flash
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+}
+
+impl<F: NorFlash, const ERASE_SIZE: usize, const ERASE_VALUE: u8> ErrorType
+    for RmwPartition<'_, F, ERASE_SIZE, ERASE_VALUE>
+{
+    type Error = Error<F::Error>;
+}
+
+impl<F: NorFlash, const ERASE_SIZE: usize, const ERASE_VALUE: u8> ReadNorFlash
+    for RmwPartition<'_, F, ERASE_SIZE, ERASE_VALUE>
+{
+    const READ_SIZE: usize = F::READ_SIZE;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_area(self.capacity() as u32, F::READ_SIZE, offset, bytes.len())?;
+        self.flash.read(offset, bytes).await.map_err(Error::Flash)
+    }
+
+    fn capacity(&self) -> usize {
+        self.flash.capacity()
+    }
+}
+
+impl<F: NorFlash, const ERASE_SIZE: usize, const ERASE_VALUE: u8> NorFlash
+    for RmwPartition<'_, F, ERASE_SIZE, ERASE_VALUE>
+{
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_area(self.capacity() as u32, F::WRITE_SIZE, offset, bytes.len())?;
+        self.flash.write(offset, bytes).await.map_err(Error::Flash)
+    }
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self.capacity() as u32, ERASE_SIZE, from, to)?;
+
+        for (sector, range) in sectors(from, to, F::ERASE_SIZE) {
+            let sector_end = sector + F::ERASE_SIZE as u32;
+            if range.len() == F::ERASE_SIZE {
+                self.flash.erase(sector, sector_end).await.map_err(Error::Flash)?;
+                continue;
+            }
+
+            self.flash.read(sector, self.buf).await.map_err(Error::Flash)?;
+            if self.buf[range.clone()].iter().all(|&b| b == ERASE_VALUE) {
+                continue;
+            }
+            self.buf[range].fill(ERASE_VALUE);
+            self.flash.erase(sector, sector_end).await.map_err(Error::Flash)?;
+            for run in dirty_runs(self.buf, F::WRITE_SIZE, ERASE_VALUE) {
+                self.flash
+                    .write(sector + run.start as u32, &self.buf[run])
+                    .await
+                    .map_err(Error::Flash)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<F: MultiwriteNorFlash, const ERASE_SIZE: usize, const ERASE_VALUE: u8> MultiwriteNorFlash
+    for RmwPartition<'_, F, ERASE_SIZE, ERASE_VALUE>
+{
+}
+
 #[cfg(test)]
 mod tests {
     use embassy_sync::blocking_mutex::raw::NoopRawMutex;
@@ -148,4 +248,48 @@ mod tests {
         let flash = flash.try_lock().unwrap();
         assert!(!flash.mem[128..256].iter().any(|&x| x != 0xFF));
     }
+
+    #[futures_test::test]
+    async fn reports_offending_area() {
+        let flash = MemFlash::<1024, 128, 4>::default();
+
+        let flash = Mutex::<NoopRawMutex, _>::new(flash);
+        let mut partition = Partition::new(&flash, 128, 256);
+
+        let mut read_buf = [0; 8];
+        assert_eq!(
+            partition.read(252, &mut read_buf).await,
+            Err(Error::OutOfBounds { offset: 252, length: 8 })
+        );
+        assert_eq!(
+            partition.write(2, &[0xAA; 8]).await,
+            Err(Error::NotAligned { offset: 2, length: 8 })
+        );
+        assert_eq!(
+            partition.erase(0, 64).await,
+            Err(Error::NotAligned { offset: 0, length: 64 })
+        );
+    }
+
+    #[futures_test::test]
+    async fn rmw_erases_part_of_a_sector() {
+        let flash = MemFlash::<1024, 128, 4>::new(0x00);
+
+        let flash = Mutex::<NoopRawMutex, _>::new(flash);
+        let mut buf = [0; 128];
+        let mut partition = RmwPartition::<_, 32>::new(Partition::new(&flash, 128, 256), &mut buf);
+
+        partition.erase(32, 64).await.unwrap();
+        // Already erased, so the sector is left alone.
+        partition.erase(32, 64).await.unwrap();
+        partition.erase(128, 256).await.unwrap();
+
+        let flash = flash.try_lock().unwrap();
+        assert!(!flash.mem[128..160].iter().any(|&x| x != 0x00));
+        assert!(!flash.mem[160..192].iter().any(|&x| x != 0xFF));
+        assert!(!flash.mem[192..256].iter().any(|&x| x != 0x00));
+        assert!(!flash.mem[256..384].iter().any(|&x| x != 0xFF));
+        assert_eq!(flash.erases, [(128, 256), (256, 384)]);
+        assert_eq!(flash.writes, [(128, 32), (192, 64)]);
+    }
 }
@@ -1,19 +1,33 @@
 //! Flash Partition utilities
 
+use core::ops::Range;
+
 use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
 
 mod asynch;
 mod blocking;
 
-pub use asynch::Partition;
-pub use blocking::BlockingPartition;
+pub use asynch::{Partition, RmwPartition};
+pub use blocking::{BlockingPartition, BlockingRmwPartition};
 
 /// Partition error
 #[derive(Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error<T> {
     /// The requested flash area is outside the partition
-    OutOfBounds,
+    OutOfBounds {
+        /// Offset of the requested area, relative to the partition
+        offset: u32,
+        /// Length of the requested area
+        length: u32,
+    },
+    /// The requested flash area is not aligned to the read, write or erase size
+    NotAligned {
+        /// Offset of the requested area, relative to the partition
+        offset: u32,
+        /// Length of the requested area
+        length: u32,
+    },
     /// Underlying flash error
     Flash(T),
 }
@@ -21,8 +35,137 @@ pub enum Error<T> {
 impl<T: NorFlashError> NorFlashError for Error<T> {
     fn kind(&self) -> NorFlashErrorKind {
         match self {
-            Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Error::OutOfBounds { .. } => NorFlashErrorKind::OutOfBounds,
+            Error::NotAligned { .. } => NorFlashErrorKind::NotAligned,
             Error::Flash(f) => f.kind(),
         }
     }
 }
+
+/// Check that `length` bytes at `offset` are within `size` and aligned to `align`.
+fn check_area<T>(size: u32, align: usize, offset: u32, length: usize) -> Result<(), Error<T>> {
+    let length = length as u32;
+    match offset.checked_add(length) {
+        Some(end) if end <= size => {}
+        _ => return Err(Error::OutOfBounds { offset, length }),
+    }
+    if !offset.is_multiple_of(align as u32) || !length.is_multiple_of(align as u32) {
+        return Err(Error::NotAligned { offset, length });
+    }
+    Ok(())
+}
+
+/// Check the erase of `from..to` within `size`, aligned to `align`.
+fn check_erase<T>(size: u32, align: usize, from: u32, to: u32) -> Result<(), Error<T>> {
+    if to < from {
+        return Err(Error::OutOfBounds {
+            offset: from,
+            length: 0,
+        });
+    }
+    check_area(size, align, from, (to - from) as usize)
+}
+
+/// Iterate over the runs of `buf`, in `write_size` chunks, holding anything else than `erase_value`.
+///
+/// Writing back erased chunks is skipped, to spare the write cycles of the parent flash.
+fn dirty_runs(buf: &[u8], write_size: usize, erase_value: u8) -> impl Iterator<Item = Range<usize>> + '_ {
+    let is_erased = move |i: usize| buf[i..i + write_size].iter().all(|&b| b == erase_value);
+    let mut start = 0;
+    core::iter::from_fn(move || {
+        while start < buf.len() && is_erased(start) {
+            start += write_size;
+        }
+        if start >= buf.len() {
+            return None;
+        }
+        let mut end = start + write_size;
+        while end < buf.len() && !is_erased(end) {
+            end += write_size;
+        }
+        let run = start..end;
+        start = end;
+        Some(run)
+    })
+}
+
+/// Parent sectors touched by the erase of `from..to`, in a partition of `erase_size` sectors.
+///
+/// Each item is the offset of the parent sector, and the range to erase within it.
+fn sectors(from: u32, to: u32, erase_size: usize) -> impl Iterator<Item = (u32, Range<usize>)> {
+    let erase_size = erase_size as u32;
+    let mut offset = from;
+    core::iter::from_fn(move || {
+        if offset >= to {
+            return None;
+        }
+        let sector = offset - offset % erase_size;
+        let end = to.min(sector + erase_size);
+        let range = (offset - sector) as usize..(end - sector) as usize;
+        offset = end;
+        Some((sector, range))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_dirty_runs() {
+        let mut buf = [0xFF; 32];
+        assert_eq!(dirty_runs(&buf, 4, 0xFF).count(), 0);
+
+        buf[1] = 0;
+        buf[12..20].fill(0);
+        buf[31] = 0;
+        let mut runs = dirty_runs(&buf, 4, 0xFF);
+        assert_eq!(runs.next(), Some(0..4));
+        assert_eq!(runs.next(), Some(12..20));
+        assert_eq!(runs.next(), Some(28..32));
+        assert_eq!(runs.next(), None);
+
+        // A flash erased to zeros.
+        let mut runs = dirty_runs(&buf, 4, 0x00);
+        assert_eq!(runs.next(), Some(0..12));
+        assert_eq!(runs.next(), Some(20..32));
+        assert_eq!(runs.next(), None);
+    }
+
+    #[test]
+    fn splits_erase_into_sectors() {
+        let mut sectors = sectors(96, 288, 128);
+        assert_eq!(sectors.next(), Some((0, 96..128)));
+        assert_eq!(sectors.next(), Some((128, 0..128)));
+        assert_eq!(sectors.next(), Some((256, 0..32)));
+        assert_eq!(sectors.next(), None);
+    }
+
+    #[test]
+    fn reports_offending_area() {
+        assert_eq!(
+            check_area::<()>(256, 4, 252, 8),
+            Err(Error::OutOfBounds { offset: 252, length: 8 })
+        );
+        assert_eq!(
+            check_area::<()>(256, 4, u32::MAX, 8),
+            Err(Error::OutOfBounds {
+                offset: u32::MAX,
+                length: 8
+            })
+        );
+        assert_eq!(
+            check_area::<()>(256, 4, 2, 8),
+            Err(Error::NotAligned { offset: 2, length: 8 })
+        );
+        assert_eq!(
+            check_erase::<()>(256, 128, 128, 0),
+            Err(Error::OutOfBounds { offset: 128, length: 0 })
+        );
+        assert_eq!(
+            check_erase::<()>(256, 128, 0, 64),
+            Err(Error::NotAligned { offset: 0, length: 64 })
+        );
+        assert_eq!(check_erase::<()>(256, 128, 0, 256), Ok(()));
+    }
+}
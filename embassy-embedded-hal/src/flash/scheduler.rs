@@ -0,0 +1,574 @@
+//! Scheduling of flash operations around timing critical work
+//!
+//! Erasing or writing internal flash stalls the CPU, and external flash shares buses and
+//! power with the rest of the system. While a radio is keeping a connection alive, long flash
+//! operations make it miss connection events, so firmware updates over the air degrade the
+//! very link they are received on.
+//!
+//! A [`FlashScheduler`] is a single coordination point for all flash work in the system.
+//! Flash drivers split their operations into work items of bounded duration, and the
+//! scheduler only starts a work item if it ends before the next blackout window declared by
+//! its [`BlackoutPolicy`]. Radio code (or any other timing critical subsystem) updates the
+//! policy, for example a [`PeriodicBlackout`] following the connection event interval.
+//!
+//! Wrap each flash in a [`ScheduledFlash`] to route all its erases and writes through the
+//! scheduler. As it implements the async `embedded-storage` traits, it can be passed to
+//! anything taking a flash, such as the firmware updater of `embassy-boot`. Flashes that
+//! cannot split their own operations can be wrapped in a [`TimedFlash`], which issues one
+//! work item per page erase and per write chunk.
+//!
+//! ```rust,ignore
+//! static SCHEDULER: FlashScheduler<CriticalSectionRawMutex, PeriodicBlackout<CriticalSectionRawMutex>> =
+//!     FlashScheduler::new(PeriodicBlackout::new());
+//!
+//! // In the radio task, when a connection is established or updated:
+//! SCHEDULER.policy().set(anchor, Duration::from_micros(7500), Duration::from_micros(2500));
+//!
+//! // In the update task:
+//! let flash = ScheduledFlash::new(&SCHEDULER, TimedFlash::new(qspi, FlashTiming::default()));
+//! ```
+
+use core::cell::Cell;
+use core::future::Future;
+
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_storage::nor_flash::ErrorType;
+use embedded_storage_async::nor_flash::{MultiwriteNorFlash, NorFlash, ReadNorFlash};
+
+/// Decides when flash work may run.
+pub trait BlackoutPolicy {
+    /// Get the earliest instant, at or after `now`, at which a work item taking `duration`
+    /// can run without overlapping a blackout window.
+    ///
+    /// If a work item is too long to ever fit between two blackouts, the start of the next
+    /// gap should be returned, so that the overlap is as short as possible.
+    fn next_slot(&self, now: Instant, duration: Duration) -> Instant;
+}
+
+/// Policy without blackouts, letting all work run immediately.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoBlackout;
+
+impl BlackoutPolicy for NoBlackout {
+    fn next_slot(&self, now: Instant, _duration: Duration) -> Instant {
+        now
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Period {
+    anchor: Instant,
+    interval: Duration,
+    length: Duration,
+}
+
+/// Policy with blackout windows repeating at a fixed interval, such as BLE connection events.
+///
+/// Starts without blackouts until [`set`](Self::set) is called.
+pub struct PeriodicBlackout<M: RawMutex> {
+    period: BlockingMutex<M, Cell<Option<Period>>>,
+}
+
+impl<M: RawMutex> PeriodicBlackout<M> {
+    /// Create a policy without blackouts.
+    pub const fn new() -> Self {
+        Self {
+            period: BlockingMutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Declare blackout windows of `length`, starting at `anchor` and repeating every
+    /// `interval`.
+    ///
+    /// Windows also repeat before `anchor`, so the anchor can be any past or future event.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length` is not shorter than `interval`, which would leave no time for flash work.
+    pub fn set(&self, anchor: Instant, interval: Duration, length: Duration) {
+        assert!(length < interval, "Blackout must be shorter than its interval");
+        self.period.lock(|p| {
+            p.set(Some(Period {
+                anchor,
+                interval,
+                length,
+            }))
+        });
+    }
+
+    /// Remove all blackout windows.
+    pub fn clear(&self) {
+        self.period.lock(|p| p.set(None));
+    }
+}
+
+impl<M: RawMutex> Default for PeriodicBlackout<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: RawMutex> BlackoutPolicy for PeriodicBlackout<M> {
+    fn next_slot(&self, now: Instant, duration: Duration) -> Instant {
+        let Some(p) = self.period.lock(|p| p.get()) else {
+            return now;
+        };
+
+        let interval = p.interval.as_ticks();
+        let length = p.length.as_ticks();
+        let phase = (now.as_ticks() as i128 - p.anchor.as_ticks() as i128).rem_euclid(interval as i128) as u64;
+
+        // Start of the gap we are in, or of the next one if we are in a blackout.
+        let (gap_start, phase) = if phase < length {
+            (now + Duration::from_ticks(length - phase), length)
+        } else {
+            (now, phase)
+        };
+
+        if phase + duration.as_ticks() <= interval || (phase == length && duration.as_ticks() > interval - length) {
+            gap_start
+        } else {
+            gap_start + Duration::from_ticks(interval - phase + length)
+        }
+    }
+}
+
+/// Coordinates flash work items so that they only run outside blackout windows.
+///
+/// Work items are run one at a time, in the order they were submitted.
+pub struct FlashScheduler<M: RawMutex, P: BlackoutPolicy> {
+    policy: P,
+    lock: Mutex<M, ()>,
+}
+
+impl<M: RawMutex, P: BlackoutPolicy> FlashScheduler<M, P> {
+    /// Create a scheduler with the given policy.
+    pub const fn new(policy: P) -> Self {
+        Self {
+            policy,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Get the policy, to update its blackout windows.
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Run a work item taking at most `duration`, once the policy allows it.
+    ///
+    /// `op` is not polled before its slot, so it should not start any work before it is awaited.
+    pub async fn run<F: Future>(&self, duration: Duration, op: F) -> F::Output {
+        let _guard = self.lock.lock().await;
+        loop {
+            let now = Instant::now();
+            let at = self.policy.next_slot(now, duration);
+            if at <= now {
+                break;
+            }
+            // The policy may have changed while waiting, so check again.
+            Timer::at(at).await;
+        }
+        op.await
+    }
+}
+
+/// A flash that can split its operations into work items for a [`FlashScheduler`].
+pub trait SlicedNorFlash: NorFlash {
+    /// Erase `[from, to)`, running each step through `scheduler`.
+    async fn erase_sliced<M: RawMutex, P: BlackoutPolicy>(
+        &mut self,
+        scheduler: &FlashScheduler<M, P>,
+        from: u32,
+        to: u32,
+    ) -> Result<(), Self::Error>;
+
+    /// Write `bytes` at `offset`, running each step through `scheduler`.
+    async fn write_sliced<M: RawMutex, P: BlackoutPolicy>(
+        &mut self,
+        scheduler: &FlashScheduler<M, P>,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), Self::Error>;
+}
+
+/// A flash whose erases and writes are run through a [`FlashScheduler`].
+///
+/// Reads are not scheduled.
+pub struct ScheduledFlash<'a, M: RawMutex, P: BlackoutPolicy, F: SlicedNorFlash> {
+    scheduler: &'a FlashScheduler<M, P>,
+    flash: F,
+}
+
+impl<'a, M: RawMutex, P: BlackoutPolicy, F: SlicedNorFlash> ScheduledFlash<'a, M, P, F> {
+    /// Create a new scheduled flash.
+    pub fn new(scheduler: &'a FlashScheduler<M, P>, flash: F) -> Self {
+        Self { scheduler, flash }
+    }
+
+    /// Release the wrapped flash.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+}
+
+impl<M: RawMutex, P: BlackoutPolicy, F: SlicedNorFlash> ErrorType for ScheduledFlash<'_, M, P, F> {
+    type Error = F::Error;
+}
+
+impl<M: RawMutex, P: BlackoutPolicy, F: SlicedNorFlash> ReadNorFlash for ScheduledFlash<'_, M, P, F> {
+    const READ_SIZE: usize = F::READ_SIZE;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.flash.read(offset, bytes).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.flash.capacity()
+    }
+}
+
+impl<M: RawMutex, P: BlackoutPolicy, F: SlicedNorFlash> NorFlash for ScheduledFlash<'_, M, P, F> {
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const ERASE_SIZE: usize = F::ERASE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.flash.erase_sliced(self.scheduler, from, to).await
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.flash.write_sliced(self.scheduler, offset, bytes).await
+    }
+}
+
+impl<M: RawMutex, P: BlackoutPolicy, F: SlicedNorFlash + MultiwriteNorFlash> MultiwriteNorFlash
+    for ScheduledFlash<'_, M, P, F>
+{
+}
+
+/// Worst case durations of the operations of a flash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FlashTiming {
+    /// Time to erase one erase block.
+    pub erase: Duration,
+    /// Bytes written per work item. Rounded down to a multiple of the write size.
+    pub write_chunk: usize,
+    /// Time to write one chunk.
+    pub write: Duration,
+}
+
+impl Default for FlashTiming {
+    /// Timing of a typical QSPI NOR flash, with 4 kB sector erases and 256 byte page programs.
+    fn default() -> Self {
+        Self {
+            erase: Duration::from_millis(45),
+            write_chunk: 256,
+            write: Duration::from_micros(700),
+        }
+    }
+}
+
+/// Adds scheduling to a flash that cannot split its own operations, by running one work
+/// item per erase block and per write chunk.
+pub struct TimedFlash<F: NorFlash> {
+    flash: F,
+    timing: FlashTiming,
+}
+
+impl<F: NorFlash> TimedFlash<F> {
+    /// Create a new timed flash.
+    pub fn new(flash: F, timing: FlashTiming) -> Self {
+        assert!(timing.write_chunk >= F::WRITE_SIZE);
+        Self { flash, timing }
+    }
+
+    /// Release the wrapped flash.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+}
+
+impl<F: NorFlash> ErrorType for TimedFlash<F> {
+    type Error = F::Error;
+}
+
+impl<F: NorFlash> ReadNorFlash for TimedFlash<F> {
+    const READ_SIZE: usize = F::READ_SIZE;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.flash.read(offset, bytes).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.flash.capacity()
+    }
+}
+
+impl<F: NorFlash> NorFlash for TimedFlash<F> {
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const ERASE_SIZE: usize = F::ERASE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.flash.erase(from, to).await
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.flash.write(offset, bytes).await
+    }
+}
+
+impl<F: NorFlash + MultiwriteNorFlash> MultiwriteNorFlash for TimedFlash<F> {}
+
+impl<F: NorFlash> SlicedNorFlash for TimedFlash<F> {
+    async fn erase_sliced<M: RawMutex, P: BlackoutPolicy>(
+        &mut self,
+        scheduler: &FlashScheduler<M, P>,
+        from: u32,
+        to: u32,
+    ) -> Result<(), Self::Error> {
+        // Empty and reversed ranges are left to the flash to accept or reject.
+        if from >= to {
+            return self.flash.erase(from, to).await;
+        }
+        for block in (from..to).step_by(F::ERASE_SIZE) {
+            let end = (block + F::ERASE_SIZE as u32).min(to);
+            scheduler.run(self.timing.erase, self.flash.erase(block, end)).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_sliced<M: RawMutex, P: BlackoutPolicy>(
+        &mut self,
+        scheduler: &FlashScheduler<M, P>,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), Self::Error> {
+        let chunk_len = self.timing.write_chunk - self.timing.write_chunk % F::WRITE_SIZE;
+        let mut offset = offset;
+        for chunk in bytes.chunks(chunk_len) {
+            scheduler
+                .run(self.timing.write, self.flash.write(offset, chunk))
+                .await?;
+            offset += chunk.len() as u32;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::pin::pin;
+    use core::task::{Context, Waker};
+    use std::vec::Vec;
+
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_time::MockDriver;
+
+    use super::*;
+    use crate::flash::mem_flash::MemFlash;
+
+    const INTERVAL: Duration = Duration::from_micros(7500);
+    const BLACKOUT: Duration = Duration::from_micros(2500);
+
+    fn in_blackout(anchor: Instant, t: Instant) -> bool {
+        let phase = (t.as_ticks() as i64 - anchor.as_ticks() as i64).rem_euclid(INTERVAL.as_ticks() as i64);
+        phase < BLACKOUT.as_ticks() as i64
+    }
+
+    fn assert_outside_blackouts(anchor: Instant, start: Instant, duration: Duration) {
+        // Windows are at least as long as the resolution used here, so sampling finds any overlap.
+        let mut t = start;
+        while t < start + duration {
+            assert!(
+                !in_blackout(anchor, t),
+                "work at {} overlaps a blackout",
+                start.as_micros()
+            );
+            t += Duration::from_micros(100).min(start + duration - t);
+        }
+    }
+
+    #[test]
+    fn no_blackout_runs_immediately() {
+        let now = Instant::from_millis(3);
+        assert_eq!(NoBlackout.next_slot(now, Duration::from_secs(1)), now);
+        let policy = PeriodicBlackout::<NoopRawMutex>::new();
+        assert_eq!(policy.next_slot(now, Duration::from_secs(1)), now);
+    }
+
+    #[test]
+    fn periodic_slots() {
+        let policy = PeriodicBlackout::<NoopRawMutex>::new();
+        let anchor = Instant::from_micros(10_000);
+        policy.set(anchor, INTERVAL, BLACKOUT);
+        let ms = Duration::from_millis(1);
+
+        // In a gap with room to spare.
+        assert_eq!(
+            policy.next_slot(Instant::from_micros(13_000), ms),
+            Instant::from_micros(13_000)
+        );
+        // In a blackout, waits for its end.
+        assert_eq!(
+            policy.next_slot(Instant::from_micros(11_000), ms),
+            Instant::from_micros(12_500)
+        );
+        // Before the anchor, windows repeat backwards.
+        assert_eq!(
+            policy.next_slot(Instant::from_micros(3_000), ms),
+            Instant::from_micros(5_000)
+        );
+        // Too little room left in the gap, waits for the next one.
+        assert_eq!(
+            policy.next_slot(Instant::from_micros(16_800), ms),
+            Instant::from_micros(20_000)
+        );
+        // Exactly fits.
+        assert_eq!(
+            policy.next_slot(Instant::from_micros(16_500), ms),
+            Instant::from_micros(16_500)
+        );
+        // Never fits, starts at the beginning of a gap.
+        let long = Duration::from_millis(6);
+        assert_eq!(
+            policy.next_slot(Instant::from_micros(12_500), long),
+            Instant::from_micros(12_500)
+        );
+        assert_eq!(
+            policy.next_slot(Instant::from_micros(13_000), long),
+            Instant::from_micros(20_000)
+        );
+
+        policy.clear();
+        assert_eq!(
+            policy.next_slot(Instant::from_micros(11_000), ms),
+            Instant::from_micros(11_000)
+        );
+    }
+
+    #[test]
+    fn interleaved_work_items_avoid_blackouts() {
+        let policy = PeriodicBlackout::<NoopRawMutex>::new();
+        let anchor = Instant::from_micros(1234);
+        policy.set(anchor, INTERVAL, BLACKOUT);
+
+        let durations = [400, 1000, 4000, 5000, 2600, 700, 3300];
+        let mut now = Instant::from_ticks(0);
+        for i in 0..1000 {
+            let duration = Duration::from_micros(durations[i % durations.len()]);
+            let start = policy.next_slot(now, duration);
+            assert!(start >= now);
+            assert!(start - now < INTERVAL + BLACKOUT);
+            assert_outside_blackouts(anchor, start, duration);
+            now = start + duration;
+        }
+    }
+
+    /// Flash whose operations take mock time, recording when each one ran.
+    struct SlowFlash {
+        mem: MemFlash<{ 128 * 1024 }, 4096, 4>,
+        timing: FlashTiming,
+        ops: Vec<(Instant, Duration)>,
+    }
+
+    impl SlowFlash {
+        fn busy(&mut self, duration: Duration) {
+            self.ops.push((Instant::now(), duration));
+            MockDriver::get().advance(duration);
+        }
+    }
+
+    impl ErrorType for SlowFlash {
+        type Error = core::convert::Infallible;
+    }
+
+    impl ReadNorFlash for SlowFlash {
+        const READ_SIZE: usize = 1;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            self.mem.read(offset, bytes).await
+        }
+
+        fn capacity(&self) -> usize {
+            self.mem.capacity()
+        }
+    }
+
+    impl NorFlash for SlowFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4096;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            let blocks = (to - from) / Self::ERASE_SIZE as u32;
+            self.busy(self.timing.erase * blocks);
+            self.mem.erase(from, to).await
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let chunks = bytes.len().div_ceil(self.timing.write_chunk) as u32;
+            self.busy(self.timing.write * chunks);
+            self.mem.write(offset, bytes).await
+        }
+    }
+
+    #[test]
+    fn firmware_write_during_connection() {
+        let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+        let driver = MockDriver::get();
+        driver.reset();
+        driver.advance(Duration::from_micros(4321));
+
+        let timing = FlashTiming {
+            erase: Duration::from_millis(4),
+            write_chunk: 1024,
+            write: Duration::from_micros(1500),
+        };
+        let scheduler = FlashScheduler::<NoopRawMutex, _>::new(PeriodicBlackout::<NoopRawMutex>::new());
+        let anchor = Instant::from_micros(1000);
+        scheduler.policy().set(anchor, INTERVAL, BLACKOUT);
+
+        let slow = SlowFlash {
+            mem: MemFlash::new(0x00),
+            timing,
+            ops: Vec::new(),
+        };
+        let mut flash = ScheduledFlash::new(&scheduler, TimedFlash::new(slow, timing));
+
+        let start = Instant::now();
+        {
+            let image = [0xA5; 64 * 1024];
+            let mut update = pin!(async {
+                flash.erase(0, image.len() as u32).await.unwrap();
+                for (i, block) in image.chunks(4096).enumerate() {
+                    flash.write(i as u32 * 4096, block).await.unwrap();
+                }
+            });
+            let mut cx = Context::from_waker(Waker::noop());
+            while update.as_mut().poll(&mut cx).is_pending() {
+                driver.advance(Duration::from_micros(10));
+                assert!(
+                    Instant::now() - start < Duration::from_secs(1),
+                    "update did not complete"
+                );
+            }
+        }
+        let elapsed = Instant::now() - start;
+
+        let slow = flash.into_inner().into_inner();
+        assert!(slow.mem.mem[..64 * 1024].iter().all(|&b| b == 0xA5));
+        assert_eq!(slow.ops.len(), 16 + 64);
+        for &(start, duration) in &slow.ops {
+            assert_outside_blackouts(anchor, start, duration);
+        }
+
+        // Each gap fits one erase or three writes, so 16 erases and 64 writes need at most
+        // 16 + 22 connection intervals, plus polling overhead.
+        assert!(elapsed < INTERVAL * 40, "took {} ms", elapsed.as_millis());
+    }
+}
@@ -0,0 +1,811 @@
+//! Driver for serial NOR flash chips
+//!
+//! [`SpiNor`] drives the common SPI NOR flash chips (Winbond W25Q, Macronix MX25, GigaDevice
+//! GD25, ...) with the standard single-lane command set. The geometry is not hardcoded: it is
+//! read from the chip's SFDP tables (JESD216), which all current parts provide.
+//!
+//! It works over any async [`SpiDevice`], such as a device of a
+//! [shared bus](crate::shared_bus::asynch::spi::SpiDevice), or an SPI peripheral owned
+//! exclusively, with for instance `embedded_hal_bus::spi::ExclusiveDevice`.
+//!
+//! The async `embedded-storage` traits are implemented, for instance for the firmware updater of
+//! `embassy-boot`. The blocking traits are implemented as well, for the bootloader: they
+//! busy-poll the async implementation, so they must not be used from an async task.
+//!
+//! [`ERASE_SIZE`](NorFlash::ERASE_SIZE) is 4 kB. Erases use the largest erase type the chip
+//! supports (typically 4 kB, 32 kB and 64 kB) that fits the aligned range left to erase.
+//!
+//! While the chip is busy erasing or programming, its status is polled with an `embassy-time`
+//! timer in between when the `time` feature is enabled, and with a yield otherwise.
+//!
+//! ```rust,ignore
+//! let spi = ExclusiveDevice::new(spim, cs, Delay);
+//! let mut flash = SpiNor::new(spi).await?;
+//! info!("JEDEC ID {}, {} bytes", flash.jedec_id(), flash.capacity());
+//! ```
+
+use embedded_hal_async::spi::{Operation, SpiDevice};
+use embedded_storage::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind};
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+
+const READ_JEDEC_ID: u8 = 0x9F;
+const READ_SFDP: u8 = 0x5A;
+const WRITE_ENABLE: u8 = 0x06;
+const READ_STATUS: u8 = 0x05;
+const PAGE_PROGRAM: u8 = 0x02;
+const FAST_READ: u8 = 0x0B;
+const ENTER_4_BYTE_ADDRESS: u8 = 0xB7;
+const DEEP_POWER_DOWN: u8 = 0xB9;
+const RELEASE_POWER_DOWN: u8 = 0xAB;
+
+const STATUS_BUSY: u8 = 0x01;
+
+/// Capacity above which 4 byte addresses are needed.
+const MAX_3_BYTE_CAPACITY: u32 = 1 << 24;
+
+/// Status polling interval while programming, in microseconds.
+const PROGRAM_POLL_US: u64 = 100;
+/// Status polling interval while erasing, in microseconds.
+const ERASE_POLL_US: u64 = 1000;
+/// Time to release from deep power-down, in microseconds.
+///
+/// Covers the worst case of the common parts, 30 µs for the MX25R.
+const RELEASE_POWER_DOWN_US: u64 = 35;
+
+/// SPI NOR flash error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// SPI error.
+    Spi(E),
+    /// The chip has no SFDP tables, or is not connected.
+    NoSfdp,
+    /// The SFDP tables describe a chip this driver can't drive, for instance without 4 kB
+    /// erases.
+    Unsupported,
+    /// The range is outside the flash.
+    OutOfBounds,
+    /// The erase range is not aligned to 4 kB.
+    NotAligned,
+}
+
+impl<E: core::fmt::Debug> NorFlashError for Error<E> {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Error::NotAligned => NorFlashErrorKind::NotAligned,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// JEDEC ID of a chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct JedecId {
+    /// Manufacturer ID, for instance `0xEF` for Winbond or `0xC2` for Macronix.
+    pub manufacturer: u8,
+    /// Memory type, specific to the manufacturer.
+    pub memory_type: u8,
+    /// Capacity code, specific to the manufacturer. Usually the log2 of the capacity in bytes.
+    pub capacity: u8,
+}
+
+/// An erase instruction of a chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EraseType {
+    /// Size of the erased block, in bytes.
+    pub size: u32,
+    /// Instruction.
+    pub opcode: u8,
+}
+
+/// Chip geometry and capabilities, read from the SFDP basic flash parameter table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Geometry {
+    /// Capacity, in bytes.
+    pub capacity: u32,
+    /// Page size, in bytes. Programming wraps around at page boundaries.
+    pub page_size: u32,
+    /// Erase types, smallest first.
+    pub erase_types: [Option<EraseType>; 4],
+    /// Whether 1-1-2 fast reads (dual output) are supported.
+    pub fast_read_1_1_2: bool,
+    /// Whether 1-2-2 fast reads (dual I/O) are supported.
+    pub fast_read_1_2_2: bool,
+    /// Whether 1-1-4 fast reads (quad output) are supported.
+    pub fast_read_1_1_4: bool,
+    /// Whether 1-4-4 fast reads (quad I/O) are supported.
+    pub fast_read_1_4_4: bool,
+}
+
+impl Geometry {
+    /// Parse the basic flash parameter table, given as little-endian dwords.
+    ///
+    /// Returns `None` if the table is too short or malformed.
+    pub fn from_bfpt(dwords: &[u32]) -> Option<Self> {
+        // JESD216 (revision 0) defines 9 dwords, later revisions append to them.
+        if dwords.len() < 9 {
+            return None;
+        }
+
+        let density = dwords[1];
+        let bits = if density & 0x8000_0000 == 0 {
+            density as u64 + 1
+        } else {
+            1u64.checked_shl(density & 0x7FFF_FFFF)?
+        };
+        let capacity = u32::try_from(bits / 8).ok().filter(|c| *c > 0)?;
+
+        let mut erase_types = [None; 4];
+        let mut n = 0;
+        for dword in &dwords[7..9] {
+            for half in [*dword as u16, (*dword >> 16) as u16] {
+                let [exponent, opcode] = half.to_le_bytes();
+                if exponent != 0 && exponent < 32 {
+                    erase_types[n] = Some(EraseType {
+                        size: 1 << exponent,
+                        opcode,
+                    });
+                    n += 1;
+                }
+            }
+        }
+        // Tables without erase types still announce the 4 kB erase in the first dword.
+        if n == 0 && dwords[0] & 0b11 == 0b01 {
+            erase_types[0] = Some(EraseType {
+                size: 4096,
+                opcode: (dwords[0] >> 8) as u8,
+            });
+        }
+        erase_types.sort_unstable_by_key(|e| e.map_or(u32::MAX, |e| e.size));
+
+        // The page size is only in JESD216A and later.
+        let page_size = match dwords.get(10) {
+            Some(dword) => 1 << ((dword >> 4) & 0xF),
+            None => 256,
+        };
+
+        Some(Self {
+            capacity,
+            page_size,
+            erase_types,
+            fast_read_1_1_2: dwords[0] & (1 << 16) != 0,
+            fast_read_1_2_2: dwords[0] & (1 << 20) != 0,
+            fast_read_1_4_4: dwords[0] & (1 << 21) != 0,
+            fast_read_1_1_4: dwords[0] & (1 << 22) != 0,
+        })
+    }
+
+    /// Largest erase type erasing an aligned block starting at `addr` and ending by `end`.
+    fn erase_type(&self, addr: u32, end: u32) -> Option<EraseType> {
+        self.erase_types
+            .iter()
+            .rev()
+            .flatten()
+            .find(|e| addr.is_multiple_of(e.size) && end - addr >= e.size)
+            .copied()
+    }
+}
+
+/// SPI NOR flash driver.
+pub struct SpiNor<SPI> {
+    spi: SPI,
+    jedec_id: JedecId,
+    geometry: Geometry,
+}
+
+impl<SPI: SpiDevice> SpiNor<SPI> {
+    /// Create a new driver, reading the JEDEC ID and the SFDP tables.
+    ///
+    /// The chip is released from deep power-down first. Chips larger than 16 MB are switched to 4
+    /// byte addresses.
+    pub async fn new(mut spi: SPI) -> Result<Self, Error<SPI::Error>> {
+        command(&mut spi, RELEASE_POWER_DOWN).await?;
+        pause(RELEASE_POWER_DOWN_US).await;
+
+        let mut id = [0; 3];
+        spi.transaction(&mut [Operation::Write(&[READ_JEDEC_ID]), Operation::Read(&mut id)])
+            .await
+            .map_err(Error::Spi)?;
+        let jedec_id = JedecId {
+            manufacturer: id[0],
+            memory_type: id[1],
+            capacity: id[2],
+        };
+
+        let geometry = read_geometry(&mut spi).await?;
+        if !geometry
+            .erase_types
+            .iter()
+            .flatten()
+            .any(|e| e.size == <Self as NorFlash>::ERASE_SIZE as u32)
+        {
+            return Err(Error::Unsupported);
+        }
+
+        if geometry.capacity > MAX_3_BYTE_CAPACITY {
+            command(&mut spi, WRITE_ENABLE).await?;
+            command(&mut spi, ENTER_4_BYTE_ADDRESS).await?;
+        }
+
+        Ok(Self {
+            spi,
+            jedec_id,
+            geometry,
+        })
+    }
+
+    /// Get the JEDEC ID of the chip.
+    pub fn jedec_id(&self) -> JedecId {
+        self.jedec_id
+    }
+
+    /// Get the geometry of the chip.
+    pub fn geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+
+    /// Put the chip in deep power-down.
+    ///
+    /// The chip ignores all commands until [`wake_up`](Self::wake_up) is called.
+    pub async fn power_down(&mut self) -> Result<(), Error<SPI::Error>> {
+        command(&mut self.spi, DEEP_POWER_DOWN).await
+    }
+
+    /// Release the chip from deep power-down.
+    ///
+    /// Without the `time` feature, the release time of the chip (a few tens of microseconds)
+    /// must elapse before the next command.
+    pub async fn wake_up(&mut self) -> Result<(), Error<SPI::Error>> {
+        command(&mut self.spi, RELEASE_POWER_DOWN).await?;
+        pause(RELEASE_POWER_DOWN_US).await;
+        Ok(())
+    }
+
+    /// Release the SPI device.
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+
+    /// Command with an address, followed by `dummy` dummy bytes.
+    fn address_command(&self, opcode: u8, addr: u32, dummy: usize) -> ([u8; 6], usize) {
+        let mut cmd = [0; 6];
+        cmd[0] = opcode;
+        let len = if self.geometry.capacity > MAX_3_BYTE_CAPACITY {
+            cmd[1..5].copy_from_slice(&addr.to_be_bytes());
+            5
+        } else {
+            cmd[1..4].copy_from_slice(&addr.to_be_bytes()[1..]);
+            4
+        };
+        (cmd, len + dummy)
+    }
+
+    /// Wait until the erase or program in progress is done.
+    async fn wait_idle(&mut self, poll_us: u64) -> Result<(), Error<SPI::Error>> {
+        loop {
+            let mut status = [0];
+            self.spi
+                .transaction(&mut [Operation::Write(&[READ_STATUS]), Operation::Read(&mut status)])
+                .await
+                .map_err(Error::Spi)?;
+            if status[0] & STATUS_BUSY == 0 {
+                return Ok(());
+            }
+            pause(poll_us).await;
+        }
+    }
+
+    fn check_bounds(&self, offset: u32, len: usize) -> Result<(), Error<SPI::Error>> {
+        match (offset as usize).checked_add(len) {
+            Some(end) if end <= self.geometry.capacity as usize => Ok(()),
+            _ => Err(Error::OutOfBounds),
+        }
+    }
+}
+
+/// Send a command without address or data.
+async fn command<SPI: SpiDevice>(spi: &mut SPI, opcode: u8) -> Result<(), Error<SPI::Error>> {
+    spi.write(&[opcode]).await.map_err(Error::Spi)
+}
+
+async fn read_sfdp<SPI: SpiDevice>(spi: &mut SPI, addr: u32, buf: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+    // SFDP reads always use 3 byte addresses and 8 dummy cycles.
+    let [_, a2, a1, a0] = addr.to_be_bytes();
+    spi.transaction(&mut [Operation::Write(&[READ_SFDP, a2, a1, a0, 0]), Operation::Read(buf)])
+        .await
+        .map_err(Error::Spi)
+}
+
+async fn read_geometry<SPI: SpiDevice>(spi: &mut SPI) -> Result<Geometry, Error<SPI::Error>> {
+    let mut header = [0; 8];
+    read_sfdp(spi, 0, &mut header).await?;
+    if header[0..4] != *b"SFDP" {
+        return Err(Error::NoSfdp);
+    }
+
+    let headers = header[6] as u32 + 1;
+    for i in 0..headers {
+        let mut param = [0; 8];
+        read_sfdp(spi, 8 + 8 * i, &mut param).await?;
+        // The basic flash parameter table has ID 0xFF00.
+        if (param[0], param[7]) != (0x00, 0xFF) {
+            continue;
+        }
+
+        let len = (param[3] as usize).min(16);
+        let ptr = u32::from_le_bytes([param[4], param[5], param[6], 0]);
+        let mut table = [0; 64];
+        read_sfdp(spi, ptr, &mut table[..len * 4]).await?;
+
+        let mut dwords = [0; 16];
+        for (dword, bytes) in dwords.iter_mut().zip(table.chunks_exact(4)) {
+            *dword = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        return Geometry::from_bfpt(&dwords[..len]).ok_or(Error::Unsupported);
+    }
+
+    Err(Error::Unsupported)
+}
+
+#[cfg(feature = "time")]
+async fn pause(micros: u64) {
+    embassy_time::Timer::after_micros(micros).await
+}
+
+#[cfg(not(feature = "time"))]
+async fn pause(_micros: u64) {
+    embassy_futures::yield_now().await
+}
+
+impl<SPI: SpiDevice> ErrorType for SpiNor<SPI> {
+    type Error = Error<SPI::Error>;
+}
+
+impl<SPI: SpiDevice> ReadNorFlash for SpiNor<SPI> {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_bounds(offset, bytes.len())?;
+        let (cmd, len) = self.address_command(FAST_READ, offset, 1);
+        self.spi
+            .transaction(&mut [Operation::Write(&cmd[..len]), Operation::Read(bytes)])
+            .await
+            .map_err(Error::Spi)
+    }
+
+    fn capacity(&self) -> usize {
+        self.geometry.capacity as usize
+    }
+}
+
+impl<SPI: SpiDevice> NorFlash for SpiNor<SPI> {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = 4096;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from > to || to > self.geometry.capacity {
+            return Err(Error::OutOfBounds);
+        }
+        if !from.is_multiple_of(Self::ERASE_SIZE as u32) || !to.is_multiple_of(Self::ERASE_SIZE as u32) {
+            return Err(Error::NotAligned);
+        }
+
+        let mut addr = from;
+        while addr < to {
+            // Always found, as 4 kB erases are supported.
+            let Some(erase) = self.geometry.erase_type(addr, to) else {
+                return Err(Error::Unsupported);
+            };
+            command(&mut self.spi, WRITE_ENABLE).await?;
+            let (cmd, len) = self.address_command(erase.opcode, addr, 0);
+            self.spi.write(&cmd[..len]).await.map_err(Error::Spi)?;
+            self.wait_idle(ERASE_POLL_US).await?;
+            addr += erase.size;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.check_bounds(offset, bytes.len())?;
+
+        let mut addr = offset;
+        let mut data = bytes;
+        while !data.is_empty() {
+            // Programming wraps around at the end of a page.
+            let n = data
+                .len()
+                .min((self.geometry.page_size - addr % self.geometry.page_size) as usize);
+            command(&mut self.spi, WRITE_ENABLE).await?;
+            let (cmd, len) = self.address_command(PAGE_PROGRAM, addr, 0);
+            self.spi
+                .transaction(&mut [Operation::Write(&cmd[..len]), Operation::Write(&data[..n])])
+                .await
+                .map_err(Error::Spi)?;
+            self.wait_idle(PROGRAM_POLL_US).await?;
+            addr += n as u32;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+}
+
+impl<SPI: SpiDevice> embedded_storage::nor_flash::ReadNorFlash for SpiNor<SPI> {
+    const READ_SIZE: usize = <Self as ReadNorFlash>::READ_SIZE;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        embassy_futures::block_on(ReadNorFlash::read(self, offset, bytes))
+    }
+
+    fn capacity(&self) -> usize {
+        self.geometry.capacity as usize
+    }
+}
+
+impl<SPI: SpiDevice> embedded_storage::nor_flash::NorFlash for SpiNor<SPI> {
+    const WRITE_SIZE: usize = <Self as NorFlash>::WRITE_SIZE;
+    const ERASE_SIZE: usize = <Self as NorFlash>::ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        embassy_futures::block_on(NorFlash::erase(self, from, to))
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        embassy_futures::block_on(NorFlash::write(self, offset, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::Infallible;
+    use core::future::Future;
+    use std::vec;
+    use std::vec::Vec;
+
+    use embedded_hal_async::spi::ErrorType as SpiErrorType;
+
+    use super::*;
+
+    /// A chip executing the commands, keeping a log of all but status reads.
+    struct MockChip {
+        mem: Vec<u8>,
+        sfdp: Vec<u8>,
+        write_enabled: bool,
+        four_byte: bool,
+        powered_down: bool,
+        /// Status reads reporting busy after each erase or program.
+        busy_polls: usize,
+        busy: usize,
+        status_reads: usize,
+        log: Vec<(u8, Option<u32>, usize)>,
+    }
+
+    /// SFDP tables of a chip with 4, 32 and 64 kB erases and 256 byte pages.
+    fn sfdp(capacity: u32) -> Vec<u8> {
+        let mut sfdp = vec![0xFF; 0x80];
+        sfdp[..8].copy_from_slice(&[b'S', b'F', b'D', b'P', 0x06, 0x01, 0x00, 0xFF]);
+        sfdp[8..16].copy_from_slice(&[0x00, 0x06, 0x01, 16, 0x80, 0x00, 0x00, 0xFF]);
+        let mut bfpt = [0u32; 16];
+        bfpt[0] = 0b01 | 0x20 << 8 | 1 << 16 | 1 << 20 | 1 << 21 | 1 << 22;
+        bfpt[1] = capacity * 8 - 1;
+        bfpt[7] = 0x520F_200C;
+        bfpt[8] = 0x0000_D810;
+        bfpt[10] = 8 << 4;
+        for dword in bfpt {
+            sfdp.extend_from_slice(&dword.to_le_bytes());
+        }
+        sfdp
+    }
+
+    impl MockChip {
+        fn new(capacity: u32) -> Self {
+            Self {
+                mem: vec![0xFF; capacity as usize],
+                sfdp: sfdp(capacity),
+                write_enabled: false,
+                four_byte: false,
+                powered_down: false,
+                busy_polls: 2,
+                busy: 0,
+                status_reads: 0,
+                log: Vec::new(),
+            }
+        }
+
+        fn address(&self, cmd: &[u8]) -> u32 {
+            if self.four_byte {
+                u32::from_be_bytes([cmd[1], cmd[2], cmd[3], cmd[4]])
+            } else {
+                u32::from_be_bytes([0, cmd[1], cmd[2], cmd[3]])
+            }
+        }
+
+        fn take_write_enable(&mut self) {
+            assert!(self.write_enabled, "write not enabled");
+            self.write_enabled = false;
+            self.busy = self.busy_polls;
+        }
+
+        /// Commands other than status reads, with their address and data length.
+        fn commands(&self) -> Vec<(u8, Option<u32>, usize)> {
+            self.log.clone()
+        }
+    }
+
+    impl SpiErrorType for MockChip {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for MockChip {
+        async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+            let (cmd, rest) = match operations {
+                [Operation::Write(cmd), rest @ ..] => (*cmd, rest),
+                _ => panic!("transaction without command"),
+            };
+            let opcode = cmd[0];
+            if self.powered_down {
+                assert_eq!(opcode, RELEASE_POWER_DOWN, "command while powered down");
+            }
+            if opcode != READ_STATUS && self.busy != 0 {
+                panic!("command {:#x} while busy", opcode);
+            }
+
+            let mut data = 0;
+            let mut address = None;
+            match opcode {
+                READ_JEDEC_ID => {
+                    let [Operation::Read(buf)] = rest else { panic!() };
+                    buf.copy_from_slice(&[0xEF, 0x40, 0x15]);
+                }
+                READ_SFDP => {
+                    assert_eq!(cmd.len(), 5);
+                    let addr = u32::from_be_bytes([0, cmd[1], cmd[2], cmd[3]]) as usize;
+                    let [Operation::Read(buf)] = rest else { panic!() };
+                    buf.copy_from_slice(&self.sfdp[addr..addr + buf.len()]);
+                    address = Some(addr as u32);
+                    data = buf.len();
+                }
+                READ_STATUS => {
+                    let [Operation::Read(buf)] = rest else { panic!() };
+                    self.status_reads += 1;
+                    buf[0] = (self.write_enabled as u8) << 1;
+                    if self.busy != 0 {
+                        self.busy -= 1;
+                        buf[0] |= STATUS_BUSY;
+                    }
+                    return Ok(());
+                }
+                WRITE_ENABLE => self.write_enabled = true,
+                ENTER_4_BYTE_ADDRESS => {
+                    self.take_write_enable();
+                    self.busy = 0;
+                    self.four_byte = true;
+                }
+                DEEP_POWER_DOWN => self.powered_down = true,
+                RELEASE_POWER_DOWN => self.powered_down = false,
+                FAST_READ => {
+                    assert_eq!(cmd.len(), if self.four_byte { 6 } else { 5 });
+                    let addr = self.address(cmd);
+                    let [Operation::Read(buf)] = rest else { panic!() };
+                    let addr_usize = addr as usize;
+                    buf.copy_from_slice(&self.mem[addr_usize..addr_usize + buf.len()]);
+                    address = Some(addr);
+                    data = buf.len();
+                }
+                PAGE_PROGRAM => {
+                    self.take_write_enable();
+                    let addr = self.address(cmd);
+                    let [Operation::Write(bytes)] = rest else { panic!() };
+                    assert!(
+                        addr / 256 == (addr + bytes.len() as u32 - 1) / 256,
+                        "program across pages"
+                    );
+                    for (i, b) in bytes.iter().enumerate() {
+                        self.mem[addr as usize + i] &= b;
+                    }
+                    address = Some(addr);
+                    data = bytes.len();
+                }
+                0x20 | 0x52 | 0xD8 => {
+                    self.take_write_enable();
+                    let addr = self.address(cmd);
+                    let size = match opcode {
+                        0x20 => 4096,
+                        0x52 => 32768,
+                        _ => 65536,
+                    };
+                    assert_eq!(addr % size, 0, "unaligned erase");
+                    self.mem[addr as usize..(addr + size) as usize].fill(0xFF);
+                    address = Some(addr);
+                }
+                _ => panic!("unknown command {:#x}", opcode),
+            }
+            self.log.push((opcode, address, data));
+            Ok(())
+        }
+    }
+
+    /// Run `fut` to completion, advancing the mock time if the status polls use timers.
+    fn run<F: Future>(fut: F) -> F::Output {
+        #[cfg(feature = "time")]
+        {
+            let _guard = crate::MOCK_TIME.lock().unwrap_or_else(|e| e.into_inner());
+            crate::bitbang::tests::run(fut).0
+        }
+        #[cfg(not(feature = "time"))]
+        embassy_futures::block_on(fut)
+    }
+
+    fn new_flash(chip: MockChip) -> SpiNor<MockChip> {
+        let mut flash = run(SpiNor::new(chip)).unwrap();
+        flash.spi.log.clear();
+        flash
+    }
+
+    #[test]
+    fn discovery() {
+        let flash = new_flash(MockChip::new(2 * 1024 * 1024));
+        assert_eq!(
+            flash.jedec_id(),
+            JedecId {
+                manufacturer: 0xEF,
+                memory_type: 0x40,
+                capacity: 0x15
+            }
+        );
+
+        let geometry = flash.geometry();
+        assert_eq!(geometry.capacity, 2 * 1024 * 1024);
+        assert_eq!(geometry.page_size, 256);
+        assert_eq!(
+            geometry.erase_types,
+            [
+                Some(EraseType {
+                    size: 4096,
+                    opcode: 0x20
+                }),
+                Some(EraseType {
+                    size: 32768,
+                    opcode: 0x52
+                }),
+                Some(EraseType {
+                    size: 65536,
+                    opcode: 0xD8
+                }),
+                None,
+            ]
+        );
+        assert!(geometry.fast_read_1_1_2 && geometry.fast_read_1_2_2);
+        assert!(geometry.fast_read_1_1_4 && geometry.fast_read_1_4_4);
+        assert_eq!(ReadNorFlash::capacity(&flash), 2 * 1024 * 1024);
+        assert!(!flash.spi.four_byte);
+    }
+
+    #[test]
+    fn no_sfdp() {
+        let mut chip = MockChip::new(2 * 1024 * 1024);
+        chip.sfdp[0] = 0xFF;
+        assert_eq!(run(SpiNor::new(chip)).err(), Some(Error::NoSfdp));
+
+        // Without 4 kB erases.
+        let mut chip = MockChip::new(2 * 1024 * 1024);
+        chip.sfdp[0x80 + 28] = 0;
+        assert_eq!(run(SpiNor::new(chip)).err(), Some(Error::Unsupported));
+    }
+
+    #[test]
+    fn erase_selection() {
+        let mut flash = new_flash(MockChip::new(2 * 1024 * 1024));
+        flash.spi.mem.fill(0x00);
+
+        run(NorFlash::erase(&mut flash, 0x1000, 0x21000)).unwrap();
+
+        let erases: Vec<_> = flash
+            .spi
+            .commands()
+            .into_iter()
+            .filter(|(opcode, _, _)| *opcode != WRITE_ENABLE)
+            .map(|(opcode, addr, _)| (opcode, addr.unwrap()))
+            .collect();
+        let mut expected: Vec<_> = (1..8).map(|i| (0x20, i * 0x1000)).collect();
+        expected.extend([(0x52, 0x8000), (0xD8, 0x10000), (0x20, 0x20000)]);
+        assert_eq!(erases, expected);
+
+        assert!(flash.spi.mem[..0x1000].iter().all(|b| *b == 0x00));
+        assert!(flash.spi.mem[0x1000..0x21000].iter().all(|b| *b == 0xFF));
+        assert!(flash.spi.mem[0x21000..].iter().all(|b| *b == 0x00));
+    }
+
+    #[test]
+    fn write_and_read() {
+        let mut flash = new_flash(MockChip::new(2 * 1024 * 1024));
+
+        let data: Vec<u8> = (0..32).collect();
+        run(NorFlash::write(&mut flash, 0xF0, &data)).unwrap();
+        assert_eq!(
+            flash.spi.commands(),
+            [
+                (WRITE_ENABLE, None, 0),
+                (PAGE_PROGRAM, Some(0xF0), 16),
+                (WRITE_ENABLE, None, 0),
+                (PAGE_PROGRAM, Some(0x100), 16),
+            ]
+        );
+        // The busy polls, and a last one for each program.
+        assert_eq!(flash.spi.status_reads, 2 * 3);
+
+        let mut buf = [0; 32];
+        run(ReadNorFlash::read(&mut flash, 0xF0, &mut buf)).unwrap();
+        assert_eq!(buf[..], data[..]);
+    }
+
+    #[test]
+    fn bounds() {
+        let mut flash = new_flash(MockChip::new(2 * 1024 * 1024));
+        let capacity = 2 * 1024 * 1024;
+
+        let mut buf = [0; 2];
+        assert_eq!(
+            run(ReadNorFlash::read(&mut flash, capacity - 1, &mut buf)),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            run(NorFlash::write(&mut flash, capacity - 1, &buf)),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            run(NorFlash::erase(&mut flash, capacity - 4096, capacity + 4096)),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(run(NorFlash::erase(&mut flash, 0x800, 0x1000)), Err(Error::NotAligned));
+        assert!(flash.spi.commands().is_empty());
+    }
+
+    #[test]
+    fn four_byte_addresses() {
+        let mut flash = run(SpiNor::new(MockChip::new(32 * 1024 * 1024))).unwrap();
+        assert!(flash.spi.four_byte);
+        flash.spi.log.clear();
+
+        run(NorFlash::write(&mut flash, 0x0100_0000, &[0x42])).unwrap();
+        let mut buf = [0];
+        run(ReadNorFlash::read(&mut flash, 0x0100_0000, &mut buf)).unwrap();
+        assert_eq!(buf, [0x42]);
+    }
+
+    #[test]
+    fn power_down() {
+        let mut flash = new_flash(MockChip::new(2 * 1024 * 1024));
+
+        run(flash.power_down()).unwrap();
+        assert!(flash.spi.powered_down);
+        run(flash.wake_up()).unwrap();
+        assert!(!flash.spi.powered_down);
+        assert_eq!(
+            flash.spi.commands(),
+            [(DEEP_POWER_DOWN, None, 0), (RELEASE_POWER_DOWN, None, 0)]
+        );
+    }
+
+    #[test]
+    fn blocking() {
+        use embedded_storage::nor_flash::{NorFlash as BlockingNorFlash, ReadNorFlash as BlockingReadNorFlash};
+
+        let mut chip = MockChip::new(2 * 1024 * 1024);
+        // The blocking implementation doesn't advance the mock time.
+        chip.busy_polls = 0;
+        let mut flash = new_flash(chip);
+
+        BlockingNorFlash::erase(&mut flash, 0, 4096).unwrap();
+        BlockingNorFlash::write(&mut flash, 4, &[1, 2, 3]).unwrap();
+        let mut buf = [0; 8];
+        BlockingReadNorFlash::read(&mut flash, 0, &mut buf).unwrap();
+        assert_eq!(buf, [0xFF, 0xFF, 0xFF, 0xFF, 1, 2, 3, 0xFF]);
+    }
+}
@@ -4,9 +4,19 @@
 #![doc = include_str!("../README.md")]
 
 pub mod adapter;
+#[cfg(feature = "time")]
+pub mod bitbang;
+#[cfg(feature = "time")]
+pub mod button;
 pub mod flash;
 pub mod shared_bus;
 
+/// Serializes the tests that use the global mock time driver.
+#[cfg(all(test, feature = "time"))]
+pub(crate) static MOCK_TIME: std::sync::Mutex<()> = std::sync::Mutex::new(());
+#[cfg(all(test, feature = "time"))]
+extern crate std;
+
 /// Set the configuration of a peripheral driver.
 ///
 /// This trait is intended to be implemented by peripheral drivers such as SPI
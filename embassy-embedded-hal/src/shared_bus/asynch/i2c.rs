@@ -22,51 +22,75 @@
 //! let mpu = Mpu6050::new(i2c_dev2);
 //! ```
 
+use core::marker::PhantomData;
+
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::mutex::Mutex;
 use embedded_hal_async::i2c;
 
+use super::BusMutex;
 use crate::SetConfig;
 use crate::shared_bus::I2cDeviceError;
 
 /// I2C device on a shared bus.
-pub struct I2cDevice<'a, M: RawMutex, BUS> {
-    bus: &'a Mutex<M, BUS>,
+///
+/// The bus is shared with a [`Mutex`] by default, or with any other [`BusMutex`] `L`.
+pub struct I2cDevice<'a, M: RawMutex, BUS, L: BusMutex = Mutex<M, BUS>> {
+    bus: &'a L,
+    priority: u8,
+    _phantom: PhantomData<fn() -> (M, BUS)>,
 }
 
-impl<'a, M: RawMutex, BUS> I2cDevice<'a, M, BUS> {
+impl<'a, L: BusMutex> I2cDevice<'a, L::Raw, L::Bus, L> {
     /// Create a new `I2cDevice`.
-    pub fn new(bus: &'a Mutex<M, BUS>) -> Self {
-        Self { bus }
+    pub fn new(bus: &'a L) -> Self {
+        Self::new_with_priority(bus, 0)
+    }
+
+    /// Create a new `I2cDevice`, which locks the bus with the given priority.
+    ///
+    /// With a [`PriorityMutex`](embassy_sync::priority_mutex::PriorityMutex), devices with a
+    /// higher priority get the bus first.
+    pub fn new_with_priority(bus: &'a L, priority: u8) -> Self {
+        Self {
+            bus,
+            priority,
+            _phantom: PhantomData,
+        }
     }
 }
 
-impl<'a, M: RawMutex, BUS> Clone for I2cDevice<'a, M, BUS> {
+impl<'a, M: RawMutex, BUS, L: BusMutex> Clone for I2cDevice<'a, M, BUS, L> {
     fn clone(&self) -> Self {
-        Self { bus: self.bus }
+        Self {
+            bus: self.bus,
+            priority: self.priority,
+            _phantom: PhantomData,
+        }
     }
 }
 
-impl<'a, M: RawMutex, BUS> i2c::ErrorType for I2cDevice<'a, M, BUS>
+impl<'a, M: RawMutex, BUS, L: BusMutex> i2c::ErrorType for I2cDevice<'a, M, BUS, L>
 where
     BUS: i2c::ErrorType,
 {
     type Error = I2cDeviceError<BUS::Error>;
 }
 
-impl<M, BUS> i2c::I2c for I2cDevice<'_, M, BUS>
+impl<M, BUS, L> i2c::I2c for I2cDevice<'_, M, BUS, L>
 where
     M: RawMutex,
     BUS: i2c::I2c,
+    L: BusMutex<Bus = BUS>,
 {
     async fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), I2cDeviceError<BUS::Error>> {
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.read(address, read).await.map_err(I2cDeviceError::I2c)?;
         Ok(())
     }
 
     async fn write(&mut self, address: u8, write: &[u8]) -> Result<(), I2cDeviceError<BUS::Error>> {
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.write(address, write).await.map_err(I2cDeviceError::I2c)?;
         Ok(())
     }
@@ -77,7 +101,7 @@ where
         write: &[u8],
         read: &mut [u8],
     ) -> Result<(), I2cDeviceError<BUS::Error>> {
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.write_read(address, write, read)
             .await
             .map_err(I2cDeviceError::I2c)?;
@@ -89,7 +113,7 @@ where
         address: u8,
         operations: &mut [embedded_hal_async::i2c::Operation<'_>],
     ) -> Result<(), I2cDeviceError<BUS::Error>> {
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.transaction(address, operations)
             .await
             .map_err(I2cDeviceError::I2c)?;
@@ -102,24 +126,44 @@ where
 /// This is like [`I2cDevice`], with an additional bus configuration that's applied
 /// to the bus before each use using [`SetConfig`]. This allows different
 /// devices on the same bus to use different communication settings.
-pub struct I2cDeviceWithConfig<'a, M: RawMutex, BUS: SetConfig> {
-    bus: &'a Mutex<M, BUS>,
+pub struct I2cDeviceWithConfig<'a, M: RawMutex, BUS: SetConfig, L: BusMutex = Mutex<M, BUS>> {
+    bus: &'a L,
     config: BUS::Config,
+    priority: u8,
+    _phantom: PhantomData<fn() -> M>,
 }
 
-impl<'a, M: RawMutex, BUS: SetConfig> I2cDeviceWithConfig<'a, M, BUS> {
+impl<'a, L: BusMutex> I2cDeviceWithConfig<'a, L::Raw, L::Bus, L>
+where
+    L::Bus: SetConfig,
+{
     /// Create a new `I2cDeviceWithConfig`.
-    pub fn new(bus: &'a Mutex<M, BUS>, config: BUS::Config) -> Self {
-        Self { bus, config }
+    pub fn new(bus: &'a L, config: <L::Bus as SetConfig>::Config) -> Self {
+        Self::new_with_priority(bus, config, 0)
+    }
+
+    /// Create a new `I2cDeviceWithConfig`, which locks the bus with the given priority.
+    ///
+    /// With a [`PriorityMutex`](embassy_sync::priority_mutex::PriorityMutex), devices with a
+    /// higher priority get the bus first.
+    pub fn new_with_priority(bus: &'a L, config: <L::Bus as SetConfig>::Config, priority: u8) -> Self {
+        Self {
+            bus,
+            config,
+            priority,
+            _phantom: PhantomData,
+        }
     }
+}
 
+impl<'a, M: RawMutex, BUS: SetConfig, L: BusMutex> I2cDeviceWithConfig<'a, M, BUS, L> {
     /// Change the device's config at runtime
     pub fn set_config(&mut self, config: BUS::Config) {
         self.config = config;
     }
 }
 
-impl<'a, M: RawMutex, BUS: SetConfig> Clone for I2cDeviceWithConfig<'a, M, BUS>
+impl<'a, M: RawMutex, BUS: SetConfig, L: BusMutex> Clone for I2cDeviceWithConfig<'a, M, BUS, L>
 where
     BUS::Config: Clone,
 {
@@ -127,33 +171,37 @@ where
         Self {
             bus: self.bus,
             config: self.config.clone(),
+            priority: self.priority,
+            _phantom: PhantomData,
         }
     }
 }
 
-impl<'a, M, BUS> i2c::ErrorType for I2cDeviceWithConfig<'a, M, BUS>
+impl<'a, M, BUS, L> i2c::ErrorType for I2cDeviceWithConfig<'a, M, BUS, L>
 where
     BUS: i2c::ErrorType,
     M: RawMutex,
     BUS: SetConfig,
+    L: BusMutex,
 {
     type Error = I2cDeviceError<BUS::Error>;
 }
 
-impl<M, BUS> i2c::I2c for I2cDeviceWithConfig<'_, M, BUS>
+impl<M, BUS, L> i2c::I2c for I2cDeviceWithConfig<'_, M, BUS, L>
 where
     M: RawMutex,
     BUS: i2c::I2c + SetConfig,
+    L: BusMutex<Bus = BUS>,
 {
     async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), I2cDeviceError<BUS::Error>> {
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.set_config(&self.config).map_err(|_| I2cDeviceError::Config)?;
         bus.read(address, buffer).await.map_err(I2cDeviceError::I2c)?;
         Ok(())
     }
 
     async fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), I2cDeviceError<BUS::Error>> {
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.set_config(&self.config).map_err(|_| I2cDeviceError::Config)?;
         bus.write(address, bytes).await.map_err(I2cDeviceError::I2c)?;
         Ok(())
@@ -165,7 +213,7 @@ where
         wr_buffer: &[u8],
         rd_buffer: &mut [u8],
     ) -> Result<(), I2cDeviceError<BUS::Error>> {
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.set_config(&self.config).map_err(|_| I2cDeviceError::Config)?;
         bus.write_read(address, wr_buffer, rd_buffer)
             .await
@@ -174,7 +222,7 @@ where
     }
 
     async fn transaction(&mut self, address: u8, operations: &mut [i2c::Operation<'_>]) -> Result<(), Self::Error> {
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.set_config(&self.config).map_err(|_| I2cDeviceError::Config)?;
         bus.transaction(address, operations)
             .await
@@ -182,3 +230,54 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::Infallible;
+    use core::pin::pin;
+    use std::vec;
+    use std::vec::Vec;
+
+    use embassy_futures::join::join;
+    use embassy_futures::poll_once;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_sync::priority_mutex::PriorityMutex;
+    use embedded_hal_async::i2c::I2c;
+
+    use super::*;
+
+    /// Records the addresses of the transactions.
+    struct MockI2c(Vec<u8>);
+
+    impl i2c::ErrorType for MockI2c {
+        type Error = Infallible;
+    }
+
+    impl i2c::I2c for MockI2c {
+        async fn transaction(&mut self, address: u8, _operations: &mut [i2c::Operation<'_>]) -> Result<(), Infallible> {
+            self.0.push(address);
+            Ok(())
+        }
+    }
+
+    #[futures_test::test]
+    async fn priority_mutex_serves_highest_priority_first() {
+        let bus = PriorityMutex::<NoopRawMutex, _>::new(MockI2c(Vec::new()));
+        let mut low = I2cDevice::new_with_priority(&bus, 1);
+        let mut high = I2cDevice::new_with_priority(&bus, 5);
+
+        let guard = bus.lock().await;
+        let mut low_write = pin!(low.write(0x10, &[0]));
+        let mut high_write = pin!(high.write(0x20, &[0]));
+        assert!(poll_once(low_write.as_mut()).is_pending());
+        assert!(poll_once(high_write.as_mut()).is_pending());
+        drop(guard);
+
+        let (low_res, high_res) = join(low_write, high_write).await;
+        low_res.unwrap();
+        high_res.unwrap();
+        assert_eq!(bus.lock().await.0, vec![0x20, 0x10]);
+    }
+}
@@ -1,3 +1,56 @@
 //! Asynchronous shared bus implementations for embedded-hal-async
+use core::ops::DerefMut;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use embassy_sync::priority_mutex::{PriorityMutex, PriorityMutexGuard};
+
 pub mod i2c;
 pub mod spi;
+
+/// Async mutex a bus is shared with.
+///
+/// The devices of this module lock the bus through this trait, so the bus can be shared with a
+/// [`Mutex`], or with a [`PriorityMutex`] to serve devices created with a higher priority
+/// (with `new_with_priority`) first when several are waiting for the bus.
+pub trait BusMutex {
+    /// Blocking mutex guarding the lock state.
+    type Raw: RawMutex;
+    /// The shared bus.
+    type Bus;
+    /// Guard giving access to the bus, which unlocks it when dropped.
+    type Guard<'a>: DerefMut<Target = Self::Bus>
+    where
+        Self: 'a;
+
+    /// Lock the bus for a device with the given priority.
+    ///
+    /// Mutexes that don't order their waiters ignore `priority`.
+    async fn lock(&self, priority: u8) -> Self::Guard<'_>;
+}
+
+impl<M: RawMutex, BUS> BusMutex for Mutex<M, BUS> {
+    type Raw = M;
+    type Bus = BUS;
+    type Guard<'a>
+        = MutexGuard<'a, M, BUS>
+    where
+        Self: 'a;
+
+    async fn lock(&self, _priority: u8) -> Self::Guard<'_> {
+        Mutex::lock(self).await
+    }
+}
+
+impl<M: RawMutex, BUS> BusMutex for PriorityMutex<M, BUS> {
+    type Raw = M;
+    type Bus = BUS;
+    type Guard<'a>
+        = PriorityMutexGuard<'a, M, BUS>
+    where
+        Self: 'a;
+
+    async fn lock(&self, priority: u8) -> Self::Guard<'_> {
+        self.lock_with_priority(priority).await
+    }
+}
@@ -25,6 +25,8 @@
 //! let display2 = ST7735::new(spi_dev2, dc2, rst2, Default::default(), 160, 128);
 //! ```
 
+use core::marker::PhantomData;
+
 use embassy_hal_internal::drop::OnDrop;
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::mutex::Mutex;
@@ -32,23 +34,41 @@ use embedded_hal_1::digital::OutputPin;
 use embedded_hal_1::spi::Operation;
 use embedded_hal_async::spi;
 
+use super::BusMutex;
 use crate::SetConfig;
 use crate::shared_bus::SpiDeviceError;
 
 /// SPI device on a shared bus.
-pub struct SpiDevice<'a, M: RawMutex, BUS, CS> {
-    bus: &'a Mutex<M, BUS>,
+///
+/// The bus is shared with a [`Mutex`] by default, or with any other [`BusMutex`] `L`.
+pub struct SpiDevice<'a, M: RawMutex, BUS, CS, L: BusMutex = Mutex<M, BUS>> {
+    bus: &'a L,
     cs: CS,
+    priority: u8,
+    _phantom: PhantomData<fn() -> (M, BUS)>,
 }
 
-impl<'a, M: RawMutex, BUS, CS> SpiDevice<'a, M, BUS, CS> {
+impl<'a, L: BusMutex, CS> SpiDevice<'a, L::Raw, L::Bus, CS, L> {
     /// Create a new `SpiDevice`.
-    pub fn new(bus: &'a Mutex<M, BUS>, cs: CS) -> Self {
-        Self { bus, cs }
+    pub fn new(bus: &'a L, cs: CS) -> Self {
+        Self::new_with_priority(bus, cs, 0)
+    }
+
+    /// Create a new `SpiDevice`, which locks the bus with the given priority.
+    ///
+    /// With a [`PriorityMutex`](embassy_sync::priority_mutex::PriorityMutex), devices with a
+    /// higher priority get the bus first.
+    pub fn new_with_priority(bus: &'a L, cs: CS, priority: u8) -> Self {
+        Self {
+            bus,
+            cs,
+            priority,
+            _phantom: PhantomData,
+        }
     }
 }
 
-impl<'a, M: RawMutex, BUS, CS> spi::ErrorType for SpiDevice<'a, M, BUS, CS>
+impl<'a, M: RawMutex, BUS, CS, L: BusMutex> spi::ErrorType for SpiDevice<'a, M, BUS, CS, L>
 where
     BUS: spi::ErrorType,
     CS: OutputPin,
@@ -56,11 +76,12 @@ where
     type Error = SpiDeviceError<BUS::Error, CS::Error>;
 }
 
-impl<M, BUS, CS, Word> spi::SpiDevice<Word> for SpiDevice<'_, M, BUS, CS>
+impl<M, BUS, CS, L, Word> spi::SpiDevice<Word> for SpiDevice<'_, M, BUS, CS, L>
 where
     M: RawMutex,
     BUS: spi::SpiBus<Word>,
     CS: OutputPin,
+    L: BusMutex<Bus = BUS>,
     Word: Copy + 'static,
 {
     async fn transaction(&mut self, operations: &mut [spi::Operation<'_, Word>]) -> Result<(), Self::Error> {
@@ -68,7 +89,7 @@ where
             return Err(SpiDeviceError::DelayNotSupported);
         }
 
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         self.cs.set_low().map_err(SpiDeviceError::Cs)?;
 
         let cs_drop = OnDrop::new(|| {
@@ -125,38 +146,61 @@ where
 /// This is like [`SpiDevice`], with an additional bus configuration that's applied
 /// to the bus before each use using [`SetConfig`]. This allows different
 /// devices on the same bus to use different communication settings.
-pub struct SpiDeviceWithConfig<'a, M: RawMutex, BUS: SetConfig, CS> {
-    bus: &'a Mutex<M, BUS>,
+pub struct SpiDeviceWithConfig<'a, M: RawMutex, BUS: SetConfig, CS, L: BusMutex = Mutex<M, BUS>> {
+    bus: &'a L,
     cs: CS,
     config: BUS::Config,
+    priority: u8,
+    _phantom: PhantomData<fn() -> M>,
 }
 
-impl<'a, M: RawMutex, BUS: SetConfig, CS> SpiDeviceWithConfig<'a, M, BUS, CS> {
+impl<'a, L: BusMutex, CS> SpiDeviceWithConfig<'a, L::Raw, L::Bus, CS, L>
+where
+    L::Bus: SetConfig,
+{
     /// Create a new `SpiDeviceWithConfig`.
-    pub fn new(bus: &'a Mutex<M, BUS>, cs: CS, config: BUS::Config) -> Self {
-        Self { bus, cs, config }
+    pub fn new(bus: &'a L, cs: CS, config: <L::Bus as SetConfig>::Config) -> Self {
+        Self::new_with_priority(bus, cs, config, 0)
     }
 
+    /// Create a new `SpiDeviceWithConfig`, which locks the bus with the given priority.
+    ///
+    /// With a [`PriorityMutex`](embassy_sync::priority_mutex::PriorityMutex), devices with a
+    /// higher priority get the bus first.
+    pub fn new_with_priority(bus: &'a L, cs: CS, config: <L::Bus as SetConfig>::Config, priority: u8) -> Self {
+        Self {
+            bus,
+            cs,
+            config,
+            priority,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, M: RawMutex, BUS: SetConfig, CS, L: BusMutex> SpiDeviceWithConfig<'a, M, BUS, CS, L> {
     /// Change the device's config at runtime
     pub fn set_config(&mut self, config: BUS::Config) {
         self.config = config;
     }
 }
 
-impl<'a, M, BUS, CS> spi::ErrorType for SpiDeviceWithConfig<'a, M, BUS, CS>
+impl<'a, M, BUS, CS, L> spi::ErrorType for SpiDeviceWithConfig<'a, M, BUS, CS, L>
 where
     BUS: spi::ErrorType + SetConfig,
     CS: OutputPin,
     M: RawMutex,
+    L: BusMutex,
 {
     type Error = SpiDeviceError<BUS::Error, CS::Error>;
 }
 
-impl<M, BUS, CS, Word> spi::SpiDevice<Word> for SpiDeviceWithConfig<'_, M, BUS, CS>
+impl<M, BUS, CS, L, Word> spi::SpiDevice<Word> for SpiDeviceWithConfig<'_, M, BUS, CS, L>
 where
     M: RawMutex,
     BUS: spi::SpiBus<Word> + SetConfig,
     CS: OutputPin,
+    L: BusMutex<Bus = BUS>,
     Word: Copy + 'static,
 {
     async fn transaction(&mut self, operations: &mut [spi::Operation<'_, Word>]) -> Result<(), Self::Error> {
@@ -164,7 +208,7 @@ where
             return Err(SpiDeviceError::DelayNotSupported);
         }
 
-        let mut bus = self.bus.lock().await;
+        let mut bus = self.bus.lock(self.priority).await;
         bus.set_config(&self.config).map_err(|_| SpiDeviceError::Config)?;
         self.cs.set_low().map_err(SpiDeviceError::Cs)?;
 
@@ -0,0 +1,264 @@
+//! Labeled devices
+//!
+//! [`Labeled`] wraps an I2C or SPI device, such as the shared bus devices in this crate, and
+//! attaches a label and the failed operation to its errors. This makes it possible to tell which
+//! device failed from an error that bubbled up through a device driver.
+//!
+//! # Example (nrf52)
+//!
+//! ```rust,ignore
+//! use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+//! use embassy_embedded_hal::shared_bus::labeled::Labeled;
+//!
+//! let imu = Labeled::new(I2cDevice::new(i2c_bus), "imu");
+//! let mut mpu = Mpu6050::new(imu);
+//!
+//! // Errors now look like `imu: I2c(AddressNack) during write`.
+//! mpu.init().await?;
+//! ```
+
+use core::fmt::Debug;
+
+use embedded_hal_1::{i2c, spi};
+
+use crate::shared_bus::{BusOperation, LabeledError};
+
+/// I2C or SPI device with a label attached to its errors.
+///
+/// `L` is the label type. It's usually a `&'static str`, but HALs can provide richer labels, for
+/// example also identifying the bus peripheral.
+pub struct Labeled<D, L = &'static str> {
+    device: D,
+    label: L,
+}
+
+impl<D, L: Copy> Labeled<D, L> {
+    /// Create a new `Labeled` device.
+    pub fn new(device: D, label: L) -> Self {
+        Self { device, label }
+    }
+
+    /// Get the label of the device.
+    pub fn label(&self) -> L {
+        self.label
+    }
+
+    /// Get a reference to the inner device.
+    pub fn inner(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    /// Consume the `Labeled` device, returning the inner device.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+}
+
+fn labeled<E, L>(label: L, operation: BusOperation) -> impl FnOnce(E) -> LabeledError<E, L> {
+    move |error| LabeledError {
+        label,
+        operation,
+        error,
+    }
+}
+
+impl<D: i2c::ErrorType, L: Copy + Debug> i2c::ErrorType for Labeled<D, L> {
+    type Error = LabeledError<D::Error, L>;
+}
+
+impl<D: i2c::I2c, L: Copy + Debug> i2c::I2c for Labeled<D, L> {
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.device
+            .read(address, read)
+            .map_err(labeled(self.label, BusOperation::Read))
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.device
+            .write(address, write)
+            .map_err(labeled(self.label, BusOperation::Write))
+    }
+
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Self::Error> {
+        self.device
+            .write_read(address, write, read)
+            .map_err(labeled(self.label, BusOperation::WriteRead))
+    }
+
+    fn transaction(&mut self, address: u8, operations: &mut [i2c::Operation<'_>]) -> Result<(), Self::Error> {
+        self.device
+            .transaction(address, operations)
+            .map_err(labeled(self.label, BusOperation::Transaction))
+    }
+}
+
+impl<D: embedded_hal_async::i2c::I2c, L: Copy + Debug> embedded_hal_async::i2c::I2c for Labeled<D, L> {
+    async fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.device
+            .read(address, read)
+            .await
+            .map_err(labeled(self.label, BusOperation::Read))
+    }
+
+    async fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.device
+            .write(address, write)
+            .await
+            .map_err(labeled(self.label, BusOperation::Write))
+    }
+
+    async fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Self::Error> {
+        self.device
+            .write_read(address, write, read)
+            .await
+            .map_err(labeled(self.label, BusOperation::WriteRead))
+    }
+
+    async fn transaction(&mut self, address: u8, operations: &mut [i2c::Operation<'_>]) -> Result<(), Self::Error> {
+        self.device
+            .transaction(address, operations)
+            .await
+            .map_err(labeled(self.label, BusOperation::Transaction))
+    }
+}
+
+impl<D: spi::ErrorType, L: Copy + Debug> spi::ErrorType for Labeled<D, L> {
+    type Error = LabeledError<D::Error, L>;
+}
+
+impl<D, L, Word> spi::SpiDevice<Word> for Labeled<D, L>
+where
+    D: spi::SpiDevice<Word>,
+    L: Copy + Debug,
+    Word: Copy + 'static,
+{
+    fn transaction(&mut self, operations: &mut [spi::Operation<'_, Word>]) -> Result<(), Self::Error> {
+        self.device
+            .transaction(operations)
+            .map_err(labeled(self.label, BusOperation::Transaction))
+    }
+
+    fn read(&mut self, buf: &mut [Word]) -> Result<(), Self::Error> {
+        self.device.read(buf).map_err(labeled(self.label, BusOperation::Read))
+    }
+
+    fn write(&mut self, buf: &[Word]) -> Result<(), Self::Error> {
+        self.device.write(buf).map_err(labeled(self.label, BusOperation::Write))
+    }
+
+    fn transfer(&mut self, read: &mut [Word], write: &[Word]) -> Result<(), Self::Error> {
+        self.device
+            .transfer(read, write)
+            .map_err(labeled(self.label, BusOperation::Transfer))
+    }
+
+    fn transfer_in_place(&mut self, buf: &mut [Word]) -> Result<(), Self::Error> {
+        self.device
+            .transfer_in_place(buf)
+            .map_err(labeled(self.label, BusOperation::TransferInPlace))
+    }
+}
+
+impl<D, L, Word> embedded_hal_async::spi::SpiDevice<Word> for Labeled<D, L>
+where
+    D: embedded_hal_async::spi::SpiDevice<Word>,
+    L: Copy + Debug,
+    Word: Copy + 'static,
+{
+    async fn transaction(&mut self, operations: &mut [spi::Operation<'_, Word>]) -> Result<(), Self::Error> {
+        self.device
+            .transaction(operations)
+            .await
+            .map_err(labeled(self.label, BusOperation::Transaction))
+    }
+
+    async fn read(&mut self, buf: &mut [Word]) -> Result<(), Self::Error> {
+        self.device
+            .read(buf)
+            .await
+            .map_err(labeled(self.label, BusOperation::Read))
+    }
+
+    async fn write(&mut self, buf: &[Word]) -> Result<(), Self::Error> {
+        self.device
+            .write(buf)
+            .await
+            .map_err(labeled(self.label, BusOperation::Write))
+    }
+
+    async fn transfer(&mut self, read: &mut [Word], write: &[Word]) -> Result<(), Self::Error> {
+        self.device
+            .transfer(read, write)
+            .await
+            .map_err(labeled(self.label, BusOperation::Transfer))
+    }
+
+    async fn transfer_in_place(&mut self, buf: &mut [Word]) -> Result<(), Self::Error> {
+        self.device
+            .transfer_in_place(buf)
+            .await
+            .map_err(labeled(self.label, BusOperation::TransferInPlace))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use embedded_hal_1::i2c::{ErrorKind, I2c, NoAcknowledgeSource};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum MockError {
+        AddressNack,
+    }
+
+    impl i2c::Error for MockError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+        }
+    }
+
+    struct MockI2c;
+
+    impl i2c::ErrorType for MockI2c {
+        type Error = MockError;
+    }
+
+    impl I2c for MockI2c {
+        fn transaction(&mut self, _address: u8, _operations: &mut [i2c::Operation<'_>]) -> Result<(), MockError> {
+            Err(MockError::AddressNack)
+        }
+    }
+
+    #[test]
+    fn error_carries_label_and_operation() {
+        let mut dev = Labeled::new(MockI2c, "imu");
+
+        let err = dev.write(0x68, &[0x6B, 0x00]).unwrap_err();
+        assert_eq!("imu", err.label);
+        assert_eq!(BusOperation::Write, err.operation);
+        assert_eq!(MockError::AddressNack, err.error);
+        assert_eq!(
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            i2c::Error::kind(&err)
+        );
+
+        let err = dev.write_read(0x68, &[0x75], &mut [0]).unwrap_err();
+        assert_eq!(BusOperation::WriteRead, err.operation);
+    }
+
+    #[test]
+    fn display() {
+        let mut dev = Labeled::new(MockI2c, "imu");
+
+        let err = dev.write(0x68, &[0x6B, 0x00]).unwrap_err();
+        assert_eq!("imu: AddressNack during write", format!("{}", err));
+
+        let err = dev.read(0x68, &mut [0]).unwrap_err();
+        assert_eq!("imu: AddressNack during read", format!("{}", err));
+    }
+}
@@ -1,10 +1,11 @@
 //! Shared bus implementations
-use core::fmt::Debug;
+use core::fmt::{Debug, Display};
 
 use embedded_hal_1::{i2c, spi};
 
 pub mod asynch;
 pub mod blocking;
+pub mod labeled;
 
 /// Error returned by I2C device implementations in this crate.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -57,3 +58,82 @@ where
         }
     }
 }
+
+/// Bus operation that was being performed when an error occurred.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum BusOperation {
+    /// I2C read, or SPI read.
+    Read,
+    /// I2C write, or SPI write.
+    Write,
+    /// I2C write followed by a read.
+    WriteRead,
+    /// I2C or SPI transaction.
+    Transaction,
+    /// SPI transfer.
+    Transfer,
+    /// SPI in-place transfer.
+    TransferInPlace,
+}
+
+impl Display for BusOperation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let s = match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::WriteRead => "write-read",
+            Self::Transaction => "transaction",
+            Self::Transfer => "transfer",
+            Self::TransferInPlace => "in-place transfer",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Error returned by [`Labeled`](labeled::Labeled) devices, carrying the device label and the failed operation.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct LabeledError<E, L = &'static str> {
+    /// Label of the device the operation was performed on.
+    pub label: L,
+    /// Operation that failed.
+    pub operation: BusOperation,
+    /// Error returned by the device.
+    pub error: E,
+}
+
+impl<E: Debug, L: Display> Display for LabeledError<E, L> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}: {:?} during {}", self.label, self.error, self.operation)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<E: defmt::Format, L: defmt::Format> defmt::Format for LabeledError<E, L> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{}: {} during {}", self.label, self.error, self.operation)
+    }
+}
+
+impl<E: Debug, L: Debug + Display> core::error::Error for LabeledError<E, L> {}
+
+impl<E, L> i2c::Error for LabeledError<E, L>
+where
+    E: i2c::Error,
+    L: Debug,
+{
+    fn kind(&self) -> i2c::ErrorKind {
+        self.error.kind()
+    }
+}
+
+impl<E, L> spi::Error for LabeledError<E, L>
+where
+    E: spi::Error,
+    L: Debug,
+{
+    fn kind(&self) -> spi::ErrorKind {
+        self.error.kind()
+    }
+}
@@ -193,6 +193,21 @@ pub fn run(args: TokenStream, item: TokenStream) -> TokenStream {
         quote!(_spawn_async_fn)
     };
 
+    let task_name = task_ident.to_string();
+
+    // Record the function name for the tracing hooks, which use it for tasks without a name.
+    // It is NUL-terminated, as expected by rtos-trace.
+    let name_task = if cfg!(feature = "metadata-name") {
+        let fn_name = format!("{}\0", task_ident);
+        quote! {
+            if let Ok(token) = &token {
+                token.metadata().__set_fn_name(#fn_name);
+            }
+        }
+    } else {
+        quote!()
+    };
+
     #[cfg(feature = "nightly")]
     let mut task_outer_body = quote! {
         trait _EmbassyInternalTaskTrait {
@@ -209,7 +224,13 @@ pub fn run(args: TokenStream, item: TokenStream) -> TokenStream {
 
         const POOL_SIZE: usize = #pool_size;
         static POOL: #embassy_executor::raw::TaskPool<<() as _EmbassyInternalTaskTrait>::Fut, POOL_SIZE> = #embassy_executor::raw::TaskPool::new();
-        unsafe { POOL.#spawn(move || <() as _EmbassyInternalTaskTrait>::construct(#(#full_args,)*)) }
+        let token = unsafe { POOL.#spawn(move || <() as _EmbassyInternalTaskTrait>::construct(#(#full_args,)*)) };
+        let token = match token {
+            Ok(token) => Ok(token),
+            Err(e) => Err(e.__with_task(#task_name)),
+        };
+        #name_task
+        token
     };
     #[cfg(not(feature = "nightly"))]
     let mut task_outer_body = quote! {
@@ -226,7 +247,13 @@ pub fn run(args: TokenStream, item: TokenStream) -> TokenStream {
             {#embassy_executor::_export::task_pool_size::<_, _, _, POOL_SIZE>(#task_inner_ident)},
             {#embassy_executor::_export::task_pool_align::<_, _, _, POOL_SIZE>(#task_inner_ident)},
         > = unsafe { ::core::mem::transmute(#embassy_executor::_export::task_pool_new::<_, _, _, POOL_SIZE>(#task_inner_ident)) };
-        unsafe { __task_pool_get(#task_inner_ident).#spawn(move || #task_inner_ident(#(#full_args,)*)) }
+        let token = unsafe { __task_pool_get(#task_inner_ident).#spawn(move || #task_inner_ident(#(#full_args,)*)) };
+        let token = match token {
+            Ok(token) => Ok(token),
+            Err(e) => Err(e.__with_task(#task_name)),
+        };
+        #name_task
+        token
     };
 
     let task_outer_attrs = &f.attrs;
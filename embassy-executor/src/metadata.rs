@@ -16,6 +16,8 @@ use crate::raw::Deadline;
 pub struct Metadata {
     #[cfg(feature = "metadata-name")]
     name: Mutex<Cell<Option<&'static str>>>,
+    #[cfg(feature = "metadata-name")]
+    fn_name: Mutex<Cell<Option<&'static str>>>,
     #[cfg(feature = "scheduler-priority")]
     priority: AtomicU8,
     #[cfg(feature = "scheduler-deadline")]
@@ -27,6 +29,8 @@ impl Metadata {
         Self {
             #[cfg(feature = "metadata-name")]
             name: Mutex::new(Cell::new(None)),
+            #[cfg(feature = "metadata-name")]
+            fn_name: Mutex::new(Cell::new(None)),
             #[cfg(feature = "scheduler-priority")]
             priority: AtomicU8::new(0),
             // NOTE: The deadline is set to zero to allow the initializer to reside in `.bss`. This
@@ -38,7 +42,10 @@ impl Metadata {
 
     pub(crate) fn reset(&self) {
         #[cfg(feature = "metadata-name")]
-        critical_section::with(|cs| self.name.borrow(cs).set(None));
+        critical_section::with(|cs| {
+            self.name.borrow(cs).set(None);
+            self.fn_name.borrow(cs).set(None);
+        });
 
         #[cfg(feature = "scheduler-priority")]
         self.set_priority(0);
@@ -75,6 +82,19 @@ impl Metadata {
         critical_section::with(|cs| self.name.borrow(cs).set(Some(name)))
     }
 
+    #[doc(hidden)]
+    #[cfg(feature = "metadata-name")]
+    pub fn __set_fn_name(&self, name: &'static str) {
+        critical_section::with(|cs| self.fn_name.borrow(cs).set(Some(name)))
+    }
+
+    /// Name reported by the tracing hooks: the name set with [`set_name`](Self::set_name), or
+    /// else the name of the `#[embassy_executor::task]` function.
+    #[cfg(any(feature = "rtos-trace", feature = "trace-stats"))]
+    pub(crate) fn trace_name(&self) -> Option<&'static str> {
+        critical_section::with(|cs| self.name.borrow(cs).get().or(self.fn_name.borrow(cs).get()))
+    }
+
     /// Get this task's priority.
     #[cfg(feature = "scheduler-priority")]
     pub fn priority(&self) -> u8 {
@@ -114,8 +114,11 @@ pub(crate) struct TaskHeader {
 
     pub(crate) metadata: Metadata,
 
-    #[cfg(feature = "rtos-trace")]
+    #[cfg(feature = "_task_tracker")]
     all_tasks_next: AtomicPtr<TaskHeader>,
+
+    #[cfg(feature = "trace-stats")]
+    stats: trace::stats::TaskCounters,
 }
 
 /// This is essentially a `&'static TaskStorage<F>` where the type of the future has been erased.
@@ -216,8 +219,10 @@ impl<F: Future + 'static> TaskStorage<F> {
 
                 timer_queue_item: TimerQueueItem::new(),
                 metadata: Metadata::new(),
-                #[cfg(feature = "rtos-trace")]
+                #[cfg(feature = "_task_tracker")]
                 all_tasks_next: AtomicPtr::new(core::ptr::null_mut()),
+                #[cfg(feature = "trace-stats")]
+                stats: trace::stats::TaskCounters::new(),
             },
             future: UninitCell::uninit(),
         }
@@ -240,7 +245,7 @@ impl<F: Future + 'static> TaskStorage<F> {
         let task = AvailableTask::claim(self);
         match task {
             Some(task) => Ok(task.initialize(future)),
-            None => Err(SpawnError::Busy),
+            None => Err(SpawnError::Busy { task: None }),
         }
     }
 
@@ -372,7 +377,7 @@ impl<F: Future + 'static, const N: usize> TaskPool<F, N> {
     fn spawn_impl<T>(&'static self, future: impl FnOnce() -> F) -> Result<SpawnToken<T>, SpawnError> {
         match self.pool.iter().find_map(AvailableTask::claim) {
             Some(task) => Ok(task.initialize_impl::<T>(future)),
-            None => Err(SpawnError::Busy),
+            None => Err(SpawnError::Busy { task: None }),
         }
     }
 
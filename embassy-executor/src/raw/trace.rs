@@ -81,6 +81,9 @@
 
 #![allow(unused)]
 
+#[cfg(feature = "trace-stats")]
+pub mod stats;
+
 use core::cell::UnsafeCell;
 use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 
@@ -95,7 +98,7 @@ use crate::spawner::{SpawnError, SpawnToken, Spawner};
 /// This static provides access to the global task tracker which maintains
 /// a list of all tasks in the system. It's automatically updated by the
 /// task lifecycle hooks in the trace module.
-#[cfg(feature = "rtos-trace")]
+#[cfg(feature = "_task_tracker")]
 pub(crate) static TASK_TRACKER: TaskTracker = TaskTracker::new();
 
 /// A thread-safe tracker for all tasks in the system
@@ -103,12 +106,12 @@ pub(crate) static TASK_TRACKER: TaskTracker = TaskTracker::new();
 /// This struct uses an intrusive linked list approach to track all tasks
 /// without additional memory allocations. It maintains a global list of
 /// tasks that can be traversed to find all currently existing tasks.
-#[cfg(feature = "rtos-trace")]
+#[cfg(feature = "_task_tracker")]
 pub(crate) struct TaskTracker {
     head: AtomicPtr<TaskHeader>,
 }
 
-#[cfg(feature = "rtos-trace")]
+#[cfg(feature = "_task_tracker")]
 impl TaskTracker {
     /// Creates a new empty task tracker
     ///
@@ -130,6 +133,13 @@ impl TaskTracker {
     pub fn add(&self, task: TaskRef) {
         let task_ptr = task.as_ptr();
 
+        // A task spawned again after it ended is still in the list.
+        let mut tracked = false;
+        self.for_each(|t| tracked |= t == task);
+        if tracked {
+            return;
+        }
+
         loop {
             let current_head = self.head.load(Ordering::Acquire);
             unsafe {
@@ -246,7 +256,7 @@ pub(crate) fn task_new(executor: &SyncExecutor, task: &TaskRef) {
     #[cfg(feature = "rtos-trace")]
     {
         rtos_trace::trace::task_new(task.as_ptr() as u32);
-        let name = task.metadata().name().unwrap_or("unnamed task\0");
+        let name = task.metadata().trace_name().unwrap_or("unnamed task\0");
         let info = rtos_trace::TaskInfo {
             name,
             priority: 0,
@@ -256,8 +266,11 @@ pub(crate) fn task_new(executor: &SyncExecutor, task: &TaskRef) {
         rtos_trace::trace::task_send_info(task.id(), info);
     }
 
-    #[cfg(feature = "rtos-trace")]
+    #[cfg(feature = "_task_tracker")]
     TASK_TRACKER.add(*task);
+
+    #[cfg(feature = "trace-stats")]
+    stats::task_new(task);
 }
 
 #[inline]
@@ -286,6 +299,9 @@ pub(crate) fn task_exec_begin(executor: &SyncExecutor, task: &TaskRef) {
     }
     #[cfg(feature = "rtos-trace")]
     rtos_trace::trace::task_exec_begin(task.as_ptr() as u32);
+
+    #[cfg(feature = "trace-stats")]
+    stats::task_exec_begin(task);
 }
 
 #[inline]
@@ -296,6 +312,9 @@ pub(crate) fn task_exec_end(executor: &SyncExecutor, task: &TaskRef) {
     }
     #[cfg(feature = "rtos-trace")]
     rtos_trace::trace::task_exec_end();
+
+    #[cfg(feature = "trace-stats")]
+    stats::task_exec_end(task);
 }
 
 #[inline]
@@ -358,7 +377,7 @@ impl rtos_trace::RtosTraceOSCallbacks for crate::raw::SyncExecutor {
     fn task_list() {
         with_all_active_tasks(|task| {
             let info = rtos_trace::TaskInfo {
-                name: task.metadata().name().unwrap_or("unnamed task\0"),
+                name: task.metadata().trace_name().unwrap_or("unnamed task\0"),
                 priority: 0,
                 stack_base: 0,
                 stack_size: 0,
@@ -0,0 +1,125 @@
+//! # Task statistics
+//!
+//! The `trace-stats` feature counts the polls of each task and the time spent polling it,
+//! to find out which task hogs the CPU without a debugger. It is a consumer of the tracing hooks
+//! of this module, like `rtos-trace`, and can be enabled alongside `trace`.
+//!
+//! Poll durations are measured with the time driver, in ticks of `embassy-time`: convert them
+//! with `embassy_time::Duration::from_ticks`. They are wall-clock durations, so they include the
+//! time the poll was preempted by interrupts, or by the tasks of a higher priority interrupt
+//! executor.
+//!
+//! Tasks are named after their `#[embassy_executor::task]` function, unless named with
+//! [`Metadata::set_name`](crate::Metadata::set_name).
+//!
+//! ```rust,ignore
+//! use embassy_executor::raw::trace::stats;
+//! use embassy_time::Duration;
+//!
+//! stats::for_each(|task| {
+//!     defmt::info!(
+//!         "{}: {} polls, {} us",
+//!         task.name,
+//!         task.polls,
+//!         Duration::from_ticks(task.poll_ticks).as_micros()
+//!     );
+//! });
+//! ```
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+use super::TASK_TRACKER;
+use crate::raw::TaskRef;
+
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    polls: u32,
+    poll_ticks: u64,
+    poll_start: u64,
+}
+
+/// Counters of a task, stored in its header.
+pub(crate) struct TaskCounters {
+    counters: Mutex<Cell<Counters>>,
+}
+
+impl TaskCounters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            counters: Mutex::new(Cell::new(Counters {
+                polls: 0,
+                poll_ticks: 0,
+                poll_start: 0,
+            })),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Counters)) {
+        critical_section::with(|cs| {
+            let cell = self.counters.borrow(cs);
+            let mut counters = cell.get();
+            f(&mut counters);
+            cell.set(counters);
+        })
+    }
+}
+
+pub(crate) fn task_new(task: &TaskRef) {
+    task.header().stats.update(|c| *c = Counters::default());
+}
+
+pub(crate) fn task_exec_begin(task: &TaskRef) {
+    let now = embassy_time_driver::now();
+    task.header().stats.update(|c| c.poll_start = now);
+}
+
+pub(crate) fn task_exec_end(task: &TaskRef) {
+    let now = embassy_time_driver::now();
+    task.header().stats.update(|c| {
+        c.polls = c.polls.wrapping_add(1);
+        c.poll_ticks += now.saturating_sub(c.poll_start);
+    });
+}
+
+/// Poll statistics of a task.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TaskStats {
+    /// ID of the task, as passed to the tracing callbacks.
+    pub id: u32,
+    /// Name of the task, `"unnamed task"` if it has none.
+    pub name: &'static str,
+    /// Number of times the task was polled since it was spawned.
+    pub polls: u32,
+    /// Total time spent polling the task since it was spawned, in time driver ticks.
+    pub poll_ticks: u64,
+}
+
+/// Call `f` with the statistics of each task spawned so far.
+///
+/// Tasks that ended are reported with the statistics of their last run.
+pub fn for_each(mut f: impl FnMut(TaskStats)) {
+    TASK_TRACKER.for_each(|task| {
+        let counters = critical_section::with(|cs| task.header().stats.counters.borrow(cs).get());
+        let name = task.metadata().trace_name().unwrap_or("unnamed task");
+        f(TaskStats {
+            id: task.id(),
+            // Names given to rtos-trace are NUL-terminated.
+            name: name.trim_end_matches('\0'),
+            polls: counters.polls,
+            poll_ticks: counters.poll_ticks,
+        })
+    });
+}
+
+/// Reset the statistics of all tasks, to measure from now on.
+pub fn reset() {
+    TASK_TRACKER.for_each(|task| {
+        task.header().stats.update(|c| {
+            c.polls = 0;
+            c.poll_ticks = 0;
+        })
+    });
+}
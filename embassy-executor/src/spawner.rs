@@ -63,7 +63,19 @@ pub enum SpawnError {
     /// By default, a task marked with `#[embassy_executor::task]` can only have one instance
     /// running at a time. You may allow multiple instances to run in parallel with
     /// `#[embassy_executor::task(pool_size = 4)]`, at the cost of higher RAM usage.
-    Busy,
+    Busy {
+        /// Name of the task whose pool is full, for tasks marked with `#[embassy_executor::task]`.
+        task: Option<&'static str>,
+    },
+}
+
+impl SpawnError {
+    #[doc(hidden)]
+    pub fn __with_task(self, name: &'static str) -> Self {
+        match self {
+            SpawnError::Busy { .. } => SpawnError::Busy { task: Some(name) },
+        }
+    }
 }
 
 impl core::fmt::Debug for SpawnError {
@@ -75,7 +87,12 @@ impl core::fmt::Debug for SpawnError {
 impl core::fmt::Display for SpawnError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            SpawnError::Busy => write!(
+            SpawnError::Busy { task: Some(task) } => write!(
+                f,
+                "Busy - Too many instances of task `{}` are already running. Check the `pool_size` attribute of the task.",
+                task
+            ),
+            SpawnError::Busy { task: None } => write!(
                 f,
                 "Busy - Too many instances of this task are already running. Check the `pool_size` attribute of the task."
             ),
@@ -87,7 +104,12 @@ impl core::fmt::Display for SpawnError {
 impl defmt::Format for SpawnError {
     fn format(&self, f: defmt::Formatter) {
         match self {
-            SpawnError::Busy => defmt::write!(
+            SpawnError::Busy { task: Some(task) } => defmt::write!(
+                f,
+                "Busy - Too many instances of task `{}` are already running. Check the `pool_size` attribute of the task.",
+                task
+            ),
+            SpawnError::Busy { task: None } => defmt::write!(
                 f,
                 "Busy - Too many instances of this task are already running. Check the `pool_size` attribute of the task."
             ),
@@ -7,7 +7,7 @@ use std::sync::{Arc, Mutex};
 use std::task::Poll;
 
 use embassy_executor::raw::Executor;
-use embassy_executor::{Spawner, task};
+use embassy_executor::{SpawnError, Spawner, task};
 
 #[unsafe(export_name = "__pender")]
 fn __pender(context: *mut ()) {
@@ -326,6 +326,23 @@ fn recursive_task() {
     }
 }
 
+#[test]
+fn spawn_busy_names_task() {
+    #[task]
+    async fn task1() {}
+
+    let (executor, _) = setup();
+    executor.spawner().spawn(task1().unwrap());
+    match task1() {
+        Err(SpawnError::Busy { task }) => assert_eq!(task, Some("task1")),
+        Ok(_) => panic!("spawned twice in a pool of one"),
+    }
+    assert_eq!(
+        format!("{}", task1().err().unwrap()),
+        "Busy - Too many instances of task `task1` are already running. Check the `pool_size` attribute of the task."
+    );
+}
+
 #[cfg(feature = "metadata-name")]
 #[test]
 fn task_metadata() {
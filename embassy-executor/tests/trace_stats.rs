@@ -0,0 +1,81 @@
+#![cfg(feature = "trace-stats")]
+
+use std::boxed::Box;
+use std::future::poll_fn;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Poll, Waker};
+
+use embassy_executor::raw::Executor;
+use embassy_executor::raw::trace::stats::{self, TaskStats};
+use embassy_executor::task;
+
+static NOW: AtomicU64 = AtomicU64::new(0);
+
+struct TestDriver;
+
+impl embassy_time_driver::Driver for TestDriver {
+    fn now(&self) -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+
+    fn schedule_wake(&self, _at: u64, _waker: &Waker) {}
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: TestDriver = TestDriver);
+
+#[unsafe(export_name = "__pender")]
+fn __pender(_context: *mut ()) {}
+
+#[task]
+async fn busy(polls: u32) {
+    let mut n = 0;
+    poll_fn(|cx| {
+        // Each poll takes 10 ticks.
+        NOW.fetch_add(10, Ordering::Relaxed);
+        n += 1;
+        if n == polls {
+            Poll::Ready(())
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+fn stats_of(name: &str) -> TaskStats {
+    let mut found = None;
+    stats::for_each(|task| {
+        if task.name == name {
+            found = Some(task);
+        }
+    });
+    found.unwrap()
+}
+
+#[test]
+fn task_stats() {
+    let executor: &'static Executor = Box::leak(Box::new(Executor::new(core::ptr::null_mut())));
+    executor.spawner().spawn(busy(3).unwrap());
+
+    unsafe { executor.poll() };
+    let task = stats_of("busy");
+    assert_eq!((task.polls, task.poll_ticks), (1, 10));
+
+    unsafe { executor.poll() };
+    unsafe { executor.poll() };
+    let task = stats_of("busy");
+    assert_eq!((task.polls, task.poll_ticks), (3, 30));
+
+    stats::reset();
+    let task = stats_of("busy");
+    assert_eq!((task.polls, task.poll_ticks), (0, 0));
+
+    // Spawning the task again starts over, and doesn't track it twice.
+    executor.spawner().spawn(busy(1).unwrap());
+    unsafe { executor.poll() };
+    let mut count = 0;
+    stats::for_each(|task| count += (task.name == "busy") as usize);
+    assert_eq!(count, 1);
+    assert_eq!(stats_of("busy").polls, 1);
+}
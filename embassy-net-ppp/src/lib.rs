@@ -66,6 +66,12 @@ impl<'d> Runner<'d> {
     ///
     /// After this function returns or is canceled, you can call it again to establish
     /// a new PPP connection.
+    ///
+    /// `on_ipv4_up` is called each time IPCP is opened, with our address, the peer's address and
+    /// the DNS servers negotiated with the peer. DNS servers are requested even if the peer doesn't
+    /// offer them. It is called again after a renegotiation, e.g. after an LCP restart, which may
+    /// change the address, so the network stack configuration should be updated each time. The
+    /// link state is down while IPCP is not open.
     pub async fn run<RW: BufRead + Write>(
         &mut self,
         mut rw: RW,
@@ -83,7 +89,7 @@ impl<'d> Runner<'d> {
         let mut tx_buf = [0; 2048];
 
         let mut needs_poll = true;
-        let mut was_up = false;
+        let mut ipv4_up = false;
 
         loop {
             let rx_fut = async {
@@ -118,19 +124,21 @@ impl<'d> Runner<'d> {
                     }
 
                     let status = ppp.status();
-                    match status.phase {
-                        ppproto::Phase::Dead => {
+                    match (status.phase, status.ipv4) {
+                        (ppproto::Phase::Dead, _) => {
                             return Err(RunError::Terminated);
                         }
-                        ppproto::Phase::Open => {
-                            if !was_up {
-                                on_ipv4_up(status.ipv4.unwrap());
+                        // IPCP leaves the opened state while renegotiating, without leaving the
+                        // open phase.
+                        (ppproto::Phase::Open, Some(ipv4)) => {
+                            if !ipv4_up {
+                                on_ipv4_up(ipv4);
                             }
-                            was_up = true;
+                            ipv4_up = true;
                             state_chan.set_link_state(LinkState::Up);
                         }
                         _ => {
-                            was_up = false;
+                            ipv4_up = false;
                             state_chan.set_link_state(LinkState::Down);
                         }
                     }
@@ -0,0 +1,676 @@
+//! DHCPv4 server, handing out addresses to the clients of the device, e.g. when it runs an access
+//! point for provisioning.
+//!
+//! The [`DhcpServer`] answers on the stack's own IPv4 configuration, which must be static. It
+//! hands out addresses from a pool starting at [`Config::pool_start`], one address per slot of
+//! the caller-provided lease table, and handles `DISCOVER`, `REQUEST`, `RELEASE` and `DECLINE`.
+//! Leases expire after [`Config::lease_time`], and an offer that isn't requested within a minute
+//! is freed.
+//!
+//! Replies are broadcast, except to clients renewing a lease, since clients without an address
+//! can't answer ARP requests.
+//!
+//! ```ignore
+//! let mut config = dhcp_server::Config::new(Ipv4Address::new(192, 168, 4, 100));
+//! config.router = Some(Ipv4Address::new(192, 168, 4, 1));
+//!
+//! let mut leases = [Lease::EMPTY; 8];
+//! let mut rx_meta = [PacketMetadata::EMPTY; 4];
+//! let mut rx_buffer = [0; 1024];
+//! let mut tx_meta = [PacketMetadata::EMPTY; 4];
+//! let mut tx_buffer = [0; 1024];
+//! let mut buf = [0; 576];
+//! let mut server = DhcpServer::new(
+//!     stack,
+//!     config,
+//!     &mut leases,
+//!     &mut rx_meta,
+//!     &mut rx_buffer,
+//!     &mut tx_meta,
+//!     &mut tx_buffer,
+//!     &mut buf,
+//! );
+//! server.run().await;
+//! ```
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use smoltcp::wire::{IpEndpoint, Ipv4Address, Ipv4Cidr};
+
+use crate::Stack;
+use crate::udp::{PacketMetadata, UdpSocket};
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+
+const OP_REPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Offset of the options, after the fixed fields and the magic cookie.
+const OPTIONS_OFFSET: usize = 240;
+/// Minimum length of a BOOTP message, RFC 1542 section 3.
+const MIN_MESSAGE_LEN: usize = 300;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPDECLINE: u8 = 4;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+const DHCPRELEASE: u8 = 7;
+
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS_SERVERS: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_END: u8 = 255;
+
+/// Time a client has to request an offered address.
+const OFFER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Configuration of a [`DhcpServer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config {
+    /// First address of the pool, which has as many addresses as the lease table has slots.
+    ///
+    /// The pool must be in the subnet of the stack's address. If it contains the stack's address,
+    /// that one is skipped.
+    pub pool_start: Ipv4Address,
+    /// Router given to the clients, e.g. the device's own address if it routes their traffic.
+    pub router: Option<Ipv4Address>,
+    /// DNS servers given to the clients.
+    pub dns_servers: Vec<Ipv4Address, 3>,
+    /// Duration of the leases.
+    pub lease_time: Duration,
+}
+
+impl Config {
+    /// Create a configuration with a pool starting at `pool_start`, one hour leases, and neither
+    /// router nor DNS servers.
+    pub fn new(pool_start: Ipv4Address) -> Self {
+        Self {
+            pool_start,
+            router: None,
+            dns_servers: Vec::new(),
+            lease_time: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LeaseState {
+    Free,
+    Offered,
+    Bound,
+    /// A client reported the address in use by another host.
+    Declined,
+}
+
+/// Slot of the lease table of a [`DhcpServer`].
+#[derive(Debug, Clone, Copy)]
+pub struct Lease {
+    state: LeaseState,
+    hardware_address: [u8; 6],
+    expires: Instant,
+}
+
+impl Lease {
+    /// A free slot.
+    pub const EMPTY: Self = Self {
+        state: LeaseState::Free,
+        hardware_address: [0; 6],
+        expires: Instant::MIN,
+    };
+
+    fn is_free(&self, now: Instant) -> bool {
+        self.state == LeaseState::Free || now >= self.expires
+    }
+
+    /// Whether the slot is, or was last, leased to `hardware_address`.
+    fn belongs_to(&self, hardware_address: &[u8; 6]) -> bool {
+        matches!(self.state, LeaseState::Offered | LeaseState::Bound) && self.hardware_address == *hardware_address
+    }
+}
+
+/// Fields of a client message.
+struct Request {
+    message_type: u8,
+    xid: [u8; 4],
+    flags: [u8; 2],
+    ciaddr: Ipv4Address,
+    giaddr: Ipv4Address,
+    chaddr: [u8; 16],
+    requested_ip: Option<Ipv4Address>,
+    server_id: Option<Ipv4Address>,
+}
+
+impl Request {
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < OPTIONS_OFFSET
+            || packet[0] != 1
+            || packet[1] != HTYPE_ETHERNET
+            || packet[2] != 6
+            || packet[236..240] != MAGIC_COOKIE
+        {
+            return None;
+        }
+        let addr = |pos: usize| Ipv4Address::new(packet[pos], packet[pos + 1], packet[pos + 2], packet[pos + 3]);
+
+        let mut request = Self {
+            message_type: 0,
+            xid: packet[4..8].try_into().ok()?,
+            flags: packet[10..12].try_into().ok()?,
+            ciaddr: addr(12),
+            giaddr: addr(24),
+            chaddr: packet[28..44].try_into().ok()?,
+            requested_ip: None,
+            server_id: None,
+        };
+
+        let mut pos = OPTIONS_OFFSET;
+        while let Some(&code) = packet.get(pos) {
+            match code {
+                OPT_PAD => {
+                    pos += 1;
+                    continue;
+                }
+                OPT_END => break,
+                _ => {}
+            }
+            let len = *packet.get(pos + 1)? as usize;
+            let data = packet.get(pos + 2..pos + 2 + len)?;
+            match (code, data) {
+                (OPT_MESSAGE_TYPE, &[t]) => request.message_type = t,
+                (OPT_REQUESTED_IP, &[a, b, c, d]) => request.requested_ip = Some(Ipv4Address::new(a, b, c, d)),
+                (OPT_SERVER_ID, &[a, b, c, d]) => request.server_id = Some(Ipv4Address::new(a, b, c, d)),
+                _ => {}
+            }
+            pos += 2 + len;
+        }
+        Some(request)
+    }
+
+    fn hardware_address(&self) -> [u8; 6] {
+        let mut hardware_address = [0; 6];
+        hardware_address.copy_from_slice(&self.chaddr[..6]);
+        hardware_address
+    }
+}
+
+/// Lease table of a [`DhcpServer`], updated by the client messages.
+struct LeaseTable<'a> {
+    pool_start: Ipv4Address,
+    lease_time: Duration,
+    leases: &'a mut [Lease],
+}
+
+impl LeaseTable<'_> {
+    /// Leases bound at `now`, as the address and the hardware address of the client.
+    fn bound(&self, now: Instant) -> impl Iterator<Item = (Ipv4Address, [u8; 6])> + '_ {
+        self.leases
+            .iter()
+            .enumerate()
+            .filter(move |(_, l)| l.state == LeaseState::Bound && now < l.expires)
+            .map(|(i, l)| (self.address(i), l.hardware_address))
+    }
+
+    fn address(&self, index: usize) -> Ipv4Address {
+        Ipv4Address::from(u32::from(self.pool_start).wrapping_add(index as u32))
+    }
+
+    fn index_of(&self, addr: Ipv4Address) -> Option<usize> {
+        let offset = u32::from(addr).wrapping_sub(u32::from(self.pool_start)) as usize;
+        (offset < self.leases.len()).then_some(offset)
+    }
+
+    /// Pick the slot to offer: the client's previous one, else the requested one, else a slot
+    /// never leased, else an expired one.
+    fn pick(&self, request: &Request, own: Ipv4Address, now: Instant) -> Option<usize> {
+        let hardware_address = request.hardware_address();
+        let usable = |i: usize| self.address(i) != own;
+
+        let previous = (0..self.leases.len()).find(|&i| usable(i) && self.leases[i].belongs_to(&hardware_address));
+        let requested = request
+            .requested_ip
+            .and_then(|a| self.index_of(a))
+            .filter(|&i| usable(i) && self.leases[i].is_free(now));
+        previous
+            .or(requested)
+            .or_else(|| (0..self.leases.len()).find(|&i| usable(i) && self.leases[i].state == LeaseState::Free))
+            .or_else(|| (0..self.leases.len()).find(|&i| usable(i) && self.leases[i].is_free(now)))
+    }
+
+    /// Update the leases for `request`, returning the type of the reply and the address for the
+    /// client, if any.
+    fn handle(&mut self, request: &Request, own: Ipv4Cidr, now: Instant) -> Option<(u8, Ipv4Address)> {
+        let hardware_address = request.hardware_address();
+        let for_us = request.server_id.map(|id| id == own.address());
+
+        match request.message_type {
+            DHCPDISCOVER => {
+                let Some(i) = self.pick(request, own.address(), now) else {
+                    warn!("DHCP server: no free address");
+                    return None;
+                };
+                // A lease still bound to the client is offered again as is.
+                let lease = &mut self.leases[i];
+                if !(lease.state == LeaseState::Bound && lease.belongs_to(&hardware_address) && now < lease.expires) {
+                    *lease = Lease {
+                        state: LeaseState::Offered,
+                        hardware_address,
+                        expires: now + OFFER_TIMEOUT,
+                    };
+                }
+                Some((DHCPOFFER, self.address(i)))
+            }
+            DHCPREQUEST => {
+                if for_us == Some(false) {
+                    // The client took the offer of another server.
+                    for lease in self.leases.iter_mut() {
+                        if lease.state == LeaseState::Offered && lease.hardware_address == hardware_address {
+                            *lease = Lease::EMPTY;
+                        }
+                    }
+                    return None;
+                }
+
+                let addr = request
+                    .requested_ip
+                    .or((request.ciaddr != Ipv4Address::UNSPECIFIED).then_some(request.ciaddr))?;
+                let index = self.index_of(addr).filter(|&i| {
+                    let lease = &self.leases[i];
+                    addr != own.address() && (lease.belongs_to(&hardware_address) || lease.is_free(now))
+                });
+                let Some(i) = index else {
+                    // Only refuse addresses of our network, another server may own the others.
+                    return (for_us == Some(true) || own.contains_addr(&addr))
+                        .then_some((DHCPNAK, Ipv4Address::UNSPECIFIED));
+                };
+
+                for (j, lease) in self.leases.iter_mut().enumerate() {
+                    if j != i && lease.belongs_to(&hardware_address) {
+                        *lease = Lease::EMPTY;
+                    }
+                }
+                self.leases[i] = Lease {
+                    state: LeaseState::Bound,
+                    hardware_address,
+                    expires: now + self.lease_time,
+                };
+                debug!("DHCP server: leased {}", addr);
+                Some((DHCPACK, addr))
+            }
+            DHCPDECLINE => {
+                if for_us == Some(true)
+                    && let Some(i) = request.requested_ip.and_then(|a| self.index_of(a))
+                    && self.leases[i].belongs_to(&hardware_address)
+                {
+                    warn!("DHCP server: {} is in use by another host", self.address(i));
+                    self.leases[i] = Lease {
+                        state: LeaseState::Declined,
+                        hardware_address: [0; 6],
+                        expires: now + self.lease_time,
+                    };
+                }
+                None
+            }
+            DHCPRELEASE => {
+                if let Some(i) = self.index_of(request.ciaddr)
+                    && self.leases[i].belongs_to(&hardware_address)
+                {
+                    debug!("DHCP server: released {}", request.ciaddr);
+                    self.leases[i] = Lease::EMPTY;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// DHCPv4 server, see the [module documentation](self).
+pub struct DhcpServer<'a> {
+    stack: Stack<'a>,
+    socket: UdpSocket<'a>,
+    config: Config,
+    leases: LeaseTable<'a>,
+    buf: &'a mut [u8],
+}
+
+impl<'a> DhcpServer<'a> {
+    /// Create a new DHCP server using the provided stack and buffers.
+    ///
+    /// `leases` is the lease table, whose length is the size of the address pool. The socket
+    /// buffers are those of a [`UdpSocket`]. `buf` holds a received message, then the reply:
+    /// clients may send messages up to 576 bytes long, longer ones are dropped.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        stack: Stack<'a>,
+        config: Config,
+        leases: &'a mut [Lease],
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+        buf: &'a mut [u8],
+    ) -> Self {
+        let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+        unwrap!(socket.bind(SERVER_PORT));
+
+        Self {
+            stack,
+            socket,
+            leases: LeaseTable {
+                pool_start: config.pool_start,
+                lease_time: config.lease_time,
+                leases,
+            },
+            config,
+            buf,
+        }
+    }
+
+    /// Get the current leases, as the address and the hardware address of the client.
+    pub fn leases(&self) -> impl Iterator<Item = (Ipv4Address, [u8; 6])> + '_ {
+        self.leases.bound(Instant::now())
+    }
+
+    /// Run the server.
+    ///
+    /// Messages received while the stack has no IPv4 configuration are dropped.
+    pub async fn run(&mut self) -> ! {
+        loop {
+            let Ok((n, _)) = self.socket.recv_from(self.buf).await else {
+                continue;
+            };
+            let Some(own) = self.stack.config_v4().map(|c| c.address) else {
+                continue;
+            };
+            let Some(request) = Request::parse(&self.buf[..n]) else {
+                continue;
+            };
+
+            let Some((message_type, yiaddr)) = self.leases.handle(&request, own, Instant::now()) else {
+                continue;
+            };
+            let Some(len) = self.write_reply(&request, message_type, yiaddr, own) else {
+                warn!("DHCP server: buffer too small for the reply");
+                continue;
+            };
+
+            let to = if request.giaddr != Ipv4Address::UNSPECIFIED {
+                IpEndpoint::new(request.giaddr.into(), SERVER_PORT)
+            } else if message_type != DHCPNAK && request.ciaddr != Ipv4Address::UNSPECIFIED {
+                IpEndpoint::new(request.ciaddr.into(), CLIENT_PORT)
+            } else {
+                IpEndpoint::new(Ipv4Address::BROADCAST.into(), CLIENT_PORT)
+            };
+            if self.socket.send_to(&self.buf[..len], to).await.is_err() {
+                warn!("DHCP server: failed to send the reply");
+            }
+        }
+    }
+
+    /// Write the reply to `request` to the buffer, returning its length.
+    fn write_reply(
+        &mut self,
+        request: &Request,
+        message_type: u8,
+        yiaddr: Ipv4Address,
+        own: Ipv4Cidr,
+    ) -> Option<usize> {
+        let buf = &mut *self.buf;
+        if buf.len() < MIN_MESSAGE_LEN {
+            return None;
+        }
+        buf[..OPTIONS_OFFSET].fill(0);
+        buf[0] = OP_REPLY;
+        buf[1] = HTYPE_ETHERNET;
+        buf[2] = 6;
+        buf[4..8].copy_from_slice(&request.xid);
+        buf[10..12].copy_from_slice(&request.flags);
+        if message_type == DHCPACK {
+            buf[12..16].copy_from_slice(&request.ciaddr.octets());
+        }
+        buf[16..20].copy_from_slice(&yiaddr.octets());
+        buf[24..28].copy_from_slice(&request.giaddr.octets());
+        buf[28..44].copy_from_slice(&request.chaddr);
+        buf[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut options = Options {
+            buf,
+            len: OPTIONS_OFFSET,
+        };
+        options.push(OPT_MESSAGE_TYPE, &[message_type])?;
+        options.push(OPT_SERVER_ID, &own.address().octets())?;
+        if message_type != DHCPNAK {
+            let lease_time = self.config.lease_time.as_secs().min(u32::MAX as u64) as u32;
+            options.push(OPT_LEASE_TIME, &lease_time.to_be_bytes())?;
+            options.push(OPT_SUBNET_MASK, &own.netmask().octets())?;
+            if let Some(router) = self.config.router {
+                options.push(OPT_ROUTER, &router.octets())?;
+            }
+            if !self.config.dns_servers.is_empty() {
+                let mut dns = [0; 12];
+                for (chunk, server) in dns.chunks_mut(4).zip(&self.config.dns_servers) {
+                    chunk.copy_from_slice(&server.octets());
+                }
+                options.push(OPT_DNS_SERVERS, &dns[..4 * self.config.dns_servers.len()])?;
+            }
+        }
+        options.push_end()
+    }
+}
+
+struct Options<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl Options<'_> {
+    fn push(&mut self, code: u8, data: &[u8]) -> Option<()> {
+        let end = self.len + 2 + data.len();
+        let option = self.buf.get_mut(self.len..end)?;
+        option[0] = code;
+        option[1] = data.len() as u8;
+        option[2..].copy_from_slice(data);
+        self.len = end;
+        Some(())
+    }
+
+    /// Write the end option and pad to the minimum message length, returning the length.
+    fn push_end(self) -> Option<usize> {
+        *self.buf.get_mut(self.len)? = OPT_END;
+        let len = (self.len + 1).max(MIN_MESSAGE_LEN);
+        self.buf[self.len + 1..len].fill(OPT_PAD);
+        Some(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+    const OTHER: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+    const OWN: Ipv4Address = Ipv4Address::new(192, 168, 4, 1);
+    const FIRST: Ipv4Address = Ipv4Address::new(192, 168, 4, 100);
+    const SECOND: Ipv4Address = Ipv4Address::new(192, 168, 4, 101);
+
+    fn own() -> Ipv4Cidr {
+        Ipv4Cidr::new(OWN, 24)
+    }
+
+    /// A client message of `message_type` from `hardware_address`, with `options` after the
+    /// message type option.
+    fn message(message_type: u8, hardware_address: [u8; 6], ciaddr: Ipv4Address, options: &[u8]) -> [u8; 300] {
+        let mut packet = [0; 300];
+        packet[0] = 1;
+        packet[1] = HTYPE_ETHERNET;
+        packet[2] = 6;
+        packet[4..8].copy_from_slice(&[1, 2, 3, 4]);
+        packet[12..16].copy_from_slice(&ciaddr.octets());
+        packet[28..34].copy_from_slice(&hardware_address);
+        packet[236..240].copy_from_slice(&MAGIC_COOKIE);
+        packet[240..243].copy_from_slice(&[OPT_MESSAGE_TYPE, 1, message_type]);
+        packet[243..243 + options.len()].copy_from_slice(options);
+        packet[243 + options.len()] = OPT_END;
+        packet
+    }
+
+    fn addr_option(code: u8, addr: Ipv4Address) -> [u8; 6] {
+        let [a, b, c, d] = addr.octets();
+        [code, 4, a, b, c, d]
+    }
+
+    fn request(packet: &[u8]) -> Request {
+        Request::parse(packet).unwrap()
+    }
+
+    fn table(leases: &mut [Lease]) -> LeaseTable<'_> {
+        LeaseTable {
+            pool_start: FIRST,
+            lease_time: Duration::from_secs(3600),
+            leases,
+        }
+    }
+
+    /// A REQUEST for `addr` from `hardware_address`, selecting this server.
+    fn request_for(hardware_address: [u8; 6], addr: Ipv4Address) -> Request {
+        let mut options = [0; 12];
+        options[..6].copy_from_slice(&addr_option(OPT_REQUESTED_IP, addr));
+        options[6..].copy_from_slice(&addr_option(OPT_SERVER_ID, OWN));
+        request(&message(
+            DHCPREQUEST,
+            hardware_address,
+            Ipv4Address::UNSPECIFIED,
+            &options,
+        ))
+    }
+
+    #[test]
+    fn parse_request() {
+        let mut options = [0; 14];
+        // Padding is skipped, unknown options too.
+        options[0] = OPT_PAD;
+        options[1..4].copy_from_slice(&[12, 1, b'x']);
+        options[4..10].copy_from_slice(&addr_option(OPT_REQUESTED_IP, SECOND));
+        options[10..14].copy_from_slice(&[OPT_SERVER_ID, 2, 0, 0]);
+        let request = request(&message(DHCPDISCOVER, CLIENT, Ipv4Address::UNSPECIFIED, &options));
+        assert_eq!(request.message_type, DHCPDISCOVER);
+        assert_eq!(request.xid, [1, 2, 3, 4]);
+        assert_eq!(request.hardware_address(), CLIENT);
+        assert_eq!(request.requested_ip, Some(SECOND));
+        // An option of the wrong length is ignored.
+        assert_eq!(request.server_id, None);
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let packet = message(DHCPDISCOVER, CLIENT, Ipv4Address::UNSPECIFIED, &[]);
+        assert!(Request::parse(&packet[..OPTIONS_OFFSET - 1]).is_none());
+
+        // An option running past the end of the message.
+        let mut packet = message(DHCPDISCOVER, CLIENT, Ipv4Address::UNSPECIFIED, &[]);
+        packet[243..246].copy_from_slice(&[OPT_REQUESTED_IP, 4, 192]);
+        assert!(Request::parse(&packet[..246]).is_none());
+
+        // The options may end without an end option.
+        let request = request(&packet[..243]);
+        assert_eq!(request.message_type, DHCPDISCOVER);
+
+        let mut packet = message(DHCPDISCOVER, CLIENT, Ipv4Address::UNSPECIFIED, &[]);
+        packet[236] = 0;
+        assert!(Request::parse(&packet).is_none());
+    }
+
+    #[test]
+    fn discover_request_release() {
+        let mut leases = [Lease::EMPTY; 2];
+        let mut table = table(&mut leases);
+        let now = Instant::from_secs(10);
+
+        let discover = request(&message(DHCPDISCOVER, CLIENT, Ipv4Address::UNSPECIFIED, &[]));
+        assert_eq!(table.handle(&discover, own(), now), Some((DHCPOFFER, FIRST)));
+        assert_eq!(table.bound(now).count(), 0);
+        // The offer is reserved for the client.
+        let discover_other = request(&message(DHCPDISCOVER, OTHER, Ipv4Address::UNSPECIFIED, &[]));
+        assert_eq!(table.handle(&discover_other, own(), now), Some((DHCPOFFER, SECOND)));
+
+        assert_eq!(
+            table.handle(&request_for(CLIENT, FIRST), own(), now),
+            Some((DHCPACK, FIRST))
+        );
+        assert!(table.bound(now).eq([(FIRST, CLIENT)]));
+
+        // A DISCOVER from a bound client offers its lease again.
+        assert_eq!(table.handle(&discover, own(), now), Some((DHCPOFFER, FIRST)));
+        assert!(table.bound(now).eq([(FIRST, CLIENT)]));
+
+        // Only the client holding the lease can release it.
+        let release_other = request(&message(DHCPRELEASE, OTHER, FIRST, &addr_option(OPT_SERVER_ID, OWN)));
+        assert_eq!(table.handle(&release_other, own(), now), None);
+        assert!(table.bound(now).eq([(FIRST, CLIENT)]));
+
+        let release = request(&message(DHCPRELEASE, CLIENT, FIRST, &addr_option(OPT_SERVER_ID, OWN)));
+        assert_eq!(table.handle(&release, own(), now), None);
+        assert_eq!(table.bound(now).count(), 0);
+    }
+
+    #[test]
+    fn request_expired_and_taken() {
+        let mut leases = [Lease::EMPTY; 2];
+        let mut table = table(&mut leases);
+        let now = Instant::from_secs(10);
+
+        assert_eq!(
+            table.handle(&request_for(CLIENT, FIRST), own(), now),
+            Some((DHCPACK, FIRST))
+        );
+        // The address is taken, until the lease expires.
+        assert_eq!(
+            table.handle(&request_for(OTHER, FIRST), own(), now),
+            Some((DHCPNAK, Ipv4Address::UNSPECIFIED))
+        );
+        let later = now + Duration::from_secs(3600);
+        assert_eq!(table.bound(later).count(), 0);
+        assert_eq!(
+            table.handle(&request_for(OTHER, FIRST), own(), later),
+            Some((DHCPACK, FIRST))
+        );
+        assert!(table.bound(later).eq([(FIRST, OTHER)]));
+    }
+
+    #[test]
+    fn decline() {
+        let mut leases = [Lease::EMPTY; 2];
+        let mut table = table(&mut leases);
+        let now = Instant::from_secs(10);
+
+        let discover = request(&message(DHCPDISCOVER, CLIENT, Ipv4Address::UNSPECIFIED, &[]));
+        assert_eq!(table.handle(&discover, own(), now), Some((DHCPOFFER, FIRST)));
+        assert_eq!(
+            table.handle(&request_for(CLIENT, FIRST), own(), now),
+            Some((DHCPACK, FIRST))
+        );
+
+        let mut options = [0; 12];
+        options[..6].copy_from_slice(&addr_option(OPT_REQUESTED_IP, FIRST));
+        options[6..].copy_from_slice(&addr_option(OPT_SERVER_ID, OWN));
+        let decline = request(&message(DHCPDECLINE, CLIENT, Ipv4Address::UNSPECIFIED, &options));
+        assert_eq!(table.handle(&decline, own(), now), None);
+        assert_eq!(table.bound(now).count(), 0);
+
+        // The declined address isn't offered again until the lease time has passed.
+        assert_eq!(table.handle(&discover, own(), now), Some((DHCPOFFER, SECOND)));
+        let later = now + Duration::from_secs(3600);
+        let discover_other = request(&message(DHCPDISCOVER, OTHER, Ipv4Address::UNSPECIFIED, &[]));
+        assert_eq!(table.handle(&discover_other, own(), later), Some((DHCPOFFER, FIRST)));
+    }
+}
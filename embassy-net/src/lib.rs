@@ -13,13 +13,19 @@ compile_error!("You must enable at least one of the following features: proto-ip
 // This mod MUST go first, so that the others see its macros.
 pub(crate) mod fmt;
 
+#[cfg(feature = "dhcpv4-server")]
+pub mod dhcp_server;
 #[cfg(feature = "dns")]
 pub mod dns;
 mod driver_util;
 #[cfg(feature = "icmp")]
 pub mod icmp;
+#[cfg(feature = "mdns-responder")]
+pub mod mdns;
 #[cfg(feature = "raw")]
 pub mod raw;
+#[cfg(feature = "sntp")]
+pub mod sntp;
 #[cfg(feature = "tcp")]
 pub mod tcp;
 mod time;
@@ -34,7 +40,7 @@ use core::task::{Context, Poll};
 
 pub use embassy_net_driver as driver;
 use embassy_net_driver::{Driver, LinkState};
-use embassy_sync::waitqueue::WakerRegistration;
+use embassy_sync::waitqueue::{MultiWakerRegistration, WakerRegistration};
 use embassy_time::{Instant, Timer};
 use heapless::Vec;
 #[cfg(feature = "dns")]
@@ -271,8 +277,8 @@ pub(crate) struct Inner {
     pub(crate) iface: Interface,
     /// Waker used for triggering polls.
     pub(crate) waker: WakerRegistration,
-    /// Waker used for waiting for link up or config up.
-    state_waker: WakerRegistration,
+    /// Wakers used for waiting for link up or config up.
+    pub(crate) state_waker: MultiWakerRegistration<4>,
     hardware_address: HardwareAddress,
     next_local_port: u16,
     link_up: bool,
@@ -337,7 +343,7 @@ pub fn new<'d, D: Driver, const SOCK: usize>(
         sockets,
         iface,
         waker: WakerRegistration::new(),
-        state_waker: WakerRegistration::new(),
+        state_waker: MultiWakerRegistration::new(),
         next_local_port,
         hardware_address,
         link_up: false,
@@ -0,0 +1,763 @@
+//! mDNS responder, making the device discoverable on the local link as `<hostname>.local`.
+//!
+//! The [`Responder`] answers `A` and `AAAA` queries for its hostname, and advertises a static set
+//! of DNS-SD services, such as `_http._tcp`, with `PTR`, `SRV` and `TXT` records, following
+//! [RFC 6762] and [RFC 6763]. Each time the link comes up or the IP configuration changes, it
+//! probes for its names and announces its records again. Records are multicast at most once per
+//! second.
+//!
+//! This is only a responder: to resolve `.local` names, use [`Stack::dns_query`] with the `mdns`
+//! feature.
+//!
+//! ```ignore
+//! static SERVICES: [Service; 1] = [Service {
+//!     instance: "Thermostat",
+//!     service: "_http._tcp",
+//!     port: 80,
+//!     txt: &["path=/"],
+//! }];
+//!
+//! let mut config = mdns::Config::new("thermostat");
+//! config.services = &SERVICES;
+//!
+//! let mut rx_meta = [PacketMetadata::EMPTY; 4];
+//! let mut rx_buffer = [0; 1024];
+//! let mut tx_meta = [PacketMetadata::EMPTY; 4];
+//! let mut tx_buffer = [0; 1024];
+//! let mut buf = [0; 1024];
+//! let mut responder = Responder::new(
+//!     stack,
+//!     config,
+//!     &mut rx_meta,
+//!     &mut rx_buffer,
+//!     &mut tx_meta,
+//!     &mut tx_buffer,
+//!     &mut buf,
+//! );
+//! responder.run().await;
+//! ```
+//!
+//! [RFC 6762]: https://www.rfc-editor.org/rfc/rfc6762
+//! [RFC 6763]: https://www.rfc-editor.org/rfc/rfc6763
+
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embassy_time::{Duration, Instant, Timer, with_deadline};
+#[cfg(feature = "proto-ipv4")]
+use smoltcp::wire::Ipv4Address;
+#[cfg(feature = "proto-ipv6")]
+use smoltcp::wire::Ipv6Address;
+use smoltcp::wire::{IpAddress, IpEndpoint};
+
+use crate::Stack;
+use crate::udp::{PacketMetadata, UdpSocket};
+
+/// Maximum number of services a [`Responder`] advertises.
+pub const MAX_SERVICES: usize = 8;
+
+const MDNS_PORT: u16 = 5353;
+#[cfg(feature = "proto-ipv4")]
+const MDNS_GROUP_V4: Ipv4Address = Ipv4Address::new(224, 0, 0, 251);
+#[cfg(feature = "proto-ipv6")]
+const MDNS_GROUP_V6: Ipv6Address = Ipv6Address::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+
+const LOCAL: &str = "local";
+const SERVICES_ENUMERATION: &str = "_services._dns-sd._udp";
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+/// Cache flush bit of unique records, in the class of a resource record.
+const CACHE_FLUSH: u16 = 0x8000;
+/// Unicast response bit, in the class of a question.
+const UNICAST_RESPONSE: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+/// TTL of the records containing a hostname or an address, RFC 6762 section 10.
+const HOST_TTL: u32 = 120;
+/// TTL of the other records.
+const SERVICE_TTL: u32 = 4500;
+/// Maximum TTL of the answers to legacy unicast queries, RFC 6762 section 6.7.
+const LEGACY_TTL: u32 = 10;
+
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+const PROBE_COUNT: usize = 3;
+const CONFLICT_DELAY: Duration = Duration::from_secs(1);
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+const ANNOUNCE_COUNT: usize = 2;
+const MULTICAST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A DNS-SD service advertised by a [`Responder`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Service<'a> {
+    /// Instance name, shown to users when browsing, e.g. `Living room thermostat`.
+    ///
+    /// A single label: it may contain dots and spaces, but no more than 63 bytes.
+    pub instance: &'a str,
+    /// Service type and protocol, e.g. `_http._tcp`.
+    pub service: &'a str,
+    /// Port the service is listening on.
+    pub port: u16,
+    /// `key=value` strings of the TXT record, up to 255 bytes each.
+    pub txt: &'a [&'a str],
+}
+
+/// Configuration of a [`Responder`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config<'a> {
+    /// Hostname, without the `.local` suffix.
+    ///
+    /// It must be unique on the link: the responder doesn't pick another name when probing finds
+    /// it in use, it logs a warning and probes again. Include e.g. the end of the MAC address.
+    pub hostname: &'a str,
+    /// Services to advertise, at most [`MAX_SERVICES`].
+    pub services: &'a [Service<'a>],
+}
+
+impl<'a> Config<'a> {
+    /// Create a configuration answering for `hostname`, without services.
+    pub const fn new(hostname: &'a str) -> Self {
+        Self {
+            hostname,
+            services: &[],
+        }
+    }
+}
+
+/// Addresses the host records point to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Addresses {
+    #[cfg(feature = "proto-ipv4")]
+    v4: Option<Ipv4Address>,
+    #[cfg(feature = "proto-ipv6")]
+    v6: Option<Ipv6Address>,
+}
+
+/// Records to write, from the questions of a query.
+#[derive(Clone, Copy, Default)]
+struct Records {
+    /// `A` and `AAAA` records of the hostname.
+    host: bool,
+    /// `PTR` records from the service enumeration name to each service type.
+    enumeration: bool,
+    /// `PTR` records from the service type to the instance, one bit per service.
+    ptr: u32,
+    /// `SRV` and `TXT` records of the instance, one bit per service.
+    instance: u32,
+}
+
+impl Records {
+    const fn all(services: usize) -> Self {
+        let mask = (1 << services) - 1;
+        Self {
+            host: true,
+            enumeration: true,
+            ptr: mask,
+            instance: mask,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.host && !self.enumeration && self.ptr == 0 && self.instance == 0
+    }
+}
+
+/// mDNS responder, see the [module documentation](self).
+pub struct Responder<'a> {
+    stack: Stack<'a>,
+    socket: UdpSocket<'a>,
+    config: Config<'a>,
+    buf: &'a mut [u8],
+    /// Last time each record set was multicast: host, service enumeration, then each service.
+    last_multicast: [Instant; 2 + MAX_SERVICES],
+}
+
+impl<'a> Responder<'a> {
+    /// Create a new responder using the provided stack and buffers.
+    ///
+    /// The socket buffers are those of a [`UdpSocket`]. `buf` holds a received query and the
+    /// response being built, in two halves: 1024 bytes are enough for a few services.
+    ///
+    /// Panics if more than [`MAX_SERVICES`] services are configured.
+    pub fn new(
+        stack: Stack<'a>,
+        config: Config<'a>,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+        buf: &'a mut [u8],
+    ) -> Self {
+        assert!(
+            config.services.len() <= MAX_SERVICES,
+            "too many mDNS services, the maximum is MAX_SERVICES"
+        );
+
+        let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+        unwrap!(socket.bind(MDNS_PORT));
+        // RFC 6762 section 11: mDNS packets are sent with an IP TTL of 255.
+        socket.set_hop_limit(Some(255));
+
+        Self {
+            stack,
+            socket,
+            config,
+            buf,
+            last_multicast: [Instant::MIN; 2 + MAX_SERVICES],
+        }
+    }
+
+    /// Run the responder.
+    ///
+    /// Waits for the link and the IP configuration, then probes, announces and answers queries
+    /// until the link goes down or the addresses change, and starts over.
+    pub async fn run(&mut self) -> ! {
+        loop {
+            self.stack
+                .wait(|| self.stack.is_link_up() && self.stack.is_config_up())
+                .await;
+            self.join_groups();
+
+            let addrs = self.addresses();
+            self.probe(addrs).await;
+            self.announce(addrs).await;
+            self.serve(addrs).await;
+            debug!("mDNS: link or IP configuration changed");
+        }
+    }
+
+    fn addresses(&self) -> Addresses {
+        Addresses {
+            #[cfg(feature = "proto-ipv4")]
+            v4: self.stack.config_v4().map(|c| c.address.address()),
+            #[cfg(feature = "proto-ipv6")]
+            v6: self.stack.config_v6().map(|c| c.address.address()),
+        }
+    }
+
+    fn join_groups(&self) {
+        #[cfg(feature = "proto-ipv4")]
+        if !self.stack.has_multicast_group(MDNS_GROUP_V4) && self.stack.join_multicast_group(MDNS_GROUP_V4).is_err() {
+            warn!("mDNS: failed to join the IPv4 multicast group");
+        }
+        #[cfg(feature = "proto-ipv6")]
+        if !self.stack.has_multicast_group(MDNS_GROUP_V6) && self.stack.join_multicast_group(MDNS_GROUP_V6).is_err() {
+            warn!("mDNS: failed to join the IPv6 multicast group");
+        }
+    }
+
+    /// Probe for the hostname and service instance names, RFC 6762 section 8.1, until no other
+    /// host claims them.
+    async fn probe(&mut self, addrs: Addresses) {
+        let half = self.buf.len() / 2;
+        let (rx, tx) = self.buf.split_at_mut(half);
+
+        'probing: loop {
+            for _ in 0..PROBE_COUNT {
+                match write_probe(&self.config, addrs, tx) {
+                    Ok(len) => send_multicast(&self.socket, addrs, &tx[..len]).await,
+                    Err(_) => warn!("mDNS: buffer too small for the probe"),
+                }
+
+                let deadline = Instant::now() + PROBE_INTERVAL;
+                while let Ok(r) = with_deadline(deadline, self.socket.recv_from(rx)).await {
+                    if let Ok((n, _)) = r
+                        && conflicts(&self.config, &rx[..n])
+                    {
+                        warn!("mDNS: {}.local is already in use on the link", self.config.hostname);
+                        Timer::after(CONFLICT_DELAY).await;
+                        continue 'probing;
+                    }
+                }
+            }
+            return;
+        }
+    }
+
+    /// Multicast all records, RFC 6762 section 8.3.
+    async fn announce(&mut self, addrs: Addresses) {
+        let half = self.buf.len() / 2;
+        let tx = &mut self.buf[half..];
+
+        for i in 0..ANNOUNCE_COUNT {
+            if i > 0 {
+                Timer::after(ANNOUNCE_INTERVAL).await;
+            }
+            let records = Records::all(self.config.services.len());
+            match write_response(&self.config, addrs, records, None, tx) {
+                Ok(len) => send_multicast(&self.socket, addrs, &tx[..len]).await,
+                Err(_) => warn!("mDNS: buffer too small for the announcement"),
+            }
+        }
+        self.last_multicast = [Instant::now(); 2 + MAX_SERVICES];
+    }
+
+    /// Answer queries until the link goes down or the addresses change.
+    async fn serve(&mut self, addrs: Addresses) {
+        loop {
+            let changed = poll_fn(|cx| {
+                if self.socket.poll_recv_ready(cx).is_ready() {
+                    return Poll::Ready(false);
+                }
+                if !self.stack.is_link_up() || self.addresses() != addrs {
+                    return Poll::Ready(true);
+                }
+                self.stack.with_mut(|i| i.state_waker.register(cx.waker()));
+                Poll::Pending
+            })
+            .await;
+            if changed {
+                return;
+            }
+
+            let half = self.buf.len() / 2;
+            let (rx, tx) = self.buf.split_at_mut(half);
+            let Ok((n, meta)) = self.socket.recv_from(rx).await else {
+                continue;
+            };
+            let Some(query) = parse_query(&self.config, &rx[..n]) else {
+                continue;
+            };
+
+            let legacy = meta.endpoint.port != MDNS_PORT;
+            let mut records = query.records;
+            let to = if legacy || query.unicast {
+                meta.endpoint
+            } else {
+                // Drop the records multicast less than a second ago, RFC 6762 section 6.
+                let now = Instant::now();
+                let recent = |i: usize| now < self.last_multicast[i] + MULTICAST_INTERVAL;
+                records.host &= !recent(0);
+                records.enumeration &= !recent(1);
+                for i in 0..self.config.services.len() {
+                    if recent(2 + i) {
+                        records.ptr &= !(1 << i);
+                        records.instance &= !(1 << i);
+                    }
+                }
+                IpEndpoint::new(group_of(meta.endpoint.addr), MDNS_PORT)
+            };
+            if records.is_empty() {
+                continue;
+            }
+
+            let legacy = legacy.then(|| (&rx[..query.questions_end], query.question_count));
+            let len = match write_response(&self.config, addrs, records, legacy, tx) {
+                Ok(len) => len,
+                Err(_) => {
+                    warn!("mDNS: buffer too small for the response");
+                    continue;
+                }
+            };
+            if self.socket.send_to(&tx[..len], to).await.is_err() {
+                warn!("mDNS: failed to send the response");
+                continue;
+            }
+
+            if to.port == MDNS_PORT && to.addr.is_multicast() {
+                let now = Instant::now();
+                if records.host {
+                    self.last_multicast[0] = now;
+                }
+                if records.enumeration {
+                    self.last_multicast[1] = now;
+                }
+                for i in 0..self.config.services.len() {
+                    if (records.ptr | records.instance) & (1 << i) != 0 {
+                        self.last_multicast[2 + i] = now;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn group_of(addr: IpAddress) -> IpAddress {
+    match addr {
+        #[cfg(feature = "proto-ipv4")]
+        IpAddress::Ipv4(_) => MDNS_GROUP_V4.into(),
+        #[cfg(feature = "proto-ipv6")]
+        IpAddress::Ipv6(_) => MDNS_GROUP_V6.into(),
+    }
+}
+
+/// Send `packet` to the mDNS group of each IP version with an address.
+async fn send_multicast(socket: &UdpSocket<'_>, addrs: Addresses, packet: &[u8]) {
+    #[cfg(feature = "proto-ipv4")]
+    if addrs.v4.is_some() && socket.send_to(packet, (MDNS_GROUP_V4, MDNS_PORT)).await.is_err() {
+        warn!("mDNS: failed to send to the IPv4 multicast group");
+    }
+    #[cfg(feature = "proto-ipv6")]
+    if addrs.v6.is_some() && socket.send_to(packet, (MDNS_GROUP_V6, MDNS_PORT)).await.is_err() {
+        warn!("mDNS: failed to send to the IPv6 multicast group");
+    }
+}
+
+/// Questions of a query, matched against the configured names.
+struct Query {
+    records: Records,
+    /// Whether a question asked for a unicast response.
+    unicast: bool,
+    question_count: u16,
+    /// End of the question section.
+    questions_end: usize,
+}
+
+fn parse_query(config: &Config, packet: &[u8]) -> Option<Query> {
+    let flags = read_u16(packet, 2)?;
+    // Responses, and queries with a non zero opcode, are ignored, RFC 6762 section 18.
+    if flags & 0xF800 != 0 {
+        return None;
+    }
+    let question_count = read_u16(packet, 4)?;
+
+    let mut query = Query {
+        records: Records::default(),
+        unicast: false,
+        question_count,
+        questions_end: 0,
+    };
+    let mut pos = 12;
+    for _ in 0..question_count {
+        let name = pos;
+        pos = skip_name(packet, pos)?;
+        let qtype = read_u16(packet, pos)?;
+        let qclass = read_u16(packet, pos + 2)?;
+        pos += 4;
+
+        if !matches!(qclass & !UNICAST_RESPONSE, CLASS_IN | CLASS_ANY) {
+            continue;
+        }
+        query.unicast |= qclass & UNICAST_RESPONSE != 0;
+
+        let is = |types: &[u16]| qtype == TYPE_ANY || types.contains(&qtype);
+        let records = &mut query.records;
+        if is(&[TYPE_A, TYPE_AAAA]) && name_eq(packet, name, None, &[config.hostname, LOCAL]) {
+            records.host = true;
+        }
+        if is(&[TYPE_PTR]) && name_eq(packet, name, None, &[SERVICES_ENUMERATION, LOCAL]) {
+            records.enumeration = true;
+        }
+        for (i, service) in config.services.iter().enumerate() {
+            if is(&[TYPE_PTR]) && name_eq(packet, name, None, &[service.service, LOCAL]) {
+                // The SRV, TXT and address records are sent along, RFC 6763 section 12.1.
+                records.ptr |= 1 << i;
+                records.instance |= 1 << i;
+                records.host = true;
+            }
+            if is(&[TYPE_SRV, TYPE_TXT]) && name_eq(packet, name, Some(service.instance), &[service.service, LOCAL]) {
+                records.instance |= 1 << i;
+                records.host = true;
+            }
+        }
+    }
+    query.questions_end = pos;
+    Some(query)
+}
+
+/// Whether `packet` is a response with records for one of the configured names.
+fn conflicts(config: &Config, packet: &[u8]) -> bool {
+    let conflicts = || -> Option<bool> {
+        let flags = read_u16(packet, 2)?;
+        if flags & 0x8000 == 0 {
+            return Some(false);
+        }
+        let question_count = read_u16(packet, 4)?;
+        let answer_count = read_u16(packet, 6)?;
+
+        let mut pos = 12;
+        for _ in 0..question_count {
+            pos = skip_name(packet, pos)? + 4;
+        }
+        for _ in 0..answer_count {
+            let name = pos;
+            pos = skip_name(packet, pos)?;
+            let rtype = read_u16(packet, pos)?;
+            let len = read_u16(packet, pos + 8)? as usize;
+            pos += 10 + len;
+
+            if matches!(rtype, TYPE_A | TYPE_AAAA) && name_eq(packet, name, None, &[config.hostname, LOCAL]) {
+                return Some(true);
+            }
+            let instance = |s: &Service| name_eq(packet, name, Some(s.instance), &[s.service, LOCAL]);
+            if matches!(rtype, TYPE_SRV | TYPE_TXT) && config.services.iter().any(instance) {
+                return Some(true);
+            }
+        }
+        Some(false)
+    };
+    conflicts().unwrap_or(false)
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(packet.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+/// Position after the name at `pos`.
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            l if l & 0xC0 == 0xC0 => return (pos + 2 <= packet.len()).then_some(pos + 2),
+            l if l & 0xC0 == 0 => pos += 1 + l as usize,
+            _ => return None,
+        }
+    }
+}
+
+/// Whether the name at `pos` is `first`, then the dot separated labels of `rest`, ignoring
+/// ASCII case.
+fn name_eq(packet: &[u8], mut pos: usize, first: Option<&str>, rest: &[&str]) -> bool {
+    let mut expected = first.into_iter().chain(labels(rest));
+    // Bounds the pointers followed, so that a loop of pointers can't hang the responder.
+    let mut jumps = 0;
+    loop {
+        let Some(&len) = packet.get(pos) else {
+            return false;
+        };
+        match len {
+            0 => return expected.next().is_none(),
+            l if l & 0xC0 == 0xC0 => {
+                let Some(&low) = packet.get(pos + 1) else {
+                    return false;
+                };
+                jumps += 1;
+                if jumps > 16 {
+                    return false;
+                }
+                pos = (l as usize & 0x3F) << 8 | low as usize;
+            }
+            l if l & 0xC0 == 0 => {
+                let Some(label) = packet.get(pos + 1..pos + 1 + l as usize) else {
+                    return false;
+                };
+                match expected.next() {
+                    Some(e) if e.as_bytes().eq_ignore_ascii_case(label) => pos += 1 + l as usize,
+                    _ => return false,
+                }
+            }
+            _ => return false,
+        }
+    }
+}
+
+fn labels<'s>(parts: &'s [&'s str]) -> impl Iterator<Item = &'s str> {
+    parts.iter().flat_map(|p| p.split('.')).filter(|l| !l.is_empty())
+}
+
+/// Error returned when a packet doesn't fit in the buffer, or a name can't be encoded.
+struct WriteError;
+
+struct Writer<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> Writer<'b> {
+    fn bytes(&mut self, data: &[u8]) -> Result<(), WriteError> {
+        let end = self.len + data.len();
+        self.buf.get_mut(self.len..end).ok_or(WriteError)?.copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+
+    fn u16(&mut self, value: u16) -> Result<(), WriteError> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    fn set_u16(&mut self, pos: usize, value: u16) {
+        self.buf[pos..pos + 2].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// Write a length prefixed string, as in a label or a TXT record.
+    fn string(&mut self, s: &str, max: usize) -> Result<(), WriteError> {
+        if s.len() > max {
+            return Err(WriteError);
+        }
+        self.bytes(&[s.len() as u8])?;
+        self.bytes(s.as_bytes())
+    }
+
+    /// Write the name `first`, then the dot separated labels of `rest`, uncompressed.
+    fn name(&mut self, first: Option<&str>, rest: &[&str]) -> Result<(), WriteError> {
+        for label in first.into_iter().chain(labels(rest)) {
+            self.string(label, 63)?;
+        }
+        self.bytes(&[0])
+    }
+
+    fn record(
+        &mut self,
+        name: (Option<&str>, &[&str]),
+        rtype: u16,
+        class: u16,
+        ttl: u32,
+        rdata: impl FnOnce(&mut Self) -> Result<(), WriteError>,
+    ) -> Result<(), WriteError> {
+        self.name(name.0, name.1)?;
+        self.u16(rtype)?;
+        self.u16(class)?;
+        self.bytes(&ttl.to_be_bytes())?;
+        let len_pos = self.len;
+        self.u16(0)?;
+        rdata(self)?;
+        let len = self.len - len_pos - 2;
+        self.set_u16(len_pos, len as u16);
+        Ok(())
+    }
+
+    /// Write `records`, returning how many were written.
+    ///
+    /// For a legacy unicast query, the TTLs are capped and the cache flush bits cleared.
+    fn records(
+        &mut self,
+        config: &Config,
+        addrs: Addresses,
+        records: Records,
+        legacy: bool,
+    ) -> Result<u16, WriteError> {
+        let ttl = |ttl: u32| if legacy { ttl.min(LEGACY_TTL) } else { ttl };
+        let unique = if legacy { CLASS_IN } else { CLASS_IN | CACHE_FLUSH };
+        let host: &[&str] = &[config.hostname, LOCAL];
+        let mut count = 0;
+
+        if records.host {
+            #[cfg(feature = "proto-ipv4")]
+            if let Some(addr) = addrs.v4 {
+                self.record((None, host), TYPE_A, unique, ttl(HOST_TTL), |w| w.bytes(&addr.octets()))?;
+                count += 1;
+            }
+            #[cfg(feature = "proto-ipv6")]
+            if let Some(addr) = addrs.v6 {
+                self.record((None, host), TYPE_AAAA, unique, ttl(HOST_TTL), |w| {
+                    w.bytes(&addr.octets())
+                })?;
+                count += 1;
+            }
+        }
+
+        for (i, service) in config.services.iter().enumerate() {
+            let service_type: &[&str] = &[service.service, LOCAL];
+            let instance = (Some(service.instance), service_type);
+
+            let first_of_type = !config.services[..i]
+                .iter()
+                .any(|s| s.service.eq_ignore_ascii_case(service.service));
+            if records.enumeration && first_of_type {
+                let name = (None, &[SERVICES_ENUMERATION, LOCAL][..]);
+                self.record(name, TYPE_PTR, CLASS_IN, ttl(SERVICE_TTL), |w| {
+                    w.name(None, service_type)
+                })?;
+                count += 1;
+            }
+            if records.ptr & (1 << i) != 0 {
+                self.record((None, service_type), TYPE_PTR, CLASS_IN, ttl(SERVICE_TTL), |w| {
+                    w.name(instance.0, instance.1)
+                })?;
+                count += 1;
+            }
+            if records.instance & (1 << i) != 0 {
+                self.record(instance, TYPE_SRV, unique, ttl(HOST_TTL), |w| {
+                    // Priority and weight.
+                    w.u16(0)?;
+                    w.u16(0)?;
+                    w.u16(service.port)?;
+                    w.name(None, host)
+                })?;
+                self.record(instance, TYPE_TXT, unique, ttl(SERVICE_TTL), |w| {
+                    if service.txt.is_empty() {
+                        // A TXT record holds at least one string, RFC 6763 section 6.1.
+                        return w.bytes(&[0]);
+                    }
+                    for entry in service.txt {
+                        w.string(entry, 255)?;
+                    }
+                    Ok(())
+                })?;
+                count += 2;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Write a response with `records` to `buf`, returning its length.
+///
+/// For a legacy unicast query, `legacy` holds the query up to the end of its questions and the
+/// number of questions, which are repeated in the response, RFC 6762 section 6.7.
+fn write_response(
+    config: &Config,
+    addrs: Addresses,
+    records: Records,
+    legacy: Option<(&[u8], u16)>,
+    buf: &mut [u8],
+) -> Result<usize, WriteError> {
+    let mut w = Writer { buf, len: 0 };
+    match legacy {
+        Some((query, question_count)) => {
+            // Same ID, then the flags and counts written below.
+            w.bytes(query.get(..2).ok_or(WriteError)?)?;
+            w.u16(FLAGS_RESPONSE)?;
+            w.u16(question_count)?;
+        }
+        None => {
+            w.u16(0)?;
+            w.u16(FLAGS_RESPONSE)?;
+            w.u16(0)?;
+        }
+    }
+    // Answer, authority and additional record counts.
+    w.bytes(&[0; 6])?;
+    if let Some((query, _)) = legacy {
+        // Compression pointers in the questions stay valid, they are at the same offsets.
+        w.bytes(query.get(12..).ok_or(WriteError)?)?;
+    }
+
+    let count = w.records(config, addrs, records, legacy.is_some())?;
+    w.set_u16(6, count);
+    Ok(w.len)
+}
+
+/// Write a probe for the hostname and the service instance names to `buf`, returning its
+/// length, RFC 6762 section 8.1.
+fn write_probe(config: &Config, addrs: Addresses, buf: &mut [u8]) -> Result<usize, WriteError> {
+    let mut w = Writer { buf, len: 0 };
+    let question_count = 1 + config.services.len() as u16;
+    w.u16(0)?;
+    w.u16(0)?;
+    w.u16(question_count)?;
+    // Answer, authority and additional record counts.
+    w.bytes(&[0; 6])?;
+
+    // Questions for any type, asking for unicast responses.
+    w.name(None, &[config.hostname, LOCAL])?;
+    w.u16(TYPE_ANY)?;
+    w.u16(CLASS_IN | UNICAST_RESPONSE)?;
+    for service in config.services {
+        w.name(Some(service.instance), &[service.service, LOCAL])?;
+        w.u16(TYPE_ANY)?;
+        w.u16(CLASS_IN | UNICAST_RESPONSE)?;
+    }
+
+    // The records claimed go in the authority section.
+    let mask = (1 << config.services.len()) - 1;
+    let records = Records {
+        host: true,
+        enumeration: false,
+        ptr: 0,
+        instance: mask,
+    };
+    let count = w.records(config, addrs, records, false)?;
+    w.set_u16(8, count);
+    Ok(w.len)
+}
@@ -0,0 +1,410 @@
+//! SNTP client, to get the wall-clock time from an NTP server.
+//!
+//! [`get_time`] makes a single query. [`SntpClient`] keeps the time in sync in the background and
+//! maps [`Instant`]s to Unix time, from the offset between the two measured at the last sync:
+//!
+//! ```ignore
+//! static SNTP: StaticCell<SntpClient<'static>> = StaticCell::new();
+//! let sntp = &*SNTP.init(SntpClient::new(stack, "pool.ntp.org", sntp::Config::default()));
+//! spawner.spawn(sntp_task(sntp).unwrap());
+//!
+//! // In another task on the same executor.
+//! if let Some(secs) = sntp.now_unix() {
+//!     info!("Unix time: {}", secs);
+//! }
+//! ```
+//!
+//! Unix time ignores leap seconds, and so does the mapping: it advances with [`Instant`], so it
+//! never jumps between syncs. A sync steps it by the drift of the local clock since the previous
+//! one, forwards or backwards.
+//!
+//! Each query uses a UDP socket of the stack while it runs, and the server name is resolved with
+//! [`Stack::dns_query`], which also accepts IP addresses.
+
+use core::cell::Cell;
+
+use embassy_time::{Duration, Instant, Timer, with_deadline};
+use smoltcp::wire::{IpAddress, IpEndpoint};
+
+use crate::Stack;
+use crate::dns::{self, DnsQueryType};
+use crate::udp::{PacketMetadata, SendError, UdpSocket};
+
+const NTP_PORT: u16 = 123;
+const PACKET_LEN: usize = 48;
+/// Leap indicator 0, version 4, client mode.
+const CLIENT_HEADER: u8 = 4 << 3 | 3;
+const MODE_SERVER: u8 = 4;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+/// Seconds from the NTP epoch, 1900, to the Unix epoch, 1970.
+const UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Unix time, with microsecond resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UnixTimestamp {
+    micros: u64,
+}
+
+impl UnixTimestamp {
+    /// Create a timestamp from microseconds since the Unix epoch.
+    pub const fn from_micros(micros: u64) -> Self {
+        Self { micros }
+    }
+
+    /// Microseconds since the Unix epoch.
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    /// Whole seconds since the Unix epoch.
+    pub const fn as_secs(&self) -> u64 {
+        self.micros / 1_000_000
+    }
+
+    /// Microseconds past the last whole second.
+    pub const fn subsec_micros(&self) -> u32 {
+        (self.micros % 1_000_000) as u32
+    }
+}
+
+/// Error returned by [`get_time`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The server name couldn't be resolved.
+    Dns(dns::Error),
+    /// The request couldn't be sent.
+    Send(SendError),
+    /// No valid response was received in time.
+    Timeout,
+    /// The server sent a kiss-o'-death packet with this code, e.g. `RATE`, `DENY` or `RSTR`.
+    ///
+    /// With `DENY` and `RSTR`, the server must not be queried again. With `RATE`, the client
+    /// must query it less often.
+    KissOfDeath([u8; 4]),
+    /// The server isn't synchronized.
+    Unsynchronized,
+    /// The response holds a timestamp before the Unix epoch.
+    InvalidResponse,
+}
+
+/// A Unix time and the [`Instant`] it was measured at.
+#[derive(Clone, Copy)]
+struct Sync {
+    instant: Instant,
+    time: UnixTimestamp,
+}
+
+impl Sync {
+    fn to_unix(self, instant: Instant) -> UnixTimestamp {
+        let micros = if instant >= self.instant {
+            self.time.micros + (instant - self.instant).as_micros()
+        } else {
+            self.time.micros.saturating_sub((self.instant - instant).as_micros())
+        };
+        UnixTimestamp::from_micros(micros)
+    }
+}
+
+/// Get the current time from the NTP server `server`, a hostname or an IP address.
+///
+/// Waits up to `timeout` for the response, not counting the name resolution.
+pub async fn get_time(stack: Stack<'_>, server: &str, timeout: Duration) -> Result<UnixTimestamp, Error> {
+    let sync = query(stack, server, timeout).await?;
+    Ok(sync.to_unix(Instant::now()))
+}
+
+async fn query(stack: Stack<'_>, server: &str, timeout: Duration) -> Result<Sync, Error> {
+    #[cfg(feature = "proto-ipv4")]
+    let qtype = DnsQueryType::A;
+    #[cfg(not(feature = "proto-ipv4"))]
+    let qtype = DnsQueryType::Aaaa;
+    let addrs = stack.dns_query(server, qtype).await.map_err(Error::Dns)?;
+    let Some(&addr) = addrs.first() else {
+        return Err(Error::Dns(dns::Error::Failed));
+    };
+    query_addr(stack, addr, timeout).await
+}
+
+async fn query_addr(stack: Stack<'_>, addr: IpAddress, timeout: Duration) -> Result<Sync, Error> {
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; PACKET_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; PACKET_LEN];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+    // A new socket bound to an ephemeral port can't fail to bind.
+    unwrap!(socket.bind(0));
+    let server = IpEndpoint::new(addr, NTP_PORT);
+
+    // The transmit timestamp is only used to match the response, RFC 4330 section 5: the local
+    // clock ticks make it unique.
+    let sent = Instant::now();
+    let mut request = [0; PACKET_LEN];
+    request[0] = CLIENT_HEADER;
+    request[40..48].copy_from_slice(&sent.as_ticks().to_be_bytes());
+    socket.send_to(&request, server).await.map_err(Error::Send)?;
+
+    let deadline = sent + timeout;
+    let mut response = [0; PACKET_LEN];
+    loop {
+        let Ok(received) = with_deadline(deadline, socket.recv_from(&mut response)).await else {
+            return Err(Error::Timeout);
+        };
+        let received_at = Instant::now();
+        match received {
+            Ok((PACKET_LEN, meta))
+                if meta.endpoint == server
+                    && response[0] & 0x7 == MODE_SERVER
+                    && response[24..32] == request[40..48] =>
+            {
+                return parse_response(&response, sent, received_at);
+            }
+            // Not the response to our request, or truncated.
+            _ => continue,
+        }
+    }
+}
+
+fn parse_response(response: &[u8; PACKET_LEN], sent: Instant, received_at: Instant) -> Result<Sync, Error> {
+    let leap = response[0] >> 6;
+    let stratum = response[1];
+    if stratum == 0 {
+        let mut code = [0; 4];
+        code.copy_from_slice(&response[12..16]);
+        return Err(Error::KissOfDeath(code));
+    }
+    if leap == LEAP_UNSYNCHRONIZED {
+        return Err(Error::Unsynchronized);
+    }
+
+    let server_received = ntp_to_unix_micros(&response[32..40])?;
+    let server_sent = ntp_to_unix_micros(&response[40..48])?;
+
+    // The response took half of the round trip, minus the time the server held the request.
+    let round_trip = (received_at - sent).as_micros();
+    let held = server_sent.saturating_sub(server_received);
+    let delay = round_trip.saturating_sub(held) / 2;
+    Ok(Sync {
+        instant: received_at,
+        time: UnixTimestamp::from_micros(server_sent + delay),
+    })
+}
+
+/// Convert an NTP timestamp to microseconds since the Unix epoch.
+///
+/// A server that isn't synchronized may leave its timestamps unset, at 0.
+fn ntp_to_unix_micros(timestamp: &[u8]) -> Result<u64, Error> {
+    let secs = u32::from_be_bytes([timestamp[0], timestamp[1], timestamp[2], timestamp[3]]);
+    let fraction = u32::from_be_bytes([timestamp[4], timestamp[5], timestamp[6], timestamp[7]]);
+    if secs == 0 && fraction == 0 {
+        return Err(Error::Unsynchronized);
+    }
+    // Timestamps with the top bit clear are in era 1, from 2036, RFC 4330 section 3.
+    let mut secs = secs as u64;
+    if secs & 0x8000_0000 == 0 {
+        secs += 1 << 32;
+    }
+    // Era 0 timestamps from 1968 to 1970 are before the Unix epoch.
+    let secs = secs.checked_sub(UNIX_OFFSET).ok_or(Error::InvalidResponse)?;
+    Ok(secs * 1_000_000 + ((fraction as u64 * 1_000_000) >> 32))
+}
+
+/// Configuration of a [`SntpClient`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config {
+    /// Time between syncs.
+    pub interval: Duration,
+    /// Time to wait for a response.
+    pub timeout: Duration,
+    /// Time to wait after a first failed sync. It doubles after each failure, up to `interval`.
+    pub retry_delay: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(3600),
+            timeout: Duration::from_secs(5),
+            retry_delay: Duration::from_secs(15),
+        }
+    }
+}
+
+/// SNTP client syncing in the background, see the [module documentation](self).
+pub struct SntpClient<'a> {
+    stack: Stack<'a>,
+    server: &'a str,
+    config: Config,
+    sync: Cell<Option<Sync>>,
+}
+
+impl<'a> SntpClient<'a> {
+    /// Create a client syncing with `server`, a hostname or an IP address.
+    pub fn new(stack: Stack<'a>, server: &'a str, config: Config) -> Self {
+        Self {
+            stack,
+            server,
+            config,
+            sync: Cell::new(None),
+        }
+    }
+
+    /// Run the client, syncing every [`Config::interval`].
+    ///
+    /// Failed syncs are retried with an exponential backoff. A `RATE` kiss-o'-death doubles the
+    /// interval, and `DENY` or `RSTR` stop the syncs for good, as required by RFC 4330.
+    pub async fn run(&self) -> ! {
+        let mut interval = self.config.interval;
+        let mut retry_delay = self.config.retry_delay;
+        loop {
+            self.stack.wait_config_up().await;
+
+            let delay = match query(self.stack, self.server, self.config.timeout).await {
+                Ok(sync) => {
+                    if self.sync.get().is_none() {
+                        info!("SNTP: synced to Unix time {}", sync.time.as_secs());
+                    }
+                    self.sync.set(Some(sync));
+                    retry_delay = self.config.retry_delay;
+                    interval
+                }
+                Err(Error::KissOfDeath(code)) if code == *b"RATE" => {
+                    interval *= 2;
+                    warn!(
+                        "SNTP: server asked to slow down, now syncing every {} s",
+                        interval.as_secs()
+                    );
+                    interval
+                }
+                Err(Error::KissOfDeath(code)) if code == *b"DENY" || code == *b"RSTR" => {
+                    error!("SNTP: server denied access, syncing stopped");
+                    core::future::pending().await
+                }
+                Err(_e) => {
+                    debug!("SNTP: sync failed: {:?}", _e);
+                    let delay = retry_delay;
+                    retry_delay = (retry_delay * 2).min(interval);
+                    delay
+                }
+            };
+            Timer::after(delay).await;
+        }
+    }
+
+    /// Whether the client synced at least once.
+    pub fn is_synced(&self) -> bool {
+        self.sync.get().is_some()
+    }
+
+    /// Get the current Unix time, or `None` before the first sync.
+    pub fn now(&self) -> Option<UnixTimestamp> {
+        self.to_unix(Instant::now())
+    }
+
+    /// Get the current Unix time in whole seconds, or `None` before the first sync.
+    pub fn now_unix(&self) -> Option<u64> {
+        self.now().map(|t| t.as_secs())
+    }
+
+    /// Map `instant` to Unix time, or `None` before the first sync.
+    pub fn to_unix(&self, instant: Instant) -> Option<UnixTimestamp> {
+        self.sync.get().map(|sync| sync.to_unix(instant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A server response with the given header and transmit timestamp, received 1 s after the
+    /// request.
+    fn response(header: u8, stratum: u8, secs: u32) -> [u8; PACKET_LEN] {
+        let mut response = [0; PACKET_LEN];
+        response[0] = header;
+        response[1] = stratum;
+        response[32..36].copy_from_slice(&secs.to_be_bytes());
+        response[40..44].copy_from_slice(&secs.to_be_bytes());
+        response
+    }
+
+    fn timestamp(secs: u32, fraction: u32) -> [u8; 8] {
+        let mut timestamp = [0; 8];
+        timestamp[..4].copy_from_slice(&secs.to_be_bytes());
+        timestamp[4..].copy_from_slice(&fraction.to_be_bytes());
+        timestamp
+    }
+
+    #[test]
+    fn era_0() {
+        // 2024-01-01 00:00:00 UTC, plus half a second.
+        assert_eq!(
+            ntp_to_unix_micros(&timestamp(3_913_056_000, 1 << 31)),
+            Ok(1_704_067_200_500_000)
+        );
+        assert_eq!(ntp_to_unix_micros(&timestamp(UNIX_OFFSET as u32, 0)), Ok(0));
+    }
+
+    #[test]
+    fn era_1() {
+        // 2036-02-07 06:28:16 UTC, the start of era 1, and one second later.
+        assert_eq!(ntp_to_unix_micros(&timestamp(0, 1)), Ok(2_085_978_496_000_000));
+        assert_eq!(ntp_to_unix_micros(&timestamp(1, 0)), Ok(2_085_978_497_000_000));
+    }
+
+    #[test]
+    fn zero_timestamp() {
+        assert_eq!(ntp_to_unix_micros(&timestamp(0, 0)), Err(Error::Unsynchronized));
+    }
+
+    #[test]
+    fn before_unix_epoch() {
+        assert_eq!(
+            ntp_to_unix_micros(&timestamp(0x8000_0000, 0)),
+            Err(Error::InvalidResponse)
+        );
+        assert_eq!(
+            ntp_to_unix_micros(&timestamp(UNIX_OFFSET as u32 - 1, 0)),
+            Err(Error::InvalidResponse)
+        );
+    }
+
+    #[test]
+    fn kiss_of_death() {
+        let mut kod = response(LEAP_UNSYNCHRONIZED << 6 | 4 << 3 | MODE_SERVER, 0, 0);
+        kod[12..16].copy_from_slice(b"RATE");
+        let at = Instant::from_secs(1);
+        assert_eq!(
+            parse_response(&kod, at, at).map(|sync| sync.time),
+            Err(Error::KissOfDeath(*b"RATE"))
+        );
+    }
+
+    #[test]
+    fn leap_unsynchronized() {
+        let response = response(LEAP_UNSYNCHRONIZED << 6 | 4 << 3 | MODE_SERVER, 2, 3_913_056_000);
+        let at = Instant::from_secs(1);
+        assert_eq!(
+            parse_response(&response, at, at).map(|sync| sync.time),
+            Err(Error::Unsynchronized)
+        );
+    }
+
+    #[test]
+    fn round_trip_delay() {
+        // The server held the request for 200 ms of a 1 s round trip, so the response took 400 ms.
+        let mut response = response(4 << 3 | MODE_SERVER, 2, 3_913_056_000);
+        response[40..48].copy_from_slice(&timestamp(3_913_056_000, 858_993_460));
+        let sent = Instant::from_secs(10);
+        let received_at = Instant::from_secs(11);
+        let sync = parse_response(&response, sent, received_at).unwrap();
+        assert!(sync.instant == received_at);
+        assert_eq!(sync.time, UnixTimestamp::from_micros(1_704_067_200_600_000));
+        assert_eq!(
+            sync.to_unix(Instant::from_secs(12)),
+            UnixTimestamp::from_micros(1_704_067_201_600_000)
+        );
+    }
+}
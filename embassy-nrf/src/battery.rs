@@ -0,0 +1,528 @@
+//! Battery state-of-charge estimation.
+//!
+//! A single supply voltage reading maps poorly to remaining capacity: the voltage sags under
+//! load and in the cold, and ADC noise makes the result jump around. [`Estimator`] turns a
+//! stream of voltage readings into a steady state of charge by
+//!
+//! - taking the median of the last `N` readings to reject spikes,
+//! - adding back the voltage dropped across the battery's internal resistance at the
+//!   current load, as hinted by the application through a [`LoadHint`],
+//! - correcting for temperature,
+//! - looking the result up in the [`DischargeCurve`] of the battery chemistry,
+//! - smoothing the result, and only letting it go up again after it has risen by more
+//!   than a hysteresis margin, e.g. while charging.
+//!
+//! [`run`] drives an estimator from the SAADC at a fixed period and publishes each
+//! [`BatteryState`] to a [`Watch`](embassy_sync::watch::Watch), so that UI and radio tasks
+//! can subscribe to it.
+//!
+//! ```rust,ignore
+//! use embassy_nrf::battery::{self, Estimator, LoadHint, Scale};
+//! use embassy_nrf::saadc::{self, ChannelConfig, Resolution, Saadc, VddInput};
+//! use embassy_nrf::{bind_interrupts, temp};
+//! use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+//! use embassy_sync::watch::Watch;
+//! use embassy_time::Duration;
+//!
+//! bind_interrupts!(struct Irqs {
+//!     SAADC => saadc::InterruptHandler;
+//!     TEMP => temp::InterruptHandler;
+//! });
+//!
+//! static BATTERY: Watch<CriticalSectionRawMutex, battery::BatteryState, 2> = Watch::new();
+//! static LOAD: LoadHint = LoadHint::new();
+//!
+//! # async {
+//! # let p: embassy_nrf::Peripherals = todo!();
+//! let mut saadc = Saadc::new(p.SAADC, Irqs, saadc::Config::default(), [ChannelConfig::single_ended(VddInput)]);
+//! saadc.calibrate().await;
+//! let mut temp = temp::Temp::new(p.TEMP, Irqs);
+//! let mut estimator: Estimator<'_, 5> = Estimator::new(battery::Config::default());
+//!
+//! battery::run(
+//!     &mut saadc,
+//!     Scale::new(3600, Resolution::_12BIT),
+//!     &mut temp,
+//!     &LOAD,
+//!     &mut estimator,
+//!     Duration::from_secs(10),
+//!     BATTERY.dyn_sender(),
+//! )
+//! .await;
+//! # };
+//! ```
+
+use core::future::Future;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+#[cfg(feature = "time")]
+use embassy_sync::watch::DynSender;
+#[cfg(feature = "time")]
+use embassy_time::{Duration, Ticker};
+
+use crate::saadc::Resolution;
+#[cfg(feature = "time")]
+use crate::saadc::Saadc;
+
+/// Open-circuit voltage to state of charge table for a battery chemistry.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DischargeCurve<'a> {
+    points: &'a [(u16, u8)],
+}
+
+impl<'a> DischargeCurve<'a> {
+    /// Single lithium-ion or lithium-polymer cell, charged to 4.2 V.
+    pub const LI_ION: DischargeCurve<'static> = DischargeCurve::new(&[
+        (4200, 100),
+        (4100, 90),
+        (4000, 80),
+        (3920, 70),
+        (3850, 60),
+        (3800, 50),
+        (3760, 40),
+        (3730, 30),
+        (3700, 20),
+        (3650, 10),
+        (3500, 5),
+        (3300, 0),
+    ]);
+
+    /// CR2032 lithium coin cell.
+    pub const CR2032: DischargeCurve<'static> = DischargeCurve::new(&[
+        (3000, 100),
+        (2900, 80),
+        (2800, 60),
+        (2700, 40),
+        (2600, 20),
+        (2500, 10),
+        (2000, 0),
+    ]);
+
+    /// Two alkaline AA or AAA cells in series.
+    pub const ALKALINE_2S: DischargeCurve<'static> = DischargeCurve::new(&[
+        (3200, 100),
+        (2900, 90),
+        (2700, 75),
+        (2550, 55),
+        (2450, 40),
+        (2350, 25),
+        (2200, 10),
+        (2000, 0),
+    ]);
+
+    /// Create a curve from `(millivolts, percent)` points.
+    ///
+    /// Voltages below the last point map to the last point's percentage, and voltages
+    /// above the first point to the first point's percentage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `points` is empty, is not sorted by strictly decreasing voltage and
+    /// non-increasing percentage, or has a percentage above 100.
+    pub const fn new(points: &'a [(u16, u8)]) -> Self {
+        core::assert!(!points.is_empty(), "discharge curve is empty");
+        let mut i = 0;
+        while i < points.len() {
+            core::assert!(points[i].1 <= 100, "discharge curve percentage above 100");
+            if i > 0 {
+                core::assert!(
+                    points[i].0 < points[i - 1].0 && points[i].1 <= points[i - 1].1,
+                    "discharge curve not sorted by decreasing voltage"
+                );
+            }
+            i += 1;
+        }
+        Self { points }
+    }
+
+    /// State of charge in tenths of a percent at `millivolts`, interpolated linearly
+    /// between points.
+    pub fn permille(&self, millivolts: u16) -> u16 {
+        let (first_mv, first_pct) = self.points[0];
+        if millivolts >= first_mv {
+            return first_pct as u16 * 10;
+        }
+        for w in self.points.windows(2) {
+            let (hi_mv, hi_pct) = w[0];
+            let (lo_mv, lo_pct) = w[1];
+            if millivolts >= lo_mv {
+                let span = (hi_pct - lo_pct) as u32 * 10;
+                let offset = (millivolts - lo_mv) as u32 * span / (hi_mv - lo_mv) as u32;
+                return (lo_pct as u32 * 10 + offset) as u16;
+            }
+        }
+        self.points[self.points.len() - 1].1 as u16 * 10
+    }
+}
+
+/// Estimator configuration.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config<'a> {
+    /// Discharge curve of the battery.
+    pub curve: DischargeCurve<'a>,
+    /// Internal resistance of the battery and its wiring in milliohms, used to estimate
+    /// the open-circuit voltage from the load hint.
+    pub internal_resistance_mohm: u16,
+    /// Voltage lost per degree Celsius below `reference_temperature`, in microvolts.
+    pub temperature_coefficient_uv: i32,
+    /// Temperature at which the discharge curve was measured, in degrees Celsius.
+    pub reference_temperature: i32,
+    /// Smoothing of the state of charge. Each reading moves the estimate by 1/2^`smoothing`
+    /// of the difference.
+    pub smoothing: u8,
+    /// How far the smoothed state of charge must rise, in tenths of a percent, before the
+    /// reported value goes up.
+    pub rise_hysteresis: u16,
+}
+
+impl Default for Config<'static> {
+    fn default() -> Self {
+        Self {
+            curve: DischargeCurve::LI_ION,
+            internal_resistance_mohm: 150,
+            temperature_coefficient_uv: 0,
+            reference_temperature: 25,
+            smoothing: 2,
+            rise_hysteresis: 30,
+        }
+    }
+}
+
+/// Battery state published by the estimator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryState {
+    /// Median-filtered open-circuit voltage estimate in millivolts, after load and
+    /// temperature compensation.
+    pub millivolts: u16,
+    /// Estimated state of charge in percent.
+    pub percent: u8,
+    /// Temperature used for compensation, in degrees Celsius.
+    pub temperature: i32,
+}
+
+/// Filters voltage readings into a state of charge.
+///
+/// `N` is the length of the median filter window.
+pub struct Estimator<'a, const N: usize> {
+    config: Config<'a>,
+    window: [u16; N],
+    len: usize,
+    next: usize,
+    /// Smoothed state of charge in tenths of a percent, with 8 fractional bits.
+    smoothed: Option<u32>,
+    reported: u16,
+}
+
+impl<'a, const N: usize> Estimator<'a, N> {
+    /// Create a new estimator.
+    pub fn new(config: Config<'a>) -> Self {
+        core::assert!(N > 0, "median window must not be empty");
+        Self {
+            config,
+            window: [0; N],
+            len: 0,
+            next: 0,
+            smoothed: None,
+            reported: 0,
+        }
+    }
+
+    /// Discard the filter state, e.g. after the battery was replaced.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.next = 0;
+        self.smoothed = None;
+        self.reported = 0;
+    }
+
+    /// Feed a reading of the battery voltage taken while drawing `load_ma` milliamps at
+    /// `temperature` degrees Celsius.
+    pub fn update(&mut self, millivolts: u16, load_ma: u16, temperature: i32) -> BatteryState {
+        // Compensate each reading before filtering, so that the median is taken over readings
+        // that were each corrected for the conditions they were taken in.
+        let c = &self.config;
+        let load_mv = load_ma as i32 * c.internal_resistance_mohm as i32 / 1000;
+        let temperature_mv = (c.reference_temperature - temperature) * c.temperature_coefficient_uv / 1000;
+        let compensated = (millivolts as i32 + load_mv + temperature_mv).clamp(0, u16::MAX as i32) as u16;
+
+        self.window[self.next] = compensated;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+
+        let mut sorted = self.window;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable();
+        let median = sorted[self.len / 2];
+
+        let target = (c.curve.permille(median) as u32) << 8;
+
+        let smoothed = match self.smoothed {
+            None => target,
+            Some(s) if target >= s => s + ((target - s) >> c.smoothing),
+            Some(s) => s - ((s - target) >> c.smoothing),
+        };
+        let first = self.smoothed.is_none();
+        self.smoothed = Some(smoothed);
+
+        let smoothed = (smoothed >> 8) as u16;
+        if first || smoothed < self.reported || smoothed > self.reported + c.rise_hysteresis {
+            self.reported = smoothed;
+        }
+
+        BatteryState {
+            millivolts: median,
+            percent: (self.reported / 10) as u8,
+            temperature,
+        }
+    }
+}
+
+/// Conversion from SAADC results to battery millivolts.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Scale {
+    full_scale_mv: u32,
+    max_code: u32,
+}
+
+impl Scale {
+    /// Scale for a channel whose input range is `full_scale_mv`, sampled at `resolution`.
+    ///
+    /// With the default [`ChannelConfig`](crate::saadc::ChannelConfig) the range is
+    /// 3600 mV. Sampling `VddhDiv5Input` with it covers 18000 mV of VDDH.
+    pub const fn new(full_scale_mv: u32, resolution: Resolution) -> Self {
+        Self {
+            full_scale_mv,
+            max_code: 1 << (8 + 2 * resolution as u32),
+        }
+    }
+
+    /// Account for an external resistor divider in front of the input, with `top_ohms`
+    /// between the battery and the input and `bottom_ohms` between the input and ground.
+    pub const fn with_divider(self, top_ohms: u32, bottom_ohms: u32) -> Self {
+        let full_scale_mv =
+            (self.full_scale_mv as u64 * (top_ohms as u64 + bottom_ohms as u64) / bottom_ohms as u64) as u32;
+        Self {
+            full_scale_mv,
+            max_code: self.max_code,
+        }
+    }
+
+    /// Convert a sample to millivolts at the battery.
+    pub fn millivolts(&self, sample: i16) -> u16 {
+        let sample = sample.max(0) as u32;
+        (sample * self.full_scale_mv / self.max_code).min(u16::MAX as u32) as u16
+    }
+}
+
+/// Current drawn from the battery, as hinted by the application.
+///
+/// Set it whenever the activity changes, e.g. before keying a radio or turning on a
+/// display, so that the voltage sag it causes is not mistaken for a drained battery.
+pub struct LoadHint(AtomicU16);
+
+impl LoadHint {
+    /// Create a new hint of 0 mA.
+    pub const fn new() -> Self {
+        Self(AtomicU16::new(0))
+    }
+
+    /// Set the current in milliamps.
+    pub fn set(&self, milliamps: u16) {
+        self.0.store(milliamps, Ordering::Relaxed);
+    }
+
+    /// Current in milliamps.
+    pub fn get(&self) -> u16 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for LoadHint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A source of temperature readings for compensation.
+pub trait Thermometer {
+    /// Read the temperature in degrees Celsius.
+    fn read_celsius(&mut self) -> impl Future<Output = i32>;
+}
+
+/// A fixed temperature, for boards without a temperature source.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FixedTemperature(pub i32);
+
+impl Thermometer for FixedTemperature {
+    async fn read_celsius(&mut self) -> i32 {
+        self.0
+    }
+}
+
+#[cfg(not(any(feature = "_nrf5340-app", feature = "_nrf91")))]
+impl<'d> Thermometer for crate::temp::Temp<'d> {
+    async fn read_celsius(&mut self) -> i32 {
+        self.read().await.to_num()
+    }
+}
+
+/// Sample the battery every `period` and publish its state to `sender`.
+///
+/// `saadc` must be configured with a single channel measuring the battery, and `scale`
+/// must match that channel.
+#[cfg(feature = "time")]
+pub async fn run<const N: usize>(
+    saadc: &mut Saadc<'_, 1>,
+    scale: Scale,
+    thermometer: &mut impl Thermometer,
+    load: &LoadHint,
+    estimator: &mut Estimator<'_, N>,
+    period: Duration,
+    sender: DynSender<'_, BatteryState>,
+) -> ! {
+    let mut ticker = Ticker::every(period);
+    loop {
+        let mut buf = [0; 1];
+        saadc.sample(&mut buf).await;
+        let temperature = thermometer.read_celsius().await;
+        sender.send(estimator.update(scale.millivolts(buf[0]), load.get(), temperature));
+        ticker.next().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise in `-amplitude..=amplitude`.
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self, amplitude: i32) -> i32 {
+            self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (self.0 >> 16) as i32 % (amplitude + 1) * if self.0 & 1 == 0 { 1 } else { -1 }
+        }
+    }
+
+    fn config() -> Config<'static> {
+        Config {
+            temperature_coefficient_uv: 2000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn curve_interpolates_and_clamps() {
+        let curve = DischargeCurve::LI_ION;
+        assert_eq!(curve.permille(4300), 1000);
+        assert_eq!(curve.permille(4200), 1000);
+        assert_eq!(curve.permille(4150), 950);
+        assert_eq!(curve.permille(3800), 500);
+        assert_eq!(curve.permille(3300), 0);
+        assert_eq!(curve.permille(3000), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn curve_must_be_sorted() {
+        DischargeCurve::new(&[(3000, 50), (3100, 40)]);
+    }
+
+    #[test]
+    fn scale_converts_samples() {
+        let scale = Scale::new(3600, Resolution::_12BIT);
+        assert_eq!(scale.millivolts(4096), 3600);
+        assert_eq!(scale.millivolts(2048), 1800);
+        assert_eq!(scale.millivolts(-5), 0);
+        let divided = Scale::new(3600, Resolution::_10BIT).with_divider(1_000_000, 1_000_000);
+        assert_eq!(divided.millivolts(512), 3600);
+    }
+
+    #[test]
+    fn median_rejects_spikes() {
+        let mut est: Estimator<'_, 5> = Estimator::new(config());
+        for _ in 0..5 {
+            est.update(3800, 0, 25);
+        }
+        let state = est.update(3000, 0, 25);
+        assert_eq!(state.millivolts, 3800);
+        let state = est.update(4200, 0, 25);
+        assert_eq!(state.millivolts, 3800);
+        assert_eq!(state.percent, 50);
+    }
+
+    #[test]
+    fn discharge_with_noise_load_and_cold_is_monotonic() {
+        let mut est: Estimator<'_, 7> = Estimator::new(config());
+        let mut noise = Noise(1);
+        let mut prev = 100;
+        for i in 0..2000 {
+            let ocv = 4200 - i * 900 / 2000;
+            // Radio bursts of 40 mA on every tenth reading, with 150 mOhm of internal resistance.
+            let load = if i % 10 == 0 { 40 } else { 0 };
+            // A cold spell in the middle, losing 2 mV per degree.
+            let temperature = if (800..1200).contains(&i) { -5 } else { 25 };
+            let mv = ocv - load * 150 / 1000 - (25 - temperature) * 2 + noise.next(15);
+            let state = est.update(mv as u16, load as u16, temperature);
+            assert!(state.percent <= 100);
+            assert!(
+                state.percent <= prev,
+                "rose from {} to {} at {}",
+                prev,
+                state.percent,
+                i
+            );
+            prev = state.percent;
+        }
+        assert!(prev <= 5);
+    }
+
+    #[test]
+    fn hinted_load_does_not_drop_estimate() {
+        let mut est: Estimator<'_, 3> = Estimator::new(config());
+        for _ in 0..10 {
+            est.update(3850, 0, 25);
+        }
+        let before = est.update(3850, 0, 25).percent;
+        // A 400 mA burst sags the voltage by 60 mV across 150 mOhm.
+        for _ in 0..10 {
+            assert_eq!(est.update(3790, 400, 25).percent, before);
+        }
+        for _ in 0..10 {
+            assert_eq!(est.update(3850, 0, 25).percent, before);
+        }
+    }
+
+    #[test]
+    fn charging_rises_past_hysteresis() {
+        let mut est: Estimator<'_, 3> = Estimator::new(config());
+        for _ in 0..10 {
+            est.update(3700, 0, 25);
+        }
+        assert_eq!(est.update(3700, 0, 25).percent, 20);
+        let mut prev = 20;
+        for i in 0..300 {
+            let state = est.update(3700 + i * 2, 0, 25);
+            assert!(state.percent >= prev);
+            assert!(state.percent <= 100);
+            prev = state.percent;
+        }
+        assert!(prev >= 90);
+    }
+
+    #[test]
+    fn reset_forgets_history() {
+        let mut est: Estimator<'_, 3> = Estimator::new(config());
+        for _ in 0..10 {
+            est.update(3500, 0, 25);
+        }
+        est.reset();
+        assert_eq!(est.update(4200, 0, 25).percent, 100);
+    }
+}
@@ -28,7 +28,9 @@ use crate::ppi::{
     self, AnyConfigurableChannel, AnyGroup, Channel, ConfigurableChannel, Event, Group, Ppi, PpiGroup, Task,
 };
 use crate::timer::{Instance as TimerInstance, Timer};
-use crate::uarte::{Config, Instance as UarteInstance, configure, configure_rx_pins, configure_tx_pins, drop_tx_rx};
+use crate::uarte::{
+    Config, Instance as UarteInstance, configure, configure_rx_pins, configure_tx_pins, drop_tx_rx, has_rtscts,
+};
 use crate::{EASY_DMA_SIZE, interrupt, pac};
 
 pub(crate) struct State {
@@ -50,6 +52,8 @@ pub(crate) struct State {
 pub enum Error {
     /// Buffer Overrun
     Overrun,
+    /// Data is still waiting to be transmitted.
+    Busy,
 }
 
 impl State {
@@ -326,8 +330,47 @@ impl<'d> BufferedUarte<'d> {
     }
 
     /// Adjust the baud rate to the provided value.
-    pub fn set_baudrate(&mut self, baudrate: Baudrate) {
-        self.tx.set_baudrate(baudrate);
+    ///
+    /// The transmitter and the receiver are stopped while the rate changes, and the receiver is
+    /// restarted afterwards. Received bytes are kept, but a byte arriving during the change may be
+    /// corrupted, so switch when the other side is known to be silent.
+    ///
+    /// Returns [`Error::Busy`] if data is still waiting to be transmitted, see [`flush`](Self::flush).
+    pub fn set_baudrate(&mut self, baudrate: Baudrate) -> Result<(), Error> {
+        self.reconfigure(|r| r.baudrate().write(|w| w.set_baudrate(baudrate)))
+    }
+
+    /// Enable or disable hardware flow control (RTS/CTS).
+    ///
+    /// The peripheral is stopped and restarted like in [`set_baudrate`](Self::set_baudrate).
+    ///
+    /// Returns [`Error::Busy`] if data is still waiting to be transmitted, see [`flush`](Self::flush).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `enabled` is true but the driver was created without RTS and CTS pins.
+    pub fn set_hwfc(&mut self, enabled: bool) -> Result<(), Error> {
+        if enabled && !has_rtscts(self.tx.r) {
+            panic!("Hardware flow control requires RTS and CTS pins.");
+        }
+        self.reconfigure(|r| r.config().modify(|w| w.set_hwfc(enabled)))
+    }
+
+    fn reconfigure(&mut self, f: impl FnOnce(pac::uarte::Uarte)) -> Result<(), Error> {
+        self.tx.check_idle()?;
+
+        // Keep the interrupt handler from restarting the receiver while it is stopped.
+        let irq = self.tx._irq;
+        irq.disable();
+        self.tx.stop();
+        self.rx.stop();
+        f(self.tx.r);
+        self.rx.restart();
+
+        compiler_fence(Ordering::SeqCst);
+        irq.pend();
+        unsafe { irq.enable() };
+        Ok(())
     }
 
     /// Split the UART in reader and writer parts.
@@ -540,8 +583,35 @@ impl<'d> BufferedUarteTx<'d> {
     }
 
     /// Adjust the baud rate to the provided value.
-    pub fn set_baudrate(&mut self, baudrate: Baudrate) {
+    ///
+    /// The transmitter is stopped while the rate changes. A receiver split from the same driver
+    /// keeps running, so bytes arriving at that moment may be corrupted: use
+    /// [`BufferedUarte::set_baudrate`] to stop it too.
+    ///
+    /// Returns [`Error::Busy`] if data is still waiting to be transmitted, see [`flush`](Self::flush).
+    pub fn set_baudrate(&mut self, baudrate: Baudrate) -> Result<(), Error> {
+        self.check_idle()?;
+        self.stop();
         self.r.baudrate().write(|w| w.set_baudrate(baudrate));
+        Ok(())
+    }
+
+    /// Stop the transmitter, once the data has been transmitted.
+    fn stop(&mut self) {
+        // ENDTX comes once the last byte is handed to the transmitter, which may still be shifting
+        // it out. TXSTOPPED waits for the transmitter to come to a stop.
+        let r = self.r;
+        r.events_txstopped().write_value(0);
+        r.tasks_dma().tx().stop().write_value(1);
+        while r.events_txstopped().read() == 0 {}
+    }
+
+    fn check_idle(&self) -> Result<(), Error> {
+        if self.buffered_state.tx_buf.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Busy)
+        }
     }
 }
 
@@ -835,6 +905,84 @@ impl<'d> BufferedUarteRx<'d> {
         self.r.intenset().write(|w| w.set_dmarxready(true));
     }
 
+    /// Stop the receiver, with the interrupt disabled.
+    ///
+    /// Stopping moves the bytes left in the RX FIFO to the buffer, so the RXDRDY counter gives
+    /// the exact end of the received data.
+    fn stop(&mut self) {
+        let r = self.r;
+        let s = self.buffered_state;
+        if !s.rx_started.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Keep ENDRX from starting the next transfer.
+        self._ppi_group.disable_all();
+        r.events_rxto().write_value(0);
+        r.tasks_dma().rx().stop().write_value(1);
+        while r.events_rxto().read() == 0 {}
+        r.events_rxto().write_value(0);
+    }
+
+    /// Restart the receiver after [`stop`](Self::stop), with the interrupt disabled.
+    ///
+    /// The buffer is filled in halves, and up to two of them are reserved for the transfer in
+    /// progress and the next one. The reception resumes right after the received data, up to
+    /// the end of the reserved space, so the halves stay aligned with the RXDRDY counter.
+    fn restart(&mut self) {
+        let r = self.r;
+        let s = self.buffered_state;
+        let len = s.rx_buf.len();
+        let half_len = len / 2;
+
+        // The events of the stopped transfers are stale.
+        r.events_dma().rx().end().write_value(0);
+        r.events_dma().rx().ready().write_value(0);
+        r.intenset().write(|w| w.set_dmarxready(true));
+
+        // Both the counter and the ring buffer indices wrap at `len * 2`.
+        let received = self.get_rxdrdy_counter();
+        let reserved = s.rx_buf.end.load(Ordering::Relaxed);
+        let gap = (reserved + 2 * len - received) % (2 * len);
+        if gap == 0 {
+            // The last transfer was complete, so let the interrupt handler start the next one.
+            s.rx_started_count.store(0, Ordering::Relaxed);
+            s.rx_ended_count.store(0, Ordering::Relaxed);
+            s.rx_started.store(false, Ordering::Relaxed);
+            return;
+        }
+
+        let buf = s.rx_buf.buf.load(Ordering::Relaxed);
+        let offset = received % len;
+        let first = if gap > half_len { gap - half_len } else { gap };
+        r.dma().rx().ptr().write_value(buf.wrapping_add(offset) as u32);
+        r.dma().rx().maxcnt().write(|w| w.set_maxcnt(first as _));
+        r.tasks_dma().rx().start().write_value(1);
+        let mut started = 1u8;
+
+        if gap > half_len {
+            // The next half was reserved too: chain it as the interrupt handler does on RXSTARTED.
+            while r.events_dma().rx().ready().read() == 0 {}
+            r.events_dma().rx().ready().write_value(0);
+            let offset = (offset + first) % len;
+            r.dma().rx().ptr().write_value(buf.wrapping_add(offset) as u32);
+            r.dma().rx().maxcnt().write(|w| w.set_maxcnt(half_len as _));
+
+            let chn = s.rx_ppi_ch.load(Ordering::Relaxed);
+            ppi::regs().chenset().write(|w| w.0 = 1 << chn);
+            // The first transfer may have ended before the PPI channel was enabled.
+            if r.events_dma().rx().end().read() != 0 && ppi::regs().chen().read().ch(chn as _) {
+                ppi::regs().chenclr().write(|w| w.set_ch(chn as _, true));
+                r.tasks_dma().rx().start().write_value(1);
+            }
+            started = 2;
+        }
+
+        s.rx_started_count.store(started, Ordering::Relaxed);
+        s.rx_ended_count.store(0, Ordering::Relaxed);
+        s.rx_started.store(true, Ordering::Relaxed);
+    }
+
     /// we are ready to read if there is data in the buffer
     fn read_ready(&self) -> Result<bool, Error> {
         let state = self.buffered_state;
@@ -878,6 +1026,7 @@ impl core::fmt::Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match *self {
             Error::Overrun => write!(f, "Buffer Overrun"),
+            Error::Busy => write!(f, "Transmission in progress"),
         }
     }
 }
@@ -890,6 +1039,7 @@ mod _embedded_io {
         fn kind(&self) -> embedded_io_async::ErrorKind {
             match *self {
                 Error::Overrun => embedded_io_async::ErrorKind::OutOfMemory,
+                Error::Busy => embedded_io_async::ErrorKind::Other,
             }
         }
     }
@@ -31,7 +31,9 @@ pub use pac::uarte::vals::{Baudrate, ConfigParity as Parity};
 
 use crate::gpio::{AnyPin, Pin as GpioPin};
 use crate::interrupt::typelevel::Interrupt;
-use crate::uarte::{Config, Instance as UarteInstance, configure, configure_rx_pins, configure_tx_pins, drop_tx_rx};
+use crate::uarte::{
+    Config, Instance as UarteInstance, configure, configure_rx_pins, configure_tx_pins, drop_tx_rx, has_rtscts,
+};
 use crate::{EASY_DMA_SIZE, interrupt, pac};
 
 pub(crate) struct State {
@@ -47,12 +49,15 @@ pub(crate) struct State {
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[non_exhaustive]
 pub enum Error {
-    // No errors for now
+    /// Data is still waiting to be transmitted.
+    Busy,
 }
 
 impl core::fmt::Display for Error {
-    fn fmt(&self, _f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        match *self {}
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Error::Busy => write!(f, "Transmission in progress"),
+        }
     }
 }
 
@@ -70,6 +75,24 @@ impl State {
     }
 }
 
+fn check_idle<U: UarteInstance>() -> Result<(), Error> {
+    if U::buffered_state().tx_buf.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Busy)
+    }
+}
+
+/// Stop the transmitter, once the data has been transmitted.
+fn stop_tx<U: UarteInstance>() {
+    // ENDTX comes once the last byte is handed to the transmitter, which may still be shifting it
+    // out. TXSTOPPED waits for the transmitter to come to a stop.
+    let r = U::regs();
+    r.events_txstopped().write_value(0);
+    r.tasks_dma().tx().stop().write_value(1);
+    while r.events_txstopped().read() == 0 {}
+}
+
 /// Interrupt handler.
 pub struct InterruptHandler<U: UarteInstance> {
     _phantom: PhantomData<U>,
@@ -119,7 +142,8 @@ impl<U: UarteInstance> interrupt::typelevel::Handler<U::Interrupt> for Interrupt
                 r.dma().rx().ptr().write_value(ptr as u32);
                 r.dma().rx().maxcnt().write(|w| w.set_maxcnt(len as _));
 
-                // manually start
+                // manually start, RXTO then tells when this transfer has stopped
+                r.events_rxto().write_value(0);
                 r.tasks_dma().rx().start().write_value(1);
             }
         }
@@ -234,9 +258,53 @@ impl<'d, U: UarteInstance> BufferedUarte<'d, U> {
     }
 
     /// Adjust the baud rate to the provided value.
-    pub fn set_baudrate(&mut self, baudrate: Baudrate) {
+    ///
+    /// The transmitter and the receiver are stopped while the rate changes, and the receiver is
+    /// restarted afterwards. Received bytes are kept, but a byte arriving during the change may be
+    /// corrupted, so switch when the other side is known to be silent.
+    ///
+    /// Returns [`Error::Busy`] if data is still waiting to be transmitted, see [`flush`](Self::flush).
+    pub fn set_baudrate(&mut self, baudrate: Baudrate) -> Result<(), Error> {
+        self.reconfigure(|r| r.baudrate().write(|w| w.set_baudrate(baudrate)))
+    }
+
+    /// Enable or disable hardware flow control (RTS/CTS).
+    ///
+    /// The peripheral is stopped and restarted like in [`set_baudrate`](Self::set_baudrate).
+    ///
+    /// Returns [`Error::Busy`] if data is still waiting to be transmitted, see [`flush`](Self::flush).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `enabled` is true but the driver was created without RTS and CTS pins.
+    pub fn set_hwfc(&mut self, enabled: bool) -> Result<(), Error> {
+        if enabled && !has_rtscts(U::regs()) {
+            panic!("Hardware flow control requires RTS and CTS pins.");
+        }
+        self.reconfigure(|r| r.config().modify(|w| w.set_hwfc(enabled)))
+    }
+
+    fn reconfigure(&mut self, f: impl FnOnce(pac::uarte::Uarte)) -> Result<(), Error> {
+        check_idle::<U>()?;
         let r = U::regs();
-        r.baudrate().write(|w| w.set_baudrate(baudrate));
+
+        // The interrupt handler restarts the receiver on ENDRX, so keep it from running until
+        // the new configuration is in place.
+        U::Interrupt::disable();
+        stop_tx::<U>();
+
+        // Stopping the receiver moves the bytes received so far to the buffer and raises ENDRX,
+        // which the interrupt handler then processes like a frame timeout. If a frame timeout
+        // already stopped it, RXTO is already set.
+        r.tasks_dma().rx().stop().write_value(1);
+        while r.events_rxto().read() == 0 {}
+
+        f(r);
+
+        compiler_fence(Ordering::SeqCst);
+        U::Interrupt::pend();
+        unsafe { U::Interrupt::enable() };
+        Ok(())
     }
 
     /// Split the UART in reader and writer parts.
@@ -613,7 +681,9 @@ mod _embedded_io {
 
     impl embedded_io_async::Error for Error {
         fn kind(&self) -> embedded_io_async::ErrorKind {
-            match *self {}
+            match *self {
+                Error::Busy => embedded_io_async::ErrorKind::Other,
+            }
         }
     }
 
@@ -0,0 +1,318 @@
+//! Unified error type for the bus drivers.
+
+use core::fmt;
+
+use embassy_embedded_hal::shared_bus::{BusOperation, I2cDeviceError, LabeledError, SpiDeviceError};
+
+use crate::{spim, twim, uarte};
+
+/// Identity of a bus peripheral instance, such as `TWIM1`.
+///
+/// Obtained with `bus_id()` on the [`Twim`](twim::Twim), [`Spim`](spim::Spim) and
+/// [`Uarte`](uarte::Uarte) drivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusId(&'static str);
+
+impl BusId {
+    pub(crate) const fn new(name: &'static str) -> Self {
+        Self(name)
+    }
+
+    /// Name of the peripheral instance, as in the product specification.
+    pub const fn name(&self) -> &'static str {
+        self.0
+    }
+
+    /// Create a label for a device on this bus, to use with
+    /// [`Labeled`](embassy_embedded_hal::shared_bus::labeled::Labeled).
+    pub const fn device(self, name: &'static str) -> DeviceLabel {
+        DeviceLabel { bus: self, name }
+    }
+}
+
+impl fmt::Display for BusId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for BusId {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{=str}", self.0)
+    }
+}
+
+/// Label of a device on a bus, identifying both the bus peripheral and the device.
+///
+/// Use it with [`Labeled`](embassy_embedded_hal::shared_bus::labeled::Labeled) so that errors
+/// converted to [`BusError`] carry both:
+///
+/// ```rust,ignore
+/// let imu_label = twim.bus_id().device("imu");
+/// let i2c_bus = Mutex::new(twim);
+/// let imu = Labeled::new(I2cDevice::new(&i2c_bus), imu_label);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceLabel {
+    /// The bus peripheral the device is attached to.
+    pub bus: BusId,
+    /// Name of the device.
+    pub name: &'static str,
+}
+
+impl fmt::Display for DeviceLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.bus, self.name)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for DeviceLabel {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{}/{=str}", self.bus, self.name)
+    }
+}
+
+/// Kind of a [`BusError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum BusErrorKind {
+    /// SPIM error.
+    Spim(spim::Error),
+    /// TWIM error.
+    Twim(twim::Error),
+    /// UARTE error.
+    Uarte(uarte::Error),
+    /// Configuring the bus failed.
+    Config,
+    /// Setting the chip select pin failed.
+    ChipSelect,
+    /// Other error of a shared bus device.
+    Other,
+}
+
+impl fmt::Display for BusErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spim(e) => write!(f, "{:?}", e),
+            Self::Twim(e) => write!(f, "{:?}", e),
+            Self::Uarte(e) => write!(f, "{}", e),
+            Self::Config => f.write_str("Config"),
+            Self::ChipSelect => f.write_str("ChipSelect"),
+            Self::Other => f.write_str("Other"),
+        }
+    }
+}
+
+/// Error of any of the bus drivers, with context on where it happened.
+///
+/// All bus driver errors convert into `BusError`, so application code can use it as the error type
+/// when talking to several buses. Errors of [`Labeled`](embassy_embedded_hal::shared_bus::labeled::Labeled)
+/// devices also carry the device label and the failed operation, and the bus peripheral when
+/// labeled with a [`DeviceLabel`]. These are displayed like `TWIM1/imu: AddressNack during write`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BusError {
+    /// The bus peripheral the error happened on, if known.
+    pub bus: Option<BusId>,
+    /// The device the error happened on, if known.
+    pub device: Option<&'static str>,
+    /// The operation that failed, if known.
+    pub operation: Option<BusOperation>,
+    /// The error.
+    pub kind: BusErrorKind,
+}
+
+impl BusError {
+    /// Create a bus error without context.
+    pub const fn new(kind: BusErrorKind) -> Self {
+        Self {
+            bus: None,
+            device: None,
+            operation: None,
+            kind,
+        }
+    }
+
+    /// Set the bus peripheral the error happened on.
+    pub const fn with_bus(mut self, bus: BusId) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.bus, self.device) {
+            (Some(bus), Some(device)) => write!(f, "{}/{}: ", bus, device)?,
+            (Some(bus), None) => write!(f, "{}: ", bus)?,
+            (None, Some(device)) => write!(f, "{}: ", device)?,
+            (None, None) => {}
+        }
+        write!(f, "{}", self.kind)?;
+        if let Some(operation) = self.operation {
+            write!(f, " during {}", operation)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for BusError {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match (self.bus, self.device) {
+            (Some(bus), Some(device)) => defmt::write!(f, "{}/{=str}: ", bus, device),
+            (Some(bus), None) => defmt::write!(f, "{}: ", bus),
+            (None, Some(device)) => defmt::write!(f, "{=str}: ", device),
+            (None, None) => {}
+        }
+        match self.kind {
+            BusErrorKind::Spim(e) => defmt::write!(f, "{}", e),
+            BusErrorKind::Twim(e) => defmt::write!(f, "{}", e),
+            BusErrorKind::Uarte(e) => defmt::write!(f, "{}", e),
+            kind => defmt::write!(f, "{}", kind),
+        }
+        if let Some(operation) = self.operation {
+            defmt::write!(f, " during {}", operation);
+        }
+    }
+}
+
+impl core::error::Error for BusError {}
+
+impl From<spim::Error> for BusError {
+    fn from(e: spim::Error) -> Self {
+        Self::new(BusErrorKind::Spim(e))
+    }
+}
+
+impl From<twim::Error> for BusError {
+    fn from(e: twim::Error) -> Self {
+        Self::new(BusErrorKind::Twim(e))
+    }
+}
+
+impl From<uarte::Error> for BusError {
+    fn from(e: uarte::Error) -> Self {
+        Self::new(BusErrorKind::Uarte(e))
+    }
+}
+
+impl From<I2cDeviceError<twim::Error>> for BusError {
+    fn from(e: I2cDeviceError<twim::Error>) -> Self {
+        match e {
+            I2cDeviceError::I2c(e) => e.into(),
+            I2cDeviceError::Config => Self::new(BusErrorKind::Config),
+        }
+    }
+}
+
+impl<CS> From<SpiDeviceError<spim::Error, CS>> for BusError {
+    fn from(e: SpiDeviceError<spim::Error, CS>) -> Self {
+        match e {
+            SpiDeviceError::Spi(e) => e.into(),
+            SpiDeviceError::Cs(_) => Self::new(BusErrorKind::ChipSelect),
+            SpiDeviceError::Config => Self::new(BusErrorKind::Config),
+            _ => Self::new(BusErrorKind::Other),
+        }
+    }
+}
+
+impl<E: Into<BusError>> From<LabeledError<E, DeviceLabel>> for BusError {
+    fn from(e: LabeledError<E, DeviceLabel>) -> Self {
+        let mut err: BusError = e.error.into();
+        err.bus = Some(e.label.bus);
+        err.device = Some(e.label.name);
+        err.operation = Some(e.operation);
+        err
+    }
+}
+
+impl<E: Into<BusError>> From<LabeledError<E, &'static str>> for BusError {
+    fn from(e: LabeledError<E, &'static str>) -> Self {
+        let mut err: BusError = e.error.into();
+        err.device = Some(e.label);
+        err.operation = Some(e.operation);
+        err
+    }
+}
+
+impl embedded_hal_1::i2c::Error for BusError {
+    fn kind(&self) -> embedded_hal_1::i2c::ErrorKind {
+        match self.kind {
+            BusErrorKind::Twim(e) => embedded_hal_1::i2c::Error::kind(&e),
+            _ => embedded_hal_1::i2c::ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_hal_1::spi::Error for BusError {
+    fn kind(&self) -> embedded_hal_1::spi::ErrorKind {
+        match self.kind {
+            BusErrorKind::Spim(e) => embedded_hal_1::spi::Error::kind(&e),
+            BusErrorKind::ChipSelect => embedded_hal_1::spi::ErrorKind::ChipSelectFault,
+            _ => embedded_hal_1::spi::ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_io_async::Error for BusError {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        match self.kind {
+            BusErrorKind::Uarte(e) => embedded_io_async::Error::kind(&e),
+            _ => embedded_io_async::ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use super::*;
+
+    const TWIM1: BusId = BusId::new("TWIM1");
+
+    #[test]
+    fn display_labeled_twim_error() {
+        let err: BusError = LabeledError {
+            label: TWIM1.device("imu"),
+            operation: BusOperation::Write,
+            error: I2cDeviceError::I2c(twim::Error::AddressNack),
+        }
+        .into();
+
+        assert_eq!(Some(TWIM1), err.bus);
+        assert_eq!(Some("imu"), err.device);
+        assert_eq!("TWIM1/imu: AddressNack during write", format!("{}", err));
+    }
+
+    #[test]
+    fn display_labeled_spim_error() {
+        let err: BusError = LabeledError {
+            label: "flash",
+            operation: BusOperation::Transaction,
+            error: SpiDeviceError::<_, ()>::Cs(()),
+        }
+        .into();
+
+        assert_eq!("flash: ChipSelect during transaction", format!("{}", err));
+        assert_eq!(
+            embedded_hal_1::spi::ErrorKind::ChipSelectFault,
+            embedded_hal_1::spi::Error::kind(&err)
+        );
+    }
+
+    #[test]
+    fn display_unlabeled_errors() {
+        let err = BusError::from(uarte::Error::Framing);
+        assert_eq!("Framing", format!("{}", err));
+
+        let err = BusError::from(spim::Error::BufferNotInRAM).with_bus(BusId::new("SPIM3"));
+        assert_eq!("SPIM3: BufferNotInRAM", format!("{}", err));
+    }
+}
@@ -34,6 +34,9 @@ embassy_hal_internal::peripherals! {
     // SAADC
     SAADC,
 
+    // COMP
+    COMP,
+
     // PWM
     PWM0,
 
@@ -163,6 +166,8 @@ impl_pdm!(PDM, PDM, PDM);
 
 impl_qdec!(QDEC, QDEC, QDEC);
 
+impl_comp!(COMP, COMP, COMP);
+
 impl_rng!(RNG, RNG, RNG);
 
 impl_timer!(TIMER0, TIMER0, TIMER0);
@@ -40,6 +40,9 @@ embassy_hal_internal::peripherals! {
     // SAADC
     SAADC,
 
+    // COMP
+    COMP,
+
     // PWM
     PWM0,
     PWM1,
@@ -196,6 +199,9 @@ impl_pdm!(PDM, PDM, PDM);
 
 impl_qdec!(QDEC, QDEC, QDEC);
 
+impl_comp!(COMP, COMP, COMP_LPCOMP);
+impl_lpcomp!(COMP, LPCOMP, COMP_LPCOMP);
+
 impl_rng!(RNG, RNG, RNG);
 
 impl_timer!(TIMER0, TIMER0, TIMER0);
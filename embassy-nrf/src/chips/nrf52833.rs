@@ -41,6 +41,9 @@ embassy_hal_internal::peripherals! {
     // SAADC
     SAADC,
 
+    // COMP
+    COMP,
+
     // PWM
     PWM0,
     PWM1,
@@ -195,7 +198,7 @@ impl_uarte!(UARTE1, UARTE1, UARTE1);
 impl_spim!(TWISPI0, SPIM0, TWISPI0);
 impl_spim!(TWISPI1, SPIM1, TWISPI1);
 impl_spim!(SPI2, SPIM2, SPI2);
-impl_spim!(SPI3, SPIM3, SPIM3);
+impl_spim!(SPI3, SPIM3, SPIM3, dcx);
 
 impl_spis!(TWISPI0, SPIS0, TWISPI0);
 impl_spis!(TWISPI1, SPIS1, TWISPI1);
@@ -216,6 +219,9 @@ impl_pdm!(PDM, PDM, PDM);
 
 impl_qdec!(QDEC, QDEC, QDEC);
 
+impl_comp!(COMP, COMP, COMP_LPCOMP);
+impl_lpcomp!(COMP, LPCOMP, COMP_LPCOMP);
+
 impl_rng!(RNG, RNG, RNG);
 
 impl_timer!(TIMER0, TIMER0, TIMER0);
@@ -193,6 +193,9 @@ embassy_hal_internal::peripherals! {
     // SAADC
     SAADC,
 
+    // COMP
+    COMP,
+
     // PWM
     PWM0,
     PWM1,
@@ -350,7 +353,7 @@ impl_spim!(SERIAL0, SPIM0, SERIAL0);
 impl_spim!(SERIAL1, SPIM1, SERIAL1);
 impl_spim!(SERIAL2, SPIM2, SERIAL2);
 impl_spim!(SERIAL3, SPIM3, SERIAL3);
-impl_spim!(SPIM4, SPIM4, SPIM4);
+impl_spim!(SPIM4, SPIM4, SPIM4, dcx);
 
 impl_spis!(SERIAL0, SPIS0, SERIAL0);
 impl_spis!(SERIAL1, SPIS1, SERIAL1);
@@ -390,6 +393,9 @@ impl_pdm!(PDM0, PDM0, PDM0);
 impl_qdec!(QDEC0, QDEC0, QDEC0);
 impl_qdec!(QDEC1, QDEC1, QDEC1);
 
+impl_comp!(COMP, COMP, COMP_LPCOMP);
+impl_lpcomp!(COMP, LPCOMP, COMP_LPCOMP);
+
 #[cfg(feature = "lfxo-pins-as-gpio")]
 impl_pin!(P0_00, 0, 0);
 #[cfg(feature = "lfxo-pins-as-gpio")]
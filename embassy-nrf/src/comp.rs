@@ -0,0 +1,348 @@
+//! Comparator (COMP) driver.
+//!
+//! The comparator compares an analog input either to thresholds derived from a reference
+//! voltage (single-ended mode), or to a second analog input (differential mode), for example to
+//! detect a battery dropping below a level, or the zero crossings of a signal.
+//!
+//! Its events wake the CPU from System ON sleep: awaiting [`Comp::wait_for_down`] lets the
+//! executor sleep until the input crosses the threshold, with the comparator as the only
+//! running analog peripheral.
+//!
+//! On chips that have LPCOMP, it shares its registers and interrupt with COMP, so both drivers
+//! take the `COMP` peripheral, see [`lpcomp`](crate::lpcomp).
+
+#![macro_use]
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy_hal_internal::drop::OnDrop;
+use embassy_hal_internal::{Peri, PeripheralType};
+use embassy_sync::waitqueue::AtomicWaker;
+
+use crate::interrupt::typelevel::Interrupt;
+use crate::pac::comp::vals;
+use crate::saadc::{AnyInput, Input};
+use crate::{interrupt, pac};
+
+/// Interrupt handler.
+pub struct InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        let r = T::regs();
+        // Leave the events set, for the waiting task to see them.
+        if r.events_up().read() != 0 {
+            r.intenclr().write(|w| w.set_up(true));
+        }
+        if r.events_down().read() != 0 {
+            r.intenclr().write(|w| w.set_down(true));
+        }
+        if r.events_cross().read() != 0 {
+            r.intenclr().write(|w| w.set_cross(true));
+        }
+        T::state().waker.wake();
+    }
+}
+
+/// Reference voltage of single-ended mode.
+pub enum Reference<'d> {
+    /// Internal 1.2 V reference.
+    Internal1V2,
+    /// Internal 1.8 V reference. VDD must be at least 2.0 V.
+    Internal1V8,
+    /// Internal 2.4 V reference. VDD must be at least 2.6 V.
+    Internal2V4,
+    /// The supply voltage VDD.
+    Vdd,
+    /// An external reference, on an analog input pin.
+    External(AnyInput<'d>),
+}
+
+/// Thresholds of single-ended mode, in 64ths of the reference voltage.
+///
+/// The output goes up when the input rises above `(up + 1) / 64` of the reference, and down when
+/// it falls below `(down + 1) / 64` of it. Setting `up` above `down` adds hysteresis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Thresholds {
+    /// Threshold of the downward crossings, `0..=63`.
+    pub down: u8,
+    /// Threshold of the upward crossings, `0..=63`.
+    pub up: u8,
+}
+
+/// Speed and power mode of the comparator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Speed {
+    /// Low power, slow response.
+    Low,
+    /// Normal power and response time.
+    Normal,
+    /// High power, fast response.
+    High,
+}
+
+/// Comparator config.
+#[non_exhaustive]
+pub struct Config {
+    /// Speed and power mode.
+    pub speed: Speed,
+    /// Enable the 50 mV hysteresis of differential mode.
+    ///
+    /// Single-ended mode gets its hysteresis from the [`Thresholds`] instead.
+    pub hysteresis: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            speed: Speed::Low,
+            hysteresis: false,
+        }
+    }
+}
+
+/// Output of the comparator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Output {
+    /// The input is below the threshold, or the negative input.
+    Below,
+    /// The input is above the threshold, or the negative input.
+    Above,
+}
+
+/// Comparator driver.
+pub struct Comp<'d> {
+    r: pac::comp::Comp,
+    state: &'static State,
+    _phantom: PhantomData<&'d ()>,
+}
+
+impl<'d> Comp<'d> {
+    /// Create a comparator in single-ended mode, comparing `input` to thresholds of `reference`.
+    pub fn new_single_ended<T: Instance>(
+        _comp: Peri<'d, T>,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        input: impl Input + 'd,
+        reference: Reference<'d>,
+        thresholds: Thresholds,
+        config: Config,
+    ) -> Self {
+        assert!(thresholds.down < 64 && thresholds.up < 64);
+
+        let r = T::regs();
+        r.psel()
+            .write(|w| w.set_psel(vals::PselPsel::from_bits(analog_input(&input))));
+        r.refsel().write(|w| {
+            w.set_refsel(match reference {
+                Reference::Internal1V2 => vals::Refsel::INT1V2,
+                Reference::Internal1V8 => vals::Refsel::INT1V8,
+                Reference::Internal2V4 => vals::Refsel::INT2V4,
+                Reference::Vdd => vals::Refsel::VDD,
+                Reference::External(_) => vals::Refsel::AREF,
+            })
+        });
+        if let Reference::External(pin) = &reference {
+            r.extrefsel()
+                .write(|w| w.set_extrefsel(vals::Extrefsel::from_bits(analog_input(pin))));
+        }
+        r.th().write(|w| {
+            w.set_thdown(thresholds.down);
+            w.set_thup(thresholds.up);
+        });
+        r.hyst().write(|w| w.set_hyst(vals::Hyst::NO_HYST));
+
+        Self::new_inner::<T>(vals::Main::SE, config)
+    }
+
+    /// Create a comparator in differential mode, comparing `positive` to `negative`.
+    pub fn new_differential<T: Instance>(
+        _comp: Peri<'d, T>,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        positive: impl Input + 'd,
+        negative: impl Input + 'd,
+        config: Config,
+    ) -> Self {
+        let r = T::regs();
+        r.psel()
+            .write(|w| w.set_psel(vals::PselPsel::from_bits(analog_input(&positive))));
+        r.extrefsel()
+            .write(|w| w.set_extrefsel(vals::Extrefsel::from_bits(analog_input(&negative))));
+        r.hyst().write(|w| {
+            w.set_hyst(match config.hysteresis {
+                true => vals::Hyst::HYST50M_V,
+                false => vals::Hyst::NO_HYST,
+            })
+        });
+
+        Self::new_inner::<T>(vals::Main::DIFF, config)
+    }
+
+    fn new_inner<T: Instance>(main: vals::Main, config: Config) -> Self {
+        let r = T::regs();
+        r.mode().write(|w| {
+            w.set_main(main);
+            w.set_sp(match config.speed {
+                Speed::Low => vals::Sp::LOW,
+                Speed::Normal => vals::Sp::NORMAL,
+                Speed::High => vals::Sp::HIGH,
+            });
+        });
+
+        r.intenclr().write(|w| {
+            w.set_ready(true);
+            w.set_down(true);
+            w.set_up(true);
+            w.set_cross(true);
+        });
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+
+        r.enable().write(|w| w.set_enable(vals::Enable::ENABLED));
+        r.events_ready().write_value(0);
+        r.tasks_start().write_value(1);
+        // The output is valid after the startup time, a few microseconds.
+        while r.events_ready().read() == 0 {}
+        r.events_ready().write_value(0);
+
+        Self {
+            r,
+            state: T::state(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sample the output of the comparator.
+    pub fn sample(&mut self) -> Output {
+        self.r.tasks_sample().write_value(1);
+        match self.r.result().read().result() {
+            vals::Result::ABOVE => Output::Above,
+            _ => Output::Below,
+        }
+    }
+
+    /// Wait for the input to rise above the threshold.
+    ///
+    /// This only completes on a crossing after the call, use [`sample`](Self::sample) to know
+    /// whether the input is already above the threshold.
+    pub async fn wait_for_up(&mut self) {
+        self.wait(Event::Up).await
+    }
+
+    /// Wait for the input to fall below the threshold.
+    ///
+    /// This only completes on a crossing after the call, use [`sample`](Self::sample) to know
+    /// whether the input is already below the threshold.
+    pub async fn wait_for_down(&mut self) {
+        self.wait(Event::Down).await
+    }
+
+    /// Wait for the input to cross the threshold in either direction, and return the new output.
+    pub async fn wait_for_cross(&mut self) -> Output {
+        self.wait(Event::Cross).await;
+        self.sample()
+    }
+
+    async fn wait(&mut self, event: Event) {
+        let r = self.r;
+        let reg = match event {
+            Event::Up => r.events_up(),
+            Event::Down => r.events_down(),
+            Event::Cross => r.events_cross(),
+        };
+        let set_int = |w: &mut pac::comp::regs::Int| match event {
+            Event::Up => w.set_up(true),
+            Event::Down => w.set_down(true),
+            Event::Cross => w.set_cross(true),
+        };
+
+        reg.write_value(0);
+        r.intenset().write(set_int);
+        let _on_drop = OnDrop::new(|| r.intenclr().write(set_int));
+
+        poll_fn(|cx| {
+            self.state.waker.register(cx.waker());
+            if reg.read() != 0 {
+                reg.write_value(0);
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<'d> Drop for Comp<'d> {
+    fn drop(&mut self) {
+        self.r.tasks_stop().write_value(1);
+        self.r.intenclr().write(|w| {
+            w.set_down(true);
+            w.set_up(true);
+            w.set_cross(true);
+        });
+        self.r.enable().write(|w| w.set_enable(vals::Enable::DISABLED));
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Event {
+    Up,
+    Down,
+    Cross,
+}
+
+/// Index of the analog input pin `input`, as selected by PSEL and EXTREFSEL.
+pub(crate) fn analog_input(input: &impl Input) -> u8 {
+    let bits = input.channel().to_bits();
+    // SAADC numbers the analog inputs from 1, and has internal inputs the comparators lack.
+    assert!((1..=8).contains(&bits), "the comparators only take analog input pins");
+    bits - 1
+}
+
+pub(crate) struct State {
+    waker: AtomicWaker,
+}
+
+impl State {
+    pub(crate) const fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+pub(crate) trait SealedInstance {
+    fn regs() -> pac::comp::Comp;
+    fn state() -> &'static State;
+}
+
+/// COMP peripheral instance.
+#[allow(private_bounds)]
+pub trait Instance: SealedInstance + PeripheralType + 'static + Send {
+    /// Interrupt for this peripheral.
+    type Interrupt: interrupt::typelevel::Interrupt;
+}
+
+macro_rules! impl_comp {
+    ($type:ident, $pac_type:ident, $irq:ident) => {
+        impl crate::comp::SealedInstance for peripherals::$type {
+            fn regs() -> pac::comp::Comp {
+                pac::$pac_type
+            }
+            fn state() -> &'static crate::comp::State {
+                static STATE: crate::comp::State = crate::comp::State::new();
+                &STATE
+            }
+        }
+        impl crate::comp::Instance for peripherals::$type {
+            type Interrupt = crate::interrupt::typelevel::$irq;
+        }
+    };
+}
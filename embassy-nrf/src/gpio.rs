@@ -3,6 +3,8 @@
 
 use core::convert::Infallible;
 use core::hint::unreachable_unchecked;
+#[cfg(not(feature = "_nrf51"))]
+use core::sync::atomic::{AtomicU8, Ordering};
 
 use cfg_if::cfg_if;
 use embassy_hal_internal::{Peri, PeripheralType, impl_peripheral};
@@ -73,6 +75,21 @@ impl<'d> Input<'d> {
     pub fn get_level(&self) -> Level {
         self.pin.get_level()
     }
+
+    /// Arm the pin to wake the chip from System OFF when it reaches `level`.
+    ///
+    /// This sets the SENSE setting of the pin, which is reset when the input is dropped, so keep it
+    /// alive or [`persist`](Self::persist) it before entering System OFF. After the wakeup,
+    /// [`wakeup_source`] tells which pin woke the chip.
+    #[cfg(not(feature = "_nrf51"))]
+    pub fn enable_sense_wakeup(&mut self, level: Level) {
+        self.pin.pin.conf().modify(|w| {
+            w.set_sense(match level {
+                Level::Low => vals::Sense::LOW,
+                Level::High => vals::Sense::HIGH,
+            })
+        });
+    }
 }
 
 impl Input<'static> {
@@ -271,6 +288,18 @@ impl<'d> Output<'d> {
     pub fn get_output_level(&self) -> Level {
         self.pin.get_output_level()
     }
+
+    /// Change the drive strength of the output, keeping its level.
+    #[inline]
+    pub fn set_drive(&mut self, drive: OutputDrive) {
+        self.pin.set_drive(drive)
+    }
+
+    /// Get the drive strength of the output.
+    #[inline]
+    pub fn get_drive(&self) -> OutputDrive {
+        self.pin.get_drive()
+    }
 }
 
 impl Output<'static> {
@@ -282,6 +311,101 @@ impl Output<'static> {
     }
 }
 
+/// GPIO open-drain driver.
+///
+/// The pin drives the line low when set low, and leaves it floating when set high, so that
+/// several devices can share the line, as for an interrupt line or a 1-Wire bus. The input
+/// buffer stays connected, to read the actual level of the line.
+pub struct OpenDrain<'d> {
+    pub(crate) pin: Flex<'d>,
+}
+
+impl<'d> OpenDrain<'d> {
+    /// Create GPIO open-drain driver for a [Pin] with the provided [Level] and [Pull] configuration.
+    ///
+    /// With [`Pull::Up`], the internal pull-up holds the line high when no device drives it low.
+    #[inline]
+    pub fn new(pin: Peri<'d, impl Pin>, initial_output: Level, pull: Pull) -> Self {
+        let mut pin = Flex::new(pin);
+        pin.set_level(initial_output);
+        pin.set_as_input_output(pull, OutputDrive::Standard0Disconnect1);
+
+        Self { pin }
+    }
+
+    /// Release the line, letting it float high.
+    #[inline]
+    pub fn set_high(&mut self) {
+        self.pin.set_high()
+    }
+
+    /// Drive the line low.
+    #[inline]
+    pub fn set_low(&mut self) {
+        self.pin.set_low()
+    }
+
+    /// Toggle the output level.
+    #[inline]
+    pub fn toggle(&mut self) {
+        self.pin.toggle()
+    }
+
+    /// Set the output level.
+    #[inline]
+    pub fn set_level(&mut self, level: Level) {
+        self.pin.set_level(level)
+    }
+
+    /// Get whether the output level is set to high.
+    #[inline]
+    pub fn is_set_high(&self) -> bool {
+        self.pin.is_set_high()
+    }
+
+    /// Get whether the output level is set to low.
+    #[inline]
+    pub fn is_set_low(&self) -> bool {
+        self.pin.is_set_low()
+    }
+
+    /// Get the current output level.
+    #[inline]
+    pub fn get_output_level(&self) -> Level {
+        self.pin.get_output_level()
+    }
+
+    /// Get whether the line is high.
+    ///
+    /// This reads the input buffer, so it is low when another device drives the line low,
+    /// even if the output is set high.
+    #[inline]
+    pub fn is_high(&self) -> bool {
+        self.pin.is_high()
+    }
+
+    /// Get whether the line is low.
+    #[inline]
+    pub fn is_low(&self) -> bool {
+        self.pin.is_low()
+    }
+
+    /// Get the current line level.
+    #[inline]
+    pub fn get_level(&self) -> Level {
+        self.pin.get_level()
+    }
+}
+
+impl OpenDrain<'static> {
+    /// Persist the pin's configuration for the rest of the program's lifetime. This method should
+    /// be preferred over [`core::mem::forget()`] because the `'static` bound prevents accidental
+    /// reuse of the underlying peripheral.
+    pub fn persist(self) {
+        self.pin.persist()
+    }
+}
+
 pub(crate) fn convert_drive(w: &mut pac::gpio::regs::PinCnf, drive: OutputDrive) {
     #[cfg(not(feature = "_nrf54l"))]
     {
@@ -314,7 +438,43 @@ pub(crate) fn convert_drive(w: &mut pac::gpio::regs::PinCnf, drive: OutputDrive)
     }
 }
 
-fn convert_pull(pull: Pull) -> vals::Pull {
+pub(crate) fn read_drive(r: pac::gpio::regs::PinCnf) -> OutputDrive {
+    #[cfg(not(feature = "_nrf54l"))]
+    {
+        match r.drive() {
+            vals::Drive::S0S1 => OutputDrive::Standard,
+            vals::Drive::H0S1 => OutputDrive::HighDrive0Standard1,
+            vals::Drive::S0H1 => OutputDrive::Standard0HighDrive1,
+            vals::Drive::H0H1 => OutputDrive::HighDrive,
+            vals::Drive::D0S1 => OutputDrive::Disconnect0Standard1,
+            vals::Drive::D0H1 => OutputDrive::Disconnect0HighDrive1,
+            vals::Drive::S0D1 => OutputDrive::Standard0Disconnect1,
+            vals::Drive::H0D1 => OutputDrive::HighDrive0Disconnect1,
+            // Extra high drive, which this driver doesn't configure.
+            #[cfg(feature = "_nrf5340")]
+            _ => OutputDrive::HighDrive,
+        }
+    }
+
+    #[cfg(feature = "_nrf54l")]
+    {
+        fn convert(d: vals::Drive) -> LevelDrive {
+            match d {
+                vals::Drive::D => LevelDrive::Disconnect,
+                vals::Drive::S => LevelDrive::Standard,
+                vals::Drive::H => LevelDrive::High,
+                vals::Drive::E => LevelDrive::ExtraHigh,
+            }
+        }
+
+        OutputDrive {
+            low: convert(r.drive0()),
+            high: convert(r.drive1()),
+        }
+    }
+}
+
+pub(crate) fn convert_pull(pull: Pull) -> vals::Pull {
     match pull {
         Pull::None => vals::Pull::DISABLED,
         Pull::Up => vals::Pull::PULLUP,
@@ -463,6 +623,24 @@ impl<'d> Flex<'d> {
     pub fn get_output_level(&self) -> Level {
         self.is_set_high().into()
     }
+
+    /// Change the drive strength of the pin, keeping its mode and output level.
+    ///
+    /// This is a single write of the pin configuration, so the pin isn't released in between,
+    /// unlike when re-creating the driver.
+    #[inline]
+    pub fn set_drive(&mut self, drive: OutputDrive) {
+        self.pin.conf().modify(|w| convert_drive(w, drive));
+    }
+
+    /// Get the drive strength of the pin, as configured in PIN_CNF.
+    ///
+    /// On nRF5340, the extra high drive, which can't be set with [OutputDrive], reads as
+    /// [`OutputDrive::HighDrive`].
+    #[inline]
+    pub fn get_drive(&self) -> OutputDrive {
+        read_drive(self.pin.conf().read())
+    }
 }
 
 impl Flex<'static> {
@@ -480,6 +658,181 @@ impl<'d> Drop for Flex<'d> {
     }
 }
 
+/// GPIO input driver reading a group of pins of the same port at once.
+///
+/// The levels are read with a single access to the IN register, so they are sampled at the same
+/// time. Bit `n` of the masks is pin `n` of the port.
+pub struct PinGroupInput<'d, const N: usize> {
+    _pins: [Flex<'d>; N],
+    block: gpio::Gpio,
+    mask: u32,
+}
+
+impl<'d, const N: usize> PinGroupInput<'d, N> {
+    /// Create a GPIO input driver for `pins`, with the provided [Pull] configuration.
+    ///
+    /// Panics if the pins don't all belong to the same port.
+    pub fn new(pins: [Peri<'d, AnyPin>; N], pull: Pull) -> Self {
+        let (block, mask) = port_mask(&pins);
+        let pins = pins.map(|pin| {
+            let mut pin = Flex::new(pin);
+            pin.set_as_input(pull);
+            pin
+        });
+
+        Self {
+            _pins: pins,
+            block,
+            mask,
+        }
+    }
+
+    /// Get the mask of the pins of the group.
+    #[inline]
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// Get the input levels of the pins of the group, the bits of other pins being zero.
+    #[inline]
+    pub fn read_mask(&self) -> u32 {
+        self.block.in_().read().0 & self.mask
+    }
+}
+
+/// GPIO output driver setting a group of pins of the same port at once.
+///
+/// The levels are written with a single store to the OUT register, so the pins change at the same
+/// time, without affecting the other pins of the port. Bit `n` of the masks is pin `n` of the port.
+pub struct PinGroupOutput<'d, const N: usize> {
+    _pins: [Flex<'d>; N],
+    block: gpio::Gpio,
+    mask: u32,
+}
+
+impl<'d, const N: usize> PinGroupOutput<'d, N> {
+    /// Create a GPIO output driver for `pins`, with the provided initial levels and [OutputDrive]
+    /// configuration.
+    ///
+    /// Panics if the pins don't all belong to the same port.
+    pub fn new(pins: [Peri<'d, AnyPin>; N], initial_output: u32, drive: OutputDrive) -> Self {
+        let (block, mask) = port_mask(&pins);
+        let mut this = Self {
+            _pins: pins.map(Flex::new),
+            block,
+            mask,
+        };
+        this.write_mask(initial_output);
+        for pin in &mut this._pins {
+            pin.set_as_output(drive);
+        }
+        this
+    }
+
+    /// Get the mask of the pins of the group.
+    #[inline]
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// Set the output levels of the pins of the group, ignoring the bits of other pins.
+    ///
+    /// This takes a critical section, for the read-modify-write of the OUT register not to race
+    /// with changes of the other pins of the port.
+    #[inline]
+    pub fn write_mask(&mut self, value: u32) {
+        critical_section::with(|_| {
+            self.block
+                .out()
+                .modify(|w| w.0 = (w.0 & !self.mask) | (value & self.mask));
+        })
+    }
+
+    /// Get the output levels set for the pins of the group, the bits of other pins being zero.
+    #[inline]
+    pub fn get_output_mask(&self) -> u32 {
+        self.block.out().read().0 & self.mask
+    }
+}
+
+/// Index of a pin, `32 * port + pin`, as used by [`AnyPin::steal`].
+#[cfg(not(feature = "_nrf51"))]
+pub type PinIndex = u8;
+
+#[cfg(not(feature = "_nrf51"))]
+const NO_WAKEUP_SOURCE: u8 = u8::MAX;
+
+#[cfg(not(feature = "_nrf51"))]
+static WAKEUP_SOURCE: AtomicU8 = AtomicU8::new(NO_WAKEUP_SOURCE);
+
+#[cfg(not(feature = "_nrf51"))]
+const PORTS: &[u8] = &[
+    0,
+    #[cfg(feature = "_gpio-p1")]
+    1,
+    #[cfg(feature = "_gpio-p2")]
+    2,
+];
+
+/// Record the pin that woke the chip, before GPIOTE clears the LATCH registers.
+#[cfg(not(feature = "_nrf51"))]
+pub(crate) fn capture_wakeup_source() {
+    for &port in PORTS {
+        let block = AnyPin { pin_port: port * 32 }.block();
+        let latch = block.latch().read().0;
+        for pin in 0..32 {
+            if latch & (1 << pin) != 0 && block.pin_cnf(pin).read().sense() != vals::Sense::DISABLED {
+                WAKEUP_SOURCE.store(port * 32 + pin as u8, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+}
+
+/// Pin that woke the chip from System OFF, armed with [`Input::enable_sense_wakeup`] or
+/// [`power::system_off`](crate::power::system_off).
+///
+/// This is read from the LATCH registers by [`init`](crate::init), which records the pins that met
+/// their SENSE condition. Pin configurations, and so the LATCH registers, are kept through the
+/// reset that follows the wakeup. If several pins were latched, the lowest index is reported.
+#[cfg(not(feature = "_nrf51"))]
+pub fn wakeup_source() -> Option<PinIndex> {
+    match WAKEUP_SOURCE.load(Ordering::Relaxed) {
+        NO_WAKEUP_SOURCE => None,
+        pin => Some(pin),
+    }
+}
+
+/// Clear the LATCH registers, and the pin reported by [`wakeup_source`].
+///
+/// A pin left latched keeps the DETECT signal up, which wakes the chip right away from the next
+/// System OFF.
+#[cfg(not(feature = "_nrf51"))]
+pub fn clear_latch() {
+    for &port in PORTS {
+        AnyPin { pin_port: port * 32 }
+            .block()
+            .latch()
+            .write(|w| w.0 = 0xFFFF_FFFF);
+    }
+    WAKEUP_SOURCE.store(NO_WAKEUP_SOURCE, Ordering::Relaxed);
+}
+
+/// Port register block and mask of `pins`, which must all belong to the same port.
+fn port_mask(pins: &[Peri<'_, AnyPin>]) -> (gpio::Gpio, u32) {
+    assert!(!pins.is_empty(), "a pin group needs at least one pin");
+    let port = pins[0].pin_port() / 32;
+    let mut mask = 0;
+    for pin in pins {
+        assert!(
+            pin.pin_port() / 32 == port,
+            "all pins of a group must belong to the same port"
+        );
+        mask |= 1 << pin._pin();
+    }
+    (pins[0].block(), mask)
+}
+
 pub(crate) trait SealedPin {
     fn pin_port(&self) -> u8;
 
@@ -686,6 +1039,42 @@ mod eh02 {
         }
     }
 
+    impl<'d> embedded_hal_02::digital::v2::InputPin for OpenDrain<'d> {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(self.is_high())
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            Ok(self.is_low())
+        }
+    }
+
+    impl<'d> embedded_hal_02::digital::v2::OutputPin for OpenDrain<'d> {
+        type Error = Infallible;
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.set_high();
+            Ok(())
+        }
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.set_low();
+            Ok(())
+        }
+    }
+
+    impl<'d> embedded_hal_02::digital::v2::StatefulOutputPin for OpenDrain<'d> {
+        fn is_set_high(&self) -> Result<bool, Self::Error> {
+            Ok(self.is_set_high())
+        }
+
+        fn is_set_low(&self) -> Result<bool, Self::Error> {
+            Ok(self.is_set_low())
+        }
+    }
+
     /// Implement [`embedded_hal_02::digital::v2::InputPin`] for [`Flex`];
     ///
     /// If the pin is not in input mode the result is unspecified.
@@ -775,6 +1164,42 @@ impl<'d> embedded_hal_1::digital::StatefulOutputPin for Output<'d> {
     }
 }
 
+impl<'d> embedded_hal_1::digital::ErrorType for OpenDrain<'d> {
+    type Error = Infallible;
+}
+
+impl<'d> embedded_hal_1::digital::InputPin for OpenDrain<'d> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok((*self).is_high())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok((*self).is_low())
+    }
+}
+
+impl<'d> embedded_hal_1::digital::OutputPin for OpenDrain<'d> {
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_high();
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_low();
+        Ok(())
+    }
+}
+
+impl<'d> embedded_hal_1::digital::StatefulOutputPin for OpenDrain<'d> {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok((*self).is_set_high())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok((*self).is_set_low())
+    }
+}
+
 impl<'d> embedded_hal_1::digital::ErrorType for Flex<'d> {
     type Error = Infallible;
 }
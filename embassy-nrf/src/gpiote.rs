@@ -5,6 +5,7 @@ use core::convert::Infallible;
 use core::future::{Future, poll_fn};
 use core::task::{Context, Poll};
 
+use embassy_hal_internal::drop::OnDrop;
 use embassy_hal_internal::{Peri, PeripheralType, impl_peripheral};
 use embassy_sync::waitqueue::AtomicWaker;
 
@@ -137,6 +138,7 @@ const INTNUM: usize = 0;
 #[cfg(feature = "rt")]
 #[interrupt]
 fn GPIOTE0() {
+    crate::__on_interrupt(interrupt::GPIOTE0);
     unsafe { handle_gpiote_interrupt(pac::GPIOTE0) };
 }
 
@@ -144,6 +146,7 @@ fn GPIOTE0() {
 #[cfg(feature = "rt")]
 #[interrupt]
 fn GPIOTE1() {
+    crate::__on_interrupt(interrupt::GPIOTE1);
     unsafe { handle_gpiote_interrupt(pac::GPIOTE1) };
 }
 
@@ -151,6 +154,7 @@ fn GPIOTE1() {
 #[cfg(feature = "rt")]
 #[interrupt]
 fn GPIOTE() {
+    crate::__on_interrupt(interrupt::GPIOTE);
     unsafe { handle_gpiote_interrupt(pac::GPIOTE) };
 }
 
@@ -158,6 +162,7 @@ fn GPIOTE() {
 #[cfg(feature = "rt")]
 #[interrupt]
 fn GPIOTE20_0() {
+    crate::__on_interrupt(interrupt::GPIOTE20_0);
     unsafe { handle_gpiote_interrupt(pac::GPIOTE20) };
 }
 
@@ -165,6 +170,7 @@ fn GPIOTE20_0() {
 #[cfg(feature = "rt")]
 #[interrupt]
 fn GPIOTE30_0() {
+    crate::__on_interrupt(interrupt::GPIOTE30_0);
     unsafe { handle_gpiote_interrupt(pac::GPIOTE30) };
 }
 
@@ -172,6 +178,7 @@ fn GPIOTE30_0() {
 #[cfg(feature = "rt")]
 #[interrupt]
 fn GPIOTE20_1() {
+    crate::__on_interrupt(interrupt::GPIOTE20_1);
     unsafe { handle_gpiote_interrupt(pac::GPIOTE20) };
 }
 
@@ -179,6 +186,7 @@ fn GPIOTE20_1() {
 #[cfg(feature = "rt")]
 #[interrupt]
 fn GPIOTE30_1() {
+    crate::__on_interrupt(interrupt::GPIOTE30_1);
     unsafe { handle_gpiote_interrupt(pac::GPIOTE30) };
 }
 
@@ -662,6 +670,126 @@ impl<'d> Flex<'d> {
         PortInputFuture::new(self.pin.reborrow()).await
     }
 }
+
+/// A group of up to 32 inputs, waited on together with a single future.
+///
+/// Like [`Input::wait_for_any_edge`], this uses the GPIO PORT event with the SENSE setting of
+/// each pin rather than GPIOTE channels, so any number of groups and pins can be used, on
+/// both P0 and P1, alongside [`InputChannel`]s.
+///
+/// SENSE is level sensitive. To report edges, each pin is armed with the level opposite to
+/// the one last observed, so a pin that changed since the previous wait is reported right
+/// away. A pin that changes and changes back between two waits is not reported.
+///
+/// ```rust,ignore
+/// let mut keys = PortInput::new(rows.map(|pin| Input::new(pin, Pull::Up)));
+/// loop {
+///     let row = keys.wait_for_change().await;
+///     info!("row {} is {}", row, keys.levels() & (1 << row) != 0);
+/// }
+/// ```
+pub struct PortInput<'d, const N: usize> {
+    pins: [Input<'d>; N],
+    levels: u32,
+    /// Changes detected but not reported yet by [`Self::wait_for_change`].
+    pending: u32,
+}
+
+impl<'d, const N: usize> PortInput<'d, N> {
+    /// Create a group from `pins`, recording their current levels.
+    pub fn new(pins: [Input<'d>; N]) -> Self {
+        const { core::assert!(N <= 32, "PortInput supports at most 32 pins") };
+
+        let mut this = Self {
+            pins,
+            levels: 0,
+            pending: 0,
+        };
+        for (i, pin) in this.pins.iter().enumerate() {
+            if pin.is_high() {
+                this.levels |= 1 << i;
+            }
+        }
+        this
+    }
+
+    /// Wait for one or more of the pins to change level.
+    ///
+    /// Returns a bitmask where bit `i` is set if `pins[i]` changed. Changes left pending by
+    /// [`Self::wait_for_change`] are returned right away.
+    pub async fn wait_for_port_event(&mut self) -> u32 {
+        if self.pending != 0 {
+            return core::mem::take(&mut self.pending);
+        }
+
+        for (i, pin) in self.pins.iter().enumerate() {
+            let sense = if self.levels & (1 << i) != 0 {
+                Sense::LOW
+            } else {
+                Sense::HIGH
+            };
+            pin.pin.pin.conf().modify(|w| w.set_sense(sense));
+        }
+
+        let pins = &self.pins;
+        let _on_drop = OnDrop::new(|| {
+            for pin in pins {
+                pin.pin.pin.conf().modify(|w| w.set_sense(Sense::DISABLED));
+            }
+        });
+
+        let fired = poll_fn(|cx| {
+            let mut fired = 0;
+            for (i, pin) in pins.iter().enumerate() {
+                PORT_WAKERS[pin.pin.pin.pin_port() as usize].register(cx.waker());
+                if pin.pin.pin.conf().read().sense() == Sense::DISABLED {
+                    fired |= 1 << i;
+                }
+            }
+
+            if fired != 0 { Poll::Ready(fired) } else { Poll::Pending }
+        })
+        .await;
+
+        for (i, pin) in self.pins.iter().enumerate() {
+            if fired & (1 << i) != 0 {
+                self.levels &= !(1 << i);
+                if pin.is_high() {
+                    self.levels |= 1 << i;
+                }
+            }
+        }
+        fired
+    }
+
+    /// Wait for one of the pins to change level, and return its index.
+    ///
+    /// When several pins changed at once, this returns the lowest index, and the next calls
+    /// return the other ones right away, in order.
+    pub async fn wait_for_change(&mut self) -> usize {
+        if self.pending == 0 {
+            self.pending = self.wait_for_port_event().await;
+        }
+        let index = self.pending.trailing_zeros();
+        self.pending &= !(1 << index);
+        index as usize
+    }
+
+    /// Levels of the pins as of the last event, bit `i` set if `pins[i]` was high.
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+
+    /// Get a reference to one of the pins.
+    pub fn pin(&self, index: usize) -> &Input<'d> {
+        &self.pins[index]
+    }
+
+    /// Release the pins.
+    pub fn into_inner(self) -> [Input<'d>; N] {
+        self.pins
+    }
+}
 // =======================
 //
 
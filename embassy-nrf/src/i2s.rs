@@ -535,11 +535,10 @@ impl<'d> I2S<'d> {
     fn apply_config(&self) {
         let c = self.r.config();
         match &self.master_clock {
-            Some(MasterClock { freq, ratio }) => {
+            Some(master_clock) => {
                 c.mode().write(|w| w.set_mode(vals::Mode::MASTER));
                 c.mcken().write(|w| w.set_mcken(true));
-                c.mckfreq().write(|w| w.set_mckfreq(freq.to_register_value()));
-                c.ratio().write(|w| w.set_ratio(ratio.to_register_value()));
+                Self::apply_master_clock(self.r, master_clock);
             }
             None => {
                 c.mode().write(|w| w.set_mode(vals::Mode::SLAVE));
@@ -552,6 +551,22 @@ impl<'d> I2S<'d> {
         c.channels().write(|w| w.set_channels(self.config.channels.into()));
     }
 
+    fn apply_master_clock(r: pac::i2s::I2s, master_clock: &MasterClock) {
+        let c = r.config();
+        c.mckfreq()
+            .write(|w| w.set_mckfreq(master_clock.freq.to_register_value()));
+        c.ratio().write(|w| w.set_ratio(master_clock.ratio.to_register_value()));
+    }
+
+    async fn set_master_clock(r: pac::i2s::I2s, state: &State, master_clock: MasterClock) {
+        assert!(
+            r.config().mode().read().mode() == vals::Mode::MASTER,
+            "the sample rate of an I2S slave is set by its master"
+        );
+        Self::stop(r, state).await;
+        Self::apply_master_clock(r, &master_clock);
+    }
+
     fn select_pins(&self) {
         let psel = self.r.psel();
         psel.mck().write_value(self.mck.psel_bits());
@@ -582,6 +597,15 @@ impl<'d> I2S<'d> {
         compiler_fence(Ordering::SeqCst);
 
         let device = Device::new(r);
+        if !state.started.load(Ordering::Relaxed) {
+            // A stopped peripheral doesn't generate the STOPPED event again.
+            device.disable();
+            return;
+        }
+
+        // The interrupt handler disabled the interrupt at the previous stop.
+        device.reset_stopped_event();
+        device.enable_stopped_interrupt();
         device.stop();
 
         state.started.store(false, Ordering::Relaxed);
@@ -600,6 +624,31 @@ impl<'d> I2S<'d> {
         })
         .await;
 
+        // Pointer updates until the stop would make the next start return before its first
+        // buffer is in use.
+        device.reset_tx_ptr_event();
+        device.reset_rx_ptr_event();
+        device.enable_tx_ptr_interrupt();
+        device.enable_rx_ptr_interrupt();
+
+        device.disable();
+    }
+
+    /// Stop the transfer, blocking until the peripheral stopped, when a stream is dropped.
+    fn stop_blocking(r: pac::i2s::I2s, state: &State) {
+        compiler_fence(Ordering::SeqCst);
+
+        let device = Device::new(r);
+        if state.started.load(Ordering::Relaxed) {
+            device.disable_stopped_interrupt();
+            device.stop();
+            state.started.store(false, Ordering::Relaxed);
+
+            // The transfer stops at the end of the current frame, spinning is fine.
+            while !device.is_stopped() {}
+            device.reset_stopped_event();
+        }
+
         device.disable();
     }
 
@@ -760,6 +809,14 @@ impl<'d, S: Sample, const NB: usize, const NS: usize> OutputStream<'d, S, NB, NS
         I2S::stop(self.r, self.state).await
     }
 
+    /// Change the sample rate, stopping the transfer if it is running.
+    ///
+    /// Start the transfer again with [`start`](Self::start). Panics in slave mode, where the
+    /// sample rate is set by the master.
+    pub async fn set_master_clock(&mut self, master_clock: MasterClock) {
+        I2S::set_master_clock(self.r, self.state, master_clock).await
+    }
+
     /// Sends the current buffer for transmission in the DMA.
     /// Switches to use the next available buffer.
     pub async fn send(&mut self) -> Result<(), Error>
@@ -770,6 +827,12 @@ impl<'d, S: Sample, const NB: usize, const NS: usize> OutputStream<'d, S, NB, NS
     }
 }
 
+impl<'d, S: Sample, const NB: usize, const NS: usize> Drop for OutputStream<'d, S, NB, NS> {
+    fn drop(&mut self) {
+        I2S::stop_blocking(self.r, self.state);
+    }
+}
+
 /// I2S input
 pub struct InputStream<'d, S: Sample, const NB: usize, const NS: usize> {
     r: pac::i2s::I2s,
@@ -815,6 +878,14 @@ impl<'d, S: Sample, const NB: usize, const NS: usize> InputStream<'d, S, NB, NS>
         I2S::stop(self.r, self.state).await
     }
 
+    /// Change the sample rate, stopping the transfer if it is running.
+    ///
+    /// Start the transfer again with [`start`](Self::start). Panics in slave mode, where the
+    /// sample rate is set by the master.
+    pub async fn set_master_clock(&mut self, master_clock: MasterClock) {
+        I2S::set_master_clock(self.r, self.state, master_clock).await
+    }
+
     /// Sets the current buffer for reception from the DMA.
     /// Switches to use the next available buffer.
     #[allow(unused_mut)]
@@ -826,6 +897,12 @@ impl<'d, S: Sample, const NB: usize, const NS: usize> InputStream<'d, S, NB, NS>
     }
 }
 
+impl<'d, S: Sample, const NB: usize, const NS: usize> Drop for InputStream<'d, S, NB, NS> {
+    fn drop(&mut self) {
+        I2S::stop_blocking(self.r, self.state);
+    }
+}
+
 /// I2S full duplex stream (input & output)
 pub struct FullDuplexStream<'d, S: Sample, const NB: usize, const NS: usize> {
     r: pac::i2s::I2s,
@@ -875,6 +952,14 @@ impl<'d, S: Sample, const NB: usize, const NS: usize> FullDuplexStream<'d, S, NB
         I2S::stop(self.r, self.state).await
     }
 
+    /// Change the sample rate, stopping the transfer if it is running.
+    ///
+    /// Start the transfer again with [`start`](Self::start). Panics in slave mode, where the
+    /// sample rate is set by the master.
+    pub async fn set_master_clock(&mut self, master_clock: MasterClock) {
+        I2S::set_master_clock(self.r, self.state, master_clock).await
+    }
+
     /// Sets the current buffers for output and input for transmission/reception from the DMA.
     /// Switch to use the next available buffers for output/input.
     pub async fn send_and_receive(&mut self) -> Result<(), Error>
@@ -887,6 +972,12 @@ impl<'d, S: Sample, const NB: usize, const NS: usize> FullDuplexStream<'d, S, NB
     }
 }
 
+impl<'d, S: Sample, const NB: usize, const NS: usize> Drop for FullDuplexStream<'d, S, NB, NS> {
+    fn drop(&mut self) {
+        I2S::stop_blocking(self.r, self.state);
+    }
+}
+
 /// Helper encapsulating common I2S device operations.
 struct Device(pac::i2s::I2s);
 
@@ -0,0 +1,218 @@
+//! Input capture driver, measuring the period and duty cycle of a digital signal.
+//!
+//! Both edges of the signal are detected by a GPIOTE channel, whose event captures the counter of
+//! a timer through PPI. Edge timestamps are thus exact, whatever the latency of the task reading
+//! them, as long as it reads them before the next edge. This suits signals such as a fan tachometer
+//! or an RC receiver output, up to a few kHz.
+//!
+//! Timestamps are 32-bit counter values, so an interval of up to `2^32` ticks is measured across
+//! a counter overflow, which is over an hour at 1 MHz, and about 268 seconds at 16 MHz. A longer
+//! interval, when the signal stops for a while, is detected and discarded.
+//!
+//! ```rust,ignore
+//! let mut capture = InputCapture::new(p.TIMER1, p.GPIOTE_CH0, p.P0_11, p.PPI_CH0, Default::default());
+//! let m = capture.measure_duty().await;
+//! info!("{} Hz, {}% duty", capture.tick_hz() / m.period, 100 * m.high_time / m.period);
+//! ```
+
+use embassy_hal_internal::Peri;
+
+use crate::gpio::{Pin as GpioPin, Pull};
+use crate::gpiote::{Channel, InputChannel, InputChannelPolarity};
+use crate::ppi::{AnyConfigurableChannel, ConfigurableChannel, Ppi};
+use crate::timer::{Frequency, Instance as TimerInstance, Timer};
+
+/// Input capture configuration.
+#[non_exhaustive]
+pub struct Config {
+    /// Frequency of the timer timestamping the edges.
+    ///
+    /// Higher frequencies give a finer resolution, lower ones a lower power consumption.
+    pub frequency: Frequency,
+    /// Pull of the input pin.
+    pub pull: Pull,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frequency: Frequency::F1MHz,
+            pull: Pull::None,
+        }
+    }
+}
+
+/// A cycle of the signal, from a rising edge to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurement {
+    /// Time between the two rising edges, in timer ticks.
+    pub period: u32,
+    /// Time the signal was high during the period, in timer ticks.
+    pub high_time: u32,
+}
+
+struct Edge {
+    rising: bool,
+    at: u32,
+}
+
+/// Input capture driver.
+///
+/// The GPIOTE and PPI channels and the timer are released when the driver is dropped.
+pub struct InputCapture<'d> {
+    // Fields are dropped in order: unlink the event from the timer first.
+    _ppi: Ppi<'d, AnyConfigurableChannel, 1, 1>,
+    ch: InputChannel<'d>,
+    timer: Timer<'d>,
+    tick_hz: u32,
+}
+
+impl<'d> InputCapture<'d> {
+    /// Create a new input capture driver on `pin`.
+    pub fn new<T: TimerInstance, C: Channel, P: GpioPin>(
+        timer: Peri<'d, T>,
+        ch: Peri<'d, C>,
+        pin: Peri<'d, P>,
+        ppi_ch: Peri<'d, impl ConfigurableChannel>,
+        config: Config,
+    ) -> Self {
+        let tick_hz = 16_000_000 >> (config.frequency as u8);
+
+        let timer = Timer::new(timer);
+        timer.set_frequency(config.frequency);
+
+        let ch = InputChannel::new(ch, pin, config.pull, InputChannelPolarity::Toggle);
+
+        // Capture the counter in CC0 on both edges.
+        let mut ppi = Ppi::new_one_to_one(ppi_ch.into(), ch.event_in(), timer.cc(0).task_capture());
+        ppi.enable();
+
+        timer.start();
+
+        Self {
+            _ppi: ppi,
+            ch,
+            timer,
+            tick_hz,
+        }
+    }
+
+    /// Frequency of the timer ticks the measurements are expressed in.
+    pub fn tick_hz(&self) -> u32 {
+        self.tick_hz
+    }
+
+    /// Wait for the next rising edge, and measure the time until the following one, in timer ticks.
+    pub async fn measure_period(&mut self) -> u32 {
+        let mut start = self.wait_rising().await;
+        loop {
+            self.arm_overflow(start);
+            let end = self.wait_rising().await;
+            if !self.overflowed() {
+                return end.wrapping_sub(start);
+            }
+            start = end;
+        }
+    }
+
+    /// Wait for the next rising edge, and measure the cycle it starts.
+    pub async fn measure_duty(&mut self) -> Measurement {
+        self.stream().next().await
+    }
+
+    /// Measure consecutive cycles of the signal.
+    pub fn stream(&mut self) -> MeasurementStream<'_, 'd> {
+        MeasurementStream {
+            capture: self,
+            start: None,
+        }
+    }
+
+    /// Wait for the next edge.
+    async fn next_edge(&mut self) -> Edge {
+        self.ch.wait().await;
+        loop {
+            let high = self.ch.pin().is_high();
+            let at = self.timer.cc(0).read();
+            // An edge between the reads would make the level and the timestamp mismatch.
+            if self.ch.pin().is_high() == high {
+                return Edge { rising: high, at };
+            }
+        }
+    }
+
+    async fn wait_rising(&mut self) -> u32 {
+        loop {
+            let edge = self.next_edge().await;
+            if edge.rising {
+                return edge.at;
+            }
+        }
+    }
+
+    /// Measure the cycle starting at the rising edge at `start`.
+    ///
+    /// Returns the measurement, unless edges were missed or the cycle is longer than the counter
+    /// period, and the rising edge the next cycle starts at, if known.
+    async fn cycle(&mut self, start: u32) -> (Option<Measurement>, Option<u32>) {
+        self.arm_overflow(start);
+
+        let fall = self.next_edge().await;
+        if fall.rising {
+            // The falling edge was missed.
+            return (None, Some(fall.at));
+        }
+        let end = self.next_edge().await;
+        if !end.rising {
+            // The rising edge was missed.
+            return (None, None);
+        }
+        if self.overflowed() {
+            return (None, Some(end.at));
+        }
+
+        let measurement = Measurement {
+            period: end.at.wrapping_sub(start),
+            high_time: fall.at.wrapping_sub(start),
+        };
+        (Some(measurement), Some(end.at))
+    }
+
+    /// Detect the counter getting back to `at`, a whole counter period after it.
+    fn arm_overflow(&self, at: u32) {
+        let cc = self.timer.cc(1);
+        cc.write(at);
+        cc.clear_events();
+    }
+
+    fn overflowed(&self) -> bool {
+        self.timer.cc(1).event_compare().is_triggered()
+    }
+}
+
+/// Consecutive cycles of a signal, created by [`InputCapture::stream`].
+///
+/// Each cycle starts at the rising edge ending the previous one, as long as [`next`](Self::next)
+/// is called again before that edge is followed by another. Cycles with missed edges are skipped.
+pub struct MeasurementStream<'a, 'd> {
+    capture: &'a mut InputCapture<'d>,
+    start: Option<u32>,
+}
+
+impl<'a, 'd> MeasurementStream<'a, 'd> {
+    /// Wait for the next cycle to end, and return its measurement.
+    pub async fn next(&mut self) -> Measurement {
+        loop {
+            let start = match self.start.take() {
+                Some(start) => start,
+                None => self.capture.wait_rising().await,
+            };
+            let (measurement, next) = self.capture.cycle(start).await;
+            self.start = next;
+            if let Some(measurement) = measurement {
+                return measurement;
+            }
+        }
+    }
+}
@@ -79,16 +79,37 @@ compile_error!("feature `nfc-pins-as-gpio` is only valid for nRF52, or nRF53's a
 #[cfg(all(feature = "lfxo-pins-as-gpio", not(feature = "_nrf5340")))]
 compile_error!("feature `lfxo-pins-as-gpio` is only valid for nRF53 series chips.");
 
+#[cfg(all(feature = "low-power", not(feature = "_time-driver")))]
+compile_error!("feature `low-power` requires a `time-driver-*` feature.");
+
 // This mod MUST go first, so that the others see its macros.
 pub(crate) mod fmt;
 pub(crate) mod util;
 
+#[cfg(test)]
+mod replay;
+
+#[cfg(not(feature = "_nrf51"))]
+mod bus_error;
+
 #[cfg(feature = "_time-driver")]
 mod time_driver;
 
+#[cfg(not(any(feature = "_nrf51", feature = "nrf52820", feature = "_nrf5340-net")))]
+pub mod battery;
 #[cfg(not(feature = "_nrf51"))]
 pub mod buffered_uarte;
 #[cfg(not(feature = "_nrf54l"))] // TODO
+#[cfg(any(
+    feature = "nrf52810",
+    feature = "nrf52811",
+    feature = "nrf52832",
+    feature = "nrf52833",
+    feature = "nrf52840",
+    feature = "_nrf5340-app"
+))]
+pub mod comp;
+#[cfg(not(feature = "_nrf54l"))] // TODO
 #[cfg(not(feature = "_nrf51"))]
 pub mod egu;
 pub mod gpio;
@@ -97,8 +118,21 @@ pub mod gpiote;
 #[cfg(not(feature = "_nrf54l"))] // TODO
 #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
 pub mod i2s;
+#[cfg(not(feature = "_nrf54l"))] // TODO
+#[cfg(feature = "gpiote")]
+pub mod input_capture;
 #[cfg(feature = "_nrf5340")]
 pub mod ipc;
+#[cfg(feature = "low-power")]
+pub mod low_power;
+#[cfg(not(feature = "_nrf54l"))] // TODO
+#[cfg(any(
+    feature = "nrf52832",
+    feature = "nrf52833",
+    feature = "nrf52840",
+    feature = "_nrf5340-app"
+))]
+pub mod lpcomp;
 #[cfg(not(feature = "_nrf54l"))] // TODO
 #[cfg(any(
     feature = "nrf52832",
@@ -124,8 +158,7 @@ pub use rramc as nvmc;
     feature = "_nrf91",
 ))]
 pub mod pdm;
-#[cfg(not(feature = "_nrf54l"))] // TODO
-#[cfg(any(feature = "nrf52840", feature = "nrf9160-s", feature = "nrf9160-ns"))]
+#[cfg(any(feature = "_nrf52", feature = "nrf9160-s", feature = "nrf9160-ns"))]
 pub mod power;
 pub mod ppi;
 #[cfg(not(any(
@@ -175,6 +208,8 @@ pub mod cryptocell_rng;
 pub mod rtc;
 #[cfg(not(any(feature = "_nrf51", feature = "nrf52820", feature = "_nrf5340-net")))]
 pub mod saadc;
+#[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+pub mod security;
 #[cfg(not(feature = "_nrf51"))]
 pub mod spim;
 #[cfg(not(feature = "_nrf51"))]
@@ -196,6 +231,8 @@ pub mod uarte;
     feature = "nrf52840"
 ))]
 pub mod usb;
+#[cfg(feature = "wake-stats")]
+pub mod wake_stats;
 pub mod wdt;
 
 // This mod MUST go last, so that it sees all the `impl_foo!` macros
@@ -268,6 +305,7 @@ macro_rules! bind_interrupts {
             #[unsafe(no_mangle)]
             $(#[cfg($cond_irq)])?
             unsafe extern "C" fn $irq() {
+                $crate::__on_interrupt($crate::interrupt::$irq);
                 unsafe {
                     $(
                         $(#[cfg($cond_handler)])?
@@ -291,6 +329,13 @@ macro_rules! bind_interrupts {
     }
 }
 
+#[doc(hidden)]
+#[inline(always)]
+pub fn __on_interrupt(_irq: interrupt::Interrupt) {
+    #[cfg(feature = "wake-stats")]
+    wake_stats::on_interrupt(_irq);
+}
+
 // Reexports
 
 #[cfg(feature = "unstable-pac")]
@@ -300,6 +345,9 @@ pub(crate) use chip::pac;
 pub use chip::{EASY_DMA_SIZE, Peripherals, peripherals};
 pub use embassy_hal_internal::{Peri, PeripheralType};
 
+#[cfg(not(feature = "_nrf51"))]
+pub use crate::bus_error::{BusError, BusErrorKind, BusId, DeviceLabel};
+
 pub use crate::chip::interrupt;
 #[cfg(feature = "rt")]
 pub use crate::pac::NVIC_PRIO_BITS;
@@ -1229,6 +1277,10 @@ pub fn init(config: config::Config) -> Peripherals {
         pac::REGULATORS.vregmain().dcdcen().write(|w| w.set_val(true));
     }
 
+    // Record the pin that woke the chip from System OFF, before GPIOTE clears the latches.
+    #[cfg(not(feature = "_nrf51"))]
+    gpio::capture_wakeup_source();
+
     // Init GPIOTE
     #[cfg(feature = "gpiote")]
     gpiote::init(config.gpiote_interrupt_priority);
@@ -0,0 +1,241 @@
+//! Low-power sleep orchestration.
+//!
+//! Enabled with the `low-power` feature, which needs a `time-driver-*` feature.
+//!
+//! Peripherals left enabled while the chip sleeps keep drawing current: a UARTE with its
+//! receiver running keeps the high frequency clock requested, the HFXO itself draws hundreds of
+//! microamps. Drivers don't know when the chip is about to sleep, and the application doesn't
+//! know which peripherals are idle. This module connects the two: drivers provide
+//! [`SleepHook`]s which the application [`register`]s, and [`sleep`] runs them around the sleep
+//! of the thread-mode executor when the next timer is far enough away to be worth it.
+//!
+//! With a thread-mode loop written out around `embassy_executor::raw::Executor`, `sleep`
+//! replaces the `WFE`:
+//!
+//! ```rust,ignore
+//! loop {
+//!     unsafe { executor.poll() };
+//!     low_power::sleep(Duration::from_millis(5));
+//! }
+//! ```
+//!
+//! [`wake_stats::on_idle`](crate::wake_stats::on_idle) and
+//! [`wake_stats::on_wake`](crate::wake_stats::on_wake) can go around it as usual.
+//!
+//! # Hooks
+//!
+//! The hooks are prepared in registration order and resumed in reverse order, so a hook
+//! prepares and resumes while the hooks registered after it are awake: register the
+//! peripheral hooks before the [`HfxoHook`]. They run with interrupts disabled: they must be
+//! short and must not block on anything an interrupt would provide.
+//!
+//! Hooks are provided for the [`Uarte`](crate::uarte::Uarte::sleep_hook) and the
+//! [`Saadc`](crate::saadc::Saadc::sleep_hook), and [`HfxoHook`] releases the crystal
+//! oscillator.
+//!
+//! # Vetoes
+//!
+//! A transfer in progress can't survive its peripheral being disabled. Drivers hold a
+//! [`DeepSleepVeto`] while they have a transfer in progress, and so can applications, for
+//! instance while the radio is in use. While any veto is held, [`sleep`] sleeps without running
+//! the hooks.
+
+use core::cell::{Cell, RefCell};
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant};
+
+use crate::pac;
+
+/// Maximum number of hooks that can be registered.
+pub const MAX_HOOKS: usize = 8;
+
+/// A peripheral to power down while the chip sleeps.
+pub trait SleepHook {
+    /// Called before sleeping, with the time until the next timer expires.
+    ///
+    /// `planned` is [`Duration::MAX`] if no timer is scheduled. The sleep can end earlier, on
+    /// any interrupt.
+    fn prepare_sleep(&mut self, planned: Duration);
+
+    /// Called after waking up, to undo [`prepare_sleep`](Self::prepare_sleep).
+    fn resume(&mut self);
+}
+
+struct Hooks {
+    hooks: [Option<&'static mut dyn SleepHook>; MAX_HOOKS],
+    len: usize,
+}
+
+// The hooks are only accessed within a critical section.
+unsafe impl Send for Hooks {}
+
+static HOOKS: Mutex<CriticalSectionRawMutex, RefCell<Hooks>> = Mutex::new(RefCell::new(Hooks {
+    hooks: [const { None }; MAX_HOOKS],
+    len: 0,
+}));
+
+static VETOES: Mutex<CriticalSectionRawMutex, Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// Register a hook to run around each deep sleep.
+///
+/// Hooks run in the order they are registered in, and can't be unregistered.
+///
+/// # Panics
+///
+/// Panics if [`MAX_HOOKS`] hooks are already registered, or if called from a hook.
+pub fn register(hook: &'static mut dyn SleepHook) {
+    HOOKS.lock(|hooks| {
+        let mut hooks = hooks.borrow_mut();
+        let len = hooks.len;
+        assert!(
+            len < MAX_HOOKS,
+            "at most {} low-power hooks can be registered",
+            MAX_HOOKS
+        );
+        hooks.hooks[len] = Some(hook);
+        hooks.len = len + 1;
+    })
+}
+
+/// Prevents deep sleep while it is held.
+///
+/// Obtained with [`veto`], released on drop.
+#[must_use = "the veto is released when dropped"]
+pub struct DeepSleepVeto {
+    _private: (),
+}
+
+/// Prevent deep sleep until the returned guard is dropped.
+///
+/// The chip still sleeps, but without running the hooks.
+pub fn veto() -> DeepSleepVeto {
+    VETOES.lock(|v| v.set(v.get() + 1));
+    DeepSleepVeto { _private: () }
+}
+
+impl Drop for DeepSleepVeto {
+    fn drop(&mut self) {
+        VETOES.lock(|v| v.set(v.get() - 1));
+    }
+}
+
+/// Whether a [`DeepSleepVeto`] is held.
+pub fn is_vetoed() -> bool {
+    VETOES.lock(|v| v.get() != 0)
+}
+
+/// Sleep until the next event, running the hooks if the next timer is at least `min` away.
+///
+/// Call this from the thread-mode executor loop after polling, in place of `WFE`. It returns
+/// on any interrupt or event, including the `SEV` of a task being woken, so it also returns
+/// immediately if a task was woken while polling.
+///
+/// The hooks and the sleep run with interrupts disabled, so nothing can run in between. The
+/// interrupt that woke the chip runs when this returns, after the hooks are resumed.
+pub fn sleep(min: Duration) {
+    // Let interrupts becoming pending while they are disabled wake `WFE`.
+    unsafe { cortex_m::Peripherals::steal().SCB.set_sevonpend() };
+
+    cortex_m::interrupt::free(|_| {
+        let planned = planned();
+        let deep = planned >= min && !is_vetoed();
+
+        if deep {
+            HOOKS.lock(|hooks| {
+                let mut hooks = hooks.borrow_mut();
+                let len = hooks.len;
+                for hook in hooks.hooks[..len].iter_mut().flatten() {
+                    hook.prepare_sleep(planned);
+                }
+            });
+        }
+
+        cortex_m::asm::wfe();
+
+        if deep {
+            HOOKS.lock(|hooks| {
+                let mut hooks = hooks.borrow_mut();
+                let len = hooks.len;
+                for hook in hooks.hooks[..len].iter_mut().rev().flatten() {
+                    hook.resume();
+                }
+            });
+        }
+    });
+}
+
+/// Time until the next timer expires, [`Duration::MAX`] if there is none.
+fn planned() -> Duration {
+    match critical_section::with(crate::time_driver::next_alarm) {
+        u64::MAX => Duration::MAX,
+        at => Duration::from_ticks(at.saturating_sub(Instant::now().as_ticks())),
+    }
+}
+
+/// Stops the HFXO while the chip sleeps, if it is running, and restarts it on wake-up.
+///
+/// Peripherals that need an accurate clock, such as the radio or USB, must not be in use
+/// during a deep sleep with this hook registered: hold a [`DeepSleepVeto`] while they are.
+///
+/// Restarting the crystal takes about 0.36 ms on the nRF52, which the wake-up is delayed by.
+/// Register it last, so the other hooks run with the crystal running.
+pub struct HfxoHook {
+    stopped: bool,
+}
+
+impl HfxoHook {
+    /// Create the hook.
+    pub const fn new() -> Self {
+        Self { stopped: false }
+    }
+}
+
+impl Default for HfxoHook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SleepHook for HfxoHook {
+    fn prepare_sleep(&mut self, _planned: Duration) {
+        let r = pac::CLOCK;
+
+        #[cfg(not(feature = "_nrf54l"))]
+        let running = r.hfclkrun().read().status();
+        #[cfg(feature = "_nrf54l")]
+        let running = r.xo().run().read().status();
+
+        if running {
+            #[cfg(not(feature = "_nrf54l"))]
+            r.tasks_hfclkstop().write_value(1);
+            #[cfg(feature = "_nrf54l")]
+            r.tasks_xostop().write_value(1);
+        }
+        self.stopped = running;
+    }
+
+    fn resume(&mut self) {
+        if !self.stopped {
+            return;
+        }
+        self.stopped = false;
+
+        let r = pac::CLOCK;
+
+        #[cfg(not(feature = "_nrf54l"))]
+        {
+            r.events_hfclkstarted().write_value(0);
+            r.tasks_hfclkstart().write_value(1);
+            while r.events_hfclkstarted().read() == 0 {}
+        }
+
+        #[cfg(feature = "_nrf54l")]
+        {
+            r.events_xostarted().write_value(0);
+            r.tasks_xostart().write_value(1);
+            while r.events_xostarted().read() == 0 {}
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Low-power comparator (LPCOMP) driver.
+//!
+//! LPCOMP compares an analog input to a fraction of VDD or to an external reference, drawing
+//! less current than [`comp`](crate::comp). Besides waking the CPU from System ON sleep, it can
+//! wake the chip from System OFF, see [`Config::detect`].
+//!
+//! LPCOMP shares its registers and interrupt with COMP, so it takes the `COMP` peripheral.
+
+#![macro_use]
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy_hal_internal::drop::OnDrop;
+use embassy_hal_internal::{Peri, PeripheralType};
+use embassy_sync::waitqueue::AtomicWaker;
+
+pub use crate::comp::Output;
+use crate::comp::analog_input;
+use crate::interrupt::typelevel::Interrupt;
+use crate::pac::lpcomp::vals;
+use crate::saadc::{AnyInput, Input};
+use crate::{interrupt, pac};
+
+/// Interrupt handler.
+pub struct InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        let r = T::regs();
+        // Leave the events set, for the waiting task to see them.
+        if r.events_up().read() != 0 {
+            r.intenclr().write(|w| w.set_up(true));
+        }
+        if r.events_down().read() != 0 {
+            r.intenclr().write(|w| w.set_down(true));
+        }
+        if r.events_cross().read() != 0 {
+            r.intenclr().write(|w| w.set_cross(true));
+        }
+        T::state().waker.wake();
+    }
+}
+
+/// Reference voltage of the comparator.
+pub enum Reference<'d> {
+    /// A fraction of VDD, in sixteenths, `1..=15`.
+    Vdd(u8),
+    /// An external reference, on analog input pin 0 or 1.
+    External(AnyInput<'d>),
+}
+
+/// Crossing of the reference that wakes the chip from System OFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Detect {
+    /// Crossing in either direction.
+    Cross,
+    /// Upward crossing.
+    Up,
+    /// Downward crossing.
+    Down,
+}
+
+/// LPCOMP config.
+#[non_exhaustive]
+pub struct Config {
+    /// Enable the 50 mV hysteresis.
+    pub hysteresis: bool,
+    /// Crossing that wakes the chip from System OFF.
+    ///
+    /// The comparator only keeps running in System OFF if the driver isn't dropped, for example
+    /// by passing it to [`core::mem::forget`] before entering System OFF.
+    pub detect: Detect,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hysteresis: false,
+            detect: Detect::Cross,
+        }
+    }
+}
+
+/// Low-power comparator driver.
+pub struct Lpcomp<'d> {
+    r: pac::lpcomp::Lpcomp,
+    state: &'static State,
+    _phantom: PhantomData<&'d ()>,
+}
+
+impl<'d> Lpcomp<'d> {
+    /// Create a low-power comparator, comparing `input` to `reference`.
+    pub fn new<T: Instance>(
+        _lpcomp: Peri<'d, T>,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        input: impl Input + 'd,
+        reference: Reference<'d>,
+        config: Config,
+    ) -> Self {
+        let r = T::regs();
+        r.psel()
+            .write(|w| w.set_psel(vals::PselPsel::from_bits(analog_input(&input))));
+        r.refsel().write(|w| {
+            w.set_refsel(match reference {
+                Reference::Vdd(n) => {
+                    assert!((1..=15).contains(&n));
+                    match n % 2 {
+                        // Odd sixteenths, REF1_16VDD to REF15_16VDD.
+                        1 => vals::Refsel::from_bits(8 + n / 2),
+                        // Eighths, REF1_8VDD to REF7_8VDD.
+                        _ => vals::Refsel::from_bits(n / 2 - 1),
+                    }
+                }
+                Reference::External(_) => vals::Refsel::AREF,
+            })
+        });
+        if let Reference::External(pin) = &reference {
+            let n = analog_input(pin);
+            assert!(n < 2, "the external reference must be analog input pin 0 or 1");
+            r.extrefsel().write(|w| w.set_extrefsel(vals::Extrefsel::from_bits(n)));
+        }
+        #[cfg(feature = "nrf52832")]
+        r.hyst().write(|w| {
+            w.set_hyst(match config.hysteresis {
+                true => vals::Hyst::HYST50M_V,
+                false => vals::Hyst::NO_HYST,
+            })
+        });
+        #[cfg(not(feature = "nrf52832"))]
+        r.hyst().write(|w| w.set_hyst(config.hysteresis));
+        r.anadetect().write(|w| {
+            w.set_anadetect(match config.detect {
+                Detect::Cross => vals::Anadetect::CROSS,
+                Detect::Up => vals::Anadetect::UP,
+                Detect::Down => vals::Anadetect::DOWN,
+            })
+        });
+
+        r.intenclr().write(|w| {
+            w.set_ready(true);
+            w.set_down(true);
+            w.set_up(true);
+            w.set_cross(true);
+        });
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+
+        r.enable().write(|w| w.set_enable(vals::Enable::ENABLED));
+        r.events_ready().write_value(0);
+        r.tasks_start().write_value(1);
+        // The output is valid after the startup time, up to about a hundred microseconds.
+        while r.events_ready().read() == 0 {}
+        r.events_ready().write_value(0);
+
+        Self {
+            r,
+            state: T::state(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sample the output of the comparator.
+    pub fn sample(&mut self) -> Output {
+        self.r.tasks_sample().write_value(1);
+        match self.r.result().read().result() {
+            vals::Result::ABOVE => Output::Above,
+            _ => Output::Below,
+        }
+    }
+
+    /// Wait for the input to rise above the reference.
+    ///
+    /// This only completes on a crossing after the call, use [`sample`](Self::sample) to know
+    /// whether the input is already above the reference.
+    pub async fn wait_for_up(&mut self) {
+        self.wait(Event::Up).await
+    }
+
+    /// Wait for the input to fall below the reference.
+    ///
+    /// This only completes on a crossing after the call, use [`sample`](Self::sample) to know
+    /// whether the input is already below the reference.
+    pub async fn wait_for_down(&mut self) {
+        self.wait(Event::Down).await
+    }
+
+    /// Wait for the input to cross the reference in either direction, and return the new output.
+    pub async fn wait_for_cross(&mut self) -> Output {
+        self.wait(Event::Cross).await;
+        self.sample()
+    }
+
+    async fn wait(&mut self, event: Event) {
+        let r = self.r;
+        let reg = match event {
+            Event::Up => r.events_up(),
+            Event::Down => r.events_down(),
+            Event::Cross => r.events_cross(),
+        };
+        let set_int = |w: &mut pac::lpcomp::regs::Int| match event {
+            Event::Up => w.set_up(true),
+            Event::Down => w.set_down(true),
+            Event::Cross => w.set_cross(true),
+        };
+
+        reg.write_value(0);
+        r.intenset().write(set_int);
+        let _on_drop = OnDrop::new(|| r.intenclr().write(set_int));
+
+        poll_fn(|cx| {
+            self.state.waker.register(cx.waker());
+            if reg.read() != 0 {
+                reg.write_value(0);
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<'d> Drop for Lpcomp<'d> {
+    fn drop(&mut self) {
+        self.r.tasks_stop().write_value(1);
+        self.r.intenclr().write(|w| {
+            w.set_down(true);
+            w.set_up(true);
+            w.set_cross(true);
+        });
+        self.r.enable().write(|w| w.set_enable(vals::Enable::DISABLED));
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Event {
+    Up,
+    Down,
+    Cross,
+}
+
+pub(crate) struct State {
+    waker: AtomicWaker,
+}
+
+impl State {
+    pub(crate) const fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+pub(crate) trait SealedInstance {
+    fn regs() -> pac::lpcomp::Lpcomp;
+    fn state() -> &'static State;
+}
+
+/// LPCOMP peripheral instance.
+#[allow(private_bounds)]
+pub trait Instance: SealedInstance + PeripheralType + 'static + Send {
+    /// Interrupt for this peripheral.
+    type Interrupt: interrupt::typelevel::Interrupt;
+}
+
+macro_rules! impl_lpcomp {
+    ($type:ident, $pac_type:ident, $irq:ident) => {
+        impl crate::lpcomp::SealedInstance for peripherals::$type {
+            fn regs() -> pac::lpcomp::Lpcomp {
+                pac::$pac_type
+            }
+            fn state() -> &'static crate::lpcomp::State {
+                static STATE: crate::lpcomp::State = crate::lpcomp::State::new();
+                &STATE
+            }
+        }
+        impl crate::lpcomp::Instance for peripherals::$type {
+            type Interrupt = crate::interrupt::typelevel::$irq;
+        }
+    };
+}
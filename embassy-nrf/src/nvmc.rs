@@ -1,7 +1,23 @@
 //! Non-Volatile Memory Controller (NVMC, AKA internal flash) driver.
+//!
+//! The NVMC has no READY event and no interrupt, only the READY status register, so there is no
+//! interrupt driven async driver. While code executes from flash, the CPU is halted for the
+//! duration of each word write and page erase anyway. To let other tasks run between operations,
+//! wrap [`Nvmc`] in `embassy_embedded_hal::adapter::BlockingAsync` and `YieldingAsync`, and on
+//! nRF52 use [`PartialEraseNvmc`] to split page erases into short slices.
+//!
+//! On chips with an instruction cache controlled by the NVMC, the cache is disabled during each
+//! write and erase, which invalidates it, so code written to flash, e.g. by a bootloader updating
+//! itself, is not executed from stale cache lines.
 
 use core::{ptr, slice};
 
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+use embassy_embedded_hal::flash::scheduler::{BlackoutPolicy, FlashScheduler, SlicedNorFlash};
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+use embassy_sync::blocking_mutex::raw::RawMutex;
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+use embassy_time::{Duration, Timer};
 use embedded_storage::nor_flash::{
     ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
 };
@@ -20,7 +36,13 @@ pub const PAGE_SIZE: usize = 2048;
 /// Size of NVMC flash in bytes.
 pub const FLASH_SIZE: usize = crate::chip::FLASH_SIZE;
 
+/// Write size of NVMC flash in bytes. Writes must be word aligned.
+pub const WRITE_SIZE: usize = 4;
+
 /// Error type for NVMC operations.
+///
+/// Invalid arguments are reported with these errors rather than panicking, so an offset read
+/// from corrupted data, e.g. a DFU header, can be handled.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
@@ -28,6 +50,8 @@ pub enum Error {
     OutOfBounds,
     /// Unaligned operation or using unaligned buffers.
     Unaligned,
+    /// Operation on a page that is being erased with [`Nvmc::start_partial_erase`].
+    Busy,
 }
 
 impl NorFlashError for Error {
@@ -35,6 +59,78 @@ impl NorFlashError for Error {
         match self {
             Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
             Self::Unaligned => NorFlashErrorKind::NotAligned,
+            Self::Busy => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+fn check_read_range(offset: u32, len: usize) -> Result<(), Error> {
+    match (offset as usize).checked_add(len) {
+        Some(end) if (offset as usize) < FLASH_SIZE && end <= FLASH_SIZE => Ok(()),
+        _ => Err(Error::OutOfBounds),
+    }
+}
+
+fn check_erase_range(from: u32, to: u32) -> Result<(), Error> {
+    if to < from || to as usize > FLASH_SIZE {
+        return Err(Error::OutOfBounds);
+    }
+    if from as usize % PAGE_SIZE != 0 || to as usize % PAGE_SIZE != 0 {
+        return Err(Error::Unaligned);
+    }
+    Ok(())
+}
+
+fn check_write_range(offset: u32, len: usize) -> Result<(), Error> {
+    if (offset as usize).checked_add(len).is_none_or(|end| end > FLASH_SIZE) {
+        return Err(Error::OutOfBounds);
+    }
+    if !(offset as usize).is_multiple_of(WRITE_SIZE) || !len.is_multiple_of(WRITE_SIZE) {
+        return Err(Error::Unaligned);
+    }
+    Ok(())
+}
+
+/// Cumulative partial erase time needed to fully erase a page, in milliseconds.
+#[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+const PAGE_ERASE_TIME_MS: u8 = 85;
+
+/// Progress of a partial erase started with [`Nvmc::start_partial_erase`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+pub enum PartialEraseStatus {
+    /// The page is fully erased, or no partial erase was started.
+    Done,
+    /// More partial erases are needed before the page is erased.
+    InProgress,
+}
+
+#[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+#[derive(Clone, Copy)]
+struct PartialErase {
+    page_addr: u32,
+    duration_ms: u8,
+    elapsed_ms: u8,
+}
+
+#[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+impl PartialErase {
+    fn new(page_addr: u32, duration_ms: u32) -> Self {
+        Self {
+            page_addr,
+            duration_ms: duration_ms.clamp(1, PAGE_ERASE_TIME_MS as u32) as u8,
+            elapsed_ms: 0,
+        }
+    }
+
+    /// Account for one more slice, returning whether the page is erased afterwards.
+    fn advance(&mut self) -> PartialEraseStatus {
+        self.elapsed_ms = self.elapsed_ms.saturating_add(self.duration_ms);
+        if self.elapsed_ms >= PAGE_ERASE_TIME_MS {
+            PartialEraseStatus::Done
+        } else {
+            PartialEraseStatus::InProgress
         }
     }
 }
@@ -42,18 +138,187 @@ impl NorFlashError for Error {
 /// Non-Volatile Memory Controller (NVMC) that implements the `embedded-storage` traits.
 pub struct Nvmc<'d> {
     _p: Peri<'d, NVMC>,
+    #[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+    partial_erase: Option<PartialErase>,
 }
 
 impl<'d> Nvmc<'d> {
     /// Create Nvmc driver.
     pub fn new(_p: Peri<'d, NVMC>) -> Self {
-        Self { _p }
+        Self {
+            _p,
+            #[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+            partial_erase: None,
+        }
+    }
+
+    /// Erase size of the flash in bytes, i.e. [`PAGE_SIZE`].
+    pub const fn page_size() -> usize {
+        PAGE_SIZE
+    }
+
+    /// Size of the flash in bytes, i.e. [`FLASH_SIZE`].
+    pub const fn capacity() -> usize {
+        FLASH_SIZE
+    }
+
+    /// Write size of the flash in bytes, i.e. [`WRITE_SIZE`].
+    pub const fn write_size() -> usize {
+        WRITE_SIZE
+    }
+
+    /// Erase all pages in `[from, to)`.
+    ///
+    /// Both bounds must be page aligned, otherwise [`Error::Unaligned`] is returned. A range
+    /// extending past the end of flash returns [`Error::OutOfBounds`]. In both cases no page
+    /// is erased.
+    pub fn erase_range(&mut self, from: u32, to: u32) -> Result<(), Error> {
+        check_erase_range(from, to)?;
+        self.check_not_busy(from, to)?;
+
+        self.with_icache_disabled(|nvmc| {
+            nvmc.enable_erase();
+            nvmc.wait_ready();
+
+            for page_addr in (from..to).step_by(PAGE_SIZE) {
+                nvmc.erase_page(page_addr);
+                nvmc.wait_ready();
+            }
+
+            nvmc.enable_read();
+            nvmc.wait_ready();
+        });
+
+        Ok(())
+    }
+
+    /// Check whether `len` bytes of flash starting at `offset` are all erased (0xFF).
+    ///
+    /// This only reads the flash, so it can be used to skip erasing pages that are already
+    /// blank, e.g. when resuming an interrupted update.
+    pub fn is_erased(&mut self, offset: u32, len: usize) -> Result<bool, Error> {
+        check_read_range(offset, len)?;
+        self.check_not_busy(offset, offset + len as u32)?;
+
+        let flash_data = unsafe { slice::from_raw_parts(offset as *const u8, len) };
+        Ok(flash_data.iter().all(|&b| b == 0xFF))
+    }
+
+    /// Start erasing the page at `page_addr` in slices of `duration_ms` milliseconds.
+    ///
+    /// This runs the first slice, halting the CPU for `duration_ms`. Call
+    /// [`continue_partial_erase`](Self::continue_partial_erase) to run each of the following
+    /// slices, until it returns [`PartialEraseStatus::Done`]. In between, interrupts are
+    /// serviced and other code can run, so a short duration bounds the latency an erase adds,
+    /// e.g. to keep servicing USB endpoints during DFU.
+    ///
+    /// The page is not usable until all partial erases have completed: its contents are
+    /// undefined, and it must not be read or written in the meantime. A page needs 85 ms of
+    /// partial erase in total, so `duration_ms` is clamped to 1..=85. Starting a new partial erase
+    /// abandons the previous one, leaving that page in an undefined state.
+    ///
+    /// `page_addr` must be page aligned, otherwise [`Error::Unaligned`] is returned.
+    #[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+    pub fn start_partial_erase(&mut self, page_addr: u32, duration_ms: u32) -> Result<(), Error> {
+        check_erase_range(page_addr, page_addr.saturating_add(PAGE_SIZE as u32))?;
+
+        self.partial_erase = Some(PartialErase::new(page_addr, duration_ms));
+        self.continue_partial_erase();
+        Ok(())
+    }
+
+    /// Run the next slice of the partial erase started with
+    /// [`start_partial_erase`](Self::start_partial_erase).
+    ///
+    /// Returns [`PartialEraseStatus::Done`] without erasing anything once the page is erased.
+    #[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+    pub fn continue_partial_erase(&mut self) -> PartialEraseStatus {
+        let Some(mut erase) = self.partial_erase else {
+            return PartialEraseStatus::Done;
+        };
+
+        self.erase_page_slice(erase.page_addr, erase.duration_ms);
+        let status = erase.advance();
+        self.partial_erase = match status {
+            PartialEraseStatus::Done => None,
+            PartialEraseStatus::InProgress => Some(erase),
+        };
+        status
+    }
+
+    #[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+    fn erase_page_slice(&mut self, page_addr: u32, duration_ms: u8) {
+        let p = Self::regs();
+
+        self.with_icache_disabled(|nvmc| {
+            nvmc.enable_erase();
+            nvmc.wait_ready();
+
+            p.erasepagepartialcfg().write(|w| w.set_duration(duration_ms));
+            p.erasepagepartial().write_value(page_addr);
+            nvmc.wait_ready();
+
+            nvmc.enable_read();
+            nvmc.wait_ready();
+        });
+    }
+
+    /// Return [`Error::Busy`] if `[from, to)` overlaps the page being partially erased.
+    fn check_not_busy(&self, from: u32, to: u32) -> Result<(), Error> {
+        #[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+        if let Some(erase) = self.partial_erase
+            && from < erase.page_addr + PAGE_SIZE as u32
+            && erase.page_addr < to
+        {
+            return Err(Error::Busy);
+        }
+        #[cfg(not(all(feature = "_nrf52", not(feature = "nrf52832"))))]
+        let _ = (from, to);
+        Ok(())
     }
 
     fn regs() -> pac::nvmc::Nvmc {
         pac::NVMC
     }
 
+    /// Run `f`, which writes or erases flash, with the instruction cache disabled.
+    ///
+    /// Disabling the cache invalidates it. It is enabled again afterwards if it was enabled before.
+    #[cfg(any(
+        feature = "nrf52832",
+        feature = "nrf52833",
+        feature = "nrf52840",
+        feature = "_nrf5340-net",
+        all(feature = "_nrf91", not(feature = "_ns"))
+    ))]
+    fn with_icache_disabled<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let icachecnf = Self::regs().icachecnf();
+        let cnf = icachecnf.read();
+        if cnf.cacheen() {
+            icachecnf.modify(|w| w.set_cacheen(false));
+        }
+
+        let res = f(self);
+
+        if cnf.cacheen() {
+            icachecnf.write_value(cnf);
+            // Fetch the following instructions through the cache again.
+            cortex_m::asm::isb();
+        }
+        res
+    }
+
+    #[cfg(not(any(
+        feature = "nrf52832",
+        feature = "nrf52833",
+        feature = "nrf52840",
+        feature = "_nrf5340-net",
+        all(feature = "_nrf91", not(feature = "_ns"))
+    )))]
+    fn with_icache_disabled<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        f(self)
+    }
+
     fn wait_ready(&mut self) {
         let p = Self::regs();
         while !p.ready().read().ready() {}
@@ -115,9 +380,8 @@ impl<'d> ReadNorFlash for Nvmc<'d> {
     const READ_SIZE: usize = 1;
 
     fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
-        if offset as usize >= FLASH_SIZE || offset as usize + bytes.len() > FLASH_SIZE {
-            return Err(Error::OutOfBounds);
-        }
+        check_read_range(offset, bytes.len())?;
+        self.check_not_busy(offset, offset + bytes.len() as u32)?;
 
         let flash_data = unsafe { slice::from_raw_parts(offset as *const u8, bytes.len()) };
         bytes.copy_from_slice(flash_data);
@@ -125,60 +389,240 @@ impl<'d> ReadNorFlash for Nvmc<'d> {
     }
 
     fn capacity(&self) -> usize {
-        FLASH_SIZE
+        Self::capacity()
     }
 }
 
 impl<'d> NorFlash for Nvmc<'d> {
-    const WRITE_SIZE: usize = 4;
-    const ERASE_SIZE: usize = PAGE_SIZE;
+    const WRITE_SIZE: usize = Self::write_size();
+    const ERASE_SIZE: usize = Self::page_size();
 
     fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
-        if to < from || to as usize > FLASH_SIZE {
-            return Err(Error::OutOfBounds);
-        }
-        if from as usize % PAGE_SIZE != 0 || to as usize % PAGE_SIZE != 0 {
-            return Err(Error::Unaligned);
+        self.erase_range(from, to)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write_range(offset, bytes.len())?;
+        self.check_not_busy(offset, offset + bytes.len() as u32)?;
+
+        self.with_icache_disabled(|nvmc| {
+            nvmc.enable_write();
+            nvmc.wait_ready();
+
+            unsafe {
+                let p_src = bytes.as_ptr() as *const u32;
+                let p_dst = offset as *mut u32;
+                let words = bytes.len() / 4;
+                for i in 0..words {
+                    let w = ptr::read_unaligned(p_src.add(i));
+                    ptr::write_volatile(p_dst.add(i), w);
+                    nvmc.wait_ready_write();
+                }
+            }
+
+            nvmc.enable_read();
+            nvmc.wait_ready();
+        });
+
+        Ok(())
+    }
+}
+
+/// Bytes written per work item when writing through a [`FlashScheduler`].
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+const WRITE_SLICE_LEN: usize = 256;
+
+/// Maximum time to write one word, in microseconds.
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+const WORD_WRITE_TIME_US: u64 = 41;
+
+/// NVMC wrapper that erases pages in short slices using partial erase.
+///
+/// A regular page erase halts the CPU for up to 85 ms, which can break timing sensitive code
+/// such as BLE connections. This wrapper splits each page erase into slices of a configurable
+/// duration (2 ms by default) and yields to the executor between slices.
+///
+/// It implements the async `embedded-storage` traits, so it can be used as the flash for
+/// `embassy_boot::FirmwareUpdaterConfig::from_linkerfile`. Reads and writes are forwarded to
+/// the blocking [`Nvmc`] implementation.
+///
+/// To keep flash work out of radio events, wrap it in a
+/// [`ScheduledFlash`](embassy_embedded_hal::flash::scheduler::ScheduledFlash), which runs each
+/// erase slice and each 256 byte write chunk through a [`FlashScheduler`].
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+pub struct PartialEraseNvmc<'d> {
+    nvmc: Nvmc<'d>,
+    slice_ms: u8,
+}
+
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+impl<'d> PartialEraseNvmc<'d> {
+    /// Create a partial erase wrapper with the default slice duration of 2 ms.
+    pub fn new(nvmc: Nvmc<'d>) -> Self {
+        Self { nvmc, slice_ms: 2 }
+    }
+
+    /// Set the duration of each partial erase slice, in milliseconds.
+    ///
+    /// The CPU is halted for this long while each slice executes. Valid values are 1 to 85,
+    /// other values are clamped to that range.
+    pub fn set_slice_duration(&mut self, slice_ms: u8) {
+        self.slice_ms = slice_ms.clamp(1, PAGE_ERASE_TIME_MS);
+    }
+
+    /// Release the wrapped [`Nvmc`] driver.
+    pub fn into_inner(self) -> Nvmc<'d> {
+        self.nvmc
+    }
+
+    async fn erase_page_partial(&mut self, page_addr: u32) {
+        let mut elapsed_ms = 0;
+        while elapsed_ms < PAGE_ERASE_TIME_MS {
+            self.erase_slice(page_addr);
+            elapsed_ms = elapsed_ms.saturating_add(self.slice_ms);
+
+            // Let other tasks run between slices.
+            Timer::after_ticks(0).await;
         }
+    }
 
-        self.enable_erase();
-        self.wait_ready();
+    fn erase_slice(&mut self, page_addr: u32) {
+        self.nvmc.erase_page_slice(page_addr, self.slice_ms);
+    }
+}
+
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+impl<'d> ErrorType for PartialEraseNvmc<'d> {
+    type Error = Error;
+}
+
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+impl<'d> embedded_storage_async::nor_flash::ReadNorFlash for PartialEraseNvmc<'d> {
+    const READ_SIZE: usize = <Nvmc as ReadNorFlash>::READ_SIZE;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        ReadNorFlash::read(&mut self.nvmc, offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_SIZE
+    }
+}
+
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+impl<'d> embedded_storage_async::nor_flash::NorFlash for PartialEraseNvmc<'d> {
+    const WRITE_SIZE: usize = <Nvmc as NorFlash>::WRITE_SIZE;
+    const ERASE_SIZE: usize = <Nvmc as NorFlash>::ERASE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase_range(from, to)?;
+        self.nvmc.check_not_busy(from, to)?;
 
         for page_addr in (from..to).step_by(PAGE_SIZE) {
-            self.erase_page(page_addr);
-            self.wait_ready();
+            self.erase_page_partial(page_addr).await;
         }
 
-        self.enable_read();
-        self.wait_ready();
-
         Ok(())
     }
 
-    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
-        if offset as usize + bytes.len() > FLASH_SIZE {
-            return Err(Error::OutOfBounds);
-        }
-        if offset as usize % 4 != 0 || bytes.len() % 4 != 0 {
-            return Err(Error::Unaligned);
-        }
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        NorFlash::write(&mut self.nvmc, offset, bytes)
+    }
+}
 
-        self.enable_write();
-        self.wait_ready();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn erase_range_checks() {
+        let page = PAGE_SIZE as u32;
+        let end = FLASH_SIZE as u32;
+        assert_eq!(check_erase_range(0, page), Ok(()));
+        assert_eq!(check_erase_range(page, page), Ok(()));
+        assert_eq!(check_erase_range(end - page, end), Ok(()));
+        assert_eq!(check_erase_range(1, page), Err(Error::Unaligned));
+        assert_eq!(check_erase_range(0, page + 4), Err(Error::Unaligned));
+        assert_eq!(check_erase_range(end - page, end + page), Err(Error::OutOfBounds));
+        assert_eq!(check_erase_range(2 * page, page), Err(Error::OutOfBounds));
+    }
 
-        unsafe {
-            let p_src = bytes.as_ptr() as *const u32;
-            let p_dst = offset as *mut u32;
-            let words = bytes.len() / 4;
-            for i in 0..words {
-                let w = ptr::read_unaligned(p_src.add(i));
-                ptr::write_volatile(p_dst.add(i), w);
-                self.wait_ready_write();
+    #[test]
+    fn write_and_read_range_checks() {
+        let end = FLASH_SIZE as u32;
+        assert_eq!(check_write_range(0, 4), Ok(()));
+        assert_eq!(check_write_range(end - 4, 4), Ok(()));
+        assert_eq!(check_write_range(2, 4), Err(Error::Unaligned));
+        assert_eq!(check_write_range(0, 3), Err(Error::Unaligned));
+        assert_eq!(check_write_range(end, 4), Err(Error::OutOfBounds));
+        assert_eq!(check_write_range(u32::MAX - 3, usize::MAX - 3), Err(Error::OutOfBounds));
+
+        assert_eq!(check_read_range(1, 3), Ok(()));
+        assert_eq!(check_read_range(end - 1, 1), Ok(()));
+        assert_eq!(check_read_range(end, 0), Err(Error::OutOfBounds));
+        assert_eq!(check_read_range(end - 1, 2), Err(Error::OutOfBounds));
+        assert_eq!(check_read_range(1, usize::MAX), Err(Error::OutOfBounds));
+    }
+
+    #[cfg(all(feature = "_nrf52", not(feature = "nrf52832")))]
+    #[test]
+    fn partial_erase_slices() {
+        let count = |duration_ms| {
+            let mut erase = PartialErase::new(0, duration_ms);
+            let mut slices = 1;
+            while erase.advance() == PartialEraseStatus::InProgress {
+                slices += 1;
+            }
+            slices
+        };
+        assert_eq!(count(1), 85);
+        assert_eq!(count(2), 43);
+        assert_eq!(count(10), 9);
+        assert_eq!(count(85), 1);
+        assert_eq!(count(1000), 1);
+        assert_eq!(count(0), 85);
+    }
+}
+
+#[cfg(all(feature = "time", feature = "_nrf52", not(feature = "nrf52832")))]
+impl<'d> SlicedNorFlash for PartialEraseNvmc<'d> {
+    async fn erase_sliced<M: RawMutex, P: BlackoutPolicy>(
+        &mut self,
+        scheduler: &FlashScheduler<M, P>,
+        from: u32,
+        to: u32,
+    ) -> Result<(), Self::Error> {
+        check_erase_range(from, to)?;
+        self.nvmc.check_not_busy(from, to)?;
+
+        let slice = Duration::from_millis(self.slice_ms as u64);
+        for page_addr in (from..to).step_by(PAGE_SIZE) {
+            let mut elapsed_ms = 0;
+            while elapsed_ms < PAGE_ERASE_TIME_MS {
+                scheduler.run(slice, async { self.erase_slice(page_addr) }).await;
+                elapsed_ms = elapsed_ms.saturating_add(self.slice_ms);
             }
         }
 
-        self.enable_read();
-        self.wait_ready();
+        Ok(())
+    }
+
+    async fn write_sliced<M: RawMutex, P: BlackoutPolicy>(
+        &mut self,
+        scheduler: &FlashScheduler<M, P>,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), Self::Error> {
+        check_write_range(offset, bytes.len())?;
+
+        let mut offset = offset;
+        for chunk in bytes.chunks(WRITE_SLICE_LEN) {
+            let duration = Duration::from_micros(WORD_WRITE_TIME_US * (chunk.len() / 4) as u64);
+            scheduler
+                .run(duration, async { NorFlash::write(&mut self.nvmc, offset, chunk) })
+                .await?;
+            offset += chunk.len() as u32;
+        }
 
         Ok(())
     }
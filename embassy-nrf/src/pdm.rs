@@ -4,7 +4,7 @@
 
 use core::future::poll_fn;
 use core::marker::PhantomData;
-use core::sync::atomic::{Ordering, compiler_fence};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering, compiler_fence};
 use core::task::Poll;
 
 use embassy_hal_internal::drop::OnDrop;
@@ -35,13 +35,32 @@ pub struct InterruptHandler<T: Instance> {
 impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
     unsafe fn on_interrupt() {
         let r = T::regs();
+        let s = T::state();
+
+        let sampling = s.active.load(Ordering::Relaxed);
 
         if r.events_end().read() != 0 {
-            r.intenclr().write(|w| w.set_end(true));
+            if sampling {
+                r.events_end().write_value(0);
+                s.ended.fetch_add(1, Ordering::Release);
+            } else {
+                r.intenclr().write(|w| w.set_end(true));
+            }
         }
 
         if r.events_started().read() != 0 {
-            r.intenclr().write(|w| w.set_started(true));
+            if sampling {
+                // The DMA pointer was latched for the transfer that just started, so queue the
+                // other buffer for the next one. This is done here rather than in the task so
+                // that a slow sampler can't make the next transfer reuse the current buffer.
+                r.events_started().write_value(0);
+                let started = s.started.fetch_add(1, Ordering::Relaxed) + 1;
+                r.sample()
+                    .ptr()
+                    .write_value(s.bufs[started as usize % 2].load(Ordering::Relaxed));
+            } else {
+                r.intenclr().write(|w| w.set_started(true));
+            }
         }
 
         if r.events_stopped().read() != 0 {
@@ -72,6 +91,9 @@ pub enum Error {
     NotRunning,
     /// PDM is already running
     AlreadyRunning,
+    /// A continuous sampler could not keep up: the peripheral started overwriting a buffer
+    /// before the sampler had finished with it.
+    Overrun,
 }
 
 static DUMMY_BUFFER: [i16; 1] = [0; 1];
@@ -251,10 +273,16 @@ impl<'d> Pdm<'d> {
     /// A command is return from the closure that indicates whether the sampling
     /// should continue or stop.
     ///
-    /// NOTE: The time spent within the callback supplied should not exceed the time
-    /// taken to acquire the samples into a single buffer. You should measure the
-    /// time taken by the callback and set the sample buffer size accordingly.
-    /// Exceeding this time can lead to samples becoming dropped.
+    /// The peripheral switches between the two buffers without any gap between them. The
+    /// time spent within the sampler must not exceed the time taken to acquire the samples
+    /// into a single buffer, otherwise the peripheral would overwrite the buffer being
+    /// processed. In that case sampling is stopped and [`Error::Overrun`] is returned, rather
+    /// than passing on corrupted data. You should measure the time taken by the sampler and
+    /// set the sample buffer size accordingly.
+    ///
+    /// The sampling is stopped before returning, and the buffers are only released once the
+    /// peripheral reports it stopped writing to them. Cancellation also stops the sampling, and
+    /// blocks until then.
     pub async fn run_task_sampler<S, const N: usize>(
         &mut self,
         bufs: &mut [[i16; N]; 2],
@@ -263,18 +291,34 @@ impl<'d> Pdm<'d> {
     where
         S: FnMut(&[i16; N]) -> SamplerState,
     {
+        if N == 0 {
+            return Err(Error::BufferZeroLength);
+        }
+        if N > EASY_DMA_SIZE {
+            return Err(Error::BufferTooLong);
+        }
         if self.r.events_started().read() != 0 {
             return Err(Error::AlreadyRunning);
         }
 
-        self.r.sample().ptr().write_value(bufs[0].as_mut_ptr() as u32);
-        self.r.sample().maxcnt().write(|w| w.set_buffsize(N as _));
+        let r = self.r;
+        let state = self.state;
+
+        // Hand the buffers to the interrupt handler, which swaps them on each STARTED event.
+        state.bufs[0].store(bufs[0].as_mut_ptr() as u32, Ordering::Relaxed);
+        state.bufs[1].store(bufs[1].as_mut_ptr() as u32, Ordering::Relaxed);
+        state.started.store(0, Ordering::Relaxed);
+        state.ended.store(0, Ordering::Relaxed);
+        state.active.store(true, Ordering::Relaxed);
+
+        r.sample().ptr().write_value(bufs[0].as_mut_ptr() as u32);
+        r.sample().maxcnt().write(|w| w.set_buffsize(N as _));
 
         // Reset and enable the events
-        self.r.events_end().write_value(0);
-        self.r.events_started().write_value(0);
-        self.r.events_stopped().write_value(0);
-        self.r.intenset().write(|w| {
+        r.events_end().write_value(0);
+        r.events_started().write_value(0);
+        r.events_stopped().write_value(0);
+        r.intenset().write(|w| {
             w.set_end(true);
             w.set_started(true);
             w.set_stopped(true);
@@ -284,60 +328,75 @@ impl<'d> Pdm<'d> {
         // wouldn't happen anyway
         compiler_fence(Ordering::SeqCst);
 
-        self.r.tasks_start().write_value(1);
-
-        let mut current_buffer = 0;
-
-        let mut done = false;
+        r.tasks_start().write_value(1);
 
-        let r = self.r;
-        let drop = OnDrop::new(move || {
+        // In case the future is dropped, stop sampling and wait for the peripheral to release
+        // the buffers.
+        let on_drop = OnDrop::new(move || {
             r.tasks_stop().write_value(1);
             // N.B. It would be better if this were async, but Drop only support sync code
-            while r.events_stopped().read() != 0 {}
+            while r.events_stopped().read() == 0 {}
+            Self::stop_sampler(r, state);
         });
 
-        let state = self.state;
-        let r = self.r;
         // Wait for events and complete when the sampler indicates it has had enough
-        poll_fn(|cx| {
+        let mut delivered: u32 = 0;
+        let result = poll_fn(|cx| {
             state.waker.register(cx.waker());
 
-            if r.events_end().read() != 0 {
-                compiler_fence(Ordering::SeqCst);
+            while state.ended.load(Ordering::Acquire) != delivered {
+                // Once the transfer after the next one has started, it is writing into the
+                // buffer we are about to hand out.
+                if state.ended.load(Ordering::Acquire).wrapping_sub(delivered) > 1 {
+                    return Poll::Ready(Err(Error::Overrun));
+                }
 
-                r.events_end().write_value(0);
-                r.intenset().write(|w| w.set_end(true));
-
-                if !done {
-                    // Discard the last buffer after the user requested a stop
-                    if sampler(&bufs[current_buffer]) == SamplerState::Sampled {
-                        let next_buffer = 1 - current_buffer;
-                        current_buffer = next_buffer;
-                    } else {
-                        r.tasks_stop().write_value(1);
-                        done = true;
-                    };
-                };
-            }
+                compiler_fence(Ordering::SeqCst);
+                let command = sampler(&bufs[delivered as usize % 2]);
+                compiler_fence(Ordering::SeqCst);
 
-            if r.events_started().read() != 0 {
-                r.events_started().write_value(0);
-                r.intenset().write(|w| w.set_started(true));
+                if state.ended.load(Ordering::Acquire).wrapping_sub(delivered) > 1 {
+                    return Poll::Ready(Err(Error::Overrun));
+                }
+                delivered = delivered.wrapping_add(1);
 
-                let next_buffer = 1 - current_buffer;
-                r.sample().ptr().write_value(bufs[next_buffer].as_mut_ptr() as u32);
+                if command == SamplerState::Stopped {
+                    return Poll::Ready(Ok(()));
+                }
             }
 
+            Poll::Pending
+        })
+        .await;
+
+        r.tasks_stop().write_value(1);
+        poll_fn(|cx| {
+            state.waker.register(cx.waker());
             if r.events_stopped().read() != 0 {
-                return Poll::Ready(());
+                Poll::Ready(())
+            } else {
+                Poll::Pending
             }
-
-            Poll::Pending
         })
         .await;
-        drop.defuse();
-        Ok(())
+
+        on_drop.defuse();
+        Self::stop_sampler(r, state);
+
+        result
+    }
+
+    // Detach the interrupt handler from the buffers once the peripheral stopped.
+    fn stop_sampler(r: pac::pdm::Pdm, state: &State) {
+        r.intenclr().write(|w| {
+            w.set_end(true);
+            w.set_started(true);
+            w.set_stopped(true);
+        });
+        state.active.store(false, Ordering::Relaxed);
+        r.events_end().write_value(0);
+        r.events_started().write_value(0);
+        r.events_stopped().write_value(0);
     }
 }
 
@@ -432,12 +491,21 @@ impl<'d> Drop for Pdm<'d> {
 /// Peripheral static state
 pub(crate) struct State {
     waker: AtomicWaker,
+    // Continuous sampling, shared with the interrupt handler.
+    active: AtomicBool,
+    bufs: [AtomicU32; 2],
+    started: AtomicU32,
+    ended: AtomicU32,
 }
 
 impl State {
     pub(crate) const fn new() -> Self {
         Self {
             waker: AtomicWaker::new(),
+            active: AtomicBool::new(false),
+            bufs: [AtomicU32::new(0), AtomicU32::new(0)],
+            started: AtomicU32::new(0),
+            ended: AtomicU32::new(0),
         }
     }
 }
@@ -1,14 +1,183 @@
 //! Power
+//!
+//! System OFF is the deepest power saving mode: the CPU and all peripherals but the wakeup
+//! sources stop, and waking up resets the chip. [`system_off`] configures the wakeup sources and
+//! enters it, then [`wakeup_cause`] tells, after the reset, which source woke the chip.
+//!
+//! ```rust,ignore
+//! if let Some(cause) = power::wakeup_cause() {
+//!     info!("woken up by {}", cause);
+//! }
+//! // ...
+//! power::system_off(&[WakeupSource::Pin {
+//!     pin: p.P0_11.into(),
+//!     level: Level::Low,
+//!     pull: Pull::Up,
+//! }]);
+//! ```
 
-#[cfg(feature = "nrf52840")]
+use embassy_hal_internal::Peri;
+
+#[cfg(feature = "_nrf52")]
 use crate::chip::pac::POWER;
 #[cfg(any(feature = "nrf9160-s", feature = "nrf9160-ns"))]
-use crate::chip::pac::REGULATORS;
+use crate::chip::pac::{POWER, REGULATORS};
+use crate::gpio::{AnyPin, Level, Pull, SealedPin as _, convert_pull};
+use crate::pac;
+use crate::pac::gpio::vals as gpiovals;
 
 /// Puts the MCU into "System Off" mode with minimal power usage
 pub fn set_system_off() {
-    #[cfg(feature = "nrf52840")]
+    #[cfg(feature = "_nrf52")]
     POWER.systemoff().write(|w| w.set_systemoff(true));
     #[cfg(any(feature = "nrf9160-s", feature = "nrf9160-ns"))]
     REGULATORS.systemoff().write(|w| w.set_systemoff(true));
 }
+
+/// A source waking the chip from System OFF.
+pub enum WakeupSource<'a> {
+    /// A pin reaching `level`, detected through its SENSE setting.
+    Pin {
+        /// The pin, configured as an input.
+        pin: Peri<'a, AnyPin>,
+        /// Level waking the chip.
+        level: Level,
+        /// Pull of the input.
+        pull: Pull,
+    },
+    /// The ANADETECT signal of LPCOMP.
+    ///
+    /// The comparator must be left running, see [`lpcomp::Config::detect`](crate::lpcomp::Config::detect).
+    #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+    Lpcomp,
+    /// An NFC field, detected by NFCT. The NFC antenna pins must not be used as GPIOs.
+    #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+    Nfc,
+}
+
+/// Source that woke the chip from System OFF, as read by [`wakeup_cause`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeupCause {
+    /// A pin reached the level of its SENSE setting.
+    Pin,
+    /// The ANADETECT signal of LPCOMP.
+    #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+    Lpcomp,
+    /// An NFC field.
+    #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+    Nfc,
+    /// VBUS rose into its valid range.
+    #[cfg(any(feature = "nrf52820", feature = "nrf52833", feature = "nrf52840"))]
+    Vbus,
+    /// A debugger entered debug interface mode.
+    Debug,
+}
+
+/// Configure the wakeup sources, and enter System OFF.
+///
+/// A pin already at its wakeup level, or a pin left with a SENSE setting by another driver, wakes
+/// the chip right away. RAM is lost unless retained, see [`retain_ram`].
+///
+/// In debug interface mode, System OFF is only emulated: the CPU keeps running, and waits
+/// in a loop for the reset.
+pub fn system_off(wakeup_sources: &[WakeupSource<'_>]) -> ! {
+    for source in wakeup_sources {
+        match source {
+            WakeupSource::Pin { pin, level, pull } => pin.conf().write(|w| {
+                w.set_dir(gpiovals::Dir::INPUT);
+                w.set_input(gpiovals::Input::CONNECT);
+                w.set_pull(convert_pull(*pull));
+                w.set_sense(match level {
+                    Level::Low => gpiovals::Sense::LOW,
+                    Level::High => gpiovals::Sense::HIGH,
+                });
+            }),
+            #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+            WakeupSource::Lpcomp => {
+                let r = pac::LPCOMP;
+                r.events_up().write_value(0);
+                r.events_down().write_value(0);
+                r.events_cross().write_value(0);
+            }
+            #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+            WakeupSource::Nfc => {
+                let r = pac::NFCT;
+                r.events_fielddetected().write_value(0);
+                r.tasks_sense().write_value(1);
+            }
+        }
+    }
+
+    // A latched detection from before would keep the DETECT signal up.
+    #[cfg(not(feature = "_gpio-p1"))]
+    let ports = [pac::P0];
+    #[cfg(feature = "_gpio-p1")]
+    let ports = [pac::P0, pac::P1];
+    for port in ports {
+        port.latch().write(|w| w.0 = 0xFFFF_FFFF);
+    }
+
+    // Clear the previous wakeup causes, for `wakeup_cause` to report this one.
+    POWER.resetreas().write(|w| {
+        w.set_off(true);
+        w.set_dif(true);
+        #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+        {
+            w.set_lpcomp(true);
+            w.set_nfc(true);
+        }
+        #[cfg(any(feature = "nrf52820", feature = "nrf52833", feature = "nrf52840"))]
+        w.set_vbus(true);
+    });
+
+    // Make sure the configuration is written before turning off.
+    cortex_m::asm::dsb();
+    set_system_off();
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
+
+/// Source that woke the chip from System OFF, if it was reset by a wakeup.
+///
+/// The cause is read from RESETREAS, and is cleared when entering System OFF with [`system_off`].
+/// For [`WakeupCause::Pin`], [`gpio::wakeup_source`](crate::gpio::wakeup_source) tells which pin.
+pub fn wakeup_cause() -> Option<WakeupCause> {
+    let reasons = POWER.resetreas().read();
+    if reasons.off() {
+        return Some(WakeupCause::Pin);
+    }
+    #[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+    {
+        if reasons.lpcomp() {
+            return Some(WakeupCause::Lpcomp);
+        }
+        if reasons.nfc() {
+            return Some(WakeupCause::Nfc);
+        }
+    }
+    #[cfg(any(feature = "nrf52820", feature = "nrf52833", feature = "nrf52840"))]
+    if reasons.vbus() {
+        return Some(WakeupCause::Vbus);
+    }
+    if reasons.dif() {
+        return Some(WakeupCause::Debug);
+    }
+    None
+}
+
+/// Retain the RAM sections of `block` whose bits are set in `sections` in System OFF.
+///
+/// RAM blocks and their sections are listed in the memory chapter of the product
+/// specification. Retention costs some current per section.
+#[cfg(feature = "_nrf52")]
+pub fn retain_ram(block: usize, sections: u16) {
+    POWER.ram(block).powerset().write(|w| {
+        for section in 0..16 {
+            if sections & (1 << section) != 0 {
+                w.set_s_retention(section, true);
+            }
+        }
+    });
+}
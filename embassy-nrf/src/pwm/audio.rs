@@ -0,0 +1,645 @@
+//! PWM audio output.
+//!
+//! Uses a single PWM channel running at a high carrier frequency as a crude DAC, which
+//! is enough for beeps, clicks and short voice prompts on a piezo buzzer or a small
+//! speaker behind a transistor and an RC low-pass filter.
+//!
+//! Each PCM sample becomes one PWM duty value, held for as many carrier periods as needed
+//! to approximate the requested sample rate. Samples are streamed through two halves of a
+//! sequence buffer: while the PWM plays one half from RAM, the other half is refilled,
+//! so clips of any length can be played from a slice, a callback or an async byte source.
+//!
+//! The output idles low. Playback ramps up to mid-scale before the first sample and back
+//! down to zero after the last one, so starting and stopping does not produce a pop.
+
+use core::sync::atomic::{Ordering, compiler_fence};
+
+use embassy_hal_internal::Peri;
+use embassy_hal_internal::drop::OnDrop;
+#[cfg(feature = "time")]
+use embassy_time::Duration;
+
+use super::{
+    CNT_UNIT, Config, CounterMode, DutyCycle, Error, Instance, InterruptHandler, MAX_SEQUENCE_LEN, PWM_CLK_HZ,
+    Prescaler, SequenceLoad, SequencePwm, State, pwmseq, stop_streaming, wait_seq_end,
+};
+use crate::gpio::{Level, OutputDrive, Pin as GpioPin};
+use crate::interrupt::typelevel::Interrupt;
+use crate::{interrupt, pac};
+
+/// Audio output configuration.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct AudioConfig {
+    /// Configuration for PWM_CLK.
+    pub prescaler: Prescaler,
+    /// Top value of the PWM counter, i.e. the duty resolution.
+    ///
+    /// Together with the prescaler this sets the carrier frequency. The default of 256 with
+    /// [`Prescaler::Div1`] gives 8-bit resolution at a 62.5 kHz carrier.
+    pub max_duty: u16,
+    /// Requested sample rate in Hz.
+    ///
+    /// Each sample is held for a whole number of carrier periods, so the actual rate is the
+    /// carrier frequency divided by that number. See [`AudioPwm::sample_rate`].
+    pub sample_rate: u32,
+    /// Output volume, where 255 is full scale and 0 is silence.
+    pub volume: u8,
+    /// Number of samples used to ramp between idle and mid-scale at the start and end of a clip.
+    pub ramp_len: u16,
+    /// Drive strength for the output pin.
+    pub drive: OutputDrive,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            prescaler: Prescaler::Div1,
+            max_duty: 256,
+            sample_rate: 8_000,
+            volume: 255,
+            ramp_len: 64,
+            drive: OutputDrive::Standard,
+        }
+    }
+}
+
+/// Encoding of the bytes read by [`AudioPwm::play_from`].
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SampleFormat {
+    /// Unsigned 8-bit PCM, with silence at 128.
+    U8,
+    /// Signed 16-bit little-endian PCM.
+    I16Le,
+}
+
+/// Waveform generated by [`Tone`].
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Waveform {
+    /// Square wave. Loudest on a piezo buzzer.
+    Square,
+    /// Sine wave, from a quarter-wave lookup table.
+    Sine,
+}
+
+/// Infinite tone generator yielding signed 16-bit PCM samples.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Tone {
+    waveform: Waveform,
+    phase: u32,
+    step: u32,
+}
+
+impl Tone {
+    /// Create a tone of `freq` Hz for output at `sample_rate` Hz.
+    pub fn new(waveform: Waveform, freq: u32, sample_rate: u32) -> Self {
+        let step = ((freq as u64) << 32) / sample_rate as u64;
+        Self {
+            waveform,
+            phase: 0,
+            step: step as u32,
+        }
+    }
+}
+
+impl Iterator for Tone {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = match self.waveform {
+            Waveform::Square if self.phase < 0x8000_0000 => i16::MAX,
+            Waveform::Square => -i16::MAX,
+            Waveform::Sine => sine(self.phase),
+        };
+        self.phase = self.phase.wrapping_add(self.step);
+        Some(sample)
+    }
+}
+
+/// sin(k * pi / 128) * 32767 for k in 0..=64.
+const QUARTER_SINE: [i16; 65] = [
+    0, 804, 1608, 2410, 3212, 4011, 4808, 5602, 6393, 7179, 7962, 8739, 9512, 10278, 11039, 11793, 12539, 13279, 14010,
+    14732, 15446, 16151, 16846, 17530, 18204, 18868, 19519, 20159, 20787, 21403, 22005, 22594, 23170, 23731, 24279,
+    24811, 25329, 25832, 26319, 26790, 27245, 27683, 28105, 28510, 28898, 29268, 29621, 29956, 30273, 30571, 30852,
+    31113, 31356, 31580, 31785, 31971, 32137, 32285, 32412, 32521, 32609, 32678, 32728, 32757, 32767,
+];
+
+fn sine(phase: u32) -> i16 {
+    let index = (phase >> 24) as usize;
+    let i = index & 63;
+    match index >> 6 {
+        0 => QUARTER_SINE[i],
+        1 => QUARTER_SINE[64 - i],
+        2 => -QUARTER_SINE[i],
+        _ => -QUARTER_SINE[64 - i],
+    }
+}
+
+/// Convert a signed 16-bit PCM sample to a duty value in `0..=max_duty`.
+///
+/// Silence maps to `max_duty / 2`, and `volume` scales the swing around it, where 255 is
+/// full scale.
+pub fn duty_from_i16(sample: i16, max_duty: u16, volume: u8) -> u16 {
+    let mid = max_duty as i32 / 2;
+    let scaled = sample as i32 * volume as i32 / 255;
+    let offset = (scaled * max_duty as i32) >> 16;
+    (mid + offset).clamp(0, max_duty as i32) as u16
+}
+
+/// Convert an unsigned 8-bit PCM sample to a duty value in `0..=max_duty`.
+///
+/// See [`duty_from_i16`].
+pub fn duty_from_u8(sample: u8, max_duty: u16, volume: u8) -> u16 {
+    duty_from_i16(i16_from_u8(sample), max_duty, volume)
+}
+
+fn i16_from_u8(sample: u8) -> i16 {
+    (sample as i16 - 128) << 8
+}
+
+/// Duty value reached after `step` of `len` steps of a linear ramp from `from` to `to`.
+fn ramp(from: u16, to: u16, step: u16, len: u16) -> u16 {
+    (from as i32 + (to as i32 - from as i32) * step as i32 / len as i32) as u16
+}
+
+/// Sequence word for a duty value. Inverted polarity keeps the output high for `duty`
+/// counts, so a duty of 0 matches the low idle level.
+fn word(duty: u16) -> u16 {
+    DutyCycle::inverted(duty).raw
+}
+
+/// A source of PCM samples.
+trait Source {
+    /// Fill `buf` with samples and return how many were written. Returning fewer than
+    /// `buf.len()` ends the clip.
+    async fn fill(&mut self, buf: &mut [i16]) -> usize;
+}
+
+struct Callback<F>(F);
+
+impl<F: FnMut(&mut [i16]) -> usize> Source for Callback<F> {
+    async fn fill(&mut self, buf: &mut [i16]) -> usize {
+        (self.0)(buf).min(buf.len())
+    }
+}
+
+struct Reader<R: embedded_io_async::Read> {
+    reader: R,
+    format: SampleFormat,
+    low_byte: Option<u8>,
+    error: Option<R::Error>,
+}
+
+impl<R: embedded_io_async::Read> Source for Reader<R> {
+    async fn fill(&mut self, buf: &mut [i16]) -> usize {
+        let mut bytes = [0u8; 64];
+        let mut n = 0;
+        while n < buf.len() {
+            let want = match self.format {
+                SampleFormat::U8 => buf.len() - n,
+                SampleFormat::I16Le => (buf.len() - n) * 2 - self.low_byte.is_some() as usize,
+            }
+            .min(bytes.len());
+            let read = match self.reader.read(&mut bytes[..want]).await {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) => {
+                    self.error = Some(e);
+                    break;
+                }
+            };
+            for &b in &bytes[..read] {
+                match (self.format, self.low_byte.take()) {
+                    (SampleFormat::U8, _) => {
+                        buf[n] = i16_from_u8(b);
+                        n += 1;
+                    }
+                    (SampleFormat::I16Le, None) => self.low_byte = Some(b),
+                    (SampleFormat::I16Le, Some(low)) => {
+                        buf[n] = i16::from_le_bytes([low, b]);
+                        n += 1;
+                    }
+                }
+            }
+        }
+        n
+    }
+}
+
+#[cfg(feature = "time")]
+struct ToneSource {
+    tone: Tone,
+    remaining: usize,
+}
+
+#[cfg(feature = "time")]
+impl Source for ToneSource {
+    async fn fill(&mut self, buf: &mut [i16]) -> usize {
+        let n = buf.len().min(self.remaining);
+        for (out, sample) in buf[..n].iter_mut().zip(&mut self.tone) {
+            *out = sample;
+        }
+        self.remaining -= n;
+        n
+    }
+}
+
+enum Phase {
+    RampIn(u16),
+    Body,
+    RampOut { from: u16, step: u16 },
+    Done,
+}
+
+/// Converts a clip into sequence words, including the ramps around it.
+struct Feed {
+    phase: Phase,
+    max_duty: u16,
+    volume: u8,
+    ramp_len: u16,
+    level: u16,
+}
+
+impl Feed {
+    fn new(max_duty: u16, volume: u8, ramp_len: u16) -> Self {
+        Self {
+            phase: if ramp_len == 0 { Phase::Body } else { Phase::RampIn(0) },
+            max_duty,
+            volume,
+            ramp_len,
+            level: 0,
+        }
+    }
+
+    fn ramp_out(&mut self) {
+        self.phase = if self.ramp_len == 0 {
+            Phase::Done
+        } else {
+            Phase::RampOut {
+                from: self.level,
+                step: 0,
+            }
+        };
+    }
+
+    /// Fill `words` with the next part of the clip, padding with idle once it is over.
+    /// Returns `false` if nothing is left to play after these words.
+    async fn fill(&mut self, words: &mut [u16], scratch: &mut [i16], source: &mut impl Source) -> bool {
+        let mut n = 0;
+        while n < words.len() {
+            match self.phase {
+                Phase::RampIn(step) => {
+                    let step = step + 1;
+                    self.level = ramp(0, self.max_duty / 2, step, self.ramp_len);
+                    words[n] = word(self.level);
+                    n += 1;
+                    self.phase = if step >= self.ramp_len {
+                        Phase::Body
+                    } else {
+                        Phase::RampIn(step)
+                    };
+                }
+                Phase::Body => {
+                    let want = words.len() - n;
+                    let got = source.fill(&mut scratch[..want]).await;
+                    for (w, &sample) in words[n..n + got].iter_mut().zip(&scratch[..got]) {
+                        self.level = duty_from_i16(sample, self.max_duty, self.volume);
+                        *w = word(self.level);
+                    }
+                    n += got;
+                    if got < want {
+                        self.ramp_out();
+                    }
+                }
+                Phase::RampOut { from, step } => {
+                    let step = step + 1;
+                    self.level = ramp(from, 0, step, self.ramp_len);
+                    words[n] = word(self.level);
+                    n += 1;
+                    self.phase = if step >= self.ramp_len {
+                        Phase::Done
+                    } else {
+                        Phase::RampOut { from, step }
+                    };
+                }
+                Phase::Done => {
+                    words[n..].fill(word(0));
+                    return false;
+                }
+            }
+        }
+        !matches!(self.phase, Phase::Done)
+    }
+}
+
+/// PWM audio output on a single pin.
+///
+/// `N` is the length of each half of the internal double buffer, in samples. Refilling a
+/// half must finish before the other half has played, which takes `N` samples at the
+/// sample rate.
+pub struct AudioPwm<'d, const N: usize = 128> {
+    pwm: SequencePwm<'d>,
+    state: &'static State,
+    buffers: [[u16; N]; 2],
+    max_duty: u16,
+    refresh: u32,
+    volume: u8,
+    ramp_len: u16,
+}
+
+impl<'d, const N: usize> AudioPwm<'d, N> {
+    /// Create a new audio output on `pin`.
+    pub fn new<T: Instance>(
+        pwm: Peri<'d, T>,
+        pin: Peri<'d, impl GpioPin>,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        config: AudioConfig,
+    ) -> Result<Self, Error> {
+        if N == 0 || N > MAX_SEQUENCE_LEN {
+            return Err(Error::SequenceTooLong);
+        }
+
+        let pwm_config = Config {
+            counter_mode: CounterMode::Up,
+            max_duty: config.max_duty,
+            prescaler: config.prescaler,
+            sequence_load: SequenceLoad::Common,
+            ch0_drive: config.drive,
+            ch0_idle_level: Level::Low,
+            ..Default::default()
+        };
+        let pwm = SequencePwm::new_1ch(pwm, pin, pwm_config)?;
+
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+
+        let carrier = PWM_CLK_HZ / (1 << config.prescaler as u32) / config.max_duty.max(1) as u32;
+        let periods = (carrier + config.sample_rate / 2) / config.sample_rate.max(1);
+
+        Ok(Self {
+            pwm,
+            state: T::state(),
+            buffers: [[word(0); N]; 2],
+            max_duty: config.max_duty,
+            refresh: periods.max(1) - 1,
+            volume: config.volume,
+            ramp_len: config.ramp_len,
+        })
+    }
+
+    /// PWM carrier frequency in Hz.
+    pub fn carrier_freq(&self) -> u32 {
+        let r = self.pwm.r;
+        let prescaler = r.prescaler().read().prescaler().to_bits() as u32;
+        PWM_CLK_HZ / (1 << prescaler) / self.max_duty.max(1) as u32
+    }
+
+    /// Actual sample rate in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.carrier_freq() / (self.refresh + 1)
+    }
+
+    /// Set the output volume, where 255 is full scale. Takes effect from the next clip.
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+    }
+
+    /// Current output volume.
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Play unsigned 8-bit PCM samples.
+    pub async fn play_u8(&mut self, samples: &[u8]) {
+        let mut samples = samples.iter();
+        self.play_with(|buf| {
+            let mut n = 0;
+            for (out, &sample) in buf.iter_mut().zip(&mut samples) {
+                *out = i16_from_u8(sample);
+                n += 1;
+            }
+            n
+        })
+        .await
+    }
+
+    /// Play signed 16-bit PCM samples.
+    pub async fn play_i16(&mut self, samples: &[i16]) {
+        let mut samples = samples.iter();
+        self.play_with(|buf| {
+            let mut n = 0;
+            for (out, &sample) in buf.iter_mut().zip(&mut samples) {
+                *out = sample;
+                n += 1;
+            }
+            n
+        })
+        .await
+    }
+
+    /// Play signed 16-bit PCM samples produced by a callback.
+    ///
+    /// The callback is called whenever half of the buffer needs refilling. It must fill the
+    /// slice it is given and return the number of samples written. Returning fewer than
+    /// the slice length ends the clip.
+    pub async fn play_with<F>(&mut self, fill: F)
+    where
+        F: FnMut(&mut [i16]) -> usize,
+    {
+        self.stream(&mut Callback(fill)).await
+    }
+
+    /// Play PCM read from an async byte source until it reaches end of file.
+    ///
+    /// A read error ends the clip with the usual ramp down, and is then returned.
+    pub async fn play_from<R: embedded_io_async::Read>(
+        &mut self,
+        reader: R,
+        format: SampleFormat,
+    ) -> Result<(), R::Error> {
+        let mut source = Reader {
+            reader,
+            format,
+            low_byte: None,
+            error: None,
+        };
+        self.stream(&mut source).await;
+        match source.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Play a tone of `freq` Hz for `duration`.
+    #[cfg(feature = "time")]
+    pub async fn play_tone(&mut self, freq: u32, duration: Duration, waveform: Waveform) {
+        let sample_rate = self.sample_rate();
+        let mut source = ToneSource {
+            tone: Tone::new(waveform, freq, sample_rate),
+            remaining: (duration.as_micros() * sample_rate as u64 / 1_000_000) as usize,
+        };
+        self.stream(&mut source).await
+    }
+
+    async fn stream(&mut self, source: &mut impl Source) {
+        let r = self.pwm.r;
+        let mut feed = Feed::new(self.max_duty, self.volume, self.ramp_len);
+        let mut scratch = [0i16; N];
+
+        let mut last = None;
+        if !feed.fill(&mut self.buffers[0], &mut scratch, source).await {
+            last = Some(0);
+        } else if !feed.fill(&mut self.buffers[1], &mut scratch, source).await {
+            last = Some(1);
+        }
+
+        let on_drop = OnDrop::new(move || stop_streaming(r));
+
+        for n in 0..2 {
+            pwmseq(r, n).refresh().write(|w| w.0 = self.refresh);
+            pwmseq(r, n).enddelay().write(|w| w.0 = 0);
+            r.dma().seq(n).ptr().write_value(self.buffers[n].as_ptr() as u32);
+            r.dma().seq(n).maxcnt().write(|w| w.0 = N as u32 * CNT_UNIT);
+            r.events_seqend(n).write_value(0);
+        }
+        r.events_stopped().write_value(0);
+
+        // Play seq0, seq1, seq0, ... until the half holding the end of the clip has played.
+        r.loop_().write(|w| w.set_cnt(pac::pwm::vals::LoopCnt::from_bits(1)));
+        r.shorts().write(|w| match last {
+            Some(0) => w.set_seqend0_stop(true),
+            Some(_) => w.set_seqend1_stop(true),
+            None => w.set_loopsdone_dma_seq0_start(true),
+        });
+
+        r.enable().write(|w| w.set_enable(true));
+
+        // defensive before seqstart
+        compiler_fence(Ordering::SeqCst);
+
+        r.tasks_dma().seq(0).start().write_value(1);
+
+        let mut n = 0;
+        loop {
+            wait_seq_end(r, self.state, n).await;
+            if last == Some(n) {
+                break;
+            }
+            if last.is_none() && !feed.fill(&mut self.buffers[n], &mut scratch, source).await {
+                last = Some(n);
+                // Keep restarting seq0 after seq1 if the end of the clip is in seq0.
+                r.shorts().write(|w| {
+                    if n == 0 {
+                        w.set_loopsdone_dma_seq0_start(true);
+                        w.set_seqend0_stop(true);
+                    } else {
+                        w.set_seqend1_stop(true);
+                    }
+                });
+            }
+            n ^= 1;
+        }
+
+        drop(on_drop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silence_is_mid_scale() {
+        assert_eq!(duty_from_i16(0, 256, 255), 128);
+        assert_eq!(duty_from_u8(128, 256, 255), 128);
+        assert_eq!(duty_from_i16(0, 1000, 100), 500);
+    }
+
+    #[test]
+    fn full_scale_spans_duty_range() {
+        assert_eq!(duty_from_i16(i16::MIN, 256, 255), 0);
+        assert_eq!(duty_from_i16(i16::MAX, 256, 255), 255);
+        assert_eq!(duty_from_u8(0, 256, 255), 0);
+        assert_eq!(duty_from_u8(255, 256, 255), 255);
+        assert_eq!(duty_from_i16(i16::MIN, 1000, 255), 0);
+        assert_eq!(duty_from_i16(i16::MAX, 1000, 255), 999);
+    }
+
+    #[test]
+    fn u8_matches_i16() {
+        for sample in 0..=255u8 {
+            let wide = (sample as i16 - 128) * 256;
+            assert_eq!(duty_from_u8(sample, 256, 255), duty_from_i16(wide, 256, 255));
+        }
+    }
+
+    #[test]
+    fn conversion_is_monotonic() {
+        let mut prev = 0;
+        for sample in (i16::MIN..=i16::MAX).step_by(97) {
+            let duty = duty_from_i16(sample, 256, 255);
+            assert!(duty >= prev);
+            assert!(duty <= 256);
+            prev = duty;
+        }
+    }
+
+    #[test]
+    fn volume_scales_swing() {
+        assert_eq!(duty_from_i16(i16::MAX, 256, 0), 128);
+        assert_eq!(duty_from_i16(i16::MIN, 256, 0), 128);
+        assert_eq!(duty_from_i16(i16::MIN, 256, 128), 63);
+        assert_eq!(duty_from_i16(i16::MAX, 256, 128), 192);
+    }
+
+    #[test]
+    fn ramp_is_linear() {
+        assert_eq!(ramp(0, 128, 0, 4), 0);
+        assert_eq!(ramp(0, 128, 1, 4), 32);
+        assert_eq!(ramp(0, 128, 4, 4), 128);
+        assert_eq!(ramp(200, 0, 2, 4), 100);
+        assert_eq!(ramp(200, 0, 4, 4), 0);
+    }
+
+    #[test]
+    fn words_use_inverted_polarity() {
+        assert_eq!(word(0), 0x8000);
+        assert_eq!(word(128), 0x8080);
+    }
+
+    #[test]
+    fn square_tone() {
+        let samples: [i16; 8] = core::array::from_fn({
+            let mut tone = Tone::new(Waveform::Square, 1000, 8000);
+            move |_| tone.next().unwrap()
+        });
+        assert_eq!(
+            samples,
+            [
+                i16::MAX,
+                i16::MAX,
+                i16::MAX,
+                i16::MAX,
+                -i16::MAX,
+                -i16::MAX,
+                -i16::MAX,
+                -i16::MAX
+            ]
+        );
+    }
+
+    #[test]
+    fn sine_tone() {
+        let samples: [i16; 4] = core::array::from_fn({
+            let mut tone = Tone::new(Waveform::Sine, 2000, 8000);
+            move |_| tone.next().unwrap()
+        });
+        assert_eq!(samples, [0, 32767, 0, -32767]);
+        assert_eq!(sine(0x2000_0000), 23170);
+        assert_eq!(sine(0xE000_0000), -23170);
+    }
+}
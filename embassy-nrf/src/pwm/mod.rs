@@ -2,17 +2,53 @@
 
 #![macro_use]
 
+use core::future::poll_fn;
+use core::marker::PhantomData;
 use core::sync::atomic::{Ordering, compiler_fence};
+use core::task::Poll;
 
+use embassy_hal_internal::drop::OnDrop;
 use embassy_hal_internal::{Peri, PeripheralType};
+use embassy_sync::waitqueue::AtomicWaker;
 
 use crate::gpio::{AnyPin, DISCONNECTED, Level, OutputDrive, Pin as GpioPin, PselBits, SealedPin as _, convert_drive};
+use crate::interrupt::typelevel::Interrupt;
 use crate::pac::gpio::vals as gpiovals;
 use crate::pac::pwm::vals;
 use crate::ppi::{Event, Task};
 use crate::util::slice_in_ram_or;
 use crate::{interrupt, pac};
 
+pub mod audio;
+
+/// Interrupt handler.
+///
+/// Only needed by drivers that wait for sequence events, such as [`audio::AudioPwm`] and
+/// [`SequencePwm::run_streamed`].
+pub struct InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        let r = T::regs();
+
+        if r.events_seqend(0).read() != 0 {
+            r.intenclr().write(|w| w.set_seqend0(true));
+        }
+
+        if r.events_seqend(1).read() != 0 {
+            r.intenclr().write(|w| w.set_seqend1(true));
+        }
+
+        if r.events_stopped().read() != 0 {
+            r.intenclr().write(|w| w.set_stopped(true));
+        }
+
+        T::state().waker.wake();
+    }
+}
+
 /// SimplePwm is the traditional pwm interface you're probably used to, allowing
 /// to simply set a duty cycle across up to four channels.
 pub struct SimplePwm<'d> {
@@ -45,6 +81,8 @@ pub enum Error {
     SequenceTimesAtLeastOne,
     /// EasyDMA can only read from data memory, read only buffers in flash will fail.
     BufferNotInRAM,
+    /// A streamed buffer was not refilled before the other buffer finished playing.
+    BufferUnderrun,
 }
 
 const MAX_SEQUENCE_LEN: usize = 32767;
@@ -155,6 +193,122 @@ impl<'d> SequencePwm<'d> {
         Ok(Self { r, ch0, ch1, ch2, ch3 })
     }
 
+    /// Play a stream of duty cycle words, refilling each buffer while the other one plays.
+    ///
+    /// `bufs` are played as sequence 0 and sequence 1 in turn, both with the refresh and end
+    /// delay of `config`. Whenever a buffer has finished playing, `fill` is awaited to write
+    /// the next words into it, and returns how many it wrote. Returning fewer than the
+    /// buffer length ends the stream once those words have played, and returning 0 ends it
+    /// after the other buffer. The word layout is the same as for [`Sequence`], as set by
+    /// [`Config::sequence_load`].
+    ///
+    /// `fill` must finish before the other buffer has played. Otherwise the peripheral would
+    /// play stale words, so playback is stopped and [`Error::BufferUnderrun`] is returned. A
+    /// refill finishing right at the deadline may also be reported as an underrun.
+    ///
+    /// `T` must be the instance this `SequencePwm` was created with.
+    pub async fn run_streamed<T: Instance, const N: usize>(
+        &mut self,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>>,
+        bufs: &mut [[u16; N]; 2],
+        config: &SequenceConfig,
+        mut fill: impl AsyncFnMut(&mut [u16]) -> usize,
+    ) -> Result<(), Error> {
+        assert!(T::regs() == self.r, "Interrupt binding of another PWM instance");
+        if N == 0 || N > MAX_SEQUENCE_LEN {
+            return Err(Error::SequenceTooLong);
+        }
+        slice_in_ram_or(bufs.as_flattened(), Error::BufferNotInRAM)?;
+
+        let r = self.r;
+        let state = T::state();
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+
+        // Index of the buffer holding the end of the stream, once it is known.
+        let mut last = None;
+        let mut lens = [N; 2];
+        lens[0] = fill(&mut bufs[0]).await.min(N);
+        if lens[0] == 0 {
+            return Ok(());
+        } else if lens[0] < N {
+            last = Some(0);
+        } else {
+            lens[1] = fill(&mut bufs[1]).await.min(N);
+            if lens[1] == 0 {
+                last = Some(0);
+            } else if lens[1] < N {
+                last = Some(1);
+            }
+        }
+
+        let on_drop = OnDrop::new(move || stop_streaming(r));
+
+        for n in 0..2 {
+            pwmseq(r, n).refresh().write(|w| w.0 = config.refresh);
+            pwmseq(r, n).enddelay().write(|w| w.0 = config.end_delay);
+            r.dma().seq(n).ptr().write_value(bufs[n].as_ptr() as u32);
+            r.dma().seq(n).maxcnt().write(|w| w.0 = lens[n] as u32 * CNT_UNIT);
+            r.events_seqend(n).write_value(0);
+        }
+
+        // Play seq0, seq1, seq0, ... until the buffer holding the end of the stream has played.
+        r.loop_().write(|w| w.set_cnt(vals::LoopCnt::from_bits(1)));
+        r.shorts().write(|w| match last {
+            Some(0) => w.set_seqend0_stop(true),
+            Some(_) => w.set_seqend1_stop(true),
+            None => w.set_loopsdone_dma_seq0_start(true),
+        });
+
+        r.enable().write(|w| w.set_enable(true));
+
+        // defensive before seqstart
+        compiler_fence(Ordering::SeqCst);
+
+        r.tasks_dma().seq(0).start().write_value(1);
+
+        let mut n = 0;
+        loop {
+            wait_seq_end(r, state, n).await;
+            if last == Some(n) {
+                break;
+            }
+            if last.is_none() {
+                let len = fill(&mut bufs[n]).await.min(N);
+                if len == 0 {
+                    last = Some(n ^ 1);
+                } else {
+                    r.dma().seq(n).maxcnt().write(|w| w.0 = len as u32 * CNT_UNIT);
+                    if len < N {
+                        last = Some(n);
+                    }
+                }
+                if let Some(end) = last {
+                    // Keep restarting seq0 after seq1 if the end of the stream is in seq0.
+                    r.shorts().write(|w| {
+                        if end == 0 {
+                            w.set_loopsdone_dma_seq0_start(true);
+                            w.set_seqend0_stop(true);
+                        } else {
+                            w.set_seqend1_stop(true);
+                        }
+                    });
+                }
+
+                compiler_fence(Ordering::SeqCst);
+                if r.events_seqend(n ^ 1).read() != 0 {
+                    // The other buffer ended while this one was being refilled, so the
+                    // peripheral has already moved on to stale words.
+                    return Err(Error::BufferUnderrun);
+                }
+            }
+            n ^= 1;
+        }
+
+        drop(on_drop);
+        Ok(())
+    }
+
     /// Returns reference to `Stopped` event endpoint for PPI.
     #[inline(always)]
     pub fn event_stopped(&self) -> Event<'d> {
@@ -552,6 +706,42 @@ impl<'d, 's> Drop for Sequencer<'d, 's> {
     }
 }
 
+/// Wait for the end of sequence `n` of a streamed playback.
+pub(crate) async fn wait_seq_end(r: pac::pwm::Pwm, state: &State, n: usize) {
+    poll_fn(|cx| {
+        state.waker.register(cx.waker());
+
+        if r.events_seqend(n).read() != 0 {
+            r.events_seqend(n).write_value(0);
+            return Poll::Ready(());
+        }
+
+        r.intenset().write(|w| {
+            if n == 0 {
+                w.set_seqend0(true);
+            } else {
+                w.set_seqend1(true);
+            }
+        });
+        Poll::Pending
+    })
+    .await
+}
+
+/// Stop a streamed playback.
+pub(crate) fn stop_streaming(r: pac::pwm::Pwm) {
+    r.shorts().write(|_| ());
+    r.intenclr().write(|w| {
+        w.set_seqend0(true);
+        w.set_seqend1(true);
+    });
+
+    compiler_fence(Ordering::SeqCst);
+
+    r.tasks_stop().write_value(1);
+    r.enable().write(|w| w.set_enable(false));
+}
+
 /// How many times to run a single sequence
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -998,8 +1188,21 @@ impl<'a> Drop for SimplePwm<'a> {
     }
 }
 
+pub(crate) struct State {
+    waker: AtomicWaker,
+}
+
+impl State {
+    pub(crate) const fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
 pub(crate) trait SealedInstance {
     fn regs() -> pac::pwm::Pwm;
+    fn state() -> &'static State;
 }
 
 /// PWM peripheral instance.
@@ -1015,6 +1218,10 @@ macro_rules! impl_pwm {
             fn regs() -> pac::pwm::Pwm {
                 pac::$pac_type
             }
+            fn state() -> &'static crate::pwm::State {
+                static STATE: crate::pwm::State = crate::pwm::State::new();
+                &STATE
+            }
         }
         impl crate::pwm::Instance for peripherals::$type {
             type Interrupt = crate::interrupt::typelevel::$irq;
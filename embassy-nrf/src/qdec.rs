@@ -25,7 +25,7 @@ pub struct Qdec<'d> {
 /// QDEC config
 #[non_exhaustive]
 pub struct Config {
-    /// Number of samples
+    /// Number of samples between reports, the reporting period.
     pub num_samples: NumSamples,
     /// Sample period
     pub period: SamplePeriod,
@@ -56,7 +56,10 @@ pub struct InterruptHandler<T: Instance> {
 
 impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
     unsafe fn on_interrupt() {
-        T::regs().intenclr().write(|w| w.set_reportrdy(true));
+        T::regs().intenclr().write(|w| {
+            w.set_reportrdy(true);
+            w.set_accof(true);
+        });
         T::state().waker.wake();
     }
 }
@@ -140,6 +143,19 @@ impl<'d> Qdec<'d> {
             SamplePeriod::_131ms => w.set_sampleper(vals::Sampleper::_131MS),
         });
 
+        // Set number of samples between reports
+        r.reportper().write(|w| match config.num_samples {
+            NumSamples::_10smpl => w.set_reportper(vals::Reportper::_10SMPL),
+            NumSamples::_40smpl => w.set_reportper(vals::Reportper::_40SMPL),
+            NumSamples::_80smpl => w.set_reportper(vals::Reportper::_80SMPL),
+            NumSamples::_120smpl => w.set_reportper(vals::Reportper::_120SMPL),
+            NumSamples::_160smpl => w.set_reportper(vals::Reportper::_160SMPL),
+            NumSamples::_200smpl => w.set_reportper(vals::Reportper::_200SMPL),
+            NumSamples::_240smpl => w.set_reportper(vals::Reportper::_240SMPL),
+            NumSamples::_280smpl => w.set_reportper(vals::Reportper::_280SMPL),
+            NumSamples::_1smpl => w.set_reportper(vals::Reportper::_1SMPL),
+        });
+
         T::Interrupt::unpend();
         unsafe { T::Interrupt::enable() };
 
@@ -157,7 +173,8 @@ impl<'d> Qdec<'d> {
     }
 
     /// Perform an asynchronous read of the decoder.
-    /// The returned future can be awaited to obtain the number of steps.
+    /// The returned future can be awaited to obtain the number of steps since the last read,
+    /// once it isn't zero at the end of a reporting period.
     ///
     /// If the future is dropped, the read is cancelled.
     ///
@@ -178,26 +195,58 @@ impl<'d> Qdec<'d> {
     /// let delta = q.read().await;
     /// # };
     /// ```
-    pub async fn read(&mut self) -> i16 {
-        self.r.intenset().write(|w| w.set_reportrdy(true));
-        self.r.tasks_readclracc().write_value(1);
+    pub async fn read(&mut self) -> Result<i16, Error> {
+        loop {
+            let state = self.state;
+            let r = self.r;
+            r.intenset().write(|w| {
+                w.set_reportrdy(true);
+                w.set_accof(true);
+            });
+            poll_fn(move |cx| {
+                state.waker.register(cx.waker());
+                if r.events_reportrdy().read() == 0 && r.events_accof().read() == 0 {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(())
+                }
+            })
+            .await;
 
-        let state = self.state;
-        let r = self.r;
-        poll_fn(move |cx| {
-            state.waker.register(cx.waker());
-            if r.events_reportrdy().read() == 0 {
-                Poll::Pending
-            } else {
-                r.events_reportrdy().write_value(0);
-                let acc = r.accread().read();
-                Poll::Ready(acc as i16)
+            // A report left over from before the last read has no steps.
+            match self.try_read() {
+                Ok(0) => {}
+                res => return res,
             }
-        })
-        .await
+        }
+    }
+
+    /// Read the number of steps since the last read, without waiting.
+    pub fn try_read(&mut self) -> Result<i16, Error> {
+        let r = self.r;
+        r.events_reportrdy().write_value(0);
+        r.tasks_readclracc().write_value(1);
+        let acc = r.accread().read();
+
+        if r.events_accof().read() != 0 {
+            r.events_accof().write_value(0);
+            return Err(Error::Overflow);
+        }
+        Ok(acc as i16)
     }
 }
 
+/// QDEC error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The accumulator overflowed, more than 1023 steps in either direction since the last read.
+    ///
+    /// Steps were lost, and the accumulator was cleared.
+    Overflow,
+}
+
 /// Sample period
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum SamplePeriod {
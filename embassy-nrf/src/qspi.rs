@@ -4,9 +4,11 @@
 
 use core::future::{Future, poll_fn};
 use core::marker::PhantomData;
+use core::ops::Range;
 use core::ptr;
 use core::task::Poll;
 
+use embassy_futures::yield_now;
 use embassy_hal_internal::drop::OnDrop;
 use embassy_hal_internal::{Peri, PeripheralType};
 use embassy_sync::waitqueue::AtomicWaker;
@@ -90,6 +92,11 @@ pub struct Config {
     pub address_mode: AddressMode,
     /// Flash memory capacity in bytes. This is the value reported by the `embedded-storage` traits.
     pub capacity: u32,
+    /// Flash addresses used through XIP, such as the code executed from the flash.
+    ///
+    /// Writes and erases overlapping it return [`Error::XipRegion`] instead of corrupting the
+    /// code being executed.
+    pub xip_region: Option<Range<u32>>,
 }
 
 impl Default for Config {
@@ -106,6 +113,7 @@ impl Default for Config {
             spi_mode: SpiMode::MODE0,
             address_mode: AddressMode::_24BIT,
             capacity: 0,
+            xip_region: None,
         }
     }
 }
@@ -117,6 +125,8 @@ impl Default for Config {
 pub enum Error {
     /// Operation address was out of bounds.
     OutOfBounds,
+    /// Operation would write or erase [`Config::xip_region`].
+    XipRegion,
     // TODO add "not in data memory" error and check for it
 }
 
@@ -143,6 +153,7 @@ pub struct Qspi<'d> {
     state: &'static State,
     dpm_enabled: bool,
     capacity: u32,
+    xip_region: Option<Range<u32>>,
     _phantom: PhantomData<&'d ()>,
 }
 
@@ -220,6 +231,7 @@ impl<'d> Qspi<'d> {
             state: T::state(),
             dpm_enabled: config.deep_power_down.is_some(),
             capacity: config.capacity,
+            xip_region: config.xip_region,
             _phantom: PhantomData,
         };
 
@@ -465,6 +477,7 @@ impl<'d> Qspi<'d> {
     /// Write data to the flash memory.
     pub async fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
         self.bounds_check(address, data.len())?;
+        self.xip_check(address, data.len() as u32)?;
         self.write_raw(address, data).await
     }
 
@@ -473,6 +486,7 @@ impl<'d> Qspi<'d> {
         if address >= self.capacity {
             return Err(Error::OutOfBounds);
         }
+        self.xip_check(address, ERASE_SIZE)?;
 
         let ondrop = OnDrop::new(Self::blocking_wait_ready);
 
@@ -493,6 +507,7 @@ impl<'d> Qspi<'d> {
     /// Write data to the flash memory, blocking version.
     pub fn blocking_write(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
         self.bounds_check(address, data.len())?;
+        self.xip_check(address, data.len() as u32)?;
         self.blocking_write_raw(address, data)
     }
 
@@ -501,6 +516,7 @@ impl<'d> Qspi<'d> {
         if address >= self.capacity {
             return Err(Error::OutOfBounds);
         }
+        self.xip_check(address, ERASE_SIZE)?;
 
         self.start_erase(address)?;
         Self::blocking_wait_ready();
@@ -515,6 +531,124 @@ impl<'d> Qspi<'d> {
         }
         Ok(())
     }
+
+    /// Check that `address..address + len` doesn't overlap the XIP region. Must be called after
+    /// the bounds check, so the end doesn't overflow.
+    fn xip_check(&self, address: u32, len: u32) -> Result<(), Error> {
+        if let Some(xip) = &self.xip_region
+            && address < xip.end
+            && xip.start < address + len
+        {
+            return Err(Error::XipRegion);
+        }
+        Ok(())
+    }
+
+    /// Run `f` with XIP and the instruction cache disabled, to erase or write the flash while
+    /// code is executed from it.
+    ///
+    /// An XIP access during an erase or write would not read the flash contents, so `f` runs
+    /// in a critical section: no interrupt handler located in the QSPI flash can run meanwhile.
+    /// Neither `f` nor the code calling this method may be located in the QSPI flash, and `f`
+    /// must use the blocking operations, which return once the flash is done.
+    ///
+    /// On the nRF5340, XIP is disabled with `XIPEN`, so a stray access faults instead of
+    /// returning garbage. On the nRF52840, XIP can't be disabled and is only kept idle. The
+    /// instruction cache is disabled during `f`, so no stale code remains cached after it,
+    /// except on non-secure nRF5340 builds, which have no access to the cache.
+    ///
+    /// Interrupts are masked for the whole duration of `f`: a sector erase can take hundreds of
+    /// milliseconds. [`erase_with_xip_suspended`](Self::erase_with_xip_suspended) and
+    /// [`write_with_xip_suspended`](Self::write_with_xip_suspended) bound this to a single
+    /// sector or page.
+    pub fn with_xip_suspended<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        critical_section::with(|_| {
+            let cache_enabled = suspend_xip(self.r);
+            let res = f(self);
+            resume_xip(self.r, cache_enabled);
+            res
+        })
+    }
+
+    /// Erase the sectors in `from..to`, one at a time, each with XIP suspended.
+    ///
+    /// See [`with_xip_suspended`](Self::with_xip_suspended). XIP is enabled again and other
+    /// tasks run between sectors, so interrupts are masked for one sector erase at a time.
+    pub async fn erase_with_xip_suspended(&mut self, from: u32, to: u32) -> Result<(), Error> {
+        if from > to || to > self.capacity {
+            return Err(Error::OutOfBounds);
+        }
+        self.xip_check(from, to - from)?;
+
+        for address in (from..to).step_by(ERASE_SIZE as usize) {
+            self.with_xip_suspended(|qspi| qspi.blocking_erase(address))?;
+            yield_now().await;
+        }
+        Ok(())
+    }
+
+    /// Write data to the flash memory, one page at a time, each with XIP suspended.
+    ///
+    /// See [`with_xip_suspended`](Self::with_xip_suspended). XIP is enabled again and other
+    /// tasks run between pages of 256 bytes, so interrupts are masked for one page write at a
+    /// time.
+    pub async fn write_with_xip_suspended(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        self.bounds_check(address, data.len())?;
+        self.xip_check(address, data.len() as u32)?;
+
+        for (i, chunk) in data.chunks(XIP_WRITE_CHUNK).enumerate() {
+            let address = address + (i * XIP_WRITE_CHUNK) as u32;
+            self.with_xip_suspended(|qspi| qspi.blocking_write(address, chunk))?;
+            yield_now().await;
+        }
+        Ok(())
+    }
+}
+
+const ERASE_SIZE: u32 = 4096;
+
+/// Bytes written at a time by [`Qspi::write_with_xip_suspended`].
+const XIP_WRITE_CHUNK: usize = 256;
+
+/// Disable XIP and the instruction cache. Returns whether the cache was enabled.
+fn suspend_xip(r: pac::qspi::Qspi) -> bool {
+    #[cfg(feature = "_nrf5340-app")]
+    r.xipen().write(|w| w.set_xipen(false));
+    #[cfg(not(feature = "_nrf5340-app"))]
+    let _ = r;
+
+    #[cfg(feature = "nrf52840")]
+    {
+        let enabled = pac::NVMC.icachecnf().read().cacheen();
+        pac::NVMC.icachecnf().modify(|w| w.set_cacheen(false));
+        enabled
+    }
+    #[cfg(all(feature = "_nrf5340-app", feature = "_s"))]
+    {
+        let enabled = pac::CACHE.enable().read().enable();
+        pac::CACHE.enable().write(|w| w.set_enable(false));
+        enabled
+    }
+    #[cfg(all(feature = "_nrf5340-app", feature = "_ns"))]
+    false
+}
+
+/// Undo [`suspend_xip`].
+fn resume_xip(r: pac::qspi::Qspi, cache_enabled: bool) {
+    if cache_enabled {
+        #[cfg(feature = "nrf52840")]
+        pac::NVMC.icachecnf().modify(|w| w.set_cacheen(true));
+        #[cfg(all(feature = "_nrf5340-app", feature = "_s"))]
+        {
+            pac::CACHE.invalidate().write(|w| w.set_invalidate(true));
+            pac::CACHE.enable().write(|w| w.set_enable(true));
+        }
+    }
+
+    #[cfg(feature = "_nrf5340-app")]
+    r.xipen().write(|w| w.set_xipen(true));
+    #[cfg(not(feature = "_nrf5340-app"))]
+    let _ = r;
 }
 
 impl<'d> Drop for Qspi<'d> {
@@ -583,7 +717,7 @@ impl<'d> ReadNorFlash for Qspi<'d> {
 
 impl<'d> NorFlash for Qspi<'d> {
     const WRITE_SIZE: usize = 4;
-    const ERASE_SIZE: usize = 4096;
+    const ERASE_SIZE: usize = ERASE_SIZE as usize;
 
     fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
         for address in (from..to).step_by(<Self as NorFlash>::ERASE_SIZE) {
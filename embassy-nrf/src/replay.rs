@@ -0,0 +1,201 @@
+//! Deterministic replay of peripheral event traces, for host tests of interrupt-driven drivers.
+//!
+//! Drivers reach their peripheral through the PAC register block they hold, which is only a
+//! pointer. In tests it can point at a [`FakeRegs`] block in host memory, where the trace plays
+//! the hardware: [`replay`] raises events, runs the interrupt handler and polls the driver future
+//! in exactly the order of the trace, and checks the tasks the driver triggered and the wakes
+//! it received in between. Nothing changes for hardware builds, where the register block still
+//! points at the peripheral.
+//!
+//! Tasks are write-only, so the fake block keeps the last value written to them: the driver
+//! triggering a task leaves a 1, which [`Step::Task`] checks and clears.
+
+extern crate std;
+
+use core::future::Future;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll};
+use std::boxed::Box;
+use std::sync::Arc;
+use std::task::Wake;
+
+/// Size of a peripheral's register block, in words.
+const BLOCK_WORDS: usize = 0x1000 / 4;
+
+/// Register block of a fake peripheral, in host memory.
+///
+/// All registers read 0 until written. The block is leaked, so that register blocks pointing at
+/// it can be copied into drivers with any lifetime.
+pub(crate) struct FakeRegs {
+    base: *mut u32,
+}
+
+impl FakeRegs {
+    pub(crate) fn new() -> Self {
+        let block = Box::leak(Box::new([0u32; BLOCK_WORDS]));
+        Self {
+            base: block.as_mut_ptr(),
+        }
+    }
+
+    /// Pointer to the block, for the PAC `from_ptr` constructors.
+    pub(crate) fn ptr(&self) -> *mut () {
+        self.base as _
+    }
+
+    fn check(&self, reg: *mut u32) {
+        let offset = (reg as usize).wrapping_sub(self.base as usize);
+        assert!(offset < BLOCK_WORDS * 4, "register {:p} is outside the block", reg);
+    }
+
+    /// Read a register.
+    pub(crate) fn get(&self, reg: *mut u32) -> u32 {
+        self.check(reg);
+        unsafe { ptr::read_volatile(reg) }
+    }
+
+    /// Write a register, as the peripheral would.
+    pub(crate) fn set(&self, reg: *mut u32, value: u32) {
+        self.check(reg);
+        unsafe { ptr::write_volatile(reg, value) }
+    }
+}
+
+/// A step of a recorded trace.
+pub(crate) enum Step {
+    /// The peripheral sets a register, typically an event to 1.
+    Set(*mut u32, u32),
+    /// The interrupt fires, and its handler runs.
+    Interrupt,
+    /// The executor polls the driver future, which must still be pending.
+    Pending,
+    /// The executor polls the driver future, which must complete.
+    Ready,
+    /// The driver future is dropped, cancelling the operation.
+    Drop,
+    /// The driver must have triggered this task since it was last checked.
+    Task(*mut u32),
+    /// The driver must not have triggered this task since it was last checked.
+    NoTask(*mut u32),
+    /// The number of times the driver future was woken since the last `Wakes`.
+    Wakes(usize),
+}
+
+struct CountingWaker(AtomicUsize);
+
+/// Leaks the future when a step fails, as its drop may wait for an event the trace doesn't raise.
+struct LeakOnPanic<T>(Option<T>);
+
+impl<T> core::ops::Drop for LeakOnPanic<T> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            core::mem::forget(self.0.take());
+        }
+    }
+}
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Replay `trace` against the driver future `fut`, with `on_interrupt` running the driver's
+/// interrupt handler on `regs`.
+///
+/// Returns the output of the future if the trace completed it, or `None` if the trace dropped
+/// it. Panics with the index of the first step that doesn't match, and if the trace leaves the
+/// future pending.
+pub(crate) fn replay<F: Future>(
+    regs: &FakeRegs,
+    fut: F,
+    mut on_interrupt: impl FnMut(),
+    trace: &[Step],
+) -> Option<F::Output> {
+    let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = counter.clone().into();
+    let mut cx = Context::from_waker(&waker);
+
+    let mut fut = LeakOnPanic(Some(Box::pin(fut)));
+    let mut output = None;
+    let mut checked_wakes = 0;
+
+    for (i, step) in trace.iter().enumerate() {
+        match *step {
+            Step::Set(reg, value) => regs.set(reg, value),
+            Step::Interrupt => on_interrupt(),
+            Step::Pending | Step::Ready => {
+                let Some(f) = fut.0.as_mut() else {
+                    panic!("step {}: polled after the future was dropped or completed", i);
+                };
+                match (f.as_mut().poll(&mut cx), step) {
+                    (Poll::Pending, Step::Pending) => {}
+                    (Poll::Ready(out), Step::Ready) => {
+                        output = Some(out);
+                        fut.0 = None;
+                    }
+                    (Poll::Pending, _) => panic!("step {}: the future is still pending", i),
+                    (Poll::Ready(_), _) => panic!("step {}: the future completed early", i),
+                }
+            }
+            Step::Drop => {
+                assert!(
+                    fut.0.is_some(),
+                    "step {}: the future was already dropped or completed",
+                    i
+                );
+                fut.0 = None;
+            }
+            Step::Task(reg) => {
+                assert_eq!(regs.get(reg), 1, "step {}: the task wasn't triggered", i);
+                regs.set(reg, 0);
+            }
+            Step::NoTask(reg) => assert_eq!(regs.get(reg), 0, "step {}: the task was triggered", i),
+            Step::Wakes(n) => {
+                let wakes = counter.0.load(Ordering::Relaxed);
+                assert_eq!(wakes - checked_wakes, n, "step {}: unexpected wake count", i);
+                checked_wakes = wakes;
+            }
+        }
+    }
+
+    assert!(fut.0.is_none(), "the trace left the future pending");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_order() {
+        let regs = FakeRegs::new();
+        let event = regs.base;
+        let task = unsafe { regs.base.add(1) };
+
+        let fut = core::future::poll_fn(|cx| {
+            if regs.get(event) != 0 {
+                return Poll::Ready(7);
+            }
+            regs.set(task, 1);
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        });
+        let out = replay(
+            &regs,
+            fut,
+            || {},
+            &[
+                Step::NoTask(task),
+                Step::Pending,
+                Step::Task(task),
+                Step::Wakes(1),
+                Step::Set(event, 1),
+                Step::Ready,
+                Step::Wakes(0),
+            ],
+        );
+        assert_eq!(out, Some(7));
+    }
+}
@@ -4,7 +4,7 @@
 
 use core::future::poll_fn;
 use core::marker::PhantomData;
-use core::sync::atomic::{Ordering, compiler_fence};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering, compiler_fence};
 use core::task::Poll;
 
 use embassy_hal_internal::drop::OnDrop;
@@ -23,7 +23,14 @@ use crate::{interrupt, pac, peripherals};
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[non_exhaustive]
-pub enum Error {}
+pub enum Error {
+    /// A continuous sampler could not keep up: the peripheral started overwriting a buffer
+    /// before the callback had finished with it.
+    Overrun,
+    /// The secure firmware didn't assign the peripheral to the non-secure domain.
+    #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+    NotAssignedToNonSecure,
+}
 
 /// Interrupt handler.
 pub struct InterruptHandler {
@@ -39,13 +46,35 @@ impl interrupt::typelevel::Handler<interrupt::typelevel::SAADC> for InterruptHan
             WAKER.wake();
         }
 
+        let sampling = SAMPLER.active.load(Ordering::Relaxed);
+
         if r.events_end().read() != 0 {
-            r.intenclr().write(|w| w.set_end(true));
+            if sampling {
+                r.events_end().write_value(0);
+                if SAMPLER.restart.load(Ordering::Relaxed) {
+                    // No PPI channel links END to START in this mode.
+                    r.tasks_start().write_value(1);
+                }
+                SAMPLER.ended.fetch_add(1, Ordering::Release);
+            } else {
+                r.intenclr().write(|w| w.set_end(true));
+            }
             WAKER.wake();
         }
 
         if r.events_started().read() != 0 {
-            r.intenclr().write(|w| w.set_started(true));
+            if sampling {
+                // The DMA pointer was latched for the transfer that just started, so queue the
+                // other buffer for the next one. This is done here rather than in the task so
+                // that a slow callback can't make the next transfer reuse the current buffer.
+                r.events_started().write_value(0);
+                let started = SAMPLER.started.fetch_add(1, Ordering::Relaxed) + 1;
+                r.result()
+                    .ptr()
+                    .write_value(SAMPLER.bufs[started as usize % 2].load(Ordering::Relaxed));
+            } else {
+                r.intenclr().write(|w| w.set_started(true));
+            }
             WAKER.wake();
         }
     }
@@ -53,6 +82,23 @@ impl interrupt::typelevel::Handler<interrupt::typelevel::SAADC> for InterruptHan
 
 static WAKER: AtomicWaker = AtomicWaker::new();
 
+/// State shared with the interrupt handler during continuous sampling.
+struct SamplerState {
+    active: AtomicBool,
+    restart: AtomicBool,
+    bufs: [AtomicU32; 2],
+    started: AtomicU32,
+    ended: AtomicU32,
+}
+
+static SAMPLER: SamplerState = SamplerState {
+    active: AtomicBool::new(false),
+    restart: AtomicBool::new(false),
+    bufs: [AtomicU32::new(0), AtomicU32::new(0)],
+    started: AtomicU32::new(0),
+    ended: AtomicU32::new(0),
+};
+
 /// Used to configure the SAADC peripheral.
 ///
 /// See the `Default` impl for suitable default values.
@@ -154,6 +200,9 @@ impl<'d, const N: usize> Saadc<'d, N> {
     ) -> Self {
         let r = pac::SAADC;
 
+        #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+        crate::security::assert_assigned(r.as_ptr(), "SAADC");
+
         let Config { resolution, oversample } = config;
 
         // Configure channels
@@ -212,12 +261,42 @@ impl<'d, const N: usize> Saadc<'d, N> {
         Self { _p: saadc }
     }
 
+    /// Create a new SAADC driver, failing if the secure firmware withheld the peripheral.
+    ///
+    /// See [`crate::security`].
+    #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+    pub fn try_new(
+        saadc: Peri<'d, peripherals::SAADC>,
+        irq: impl interrupt::typelevel::Binding<interrupt::typelevel::SAADC, InterruptHandler> + 'd,
+        config: Config,
+        channel_configs: [ChannelConfig; N],
+    ) -> Result<Self, Error> {
+        if !crate::security::is_assigned(pac::SAADC.as_ptr()) {
+            return Err(Error::NotAssignedToNonSecure);
+        }
+        Ok(Self::new(saadc, irq, config, channel_configs))
+    }
+
     fn regs() -> pac::saadc::Saadc {
         pac::SAADC
     }
 
+    /// Get a hook that disables the SAADC during a deep sleep.
+    ///
+    /// See [`SaadcSleepHook`].
+    #[cfg(feature = "low-power")]
+    pub fn sleep_hook(&self) -> SaadcSleepHook<'d> {
+        SaadcSleepHook {
+            disabled: false,
+            _phantom: PhantomData,
+        }
+    }
+
     /// Perform SAADC calibration. Completes when done.
     pub async fn calibrate(&self) {
+        #[cfg(feature = "low-power")]
+        let _veto = crate::low_power::veto();
+
         let r = Self::regs();
 
         // Reset and enable the end event
@@ -250,6 +329,9 @@ impl<'d, const N: usize> Saadc<'d, N> {
     /// consumption remains higher if sampling is not stopped explicitly). Cancellation will
     /// also cause the sampling to be stopped.
     pub async fn sample(&mut self, buf: &mut [i16; N]) {
+        #[cfg(feature = "low-power")]
+        let _veto = crate::low_power::veto();
+
         // In case the future is dropped, stop the task and wait for it to end.
         let on_drop = OnDrop::new(Self::stop_sampling_immediately);
 
@@ -301,16 +383,21 @@ impl<'d, const N: usize> Saadc<'d, N> {
     /// A command is return from the closure that indicates whether the sampling
     /// should continue or stop.
     ///
-    /// NOTE: The time spent within the callback supplied should not exceed the time
-    /// taken to acquire the samples into a single buffer. You should measure the
-    /// time taken by the callback and set the sample buffer size accordingly.
-    /// Exceeding this time can lead to samples becoming dropped.
+    /// Each timer tick samples all `N` channels in one scan, so every `[i16; N]` element of
+    /// the buffer is one frame holding one sample per channel, in the order the channels
+    /// were passed to [`Saadc::new`]. Buffers always hold whole frames.
+    ///
+    /// The peripheral switches between the two buffers without any gap between them. The
+    /// time spent within the callback must not exceed the time taken to acquire the
+    /// samples into a single buffer, otherwise the peripheral would overwrite the buffer
+    /// being processed. In that case sampling is stopped and [`Error::Overrun`] is
+    /// returned, rather than passing on corrupted data. You should measure the time taken
+    /// by the callback and set the sample buffer size accordingly.
     ///
     /// The sampling is stopped prior to returning in order to reduce power consumption (power
     /// consumption remains higher if sampling is not stopped explicitly), and to
     /// free the buffers from being used by the peripheral. Cancellation will
     /// also cause the sampling to be stopped.
-
     pub async fn run_task_sampler<F, T: TimerInstance, const N0: usize>(
         &mut self,
         timer: Peri<'_, T>,
@@ -320,7 +407,8 @@ impl<'d, const N: usize> Saadc<'d, N> {
         sample_counter: u32,
         bufs: &mut [[[i16; N]; N0]; 2],
         callback: F,
-    ) where
+    ) -> Result<(), Error>
+    where
         F: FnMut(&[[i16; N]]) -> CallbackResult,
     {
         let r = Self::regs();
@@ -354,7 +442,7 @@ impl<'d, const N: usize> Saadc<'d, N> {
             },
             callback,
         )
-        .await;
+        .await
     }
 
     async fn run_sampler<I, F, const N0: usize>(
@@ -363,12 +451,16 @@ impl<'d, const N: usize> Saadc<'d, N> {
         sample_rate_divisor: Option<u16>,
         mut init: I,
         mut callback: F,
-    ) where
+    ) -> Result<(), Error>
+    where
         I: FnMut(),
         F: FnMut(&[[i16; N]]) -> CallbackResult,
     {
+        #[cfg(feature = "low-power")]
+        let _veto = crate::low_power::veto();
+
         // In case the future is dropped, stop the task and wait for it to end.
-        let on_drop = OnDrop::new(Self::stop_sampling_immediately);
+        let on_drop = OnDrop::new(Self::stop_sampler);
 
         let r = Self::regs();
 
@@ -387,6 +479,14 @@ impl<'d, const N: usize> Saadc<'d, N> {
             }),
         }
 
+        // Hand the buffers to the interrupt handler, which swaps them on each STARTED event.
+        SAMPLER.bufs[0].store(bufs[0].as_mut_ptr() as u32, Ordering::Relaxed);
+        SAMPLER.bufs[1].store(bufs[1].as_mut_ptr() as u32, Ordering::Relaxed);
+        SAMPLER.started.store(0, Ordering::Relaxed);
+        SAMPLER.ended.store(0, Ordering::Relaxed);
+        SAMPLER.restart.store(sample_rate_divisor.is_some(), Ordering::Relaxed);
+        SAMPLER.active.store(true, Ordering::Relaxed);
+
         // Set up the initial DMA
         r.result().ptr().write_value(bufs[0].as_mut_ptr() as u32);
         r.result().maxcnt().write(|w| w.set_maxcnt((N0 * N * CNT_UNIT) as _));
@@ -406,42 +506,37 @@ impl<'d, const N: usize> Saadc<'d, N> {
         r.tasks_start().write_value(1);
 
         let mut inited = false;
-        let mut current_buffer = 0;
+        let mut delivered: u32 = 0;
 
         // Wait for events and complete when the sampler indicates it has had enough.
         let r = poll_fn(|cx| {
-            let r = Self::regs();
-
             WAKER.register(cx.waker());
 
-            if r.events_end().read() != 0 {
-                compiler_fence(Ordering::SeqCst);
+            if !inited && SAMPLER.started.load(Ordering::Relaxed) != 0 {
+                init();
+                inited = true;
+            }
 
-                r.events_end().write_value(0);
-                r.intenset().write(|w| w.set_end(true));
-
-                match callback(&bufs[current_buffer]) {
-                    CallbackResult::Continue => {
-                        let next_buffer = 1 - current_buffer;
-                        current_buffer = next_buffer;
-                    }
-                    CallbackResult::Stop => {
-                        return Poll::Ready(());
-                    }
+            while SAMPLER.ended.load(Ordering::Acquire) != delivered {
+                // Once the transfer after the next one has started, it is writing into the
+                // buffer we are about to hand out.
+                let buf = &bufs[delivered as usize % 2];
+                if SAMPLER.ended.load(Ordering::Acquire).wrapping_sub(delivered) > 1 {
+                    return Poll::Ready(Err(Error::Overrun));
                 }
-            }
 
-            if r.events_started().read() != 0 {
-                r.events_started().write_value(0);
-                r.intenset().write(|w| w.set_started(true));
+                compiler_fence(Ordering::SeqCst);
+                let result = callback(buf);
+                compiler_fence(Ordering::SeqCst);
 
-                if !inited {
-                    init();
-                    inited = true;
+                if SAMPLER.ended.load(Ordering::Acquire).wrapping_sub(delivered) > 1 {
+                    return Poll::Ready(Err(Error::Overrun));
                 }
+                delivered = delivered.wrapping_add(1);
 
-                let next_buffer = 1 - current_buffer;
-                r.result().ptr().write_value(bufs[next_buffer].as_mut_ptr() as u32);
+                if result == CallbackResult::Stop {
+                    return Poll::Ready(Ok(()));
+                }
             }
 
             Poll::Pending
@@ -453,6 +548,20 @@ impl<'d, const N: usize> Saadc<'d, N> {
         r
     }
 
+    // Stop continuous sampling and detach the interrupt handler from the buffers
+    fn stop_sampler() {
+        Self::stop_sampling_immediately();
+
+        let r = Self::regs();
+        r.intenclr().write(|w| {
+            w.set_end(true);
+            w.set_started(true);
+        });
+        SAMPLER.active.store(false, Ordering::Relaxed);
+        r.events_end().write_value(0);
+        r.events_started().write_value(0);
+    }
+
     // Stop sampling and wait for it to stop in a blocking fashion
     fn stop_sampling_immediately() {
         let r = Self::regs();
@@ -478,15 +587,54 @@ impl<'d> Saadc<'d, 1> {
     /// that the size of this buffer can be less than the original buffer's size.
     /// A command is return from the closure that indicates whether the sampling
     /// should continue or stop.
+    ///
+    /// Without a PPI channel, the next buffer is started from the interrupt handler, so the
+    /// SAADC interrupt latency must stay below one sample period for there to be no gap
+    /// between buffers. Use [`Saadc::run_task_sampler`] where that can't be guaranteed. As
+    /// there, [`Error::Overrun`] is returned if the callback takes longer than it takes to
+    /// fill a buffer.
     pub async fn run_timer_sampler<I, S, const N0: usize>(
         &mut self,
         bufs: &mut [[[i16; 1]; N0]; 2],
         sample_rate_divisor: u16,
         sampler: S,
-    ) where
+    ) -> Result<(), Error>
+    where
         S: FnMut(&[[i16; 1]]) -> CallbackResult,
     {
-        self.run_sampler(bufs, Some(sample_rate_divisor), || {}, sampler).await;
+        self.run_sampler(bufs, Some(sample_rate_divisor), || {}, sampler).await
+    }
+}
+
+/// Disables the SAADC during a deep sleep, see [`low_power`](crate::low_power).
+///
+/// Sampling and calibration prevent deep sleep while they are in progress.
+///
+/// Obtained with [`Saadc::sleep_hook`]. It borrows the peripheral for as long as the driver, so
+/// no other driver can use the peripheral while the hook exists. If the driver is dropped, the
+/// hook does nothing.
+#[cfg(feature = "low-power")]
+pub struct SaadcSleepHook<'d> {
+    disabled: bool,
+    _phantom: PhantomData<&'d ()>,
+}
+
+#[cfg(feature = "low-power")]
+impl<'d> crate::low_power::SleepHook for SaadcSleepHook<'d> {
+    fn prepare_sleep(&mut self, _planned: embassy_time::Duration) {
+        let r = pac::SAADC;
+        // Not enabled if the driver was dropped.
+        self.disabled = r.enable().read().enable();
+        if self.disabled {
+            r.enable().write(|w| w.set_enable(false));
+        }
+    }
+
+    fn resume(&mut self) {
+        if self.disabled {
+            pac::SAADC.enable().write(|w| w.set_enable(true));
+            self.disabled = false;
+        }
     }
 }
 
@@ -0,0 +1,88 @@
+//! Checks for peripherals withheld by the secure firmware, on non-secure builds.
+//!
+//! On the nRF53 and nRF91, the secure firmware decides through the SPU which peripherals the
+//! non-secure application may use. The SPU itself can't be read from the non-secure side, and
+//! a withheld peripheral doesn't fault when accessed: its writes are ignored and its registers
+//! read as zero. A driver for it then waits forever for an event that never comes, which looks
+//! like a random hang.
+//!
+//! Instead, a peripheral is probed by enabling all of its interrupts and reading them back: a
+//! withheld peripheral reads back zero. The interrupts that weren't already enabled are then
+//! disabled again.
+//!
+//! `Uarte`, `Spim` and `Saadc` panic on construction if their peripheral is withheld, and have
+//! a `try_new` constructor that returns `Error::NotAssignedToNonSecure` instead.
+//!
+//! The secure firmware is notified of each blocked access through the SPU `PERIPHACCERR` event.
+//! That includes the probe, so it can log one access error for each withheld peripheral that is
+//! checked.
+
+use crate::pac;
+
+/// Offset of the INTENSET register, the same in all peripherals.
+const INTENSET: usize = 0x304;
+/// Offset of the INTENCLR register, the same in all peripherals.
+const INTENCLR: usize = 0x308;
+
+/// Whether the peripheral with its registers at `regs` was assigned to the non-secure domain.
+pub(crate) fn is_assigned(regs: *mut ()) -> bool {
+    let intenset = regs.wrapping_byte_add(INTENSET) as *mut u32;
+    let intenclr = regs.wrapping_byte_add(INTENCLR) as *mut u32;
+
+    critical_section::with(|_| unsafe {
+        if intenset.read_volatile() != 0 {
+            return true;
+        }
+        intenset.write_volatile(u32::MAX);
+        let enabled = intenset.read_volatile();
+        intenclr.write_volatile(enabled);
+        enabled != 0
+    })
+}
+
+/// Panic with a helpful message if the peripheral with its registers at `regs` is withheld.
+#[track_caller]
+pub(crate) fn assert_assigned(regs: *mut (), name: &str) {
+    if !is_assigned(regs) {
+        panic!(
+            "{} is not assigned to the non-secure domain by the secure firmware",
+            name
+        );
+    }
+}
+
+macro_rules! peripherals {
+    ($($name:ident),* $(,)?) => {
+        [$((stringify!($name), pac::$name.as_ptr() as *mut ())),*]
+    };
+}
+
+/// The peripherals that the secure firmware assigned to the non-secure domain, by name.
+///
+/// Only peripherals with interrupts can be probed, so this doesn't list e.g. the GPIO ports.
+/// Serial peripherals sharing an instance are listed once, as `UARTE0` for `SERIAL0`.
+///
+/// Meant to be printed while debugging:
+///
+/// ```rust,ignore
+/// for name in embassy_nrf::security::assigned_peripherals() {
+///     info!("assigned: {}", name);
+/// }
+/// ```
+pub fn assigned_peripherals() -> impl Iterator<Item = &'static str> {
+    #[cfg(feature = "_nrf91")]
+    let peripherals = peripherals!(
+        RTC0, RTC1, WDT, UARTE0, UARTE1, UARTE2, UARTE3, SAADC, PWM0, PWM1, PWM2, PWM3, TIMER0, TIMER1, TIMER2,
+        GPIOTE1, PDM, I2S, IPC, EGU0, EGU1, EGU2, EGU3, EGU4, EGU5,
+    );
+    #[cfg(feature = "_nrf5340-app")]
+    let peripherals = peripherals!(
+        USBD, RTC0, RTC1, WDT0, WDT1, NFCT, UARTE0, UARTE1, UARTE2, UARTE3, SPIM4, SAADC, PWM0, PWM1, PWM2, PWM3,
+        TIMER0, TIMER1, TIMER2, QSPI, PDM0, I2S0, QDEC0, QDEC1, COMP, GPIOTE1, IPC, EGU0, EGU1, EGU2, EGU3, EGU4, EGU5,
+    );
+
+    peripherals
+        .into_iter()
+        .filter(|&(_, regs)| is_assigned(regs))
+        .map(|(name, _)| name)
+}
@@ -4,6 +4,7 @@
 
 use core::future::poll_fn;
 use core::marker::PhantomData;
+use core::ops::Range;
 #[cfg(feature = "_nrf52832_anomaly_109")]
 use core::sync::atomic::AtomicU8;
 use core::sync::atomic::{Ordering, compiler_fence};
@@ -21,7 +22,7 @@ use crate::interrupt::typelevel::Interrupt;
 use crate::pac::gpio::vals as gpiovals;
 use crate::pac::spim::vals;
 use crate::util::slice_in_ram_or;
-use crate::{interrupt, pac};
+use crate::{BusId, interrupt, pac};
 
 /// SPI frequencies.
 #[repr(transparent)]
@@ -134,6 +135,12 @@ impl Into<pac::spim::vals::Frequency> for Frequency {
 pub enum Error {
     /// EasyDMA can only read from data memory, read only buffers in flash will fail.
     BufferNotInRAM,
+    /// A transfer both sending and receiving was attempted in 3-wire mode, where the data
+    /// line goes one way at a time.
+    HalfDuplex,
+    /// The secure firmware didn't assign the peripheral to the non-secure domain.
+    #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+    NotAssignedToNonSecure,
 }
 
 /// SPIM configuration.
@@ -160,6 +167,12 @@ pub struct Config {
 
     /// Drive strength for the MOSI line.
     pub mosi_drive: OutputDrive,
+
+    /// Number of command bytes at the start of each transfer, sent with the DCX line low.
+    ///
+    /// The bytes after them are data, sent with DCX high. From 15 on, all bytes are commands.
+    /// Only used with a DCX pin, see [`Spim::new_txonly_with_dcx`].
+    pub dcx_cmd_bytes: u8,
 }
 
 impl Default for Config {
@@ -171,6 +184,7 @@ impl Default for Config {
             orc: 0x00,
             sck_drive: OutputDrive::HighDrive,
             mosi_drive: OutputDrive::HighDrive,
+            dcx_cmd_bytes: 0,
         }
     }
 }
@@ -182,24 +196,27 @@ pub struct InterruptHandler<T: Instance> {
 
 impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
     unsafe fn on_interrupt() {
-        let r = T::regs();
-        let s = T::state();
-
-        #[cfg(feature = "_nrf52832_anomaly_109")]
-        {
-            // Ideally we should call this only during the first chunk transfer,
-            // but so far calling this every time doesn't seem to be causing any issues.
-            if r.events_started().read() != 0 {
-                s.waker.wake();
-                r.intenclr().write(|w| w.set_started(true));
-            }
-        }
+        on_interrupt(T::regs(), T::state())
+    }
+}
 
-        if r.events_end().read() != 0 {
+/// Shared with tests, which run it on a fake register block.
+#[inline(always)]
+fn on_interrupt(r: pac::spim::Spim, s: &State) {
+    #[cfg(feature = "_nrf52832_anomaly_109")]
+    {
+        // Ideally we should call this only during the first chunk transfer,
+        // but so far calling this every time doesn't seem to be causing any issues.
+        if r.events_started().read() != 0 {
             s.waker.wake();
-            r.intenclr().write(|w| w.set_end(true));
+            r.intenclr().write(|w| w.set_started(true));
         }
     }
+
+    if r.events_end().read() != 0 {
+        s.waker.wake();
+        r.intenclr().write(|w| w.set_end(true));
+    }
 }
 
 /// SPIM driver.
@@ -207,11 +224,23 @@ pub struct Spim<'d> {
     r: pac::spim::Spim,
     irq: interrupt::Interrupt,
     state: &'static State,
+    id: BusId,
     #[cfg(feature = "_nrf54l")]
     clk: u32,
+    dcx: Option<Dcx<'d>>,
+    sdio: Option<Peri<'d, AnyPin>>,
+    sdio_rx: bool,
     _p: PhantomData<&'d ()>,
 }
 
+/// DCX (data/command) line.
+struct Dcx<'d> {
+    pin: Peri<'d, AnyPin>,
+    /// Driven by the peripheral, rather than by the driver.
+    hw: bool,
+    cmd_bytes: u8,
+}
+
 impl<'d> Spim<'d> {
     /// Create a new SPIM driver.
     pub fn new<T: Instance>(
@@ -222,7 +251,33 @@ impl<'d> Spim<'d> {
         mosi: Peri<'d, impl GpioPin>,
         config: Config,
     ) -> Self {
-        Self::new_inner(spim, Some(sck.into()), Some(miso.into()), Some(mosi.into()), config)
+        Self::new_inner(
+            spim,
+            Some(sck.into()),
+            Some(miso.into()),
+            Some(mosi.into()),
+            None,
+            false,
+            config,
+        )
+    }
+
+    /// Create a new SPIM driver, failing if the secure firmware withheld the peripheral.
+    ///
+    /// See [`crate::security`].
+    #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+    pub fn try_new<T: Instance>(
+        spim: Peri<'d, T>,
+        irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        sck: Peri<'d, impl GpioPin>,
+        miso: Peri<'d, impl GpioPin>,
+        mosi: Peri<'d, impl GpioPin>,
+        config: Config,
+    ) -> Result<Self, Error> {
+        if !crate::security::is_assigned(T::regs().as_ptr()) {
+            return Err(Error::NotAssignedToNonSecure);
+        }
+        Ok(Self::new(spim, irq, sck, miso, mosi, config))
     }
 
     /// Create a new SPIM driver, capable of TX only (MOSI only).
@@ -233,7 +288,47 @@ impl<'d> Spim<'d> {
         mosi: Peri<'d, impl GpioPin>,
         config: Config,
     ) -> Self {
-        Self::new_inner(spim, Some(sck.into()), None, Some(mosi.into()), config)
+        Self::new_inner(spim, Some(sck.into()), None, Some(mosi.into()), None, false, config)
+    }
+
+    /// Create a new SPIM driver, capable of TX only (MOSI only), with a DCX (data/command) pin
+    /// for display controllers.
+    ///
+    /// The first [`Config::dcx_cmd_bytes`] bytes of each transfer are sent with DCX low, and
+    /// the rest with DCX high. DCX is driven by the peripheral on the instances that support it
+    /// (SPIM3 on nRF52833 and nRF52840, SPIM4 on nRF5340), and by the driver otherwise.
+    pub fn new_txonly_with_dcx<T: Instance>(
+        spim: Peri<'d, T>,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        sck: Peri<'d, impl GpioPin>,
+        mosi: Peri<'d, impl GpioPin>,
+        dcx: Peri<'d, impl GpioPin>,
+        config: Config,
+    ) -> Self {
+        Self::new_inner(
+            spim,
+            Some(sck.into()),
+            None,
+            Some(mosi.into()),
+            Some(dcx.into()),
+            false,
+            config,
+        )
+    }
+
+    /// Create a new SPIM driver for 3-wire, half-duplex operation, with a single
+    /// bidirectional data line.
+    ///
+    /// Each transfer either sends or receives, one that does both fails with
+    /// [`Error::HalfDuplex`]. The data line is released while receiving.
+    pub fn new_3wire<T: Instance>(
+        spim: Peri<'d, T>,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        sck: Peri<'d, impl GpioPin>,
+        sdio: Peri<'d, impl GpioPin>,
+        config: Config,
+    ) -> Self {
+        Self::new_inner(spim, Some(sck.into()), None, Some(sdio.into()), None, true, config)
     }
 
     /// Create a new SPIM driver, capable of RX only (MISO only).
@@ -244,7 +339,7 @@ impl<'d> Spim<'d> {
         miso: Peri<'d, impl GpioPin>,
         config: Config,
     ) -> Self {
-        Self::new_inner(spim, Some(sck.into()), Some(miso.into()), None, config)
+        Self::new_inner(spim, Some(sck.into()), Some(miso.into()), None, None, false, config)
     }
 
     /// Create a new SPIM driver, capable of TX only (MOSI only), without SCK pin.
@@ -254,7 +349,7 @@ impl<'d> Spim<'d> {
         mosi: Peri<'d, impl GpioPin>,
         config: Config,
     ) -> Self {
-        Self::new_inner(spim, None, None, Some(mosi.into()), config)
+        Self::new_inner(spim, None, None, Some(mosi.into()), None, false, config)
     }
 
     fn new_inner<T: Instance>(
@@ -262,10 +357,15 @@ impl<'d> Spim<'d> {
         sck: Option<Peri<'d, AnyPin>>,
         miso: Option<Peri<'d, AnyPin>>,
         mosi: Option<Peri<'d, AnyPin>>,
+        dcx: Option<Peri<'d, AnyPin>>,
+        sdio: bool,
         config: Config,
     ) -> Self {
         let r = T::regs();
 
+        #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+        crate::security::assert_assigned(r.as_ptr(), "SPIM");
+
         // Configure pins
         if let Some(sck) = &sck {
             sck.conf().write(|w| {
@@ -282,6 +382,16 @@ impl<'d> Spim<'d> {
         if let Some(miso) = &miso {
             miso.conf().write(|w| w.set_input(gpiovals::Input::CONNECT));
         }
+        if sdio && let Some(mosi) = &mosi {
+            mosi.conf().modify(|w| w.set_input(gpiovals::Input::CONNECT));
+        }
+        if let Some(dcx) = &dcx {
+            dcx.set_high();
+            dcx.conf().write(|w| {
+                w.set_dir(gpiovals::Dir::OUTPUT);
+                convert_drive(w, config.mosi_drive);
+            });
+        }
 
         match config.mode.polarity {
             Polarity::IdleHigh => {
@@ -306,6 +416,17 @@ impl<'d> Spim<'d> {
         r.psel().sck().write_value(sck.psel_bits());
         r.psel().mosi().write_value(mosi.psel_bits());
         r.psel().miso().write_value(miso.psel_bits());
+        #[cfg(any(feature = "nrf52833", feature = "nrf52840", feature = "_nrf5340-app"))]
+        if T::HW_DCX
+            && let Some(dcx) = &dcx
+        {
+            // PSELDCX, right before DCXCNT, is missing from the PAC.
+            unsafe {
+                (r.as_ptr() as *mut u32)
+                    .byte_add(0x56C)
+                    .write_volatile(dcx.psel_bits().0)
+            };
+        }
 
         // Enable SPIM instance.
         r.enable().write(|w| w.set_enable(vals::Enable::ENABLED));
@@ -314,8 +435,16 @@ impl<'d> Spim<'d> {
             r: T::regs(),
             irq: T::Interrupt::IRQ,
             state: T::state(),
+            id: T::BUS_ID,
             #[cfg(feature = "_nrf54l")]
             clk: T::clk(),
+            dcx: dcx.map(|pin| Dcx {
+                pin,
+                hw: T::HW_DCX,
+                cmd_bytes: 0,
+            }),
+            sdio: if sdio { mosi } else { None },
+            sdio_rx: false,
             _p: PhantomData {},
         };
 
@@ -331,6 +460,97 @@ impl<'d> Spim<'d> {
         spim
     }
 
+    /// Identity of the peripheral instance, for error reporting.
+    pub fn bus_id(&self) -> BusId {
+        self.id
+    }
+
+    /// Change [`Config::dcx_cmd_bytes`], the number of command bytes at the start of each
+    /// transfer, without reapplying the rest of the configuration.
+    pub fn set_dcx_cmd_bytes(&mut self, cmd_bytes: u8) {
+        if let Some(dcx) = &mut self.dcx {
+            dcx.cmd_bytes = cmd_bytes;
+            if dcx.hw {
+                self.set_hw_dcx_cmd_bytes(cmd_bytes);
+            }
+        }
+    }
+
+    /// In 3-wire mode, turn the data line in the direction of the transfer.
+    fn prepare_sdio(&mut self, rx_len: usize, tx_len: usize) -> Result<(), Error> {
+        let Some(sdio) = &self.sdio else {
+            return Ok(());
+        };
+        let rx = match (rx_len > 0, tx_len > 0) {
+            (true, true) => return Err(Error::HalfDuplex),
+            (rx, _) => rx,
+        };
+        if rx != self.sdio_rx {
+            let r = self.r;
+            // Pins can only be selected while the peripheral is disabled.
+            r.enable().write(|w| w.set_enable(vals::Enable::DISABLED));
+            if rx {
+                r.psel().mosi().write_value(gpio::DISCONNECTED);
+                sdio.conf().modify(|w| w.set_dir(gpiovals::Dir::INPUT));
+                r.psel().miso().write_value(sdio.psel_bits());
+            } else {
+                r.psel().miso().write_value(gpio::DISCONNECTED);
+                sdio.conf().modify(|w| w.set_dir(gpiovals::Dir::OUTPUT));
+                r.psel().mosi().write_value(sdio.psel_bits());
+            }
+            r.enable().write(|w| w.set_enable(vals::Enable::ENABLED));
+            self.sdio_rx = rx;
+        }
+        Ok(())
+    }
+
+    /// Split a transfer of `len` bytes into the command bytes, to send with DCX low, and the
+    /// data bytes, each in chunks the DMA can take.
+    ///
+    /// Only DCX driven by the driver needs the split, the peripheral does it by itself.
+    fn dcx_ranges(&self, len: usize) -> (Range<usize>, Range<usize>) {
+        let cmd_len = match &self.dcx {
+            Some(dcx) if !dcx.hw && dcx.cmd_bytes >= 15 => len,
+            Some(dcx) if !dcx.hw => core::cmp::min(dcx.cmd_bytes as usize, len),
+            _ => 0,
+        };
+        (0..cmd_len, cmd_len..len)
+    }
+
+    /// Set the level of DCX, if driven by the driver, for the chunk at `offset`.
+    ///
+    /// With DCX driven by the peripheral, only the first chunk starts with command bytes.
+    fn set_dcx(&self, command: bool, offset: usize) {
+        let Some(dcx) = &self.dcx else {
+            return;
+        };
+        if !dcx.hw {
+            if command {
+                dcx.pin.set_low();
+            } else {
+                dcx.pin.set_high();
+            }
+        } else if offset > 0 {
+            self.set_hw_dcx_cmd_bytes(0);
+        }
+    }
+
+    /// Restore the number of command bytes after a transfer in several chunks.
+    fn finish_dcx(&self, len: usize) {
+        if let Some(dcx) = &self.dcx
+            && dcx.hw
+            && len > EASY_DMA_SIZE
+        {
+            self.set_hw_dcx_cmd_bytes(dcx.cmd_bytes);
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn set_hw_dcx_cmd_bytes(&self, cmd_bytes: u8) {
+        #[cfg(any(feature = "nrf52833", feature = "nrf52840", feature = "_nrf5340-app"))]
+        self.r.dcxcnt().write(|w| w.set_dcxcnt(core::cmp::min(cmd_bytes, 0xF)));
+    }
+
     fn prepare_dma_transfer(&mut self, rx: *mut [u8], tx: *const [u8], offset: usize, length: usize) {
         compiler_fence(Ordering::SeqCst);
 
@@ -405,11 +625,18 @@ impl<'d> Spim<'d> {
         // NOTE: RAM slice check for rx is not necessary, as a mutable
         // slice can only be built from data located in RAM.
 
+        self.prepare_sdio(rx.len(), tx.len())?;
+
         let xfer_len = core::cmp::max(rx.len(), tx.len());
-        for offset in (0..xfer_len).step_by(EASY_DMA_SIZE) {
-            let length = core::cmp::min(xfer_len - offset, EASY_DMA_SIZE);
-            self.blocking_inner_from_ram_chunk(rx, tx, offset, length);
+        let (cmd, data) = self.dcx_ranges(xfer_len);
+        for (range, command) in [(cmd, true), (data, false)] {
+            for offset in range.clone().step_by(EASY_DMA_SIZE) {
+                let length = core::cmp::min(range.end - offset, EASY_DMA_SIZE);
+                self.set_dcx(command, offset);
+                self.blocking_inner_from_ram_chunk(rx, tx, offset, length);
+            }
         }
+        self.finish_dcx(xfer_len);
         Ok(())
     }
 
@@ -422,6 +649,7 @@ impl<'d> Spim<'d> {
                 tx_ram_buf.copy_from_slice(tx);
                 self.blocking_inner_from_ram(rx, tx_ram_buf)
             }
+            Err(e) => Err(e),
         }
     }
 
@@ -459,11 +687,18 @@ impl<'d> Spim<'d> {
         // NOTE: RAM slice check for rx is not necessary, as a mutable
         // slice can only be built from data located in RAM.
 
+        self.prepare_sdio(rx.len(), tx.len())?;
+
         let xfer_len = core::cmp::max(rx.len(), tx.len());
-        for offset in (0..xfer_len).step_by(EASY_DMA_SIZE) {
-            let length = core::cmp::min(xfer_len - offset, EASY_DMA_SIZE);
-            self.async_inner_from_ram_chunk(rx, tx, offset, length).await;
+        let (cmd, data) = self.dcx_ranges(xfer_len);
+        for (range, command) in [(cmd, true), (data, false)] {
+            for offset in range.clone().step_by(EASY_DMA_SIZE) {
+                let length = core::cmp::min(range.end - offset, EASY_DMA_SIZE);
+                self.set_dcx(command, offset);
+                self.async_inner_from_ram_chunk(rx, tx, offset, length).await;
+            }
         }
+        self.finish_dcx(xfer_len);
         Ok(())
     }
 
@@ -476,6 +711,7 @@ impl<'d> Spim<'d> {
                 tx_ram_buf.copy_from_slice(tx);
                 self.async_inner_from_ram(rx, tx_ram_buf).await
             }
+            Err(e) => Err(e),
         }
     }
 
@@ -586,6 +822,9 @@ impl<'d> Drop for Spim<'d> {
         gpio::deconfigure_pin(r.psel().sck().read());
         gpio::deconfigure_pin(r.psel().miso().read());
         gpio::deconfigure_pin(r.psel().mosi().read());
+        if let Some(dcx) = &self.dcx {
+            gpio::deconfigure_pin(dcx.pin.psel_bits());
+        }
 
         // Disable all events interrupts
         cortex_m::peripheral::NVIC::mask(self.irq);
@@ -615,6 +854,9 @@ impl State {
 }
 
 pub(crate) trait SealedInstance {
+    const BUS_ID: BusId;
+    /// Whether the peripheral can drive a DCX pin.
+    const HW_DCX: bool;
     fn regs() -> pac::spim::Spim;
     fn state() -> &'static State;
     #[cfg(feature = "_nrf54l")]
@@ -632,6 +874,8 @@ pub trait Instance: SealedInstance + PeripheralType + 'static {
 macro_rules! impl_spim {
     ($type:ident, $pac_type:ident, $irq:ident, $clk:expr) => {
         impl crate::spim::SealedInstance for peripherals::$type {
+            const BUS_ID: crate::BusId = crate::BusId::new(stringify!($pac_type));
+            const HW_DCX: bool = false;
             fn regs() -> pac::spim::Spim {
                 pac::$pac_type
             }
@@ -652,7 +896,15 @@ macro_rules! impl_spim {
 #[cfg(not(feature = "_nrf54l"))]
 macro_rules! impl_spim {
     ($type:ident, $pac_type:ident, $irq:ident) => {
+        impl_spim!(@impl $type, $pac_type, $irq, false);
+    };
+    ($type:ident, $pac_type:ident, $irq:ident, dcx) => {
+        impl_spim!(@impl $type, $pac_type, $irq, true);
+    };
+    (@impl $type:ident, $pac_type:ident, $irq:ident, $hw_dcx:expr) => {
         impl crate::spim::SealedInstance for peripherals::$type {
+            const BUS_ID: crate::BusId = crate::BusId::new(stringify!($pac_type));
+            const HW_DCX: bool = $hw_dcx;
             fn regs() -> pac::spim::Spim {
                 pac::$pac_type
             }
@@ -693,6 +945,9 @@ impl embedded_hal_1::spi::Error for Error {
     fn kind(&self) -> embedded_hal_1::spi::ErrorKind {
         match *self {
             Self::BufferNotInRAM => embedded_hal_1::spi::ErrorKind::Other,
+            Self::HalfDuplex => embedded_hal_1::spi::ErrorKind::Other,
+            #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+            Self::NotAssignedToNonSecure => embedded_hal_1::spi::ErrorKind::Other,
         }
     }
 }
@@ -787,6 +1042,96 @@ impl<'d> SetConfig for Spim<'d> {
         let orc = config.orc;
         r.orc().write(|w| w.set_orc(orc));
 
+        self.set_dcx_cmd_bytes(config.dcx_cmd_bytes);
+
         Ok(())
     }
 }
+
+#[cfg(all(test, feature = "nrf52840"))]
+mod tests {
+    extern crate std;
+
+    use core::mem::ManuallyDrop;
+    use std::boxed::Box;
+    use std::vec;
+
+    use super::*;
+    use crate::replay::{FakeRegs, Step, replay};
+
+    fn spim(regs: &FakeRegs) -> Spim<'static> {
+        Spim {
+            r: unsafe { pac::spim::Spim::from_ptr(regs.ptr()) },
+            irq: interrupt::Interrupt::SPIM3,
+            state: Box::leak(Box::new(State::new())),
+            id: BusId::new("SPIM3"),
+            dcx: None,
+            sdio: None,
+            sdio_rx: false,
+            _p: PhantomData,
+        }
+    }
+
+    /// END is still set from a previous transfer, made with the interrupt disabled. It must
+    /// not complete the next transfer before it starts.
+    #[test]
+    fn stale_end() {
+        let regs = FakeRegs::new();
+        let mut spim = ManuallyDrop::new(spim(&regs));
+        let (r, s) = (spim.r, spim.state);
+        let end = r.events_end().as_ptr();
+        let start = r.tasks_start().as_ptr();
+
+        let mut buf = [0; 4];
+        let out = replay(
+            &regs,
+            spim.read(&mut buf),
+            || on_interrupt(r, s),
+            &[
+                Step::Set(end, 1),
+                Step::Pending,
+                Step::Task(start),
+                Step::Wakes(0),
+                Step::Set(end, 1),
+                Step::Interrupt,
+                Step::Wakes(1),
+                Step::Ready,
+            ],
+        );
+        assert_eq!(out, Some(Ok(())));
+    }
+
+    /// A transfer longer than the DMA can do at once is split in chunks. The END of a chunk
+    /// must not complete the next one.
+    #[test]
+    fn chunked_end() {
+        let regs = FakeRegs::new();
+        let mut spim = ManuallyDrop::new(spim(&regs));
+        let (r, s) = (spim.r, spim.state);
+        let end = r.events_end().as_ptr();
+        let start = r.tasks_start().as_ptr();
+
+        let mut buf = vec![0; EASY_DMA_SIZE + 2];
+        let out = replay(
+            &regs,
+            spim.read(&mut buf),
+            || on_interrupt(r, s),
+            &[
+                Step::Pending,
+                Step::Task(start),
+                Step::Set(end, 1),
+                Step::Interrupt,
+                Step::Wakes(1),
+                Step::Pending,
+                Step::Task(start),
+                Step::Wakes(0),
+                Step::Set(end, 1),
+                Step::Interrupt,
+                Step::Wakes(1),
+                Step::Ready,
+            ],
+        );
+        assert_eq!(out, Some(Ok(())));
+        assert_eq!(r.dma().rx().maxcnt().read().maxcnt(), 2);
+    }
+}
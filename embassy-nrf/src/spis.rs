@@ -238,6 +238,12 @@ impl<'d> Spis<'d> {
         // Reset end event.
         r.events_end().write_value(0);
 
+        // Clear status register, for `is_overread` and `is_overflow` to report this transaction.
+        r.status().write(|w| {
+            w.set_overflow(true);
+            w.set_overread(true);
+        });
+
         // Release the semaphore.
         r.tasks_release().write_value(1);
 
@@ -286,12 +292,6 @@ impl<'d> Spis<'d> {
         let r = self.r;
         let s = self.state;
 
-        // Clear status register.
-        r.status().write(|w| {
-            w.set_overflow(true);
-            w.set_overread(true);
-        });
-
         // Acquire semaphore.
         if r.semstat().read().0 != 1 {
             // Reset and enable the acquire event.
@@ -440,11 +440,16 @@ impl<'d> Spis<'d> {
     }
 
     /// Checks if last transaction overread.
+    ///
+    /// The master clocked more bytes than the write buffer holds, and got the ORC byte for the
+    /// extra bytes.
     pub fn is_overread(&mut self) -> bool {
         self.r.status().read().overread()
     }
 
     /// Checks if last transaction overflowed.
+    ///
+    /// The master clocked more bytes than the read buffer holds, and the extra bytes were dropped.
     pub fn is_overflow(&mut self) -> bool {
         self.r.status().read().overflow()
     }
@@ -5,6 +5,8 @@ use core::task::Poll;
 
 use embassy_hal_internal::drop::OnDrop;
 use embassy_sync::waitqueue::AtomicWaker;
+#[cfg(feature = "time")]
+use embassy_time::{Duration, Instant, Ticker};
 use fixed::types::I30F2;
 
 use crate::interrupt::InterruptExt;
@@ -84,7 +86,7 @@ impl<'d> Temp<'d> {
             } else {
                 t.events_datardy().write_value(0);
                 let raw = t.temp().read();
-                Poll::Ready(I30F2::from_bits(raw as i32))
+                Poll::Ready(I30F2::from_bits(quarter_degrees(raw)))
             }
         })
         .await;
@@ -92,7 +94,59 @@ impl<'d> Temp<'d> {
         value
     }
 
+    /// Perform an asynchronous temperature measurement, in thousandths of a degree Celsius.
+    ///
+    /// The sensor has a resolution of 0.25 °C, so the result is a multiple of 250.
+    ///
+    /// If the future is dropped, the measurement is cancelled.
+    pub async fn read_millicelsius(&mut self) -> i32 {
+        self.read().await.to_bits() * 250
+    }
+
+    /// Measure the temperature every `period`, starting now.
+    ///
+    /// Each measurement is awaited with [`TempStream::next`], in thousandths of a degree Celsius.
+    /// A measurement that is late because `next` wasn't called in time starts right away, and the
+    /// following ones catch up to the period.
+    #[cfg(feature = "time")]
+    pub fn stream(&mut self, period: Duration) -> TempStream<'_, 'd> {
+        let mut ticker = Ticker::every(period);
+        ticker.reset_at(Instant::now());
+        TempStream { temp: self, ticker }
+    }
+
     fn regs() -> pac::temp::Temp {
         pac::TEMP
     }
 }
+
+/// Periodic temperature measurements.
+///
+/// Obtained with [`Temp::stream`].
+#[cfg(feature = "time")]
+pub struct TempStream<'a, 'd> {
+    temp: &'a mut Temp<'d>,
+    ticker: Ticker,
+}
+
+#[cfg(feature = "time")]
+impl TempStream<'_, '_> {
+    /// Wait for the next measurement, in thousandths of a degree Celsius.
+    ///
+    /// If the future is dropped, the measurement is cancelled, and the next call starts a
+    /// new one.
+    pub async fn next(&mut self) -> i32 {
+        self.ticker.next().await;
+        self.temp.read_millicelsius().await
+    }
+}
+
+/// The TEMP register value, in quarters of a degree Celsius.
+fn quarter_degrees(raw: u32) -> i32 {
+    // Anomaly 28 of nRF52832 revision 1: negative values are not sign-extended past bit 9.
+    #[cfg(feature = "nrf52832")]
+    if raw & (1 << 9) != 0 {
+        return (raw | 0xFFFF_FC00) as i32;
+    }
+    raw as i32
+}
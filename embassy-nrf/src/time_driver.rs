@@ -375,6 +375,7 @@ impl Driver for RtcDriver {
 #[cfg(feature = "rt")]
 #[interrupt]
 fn GRTC_1() {
+    crate::__on_interrupt(interrupt::GRTC_1);
     DRIVER.on_interrupt()
 }
 
@@ -382,9 +383,16 @@ fn GRTC_1() {
 #[cfg(feature = "rt")]
 #[interrupt]
 fn RTC1() {
+    crate::__on_interrupt(interrupt::RTC1);
     DRIVER.on_interrupt()
 }
 
 pub(crate) fn init(irq_prio: crate::interrupt::Priority) {
     DRIVER.init(irq_prio)
 }
+
+/// Timestamp of the next alarm, `u64::MAX` if none is scheduled.
+#[cfg(feature = "low-power")]
+pub(crate) fn next_alarm(cs: CriticalSection) -> u64 {
+    DRIVER.alarms.borrow(cs).timestamp.get()
+}
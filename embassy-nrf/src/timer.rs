@@ -52,6 +52,7 @@ macro_rules! impl_timer {
 }
 
 /// Timer frequency
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Frequency {
     /// 16MHz
@@ -22,7 +22,7 @@ use crate::interrupt::typelevel::Interrupt;
 use crate::pac::gpio::vals as gpiovals;
 use crate::pac::twim::vals;
 use crate::util::slice_in_ram;
-use crate::{gpio, interrupt, pac};
+use crate::{BusId, gpio, interrupt, pac};
 
 /// TWIM config.
 #[non_exhaustive]
@@ -84,6 +84,9 @@ pub enum Error {
     Overrun,
     /// Timeout error.
     Timeout,
+    /// The operations can't be executed as one transaction: an `Operation::Write` following an
+    /// `Operation::Read` has an empty buffer.
+    UnsupportedTransaction,
 }
 
 /// Interrupt handler.
@@ -115,6 +118,7 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
 pub struct Twim<'d> {
     r: pac::twim::Twim,
     state: &'static State,
+    id: BusId,
     tx_ram_buffer: &'d mut [u8],
     _p: PhantomData<&'d ()>,
 }
@@ -127,6 +131,9 @@ impl<'d> Twim<'d> {
     /// needs to be at least as large as the largest write operation that will be executed with a buffer
     /// that is not in RAM. If all write operations will be performed from RAM, an empty buffer (`&[]`) may
     /// be used.
+    ///
+    /// Transactions with consecutive read operations also receive into the `tx_ram_buffer`, see
+    /// [`Self::transaction`].
     pub fn new<T: Instance>(
         _twim: Peri<'d, T>,
         _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
@@ -191,6 +198,7 @@ impl<'d> Twim<'d> {
         let mut twim = Self {
             r: T::regs(),
             state: T::state(),
+            id: T::BUS_ID,
             tx_ram_buffer,
             _p: PhantomData {},
         };
@@ -207,16 +215,23 @@ impl<'d> Twim<'d> {
         twim
     }
 
+    /// Identity of the peripheral instance, for error reporting.
+    pub fn bus_id(&self) -> BusId {
+        self.id
+    }
+
     /// Set TX buffer, checking that it is in RAM and has suitable length.
-    unsafe fn set_tx_buffer(&mut self, buffer: &[u8]) -> Result<(), Error> {
+    ///
+    /// A buffer that is not in RAM is copied into the RAM buffer at `ram_offset`.
+    unsafe fn set_tx_buffer(&mut self, buffer: &[u8], ram_offset: usize) -> Result<(), Error> {
         let buffer = if slice_in_ram(buffer) {
             buffer
         } else {
-            if buffer.len() > self.tx_ram_buffer.len() {
+            if ram_offset + buffer.len() > self.tx_ram_buffer.len() {
                 return Err(Error::RAMBufferTooSmall);
             }
             trace!("Copying TWIM tx buffer into RAM for DMA");
-            let ram_buffer = &mut self.tx_ram_buffer[..buffer.len()];
+            let ram_buffer = &mut self.tx_ram_buffer[ram_offset..][..buffer.len()];
             ram_buffer.copy_from_slice(buffer);
             &*ram_buffer
         };
@@ -418,16 +433,69 @@ impl<'d> Twim<'d> {
         }
 
         assert!(!operations.is_empty());
-        match operations {
-            [Operation::Read(_), Operation::Read(_), ..] => {
-                panic!("Consecutive read operations are not supported!")
+
+        let reads = leading_reads(operations);
+        if reads > 1 {
+            // The TWIM can't receive into a new buffer without a repeated start, so the reads
+            // are received together into the RAM buffer, and copied out by `check_operations`.
+            let len = read_len(&operations[..reads]);
+            let next_write = match operations.get(reads) {
+                Some(Operation::Write(wr_buffer)) => Some(*wr_buffer),
+                _ => None,
+            };
+            let stop = operations.len() == reads + next_write.is_some() as usize;
+
+            // Set up DMA buffers.
+            unsafe {
+                if len > self.tx_ram_buffer.len() {
+                    return Err(Error::RAMBufferTooSmall);
+                }
+                // The RAM buffer outlives the transaction, and isn't touched until it's over.
+                let ram_buffer = core::slice::from_raw_parts_mut(self.tx_ram_buffer.as_mut_ptr(), len);
+                self.set_rx_buffer(ram_buffer)?;
+                if let Some(wr_buffer) = next_write {
+                    self.set_tx_buffer(wr_buffer, len)?;
+                }
+            }
+
+            r.shorts().write(|w| {
+                if next_write.is_none() {
+                    w.set_lastrx_stop(true);
+                } else {
+                    w.set_lastrx_dma_tx_start(true);
+                    if stop {
+                        w.set_lasttx_stop(true);
+                    } else {
+                        w.set_lasttx_suspend(true);
+                    }
+                }
+            });
+
+            // Start read(+write) operation.
+            r.tasks_dma().rx().start().write_value(1);
+            if last_op.is_some() {
+                r.tasks_resume().write_value(1);
             }
+
+            if len == 0 {
+                // With a zero-length buffer, LASTRX doesn't fire (because there's no last byte!), so do the STARTTX/STOP ourselves.
+                if next_write.is_some() {
+                    r.tasks_dma().tx().start().write_value(1);
+                } else {
+                    r.tasks_stop().write_value(1);
+                }
+            }
+
+            return Ok(reads + next_write.is_some() as usize);
+        }
+
+        match operations {
             [Operation::Read(rd_buffer), Operation::Write(wr_buffer), rest @ ..] => {
                 let stop = rest.is_empty();
 
                 // Set up DMA buffers.
                 unsafe {
-                    self.set_tx_buffer(wr_buffer)?;
+                    self.set_tx_buffer(wr_buffer, 0)?;
                     self.set_rx_buffer(rd_buffer)?;
                 }
 
@@ -446,7 +514,6 @@ impl<'d> Twim<'d> {
                     r.tasks_resume().write_value(1);
                 }
 
-                // TODO: Handle empty write buffer
                 if rd_buffer.is_empty() {
                     // With a zero-length buffer, LASTRX doesn't fire (because there's no last byte!), so do the STARTTX ourselves.
                     r.tasks_dma().tx().start().write_value(1);
@@ -480,7 +547,7 @@ impl<'d> Twim<'d> {
             {
                 // Set up DMA buffers.
                 unsafe {
-                    self.set_tx_buffer(wr_buffer)?;
+                    self.set_tx_buffer(wr_buffer, 0)?;
                     self.set_rx_buffer(rd_buffer)?;
                 }
 
@@ -502,7 +569,7 @@ impl<'d> Twim<'d> {
 
                 // Set up DMA buffers.
                 unsafe {
-                    self.set_tx_buffer(buffer)?;
+                    self.set_tx_buffer(buffer, 0)?;
                 }
 
                 // Start write operation.
@@ -530,14 +597,34 @@ impl<'d> Twim<'d> {
 
                 Ok(1)
             }
-            [] => unreachable!(),
+            // Consecutive reads are handled above.
+            [] | [Operation::Read(_), Operation::Read(_), ..] => unreachable!(),
         }
     }
 
-    fn check_operations(&mut self, operations: &[Operation<'_>]) -> Result<(), Error> {
+    fn check_operations(&mut self, operations: &mut [Operation<'_>]) -> Result<(), Error> {
         compiler_fence(SeqCst);
         self.check_errorsrc()?;
 
+        let reads = leading_reads(operations);
+        if reads > 1 {
+            let len = read_len(&operations[..reads]);
+            self.check_rx(len)?;
+            if let Some(Operation::Write(wr_buffer)) = operations.get(reads) {
+                self.check_tx(wr_buffer.len())?;
+            }
+
+            let mut received = &self.tx_ram_buffer[..len];
+            for op in &mut operations[..reads] {
+                if let Operation::Read(rd_buffer) = op {
+                    let (data, rest) = received.split_at(rd_buffer.len());
+                    rd_buffer.copy_from_slice(data);
+                    received = rest;
+                }
+            }
+            return Ok(());
+        }
+
         assert!(operations.len() == 1 || operations.len() == 2);
         match operations {
             [Operation::Read(rd_buffer), Operation::Write(wr_buffer)]
@@ -556,19 +643,63 @@ impl<'d> Twim<'d> {
         Ok(())
     }
 
+    /// Check for what would otherwise fail in the middle of the transaction.
+    fn check_transaction(&self, operations: &[Operation<'_>]) -> Result<(), Error> {
+        let mut rest = operations;
+        while !rest.is_empty() {
+            let reads = leading_reads(rest);
+            // Consecutive reads are received into the RAM buffer, followed by the next write
+            // if that one needs copying.
+            let ram_offset = if reads > 1 {
+                let len = read_len(&rest[..reads]);
+                if len > EASY_DMA_SIZE {
+                    return Err(Error::RxBufferTooLong);
+                }
+                if len > self.tx_ram_buffer.len() {
+                    return Err(Error::RAMBufferTooSmall);
+                }
+                len
+            } else {
+                0
+            };
+            if let Some(Operation::Write(wr_buffer)) = rest.get(reads) {
+                if reads > 0 && wr_buffer.is_empty() {
+                    return Err(Error::UnsupportedTransaction);
+                }
+                if !slice_in_ram(*wr_buffer) && ram_offset + wr_buffer.len() > self.tx_ram_buffer.len() {
+                    return Err(Error::RAMBufferTooSmall);
+                }
+                rest = &rest[reads + 1..];
+            } else {
+                rest = &rest[reads..];
+            }
+        }
+        Ok(())
+    }
+
     // ===========================================
 
     /// Execute the provided operations on the I2C bus.
     ///
+    /// The operations are executed as a single I2C transaction: consecutive operations of the
+    /// same kind are merged, a repeated start is issued when switching between reading and
+    /// writing, and a stop condition is only sent after the last operation.
+    ///
+    /// An `Operation::Write` with an empty buffer sends the address only, which fails with
+    /// [`Error::AddressNack`] if no device acknowledges it. This can be used to probe for
+    /// devices.
+    ///
     /// Each buffer must have a length of at most 255 bytes on the nRF52832
     /// and at most 65535 bytes on the nRF52840.
     ///
-    /// Consecutive `Operation::Read`s are not supported due to hardware
-    /// limitations.
+    /// Consecutive `Operation::Read`s are received into the `tx_ram_buffer` given to
+    /// [`Self::new`] and copied out after, because of hardware limitations. The buffer must be
+    /// at least as large as their combined length, which is limited like a single buffer.
     ///
     /// An `Operation::Write` following an `Operation::Read` must have a
-    /// non-empty buffer.
+    /// non-empty buffer, otherwise [`Error::UnsupportedTransaction`] is returned.
     pub fn blocking_transaction(&mut self, address: u8, mut operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        self.check_transaction(operations)?;
         let mut last_op = None;
         while !operations.is_empty() {
             let ops = self.setup_operations(address, operations, last_op, false)?;
@@ -591,6 +722,7 @@ impl<'d> Twim<'d> {
         mut operations: &mut [Operation<'_>],
         timeout: Duration,
     ) -> Result<(), Error> {
+        self.check_transaction(operations)?;
         let mut last_op = None;
         while !operations.is_empty() {
             let ops = self.setup_operations(address, operations, last_op, false)?;
@@ -605,15 +737,9 @@ impl<'d> Twim<'d> {
 
     /// Execute the provided operations on the I2C bus.
     ///
-    /// Each buffer must have a length of at most 255 bytes on the nRF52832
-    /// and at most 65535 bytes on the nRF52840.
-    ///
-    /// Consecutive `Operation::Read`s are not supported due to hardware
-    /// limitations.
-    ///
-    /// An `Operation::Write` following an `Operation::Read` must have a
-    /// non-empty buffer.
+    /// See [Self::blocking_transaction].
     pub async fn transaction(&mut self, address: u8, mut operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        self.check_transaction(operations)?;
         let mut last_op = None;
         while !operations.is_empty() {
             let ops = self.setup_operations(address, operations, last_op, true)?;
@@ -721,6 +847,25 @@ impl<'d> Twim<'d> {
     }
 }
 
+/// Number of `Operation::Read`s at the start of `operations`.
+fn leading_reads(operations: &[Operation<'_>]) -> usize {
+    operations
+        .iter()
+        .take_while(|op| matches!(op, Operation::Read(_)))
+        .count()
+}
+
+/// Combined length of the `Operation::Read`s in `operations`.
+fn read_len(operations: &[Operation<'_>]) -> usize {
+    operations
+        .iter()
+        .map(|op| match op {
+            Operation::Read(rd_buffer) => rd_buffer.len(),
+            Operation::Write(_) => 0,
+        })
+        .sum()
+}
+
 impl<'a> Drop for Twim<'a> {
     fn drop(&mut self) {
         trace!("twim drop");
@@ -751,6 +896,7 @@ impl State {
 }
 
 pub(crate) trait SealedInstance {
+    const BUS_ID: BusId;
     fn regs() -> pac::twim::Twim;
     fn state() -> &'static State;
 }
@@ -765,6 +911,7 @@ pub trait Instance: SealedInstance + PeripheralType + 'static {
 macro_rules! impl_twim {
     ($type:ident, $pac_type:ident, $irq:ident) => {
         impl crate::twim::SealedInstance for peripherals::$type {
+            const BUS_ID: crate::BusId = crate::BusId::new(stringify!($pac_type));
             fn regs() -> pac::twim::Twim {
                 pac::$pac_type
             }
@@ -825,6 +972,7 @@ impl embedded_hal_1::i2c::Error for Error {
             }
             Self::Overrun => embedded_hal_1::i2c::ErrorKind::Overrun,
             Self::Timeout => embedded_hal_1::i2c::ErrorKind::Other,
+            Self::UnsupportedTransaction => embedded_hal_1::i2c::ErrorKind::Other,
         }
     }
 }
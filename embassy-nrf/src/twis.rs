@@ -93,7 +93,9 @@ pub enum Error {
     BufferNotInRAM,
     /// Overflow
     Overflow,
-    /// Overread
+    /// The master read more bytes than the buffer holds, and got the ORC byte for the extra bytes.
+    ///
+    /// The whole buffer was sent, and the read ended normally.
     OverRead,
     /// Timeout
     Timeout,
@@ -194,7 +196,7 @@ impl<'d> Twis<'d> {
                 });
                 w.set_drive1(gpiovals::Drive::D);
             }
-            if config.sda_pullup {
+            if config.scl_pullup {
                 w.set_pull(gpiovals::Pull::PULLUP);
             }
         });
@@ -363,23 +365,10 @@ impl<'d> Twis<'d> {
     /// Wait for stop or error
     fn blocking_wait(&mut self) -> Result<usize, Error> {
         let r = self.r;
+        let mut overread = false;
         loop {
-            // stop if an error occurred
-            if r.events_error().read() != 0 {
-                r.events_error().write_value(0);
-                r.tasks_stop().write_value(1);
-                let errorsrc = r.errorsrc().read();
-                if errorsrc.overread() {
-                    return Err(Error::OverRead);
-                } else if errorsrc.dnack() {
-                    return Err(Error::DataNack);
-                } else {
-                    return Err(Error::Bus);
-                }
-            } else if r.events_stopped().read() != 0 {
-                r.events_stopped().write_value(0);
-                let n = r.dma().tx().amount().read().0 as usize;
-                return Ok(n);
+            if let Some(res) = Self::poll_respond(r, &mut overread) {
+                return res;
             }
         }
     }
@@ -389,23 +378,10 @@ impl<'d> Twis<'d> {
     fn blocking_wait_timeout(&mut self, timeout: Duration) -> Result<usize, Error> {
         let r = self.r;
         let deadline = Instant::now() + timeout;
+        let mut overread = false;
         loop {
-            // stop if an error occurred
-            if r.events_error().read() != 0 {
-                r.events_error().write_value(0);
-                r.tasks_stop().write_value(1);
-                let errorsrc = r.errorsrc().read();
-                if errorsrc.overread() {
-                    return Err(Error::OverRead);
-                } else if errorsrc.dnack() {
-                    return Err(Error::DataNack);
-                } else {
-                    return Err(Error::Bus);
-                }
-            } else if r.events_stopped().read() != 0 {
-                r.events_stopped().write_value(0);
-                let n = r.dma().tx().amount().read().0 as usize;
-                return Ok(n);
+            if let Some(res) = Self::poll_respond(r, &mut overread) {
+                return res;
             } else if Instant::now() > deadline {
                 r.tasks_stop().write_value(1);
                 return Err(Error::Timeout);
@@ -479,31 +455,45 @@ impl<'d> Twis<'d> {
     fn async_wait(&mut self) -> impl Future<Output = Result<usize, Error>> {
         let r = self.r;
         let s = self.state;
+        let mut overread = false;
         poll_fn(move |cx| {
             s.waker.register(cx.waker());
 
-            // stop if an error occurred
-            if r.events_error().read() != 0 {
-                r.events_error().write_value(0);
-                r.tasks_stop().write_value(1);
-                let errorsrc = r.errorsrc().read();
-                if errorsrc.overread() {
-                    return Poll::Ready(Err(Error::OverRead));
-                } else if errorsrc.dnack() {
-                    return Poll::Ready(Err(Error::DataNack));
-                } else {
-                    return Poll::Ready(Err(Error::Bus));
-                }
-            } else if r.events_stopped().read() != 0 {
-                r.events_stopped().write_value(0);
-                let n = r.dma().tx().amount().read().0 as usize;
-                return Poll::Ready(Ok(n));
+            match Self::poll_respond(r, &mut overread) {
+                Some(res) => Poll::Ready(res),
+                None => Poll::Pending,
             }
-
-            Poll::Pending
         })
     }
 
+    /// Check whether the response to a read ended, returning the number of bytes sent.
+    ///
+    /// A master reading past the end of the buffer gets the ORC byte for the extra bytes. The
+    /// transfer goes on until the master ends it, and [`Error::OverRead`] is returned then.
+    fn poll_respond(r: pac::twis::Twis, overread: &mut bool) -> Option<Result<usize, Error>> {
+        if r.events_error().read() != 0 {
+            r.events_error().write_value(0);
+            let errorsrc = r.errorsrc().read();
+            if errorsrc.overread() && !errorsrc.dnack() {
+                r.errorsrc().write(|w| w.set_overread(true));
+                *overread = true;
+            } else {
+                // stop if another error occurred
+                r.tasks_stop().write_value(1);
+                return Some(Err(if errorsrc.dnack() { Error::DataNack } else { Error::Bus }));
+            }
+        }
+        if r.events_stopped().read() != 0 {
+            r.events_stopped().write_value(0);
+            if *overread {
+                return Some(Err(Error::OverRead));
+            }
+            let n = r.dma().tx().amount().read().0 as usize;
+            return Some(Ok(n));
+        }
+        None
+    }
+
     /// Wait for read or write
     fn async_listen_wait(&mut self) -> impl Future<Output = Result<Status, Error>> {
         let r = self.r;
@@ -15,7 +15,7 @@
 
 use core::future::poll_fn;
 use core::marker::PhantomData;
-use core::sync::atomic::{AtomicU8, Ordering, compiler_fence};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering, compiler_fence};
 use core::task::Poll;
 
 use embassy_hal_internal::drop::OnDrop;
@@ -28,11 +28,12 @@ use crate::chip::{EASY_DMA_SIZE, FORCE_COPY_BUFFER_SIZE};
 use crate::gpio::{self, AnyPin, DISCONNECTED, Pin as GpioPin, PselBits, SealedPin as _};
 use crate::interrupt::typelevel::Interrupt;
 use crate::pac::gpio::vals as gpiovals;
+use crate::pac::shared::vals::Connect;
 use crate::pac::uarte::vals;
 use crate::ppi::{AnyConfigurableChannel, ConfigurableChannel, Event, Ppi, Task};
 use crate::timer::{Frequency, Instance as TimerInstance, Timer};
 use crate::util::slice_in_ram_or;
-use crate::{interrupt, pac};
+use crate::{BusId, interrupt, pac};
 
 /// UARTE config.
 #[derive(Clone)]
@@ -101,6 +102,11 @@ pub enum Error {
     Overrun,
     /// Break condition
     Break,
+    /// A transfer is in progress.
+    Busy,
+    /// The secure firmware didn't assign the peripheral to the non-secure domain.
+    #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+    NotAssignedToNonSecure,
 }
 
 /// Interrupt handler.
@@ -110,25 +116,28 @@ pub struct InterruptHandler<T: Instance> {
 
 impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
     unsafe fn on_interrupt() {
-        let r = T::regs();
-        let s = T::state();
+        on_interrupt(T::regs(), T::state())
+    }
+}
 
-        let endrx = r.events_dma().rx().end().read();
-        let error = r.events_error().read();
-        if endrx != 0 || error != 0 {
-            s.rx_waker.wake();
-            if endrx != 0 {
-                r.intenclr().write(|w| w.set_dmarxend(true));
-            }
-            if error != 0 {
-                r.intenclr().write(|w| w.set_error(true));
-            }
+/// Shared with tests, which run it on a fake register block.
+#[inline(always)]
+fn on_interrupt(r: pac::uarte::Uarte, s: &State) {
+    let endrx = r.events_dma().rx().end().read();
+    let error = r.events_error().read();
+    if endrx != 0 || error != 0 {
+        s.rx_waker.wake();
+        if endrx != 0 {
+            r.intenclr().write(|w| w.set_dmarxend(true));
         }
-        if r.events_dma().tx().end().read() != 0 {
-            s.tx_waker.wake();
-            r.intenclr().write(|w| w.set_dmatxend(true));
+        if error != 0 {
+            r.intenclr().write(|w| w.set_error(true));
         }
     }
+    if r.events_dma().tx().end().read() != 0 {
+        s.tx_waker.wake();
+        r.intenclr().write(|w| w.set_dmatxend(true));
+    }
 }
 
 /// UARTE driver.
@@ -143,6 +152,7 @@ pub struct Uarte<'d> {
 pub struct UarteTx<'d> {
     r: pac::uarte::Uarte,
     state: &'static State,
+    id: BusId,
     _p: PhantomData<&'d ()>,
 }
 
@@ -152,6 +162,7 @@ pub struct UarteTx<'d> {
 pub struct UarteRx<'d> {
     r: pac::uarte::Uarte,
     state: &'static State,
+    id: BusId,
     _p: PhantomData<&'d ()>,
 }
 
@@ -167,6 +178,24 @@ impl<'d> Uarte<'d> {
         Self::new_inner(uarte, rxd.into(), txd.into(), None, None, config)
     }
 
+    /// Create a new UARTE without hardware flow control, failing if the secure firmware
+    /// withheld the peripheral.
+    ///
+    /// See [`crate::security`].
+    #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+    pub fn try_new<T: Instance>(
+        uarte: Peri<'d, T>,
+        rxd: Peri<'d, impl GpioPin>,
+        txd: Peri<'d, impl GpioPin>,
+        irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        config: Config,
+    ) -> Result<Self, Error> {
+        if !crate::security::is_assigned(T::regs().as_ptr()) {
+            return Err(Error::NotAssignedToNonSecure);
+        }
+        Ok(Self::new(uarte, rxd, txd, irq, config))
+    }
+
     /// Create a new UARTE with hardware flow control (RTS/CTS)
     pub fn new_with_rtscts<T: Instance>(
         uarte: Peri<'d, T>,
@@ -197,6 +226,9 @@ impl<'d> Uarte<'d> {
     ) -> Self {
         let r = T::regs();
 
+        #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+        crate::security::assert_assigned(r.as_ptr(), "UARTE");
+
         let hardware_flow_control = match (rts.is_some(), cts.is_some()) {
             (false, false) => false,
             (true, true) => true,
@@ -217,11 +249,13 @@ impl<'d> Uarte<'d> {
             tx: UarteTx {
                 r: T::regs(),
                 state: T::state(),
+                id: T::BUS_ID,
                 _p: PhantomData {},
             },
             rx: UarteRx {
                 r: T::regs(),
                 state: T::state(),
+                id: T::BUS_ID,
                 _p: PhantomData {},
             },
         }
@@ -254,6 +288,71 @@ impl<'d> Uarte<'d> {
         (self.tx, self.rx.with_idle(timer, ppi_ch1, ppi_ch2))
     }
 
+    /// Identity of the peripheral instance, for error reporting.
+    pub fn bus_id(&self) -> BusId {
+        self.tx.id
+    }
+
+    /// Change the baud rate.
+    ///
+    /// The receiver and transmitter are stopped, the new rate is programmed and the
+    /// peripheral is re-enabled, so the driver does not need to be re-created. Bytes that
+    /// arrive while the rate is being changed are lost.
+    ///
+    /// Returns [`Error::Busy`] if a transfer is still in progress.
+    pub fn set_baudrate(&mut self, baudrate: Baudrate) -> Result<(), Error> {
+        self.reconfigure(|r| r.baudrate().write(|w| w.set_baudrate(baudrate)))
+    }
+
+    /// Enable or disable hardware flow control (RTS/CTS).
+    ///
+    /// The peripheral is stopped and restarted like in [`set_baudrate`](Self::set_baudrate).
+    ///
+    /// Returns [`Error::Busy`] if a transfer is still in progress.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `enabled` is true but the driver was created without RTS and CTS pins.
+    pub fn set_hwfc(&mut self, enabled: bool) -> Result<(), Error> {
+        if enabled && !has_rtscts(self.tx.r) {
+            panic!("Hardware flow control requires RTS and CTS pins.");
+        }
+        self.reconfigure(|r| r.config().modify(|w| w.set_hwfc(enabled)))
+    }
+
+    fn reconfigure(&mut self, f: impl FnOnce(pac::uarte::Uarte)) -> Result<(), Error> {
+        let r = self.tx.r;
+
+        // A transfer is in flight if it has started but not ended. A cancelled write
+        // leaves the started event set too, but it has already ended and been stopped.
+        let rx_started = r.events_dma().rx().ready().read() != 0;
+        let tx_started = r.events_dma().tx().ready().read() != 0;
+        if (rx_started && r.events_dma().rx().end().read() == 0)
+            || (tx_started && r.events_dma().tx().end().read() == 0)
+        {
+            return Err(Error::Busy);
+        }
+
+        stop(r, self.rx.state);
+        f(r);
+        r.enable().write(|w| w.set_enable(vals::Enable::ENABLED));
+
+        Ok(())
+    }
+
+    /// Get a hook that disables the peripheral during a deep sleep.
+    ///
+    /// See [`UarteSleepHook`].
+    #[cfg(feature = "low-power")]
+    pub fn sleep_hook(&self) -> UarteSleepHook<'d> {
+        UarteSleepHook {
+            r: self.tx.r,
+            state: self.tx.state,
+            disabled: false,
+            _p: PhantomData,
+        }
+    }
+
     /// Return the endtx event for use with PPI
     pub fn event_endtx(&self) -> Event<'_> {
         let r = self.tx.r;
@@ -353,6 +452,10 @@ pub(crate) fn configure_rx_pins(r: pac::uarte::Uarte, rxd: Peri<'_, AnyPin>, rts
     r.psel().rts().write_value(rts.psel_bits());
 }
 
+pub(crate) fn has_rtscts(r: pac::uarte::Uarte) -> bool {
+    r.psel().cts().read().connect() != Connect::DISCONNECTED && r.psel().rts().read().connect() != Connect::DISCONNECTED
+}
+
 pub(crate) fn configure(r: pac::uarte::Uarte, config: Config, hardware_flow_control: bool) {
     r.config().write(|w| {
         w.set_hwfc(hardware_flow_control);
@@ -411,6 +514,9 @@ impl<'d> UarteTx<'d> {
     ) -> Self {
         let r = T::regs();
 
+        #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+        crate::security::assert_assigned(r.as_ptr(), "UARTE");
+
         configure(r, config, cts.is_some());
         configure_tx_pins(r, txd, cts);
 
@@ -424,10 +530,16 @@ impl<'d> UarteTx<'d> {
         Self {
             r: T::regs(),
             state: T::state(),
+            id: T::BUS_ID,
             _p: PhantomData {},
         }
     }
 
+    /// Identity of the peripheral instance, for error reporting.
+    pub fn bus_id(&self) -> BusId {
+        self.id
+    }
+
     /// Write all bytes in the buffer.
     pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
         match self.write_from_ram(buffer).await {
@@ -453,6 +565,9 @@ impl<'d> UarteTx<'d> {
             return Err(Error::BufferTooLong);
         }
 
+        #[cfg(feature = "low-power")]
+        let _veto = crate::low_power::veto();
+
         let ptr = buffer.as_ptr();
         let len = buffer.len();
 
@@ -604,6 +719,9 @@ impl<'d> UarteRx<'d> {
     ) -> Self {
         let r = T::regs();
 
+        #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+        crate::security::assert_assigned(r.as_ptr(), "UARTE");
+
         configure(r, config, rts.is_some());
         configure_rx_pins(r, rxd, rts);
 
@@ -617,10 +735,16 @@ impl<'d> UarteRx<'d> {
         Self {
             r: T::regs(),
             state: T::state(),
+            id: T::BUS_ID,
             _p: PhantomData {},
         }
     }
 
+    /// Identity of the peripheral instance, for error reporting.
+    pub fn bus_id(&self) -> BusId {
+        self.id
+    }
+
     /// Upgrade to an instance that supports idle line detection.
     pub fn with_idle<U: TimerInstance>(
         self,
@@ -682,6 +806,9 @@ impl<'d> UarteRx<'d> {
             return Err(Error::BufferTooLong);
         }
 
+        #[cfg(feature = "low-power")]
+        let _veto = crate::low_power::veto();
+
         let ptr = buffer.as_ptr();
         let len = buffer.len();
 
@@ -717,6 +844,8 @@ impl<'d> UarteRx<'d> {
         compiler_fence(Ordering::SeqCst);
 
         trace!("startrx");
+        r.events_rxto().write_value(0);
+        self.state.rx_running.store(true, Ordering::Relaxed);
         r.tasks_dma().rx().start().write_value(1);
 
         let result = poll_fn(|cx| {
@@ -767,6 +896,8 @@ impl<'d> UarteRx<'d> {
         compiler_fence(Ordering::SeqCst);
 
         trace!("startrx");
+        r.events_rxto().write_value(0);
+        self.state.rx_running.store(true, Ordering::Relaxed);
         r.tasks_dma().rx().start().write_value(1);
 
         while r.events_dma().rx().end().read() == 0 && r.events_error().read() == 0 {}
@@ -832,6 +963,9 @@ impl<'d> UarteRxWithIdle<'d> {
             return Err(Error::BufferTooLong);
         }
 
+        #[cfg(feature = "low-power")]
+        let _veto = crate::low_power::veto();
+
         let ptr = buffer.as_ptr();
         let len = buffer.len();
 
@@ -998,6 +1132,65 @@ pub(crate) fn apply_workaround_for_enable_anomaly(r: pac::uarte::Uarte) {
     }
 }
 
+/// Stop the receiver and transmitter, which must have no transfer in flight, and disable
+/// the peripheral.
+fn stop(r: pac::uarte::Uarte, s: &State) {
+    // RXTO is cleared whenever a read starts.
+    if s.rx_running.load(Ordering::Relaxed) {
+        if r.events_rxto().read() == 0 {
+            r.tasks_dma().rx().stop().write_value(1);
+        }
+        while r.events_rxto().read() == 0 {}
+        s.rx_running.store(false, Ordering::Relaxed);
+    }
+
+    // A completed or cancelled write has already stopped the transmitter.
+    if r.events_dma().tx().ready().read() == 0 {
+        r.events_txstopped().write_value(0);
+        r.tasks_dma().tx().stop().write_value(1);
+    }
+    while r.events_txstopped().read() == 0 {}
+
+    r.events_dma().rx().ready().write_value(0);
+    r.events_dma().tx().ready().write_value(0);
+
+    r.enable().write(|w| w.set_enable(vals::Enable::DISABLED));
+}
+
+/// Disables an idle UARTE during a deep sleep, see [`low_power`](crate::low_power).
+///
+/// The receiver is stopped if it is still running after a read, so bytes arriving while the
+/// chip sleeps are lost. Reads and writes prevent deep sleep while they are in progress.
+///
+/// Obtained with [`Uarte::sleep_hook`]. It borrows the peripheral for as long as the driver, so
+/// no other driver can use the peripheral while the hook exists. If the driver is dropped, the
+/// hook does nothing.
+#[cfg(feature = "low-power")]
+pub struct UarteSleepHook<'d> {
+    r: pac::uarte::Uarte,
+    state: &'static State,
+    disabled: bool,
+    _p: PhantomData<&'d ()>,
+}
+
+#[cfg(feature = "low-power")]
+impl<'d> crate::low_power::SleepHook for UarteSleepHook<'d> {
+    fn prepare_sleep(&mut self, _planned: embassy_time::Duration) {
+        // Not enabled if the driver was dropped.
+        self.disabled = self.r.enable().read().enable() == vals::Enable::ENABLED;
+        if self.disabled {
+            stop(self.r, self.state);
+        }
+    }
+
+    fn resume(&mut self) {
+        if self.disabled {
+            self.r.enable().write(|w| w.set_enable(vals::Enable::ENABLED));
+            self.disabled = false;
+        }
+    }
+}
+
 pub(crate) fn drop_tx_rx(r: pac::uarte::Uarte, s: &State) {
     if s.tx_rx_refcount.fetch_sub(1, Ordering::Relaxed) == 1 {
         // Finally we can disable, and we do so for the peripheral
@@ -1017,6 +1210,9 @@ pub(crate) struct State {
     pub(crate) rx_waker: AtomicWaker,
     pub(crate) tx_waker: AtomicWaker,
     pub(crate) tx_rx_refcount: AtomicU8,
+    /// The receiver stays started after a read completes, unless it was stopped by an
+    /// error or a cancelled read.
+    pub(crate) rx_running: AtomicBool,
 }
 impl State {
     pub(crate) const fn new() -> Self {
@@ -1024,11 +1220,13 @@ impl State {
             rx_waker: AtomicWaker::new(),
             tx_waker: AtomicWaker::new(),
             tx_rx_refcount: AtomicU8::new(0),
+            rx_running: AtomicBool::new(false),
         }
     }
 }
 
 pub(crate) trait SealedInstance {
+    const BUS_ID: BusId;
     fn regs() -> pac::uarte::Uarte;
     fn state() -> &'static State;
     fn buffered_state() -> &'static crate::buffered_uarte::State;
@@ -1044,6 +1242,7 @@ pub trait Instance: SealedInstance + PeripheralType + 'static + Send {
 macro_rules! impl_uarte {
     ($type:ident, $pac_type:ident, $irq:ident) => {
         impl crate::uarte::SealedInstance for peripherals::$type {
+            const BUS_ID: crate::BusId = crate::BusId::new(stringify!($pac_type));
             fn regs() -> pac::uarte::Uarte {
                 pac::$pac_type
             }
@@ -1101,6 +1300,9 @@ impl core::fmt::Display for Error {
             Self::Parity => f.write_str("Parity"),
             Self::Overrun => f.write_str("Overrun"),
             Self::Break => f.write_str("Break"),
+            Self::Busy => f.write_str("Busy"),
+            #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+            Self::NotAssignedToNonSecure => f.write_str("NotAssignedToNonSecure"),
         }
     }
 }
@@ -1118,6 +1320,9 @@ mod _embedded_io {
                 Error::Parity => embedded_io_async::ErrorKind::InvalidData,
                 Error::Overrun => embedded_io_async::ErrorKind::OutOfMemory,
                 Error::Break => embedded_io_async::ErrorKind::ConnectionAborted,
+                Error::Busy => embedded_io_async::ErrorKind::Other,
+                #[cfg(all(feature = "_ns", any(feature = "_nrf5340-app", feature = "_nrf91")))]
+                Error::NotAssignedToNonSecure => embedded_io_async::ErrorKind::PermissionDenied,
             }
         }
     }
@@ -1150,3 +1355,101 @@ mod _embedded_io {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::mem::ManuallyDrop;
+    use std::boxed::Box;
+
+    use super::*;
+    use crate::replay::{FakeRegs, Step, replay};
+
+    fn rx(regs: &FakeRegs) -> UarteRx<'static> {
+        UarteRx {
+            r: unsafe { pac::uarte::Uarte::from_ptr(regs.ptr()) },
+            state: Box::leak(Box::new(State::new())),
+            id: BusId::new("UARTE0"),
+            _p: PhantomData,
+        }
+    }
+
+    /// A read is cancelled just as its last byte arrives, so ENDRX is raised before the drop
+    /// stops the receiver. The stale ENDRX must not complete the next read.
+    #[test]
+    fn read_cancelled_as_endrx_races_stop() {
+        let regs = FakeRegs::new();
+        let mut rx = ManuallyDrop::new(rx(&regs));
+        let (r, s) = (rx.r, rx.state);
+        let endrx = r.events_dma().rx().end().as_ptr();
+        let startrx = r.tasks_dma().rx().start().as_ptr();
+        let stoprx = r.tasks_dma().rx().stop().as_ptr();
+
+        let mut buf = [0; 4];
+        let out = replay(
+            &regs,
+            rx.read(&mut buf),
+            || on_interrupt(r, s),
+            &[
+                Step::Pending,
+                Step::Task(startrx),
+                Step::Set(endrx, 1),
+                Step::Drop,
+                Step::Task(stoprx),
+                Step::Wakes(0),
+            ],
+        );
+        assert_eq!(out, None);
+
+        let out = replay(
+            &regs,
+            rx.read(&mut buf),
+            || on_interrupt(r, s),
+            &[
+                Step::Pending,
+                Step::Task(startrx),
+                Step::Interrupt,
+                Step::Wakes(0),
+                Step::Pending,
+                Step::Set(endrx, 1),
+                Step::Interrupt,
+                Step::Wakes(1),
+                Step::Ready,
+                Step::NoTask(stoprx),
+            ],
+        );
+        assert_eq!(out, Some(Ok(())));
+    }
+
+    /// A framing error on the last byte raises ERROR and ENDRX together, handled by a single
+    /// interrupt. The read reports the error and stops the receiver.
+    #[test]
+    fn read_endrx_with_error() {
+        let regs = FakeRegs::new();
+        let mut rx = ManuallyDrop::new(rx(&regs));
+        let (r, s) = (rx.r, rx.state);
+        let endrx = r.events_dma().rx().end().as_ptr();
+        let error = r.events_error().as_ptr();
+        let errorsrc = r.errorsrc().as_ptr() as *mut u32;
+        let stoprx = r.tasks_dma().rx().stop().as_ptr();
+
+        let mut buf = [0; 4];
+        let out = replay(
+            &regs,
+            rx.read(&mut buf),
+            || on_interrupt(r, s),
+            &[
+                Step::Pending,
+                Step::Set(errorsrc, ErrorSource::FRAMING.bits()),
+                Step::Set(error, 1),
+                Step::Set(endrx, 1),
+                Step::Interrupt,
+                Step::Wakes(1),
+                Step::Ready,
+                Step::Task(stoprx),
+            ],
+        );
+        assert_eq!(out, Some(Err(Error::Framing)));
+    }
+}
@@ -7,11 +7,13 @@ pub mod vbus_detect;
 use core::future::{Future, poll_fn};
 use core::marker::PhantomData;
 use core::mem::MaybeUninit;
-use core::sync::atomic::{AtomicU32, Ordering, compiler_fence};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering, compiler_fence};
 use core::task::Poll;
 
 use cortex_m::peripheral::NVIC;
 use embassy_hal_internal::{Peri, PeripheralType};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
 use embassy_sync::waitqueue::AtomicWaker;
 use embassy_usb_driver as driver;
 use embassy_usb_driver::{Direction, EndpointAddress, EndpointError, EndpointInfo, EndpointType, Event, Unsupported};
@@ -27,6 +29,9 @@ static EP0_WAKER: AtomicWaker = AtomicWaker::new();
 static EP_IN_WAKERS: [AtomicWaker; 8] = [const { AtomicWaker::new() }; 8];
 static EP_OUT_WAKERS: [AtomicWaker; 8] = [const { AtomicWaker::new() }; 8];
 static READY_ENDPOINTS: AtomicU32 = AtomicU32::new(0);
+static POWER_EVENTS: Channel<CriticalSectionRawMutex, PowerEvent, 4> = Channel::new();
+static VBUS_DETECTED: AtomicBool = AtomicBool::new(false);
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
 
 /// Interrupt handler.
 pub struct InterruptHandler<T: Instance> {
@@ -85,11 +90,43 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
     }
 }
 
+/// Number of bulk/interrupt endpoints per direction, EP1 to EP7, as flagged in `EPDATASTATUS`.
+const MAX_ENDPOINTS: u8 = 7;
+/// Largest bulk/interrupt packet the USBD can transfer, the full speed maximum.
+const MAX_PACKET_SIZE: u16 = 64;
+
+/// Endpoint allocation limits of the USB driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct DriverConfig {
+    /// Largest packet size allowed for bulk and interrupt endpoints, 1 to 64 bytes.
+    ///
+    /// The USBD is a full speed controller: 64 bytes is the hardware and USB specification
+    /// limit. Allocating an endpoint with a larger packet size fails.
+    pub max_packet_size: u16,
+    /// Number of bulk and interrupt endpoints that may be allocated in each direction, 0 to 7.
+    ///
+    /// Endpoints are allocated from EP1 up. Allocating one past this number, or requesting an
+    /// address past it, fails.
+    pub endpoints: u8,
+}
+
+impl Default for DriverConfig {
+    fn default() -> Self {
+        Self {
+            max_packet_size: MAX_PACKET_SIZE,
+            endpoints: MAX_ENDPOINTS,
+        }
+    }
+}
+
 /// USB driver.
 pub struct Driver<'d, V: VbusDetect> {
     regs: pac::usbd::Usbd,
     alloc_in: Allocator,
     alloc_out: Allocator,
+    max_packet_size: u16,
     vbus_detect: V,
     _phantom: PhantomData<&'d ()>,
 }
@@ -97,21 +134,150 @@ pub struct Driver<'d, V: VbusDetect> {
 impl<'d, V: VbusDetect> Driver<'d, V> {
     /// Create a new USB driver.
     pub fn new<T: Instance>(
+        usb: Peri<'d, T>,
+        irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        vbus_detect: V,
+    ) -> Self {
+        Self::new_with_config(usb, irq, vbus_detect, DriverConfig::default())
+    }
+
+    /// Create a new USB driver with endpoint allocation limits.
+    ///
+    /// Panics if `config` exceeds the limits of the USBD: 64 byte packets and 7 bulk or
+    /// interrupt endpoints per direction.
+    pub fn new_with_config<T: Instance>(
         _usb: Peri<'d, T>,
         _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
         vbus_detect: V,
+        config: DriverConfig,
     ) -> Self {
+        assert!(
+            (1..=MAX_PACKET_SIZE).contains(&config.max_packet_size),
+            "USB max packet size must be 1 to 64 bytes, the USBD doesn't support larger bulk or interrupt packets"
+        );
+        assert!(
+            config.endpoints <= MAX_ENDPOINTS,
+            "the USBD has only 7 bulk or interrupt endpoints per direction"
+        );
+
+        // The controller may still be running, left enabled by a bootloader or by the application
+        // before a warm reset. Enabling it again would never report READY, so power it down first.
+        let regs = T::regs();
+        if regs.enable().read().enable() {
+            power_down(regs);
+        }
+
         T::Interrupt::unpend();
         unsafe { T::Interrupt::enable() };
 
         Self {
-            regs: crate::pac::USBD,
-            alloc_in: Allocator::new(),
-            alloc_out: Allocator::new(),
+            regs,
+            alloc_in: Allocator::new(config.endpoints),
+            alloc_out: Allocator::new(config.endpoints),
+            max_packet_size: config.max_packet_size,
             vbus_detect,
             _phantom: PhantomData,
         }
     }
+
+    fn check_packet_size(&self, ep_type: EndpointType, packet_size: u16) -> Result<(), driver::EndpointAllocError> {
+        if ep_type != EndpointType::Isochronous && packet_size > self.max_packet_size {
+            warn!(
+                "USB endpoint packet size {} exceeds the maximum of {}",
+                packet_size, self.max_packet_size
+            );
+            return Err(driver::EndpointAllocError);
+        }
+        Ok(())
+    }
+
+    /// Power down the USBD and release it.
+    ///
+    /// This detaches from the bus, disables the USBD interrupts and clears `ENABLE`, so the host
+    /// sees a disconnect. Call it before handing over to another image, e.g. before a DFU reset,
+    /// then create a new driver with [`Driver::new`] to use the USBD again. Once the driver has
+    /// been started, `UsbDevice::disable` powers the USBD down the same way.
+    pub fn disable(self) {
+        power_down(self.regs);
+    }
+
+    /// Get a handle to wait for the power events of the bus.
+    ///
+    /// The handle stays usable after the driver is moved into the USB stack.
+    pub fn power_events(&self) -> PowerEvents {
+        PowerEvents { _private: () }
+    }
+}
+
+/// Detach from the bus and power down the USBD, leaving it ready to be enabled again.
+fn power_down(regs: pac::usbd::Usbd) {
+    regs.usbpullup().write(|w| w.set_connect(false));
+    regs.intenclr().write(|w| w.0 = 0xFFFF_FFFF);
+    regs.enable().write(|w| w.set_enable(false));
+
+    regs.events_usbreset().write_value(0);
+    regs.events_usbevent().write_value(0);
+    regs.events_epdata().write_value(0);
+    regs.eventcause().write_value(regs.eventcause().read());
+    regs.epdatastatus().write_value(regs.epdatastatus().read());
+    READY_ENDPOINTS.store(0, Ordering::Release);
+}
+
+/// Power event of the bus, as seen by the USB stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerEvent {
+    /// VBUS was detected.
+    VbusDetected,
+    /// VBUS was removed.
+    VbusRemoved,
+    /// The host suspended the bus.
+    Suspend,
+    /// The bus was resumed, by the host, by a remote wakeup or by a bus reset.
+    Resume,
+}
+
+/// Handle to wait for [`PowerEvent`]s, returned by [`Driver::power_events`].
+///
+/// The events are recorded as the USB stack handles them, so `UsbDevice::run` must be running.
+/// Up to 4 events are buffered, after which the oldest ones are dropped. With several handles,
+/// each event is received by only one of them.
+pub struct PowerEvents {
+    _private: (),
+}
+
+impl PowerEvents {
+    /// Wait for the next power event.
+    pub async fn next(&mut self) -> PowerEvent {
+        POWER_EVENTS.receive().await
+    }
+
+    /// Whether VBUS is detected, as of the last event.
+    pub fn is_vbus_detected(&self) -> bool {
+        VBUS_DETECTED.load(Ordering::Relaxed)
+    }
+
+    /// Whether the bus is suspended, as of the last event.
+    pub fn is_suspended(&self) -> bool {
+        SUSPENDED.load(Ordering::Relaxed)
+    }
+}
+
+/// Record a power event, unless it doesn't change the state.
+fn power_event(event: PowerEvent) {
+    let changed = match event {
+        PowerEvent::VbusDetected => !VBUS_DETECTED.swap(true, Ordering::Relaxed),
+        PowerEvent::VbusRemoved => {
+            SUSPENDED.store(false, Ordering::Relaxed);
+            VBUS_DETECTED.swap(false, Ordering::Relaxed)
+        }
+        PowerEvent::Suspend => !SUSPENDED.swap(true, Ordering::Relaxed),
+        PowerEvent::Resume => SUSPENDED.swap(false, Ordering::Relaxed),
+    };
+    if changed && POWER_EVENTS.try_send(event).is_err() {
+        let _ = POWER_EVENTS.try_receive();
+        let _ = POWER_EVENTS.try_send(event);
+    }
 }
 
 impl<'d, V: VbusDetect + 'd> driver::Driver<'d> for Driver<'d, V> {
@@ -127,6 +293,7 @@ impl<'d, V: VbusDetect + 'd> driver::Driver<'d> for Driver<'d, V> {
         packet_size: u16,
         interval_ms: u8,
     ) -> Result<Self::EndpointIn, driver::EndpointAllocError> {
+        self.check_packet_size(ep_type, packet_size)?;
         let index = self.alloc_in.allocate(ep_type, ep_addr)?;
         let ep_addr = EndpointAddress::from_parts(index, Direction::In);
         Ok(Endpoint::new(
@@ -147,6 +314,7 @@ impl<'d, V: VbusDetect + 'd> driver::Driver<'d> for Driver<'d, V> {
         packet_size: u16,
         interval_ms: u8,
     ) -> Result<Self::EndpointOut, driver::EndpointAllocError> {
+        self.check_packet_size(ep_type, packet_size)?;
         let index = self.alloc_out.allocate(ep_type, ep_addr)?;
         let ep_addr = EndpointAddress::from_parts(index, Direction::Out);
         Ok(Endpoint::new(
@@ -226,8 +394,8 @@ impl<'d, V: VbusDetect> driver::Bus for Bus<'d, V> {
     }
 
     async fn disable(&mut self) {
-        let regs = self.regs;
-        regs.enable().write(|x| x.set_enable(false));
+        power_down(self.regs);
+        trace!("disabled");
     }
 
     fn poll(&mut self) -> impl Future<Output = Event> {
@@ -248,6 +416,7 @@ impl<'d, V: VbusDetect> driver::Bus for Bus<'d, V> {
                     Out::waker(i).wake();
                 }
 
+                power_event(PowerEvent::Resume);
                 return Poll::Ready(Event::Reset);
             }
 
@@ -264,10 +433,12 @@ impl<'d, V: VbusDetect> driver::Bus for Bus<'d, V> {
             if r.suspend() {
                 regs.eventcause().write(|w| w.set_suspend(true));
                 regs.lowpower().write(|w| w.set_lowpower(vals::Lowpower::LOW_POWER));
+                power_event(PowerEvent::Suspend);
                 return Poll::Ready(Event::Suspend);
             }
             if r.resume() {
                 regs.eventcause().write(|w| w.set_resume(true));
+                power_event(PowerEvent::Resume);
                 return Poll::Ready(Event::Resume);
             }
             if r.ready() {
@@ -279,9 +450,11 @@ impl<'d, V: VbusDetect> driver::Bus for Bus<'d, V> {
                 self.power_available = !self.power_available;
                 if self.power_available {
                     trace!("Power event: available");
+                    power_event(PowerEvent::VbusDetected);
                     return Poll::Ready(Event::PowerDetected);
                 } else {
                     trace!("Power event: removed");
+                    power_event(PowerEvent::VbusRemoved);
                     return Poll::Ready(Event::PowerRemoved);
                 }
             }
@@ -396,6 +569,7 @@ impl<'d, V: VbusDetect> driver::Bus for Bus<'d, V> {
             .await;
 
             errata::post_wakeup();
+            power_event(PowerEvent::Resume);
         }
 
         Ok(())
@@ -737,11 +911,16 @@ fn dma_end() {
 
 struct Allocator {
     used: u16,
+    /// Number of bulk/interrupt endpoints that may be allocated.
+    endpoints: usize,
 }
 
 impl Allocator {
-    fn new() -> Self {
-        Self { used: 0 }
+    fn new(endpoints: u8) -> Self {
+        Self {
+            used: 0,
+            endpoints: endpoints as usize,
+        }
     }
 
     fn allocate(
@@ -774,7 +953,7 @@ impl Allocator {
                 }
                 EndpointType::Control => return Err(driver::EndpointAllocError),
                 EndpointType::Interrupt | EndpointType::Bulk => {
-                    if requested_index < 1 || requested_index > 7 {
+                    if requested_index < 1 || requested_index > self.endpoints {
                         return Err(driver::EndpointAllocError);
                     }
                 }
@@ -788,7 +967,7 @@ impl Allocator {
                 EndpointType::Interrupt | EndpointType::Bulk => {
                     // Find rightmost zero bit in 1..=7
                     let ones = (self.used >> 1).trailing_ones() as usize;
-                    if ones >= 7 {
+                    if ones >= self.endpoints {
                         return Err(driver::EndpointAllocError);
                     }
                     ones + 1
@@ -1,12 +1,17 @@
 //! Trait and implementations for performing VBUS detection.
 
+use core::cell::RefCell;
 use core::future::{Future, poll_fn};
 use core::sync::atomic::{AtomicBool, Ordering};
 use core::task::Poll;
 
 use embassy_sync::waitqueue::AtomicWaker;
+#[cfg(feature = "time")]
+use embassy_time::{Duration, Timer};
+use embedded_hal_1::digital::InputPin;
 
 use super::BUS_WAKER;
+use crate::gpio::Level;
 use crate::interrupt::typelevel::Interrupt;
 use crate::{interrupt, pac};
 
@@ -176,3 +181,94 @@ impl VbusDetect for &SoftwareVbusDetect {
         })
     }
 }
+
+/// GPIO-backed [`VbusDetect`] implementation.
+///
+/// For boards where VBUS is sensed with a GPIO pin, through a divider if needed, rather than
+/// by the `POWER` peripheral. By default a high level on the pin means VBUS is present, use
+/// [`new_with_active_level`](Self::new_with_active_level) for an active low signal, e.g. the
+/// power good output of a PMIC. USB power is reported ready as soon as VBUS is detected.
+///
+/// The pin is only sampled by [`update`](Self::update), or on each of its edges while
+/// [`run`](Self::run) is running. The USB driver takes a reference:
+///
+/// ```rust,ignore
+/// let vbus = GpioVbusDetect::new(Input::new(p.P0_02, Pull::Down));
+/// let driver = Driver::new(p.USBD, Irqs, &vbus);
+/// // ... build the USB device ...
+/// join(usb.run(), vbus.run(Duration::from_millis(10))).await;
+/// ```
+pub struct GpioVbusDetect<P> {
+    pin: RefCell<P>,
+    active: Level,
+    usb_detected: AtomicBool,
+}
+
+impl<P: InputPin> GpioVbusDetect<P> {
+    /// Create a new `GpioVbusDetect` for an active high pin, sampling the pin once.
+    pub fn new(pin: P) -> Self {
+        Self::new_with_active_level(pin, Level::High)
+    }
+
+    /// Create a new `GpioVbusDetect`, with VBUS present while the pin is at the `active` level.
+    pub fn new_with_active_level(pin: P, active: Level) -> Self {
+        let this = Self {
+            pin: RefCell::new(pin),
+            active,
+            usb_detected: AtomicBool::new(false),
+        };
+        this.update();
+        this
+    }
+
+    /// Sample the pin, and notify the USB driver if the level changed.
+    ///
+    /// Does nothing while [`run`](Self::run) is running, which samples the pin itself.
+    pub fn update(&self) {
+        if let Ok(mut pin) = self.pin.try_borrow_mut()
+            && let Ok(high) = pin.is_high()
+        {
+            self.set_level(high);
+        }
+    }
+
+    /// Sample the pin `debounce` after each of its edges, ignoring the bounces in between.
+    ///
+    /// With an [`Input`](crate::gpio::Input), the edges are detected by GPIOTE, which needs
+    /// the `gpiote` feature.
+    #[cfg(feature = "time")]
+    #[allow(clippy::await_holding_refcell_ref)]
+    pub async fn run(&self, debounce: Duration) -> !
+    where
+        P: embedded_hal_async::digital::Wait,
+    {
+        // Held for good: `update` backs off while it is.
+        let mut pin = self.pin.borrow_mut();
+        loop {
+            let _ = pin.wait_for_any_edge().await;
+            Timer::after(debounce).await;
+            if let Ok(high) = pin.is_high() {
+                self.set_level(high);
+            }
+        }
+    }
+
+    fn set_level(&self, high: bool) {
+        let detected = high == (self.active == Level::High);
+        if self.usb_detected.load(Ordering::Relaxed) != detected {
+            self.usb_detected.store(detected, Ordering::Relaxed);
+            BUS_WAKER.wake();
+            POWER_WAKER.wake();
+        }
+    }
+}
+
+impl<P> VbusDetect for &GpioVbusDetect<P> {
+    fn is_usb_detected(&self) -> bool {
+        self.usb_detected.load(Ordering::Relaxed)
+    }
+
+    fn wait_power_ready(&mut self) -> impl Future<Output = Result<(), ()>> {
+        core::future::ready(if self.is_usb_detected() { Ok(()) } else { Err(()) })
+    }
+}
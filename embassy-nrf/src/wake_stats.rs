@@ -0,0 +1,252 @@
+//! Interrupt and wake-up statistics, for finding out what keeps the chip awake.
+//!
+//! Enabled with the `wake-stats` feature. Without it, the counting hook is an empty inline
+//! function and nothing is compiled in.
+//!
+//! Every interrupt handler bound with [`bind_interrupts!`](crate::bind_interrupts), as well as
+//! the GPIOTE and time driver handlers of this crate, increments a per-interrupt counter on
+//! entry. Handlers registered with `#[interrupt]`, such as those of an `InterruptExecutor`, can
+//! call [`on_interrupt`] themselves to be counted.
+//!
+//! To attribute each wake-up to the interrupt that ended the sleep, and to measure the time
+//! spent asleep, tell this module when the thread-mode executor goes to sleep and when it
+//! resumes. With a thread-mode loop written out around `embassy_executor::raw::Executor`:
+//!
+//! ```rust,ignore
+//! loop {
+//!     unsafe { executor.poll() };
+//!     wake_stats::on_idle();
+//!     cortex_m::asm::wfe();
+//!     wake_stats::on_wake();
+//! }
+//! ```
+//!
+//! Or, keeping the regular executor, from the callbacks of the `trace` feature of
+//! `embassy-executor`, which must all be implemented:
+//!
+//! ```rust,ignore
+//! #[unsafe(no_mangle)]
+//! fn _embassy_trace_executor_idle(_executor_id: u32) {
+//!     embassy_nrf::wake_stats::on_idle();
+//! }
+//!
+//! #[unsafe(no_mangle)]
+//! fn _embassy_trace_poll_start(_executor_id: u32) {
+//!     embassy_nrf::wake_stats::on_wake();
+//! }
+//! ```
+//!
+//! If interrupt executors are in use, only forward the calls for the thread-mode executor: an
+//! interrupt executor going idle does not put the chip to sleep.
+//!
+//! A wake-up with no counted interrupt in between is reported as unattributed. It is caused by
+//! an event (`SEV`, or a peripheral event with `SEVONPEND`), or by a handler that isn't counted.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+#[cfg(feature = "time")]
+use embassy_sync::blocking_mutex::Mutex;
+#[cfg(feature = "time")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(feature = "time")]
+use embassy_time::{Duration, Instant};
+
+use crate::interrupt::Interrupt;
+
+/// Number of interrupt lines, i.e. the size of the counter tables.
+#[cfg(feature = "_nrf54lm20")]
+pub const IRQ_COUNT: usize = 290;
+/// Number of interrupt lines, i.e. the size of the counter tables.
+#[cfg(all(feature = "_nrf54l", not(feature = "_nrf54lm20")))]
+pub const IRQ_COUNT: usize = 270;
+/// Number of interrupt lines, i.e. the size of the counter tables.
+#[cfg(feature = "_nrf5340-app")]
+pub const IRQ_COUNT: usize = 69;
+/// Number of interrupt lines, i.e. the size of the counter tables.
+#[cfg(feature = "_nrf91")]
+pub const IRQ_COUNT: usize = 65;
+/// Number of interrupt lines, i.e. the size of the counter tables.
+#[cfg(not(any(feature = "_nrf54l", feature = "_nrf5340-app", feature = "_nrf91")))]
+pub const IRQ_COUNT: usize = 48;
+
+static INTERRUPTS: [AtomicU32; IRQ_COUNT] = [const { AtomicU32::new(0) }; IRQ_COUNT];
+static WAKES: [AtomicU32; IRQ_COUNT] = [const { AtomicU32::new(0) }; IRQ_COUNT];
+static UNATTRIBUTED_WAKES: AtomicU32 = AtomicU32::new(0);
+static ASLEEP: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "time")]
+#[derive(Clone, Copy)]
+struct Timing {
+    last: Option<Instant>,
+    asleep: Duration,
+    awake: Duration,
+}
+
+#[cfg(feature = "time")]
+static TIMING: Mutex<CriticalSectionRawMutex, core::cell::Cell<Timing>> = Mutex::new(core::cell::Cell::new(Timing {
+    last: None,
+    asleep: Duration::from_ticks(0),
+    awake: Duration::from_ticks(0),
+}));
+
+/// Count an interrupt.
+///
+/// Called on entry of every handler bound with [`bind_interrupts!`](crate::bind_interrupts).
+/// Call it from other handlers to count them too.
+#[inline(always)]
+pub fn on_interrupt(irq: Interrupt) {
+    let n = irq as usize;
+    // Load and store rather than `fetch_add`, which thumbv6m lacks. A handler can't preempt
+    // itself, so only a concurrent `clear` can lose a count.
+    INTERRUPTS[n].store(INTERRUPTS[n].load(Ordering::Relaxed).wrapping_add(1), Ordering::Relaxed);
+    if ASLEEP.load(Ordering::Relaxed) {
+        ASLEEP.store(false, Ordering::Relaxed);
+        WAKES[n].store(WAKES[n].load(Ordering::Relaxed).wrapping_add(1), Ordering::Relaxed);
+    }
+}
+
+/// Record that the executor is about to put the chip to sleep.
+pub fn on_idle() {
+    #[cfg(feature = "time")]
+    account(false);
+    ASLEEP.store(true, Ordering::Relaxed);
+}
+
+/// Record that the executor has resumed after sleeping.
+pub fn on_wake() {
+    if ASLEEP.load(Ordering::Relaxed) {
+        ASLEEP.store(false, Ordering::Relaxed);
+        let n = UNATTRIBUTED_WAKES.load(Ordering::Relaxed);
+        UNATTRIBUTED_WAKES.store(n.wrapping_add(1), Ordering::Relaxed);
+    }
+    #[cfg(feature = "time")]
+    account(true);
+}
+
+/// Add the time since the last call to the awake time, or to the asleep time if `was_asleep`.
+#[cfg(feature = "time")]
+fn account(was_asleep: bool) {
+    let now = Instant::now();
+    TIMING.lock(|t| {
+        let mut timing = t.get();
+        if let Some(last) = timing.last {
+            let elapsed = now.saturating_duration_since(last);
+            if was_asleep {
+                timing.asleep += elapsed;
+            } else {
+                timing.awake += elapsed;
+            }
+        }
+        timing.last = Some(now);
+        t.set(timing);
+    });
+}
+
+/// Snapshot of the statistics, returned by [`wake_stats`].
+#[derive(Clone)]
+pub struct WakeStats {
+    /// Number of times each interrupt ran, indexed by interrupt number.
+    pub interrupts: [u32; IRQ_COUNT],
+    /// Number of sleeps ended by each interrupt, indexed by interrupt number.
+    pub wakes: [u32; IRQ_COUNT],
+    /// Number of sleeps ended without any counted interrupt.
+    pub unattributed_wakes: u32,
+    /// Time spent asleep.
+    #[cfg(feature = "time")]
+    pub asleep: Duration,
+    /// Time spent awake.
+    #[cfg(feature = "time")]
+    pub awake: Duration,
+}
+
+impl WakeStats {
+    /// Total number of sleeps ended, attributed or not.
+    pub fn total_wakes(&self) -> u32 {
+        self.wakes
+            .iter()
+            .fold(self.unattributed_wakes, |acc, n| acc.wrapping_add(*n))
+    }
+
+    /// The `N` interrupts that ended the most sleeps, as `(interrupt number, count)`, most
+    /// frequent first.
+    ///
+    /// Entries past the number of interrupts that woke the chip at least once have a count of 0.
+    pub fn top_wakes<const N: usize>(&self) -> [(u16, u32); N] {
+        let mut top = [(0, 0); N];
+        for (irq, &count) in self.wakes.iter().enumerate() {
+            if let Some(pos) = top.iter().position(|&(_, c)| count > c) {
+                top.copy_within(pos..N - 1, pos + 1);
+                top[pos] = (irq as u16, count);
+            }
+        }
+        top
+    }
+
+    /// Fraction of the time spent asleep, in per mille.
+    #[cfg(feature = "time")]
+    pub fn asleep_permille(&self) -> u32 {
+        let total = self.asleep.as_ticks() + self.awake.as_ticks();
+        if total == 0 {
+            return 0;
+        }
+        (self.asleep.as_ticks() * 1000 / total) as u32
+    }
+}
+
+/// Get the statistics since startup or the last [`clear`].
+pub fn wake_stats() -> WakeStats {
+    #[cfg(feature = "time")]
+    let timing = TIMING.lock(|t| t.get());
+    WakeStats {
+        interrupts: core::array::from_fn(|i| INTERRUPTS[i].load(Ordering::Relaxed)),
+        wakes: core::array::from_fn(|i| WAKES[i].load(Ordering::Relaxed)),
+        unattributed_wakes: UNATTRIBUTED_WAKES.load(Ordering::Relaxed),
+        #[cfg(feature = "time")]
+        asleep: timing.asleep,
+        #[cfg(feature = "time")]
+        awake: timing.awake,
+    }
+}
+
+/// Reset all counters and times to zero.
+pub fn clear() {
+    for n in INTERRUPTS.iter().chain(WAKES.iter()) {
+        n.store(0, Ordering::Relaxed);
+    }
+    UNATTRIBUTED_WAKES.store(0, Ordering::Relaxed);
+    #[cfg(feature = "time")]
+    TIMING.lock(|t| {
+        let mut timing = t.get();
+        timing.asleep = Duration::from_ticks(0);
+        timing.awake = Duration::from_ticks(0);
+        t.set(timing);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_wakes_sorted() {
+        let mut wakes = [0; IRQ_COUNT];
+        wakes[3] = 10;
+        wakes[7] = 40;
+        wakes[9] = 20;
+        wakes[12] = 40;
+        let stats = WakeStats {
+            interrupts: [0; IRQ_COUNT],
+            wakes,
+            unattributed_wakes: 5,
+            #[cfg(feature = "time")]
+            asleep: Duration::from_ticks(0),
+            #[cfg(feature = "time")]
+            awake: Duration::from_ticks(0),
+        };
+
+        assert_eq!(stats.total_wakes(), 115);
+        assert_eq!(stats.top_wakes::<3>(), [(7, 40), (12, 40), (9, 20)]);
+        assert_eq!(stats.top_wakes::<5>(), [(7, 40), (12, 40), (9, 20), (3, 10), (0, 0)]);
+        assert_eq!(stats.top_wakes::<0>(), []);
+    }
+}
@@ -2,18 +2,118 @@
 //!
 //! This HAL implements a basic watchdog timer with 1..=8 handles.
 //! Once the watchdog has been started, it cannot be stopped.
+//!
+//! # Timeout interrupt
+//!
+//! When the watchdog expires, it fires its `TIMEOUT` interrupt and resets the chip two LFCLK
+//! ticks (61 microseconds) later. This is just enough time to leave a breadcrumb for the next
+//! boot or drive outputs into a safe state, provided the handler runs right away. Use
+//! [`Watchdog::try_new_with_interrupt`] to bind [`InterruptHandler`] at the highest priority
+//! available to the application, and [`set_timeout_callback`] to register a function that is
+//! called directly from the interrupt handler.
+//!
+//! The callback runs with the reset already scheduled, so it must be short and must not wait
+//! for anything. In 61 microseconds the CPU executes a few thousand instructions at best,
+//! and the flash wait states and the interrupt latency eat into that. Things that fit:
+//!
+//! - writing a few words to a `#[unsafe(link_section = ".uninit")]` static or to `GPREGRET`,
+//! - setting or clearing GPIO outputs,
+//! - triggering a task on a peripheral.
+//!
+//! Things that don't: logging over a slow transport, writing to flash (a single word takes
+//! 41 microseconds), waiting on a peripheral event, taking a lock that may be held by lower
+//! priority code, or anything that might block. Whatever has not completed when the reset
+//! hits is lost.
+//!
+//! After the callback returns, [`Watchdog::wait_timeout`] completes. A task awaiting it only
+//! gets to run in time if it is spawned on an
+//! [`InterruptExecutor`](https://docs.embassy.dev/embassy-executor/git/cortex-m/struct.InterruptExecutor.html)
+//! whose priority is just below the watchdog interrupt; on the thread-mode executor it will
+//! usually be too late.
+//!
+//! # Resetting after a panic
+//!
+//! With the `wdt-panic-guard` feature, [`Watchdog::into_panic_guard`] arms the watchdog to reset
+//! the chip after a panic: the panic handler calls [`stop_petting`], after which every
+//! [`WatchdogHandle::pet`] is ignored, including those of tasks or interrupts that keep running.
+//! The watchdog can't be stopped or sped up once started, so the reset happens when the current
+//! period runs out, at most the configured timeout after the panic.
+//!
+//! ```rust,ignore
+//! #[panic_handler]
+//! fn panic(_info: &core::panic::PanicInfo) -> ! {
+//!     if !wdt::stop_petting() {
+//!         // No watchdog to do it, reset right away.
+//!         cortex_m::peripheral::SCB::sys_reset();
+//!     }
+//!     // Spin rather than sleep: with `SleepConfig::PAUSE` the watchdog doesn't count while the
+//!     // CPU sleeps, and with `HaltConfig::PAUSE` while it is halted by a debugger.
+//!     loop {}
+//! }
+//! ```
 
 #![macro_use]
 
+use core::future::poll_fn;
 use core::hint::unreachable_unchecked;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use core::task::Poll;
 
 use embassy_hal_internal::PeripheralType;
+use embassy_sync::waitqueue::AtomicWaker;
+#[cfg(feature = "time")]
+use embassy_time::{Duration, Ticker};
 
+use crate::interrupt::typelevel::Interrupt;
 use crate::pac::wdt::vals;
 pub use crate::pac::wdt::vals::{Halt as HaltConfig, Sleep as SleepConfig};
 use crate::{Peri, interrupt, pac, peripherals};
 
-const MIN_TICKS: u32 = 15;
+/// Frequency of the watchdog tick, in Hz.
+pub const TICK_HZ: u32 = 32768;
+
+/// Minimum number of ticks in a watchdog period (458 microseconds).
+pub const MIN_TICKS: u32 = 15;
+
+/// Maximum number of ticks in a watchdog period, limited by the 32-bit `CRV` register.
+pub const MAX_TICKS: u32 = u32::MAX;
+
+/// Check whether the watchdog `T` is running, for example because the bootloader started it.
+///
+/// This doesn't require owning the peripheral, so it can be called before initializing the HAL:
+///
+/// ```rust,ignore
+/// if embassy_nrf::wdt::is_running::<embassy_nrf::peripherals::WDT>() {
+///     // ...
+/// }
+/// ```
+pub fn is_running<T: Instance>() -> bool {
+    let r = T::REGS;
+
+    #[cfg(not(any(feature = "_nrf91", feature = "_nrf5340", feature = "_nrf54l")))]
+    let runstatus = r.runstatus().read().runstatus();
+    #[cfg(any(feature = "_nrf91", feature = "_nrf5340", feature = "_nrf54l"))]
+    let runstatus = r.runstatus().read().runstatuswdt();
+
+    runstatus
+}
+
+/// Compute the number of 32768 Hz ticks for a watchdog period of `period_ms` milliseconds.
+///
+/// The result is rounded down. Panics if it is outside of `MIN_TICKS..=MAX_TICKS`; when used
+/// in a `const` context this is a compile-time error:
+///
+/// ```rust,ignore
+/// const TIMEOUT_TICKS: u32 = wdt::ticks_from_hz(5_000);
+/// ```
+pub const fn ticks_from_hz(period_ms: u32) -> u32 {
+    let ticks = period_ms as u64 * TICK_HZ as u64 / 1000;
+    core::assert!(ticks >= MIN_TICKS as u64, "watchdog period is shorter than MIN_TICKS");
+    core::assert!(ticks <= MAX_TICKS as u64, "watchdog period is longer than MAX_TICKS");
+    ticks as u32
+}
 
 /// WDT configuration.
 #[non_exhaustive]
@@ -22,6 +122,7 @@ pub struct Config {
     ///
     /// Note: there is a minimum of 15 ticks (458 microseconds). If a lower
     /// number is provided, 15 ticks will be used as the configured value.
+    /// Use [`Config::const_new`] to reject lower values instead.
     pub timeout_ticks: u32,
 
     /// Should the watchdog continue to count during sleep modes?
@@ -32,27 +133,339 @@ pub struct Config {
 }
 
 impl Config {
+    /// Create a config structure, usable in a `const` context.
+    ///
+    /// Unlike setting [`Config::timeout_ticks`] directly, this panics if `timeout_ticks` is below
+    /// [`MIN_TICKS`] instead of silently using the minimum. When evaluated in a `const` context this
+    /// is a compile-time error:
+    ///
+    /// ```rust,ignore
+    /// const WDT_CONFIG: wdt::Config = wdt::Config::const_new(
+    ///     wdt::ticks_from_hz(5_000),
+    ///     wdt::SleepConfig::RUN,
+    ///     wdt::HaltConfig::PAUSE,
+    /// );
+    /// ```
+    pub const fn const_new(
+        timeout_ticks: u32,
+        action_during_sleep: SleepConfig,
+        action_during_debug_halt: HaltConfig,
+    ) -> Self {
+        core::assert!(timeout_ticks >= MIN_TICKS, "watchdog timeout is shorter than MIN_TICKS");
+        Self {
+            timeout_ticks,
+            action_during_sleep,
+            action_during_debug_halt,
+        }
+    }
+
     /// Create a config structure from the current configuration of the WDT
     /// peripheral.
+    ///
+    /// Use [`Config::mismatch`] to compare it with the expected configuration.
     pub fn try_new<T: Instance>(_wdt: &Peri<'_, T>) -> Option<Self> {
-        let r = T::REGS;
-
-        #[cfg(not(any(feature = "_nrf91", feature = "_nrf5340", feature = "_nrf54l")))]
-        let runstatus = r.runstatus().read().runstatus();
-        #[cfg(any(feature = "_nrf91", feature = "_nrf5340", feature = "_nrf54l"))]
-        let runstatus = r.runstatus().read().runstatuswdt();
-
-        if runstatus {
-            let config = r.config().read();
-            Some(Self {
-                timeout_ticks: r.crv().read(),
-                action_during_sleep: config.sleep(),
-                action_during_debug_halt: config.halt(),
-            })
+        if is_running::<T>() {
+            Some(Self::read(T::REGS))
         } else {
             None
         }
     }
+
+    fn read(r: pac::wdt::Wdt) -> Self {
+        let config = r.config().read();
+        Self {
+            timeout_ticks: r.crv().read(),
+            action_during_sleep: config.sleep(),
+            action_during_debug_halt: config.halt(),
+        }
+    }
+
+    /// Compare this configuration, usually that of a running watchdog, with `expected`.
+    ///
+    /// Returns the first field that differs, or `None` if the watchdog would behave the same
+    /// with either. Timeouts below [`MIN_TICKS`] compare equal to [`MIN_TICKS`].
+    pub fn mismatch(&self, expected: &Config) -> Option<ConfigMismatch> {
+        let running = self.timeout_ticks.max(MIN_TICKS);
+        let expected_ticks = expected.timeout_ticks.max(MIN_TICKS);
+        if running != expected_ticks {
+            return Some(ConfigMismatch::TimeoutTicks {
+                running,
+                expected: expected_ticks,
+            });
+        }
+        if self.action_during_sleep != expected.action_during_sleep {
+            return Some(ConfigMismatch::ActionDuringSleep {
+                running: self.action_during_sleep,
+                expected: expected.action_during_sleep,
+            });
+        }
+        if self.action_during_debug_halt != expected.action_during_debug_halt {
+            return Some(ConfigMismatch::ActionDuringDebugHalt {
+                running: self.action_during_debug_halt,
+                expected: expected.action_during_debug_halt,
+            });
+        }
+        None
+    }
+}
+
+/// Difference between the configuration of a running watchdog and the expected one.
+///
+/// Returned by [`Config::mismatch`], and logged by [`Watchdog::try_new`] when it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigMismatch {
+    /// The timeout differs.
+    TimeoutTicks {
+        /// Timeout of the running watchdog.
+        running: u32,
+        /// Expected timeout.
+        expected: u32,
+    },
+    /// The behavior during sleep differs.
+    ActionDuringSleep {
+        /// Behavior of the running watchdog.
+        running: SleepConfig,
+        /// Expected behavior.
+        expected: SleepConfig,
+    },
+    /// The behavior during debug halt differs.
+    ActionDuringDebugHalt {
+        /// Behavior of the running watchdog.
+        running: HaltConfig,
+        /// Expected behavior.
+        expected: HaltConfig,
+    },
+    /// The enabled handles differ.
+    HandleCount {
+        /// Number of handles enabled on the running watchdog.
+        running: usize,
+        /// Expected number of handles.
+        expected: usize,
+    },
+}
+
+impl core::fmt::Display for ConfigMismatch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::TimeoutTicks { running, expected } => {
+                write!(f, "timeout_ticks is {} instead of {}", running, expected)
+            }
+            Self::ActionDuringSleep { running, expected } => write!(
+                f,
+                "action_during_sleep is {} instead of {}",
+                sleep_str(running),
+                sleep_str(expected)
+            ),
+            Self::ActionDuringDebugHalt { running, expected } => write!(
+                f,
+                "action_during_debug_halt is {} instead of {}",
+                halt_str(running),
+                halt_str(expected)
+            ),
+            Self::HandleCount { running, expected } => {
+                write!(f, "{} handles are enabled instead of {}", running, expected)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ConfigMismatch {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match *self {
+            Self::TimeoutTicks { running, expected } => {
+                defmt::write!(f, "timeout_ticks is {=u32} instead of {=u32}", running, expected)
+            }
+            Self::ActionDuringSleep { running, expected } => defmt::write!(
+                f,
+                "action_during_sleep is {=str} instead of {=str}",
+                sleep_str(running),
+                sleep_str(expected)
+            ),
+            Self::ActionDuringDebugHalt { running, expected } => defmt::write!(
+                f,
+                "action_during_debug_halt is {=str} instead of {=str}",
+                halt_str(running),
+                halt_str(expected)
+            ),
+            Self::HandleCount { running, expected } => {
+                defmt::write!(f, "{=usize} handles are enabled instead of {=usize}", running, expected)
+            }
+        }
+    }
+}
+
+/// Watchdog configuration shared between a bootloader and the application it boots.
+///
+/// Once started, the watchdog can't be reconfigured, so the application must take it over with
+/// exactly the configuration and handle count the bootloader started it with. Define the
+/// configuration once as a constant in a module both images include, and create the watchdog
+/// with [`Watchdog::try_new_shared`] on both sides:
+///
+/// ```rust,ignore
+/// pub const WDT: wdt::SharedConfig = wdt::SharedConfig::new(
+///     wdt::ticks_from_hz(5_000),
+///     wdt::SleepConfig::RUN,
+///     wdt::HaltConfig::PAUSE,
+///     1,
+/// );
+/// ```
+///
+/// [`assert_wdt_handles!`](crate::assert_wdt_handles) checks at compile time that an image
+/// creates as many handles as the shared configuration has.
+///
+/// It converts to and from 8 bytes with [`to_bytes`](Self::to_bytes) and
+/// [`from_bytes`](Self::from_bytes), for images that pass it at runtime instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedConfig {
+    timeout_ticks: u32,
+    action_during_sleep: SleepConfig,
+    action_during_debug_halt: HaltConfig,
+    handles: u8,
+}
+
+impl SharedConfig {
+    /// Create a shared configuration.
+    ///
+    /// Panics if `timeout_ticks` is below [`MIN_TICKS`] or `handles` isn't between 1 and 8,
+    /// which is a compile-time error in a `const`.
+    pub const fn new(
+        timeout_ticks: u32,
+        action_during_sleep: SleepConfig,
+        action_during_debug_halt: HaltConfig,
+        handles: u8,
+    ) -> Self {
+        core::assert!(timeout_ticks >= MIN_TICKS, "watchdog timeout is shorter than MIN_TICKS");
+        core::assert!(
+            handles >= 1 && handles <= 8,
+            "watchdog handle count must be between 1 and 8"
+        );
+        Self {
+            timeout_ticks,
+            action_during_sleep,
+            action_during_debug_halt,
+            handles,
+        }
+    }
+
+    /// The watchdog configuration.
+    pub const fn config(&self) -> Config {
+        Config::const_new(
+            self.timeout_ticks,
+            self.action_during_sleep,
+            self.action_during_debug_halt,
+        )
+    }
+
+    /// Number of watchdog handles.
+    pub const fn handles(&self) -> usize {
+        self.handles as usize
+    }
+
+    /// Serialize as the little-endian timeout, the sleep and halt behaviors and the handle count.
+    pub const fn to_bytes(&self) -> [u8; 8] {
+        let t = self.timeout_ticks.to_le_bytes();
+        [
+            t[0],
+            t[1],
+            t[2],
+            t[3],
+            self.action_during_sleep.to_bits(),
+            self.action_during_debug_halt.to_bits(),
+            self.handles,
+            0,
+        ]
+    }
+
+    /// Deserialize what [`to_bytes`](Self::to_bytes) wrote.
+    ///
+    /// Returns `None` if the bytes don't hold a valid configuration, for example erased flash.
+    pub const fn from_bytes(bytes: &[u8; 8]) -> Option<Self> {
+        let timeout_ticks = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if timeout_ticks < MIN_TICKS || bytes[4] > 1 || bytes[5] > 1 || bytes[6] < 1 || bytes[6] > 8 || bytes[7] != 0 {
+            return None;
+        }
+        Some(Self::new(
+            timeout_ticks,
+            SleepConfig::from_bits(bytes[4]),
+            HaltConfig::from_bits(bytes[5]),
+            bytes[6],
+        ))
+    }
+
+    #[doc(hidden)]
+    pub const fn assert_handles(&self, n: usize) {
+        core::assert!(
+            self.handles as usize == n,
+            "the handle count doesn't match the shared watchdog configuration"
+        );
+    }
+}
+
+/// Check at compile time that `N` handles match a [`SharedConfig`] constant.
+///
+/// ```rust,ignore
+/// embassy_nrf::assert_wdt_handles!(shared::WDT, 2);
+/// let (wdt, [a, b]) = Watchdog::try_new_shared(p.WDT, &shared::WDT)?;
+/// ```
+#[macro_export]
+macro_rules! assert_wdt_handles {
+    ($shared:expr, $n:expr) => {
+        const _: () = $crate::wdt::SharedConfig::assert_handles(&$shared, $n);
+    };
+}
+
+impl Config {
+    /// Duration of the watchdog period in microseconds.
+    fn timeout_us(&self) -> u64 {
+        self.timeout_ticks.max(MIN_TICKS) as u64 * 1_000_000 / TICK_HZ as u64
+    }
+}
+
+fn sleep_str(sleep: SleepConfig) -> &'static str {
+    match sleep {
+        SleepConfig::RUN => "run",
+        SleepConfig::PAUSE => "pause",
+    }
+}
+
+fn halt_str(halt: HaltConfig) -> &'static str {
+    match halt {
+        HaltConfig::RUN => "run",
+        HaltConfig::PAUSE => "pause",
+    }
+}
+
+impl core::fmt::Debug for Config {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Config")
+            .field("timeout_ticks", &self.timeout_ticks)
+            .field("timeout_us", &self.timeout_us())
+            .field(
+                "action_during_sleep",
+                &format_args!("{}", sleep_str(self.action_during_sleep)),
+            )
+            .field(
+                "action_during_debug_halt",
+                &format_args!("{}", halt_str(self.action_during_debug_halt)),
+            )
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Config {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "Config {{ timeout_ticks: {=u32}, timeout_us: {=u64}, action_during_sleep: {=str}, action_during_debug_halt: {=str} }}",
+            self.timeout_ticks,
+            self.timeout_us(),
+            sleep_str(self.action_during_sleep),
+            halt_str(self.action_during_debug_halt),
+        )
+    }
 }
 
 impl Default for Config {
@@ -65,9 +478,74 @@ impl Default for Config {
     }
 }
 
+/// Interrupt handler.
+///
+/// Calls the function registered with [`set_timeout_callback`], then wakes
+/// [`Watchdog::wait_timeout`].
+pub struct InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        on_interrupt(T::REGS, T::state())
+    }
+}
+
+/// Shared with tests, which run it on a fake register block.
+#[inline(always)]
+fn on_interrupt(r: pac::wdt::Wdt, s: &State) {
+    if r.events_timeout().read() != 0 {
+        r.events_timeout().write_value(0);
+
+        let callback = s.callback.load(Ordering::Acquire);
+        if !callback.is_null() {
+            // Safety: only `set_timeout_callback` stores to `callback`, and it stores a `fn()`.
+            let callback: fn() = unsafe { core::mem::transmute(callback) };
+            callback();
+        }
+
+        s.timed_out.store(true, Ordering::Release);
+        s.waker.wake();
+    }
+}
+
+/// Register a function to call from [`InterruptHandler`] when the watchdog `T` times out.
+///
+/// Call this before [`Watchdog::try_new_with_interrupt`]: if a bootloader already started the
+/// watchdog, the timeout may fire as soon as the interrupt is enabled. The callback replaces
+/// any previously registered one.
+///
+/// The reset follows 61 microseconds after the timeout, see the [module documentation](self)
+/// for what can be done in that time.
+pub fn set_timeout_callback<T: Instance>(callback: fn()) {
+    T::state().callback.store(callback as *mut (), Ordering::Release);
+}
+
+/// RREN value enabling the first `n` handles.
+fn handle_mask(n: usize) -> pac::wdt::regs::Rren {
+    pac::wdt::regs::Rren((1u32 << n) - 1)
+}
+
+/// Compare a running watchdog with `config` and `n` handles.
+///
+/// The enabled handles must be exactly the first `n`. In particular, a watchdog running with
+/// more handles than requested is a mismatch rather than a match of a subset, as the handles
+/// beyond `n` would never be pet.
+fn running_mismatch(r: pac::wdt::Wdt, config: &Config, n: usize) -> Option<ConfigMismatch> {
+    let running_rren = r.rren().read();
+    Config::read(r).mismatch(config).or_else(|| {
+        (running_rren != handle_mask(n)).then(|| ConfigMismatch::HandleCount {
+            running: running_rren.0.count_ones() as usize,
+            expected: n,
+        })
+    })
+}
+
 /// Watchdog driver.
 pub struct Watchdog {
     r: pac::wdt::Wdt,
+    state: &'static State,
 }
 
 impl Watchdog {
@@ -88,20 +566,11 @@ impl Watchdog {
         let r = T::REGS;
 
         let crv = config.timeout_ticks.max(MIN_TICKS);
-        let rren = crate::pac::wdt::regs::Rren((1u32 << N) - 1);
-
-        #[cfg(not(any(feature = "_nrf91", feature = "_nrf5340", feature = "_nrf54l")))]
-        let runstatus = r.runstatus().read().runstatus();
-        #[cfg(any(feature = "_nrf91", feature = "_nrf5340", feature = "_nrf54l"))]
-        let runstatus = r.runstatus().read().runstatuswdt();
-
-        if runstatus {
-            let curr_config = r.config().read();
-            if curr_config.halt() != config.action_during_debug_halt
-                || curr_config.sleep() != config.action_during_sleep
-                || r.crv().read() != crv
-                || r.rren().read() != rren
-            {
+        let rren = handle_mask(N);
+
+        if is_running::<T>() {
+            if let Some(mismatch) = running_mismatch(r, &config, N) {
+                warn!("watchdog is already running with another configuration: {}", mismatch);
                 return Err(wdt);
             }
         } else {
@@ -116,7 +585,10 @@ impl Watchdog {
             r.tasks_start().write_value(1);
         }
 
-        let this = Self { r: T::REGS };
+        let this = Self {
+            r: T::REGS,
+            state: T::state(),
+        };
 
         let mut handles = [const { WatchdogHandle { index: 0 } }; N];
         for i in 0..N {
@@ -127,6 +599,64 @@ impl Watchdog {
         Ok((this, handles))
     }
 
+    /// Try to create a new watchdog driver with a configuration shared with the bootloader.
+    ///
+    /// This behaves like [`Watchdog::try_new`] with [`SharedConfig::config`]. `N` must be
+    /// [`SharedConfig::handles`], which [`assert_wdt_handles!`](crate::assert_wdt_handles)
+    /// checks at compile time.
+    pub fn try_new_shared<T: Instance, const N: usize>(
+        wdt: Peri<'static, T>,
+        shared: &SharedConfig,
+    ) -> Result<(Self, [WatchdogHandle; N]), Peri<'static, T>> {
+        assert!(
+            N == shared.handles(),
+            "the handle count doesn't match the shared watchdog configuration"
+        );
+        Self::try_new(wdt, shared.config())
+    }
+
+    /// Try to create a new watchdog driver, with the timeout interrupt enabled at `priority`.
+    ///
+    /// This behaves like [`Watchdog::try_new`], and additionally enables the watchdog interrupt
+    /// in the NVIC so that the callback registered with [`set_timeout_callback`] runs and
+    /// [`Watchdog::wait_timeout`] completes when the watchdog expires. `priority` should be
+    /// the highest priority the application may use (for example `P0` without a SoftDevice),
+    /// so that nothing delays the handler during the 61 microseconds before the reset.
+    pub fn try_new_with_interrupt<T: Instance, const N: usize>(
+        wdt: Peri<'static, T>,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'static,
+        config: Config,
+        priority: interrupt::Priority,
+    ) -> Result<(Self, [WatchdogHandle; N]), Peri<'static, T>> {
+        let (mut this, handles) = Self::try_new(wdt, config)?;
+
+        this.enable_interrupt();
+        T::Interrupt::unpend();
+        T::Interrupt::set_priority(priority);
+        unsafe { T::Interrupt::enable() };
+
+        Ok((this, handles))
+    }
+
+    /// Wait for the watchdog to time out.
+    ///
+    /// The chip resets 61 microseconds after this completes, so there is only time for a
+    /// handful of instructions. Await it from a task on an `InterruptExecutor` running just
+    /// below the watchdog interrupt priority.
+    ///
+    /// This only completes if the watchdog was created with [`Watchdog::try_new_with_interrupt`].
+    pub async fn wait_timeout(&mut self) {
+        let s = self.state;
+        poll_fn(|cx| {
+            s.waker.register(cx.waker());
+            if s.timed_out.load(Ordering::Acquire) {
+                return Poll::Ready(());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
     /// Enable the watchdog interrupt.
     ///
     /// NOTE: Although the interrupt will occur, there is no way to prevent
@@ -156,6 +686,104 @@ impl Watchdog {
         let status = self.r.reqstatus().read().0;
         (status & enabled) == 0
     }
+
+    /// Get a summary of which handles have been pet in the current period.
+    pub fn handle_status(&self) -> HandleStatus {
+        HandleStatus {
+            enabled: self.r.rren().read().0 as u8,
+            requested: self.r.reqstatus().read().0 as u8,
+        }
+    }
+
+    /// Arm the watchdog to reset the chip after a panic, see [`stop_petting`].
+    #[cfg(feature = "wdt-panic-guard")]
+    pub fn into_panic_guard(self) -> PanicGuard {
+        PANIC_GUARD_ARMED.store(true, Ordering::Release);
+        PanicGuard { watchdog: self }
+    }
+}
+
+/// Watchdog armed to reset the chip after a panic, returned by [`Watchdog::into_panic_guard`].
+#[cfg(feature = "wdt-panic-guard")]
+pub struct PanicGuard {
+    watchdog: Watchdog,
+}
+
+#[cfg(feature = "wdt-panic-guard")]
+impl PanicGuard {
+    /// Get the guarded watchdog, e.g. to wait for a timeout.
+    pub fn watchdog(&mut self) -> &mut Watchdog {
+        &mut self.watchdog
+    }
+}
+
+#[cfg(feature = "wdt-panic-guard")]
+static PANIC_GUARD_ARMED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "wdt-panic-guard")]
+static PETTING_STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Stop petting the watchdog, to have it reset the chip. Meant to be called from a panic handler.
+///
+/// From then on, [`WatchdogHandle::pet`] and [`WatchdogHandle::pet_shared`] do nothing, on every
+/// handle. The watchdog resets the chip when the current period runs out, so within the
+/// configured timeout, as long as it isn't paused by sleeping or by a debugger.
+///
+/// Returns whether a watchdog was armed with [`Watchdog::into_panic_guard`]. If not, nothing will
+/// reset the chip and the caller should do it.
+#[cfg(feature = "wdt-panic-guard")]
+pub fn stop_petting() -> bool {
+    PETTING_STOPPED.store(true, Ordering::Release);
+    PANIC_GUARD_ARMED.load(Ordering::Acquire)
+}
+
+/// Summary of the state of the watchdog handles, obtained with [`Watchdog::handle_status`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct HandleStatus {
+    enabled: u8,
+    requested: u8,
+}
+
+impl HandleStatus {
+    /// Number of enabled handles.
+    pub fn handle_count(&self) -> usize {
+        self.enabled.count_ones() as usize
+    }
+
+    /// Has the handle with the given index been pet within the current period?
+    ///
+    /// Returns `None` if the handle is not enabled.
+    pub fn is_pet(&self, index: usize) -> Option<bool> {
+        let mask = 1u8.checked_shl(index as u32)?;
+        if self.enabled & mask == 0 {
+            return None;
+        }
+        Some(self.requested & mask == 0)
+    }
+
+    /// Iterate over the enabled handles, yielding each index and whether it has been pet.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, bool)> + '_ {
+        (0..8).filter_map(|i| self.is_pet(i).map(|pet| (i, pet)))
+    }
+}
+
+impl core::fmt::Debug for HandleStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for HandleStatus {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{{");
+        for (n, (i, pet)) in self.iter().enumerate() {
+            if n != 0 {
+                defmt::write!(f, ", ");
+            }
+            defmt::write!(f, "{=usize}: {=bool}", i, pet);
+        }
+        defmt::write!(f, "}}");
+    }
 }
 
 /// Watchdog handle.
@@ -188,6 +816,23 @@ impl WatchdogHandle {
     /// prevent a reset from occurring.
     #[inline]
     pub fn pet(&mut self) {
+        self.pet_shared();
+    }
+
+    /// Pet the watchdog through a shared reference.
+    ///
+    /// Petting is a single write of the reload value to the handle's write-only RR register, so
+    /// a handle shared between priority levels, e.g. in a `static`, can be pet from thread mode
+    /// and interrupts concurrently without a mutex. Concurrent pets of the same handle write the
+    /// same value to the same register, and can't interfere with each other.
+    ///
+    /// With the `wdt-panic-guard` feature, this does nothing once [`stop_petting`] was called.
+    #[inline]
+    pub fn pet_shared(&self) {
+        #[cfg(feature = "wdt-panic-guard")]
+        if PETTING_STOPPED.load(Ordering::Acquire) {
+            return;
+        }
         let r = self.regs();
         r.rr(self.rr_index()).write(|w| w.set_rr(vals::Rr::RELOAD));
     }
@@ -210,9 +855,63 @@ impl WatchdogHandle {
     }
 }
 
+/// Pet the watchdog handle every `interval`, forever.
+///
+/// This is meant to be awaited from a dedicated task, so the watchdog is kept
+/// alive as long as the executor keeps running:
+///
+/// ```rust,ignore
+/// #[embassy_executor::task]
+/// async fn watchdog_task(handle: WatchdogHandle) -> ! {
+///     wdt::auto_pet(handle, Duration::from_millis(500)).await
+/// }
+/// ```
+///
+/// `interval` must be shorter than the configured watchdog timeout.
+#[cfg(feature = "time")]
+pub async fn auto_pet(mut handle: WatchdogHandle, interval: Duration) -> ! {
+    let mut ticker = Ticker::every(interval);
+    loop {
+        handle.pet();
+        ticker.next().await;
+    }
+}
+
+/// Pet the watchdog handle every `interval` while `healthy` is `true`.
+///
+/// Once `healthy` is observed as `false`, petting stops for good and the
+/// watchdog will reset the chip when the current period expires. This allows a
+/// supervisor to force a reset by clearing the flag.
+#[cfg(feature = "time")]
+pub async fn auto_pet_gated(mut handle: WatchdogHandle, interval: Duration, healthy: &AtomicBool) -> ! {
+    let mut ticker = Ticker::every(interval);
+    while healthy.load(Ordering::Relaxed) {
+        handle.pet();
+        ticker.next().await;
+    }
+    core::future::pending().await
+}
+
+pub(crate) struct State {
+    waker: AtomicWaker,
+    timed_out: AtomicBool,
+    callback: AtomicPtr<()>,
+}
+
+impl State {
+    pub(crate) const fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+            timed_out: AtomicBool::new(false),
+            callback: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
 pub(crate) trait SealedInstance {
     const REGS: pac::wdt::Wdt;
     const INDEX: u8;
+    fn state() -> &'static State;
 }
 
 /// WDT instance.
@@ -227,9 +926,200 @@ macro_rules! impl_wdt {
         impl crate::wdt::SealedInstance for peripherals::$type {
             const REGS: pac::wdt::Wdt = pac::$pac_type;
             const INDEX: u8 = $index;
+            fn state() -> &'static crate::wdt::State {
+                static STATE: crate::wdt::State = crate::wdt::State::new();
+                &STATE
+            }
         }
         impl crate::wdt::Instance for peripherals::$type {
             type Interrupt = crate::interrupt::typelevel::$irq;
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::AtomicUsize;
+    use std::boxed::Box;
+    use std::format;
+
+    use super::*;
+    use crate::replay::{FakeRegs, Step, replay};
+
+    const SHARED: SharedConfig = SharedConfig::new(ticks_from_hz(5_000), SleepConfig::RUN, HaltConfig::PAUSE, 2);
+
+    crate::assert_wdt_handles!(SHARED, 2);
+
+    #[test]
+    fn mismatch() {
+        let expected = SHARED.config();
+
+        let running = Config::const_new(ticks_from_hz(1_000), SleepConfig::PAUSE, HaltConfig::RUN);
+        let mismatch = running.mismatch(&expected);
+        assert_eq!(
+            Some(ConfigMismatch::TimeoutTicks {
+                running: 32768,
+                expected: 163840
+            }),
+            mismatch
+        );
+        assert_eq!(
+            "timeout_ticks is 32768 instead of 163840",
+            format!("{}", mismatch.unwrap())
+        );
+
+        let running = Config::const_new(ticks_from_hz(5_000), SleepConfig::PAUSE, HaltConfig::RUN);
+        assert_eq!(
+            "action_during_sleep is pause instead of run",
+            format!("{}", running.mismatch(&expected).unwrap())
+        );
+
+        let running = Config::const_new(ticks_from_hz(5_000), SleepConfig::RUN, HaltConfig::RUN);
+        assert_eq!(
+            "action_during_debug_halt is run instead of pause",
+            format!("{}", running.mismatch(&expected).unwrap())
+        );
+
+        assert_eq!(None, SHARED.config().mismatch(&expected));
+    }
+
+    #[test]
+    fn mismatch_min_ticks() {
+        let mut below = Config::default();
+        below.timeout_ticks = 1;
+        let min = Config::const_new(MIN_TICKS, SleepConfig::RUN, HaltConfig::RUN);
+        assert_eq!(None, below.mismatch(&min));
+    }
+
+    #[test]
+    fn shared_bytes() {
+        let bytes = SHARED.to_bytes();
+        assert_eq!([0x00, 0x80, 0x02, 0x00, 1, 0, 2, 0], bytes);
+        assert_eq!(Some(SHARED), SharedConfig::from_bytes(&bytes));
+
+        // Erased flash.
+        assert_eq!(None, SharedConfig::from_bytes(&[0xFF; 8]));
+        // No handles.
+        assert_eq!(None, SharedConfig::from_bytes(&[0x00, 0x80, 0x02, 0x00, 1, 0, 0, 0]));
+    }
+
+    /// A watchdog left running with 4 handles must not be taken over with 2 of them.
+    #[test]
+    fn running_handle_count() {
+        let regs = FakeRegs::new();
+        let r = unsafe { pac::wdt::Wdt::from_ptr(regs.ptr()) };
+        let config = SHARED.config();
+        r.crv().write_value(config.timeout_ticks);
+        r.config().write(|w| {
+            w.set_sleep(config.action_during_sleep);
+            w.set_halt(config.action_during_debug_halt);
+        });
+        r.rren().write_value(handle_mask(4));
+
+        assert_eq!(
+            Some(ConfigMismatch::HandleCount {
+                running: 4,
+                expected: 2
+            }),
+            running_mismatch(r, &config, 2)
+        );
+        assert_eq!(
+            Some(ConfigMismatch::HandleCount {
+                running: 4,
+                expected: 8
+            }),
+            running_mismatch(r, &config, 8)
+        );
+        assert_eq!(None, running_mismatch(r, &config, 4));
+
+        // The same count of handles, but not the first ones.
+        r.rren().write_value(pac::wdt::regs::Rren(0b1100));
+        assert_eq!(
+            Some(ConfigMismatch::HandleCount {
+                running: 2,
+                expected: 2
+            }),
+            running_mismatch(r, &config, 2)
+        );
+    }
+
+    fn watchdog(regs: &FakeRegs) -> Watchdog {
+        Watchdog {
+            r: unsafe { pac::wdt::Wdt::from_ptr(regs.ptr()) },
+            state: Box::leak(Box::new(State::new())),
+        }
+    }
+
+    /// The watchdog times out before `wait_timeout` is first polled, for instance when a
+    /// bootloader started it and the interrupt fires as soon as it is enabled. The callback
+    /// runs once, and `wait_timeout` completes without needing a wake.
+    #[test]
+    fn timeout_before_wait() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let regs = FakeRegs::new();
+        let mut wdt = watchdog(&regs);
+        let (r, s) = (wdt.r, wdt.state);
+        s.callback.store(
+            (|| {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+            }) as fn() as *mut (),
+            Ordering::Release,
+        );
+        let timeout = r.events_timeout().as_ptr();
+
+        let out = replay(
+            &regs,
+            wdt.wait_timeout(),
+            || on_interrupt(r, s),
+            &[
+                Step::Set(timeout, 1),
+                Step::Interrupt,
+                Step::Interrupt,
+                Step::Wakes(0),
+                Step::Ready,
+            ],
+        );
+        assert_eq!(out, Some(()));
+        assert_eq!(regs.get(timeout), 0);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+
+    /// An interrupt without TIMEOUT, such as one left pending from before the watchdog was
+    /// created, must neither run the callback nor complete `wait_timeout`.
+    #[test]
+    fn interrupt_without_timeout() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let regs = FakeRegs::new();
+        let mut wdt = watchdog(&regs);
+        let (r, s) = (wdt.r, wdt.state);
+        s.callback.store(
+            (|| {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+            }) as fn() as *mut (),
+            Ordering::Release,
+        );
+        let timeout = r.events_timeout().as_ptr();
+
+        let out = replay(
+            &regs,
+            wdt.wait_timeout(),
+            || on_interrupt(r, s),
+            &[
+                Step::Pending,
+                Step::Interrupt,
+                Step::Wakes(0),
+                Step::Pending,
+                Step::Set(timeout, 1),
+                Step::Interrupt,
+                Step::Wakes(1),
+                Step::Ready,
+            ],
+        );
+        assert_eq!(out, Some(()));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,336 @@
+//! A zero-copy byte queue handing out contiguous slices, for DMA producers.
+//!
+//! Unlike [`Pipe`](crate::pipe::Pipe), which copies bytes in and out, a [`BipBuffer`] (bipartite
+//! buffer) lends its storage: the [`Producer`] is granted a contiguous writable slice, which can be
+//! handed to a DMA engine as is, and commits the bytes written into it. The [`Consumer`] then reads
+//! the committed bytes in place, and releases them once processed.
+//!
+//! The producer and the consumer only share atomic indices, so the producer can run in an
+//! interrupt handler while the consumer runs in a task. Committing wakes the consumer.
+//!
+//! Grants are contiguous: when the end of the buffer is too small for a grant, it is placed at the
+//! start of the buffer instead, and the unused end is skipped by the consumer. A grant can thus fail
+//! even if there is enough free space in total.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll, ready};
+
+use crate::waitqueue::AtomicWaker;
+
+/// A single producer, single consumer byte queue handing out contiguous slices.
+///
+/// See the [module documentation](self) for details.
+pub struct BipBuffer<'a> {
+    buf: *mut u8,
+    capacity: usize,
+    /// Start of the committed data. Only written by the consumer.
+    read: AtomicUsize,
+    /// End of the committed data. Only written by the producer.
+    write: AtomicUsize,
+    /// End of the committed data before the start of the buffer, when `write` is behind `read`.
+    /// Only written by the producer.
+    watermark: AtomicUsize,
+    waker: AtomicWaker,
+    phantom: PhantomData<&'a mut [u8]>,
+}
+
+unsafe impl<'a> Send for BipBuffer<'a> {}
+unsafe impl<'a> Sync for BipBuffer<'a> {}
+
+impl<'a> BipBuffer<'a> {
+    /// Create a new [`BipBuffer`] storing the bytes in `buf`.
+    ///
+    /// One byte of `buf` is always kept free, to tell a full buffer from an empty one.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        assert!(buf.len() > 1);
+        Self {
+            buf: buf.as_mut_ptr(),
+            capacity: buf.len(),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+            watermark: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Creates the [`Producer`] and the [`Consumer`] of the buffer.
+    pub fn split(&mut self) -> (Producer<'_>, Consumer<'_>) {
+        (
+            Producer {
+                bip: self,
+                grant_start: 0,
+                grant_len: 0,
+            },
+            Consumer { bip: self },
+        )
+    }
+}
+
+impl<'a> core::fmt::Debug for BipBuffer<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BipBuffer")
+            .field("capacity", &self.capacity)
+            .field("read", &self.read.load(Ordering::Relaxed))
+            .field("write", &self.write.load(Ordering::Relaxed))
+            .field("watermark", &self.watermark.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// Write access to a [`BipBuffer`].
+#[derive(Debug)]
+pub struct Producer<'a> {
+    bip: &'a BipBuffer<'a>,
+    grant_start: usize,
+    grant_len: usize,
+}
+
+impl<'a> Producer<'a> {
+    /// Grant a contiguous writable slice of exactly `len` bytes.
+    ///
+    /// Returns `None` if there is no contiguous free space of `len` bytes. The bytes written are
+    /// published with [`commit`](Self::commit). A new grant replaces the previous one.
+    ///
+    /// The slice can be handed to a DMA engine, as long as it is done writing when committing.
+    pub fn grant(&mut self, len: usize) -> Option<&mut [u8]> {
+        self.reserve(len, true)
+    }
+
+    /// Grant the largest contiguous writable slice, up to `max` bytes.
+    ///
+    /// Returns `None` if the buffer is full. Otherwise, same as [`grant`](Self::grant).
+    pub fn grant_max(&mut self, max: usize) -> Option<&mut [u8]> {
+        self.reserve(max, false)
+    }
+
+    fn reserve(&mut self, len: usize, exact: bool) -> Option<&mut [u8]> {
+        let bip = self.bip;
+        let write = bip.write.load(Ordering::Relaxed);
+        let read = bip.read.load(Ordering::Acquire);
+
+        // Free space, keeping a byte before `read` free.
+        let (start, free) = if write < read {
+            (write, read - write - 1)
+        } else if write + len <= bip.capacity || (!exact && write < bip.capacity) {
+            (write, bip.capacity - write)
+        } else {
+            // Wrap around to the start of the buffer.
+            (0, read.saturating_sub(1))
+        };
+
+        let len = if exact { len } else { len.min(free) };
+        if len > free || (!exact && free == 0) {
+            return None;
+        }
+
+        self.grant_start = start;
+        self.grant_len = len;
+        Some(unsafe { slice::from_raw_parts_mut(bip.buf.add(start), len) })
+    }
+
+    /// Publish the first `n` bytes of the last grant to the consumer, and wake it.
+    ///
+    /// Panics if `n` is larger than the last grant.
+    pub fn commit(&mut self, n: usize) {
+        assert!(n <= self.grant_len, "commit larger than the grant");
+        self.grant_len = 0;
+        if n == 0 {
+            return;
+        }
+
+        let bip = self.bip;
+        let write = bip.write.load(Ordering::Relaxed);
+        if self.grant_start != write {
+            // The grant wrapped around, the data before it ends at the current write index.
+            bip.watermark.store(write, Ordering::Release);
+        }
+        bip.write.store(self.grant_start + n, Ordering::Release);
+        bip.waker.wake();
+    }
+}
+
+/// Read access to a [`BipBuffer`].
+#[derive(Debug)]
+pub struct Consumer<'a> {
+    bip: &'a BipBuffer<'a>,
+}
+
+impl<'a> Consumer<'a> {
+    /// Start and length of the contiguous committed data.
+    fn readable(&mut self) -> (usize, usize) {
+        let bip = self.bip;
+        let write = bip.write.load(Ordering::Acquire);
+        let mut read = bip.read.load(Ordering::Relaxed);
+
+        if write < read {
+            let watermark = bip.watermark.load(Ordering::Acquire);
+            if read != watermark {
+                return (read, watermark - read);
+            }
+            // Everything before the wrap was read, continue at the start of the buffer.
+            read = 0;
+            bip.read.store(0, Ordering::Release);
+        }
+        (read, write - read)
+    }
+
+    /// Get the contiguous committed data, which is empty if nothing was committed.
+    ///
+    /// When the committed data wraps around, only the part before the end of the buffer is
+    /// returned. The rest is returned once that part is released.
+    pub fn try_read(&mut self) -> &[u8] {
+        let (start, len) = self.readable();
+        unsafe { slice::from_raw_parts(self.bip.buf.add(start), len) }
+    }
+
+    /// Attempt to get the contiguous committed data, registering the waker of `cx` if there is none.
+    pub fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<&[u8]> {
+        let (start, len) = ready!(self.poll_readable(cx));
+        Poll::Ready(unsafe { slice::from_raw_parts(self.bip.buf.add(start), len) })
+    }
+
+    /// Wait for committed data, and get it as a contiguous slice.
+    ///
+    /// See [`try_read`](Self::try_read) for what is returned when the data wraps around.
+    pub async fn read(&mut self) -> &[u8] {
+        let (start, len) = poll_fn(|cx| self.poll_readable(cx)).await;
+        unsafe { slice::from_raw_parts(self.bip.buf.add(start), len) }
+    }
+
+    fn poll_readable(&mut self, cx: &mut Context<'_>) -> Poll<(usize, usize)> {
+        self.bip.waker.register(cx.waker());
+        match self.readable() {
+            (_, 0) => Poll::Pending,
+            readable => Poll::Ready(readable),
+        }
+    }
+
+    /// Release the first `n` bytes of the committed data, so the producer can reuse them.
+    ///
+    /// Panics if `n` is larger than the data returned by the last read.
+    pub fn release(&mut self, n: usize) {
+        let (start, len) = self.readable();
+        assert!(n <= len, "release larger than the committed data");
+        self.bip.read.store(start + n, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures_util::poll;
+
+    use super::*;
+
+    #[test]
+    fn grant_commit_read_release() {
+        let mut storage = [0; 8];
+        let mut bip = BipBuffer::new(&mut storage);
+        let (mut producer, mut consumer) = bip.split();
+
+        assert_eq!(consumer.try_read(), &[]);
+        producer.grant(4).unwrap().copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(consumer.try_read(), &[]);
+        producer.commit(3);
+        assert_eq!(consumer.try_read(), &[1, 2, 3]);
+
+        consumer.release(2);
+        assert_eq!(consumer.try_read(), &[3]);
+        consumer.release(1);
+        assert_eq!(consumer.try_read(), &[]);
+    }
+
+    #[test]
+    fn grant_wraps_around() {
+        let mut storage = [0; 8];
+        let mut bip = BipBuffer::new(&mut storage);
+        let (mut producer, mut consumer) = bip.split();
+
+        producer.grant(6).unwrap().copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+        producer.commit(6);
+        assert_eq!(consumer.try_read().len(), 6);
+        consumer.release(4);
+
+        // Only 2 bytes left at the end, so the grant wraps, keeping a byte before the data free.
+        assert!(producer.grant(4).is_none());
+        producer.grant(3).unwrap().copy_from_slice(&[7, 8, 9]);
+        producer.commit(3);
+
+        // The data before the wrap comes first.
+        assert_eq!(consumer.try_read(), &[5, 6]);
+        consumer.release(2);
+        assert_eq!(consumer.try_read(), &[7, 8, 9]);
+        consumer.release(3);
+        assert_eq!(consumer.try_read(), &[]);
+
+        producer.grant(5).unwrap().copy_from_slice(&[10, 11, 12, 13, 14]);
+        producer.commit(5);
+        assert_eq!(consumer.try_read(), &[10, 11, 12, 13, 14]);
+    }
+
+    #[test]
+    fn grant_max() {
+        let mut storage = [0; 8];
+        let mut bip = BipBuffer::new(&mut storage);
+        let (mut producer, mut consumer) = bip.split();
+
+        assert_eq!(producer.grant_max(16).unwrap().len(), 8);
+        producer.commit(6);
+        assert_eq!(producer.grant_max(16).unwrap().len(), 2);
+        producer.commit(2);
+        assert!(producer.grant_max(16).is_none());
+
+        consumer.release(3);
+        assert_eq!(producer.grant_max(16).unwrap().len(), 2);
+        assert_eq!(producer.grant_max(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn uncommitted_wrap_is_ignored() {
+        let mut storage = [0; 8];
+        let mut bip = BipBuffer::new(&mut storage);
+        let (mut producer, mut consumer) = bip.split();
+
+        producer.grant(6).unwrap();
+        producer.commit(6);
+        consumer.release(6);
+
+        // A wrapped grant that receives nothing leaves the indices alone.
+        producer.grant(4).unwrap();
+        producer.commit(0);
+        assert_eq!(consumer.try_read(), &[]);
+        assert_eq!(producer.grant(2).unwrap().len(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn commit_larger_than_grant() {
+        let mut storage = [0; 8];
+        let mut bip = BipBuffer::new(&mut storage);
+        let (mut producer, _) = bip.split();
+
+        producer.grant(2).unwrap();
+        producer.commit(3);
+    }
+
+    #[futures_test::test]
+    async fn commit_wakes_reader() {
+        let mut storage = [0; 8];
+        let mut bip = BipBuffer::new(&mut storage);
+        let (mut producer, mut consumer) = bip.split();
+
+        {
+            let mut read = pin!(consumer.read());
+            assert!(poll!(read.as_mut()).is_pending());
+            producer.grant(1).unwrap()[0] = 42;
+            producer.commit(1);
+            assert_eq!(read.await, &[42]);
+        }
+        consumer.release(1);
+    }
+}
@@ -0,0 +1,558 @@
+//! Implementation of [`BroadcastChannel`], a queue where every subscriber receives every message.
+
+use core::cell::RefCell;
+use core::future::{Future, poll_fn};
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use heapless::Deque;
+
+use crate::blocking_mutex::Mutex;
+use crate::blocking_mutex::raw::RawMutex;
+use crate::waitqueue::{MultiWakerRegistration, WakerRegistration};
+
+/// A single producer channel where every subscriber receives every message.
+///
+/// Each subscriber reads the queue at its own pace, with its own cursor. A message stays in the
+/// queue until all subscribers have received it, so publishing waits (or fails, with
+/// [`try_publish`](Self::try_publish)) while the slowest subscriber has `CAP` messages left to
+/// receive. No subscriber ever misses a message, unlike with a
+/// [`PubSubChannel`](crate::pubsub::PubSubChannel) when publishing immediately, or with a
+/// [`Watch`](crate::watch::Watch), which only keeps the latest value.
+///
+/// Up to `SUBS` subscribers can subscribe and unsubscribe (by being dropped) at any time. A new
+/// subscriber receives the messages published after it subscribed. Messages published while
+/// there are no subscribers are dropped.
+///
+/// The channel itself is the publisher. It is meant for a single producer task: if several
+/// tasks wait to publish at the same time, they keep waking each other.
+///
+/// ## Example
+///
+/// ```
+/// # use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// # use embassy_sync::broadcast::BroadcastChannel;
+/// # use futures_executor::block_on;
+/// # let test = async {
+/// // Create the channel. This can be static as well.
+/// let channel = BroadcastChannel::<NoopRawMutex, u32, 2, 3>::new();
+///
+/// let mut logger = channel.subscriber().unwrap();
+/// let mut display = channel.dyn_subscriber().unwrap();
+///
+/// channel.publish(1).await;
+/// channel.publish(2).await;
+///
+/// // The logger hasn't received anything yet, so the queue is full.
+/// assert_eq!(channel.try_publish(3), Err(3));
+///
+/// assert_eq!(logger.next_message().await, 1);
+/// assert_eq!(display.next_message().await, 1);
+/// assert_eq!(channel.try_publish(3), Ok(()));
+///
+/// assert_eq!(logger.try_next_message(), Some(2));
+/// assert_eq!(logger.try_next_message(), Some(3));
+/// assert_eq!(logger.try_next_message(), None);
+/// assert_eq!(display.available(), 2);
+/// # };
+/// #
+/// # block_on(test);
+/// ```
+#[derive(Debug)]
+pub struct BroadcastChannel<M: RawMutex, T: Clone, const CAP: usize, const SUBS: usize> {
+    inner: Mutex<M, RefCell<BroadcastState<T, CAP, SUBS>>>,
+}
+
+impl<M: RawMutex, T: Clone, const CAP: usize, const SUBS: usize> BroadcastChannel<M, T, CAP, SUBS> {
+    /// Create a new channel.
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::const_new(M::INIT, RefCell::new(BroadcastState::new())),
+        }
+    }
+
+    /// Create a new subscriber. It will only receive messages that are published after its creation.
+    ///
+    /// If there are no subscriber slots left, an error will be returned.
+    pub fn subscriber(&self) -> Result<Subscriber<'_, M, T, CAP, SUBS>, Error> {
+        self.inner
+            .lock(|s| s.borrow_mut().subscribe())
+            .map(|slot| Subscriber(Sub::new(slot, self)))
+    }
+
+    /// Create a new subscriber holding a dynamic reference to the channel. It will only receive
+    /// messages that are published after its creation.
+    ///
+    /// If there are no subscriber slots left, an error will be returned.
+    pub fn dyn_subscriber(&self) -> Result<DynSubscriber<'_, T>, Error> {
+        self.inner
+            .lock(|s| s.borrow_mut().subscribe())
+            .map(|slot| DynSubscriber(Sub::new(slot, self)))
+    }
+
+    /// Publish a message, waiting until all subscribers have room for it.
+    pub async fn publish(&self, message: T) {
+        let mut message = Some(message);
+        poll_fn(|cx| {
+            self.inner.lock(|s| {
+                let mut s = s.borrow_mut();
+                // The message is only taken out once, when it is published.
+                match s.try_publish(message.take().unwrap()) {
+                    Ok(()) => Poll::Ready(()),
+                    Err(m) => {
+                        message = Some(m);
+                        s.publisher_waker.register(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await
+    }
+
+    /// Publish a message if all subscribers have room for it.
+    ///
+    /// If the slowest subscriber has [`capacity`](Self::capacity) messages left to receive, the
+    /// message is given back.
+    pub fn try_publish(&self, message: T) -> Result<(), T> {
+        self.inner.lock(|s| s.borrow_mut().try_publish(message))
+    }
+
+    /// Returns the number of subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.inner.lock(|s| s.borrow().cursors.iter().flatten().count())
+    }
+
+    /// Returns the maximum number of messages the channel can hold.
+    pub const fn capacity(&self) -> usize {
+        CAP
+    }
+
+    /// Returns the number of messages not received by all subscribers yet.
+    pub fn len(&self) -> usize {
+        self.inner.lock(|s| s.borrow().queue.len())
+    }
+
+    /// Returns whether all subscribers have received all messages.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the channel is full, in which case publishing waits.
+    pub fn is_full(&self) -> bool {
+        self.len() == CAP
+    }
+}
+
+impl<M: RawMutex, T: Clone, const CAP: usize, const SUBS: usize> SealedBroadcastBehavior<T>
+    for BroadcastChannel<M, T, CAP, SUBS>
+{
+    fn poll_receive(&self, slot: usize, cx: Option<&mut Context<'_>>) -> Poll<T> {
+        self.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+            match s.receive(slot) {
+                Some(message) => Poll::Ready(message),
+                None => {
+                    if let Some(cx) = cx {
+                        s.subscriber_wakers.register(cx.waker());
+                    }
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    fn available(&self, slot: usize) -> usize {
+        self.inner.lock(|s| s.borrow().available(slot))
+    }
+
+    fn unsubscribe(&self, slot: usize) {
+        self.inner.lock(|s| s.borrow_mut().unsubscribe(slot))
+    }
+}
+
+impl<M: RawMutex, T: Clone, const CAP: usize, const SUBS: usize> BroadcastBehavior<T>
+    for BroadcastChannel<M, T, CAP, SUBS>
+{
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+/// Internal state for the broadcast channel
+#[derive(Debug)]
+struct BroadcastState<T: Clone, const CAP: usize, const SUBS: usize> {
+    /// The messages not received by all subscribers yet, the last one having id `next_id - 1`.
+    queue: Deque<T, CAP>,
+    /// Id of the next message to be published.
+    next_id: u64,
+    /// Id of the next message to receive, for each subscriber slot in use.
+    cursors: [Option<u64>; SUBS],
+    /// Collection of wakers for subscribers that are waiting.
+    subscriber_wakers: MultiWakerRegistration<SUBS>,
+    /// Waker of the publisher, waiting for room in the queue.
+    publisher_waker: WakerRegistration,
+}
+
+impl<T: Clone, const CAP: usize, const SUBS: usize> BroadcastState<T, CAP, SUBS> {
+    const fn new() -> Self {
+        Self {
+            queue: Deque::new(),
+            next_id: 0,
+            cursors: [None; SUBS],
+            subscriber_wakers: MultiWakerRegistration::new(),
+            publisher_waker: WakerRegistration::new(),
+        }
+    }
+
+    /// Id of the oldest message in the queue.
+    fn first_id(&self) -> u64 {
+        self.next_id - self.queue.len() as u64
+    }
+
+    fn subscribe(&mut self) -> Result<usize, Error> {
+        let slot = self
+            .cursors
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::MaximumSubscribersReached)?;
+        self.cursors[slot] = Some(self.next_id);
+        Ok(slot)
+    }
+
+    fn unsubscribe(&mut self, slot: usize) {
+        self.cursors[slot] = None;
+
+        // Drop the messages only this subscriber had left to receive.
+        let min = self.cursors.iter().flatten().min().copied().unwrap_or(self.next_id);
+        if self.first_id() < min {
+            while self.first_id() < min {
+                self.queue.pop_front();
+            }
+            self.publisher_waker.wake();
+        }
+    }
+
+    fn try_publish(&mut self, message: T) -> Result<(), T> {
+        if self.cursors.iter().all(Option::is_none) {
+            // We don't need to publish anything because there is no one to receive it
+            return Ok(());
+        }
+
+        self.queue.push_back(message)?;
+        self.next_id += 1;
+        self.subscriber_wakers.wake();
+        Ok(())
+    }
+
+    fn receive(&mut self, slot: usize) -> Option<T> {
+        let id = self.cursors[slot]?;
+        if id == self.next_id {
+            return None;
+        }
+        self.cursors[slot] = Some(id + 1);
+
+        let index = (id - self.first_id()) as usize;
+        // The last subscriber to receive the oldest message takes it without a clone.
+        if index == 0 && self.cursors.iter().flatten().all(|c| *c > id) {
+            self.publisher_waker.wake();
+            self.queue.pop_front()
+        } else {
+            self.queue.iter().nth(index).cloned()
+        }
+    }
+
+    fn available(&self, slot: usize) -> usize {
+        self.cursors[slot].map_or(0, |id| (self.next_id - id) as usize)
+    }
+}
+
+/// Error type for the [`BroadcastChannel`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// All subscriber slots are used. To add another subscriber, first another subscriber must be dropped or
+    /// the number of subscribers of the channel must be increased.
+    MaximumSubscribersReached,
+}
+
+trait SealedBroadcastBehavior<T> {
+    /// Try to receive the next message of the subscriber in `slot`.
+    ///
+    /// If there is none and a context is given, then its waker is registered in the subscriber wakers.
+    fn poll_receive(&self, slot: usize, cx: Option<&mut Context<'_>>) -> Poll<T>;
+
+    /// Get the number of messages the subscriber in `slot` has left to receive.
+    fn available(&self, slot: usize) -> usize;
+
+    /// Free the subscriber slot `slot`, dropping the messages only it had left to receive.
+    fn unsubscribe(&self, slot: usize);
+}
+
+/// 'Middle level' behaviour of the broadcast channel.
+/// Just for the subscribers to hold a dynamic reference to the channel.
+#[allow(private_bounds)]
+pub trait BroadcastBehavior<T>: SealedBroadcastBehavior<T> {
+    /// Returns the maximum number of messages the channel can hold.
+    fn capacity(&self) -> usize;
+
+    /// Returns the number of messages not received by all subscribers yet.
+    fn len(&self) -> usize;
+
+    /// Returns whether all subscribers have received all messages.
+    fn is_empty(&self) -> bool;
+}
+
+/// A subscriber to a [`BroadcastChannel`]
+#[derive(Debug)]
+pub struct Sub<'a, B: BroadcastBehavior<T> + ?Sized, T: Clone> {
+    /// The subscriber slot, holding our cursor
+    slot: usize,
+    /// The channel we are a subscriber to
+    channel: &'a B,
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, B: BroadcastBehavior<T> + ?Sized, T: Clone> Sub<'a, B, T> {
+    fn new(slot: usize, channel: &'a B) -> Self {
+        Self {
+            slot,
+            channel,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Wait for the next message.
+    pub fn next_message(&mut self) -> impl Future<Output = T> + '_ {
+        poll_fn(|cx| self.channel.poll_receive(self.slot, Some(cx)))
+    }
+
+    /// Receive the next message, if there is one.
+    pub fn try_next_message(&mut self) -> Option<T> {
+        match self.channel.poll_receive(self.slot, None) {
+            Poll::Ready(message) => Some(message),
+            Poll::Pending => None,
+        }
+    }
+
+    /// The number of messages this subscriber hasn't received yet.
+    pub fn available(&self) -> usize {
+        self.channel.available(self.slot)
+    }
+
+    /// Returns the maximum number of messages the ***channel*** can hold.
+    pub fn capacity(&self) -> usize {
+        self.channel.capacity()
+    }
+
+    /// Returns the number of messages not received by all subscribers of the ***channel*** yet.
+    /// See [`Self::available`] for how many messages are available for this subscriber.
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Returns whether the ***channel*** is empty.
+    pub fn is_empty(&self) -> bool {
+        self.channel.is_empty()
+    }
+}
+
+impl<'a, B: BroadcastBehavior<T> + ?Sized, T: Clone> Drop for Sub<'a, B, T> {
+    fn drop(&mut self) {
+        self.channel.unsubscribe(self.slot)
+    }
+}
+
+impl<'a, B: BroadcastBehavior<T> + ?Sized, T: Clone> Unpin for Sub<'a, B, T> {}
+
+impl<'a, B: BroadcastBehavior<T> + ?Sized, T: Clone> futures_core::Stream for Sub<'a, B, T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.channel.poll_receive(self.slot, Some(cx)).map(Some)
+    }
+}
+
+/// A subscriber that holds a dynamic reference to the channel
+pub struct DynSubscriber<'a, T: Clone>(Sub<'a, dyn BroadcastBehavior<T> + 'a, T>);
+
+impl<'a, T: Clone> Deref for DynSubscriber<'a, T> {
+    type Target = Sub<'a, dyn BroadcastBehavior<T> + 'a, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, T: Clone> DerefMut for DynSubscriber<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// A subscriber that holds a generic reference to the channel
+#[derive(Debug)]
+pub struct Subscriber<'a, M: RawMutex, T: Clone, const CAP: usize, const SUBS: usize>(
+    Sub<'a, BroadcastChannel<M, T, CAP, SUBS>, T>,
+);
+
+impl<'a, M: RawMutex, T: Clone, const CAP: usize, const SUBS: usize> Deref for Subscriber<'a, M, T, CAP, SUBS> {
+    type Target = Sub<'a, BroadcastChannel<M, T, CAP, SUBS>, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a, M: RawMutex, T: Clone, const CAP: usize, const SUBS: usize> DerefMut for Subscriber<'a, M, T, CAP, SUBS> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures_executor::block_on;
+    use futures_test::task::new_count_waker;
+
+    use super::*;
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    #[test]
+    fn all_subscribers_receive_all_messages() {
+        let channel = BroadcastChannel::<NoopRawMutex, u32, 4, 3>::new();
+        let mut sub0 = channel.subscriber().unwrap();
+        let mut sub1 = channel.subscriber().unwrap();
+
+        for i in 0..4 {
+            assert_eq!(channel.try_publish(i), Ok(()));
+        }
+
+        // Independent cursors.
+        assert_eq!(sub0.try_next_message(), Some(0));
+        assert_eq!(sub0.try_next_message(), Some(1));
+        assert_eq!(sub0.available(), 2);
+        assert_eq!(sub1.available(), 4);
+        assert_eq!(channel.len(), 4);
+
+        for i in 0..4 {
+            assert_eq!(sub1.try_next_message(), Some(i));
+        }
+        assert_eq!(sub1.try_next_message(), None);
+        // The messages received by both subscribers are dropped.
+        assert_eq!(channel.len(), 2);
+
+        assert_eq!(sub0.try_next_message(), Some(2));
+        assert_eq!(sub0.try_next_message(), Some(3));
+        assert_eq!(sub0.try_next_message(), None);
+        assert!(channel.is_empty());
+    }
+
+    #[test]
+    fn slowest_subscriber_blocks_publishing() {
+        let channel = BroadcastChannel::<NoopRawMutex, u32, 2, 2>::new();
+        let mut fast = channel.subscriber().unwrap();
+        let mut slow = channel.subscriber().unwrap();
+
+        assert_eq!(channel.try_publish(0), Ok(()));
+        assert_eq!(channel.try_publish(1), Ok(()));
+        assert_eq!(fast.try_next_message(), Some(0));
+        assert_eq!(fast.try_next_message(), Some(1));
+        assert!(channel.is_full());
+        assert_eq!(channel.try_publish(2), Err(2));
+
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut publish = pin!(channel.publish(2));
+        assert!(publish.as_mut().poll(&mut cx).is_pending());
+
+        assert_eq!(slow.try_next_message(), Some(0));
+        assert_eq!(count.get(), 1);
+        assert!(publish.as_mut().poll(&mut cx).is_ready());
+
+        assert_eq!(fast.try_next_message(), Some(2));
+        assert_eq!(slow.try_next_message(), Some(1));
+        assert_eq!(slow.try_next_message(), Some(2));
+    }
+
+    #[test]
+    fn subscribers_join_and_leave() {
+        let channel = BroadcastChannel::<NoopRawMutex, u32, 2, 2>::new();
+
+        // Nobody to receive it.
+        assert_eq!(channel.try_publish(0), Ok(()));
+        assert!(channel.is_empty());
+
+        let sub0 = channel.subscriber().unwrap();
+        let mut sub1 = channel.dyn_subscriber().unwrap();
+        assert_eq!(channel.subscriber().err(), Some(Error::MaximumSubscribersReached));
+        assert_eq!(channel.subscriber_count(), 2);
+
+        assert_eq!(channel.try_publish(1), Ok(()));
+        assert_eq!(channel.try_publish(2), Ok(()));
+        assert_eq!(channel.try_publish(3), Err(3));
+
+        // A new subscriber only receives new messages.
+        drop(sub0);
+        let mut sub2 = channel.subscriber().unwrap();
+        assert_eq!(sub2.available(), 0);
+
+        assert_eq!(sub1.try_next_message(), Some(1));
+        assert_eq!(channel.try_publish(3), Ok(()));
+        assert_eq!(sub2.try_next_message(), Some(3));
+        assert_eq!(sub2.try_next_message(), None);
+
+        // The messages only the dropped subscriber had left are dropped with it.
+        drop(sub1);
+        assert!(channel.is_empty());
+        assert_eq!(channel.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn next_message_waits() {
+        block_on(async {
+            let channel = BroadcastChannel::<NoopRawMutex, u32, 2, 2>::new();
+            let mut sub = channel.subscriber().unwrap();
+
+            let (waker, count) = new_count_waker();
+            let mut cx = Context::from_waker(&waker);
+            {
+                let mut next = pin!(sub.next_message());
+                assert!(next.as_mut().poll(&mut cx).is_pending());
+                channel.publish(42).await;
+                assert_eq!(count.get(), 1);
+                assert_eq!(next.as_mut().poll(&mut cx), Poll::Ready(42));
+            }
+
+            channel.publish(43).await;
+            assert_eq!(sub.next_message().await, 43);
+        });
+    }
+
+    #[test]
+    fn stream() {
+        use futures_util::StreamExt;
+
+        block_on(async {
+            let channel = BroadcastChannel::<NoopRawMutex, u32, 4, 1>::new();
+            let mut sub = channel.dyn_subscriber().unwrap();
+
+            channel.publish(1).await;
+            channel.publish(2).await;
+            assert_eq!(sub.next().await, Some(1));
+            assert_eq!(sub.next().await, Some(2));
+        });
+    }
+}
@@ -11,13 +11,16 @@ pub(crate) mod fmt;
 // internal use
 mod ring_buffer;
 
+pub mod bip_buffer;
 pub mod blocking_mutex;
+pub mod broadcast;
 pub mod channel;
 pub mod lazy_lock;
 pub mod mutex;
 pub mod once_lock;
 pub mod pipe;
 pub mod priority_channel;
+pub mod priority_mutex;
 pub mod pubsub;
 pub mod rwlock;
 pub mod semaphore;
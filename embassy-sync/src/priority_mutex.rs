@@ -0,0 +1,521 @@
+//! Async mutex granting the lock by priority.
+//!
+//! [`Mutex`](crate::mutex::Mutex) leaves the order in which waiting tasks get the lock
+//! unspecified, so a high-priority task can wait behind any number of low-priority ones.
+//! [`PriorityMutex`] hands the lock over to the waiting task with the highest priority instead,
+//! and to the one that has waited the longest among equal priorities.
+use core::cell::{RefCell, UnsafeCell};
+use core::fmt;
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::ptr::NonNull;
+use core::task::{Context, Poll, Waker};
+
+use crate::blocking_mutex::Mutex as BlockingMutex;
+use crate::blocking_mutex::raw::RawMutex;
+pub use crate::mutex::TryLockError;
+
+/// Priority of [`PriorityMutex::lock`].
+pub const DEFAULT_PRIORITY: u8 = 0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WaiterState {
+    /// Not in the queue.
+    Idle,
+    /// In the queue, waiting for the lock.
+    Queued,
+    /// Taken out of the queue by an unlock, which handed the lock over.
+    Granted,
+}
+
+/// Queue entry, stored in the lock future.
+///
+/// Only accessed with the state of the mutex locked.
+struct Waiter {
+    priority: u8,
+    state: WaiterState,
+    waker: Option<Waker>,
+    prev: Option<NonNull<Waiter>>,
+    next: Option<NonNull<Waiter>>,
+}
+
+struct State {
+    locked: bool,
+    /// Waiters, by decreasing priority and then arrival order.
+    head: Option<NonNull<Waiter>>,
+}
+
+// The waiters are only accessed with the state locked.
+unsafe impl Send for State {}
+
+impl State {
+    /// Queue `waiter` after all the waiters of the same or higher priority.
+    ///
+    /// # Safety
+    ///
+    /// `waiter` must stay valid until it is removed from the queue.
+    unsafe fn insert(&mut self, waiter: NonNull<Waiter>) {
+        let priority = (*waiter.as_ptr()).priority;
+
+        let mut prev = None;
+        let mut next = self.head;
+        while let Some(n) = next {
+            if (*n.as_ptr()).priority < priority {
+                break;
+            }
+            prev = next;
+            next = (*n.as_ptr()).next;
+        }
+
+        let w = &mut *waiter.as_ptr();
+        w.prev = prev;
+        w.next = next;
+        w.state = WaiterState::Queued;
+        match prev {
+            Some(p) => (*p.as_ptr()).next = Some(waiter),
+            None => self.head = Some(waiter),
+        }
+        if let Some(n) = next {
+            (*n.as_ptr()).prev = Some(waiter);
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `waiter` must be in the queue.
+    unsafe fn remove(&mut self, waiter: NonNull<Waiter>) {
+        let w = &mut *waiter.as_ptr();
+        match w.prev {
+            Some(p) => (*p.as_ptr()).next = w.next,
+            None => self.head = w.next,
+        }
+        if let Some(n) = w.next {
+            (*n.as_ptr()).prev = w.prev;
+        }
+        w.prev = None;
+        w.next = None;
+        w.state = WaiterState::Idle;
+    }
+
+    /// Hand the lock over to the first waiter, or unlock if there is none.
+    fn unlock(&mut self) {
+        match self.head {
+            Some(first) => unsafe {
+                self.remove(first);
+                let w = &mut *first.as_ptr();
+                w.state = WaiterState::Granted;
+                if let Some(waker) = w.waker.take() {
+                    waker.wake();
+                }
+            },
+            None => self.locked = false,
+        }
+    }
+}
+
+/// Async mutex granting the lock to the waiting task with the highest priority.
+///
+/// Tasks lock it with [`lock_with_priority`](Self::lock_with_priority), where higher values
+/// are served first. When the mutex is unlocked while tasks are waiting, it stays locked and is
+/// handed over to the waiter with the highest priority, or to the one that waited the longest
+/// among the highest priority. A task locking an unlocked mutex gets it immediately, whatever
+/// its priority.
+///
+/// The waiters are queued in their lock futures, so the mutex needs no allocation and has no
+/// limit on their number. A lock future dropped while waiting leaves the queue, and one dropped
+/// after the lock was handed over to it hands it over to the next waiter.
+///
+/// Like [`Mutex`](crate::mutex::Mutex), it is generic over a blocking [`RawMutex`], which
+/// only guards the queue and the "is locked" flag.
+pub struct PriorityMutex<M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    state: BlockingMutex<M, RefCell<State>>,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<M: RawMutex + Send, T: ?Sized + Send> Send for PriorityMutex<M, T> {}
+unsafe impl<M: RawMutex + Sync, T: ?Sized + Send> Sync for PriorityMutex<M, T> {}
+
+impl<M, T> PriorityMutex<M, T>
+where
+    M: RawMutex,
+{
+    /// Create a new mutex with the given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: UnsafeCell::new(value),
+            state: BlockingMutex::new(RefCell::new(State {
+                locked: false,
+                head: None,
+            })),
+        }
+    }
+}
+
+impl<M, T> PriorityMutex<M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    /// Lock the mutex, with [`DEFAULT_PRIORITY`].
+    ///
+    /// This will wait for the mutex to be unlocked if it's already locked.
+    pub fn lock(&self) -> PriorityLockFuture<'_, M, T> {
+        self.lock_with_priority(DEFAULT_PRIORITY)
+    }
+
+    /// Lock the mutex, with the given priority.
+    ///
+    /// This will wait for the mutex to be unlocked if it's already locked. Waiters with a higher
+    /// `priority` get the lock first.
+    pub fn lock_with_priority(&self, priority: u8) -> PriorityLockFuture<'_, M, T> {
+        PriorityLockFuture {
+            mutex: self,
+            waiter: UnsafeCell::new(Waiter {
+                priority,
+                state: WaiterState::Idle,
+                waker: None,
+                prev: None,
+                next: None,
+            }),
+            done: false,
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Attempt to immediately lock the mutex.
+    ///
+    /// If the mutex is already locked, this will return an error instead of waiting.
+    pub fn try_lock(&self) -> Result<PriorityMutexGuard<'_, M, T>, TryLockError> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.locked {
+                Err(TryLockError)
+            } else {
+                s.locked = true;
+                Ok(())
+            }
+        })?;
+
+        Ok(PriorityMutexGuard { mutex: self })
+    }
+
+    /// Consumes this mutex, returning the underlying data.
+    pub fn into_inner(self) -> T
+    where
+        T: Sized,
+    {
+        self.inner.into_inner()
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the mutex mutably, no actual locking needs to
+    /// take place -- the mutable borrow statically guarantees no locks exist.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+impl<M: RawMutex, T> From<T> for PriorityMutex<M, T> {
+    fn from(from: T) -> Self {
+        Self::new(from)
+    }
+}
+
+impl<M, T> Default for PriorityMutex<M, T>
+where
+    M: RawMutex,
+    T: Default,
+{
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<M, T> fmt::Debug for PriorityMutex<M, T>
+where
+    M: RawMutex,
+    T: ?Sized + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("PriorityMutex");
+        match self.try_lock() {
+            Ok(value) => {
+                d.field("inner", &&*value);
+            }
+            Err(TryLockError) => {
+                d.field("inner", &format_args!("<locked>"));
+            }
+        }
+
+        d.finish_non_exhaustive()
+    }
+}
+
+/// Future returned by [`PriorityMutex::lock`] and [`PriorityMutex::lock_with_priority`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct PriorityLockFuture<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    mutex: &'a PriorityMutex<M, T>,
+    waiter: UnsafeCell<Waiter>,
+    /// The guard was returned.
+    done: bool,
+    _pinned: PhantomPinned,
+}
+
+unsafe impl<M: RawMutex + Sync, T: ?Sized + Send> Send for PriorityLockFuture<'_, M, T> {}
+
+impl<'a, M, T> Future for PriorityLockFuture<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Output = PriorityMutexGuard<'a, M, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: the future is not moved out of, and the waiter is only accessed with the
+        // state locked.
+        let this = unsafe { self.get_unchecked_mut() };
+        assert!(!this.done, "PriorityLockFuture polled after completion");
+        let waiter = NonNull::from(&this.waiter).cast::<Waiter>();
+
+        let ready = this.mutex.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let w = unsafe { &mut *waiter.as_ptr() };
+            match w.state {
+                WaiterState::Granted => {
+                    w.state = WaiterState::Idle;
+                    true
+                }
+                WaiterState::Queued => {
+                    if !w.waker.as_ref().is_some_and(|waker| waker.will_wake(cx.waker())) {
+                        w.waker = Some(cx.waker().clone());
+                    }
+                    false
+                }
+                WaiterState::Idle if !s.locked => {
+                    s.locked = true;
+                    true
+                }
+                WaiterState::Idle => {
+                    w.waker = Some(cx.waker().clone());
+                    // Safety: the future is pinned, and removes the waiter from the queue
+                    // when dropped.
+                    unsafe { s.insert(waiter) };
+                    false
+                }
+            }
+        });
+
+        if ready {
+            this.done = true;
+            Poll::Ready(PriorityMutexGuard { mutex: this.mutex })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<M, T> Drop for PriorityLockFuture<'_, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        let waiter = NonNull::from(&self.waiter).cast::<Waiter>();
+        self.mutex.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            match unsafe { (*waiter.as_ptr()).state } {
+                WaiterState::Queued => unsafe { s.remove(waiter) },
+                // The lock was handed over, but nobody will use it.
+                WaiterState::Granted => s.unlock(),
+                WaiterState::Idle => {}
+            }
+        })
+    }
+}
+
+/// Async priority mutex guard.
+///
+/// Owning an instance of this type indicates having
+/// successfully locked the mutex, and grants access to the contents.
+///
+/// Dropping it unlocks the mutex, handing it over to the waiter with the highest priority.
+#[clippy::has_significant_drop]
+#[must_use = "if unused the PriorityMutex will immediately unlock"]
+pub struct PriorityMutexGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    mutex: &'a PriorityMutex<M, T>,
+}
+
+impl<'a, M, T> Drop for PriorityMutexGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.mutex.state.lock(|s| unwrap!(s.try_borrow_mut()).unlock())
+    }
+}
+
+impl<'a, M, T> Deref for PriorityMutexGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: the PriorityMutexGuard represents exclusive access to the contents
+        // of the mutex, so it's OK to get it.
+        unsafe { &*(self.mutex.inner.get() as *const T) }
+    }
+}
+
+impl<'a, M, T> DerefMut for PriorityMutexGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: the PriorityMutexGuard represents exclusive access to the contents
+        // of the mutex, so it's OK to get it.
+        unsafe { &mut *(self.mutex.inner.get()) }
+    }
+}
+
+impl<'a, M, T> fmt::Debug for PriorityMutexGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures_util::poll;
+
+    use super::*;
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    #[futures_test::test]
+    async fn highest_priority_first() {
+        let mutex = PriorityMutex::<NoopRawMutex, u32>::new(0);
+        let guard = mutex.lock().await;
+
+        let mut low = pin!(mutex.lock_with_priority(1));
+        let mut high = pin!(mutex.lock_with_priority(5));
+        let mut mid = pin!(mutex.lock_with_priority(3));
+        assert!(poll!(low.as_mut()).is_pending());
+        assert!(poll!(high.as_mut()).is_pending());
+        assert!(poll!(mid.as_mut()).is_pending());
+
+        drop(guard);
+        // Handed over to `high`, not available to others.
+        assert!(mutex.try_lock().is_err());
+        assert!(poll!(low.as_mut()).is_pending());
+        assert!(poll!(mid.as_mut()).is_pending());
+        let Poll::Ready(guard) = poll!(high.as_mut()) else {
+            panic!("high priority waiter not served first");
+        };
+
+        drop(guard);
+        assert!(poll!(low.as_mut()).is_pending());
+        let Poll::Ready(guard) = poll!(mid.as_mut()) else {
+            panic!("mid priority waiter not served second");
+        };
+
+        drop(guard);
+        assert!(poll!(low.as_mut()).is_ready());
+    }
+
+    #[futures_test::test]
+    async fn fifo_among_equal_priorities() {
+        let mutex = PriorityMutex::<NoopRawMutex, u32>::new(0);
+        let guard = mutex.lock().await;
+
+        let mut first = pin!(mutex.lock_with_priority(2));
+        let mut second = pin!(mutex.lock_with_priority(2));
+        let mut third = pin!(mutex.lock_with_priority(2));
+        assert!(poll!(first.as_mut()).is_pending());
+        assert!(poll!(second.as_mut()).is_pending());
+        assert!(poll!(third.as_mut()).is_pending());
+
+        drop(guard);
+        assert!(poll!(third.as_mut()).is_pending());
+        assert!(poll!(second.as_mut()).is_pending());
+        let Poll::Ready(guard) = poll!(first.as_mut()) else {
+            panic!("first waiter not served first");
+        };
+
+        drop(guard);
+        assert!(poll!(third.as_mut()).is_pending());
+        assert!(poll!(second.as_mut()).is_ready());
+    }
+
+    #[futures_test::test]
+    async fn dropped_waiter_leaves_queue() {
+        let mutex = PriorityMutex::<NoopRawMutex, u32>::new(0);
+        let guard = mutex.lock().await;
+
+        let mut low = pin!(mutex.lock_with_priority(1));
+        assert!(poll!(low.as_mut()).is_pending());
+        {
+            let mut high = pin!(mutex.lock_with_priority(5));
+            assert!(poll!(high.as_mut()).is_pending());
+        }
+
+        drop(guard);
+        assert!(poll!(low.as_mut()).is_ready());
+    }
+
+    #[futures_test::test]
+    async fn dropped_granted_waiter_hands_over() {
+        let mutex = PriorityMutex::<NoopRawMutex, u32>::new(0);
+        let guard = mutex.lock().await;
+
+        let mut low = pin!(mutex.lock_with_priority(1));
+        assert!(poll!(low.as_mut()).is_pending());
+        {
+            let mut high = pin!(mutex.lock_with_priority(5));
+            assert!(poll!(high.as_mut()).is_pending());
+            drop(guard);
+            // `high` is dropped with the lock handed over to it.
+        }
+
+        assert!(poll!(low.as_mut()).is_ready());
+    }
+
+    #[futures_test::test]
+    async fn unlocked_after_last_waiter() {
+        let mutex = PriorityMutex::<NoopRawMutex, u32>::new(0);
+        {
+            let guard = mutex.lock().await;
+            let mut waiter = pin!(mutex.lock_with_priority(1));
+            assert!(poll!(waiter.as_mut()).is_pending());
+            drop(guard);
+            let Poll::Ready(mut guard) = poll!(waiter.as_mut()) else {
+                panic!("waiter not served");
+            };
+            *guard = 1;
+        }
+
+        assert_eq!(*mutex.try_lock().unwrap(), 1);
+    }
+}
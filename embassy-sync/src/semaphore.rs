@@ -266,6 +266,10 @@ where
         self.state.lock(|cell| {
             let mut state = cell.borrow_mut();
             if let Some(permits) = state.take(ticket, permits, acquire_all) {
+                // The ticket was popped, dropping the future must not cancel it.
+                if let Some((ticket_ref, _)) = cx {
+                    *ticket_ref = None;
+                }
                 Poll::Ready(Ok(SemaphoreReleaser {
                     semaphore: self,
                     permits,
@@ -429,6 +433,11 @@ impl<const N: usize> FairSemaphoreState<N> {
     fn cancel(&mut self, ticket: Option<usize>) {
         if let Some(ticket) = ticket {
             self.set_waker(ticket, None);
+            // A canceled head may have been woken for permits it will never take, or may have
+            // been blocking the waiters behind it: let the next waiter try.
+            if self.permits > 0 {
+                self.wake();
+            }
         }
     }
 
@@ -490,6 +499,184 @@ impl<const N: usize> FairSemaphoreState<N> {
     }
 }
 
+/// Object-safe part of a [`Semaphore`], for [`DynamicSemaphore`].
+pub(crate) trait DynamicSemaphoreImpl {
+    /// Poll for permits. `cx` holds the ticket of the waiter, for semaphores which keep a queue.
+    ///
+    /// Returns the number of permits acquired, which the caller must release.
+    fn poll_acquire_permits(
+        &self,
+        permits: usize,
+        acquire_all: bool,
+        cx: Option<(&mut Option<usize>, &Waker)>,
+    ) -> Poll<Result<usize, WaitQueueFull>>;
+
+    /// Give up waiting, freeing the ticket of the waiter.
+    fn cancel(&self, ticket: Option<usize>);
+
+    fn release_permits(&self, permits: usize);
+
+    fn set_permits(&self, permits: usize);
+}
+
+impl<M: RawMutex> DynamicSemaphoreImpl for GreedySemaphore<M> {
+    fn poll_acquire_permits(
+        &self,
+        permits: usize,
+        acquire_all: bool,
+        cx: Option<(&mut Option<usize>, &Waker)>,
+    ) -> Poll<Result<usize, WaitQueueFull>> {
+        match self.poll_acquire(permits, acquire_all, cx.map(|(_, waker)| waker)) {
+            Poll::Ready(Ok(releaser)) => Poll::Ready(Ok(releaser.disarm())),
+            Poll::Ready(Err(e)) => match e {},
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn cancel(&self, _ticket: Option<usize>) {}
+
+    fn release_permits(&self, permits: usize) {
+        Semaphore::release(self, permits)
+    }
+
+    fn set_permits(&self, permits: usize) {
+        Semaphore::set(self, permits)
+    }
+}
+
+impl<M: RawMutex, const N: usize> DynamicSemaphoreImpl for FairSemaphore<M, N> {
+    fn poll_acquire_permits(
+        &self,
+        permits: usize,
+        acquire_all: bool,
+        cx: Option<(&mut Option<usize>, &Waker)>,
+    ) -> Poll<Result<usize, WaitQueueFull>> {
+        self.poll_acquire(permits, acquire_all, cx)
+            .map(|res| res.map(|releaser| releaser.disarm()))
+    }
+
+    fn cancel(&self, ticket: Option<usize>) {
+        self.state.lock(|cell| cell.borrow_mut().cancel(ticket));
+    }
+
+    fn release_permits(&self, permits: usize) {
+        Semaphore::release(self, permits)
+    }
+
+    fn set_permits(&self, permits: usize) {
+        Semaphore::set(self, permits)
+    }
+}
+
+/// A [`Semaphore`] with its type erased, so that code can take any semaphore without being generic.
+///
+/// It is created from a reference to a [`GreedySemaphore`] or a [`FairSemaphore`], and behaves like
+/// it. The error type is [`WaitQueueFull`], which only a [`FairSemaphore`] can return.
+#[derive(Clone, Copy)]
+pub struct DynamicSemaphore<'a> {
+    sema: &'a dyn DynamicSemaphoreImpl,
+}
+
+impl<'a> core::fmt::Debug for DynamicSemaphore<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DynamicSemaphore").finish_non_exhaustive()
+    }
+}
+
+impl<'a, M: RawMutex> From<&'a GreedySemaphore<M>> for DynamicSemaphore<'a> {
+    fn from(sema: &'a GreedySemaphore<M>) -> Self {
+        Self { sema }
+    }
+}
+
+impl<'a, M: RawMutex, const N: usize> From<&'a FairSemaphore<M, N>> for DynamicSemaphore<'a> {
+    fn from(sema: &'a FairSemaphore<M, N>) -> Self {
+        Self { sema }
+    }
+}
+
+impl<'a> DynamicSemaphore<'a> {
+    fn try_acquire_permits(&self, permits: usize, acquire_all: bool) -> Option<SemaphoreReleaser<'_, Self>> {
+        match self.sema.poll_acquire_permits(permits, acquire_all, None) {
+            Poll::Ready(Ok(permits)) => Some(SemaphoreReleaser {
+                semaphore: self,
+                permits,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Semaphore for DynamicSemaphore<'a> {
+    type Error = WaitQueueFull;
+
+    fn acquire(&self, permits: usize) -> impl Future<Output = Result<SemaphoreReleaser<'_, Self>, Self::Error>> {
+        DynamicAcquire {
+            sema: self,
+            permits,
+            acquire_all: false,
+            ticket: None,
+        }
+    }
+
+    fn try_acquire(&self, permits: usize) -> Option<SemaphoreReleaser<'_, Self>> {
+        self.try_acquire_permits(permits, false)
+    }
+
+    fn acquire_all(&self, min: usize) -> impl Future<Output = Result<SemaphoreReleaser<'_, Self>, Self::Error>> {
+        DynamicAcquire {
+            sema: self,
+            permits: min,
+            acquire_all: true,
+            ticket: None,
+        }
+    }
+
+    fn try_acquire_all(&self, min: usize) -> Option<SemaphoreReleaser<'_, Self>> {
+        self.try_acquire_permits(min, true)
+    }
+
+    fn release(&self, permits: usize) {
+        self.sema.release_permits(permits)
+    }
+
+    fn set(&self, permits: usize) {
+        self.sema.set_permits(permits)
+    }
+}
+
+#[derive(Debug)]
+struct DynamicAcquire<'s, 'a> {
+    sema: &'s DynamicSemaphore<'a>,
+    permits: usize,
+    acquire_all: bool,
+    ticket: Option<usize>,
+}
+
+impl<'s, 'a> Drop for DynamicAcquire<'s, 'a> {
+    fn drop(&mut self) {
+        self.sema.sema.cancel(self.ticket.take());
+    }
+}
+
+impl<'s, 'a> core::future::Future for DynamicAcquire<'s, 'a> {
+    type Output = Result<SemaphoreReleaser<'s, DynamicSemaphore<'a>>, WaitQueueFull>;
+
+    fn poll(mut self: core::pin::Pin<&mut Self>, cx: &mut core::task::Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let res =
+            this.sema
+                .sema
+                .poll_acquire_permits(this.permits, this.acquire_all, Some((&mut this.ticket, cx.waker())));
+        res.map(|res| {
+            res.map(|permits| SemaphoreReleaser {
+                semaphore: this.sema,
+                permits,
+            })
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     mod greedy {
@@ -613,9 +800,14 @@ mod tests {
 
     mod fair {
         use core::pin::pin;
+        use core::task::Context;
         use core::time::Duration;
 
+        extern crate std;
+        use std::boxed::Box;
+
         use futures_executor::ThreadPool;
+        use futures_test::task::new_count_waker;
         use futures_timer::Delay;
         use futures_util::poll;
         use futures_util::task::SpawnExt;
@@ -774,5 +966,97 @@ mod tests {
             let c = c_task.await.unwrap();
             assert_eq!(c.permits(), 1);
         }
+
+        #[test]
+        fn cancel_wakes_next_waiter() {
+            let semaphore = FairSemaphore::<NoopRawMutex, 2>::new(1);
+            let (waker, wakes) = new_count_waker();
+            let mut cx = Context::from_waker(&waker);
+
+            let mut a_fut = Box::pin(semaphore.acquire(2));
+            assert!(a_fut.as_mut().poll(&mut cx).is_pending());
+            let mut b_fut = pin!(semaphore.acquire(1));
+            assert!(b_fut.as_mut().poll(&mut cx).is_pending()); // `b` is blocked behind `a`
+            assert_eq!(wakes.get(), 0);
+
+            // `a` gives up, `b` must be woken to take the available permit.
+            core::mem::drop(a_fut);
+            assert_eq!(wakes.get(), 1);
+            assert!(b_fut.as_mut().poll(&mut cx).is_ready());
+        }
+
+        #[test]
+        fn drop_after_acquire() {
+            let semaphore = FairSemaphore::<NoopRawMutex, 2>::new(2);
+            let (waker, wakes) = new_count_waker();
+            let mut cx = Context::from_waker(&waker);
+
+            let a = semaphore.try_acquire(2).unwrap();
+            let mut b_fut = Box::pin(semaphore.acquire(1));
+            assert!(b_fut.as_mut().poll(&mut cx).is_pending());
+            let mut c_fut = pin!(semaphore.acquire(2));
+            assert!(c_fut.as_mut().poll(&mut cx).is_pending());
+
+            core::mem::drop(a);
+            let Poll::Ready(Ok(b)) = b_fut.as_mut().poll(&mut cx) else {
+                panic!("`b` should have acquired the permit");
+            };
+            let woken = wakes.get();
+
+            // Dropping the completed future must not cancel the ticket of the next waiter.
+            core::mem::drop(b_fut);
+            assert_eq!(wakes.get(), woken);
+            assert!(c_fut.as_mut().poll(&mut cx).is_pending());
+
+            core::mem::drop(b);
+            assert!(c_fut.as_mut().poll(&mut cx).is_ready());
+        }
+    }
+
+    mod dynamic {
+        use core::pin::pin;
+
+        use futures_util::poll;
+
+        use super::super::*;
+        use crate::blocking_mutex::raw::NoopRawMutex;
+
+        #[futures_test::test]
+        async fn greedy() {
+            let semaphore = GreedySemaphore::<NoopRawMutex>::new(3);
+            let dynamic = DynamicSemaphore::from(&semaphore);
+
+            let a = dynamic.acquire(2).await.unwrap();
+            assert_eq!(a.permits(), 2);
+            assert_eq!(semaphore.permits(), 1);
+            assert!(dynamic.try_acquire(2).is_none());
+
+            core::mem::drop(a);
+            assert_eq!(semaphore.permits(), 3);
+            assert_eq!(dynamic.try_acquire_all(1).unwrap().permits(), 3);
+        }
+
+        #[futures_test::test]
+        async fn fair() {
+            let semaphore = FairSemaphore::<NoopRawMutex, 1>::new(3);
+            let dynamic = DynamicSemaphore::from(&semaphore);
+
+            let a = dynamic.try_acquire(1).unwrap();
+
+            let mut b_fut = pin!(dynamic.acquire(3));
+            assert!(poll!(b_fut.as_mut()).is_pending());
+            assert!(dynamic.try_acquire(1).is_none()); // blocked behind `b`
+            assert!(matches!(dynamic.acquire(1).await, Err(WaitQueueFull)));
+
+            core::mem::drop(a);
+            let Poll::Ready(Ok(b)) = poll!(b_fut.as_mut()) else {
+                panic!("`b` should have acquired the permits");
+            };
+            assert_eq!(b.permits(), 3);
+            assert_eq!(semaphore.permits(), 0);
+
+            core::mem::drop(b);
+            assert_eq!(semaphore.permits(), 3);
+        }
     }
 }
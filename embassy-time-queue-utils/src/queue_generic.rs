@@ -98,6 +98,16 @@ impl<const QUEUE_SIZE: usize> ConstGenericQueue<QUEUE_SIZE> {
 
         next_alarm
     }
+
+    /// Returns the number of timers in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if there are no timers in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
 }
 
 #[cfg(feature = "generic-queue-8")]
@@ -145,4 +155,14 @@ impl Queue {
     pub fn next_expiration(&mut self, now: u64) -> u64 {
         self.queue.next_expiration(now)
     }
+
+    /// Returns the number of timers in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if there are no timers in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
 }
@@ -118,6 +118,22 @@ impl Queue {
         next_expiration
     }
 
+    /// Returns the number of timers in the queue.
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        let mut next = self.head.get();
+        while let Some(p) = next {
+            len += 1;
+            next = unsafe { p.as_ref() }.next.get();
+        }
+        len
+    }
+
+    /// Returns `true` if there are no timers in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.head.get().is_none()
+    }
+
     fn retain(&mut self, mut f: impl FnMut(&mut QueueItem) -> bool) {
         let mut prev = &self.head;
         while let Some(mut p) = prev.get() {
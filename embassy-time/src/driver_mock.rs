@@ -54,6 +54,9 @@ impl MockDriver {
 
     /// Advances the time by the specified [`Duration`].
     /// Calling any alarm callbacks that are due.
+    ///
+    /// Woken tasks only run once polled again, so a test advancing time typically polls its
+    /// futures after each call.
     pub fn advance(&self, duration: Duration) {
         critical_section::with(|cs| {
             let inner = &mut *self.0.borrow_ref_mut(cs);
@@ -63,6 +66,13 @@ impl MockDriver {
             inner.queue.next_expiration(inner.now.as_ticks());
         })
     }
+
+    /// Returns the number of scheduled wakes that have not expired yet.
+    ///
+    /// Wakes scheduled at or before the current time are woken immediately, and are not counted.
+    pub fn pending_alarm_count(&self) -> usize {
+        critical_section::with(|cs| self.0.borrow_ref(cs).queue.len())
+    }
 }
 
 impl Driver for MockDriver {
@@ -98,7 +108,10 @@ impl InnerMockDriver {
 
 #[cfg(test)]
 mod tests {
-    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::future::Future;
+    use core::pin::pin;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use core::task::{Context, Poll};
     use std::sync::Arc;
     use std::task::Wake;
 
@@ -110,6 +123,25 @@ mod tests {
         DRIVER.reset();
     }
 
+    /// Counts the wakes of the futures polled with it.
+    struct CountingWaker(AtomicUsize);
+
+    impl CountingWaker {
+        fn new() -> Arc<Self> {
+            Arc::new(Self(AtomicUsize::new(0)))
+        }
+
+        fn wakes(&self) -> usize {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     #[test]
     #[serial]
     fn test_advance() {
@@ -144,4 +176,79 @@ mod tests {
         driver.advance(Duration::from_secs(1));
         assert_eq!(true, CALLBACK_CALLED.load(Ordering::Relaxed));
     }
+
+    #[test]
+    #[serial]
+    fn test_pending_alarm_count() {
+        setup();
+
+        let driver = MockDriver::get();
+        let (first, second) = (CountingWaker::new(), CountingWaker::new());
+
+        driver.schedule_wake(driver.now() + 10, &first.clone().into());
+        driver.schedule_wake(driver.now() + 20, &second.clone().into());
+        assert_eq!(2, driver.pending_alarm_count());
+
+        driver.advance(Duration::from_ticks(10));
+        assert_eq!((1, 0), (first.wakes(), second.wakes()));
+        assert_eq!(1, driver.pending_alarm_count());
+
+        // A wake scheduled in the past fires right away.
+        driver.schedule_wake(driver.now() - 5, &first.clone().into());
+        assert_eq!(2, first.wakes());
+        assert_eq!(1, driver.pending_alarm_count());
+    }
+
+    #[test]
+    #[serial]
+    fn test_ticker() {
+        setup();
+
+        let driver = MockDriver::get();
+        let waker = CountingWaker::new().into();
+        let mut cx = Context::from_waker(&waker);
+        let mut ticker = crate::Ticker::every(Duration::from_millis(10));
+
+        for _ in 0..3 {
+            let mut tick = pin!(ticker.next());
+            assert!(tick.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(1, driver.pending_alarm_count());
+
+            driver.advance(Duration::from_millis(9));
+            assert!(tick.as_mut().poll(&mut cx).is_pending());
+            driver.advance(Duration::from_millis(1));
+            assert_eq!(Poll::Ready(()), tick.as_mut().poll(&mut cx));
+        }
+        assert_eq!(Instant::from_millis(30), Instant::now());
+    }
+
+    #[test]
+    #[serial]
+    fn test_with_timeout() {
+        setup();
+
+        let driver = MockDriver::get();
+        let counter = CountingWaker::new();
+        let waker = counter.clone().into();
+        let mut cx = Context::from_waker(&waker);
+
+        // The timeout expires first.
+        let mut fut = pin!(crate::with_timeout(
+            Duration::from_millis(10),
+            crate::Timer::after_millis(20)
+        ));
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        driver.advance(Duration::from_millis(10));
+        assert_eq!(1, counter.wakes());
+        assert_eq!(Poll::Ready(Err(crate::TimeoutError)), fut.as_mut().poll(&mut cx));
+
+        // The future completes first.
+        let mut fut = pin!(crate::with_timeout(
+            Duration::from_millis(10),
+            crate::Timer::after_millis(5)
+        ));
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        driver.advance(Duration::from_millis(5));
+        assert_eq!(Poll::Ready(Ok(())), fut.as_mut().poll(&mut cx));
+    }
 }
@@ -264,6 +264,37 @@ impl Ticker {
         self.expires_at = Instant::now() + after + self.duration;
     }
 
+    /// Waits for the next tick, and tells whether its deadline was missed.
+    ///
+    /// The deadline is missed when it has already passed when waiting starts, typically because
+    /// the work done since the previous tick overran the tick duration. Like [`next`](Self::next),
+    /// the ticker then ticks immediately, and keeps ticking immediately until it caught up.
+    ///
+    /// ## Cancel safety
+    /// The produced Future is cancel safe, meaning no tick is lost if the Future is dropped.
+    pub fn next_with_status(&mut self) -> impl Future<Output = TickStatus> + Send + Sync + '_ {
+        let mut first_poll = true;
+        poll_fn(move |cx| {
+            let now = Instant::now();
+            if self.expires_at <= now {
+                let status = if first_poll && self.expires_at < now {
+                    TickStatus::Missed {
+                        late_by: now - self.expires_at,
+                    }
+                } else {
+                    TickStatus::OnTime
+                };
+                let dur = self.duration;
+                self.expires_at += dur;
+                Poll::Ready(status)
+            } else {
+                first_poll = false;
+                embassy_time_driver::schedule_wake(self.expires_at.as_ticks(), cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+
     /// Waits for the next tick.
     ///
     /// ## Cancel safety
@@ -304,3 +335,184 @@ impl FusedStream for Ticker {
         false
     }
 }
+
+/// Status of a tick, returned by [`Ticker::next_with_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TickStatus {
+    /// Waiting for the tick started before its deadline.
+    OnTime,
+    /// The deadline of the tick had already passed when waiting for it started.
+    Missed {
+        /// How long after the deadline waiting started.
+        late_by: Duration,
+    },
+}
+
+impl TickStatus {
+    /// Returns whether the deadline of the tick was missed.
+    pub fn is_missed(&self) -> bool {
+        matches!(self, TickStatus::Missed { .. })
+    }
+}
+
+/// Enforces a minimum interval between operations.
+///
+/// Unlike a [`Ticker`], which ticks at a fixed rate and catches up on missed ticks, a throttle
+/// only makes sure that at least the interval passes between two operations. The first operation
+/// is allowed immediately.
+///
+/// ``` no_run
+/// use embassy_time::{Duration, Throttle};
+/// # fn send_report() {}
+///
+/// #[embassy_executor::task]
+/// async fn throttle_example() {
+///     // Don't send reports more than 10 times a second.
+///     let mut throttle = Throttle::new(Duration::from_millis(100));
+///     loop {
+///         throttle.ready().await;
+///         send_report();
+///     }
+/// }
+/// ```
+///
+/// ## Cancel safety
+/// It is safe to cancel waiting for the throttle: the interval only restarts once it completes.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Throttle {
+    interval: Duration,
+    next: Instant,
+}
+
+impl Throttle {
+    /// Creates a throttle allowing an operation every `interval` at most.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Instant::MIN,
+        }
+    }
+
+    /// Waits until the interval has passed since the previous operation, and starts a new one.
+    ///
+    /// ## Cancel safety
+    /// The produced Future is cancel safe, meaning the interval doesn't restart if the Future is dropped.
+    pub fn ready(&mut self) -> impl Future<Output = ()> + Send + Sync + '_ {
+        poll_fn(|cx| {
+            if self.try_ready() {
+                Poll::Ready(())
+            } else {
+                embassy_time_driver::schedule_wake(self.next.as_ticks(), cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+
+    /// Starts a new operation if the interval has passed since the previous one.
+    ///
+    /// Returns `false`, without waiting, if it hasn't.
+    pub fn try_ready(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next {
+            return false;
+        }
+        self.next = now + self.interval;
+        true
+    }
+
+    /// Allows the next operation immediately.
+    pub fn reset(&mut self) {
+        self.next = Instant::MIN;
+    }
+}
+
+#[cfg(all(test, feature = "mock-driver"))]
+mod tests {
+    use core::pin::pin;
+    use core::task::Waker;
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::MockDriver;
+
+    fn setup() -> &'static MockDriver {
+        let driver = MockDriver::get();
+        driver.reset();
+        driver
+    }
+
+    fn poll_once<F: Future>(fut: Pin<&mut F>) -> Poll<F::Output> {
+        fut.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    #[serial]
+    fn ticker_status() {
+        let driver = setup();
+        let mut ticker = Ticker::every(Duration::from_millis(10));
+
+        // Waiting before the deadline.
+        {
+            let mut tick = pin!(ticker.next_with_status());
+            assert!(poll_once(tick.as_mut()).is_pending());
+            driver.advance(Duration::from_millis(12));
+            assert_eq!(poll_once(tick.as_mut()), Poll::Ready(TickStatus::OnTime));
+        }
+
+        // The next deadline, at 20 ms, is missed by 15 ms.
+        driver.advance(Duration::from_millis(23));
+        assert_eq!(
+            poll_once(pin!(ticker.next_with_status())),
+            Poll::Ready(TickStatus::Missed {
+                late_by: Duration::from_millis(15)
+            })
+        );
+
+        // Catching up on the tick at 30 ms.
+        let status = poll_once(pin!(ticker.next_with_status()));
+        assert!(matches!(status, Poll::Ready(s) if s.is_missed()));
+        assert!(poll_once(pin!(ticker.next_with_status())).is_pending());
+    }
+
+    #[test]
+    #[serial]
+    fn ticker_status_cancel() {
+        let driver = setup();
+        let mut ticker = Ticker::every(Duration::from_millis(10));
+
+        // Dropping the future doesn't consume the tick.
+        assert!(poll_once(pin!(ticker.next_with_status())).is_pending());
+        driver.advance(Duration::from_millis(10));
+        assert_eq!(
+            poll_once(pin!(ticker.next_with_status())),
+            Poll::Ready(TickStatus::OnTime)
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn throttle() {
+        let driver = setup();
+        let mut throttle = Throttle::new(Duration::from_millis(10));
+
+        assert!(poll_once(pin!(throttle.ready())).is_ready());
+        driver.advance(Duration::from_millis(4));
+        assert!(!throttle.try_ready());
+
+        // Cancelling doesn't restart the interval.
+        assert!(poll_once(pin!(throttle.ready())).is_pending());
+        driver.advance(Duration::from_millis(6));
+        assert!(poll_once(pin!(throttle.ready())).is_ready());
+
+        // The interval starts at the operation, missed intervals don't accumulate.
+        driver.advance(Duration::from_millis(35));
+        assert!(throttle.try_ready());
+        assert!(!throttle.try_ready());
+
+        throttle.reset();
+        assert!(throttle.try_ready());
+    }
+}
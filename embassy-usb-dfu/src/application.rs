@@ -1,4 +1,90 @@
 //! Application part of DFU logic
+use core::cell::RefCell;
 
-pub use embassy_usb::class::dfu::app_mode::{DfuState, Handler, usb_dfu};
+use embassy_boot::BlockingFirmwareState;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+pub use embassy_usb::class::dfu::app_mode::{
+    DfuState, Handler, usb_dfu, usb_dfu_composite_with_msos, usb_dfu_with_msos,
+};
 pub use embassy_usb::class::dfu::consts::DfuAttributes;
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::Reset;
+
+/// Hands the device over to the bootloader after a detach request.
+///
+/// This implements the two phases of the DFU 1.1 flow on the application side. A detach request
+/// arms the detach. Then, once the host issues a USB reset within the detach timeout, or once the
+/// timeout elapsed if the device announced [`DfuAttributes::WILL_DETACH`], the DFU magic is written
+/// into the bootloader state and the device is reset with `RST`. The bootloader then sees the magic
+/// and re-enumerates as a device exposing the DFU mode interface, with which the host completes the
+/// update.
+///
+/// The DFU runtime interface is registered with [`handler`](Self::handler), while
+/// [`run`](Self::run) has to be polled alongside the USB device for the detach timeout to fire:
+///
+/// ```rust,ignore
+/// let detach = Detach::new(firmware_state, ResetImmediate);
+/// let mut dfu_state = DfuState::new(
+///     detach.handler(),
+///     DfuAttributes::CAN_DOWNLOAD | DfuAttributes::WILL_DETACH,
+///     Duration::from_millis(1000),
+/// );
+/// usb_dfu_composite_with_msos(&mut builder, &mut dfu_state, DEVICE_INTERFACE_GUID);
+/// let mut usb = builder.build();
+/// join(usb.run(), detach.run()).await;
+/// ```
+pub struct Detach<'d, STATE: NorFlash, RST: Reset> {
+    inner: Mutex<CriticalSectionRawMutex, RefCell<(BlockingFirmwareState<'d, STATE>, RST)>>,
+    armed: Signal<CriticalSectionRawMutex, Duration>,
+}
+
+impl<'d, STATE: NorFlash, RST: Reset> Detach<'d, STATE, RST> {
+    /// Create a detach handoff marking `state` for DFU, then resetting with `reset`.
+    pub fn new(state: BlockingFirmwareState<'d, STATE>, reset: RST) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new((state, reset))),
+            armed: Signal::new(),
+        }
+    }
+
+    /// Get the handler to pass to [`DfuState::new`].
+    pub fn handler(&self) -> DetachHandler<'_, 'd, STATE, RST> {
+        DetachHandler { detach: self }
+    }
+
+    /// Wait for a detach armed by a [`DfuAttributes::WILL_DETACH`] device, then enter DFU mode once
+    /// the detach timeout elapsed.
+    pub async fn run(&self) {
+        let timeout = self.armed.wait().await;
+        trace!("Detach armed, entering DFU in {} ms", timeout.as_millis());
+        Timer::after(timeout).await;
+        self.enter_dfu();
+    }
+
+    fn enter_dfu(&self) {
+        self.inner.lock(|inner| {
+            let (state, reset) = &mut *inner.borrow_mut();
+            unwrap!(state.mark_dfu());
+            reset.sys_reset();
+        });
+    }
+}
+
+/// Handler of the DFU runtime interface, created by [`Detach::handler`].
+pub struct DetachHandler<'a, 'd, STATE: NorFlash, RST: Reset> {
+    detach: &'a Detach<'d, STATE, RST>,
+}
+
+impl<STATE: NorFlash, RST: Reset> Handler for DetachHandler<'_, '_, STATE, RST> {
+    fn enter_dfu(&mut self) {
+        self.detach.enter_dfu();
+    }
+
+    fn detach(&mut self, timeout: Duration) {
+        self.detach.armed.signal(timeout);
+    }
+}
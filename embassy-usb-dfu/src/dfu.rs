@@ -1,12 +1,16 @@
 //! DFU bootloader part of DFU logic
-use embassy_boot::{AlignedBuffer, BlockingFirmwareUpdater, FirmwareUpdaterError};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use digest::Digest;
+use embassy_boot::{AlignedBuffer, BlockingFirmwareUpdater, Crc32, FirmwareUpdaterError};
 use embassy_usb::class::dfu::consts::{DfuAttributes, Status};
 /// Re-export DfuState from embassy-usb for convenience.
 pub use embassy_usb::class::dfu::dfu_mode::DfuState as UsbDfuState;
 use embassy_usb::class::dfu::dfu_mode::{self, DfuState};
+pub use embassy_usb::class::dfu::dfu_mode::{DfuProgress, DfuTarget, TargetHandlers};
 use embassy_usb::driver::Driver;
 use embassy_usb::{Builder, FunctionBuilder};
-use embedded_storage::nor_flash::{NorFlash, NorFlashErrorKind};
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
 
 use crate::Reset;
 
@@ -14,11 +18,26 @@ use crate::Reset;
 ///
 /// This implements the `embassy_usb::class::dfu::dfu_mode::Handler` trait,
 /// providing the firmware write logic using `BlockingFirmwareUpdater`.
-pub struct FirmwareHandler<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize> {
+///
+/// Created with [`FirmwareHandler::new_with_upload`], it also serves DFU uploads from the active
+/// partition, so `dfu-util -U` can read the running firmware back. The whole partition is uploaded,
+/// including the erased space after the image.
+pub struct FirmwareHandler<
+    'd,
+    DFU: NorFlash,
+    STATE: NorFlash,
+    RST: Reset,
+    const BLOCK_SIZE: usize,
+    ACTIVE: ReadNorFlash = NoUpload,
+> {
     updater: BlockingFirmwareUpdater<'d, DFU, STATE>,
+    active: Option<ACTIVE>,
+    progress: Option<&'d AtomicU32>,
     offset: usize,
     buf: AlignedBuffer<BLOCK_SIZE>,
     reset: RST,
+    checks: DfuChecks,
+    poll_timeout_ms: u32,
 
     #[cfg(feature = "_verify")]
     public_key: &'static [u8; 32],
@@ -28,16 +47,63 @@ impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize>
     FirmwareHandler<'d, DFU, STATE, RST, BLOCK_SIZE>
 {
     /// Create a new firmware handler.
+    ///
+    /// `BLOCK_SIZE` is the DFU transfer size (`wTransferSize`) advertised to the host. It may span
+    /// several flash pages, but must be a multiple of the write size of the DFU partition.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `BLOCK_SIZE` is not a multiple of `DFU::WRITE_SIZE`.
     pub fn new(
         updater: BlockingFirmwareUpdater<'d, DFU, STATE>,
         reset: RST,
         #[cfg(feature = "_verify")] public_key: &'static [u8; 32],
     ) -> Self {
+        check_block_size::<BLOCK_SIZE>(DFU::WRITE_SIZE);
+        Self {
+            updater,
+            active: None,
+            progress: None,
+            offset: 0,
+            buf: AlignedBuffer([0; BLOCK_SIZE]),
+            reset,
+            checks: DfuChecks::empty(),
+            poll_timeout_ms: poll_timeout_ms::<BLOCK_SIZE>(DFU::ERASE_SIZE),
+
+            #[cfg(feature = "_verify")]
+            public_key,
+        }
+    }
+}
+
+impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize, ACTIVE: ReadNorFlash>
+    FirmwareHandler<'d, DFU, STATE, RST, BLOCK_SIZE, ACTIVE>
+{
+    /// Create a new firmware handler which can upload the active partition.
+    ///
+    /// `active` is the active partition, typically an
+    /// `embassy_embedded_hal::flash::partition::BlockingPartition`. Uploads are only accepted if
+    /// the DFU attributes contain `DfuAttributes::CAN_UPLOAD`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `BLOCK_SIZE` is not a multiple of `DFU::WRITE_SIZE`.
+    pub fn new_with_upload(
+        updater: BlockingFirmwareUpdater<'d, DFU, STATE>,
+        active: ACTIVE,
+        reset: RST,
+        #[cfg(feature = "_verify")] public_key: &'static [u8; 32],
+    ) -> Self {
+        check_block_size::<BLOCK_SIZE>(DFU::WRITE_SIZE);
         Self {
             updater,
+            active: Some(active),
+            progress: None,
             offset: 0,
             buf: AlignedBuffer([0; BLOCK_SIZE]),
             reset,
+            checks: DfuChecks::empty(),
+            poll_timeout_ms: poll_timeout_ms::<BLOCK_SIZE>(DFU::ERASE_SIZE),
 
             #[cfg(feature = "_verify")]
             public_key,
@@ -45,24 +111,187 @@ impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize>
     }
 }
 
+impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize, ACTIVE: ReadNorFlash>
+    FirmwareHandler<'d, DFU, STATE, RST, BLOCK_SIZE, ACTIVE>
+{
+    /// Enable checks of the downloaded image before marking the firmware updated.
+    ///
+    /// The handler is then put in a state with [`DfuState::new`](UsbDfuState::new):
+    ///
+    /// ```rust,ignore
+    /// let handler = FirmwareHandler::<_, _, _, 4096>::new(updater, ResetImmediate).with_checks(DfuChecks::SUFFIX_CRC);
+    /// let mut state = UsbDfuState::new(handler, DfuAttributes::CAN_DOWNLOAD);
+    /// ```
+    pub fn with_checks(self, checks: DfuChecks) -> Self {
+        Self { checks, ..self }
+    }
+
+    /// Set the time the host waits after each block before asking for the status of the download,
+    /// reported as `bwPollTimeout`.
+    ///
+    /// The default is the time to erase the pages a block spans, at 85 ms per page like on nRF52.
+    /// Flash with slower erases needs a longer timeout, or the host gives up during an erase.
+    pub fn with_poll_timeout_ms(self, poll_timeout_ms: u32) -> Self {
+        Self {
+            poll_timeout_ms,
+            ..self
+        }
+    }
+
+    /// Report the download progress in `progress`.
+    ///
+    /// `progress` is set to 0 when a download starts, then to the number of bytes written to the
+    /// DFU partition after each block, so another task can read it to show the progress.
+    pub fn with_progress(self, progress: &'d AtomicU32) -> Self {
+        Self {
+            progress: Some(progress),
+            ..self
+        }
+    }
+
+    /// Check the CRC of the DFU file suffix ending the download, reading the download back from the DFU partition.
+    ///
+    /// Returns the length of the download without the suffix.
+    fn check_suffix_crc(&mut self) -> Result<usize, Status> {
+        let len = self.offset;
+        let Some(data_len) = len.checked_sub(DFU_SUFFIX_LEN) else {
+            error!("Download too short for a DFU suffix");
+            return Err(Status::ErrVerify);
+        };
+
+        let mut suffix = [0; DFU_SUFFIX_LEN];
+        let mut crc = Crc32::default();
+        let mut pos = 0;
+        while pos < len {
+            let n = (len - pos).min(BLOCK_SIZE);
+            let buf = &mut self.buf.as_mut()[..n.next_multiple_of(DFU::READ_SIZE).min(BLOCK_SIZE)];
+            self.updater
+                .read_dfu(pos as u32, buf)
+                .map_err(firmware_error_to_status)?;
+            // The CRC covers everything up to the CRC itself, at the end of the suffix.
+            crc.update(&buf[..(len - 4).saturating_sub(pos).min(n)]);
+            if pos + n > data_len {
+                let start = pos.max(data_len);
+                suffix[start - data_len..pos + n - data_len].copy_from_slice(&buf[start - pos..n]);
+            }
+            pos += n;
+        }
+        // The CRC of a DFU suffix is not inverted at the end, unlike the digest.
+        let crc = !u32::from_be_bytes(crc.finalize().into());
+
+        if &suffix[8..11] != b"UFD" || suffix[11] as usize != DFU_SUFFIX_LEN {
+            error!("Download doesn't end with a DFU suffix");
+            return Err(Status::ErrVerify);
+        }
+        let expected = u32::from_le_bytes([suffix[12], suffix[13], suffix[14], suffix[15]]);
+        if crc != expected {
+            error!("DFU suffix CRC mismatch: computed {:x}, expected {:x}", crc, expected);
+            return Err(Status::ErrVerify);
+        }
+        Ok(data_len)
+    }
+}
+
+#[cfg(feature = "defmt")]
+defmt::bitflags! {
+    /// Checks of a download by a [`FirmwareHandler`] before marking the firmware updated, see
+    /// [`FirmwareHandler::with_checks`].
+    pub struct DfuChecks: u8 {
+        /// The download ends with a DFU file suffix, as defined by the DFU 1.1 specification, whose CRC must
+        /// match the downloaded data. On mismatch, the download fails with `errVERIFY`.
+        ///
+        /// `dfu-util` removes the suffix of a `.dfu` file before downloading it, so the downloaded image must
+        /// itself end with a suffix. With signature verification, the signature goes before the suffix.
+        const SUFFIX_CRC = 0b0000_0001;
+    }
+}
+
+#[cfg(not(feature = "defmt"))]
+bitflags::bitflags! {
+    /// Checks of a download by a [`FirmwareHandler`] before marking the firmware updated, see
+    /// [`FirmwareHandler::with_checks`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DfuChecks: u8 {
+        /// The download ends with a DFU file suffix, as defined by the DFU 1.1 specification, whose CRC must
+        /// match the downloaded data. On mismatch, the download fails with `errVERIFY`.
+        ///
+        /// `dfu-util` removes the suffix of a `.dfu` file before downloading it, so the downloaded image must
+        /// itself end with a suffix. With signature verification, the signature goes before the suffix.
+        const SUFFIX_CRC = 0b0000_0001;
+    }
+}
+
+/// Length of the DFU file suffix.
+const DFU_SUFFIX_LEN: usize = 16;
+
+/// Placeholder for the active partition of a [`FirmwareHandler`] that can't upload.
+pub enum NoUpload {}
+
+impl ErrorType for NoUpload {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for NoUpload {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, _offset: u32, _bytes: &mut [u8]) -> Result<(), Self::Error> {
+        match *self {}
+    }
+
+    fn capacity(&self) -> usize {
+        match *self {}
+    }
+}
+
+/// Worst-case time to erase a flash page, that of an nRF52.
+const PAGE_ERASE_TIME_MS: u32 = 85;
+
+/// Default poll timeout: the time to erase the pages a block of `BLOCK_SIZE` bytes spans.
+fn poll_timeout_ms<const BLOCK_SIZE: usize>(erase_size: usize) -> u32 {
+    BLOCK_SIZE.div_ceil(erase_size) as u32 * PAGE_ERASE_TIME_MS
+}
+
+/// Check that blocks of `BLOCK_SIZE` bytes can be written to a flash with the given write size.
+fn check_block_size<const BLOCK_SIZE: usize>(write_size: usize) {
+    assert!(
+        BLOCK_SIZE > 0 && BLOCK_SIZE.is_multiple_of(write_size),
+        "DFU block size must be a multiple of the flash write size"
+    );
+}
+
+/// Read up to `buf.len()` bytes at `offset` of `flash` for an upload, stopping at its end.
+fn upload_from<F: ReadNorFlash>(flash: &mut F, offset: usize, buf: &mut [u8]) -> Result<usize, Status> {
+    let len = buf.len().min(flash.capacity().saturating_sub(offset));
+    debug!("Reading {} bytes at {}", len, offset);
+    flash.read(offset as u32, &mut buf[..len]).map_err(|e| match e.kind() {
+        NorFlashErrorKind::OutOfBounds => Status::ErrAddress,
+        _ => Status::ErrUnknown,
+    })?;
+    Ok(len)
+}
+
+/// Map an updater error to a DFU status.
 fn firmware_error_to_status(e: FirmwareUpdaterError) -> Status {
     match e {
-        FirmwareUpdaterError::Flash(e) => match e {
-            NorFlashErrorKind::NotAligned => Status::ErrWrite,
-            NorFlashErrorKind::OutOfBounds => Status::ErrAddress,
-            _ => Status::ErrUnknown,
-        },
+        FirmwareUpdaterError::Flash(NorFlashErrorKind::OutOfBounds)
+        | FirmwareUpdaterError::Erase(NorFlashErrorKind::OutOfBounds) => Status::ErrAddress,
+        FirmwareUpdaterError::Flash(_) => Status::ErrWrite,
+        FirmwareUpdaterError::Erase(_) => Status::ErrErase,
         FirmwareUpdaterError::Signature(_) => Status::ErrVerify,
+        FirmwareUpdaterError::DigestMismatch => Status::ErrVerify,
         FirmwareUpdaterError::BadState => Status::ErrUnknown,
     }
 }
 
-impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize> dfu_mode::Handler
-    for FirmwareHandler<'d, DFU, STATE, RST, BLOCK_SIZE>
+impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize, ACTIVE: ReadNorFlash> dfu_mode::Handler
+    for FirmwareHandler<'d, DFU, STATE, RST, BLOCK_SIZE, ACTIVE>
 {
     fn start(&mut self) {
         info!("Download starting");
         self.offset = 0;
+        if let Some(progress) = self.progress {
+            progress.store(0, Ordering::Relaxed);
+        }
     }
 
     fn write(&mut self, data: &[u8]) -> Result<(), Status> {
@@ -71,13 +300,20 @@ impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize> df
             return Err(Status::ErrUnknown);
         }
 
+        // Pad the last block to the write size, instead of writing what is left of the previous
+        // block after it. Blocks spanning several pages are erased page by page by the updater.
+        let len = data.len().next_multiple_of(DFU::WRITE_SIZE);
         debug!("Copying {} bytes to buffer", data.len());
         self.buf.as_mut()[..data.len()].copy_from_slice(data);
+        self.buf.as_mut()[data.len()..len].fill(0xFF);
 
         debug!("Writing {} bytes at {}", data.len(), self.offset);
-        match self.updater.write_firmware(self.offset, self.buf.as_ref()) {
+        match self.updater.write_firmware(self.offset, &self.buf.as_ref()[..len]) {
             Ok(_) => {
                 self.offset += data.len();
+                if let Some(progress) = self.progress {
+                    progress.store(self.offset as u32, Ordering::Relaxed);
+                }
                 Ok(())
             }
             Err(e) => {
@@ -90,12 +326,18 @@ impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize> df
     fn finish(&mut self) -> Result<(), Status> {
         debug!("Receiving final transfer");
 
+        let len = if self.checks.contains(DfuChecks::SUFFIX_CRC) {
+            self.check_suffix_crc()?
+        } else {
+            self.offset
+        };
+
         #[cfg(feature = "_verify")]
         let update_res: Result<(), FirmwareUpdaterError> = {
             const SIGNATURE_LEN: usize = 64;
 
             let mut signature = [0; SIGNATURE_LEN];
-            let update_len = (self.offset - SIGNATURE_LEN) as u32;
+            let update_len = (len - SIGNATURE_LEN) as u32;
 
             self.updater.read_dfu(update_len, &mut signature).and_then(|_| {
                 self.updater
@@ -108,7 +350,7 @@ impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize> df
 
         match update_res {
             Ok(_) => {
-                info!("Update complete");
+                info!("Update complete, {} bytes", len);
                 Ok(())
             }
             Err(e) => {
@@ -121,11 +363,160 @@ impl<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize> df
     fn system_reset(&mut self) {
         self.reset.sys_reset()
     }
+
+    fn abort(&mut self) {
+        if self.offset > 0 {
+            info!("Download aborted after {} bytes", self.offset);
+        }
+        self.offset = 0;
+        if let Some(progress) = self.progress {
+            progress.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn poll_timeout_ms(&self) -> u32 {
+        self.poll_timeout_ms
+    }
+
+    fn upload(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, Status> {
+        match &mut self.active {
+            Some(active) => upload_from(active, offset, buf),
+            None => {
+                error!("Upload needs the active partition");
+                Err(Status::ErrStalledPkt)
+            }
+        }
+    }
+}
+
+/// Handler writing downloads to a plain flash partition, such as a partition holding resources.
+///
+/// Unlike [`FirmwareHandler`], no bootloader state is involved: the partition is erased as the download
+/// progresses and the data is written as is. Uploads read the whole partition back. Downloads larger than the partition are rejected with
+/// `errADDRESS`. To expose only part of a flash, wrap it in an
+/// `embassy_embedded_hal::flash::partition::BlockingPartition`.
+///
+/// Combine it with a [`FirmwareHandler`] to expose several targets, selectable with `dfu-util -a N`:
+///
+/// ```rust,ignore
+/// let mut firmware = FirmwareHandler::<_, _, _, 4096>::new(updater, ResetImmediate);
+/// let mut resources = PartitionHandler::<_, _, 4096>::new(resources_partition, ResetImmediate);
+/// let mut state = UsbDfuState::new_multi(
+///     TargetHandlers::new([&mut firmware, &mut resources]),
+///     [
+///         DfuTarget::new("Application", DfuAttributes::CAN_DOWNLOAD),
+///         DfuTarget::new("Resources", DfuAttributes::CAN_DOWNLOAD),
+///     ],
+/// );
+/// embassy_usb::class::dfu::dfu_mode::usb_dfu(&mut builder, &mut state, 4096, |_| {});
+/// ```
+pub struct PartitionHandler<F: NorFlash, RST: Reset, const BLOCK_SIZE: usize> {
+    flash: F,
+    offset: usize,
+    erased: usize,
+    buf: AlignedBuffer<BLOCK_SIZE>,
+    reset: RST,
+    poll_timeout_ms: u32,
+}
+
+impl<F: NorFlash, RST: Reset, const BLOCK_SIZE: usize> PartitionHandler<F, RST, BLOCK_SIZE> {
+    /// Create a new partition handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `BLOCK_SIZE` is not a multiple of `F::WRITE_SIZE`.
+    pub fn new(flash: F, reset: RST) -> Self {
+        check_block_size::<BLOCK_SIZE>(F::WRITE_SIZE);
+        Self {
+            flash,
+            offset: 0,
+            erased: 0,
+            buf: AlignedBuffer([0; BLOCK_SIZE]),
+            reset,
+            poll_timeout_ms: poll_timeout_ms::<BLOCK_SIZE>(F::ERASE_SIZE),
+        }
+    }
+
+    /// Set the time the host waits after each block before asking for the status of the download,
+    /// reported as `bwPollTimeout`.
+    ///
+    /// The default is the time to erase the pages a block spans, at 85 ms per page like on nRF52.
+    pub fn with_poll_timeout_ms(self, poll_timeout_ms: u32) -> Self {
+        Self {
+            poll_timeout_ms,
+            ..self
+        }
+    }
+}
+
+impl<F: NorFlash, RST: Reset, const BLOCK_SIZE: usize> dfu_mode::Handler for PartitionHandler<F, RST, BLOCK_SIZE> {
+    fn start(&mut self) {
+        info!("Download starting");
+        self.offset = 0;
+        self.erased = 0;
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Status> {
+        if data.len() > BLOCK_SIZE {
+            error!("USB data len exceeded block size");
+            return Err(Status::ErrUnknown);
+        }
+
+        let end = self.offset + data.len();
+        if end > self.flash.capacity() {
+            error!("Download exceeds partition size {}", self.flash.capacity());
+            return Err(Status::ErrAddress);
+        }
+
+        if end > self.erased {
+            let erase_end = end.next_multiple_of(F::ERASE_SIZE).min(self.flash.capacity());
+            debug!("Erasing {} to {}", self.erased, erase_end);
+            self.flash.erase(self.erased as u32, erase_end as u32).map_err(|_| {
+                error!("Error erasing {} to {}", self.erased, erase_end);
+                Status::ErrErase
+            })?;
+            self.erased = erase_end;
+        }
+
+        // Pad the last block to the write size.
+        let len = data.len().next_multiple_of(F::WRITE_SIZE);
+        self.buf.as_mut()[..data.len()].copy_from_slice(data);
+        self.buf.as_mut()[data.len()..len].fill(0xFF);
+
+        debug!("Writing {} bytes at {}", data.len(), self.offset);
+        self.flash
+            .write(self.offset as u32, &self.buf.as_ref()[..len])
+            .map_err(|_| {
+                error!("Error writing {} bytes at {}", len, self.offset);
+                Status::ErrWrite
+            })?;
+        self.offset = end;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Status> {
+        info!("Download complete, {} bytes", self.offset);
+        Ok(())
+    }
+
+    fn system_reset(&mut self) {
+        self.reset.sys_reset()
+    }
+
+    fn poll_timeout_ms(&self) -> u32 {
+        self.poll_timeout_ms
+    }
+
+    fn upload(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, Status> {
+        upload_from(&mut self.flash, offset, buf)
+    }
 }
 
 /// Convenience type alias for the DFU state with firmware handler.
-pub type State<'d, DFU, STATE, RST, const BLOCK_SIZE: usize> =
-    DfuState<FirmwareHandler<'d, DFU, STATE, RST, BLOCK_SIZE>>;
+///
+/// `P` is the observer of the download progress, see [`UsbDfuState::with_progress`].
+pub type State<'d, DFU, STATE, RST, const BLOCK_SIZE: usize, ACTIVE = NoUpload, P = ()> =
+    DfuState<FirmwareHandler<'d, DFU, STATE, RST, BLOCK_SIZE, ACTIVE>, 1, P>;
 
 /// Create a new DFU state instance.
 ///
@@ -146,17 +537,274 @@ pub fn new_state<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZ
     DfuState::new(handler, attrs)
 }
 
+/// Create a new DFU state instance reporting the download progress.
+///
+/// Same as [`new_state`], with the number of bytes written stored in `progress`. See
+/// [`FirmwareHandler::with_progress`].
+pub fn new_state_with_progress<'d, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize>(
+    updater: BlockingFirmwareUpdater<'d, DFU, STATE>,
+    attrs: DfuAttributes,
+    reset: RST,
+    progress: &'d AtomicU32,
+    #[cfg(feature = "_verify")] public_key: &'static [u8; 32],
+) -> State<'d, DFU, STATE, RST, BLOCK_SIZE> {
+    let handler = FirmwareHandler::new(
+        updater,
+        reset,
+        #[cfg(feature = "_verify")]
+        public_key,
+    )
+    .with_progress(progress);
+    DfuState::new(handler, attrs)
+}
+
+/// Create a new DFU state instance which can upload the active partition.
+///
+/// Same as [`new_state`], with uploads served from `active` if `attrs` contains
+/// `DfuAttributes::CAN_UPLOAD`. See [`FirmwareHandler::new_with_upload`].
+pub fn new_state_with_upload<
+    'd,
+    DFU: NorFlash,
+    STATE: NorFlash,
+    RST: Reset,
+    const BLOCK_SIZE: usize,
+    ACTIVE: ReadNorFlash,
+>(
+    updater: BlockingFirmwareUpdater<'d, DFU, STATE>,
+    active: ACTIVE,
+    attrs: DfuAttributes,
+    reset: RST,
+    #[cfg(feature = "_verify")] public_key: &'static [u8; 32],
+) -> State<'d, DFU, STATE, RST, BLOCK_SIZE, ACTIVE> {
+    let handler = FirmwareHandler::new_with_upload(
+        updater,
+        active,
+        reset,
+        #[cfg(feature = "_verify")]
+        public_key,
+    );
+    DfuState::new(handler, attrs)
+}
+
 /// An implementation of the USB DFU 1.1 protocol
 ///
-/// This function will add a DFU interface descriptor to the provided Builder, and register the provided Control as a handler for the USB device
+/// This function will add a DFU interface descriptor to the provided Builder, and register the provided state as a handler for the USB device
 /// The handler is responsive to DFU GetState, GetStatus, Abort, and ClrStatus commands, as well as Download if configured by the user.
 ///
+/// `BLOCK_SIZE` is advertised to the host as `wTransferSize`, the size of the blocks it downloads. Larger blocks make
+/// updates faster, but the control buffer of the builder must be able to hold a whole block.
+///
 /// Once the host has initiated a DFU download operation, the chunks sent by the host will be written to the DFU partition.
 /// Once the final sync in the manifestation phase has been received, the handler will trigger a system reset to swap the new firmware.
-pub fn usb_dfu<'d, D: Driver<'d>, DFU: NorFlash, STATE: NorFlash, RST: Reset, const BLOCK_SIZE: usize>(
+///
+/// To show the progress of downloads on the device, e.g. with an LED, add an observer to the state with
+/// [`UsbDfuState::with_progress`]:
+///
+/// ```rust,ignore
+/// struct Blink<'a>(Output<'a>);
+///
+/// impl DfuProgress for Blink<'_> {
+///     fn on_download(&mut self, _bytes: usize, _blocks: usize) {
+///         self.0.toggle();
+///     }
+/// }
+///
+/// let mut state = new_state::<_, _, _, 4096>(updater, DfuAttributes::CAN_DOWNLOAD, ResetImmediate).with_progress(Blink(led));
+/// usb_dfu(&mut builder, &mut state, |_| {});
+/// ```
+pub fn usb_dfu<
+    'd,
+    D: Driver<'d>,
+    DFU: NorFlash,
+    STATE: NorFlash,
+    RST: Reset,
+    const BLOCK_SIZE: usize,
+    ACTIVE: ReadNorFlash,
+    P: DfuProgress,
+>(
     builder: &mut Builder<'d, D>,
-    state: &'d mut State<'d, DFU, STATE, RST, BLOCK_SIZE>,
+    state: &'d mut State<'d, DFU, STATE, RST, BLOCK_SIZE, ACTIVE, P>,
     func_modifier: impl Fn(&mut FunctionBuilder<'_, 'd, D>),
 ) {
     dfu_mode::usb_dfu(builder, state, BLOCK_SIZE, func_modifier);
 }
+
+/// An implementation of the USB DFU 1.1 protocol, with MS OS 2.0 descriptors for WinUSB.
+///
+/// Same as [`usb_dfu`], but also adds the MS OS 2.0 descriptors that make Windows bind the WinUSB driver
+/// to the DFU interface automatically. They are only added if the builder was created with a non-empty
+/// MS OS descriptor buffer. See [`dfu_mode::usb_dfu_with_msos`] for details.
+pub fn usb_dfu_with_msos<
+    'd,
+    D: Driver<'d>,
+    DFU: NorFlash,
+    STATE: NorFlash,
+    RST: Reset,
+    const BLOCK_SIZE: usize,
+    ACTIVE: ReadNorFlash,
+    P: DfuProgress,
+>(
+    builder: &mut Builder<'d, D>,
+    state: &'d mut State<'d, DFU, STATE, RST, BLOCK_SIZE, ACTIVE, P>,
+    guid: &str,
+) {
+    dfu_mode::usb_dfu_with_msos(builder, state, BLOCK_SIZE, guid);
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_boot::FirmwareUpdaterConfig;
+
+    use super::*;
+
+    /// A flash of `N` bytes, of which only reads are used.
+    struct Flash<const N: usize>([u8; N]);
+
+    impl<const N: usize> ErrorType for Flash<N> {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl<const N: usize> ReadNorFlash for Flash<N> {
+        const READ_SIZE: usize = 4;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(
+                self.0
+                    .get(offset..offset + bytes.len())
+                    .ok_or(NorFlashErrorKind::OutOfBounds)?,
+            );
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            N
+        }
+    }
+
+    impl<const N: usize> NorFlash for Flash<N> {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4;
+
+        fn erase(&mut self, _from: u32, _to: u32) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+
+        fn write(&mut self, _offset: u32, _bytes: &[u8]) -> Result<(), Self::Error> {
+            unimplemented!()
+        }
+    }
+
+    struct NoReset;
+
+    impl Reset for NoReset {
+        fn sys_reset(&self) {}
+    }
+
+    /// `embassy` with the suffix `dfu-suffix -a` adds by default: any device, DFU 1.0.
+    const IMAGE: [u8; 23] = [
+        b'e', b'm', b'b', b'a', b's', b's', b'y', // Image
+        0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // bcdDevice, idProduct, idVendor
+        0x00, 0x01, b'U', b'F', b'D', 16, // bcdDFU, ucDfuSignature, bLength
+        0x24, 0x65, 0x7E, 0xE4, // dwCRC
+    ];
+
+    /// Check the suffix of `image`, downloaded to the DFU partition.
+    fn check(image: &[u8]) -> Result<usize, Status> {
+        let mut dfu = Flash([0xFF; 32]);
+        dfu.0[..image.len()].copy_from_slice(image);
+        let mut aligned = [0; 4];
+        let updater = BlockingFirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu,
+                state: Flash([0xFF; 4]),
+            },
+            &mut aligned,
+        );
+        // A block smaller than the image, so it is read back in several blocks.
+        let mut handler = FirmwareHandler::<_, _, _, 8>::new(updater, NoReset);
+        handler.offset = image.len();
+        handler.check_suffix_crc()
+    }
+
+    #[test]
+    fn suffix_crc() {
+        assert!(matches!(check(&IMAGE), Ok(7)));
+
+        let mut image = IMAGE;
+        image[3] ^= 1;
+        assert!(matches!(check(&image), Err(Status::ErrVerify)));
+
+        let mut image = IMAGE;
+        image[22] ^= 1;
+        assert!(matches!(check(&image), Err(Status::ErrVerify)));
+
+        let mut image = IMAGE;
+        image[15] = b'X';
+        assert!(matches!(check(&image), Err(Status::ErrVerify)));
+
+        // Just a suffix, for an empty image.
+        assert!(matches!(check(&IMAGE[7..]), Err(Status::ErrVerify)));
+        assert!(matches!(check(&IMAGE[..15]), Err(Status::ErrVerify)));
+    }
+
+    /// Read the DFU_GETSTATUS response of the DFU interface.
+    fn get_status<H: dfu_mode::Handler>(state: &mut DfuState<H>) -> [u8; 6] {
+        use embassy_usb::Handler as _;
+        use embassy_usb::control::{Recipient, Request, RequestType};
+        use embassy_usb::driver::Direction;
+
+        let req = Request {
+            direction: Direction::In,
+            request_type: RequestType::Class,
+            recipient: Recipient::Interface,
+            request: 3, // DFU_GETSTATUS
+            value: 0,
+            index: 0,
+            length: 6,
+        };
+        let mut buf = [0; 6];
+        state.control_in(req, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn poll_timeout() {
+        // Blocks of 8 bytes span two pages of 4 bytes: 170 ms, in dfuIDLE.
+        let handler = PartitionHandler::<_, _, 8>::new(Flash([0xFF; 32]), NoReset);
+        let mut state = DfuState::new(handler, DfuAttributes::CAN_DOWNLOAD);
+        assert_eq!(get_status(&mut state), [0, 170, 0, 0, 2, 0]);
+
+        let handler = PartitionHandler::<_, _, 8>::new(Flash([0xFF; 32]), NoReset).with_poll_timeout_ms(0x12345);
+        let mut state = DfuState::new(handler, DfuAttributes::CAN_DOWNLOAD);
+        assert_eq!(get_status(&mut state), [0, 0x45, 0x23, 0x01, 2, 0]);
+
+        let mut aligned = [0; 4];
+        let updater = BlockingFirmwareUpdater::new(
+            FirmwareUpdaterConfig {
+                dfu: Flash([0xFF; 32]),
+                state: Flash([0xFF; 4]),
+            },
+            &mut aligned,
+        );
+        let handler = FirmwareHandler::<_, _, _, 4>::new(updater, NoReset);
+        let mut state = DfuState::new(handler, DfuAttributes::CAN_DOWNLOAD);
+        assert_eq!(get_status(&mut state), [0, 85, 0, 0, 2, 0]);
+    }
+
+    #[test]
+    fn maps_erase_and_write_failures() {
+        assert!(matches!(
+            firmware_error_to_status(FirmwareUpdaterError::Erase(NorFlashErrorKind::Other)),
+            Status::ErrErase
+        ));
+        assert!(matches!(
+            firmware_error_to_status(FirmwareUpdaterError::Flash(NorFlashErrorKind::Other)),
+            Status::ErrWrite
+        ));
+        assert!(matches!(
+            firmware_error_to_status(FirmwareUpdaterError::Erase(NorFlashErrorKind::OutOfBounds)),
+            Status::ErrAddress
+        ));
+    }
+}
@@ -3,6 +3,8 @@
 #![warn(missing_docs)]
 mod fmt;
 
+use core::cell::Cell;
+
 /// Re-export DFU constants from embassy-usb.
 pub mod consts {
     pub use embassy_usb::class::dfu::consts::*;
@@ -21,14 +23,49 @@ pub use self::application::*;
 /// Provides a platform-agnostic interface for initiating a system reset.
 ///
 /// This crate exposes `ResetImmediate` when compiled with cortex-m or esp32c3 support, which immediately issues a
-/// reset request without interfacing with any other peripherals.
+/// reset request without interfacing with any other peripherals. With cortex-m support, [`ResetDelay`] waits before
+/// resetting. [`ResetWithHook`] runs a closure before any other reset, for example to save state or to notify a
+/// supervisor.
 ///
 /// If alternate behaviour is desired, a custom implementation of Reset can be provided as an argument to the usb_dfu function.
 pub trait Reset {
     /// Reset the device.
+    ///
+    /// This is called once the DFU operation is complete, and should not return.
     fn sys_reset(&self);
 }
 
+/// Reset after running a closure.
+///
+/// The closure runs once, then the device is reset with the wrapped [`Reset`]:
+///
+/// ```rust,ignore
+/// let reset = ResetWithHook::new(move || supervisor_pin.set_low(), ResetDelay::<10>);
+/// ```
+pub struct ResetWithHook<F: FnOnce(), R: Reset> {
+    hook: Cell<Option<F>>,
+    reset: R,
+}
+
+impl<F: FnOnce(), R: Reset> ResetWithHook<F, R> {
+    /// Create a reset running `hook`, then resetting with `reset`.
+    pub fn new(hook: F, reset: R) -> Self {
+        Self {
+            hook: Cell::new(Some(hook)),
+            reset,
+        }
+    }
+}
+
+impl<F: FnOnce(), R: Reset> Reset for ResetWithHook<F, R> {
+    fn sys_reset(&self) {
+        if let Some(hook) = self.hook.take() {
+            hook();
+        }
+        self.reset.sys_reset()
+    }
+}
+
 /// Reset immediately.
 #[cfg(feature = "esp32c3-hal")]
 pub struct ResetImmediate;
@@ -51,3 +88,61 @@ impl Reset for ResetImmediate {
         cortex_m::peripheral::SCB::sys_reset()
     }
 }
+
+/// Reset after waiting `MS` milliseconds, for example for a supervisor or a host to notice the end of the update.
+///
+/// The wait blocks, so it needs an `embassy-time` driver.
+#[cfg(feature = "cortex-m")]
+pub struct ResetDelay<const MS: u32>;
+
+#[cfg(feature = "cortex-m")]
+impl<const MS: u32> Reset for ResetDelay<MS> {
+    fn sys_reset(&self) {
+        embassy_time::block_for(embassy_time::Duration::from_millis(MS as u64));
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the step at which it reset, without resetting.
+    struct RecordReset<'a> {
+        step: &'a Cell<u32>,
+        at: &'a Cell<Option<u32>>,
+    }
+
+    impl Reset for RecordReset<'_> {
+        fn sys_reset(&self) {
+            self.at.set(Some(self.step.get()));
+            self.step.set(self.step.get() + 1);
+        }
+    }
+
+    #[test]
+    fn reset_with_hook_runs_hook_first() {
+        let step = Cell::new(0);
+        let hook_at = Cell::new(None);
+        let reset_at = Cell::new(None);
+        let reset = ResetWithHook::new(
+            || {
+                hook_at.set(Some(step.get()));
+                step.set(step.get() + 1);
+            },
+            RecordReset {
+                step: &step,
+                at: &reset_at,
+            },
+        );
+
+        reset.sys_reset();
+        assert_eq!(hook_at.get(), Some(0));
+        assert_eq!(reset_at.get(), Some(1));
+
+        // The hook only runs once if the reset is tried again.
+        reset.sys_reset();
+        assert_eq!(hook_at.get(), Some(0));
+        assert_eq!(reset_at.get(), Some(2));
+    }
+}
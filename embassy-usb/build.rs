@@ -8,6 +8,8 @@ static CONFIGS: &[(&str, usize)] = &[
     // Generated by gen_config.py. DO NOT EDIT.
     ("MAX_INTERFACE_COUNT", 4),
     ("MAX_HANDLER_COUNT", 4),
+    ("MAX_STRING_PROVIDER_COUNT", 2),
+    ("MAX_ENDPOINT_HALT_COUNT", 2),
     // END AUTOGENERATED CONFIG FEATURES
 ];
 
@@ -29,6 +29,8 @@ def feature(name, default, min, max, pow2=None):
 
 feature("max_interface_count", default=4, min=1, max=8)
 feature("max_handler_count", default=4, min=1, max=8)
+feature("max_string_provider_count", default=2, min=1, max=8)
+feature("max_endpoint_halt_count", default=2, min=1, max=8)
 
 # ========= Update Cargo.toml
 
@@ -1,11 +1,14 @@
 use heapless::Vec;
 
-use crate::config::MAX_HANDLER_COUNT;
+use crate::config::{MAX_ENDPOINT_HALT_COUNT, MAX_HANDLER_COUNT, MAX_STRING_PROVIDER_COUNT};
 use crate::descriptor::{BosWriter, DescriptorWriter, SynchronizationType, UsageType};
 use crate::driver::{Driver, Endpoint, EndpointAddress, EndpointInfo, EndpointType};
 use crate::msos::{DeviceLevelDescriptor, FunctionLevelDescriptor, MsOsDescriptorWriter};
 use crate::types::{InterfaceNumber, StringIndex};
-use crate::{Handler, Interface, MAX_INTERFACE_COUNT, STRING_INDEX_CUSTOM_START, UsbDevice};
+use crate::{
+    EndpointHalt, Handler, Interface, MAX_INTERFACE_COUNT, STRING_INDEX_CUSTOM_START, STRING_INDEX_SERIAL_NUMBER,
+    StringProvider, UsbDevice,
+};
 
 #[derive(Debug, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -82,6 +85,8 @@ pub struct Config<'a> {
 
     /// Whether the device supports remotely waking up the host is requested.
     ///
+    /// If `false`, the host can't enable remote wakeup, and [`UsbDevice::remote_wakeup`] always fails.
+    ///
     /// Default: `false`
     pub supports_remote_wakeup: bool,
 
@@ -146,6 +151,8 @@ impl<'a> Config<'a> {
 pub struct Builder<'d, D: Driver<'d>> {
     config: Config<'d>,
     handlers: Vec<&'d mut dyn Handler, MAX_HANDLER_COUNT>,
+    string_providers: Vec<(u8, &'d mut StringProvider<'d>), MAX_STRING_PROVIDER_COUNT>,
+    endpoint_halts: Vec<(EndpointAddress, &'d EndpointHalt), MAX_ENDPOINT_HALT_COUNT>,
     interfaces: Vec<Interface, MAX_INTERFACE_COUNT>,
     control_buf: &'d mut [u8],
 
@@ -202,6 +209,8 @@ impl<'d, D: Driver<'d>> Builder<'d, D> {
             config,
             interfaces: Vec::new(),
             handlers: Vec::new(),
+            string_providers: Vec::new(),
+            endpoint_halts: Vec::new(),
             control_buf,
             next_string_index: STRING_INDEX_CUSTOM_START,
 
@@ -229,6 +238,8 @@ impl<'d, D: Driver<'d>> Builder<'d, D> {
             self.driver,
             self.config,
             self.handlers,
+            self.string_providers,
+            self.endpoint_halts,
             self.config_descriptor.into_buf(),
             self.bos_descriptor.writer.into_buf(),
             msos_descriptor,
@@ -280,6 +291,17 @@ impl<'d, D: Driver<'d>> Builder<'d, D> {
         );
     }
 
+    /// Let a class halt the endpoint `ep_addr` through `halt`.
+    ///
+    /// See [`EndpointHalt`].
+    pub fn endpoint_halt(&mut self, ep_addr: EndpointAddress, halt: &'d EndpointHalt) {
+        assert!(
+            self.endpoint_halts.push((ep_addr, halt)).is_ok(),
+            "embassy-usb: endpoint halt list full. Increase the `max_endpoint_halt_count` compile-time setting. Current value: {}",
+            MAX_ENDPOINT_HALT_COUNT
+        );
+    }
+
     /// Allocates a new string index.
     pub fn string(&mut self) -> StringIndex {
         let index = self.next_string_index;
@@ -287,6 +309,41 @@ impl<'d, D: Driver<'d>> Builder<'d, D> {
         StringIndex::new(index)
     }
 
+    /// Allocates a new string index whose string descriptor is produced at runtime by `provider`.
+    ///
+    /// `provider` is called on every GET_DESCRIPTOR request for the string, with the requested language ID
+    /// and a scratch buffer carved out of the control buffer (roughly a third of it, at most 126 bytes).
+    /// It can format the string into the scratch buffer and return it, or return any other string. If the
+    /// returned string does not fit in the control buffer, the request is rejected.
+    pub fn string_with(&mut self, provider: &'d mut StringProvider<'d>) -> StringIndex {
+        let index = self.string();
+        self.add_string_provider(index.0, provider);
+        index
+    }
+
+    /// Produce the serial number string descriptor at runtime with `provider`.
+    ///
+    /// This is an alternative to [`Config::serial_number`] for serial numbers only known at runtime,
+    /// such as ones derived from a hardware device ID. See [`Builder::string_with`] for how `provider`
+    /// is called.
+    ///
+    /// Panics if [`Config::serial_number`] is set.
+    pub fn serial_number_with(&mut self, provider: &'d mut StringProvider<'d>) {
+        assert!(
+            self.config.serial_number.is_none(),
+            "serial_number_with can't be used together with Config::serial_number"
+        );
+        self.add_string_provider(STRING_INDEX_SERIAL_NUMBER, provider);
+    }
+
+    fn add_string_provider(&mut self, index: u8, provider: &'d mut StringProvider<'d>) {
+        assert!(
+            self.string_providers.push((index, provider)).is_ok(),
+            "embassy-usb: string provider list full. Increase the `max_string_provider_count` compile-time setting. Current value: {}",
+            MAX_STRING_PROVIDER_COUNT
+        );
+    }
+
     /// Add an MS OS 2.0 Descriptor Set.
     ///
     /// Panics if called more than once.
@@ -18,6 +18,17 @@ pub trait Handler {
     /// USB reset within the timeout period). The implementation should mark the
     /// device for DFU mode and perform a system reset.
     fn enter_dfu(&mut self);
+
+    /// Called when a detach request is received and the device announced
+    /// [`DfuAttributes::WILL_DETACH`], so the host doesn't issue a USB reset.
+    ///
+    /// This is called while the detach request is being handled, before it is acknowledged. The
+    /// implementation may enter DFU mode once `timeout`, the detach timeout, has elapsed, which lets
+    /// the request complete first. The default implementation enters DFU mode right away.
+    fn detach(&mut self, timeout: Duration) {
+        let _ = timeout;
+        self.enter_dfu();
+    }
 }
 
 /// Internal state for the DFU class
@@ -75,8 +86,8 @@ impl<H: Handler> crate::Handler for DfuState<H> {
                 self.state = State::AppDetach;
                 self.detach_start = Some(Instant::now());
                 if self.attrs.contains(DfuAttributes::WILL_DETACH) {
-                    trace!("WILL_DETACH set, performing reset");
-                    self.handler.enter_dfu();
+                    trace!("WILL_DETACH set, detaching");
+                    self.handler.detach(self.timeout);
                 } else {
                     trace!("Awaiting USB reset");
                 }
@@ -125,28 +136,70 @@ pub fn usb_dfu<'d, D: Driver<'d>, H: Handler>(
     state: &'d mut DfuState<H>,
     func_modifier: impl Fn(&mut FunctionBuilder<'_, 'd, D>),
 ) {
-    let mut func = builder.function(0x00, 0x00, 0x00);
+    let mut func = builder.function(USB_CLASS_APPN_SPEC, APPN_SPEC_SUBCLASS_DFU, DFU_PROTOCOL_RT);
 
     // Here we give users the opportunity to add their own function level MSOS headers for instance.
     // This is useful when DFU functionality is part of a composite USB device.
     func_modifier(&mut func);
 
-    let timeout_ms = state.timeout.as_millis() as u16;
     let mut iface = func.interface();
     let mut alt = iface.alt_setting(USB_CLASS_APPN_SPEC, APPN_SPEC_SUBCLASS_DFU, DFU_PROTOCOL_RT, None);
     alt.descriptor(
         DESC_DFU_FUNCTIONAL,
-        &[
-            state.attrs.bits(),
-            (timeout_ms & 0xff) as u8,
-            ((timeout_ms >> 8) & 0xff) as u8,
-            0x40,
-            0x00, // 64B control buffer size for application side
-            0x10,
-            0x01, // DFU 1.1
-        ],
+        // 64B control buffer size for application side
+        &super::functional_descriptor(&state.attrs, state.timeout, 64),
     );
 
     drop(func);
     builder.handler(state);
 }
+
+/// An implementation of the USB DFU 1.1 runtime protocol, with MS OS 2.0 descriptors for WinUSB.
+///
+/// Same as [`usb_dfu`], but also adds the MS OS 2.0 descriptors that make Windows bind the WinUSB
+/// driver to the DFU interface automatically, so users don't have to do this manually using a tool
+/// like Zadig. `guid` is the device interface GUID clients on Windows use to find the device, such as
+/// `"{EAA9A5DC-30BA-44BC-9232-606CDC875321}"`.
+///
+/// The descriptors are only added if the builder was created with a non-empty MS OS descriptor buffer.
+/// If no MS OS descriptor set was started yet, the set header and the device level descriptors are
+/// added as well, using [`MSOS_VENDOR_CODE`](super::MSOS_VENDOR_CODE).
+pub fn usb_dfu_with_msos<'d, D: Driver<'d>, H: Handler>(
+    builder: &mut Builder<'d, D>,
+    state: &'d mut DfuState<H>,
+    guid: &str,
+) {
+    let msos = super::winusb_device_msos(builder, guid);
+    usb_dfu(builder, state, |func| {
+        if msos {
+            super::winusb_function_msos(func, guid);
+        }
+    });
+}
+
+/// An implementation of the USB DFU 1.1 runtime protocol, as one function of a composite device,
+/// with MS OS 2.0 descriptors for WinUSB.
+///
+/// Same as [`usb_dfu_with_msos`], but the descriptors binding WinUSB only apply to the DFU function,
+/// so that Windows keeps loading its composite driver, which binds the drivers of the other
+/// functions, such as the serial driver of a [`CdcAcmClass`](crate::class::cdc_acm::CdcAcmClass).
+/// Classes can be added before or after the DFU function, in any order.
+///
+/// The builder must be configured with [`Config::composite_with_iads`](crate::Config::composite_with_iads),
+/// the default, so that each function is announced by an interface association descriptor.
+///
+/// The descriptors are only added if the builder was created with a non-empty MS OS descriptor buffer.
+/// If no MS OS descriptor set was started yet, its header is added, using
+/// [`MSOS_VENDOR_CODE`](super::MSOS_VENDOR_CODE).
+pub fn usb_dfu_composite_with_msos<'d, D: Driver<'d>, H: Handler>(
+    builder: &mut Builder<'d, D>,
+    state: &'d mut DfuState<H>,
+    guid: &str,
+) {
+    let msos = super::winusb_header_msos(builder);
+    usb_dfu(builder, state, |func| {
+        if msos {
+            super::winusb_function_msos(func, guid);
+        }
+    });
+}
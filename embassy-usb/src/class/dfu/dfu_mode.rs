@@ -1,3 +1,4 @@
+use embassy_time::Duration;
 use embassy_usb_driver::Driver;
 
 use super::consts::{
@@ -5,11 +6,12 @@ use super::consts::{
     USB_CLASS_APPN_SPEC,
 };
 use crate::control::{InResponse, OutResponse, Recipient, Request as ControlRequest, RequestType};
+use crate::types::{InterfaceNumber, StringIndex};
 use crate::{Builder, FunctionBuilder};
 
 /// Handler trait for DFU bootloader mode.
 ///
-/// Implement this trait to handle firmware download operations.
+/// Implement this trait to handle firmware download and upload operations.
 pub trait Handler {
     /// Called when a firmware download starts.
     ///
@@ -35,41 +37,305 @@ pub trait Handler {
     /// This is typically where you would perform a system reset to boot
     /// the new firmware after a successful download.
     fn system_reset(&mut self);
+
+    /// Called to read a chunk of firmware data, for DFU_UPLOAD requests.
+    ///
+    /// Fill `buf` with the data at `offset` in the image and return the number of bytes read.
+    /// Returning fewer bytes than `buf.len()`, possibly 0, ends the upload.
+    ///
+    /// Only called if the target has [`DfuAttributes::CAN_UPLOAD`]. The default implementation
+    /// rejects the upload with `errSTALLEDPKT`.
+    fn upload(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, Status> {
+        let _ = (offset, buf);
+        Err(Status::ErrStalledPkt)
+    }
+
+    /// Called when a transfer is abandoned before its end, on DFU_ABORT, DFU_CLRSTATUS, a USB reset
+    /// or a target change.
+    ///
+    /// A new download starts again with [`start`](Self::start). This may also be called when no
+    /// transfer is in progress.
+    fn abort(&mut self) {}
+
+    /// Time in milliseconds the host should wait before the next DFU_GETSTATUS, reported as
+    /// `bwPollTimeout`.
+    ///
+    /// This should cover the flash operations following a request. Values above `0xFFFFFF` are
+    /// clamped. The default is 50 ms.
+    fn poll_timeout_ms(&self) -> u32 {
+        50
+    }
+
+    /// Called when the host selects a target, e.g. with `dfu-util -a N`.
+    ///
+    /// `target` is the index of the target in [`DfuState::new_multi`], which is also the alternate
+    /// setting number of the DFU interface. Target 0 is selected after a USB reset.
+    ///
+    /// Returning an error puts the DFU interface in the `dfuERROR` state with this status.
+    fn select_target(&mut self, target: u8) -> Result<(), Status> {
+        let _ = target;
+        Ok(())
+    }
+}
+
+/// Observer of the progress of DFU downloads, set with [`DfuState::with_progress`].
+///
+/// The methods are called from the control handler of the USB device, so they must return quickly,
+/// for instance by toggling an LED or pushing the progress into an `embassy_sync::channel::Channel`
+/// with `try_send` for another task to display it. DFU doesn't announce the size of a download, so
+/// the progress is reported as the number of bytes and blocks downloaded so far.
+pub trait DfuProgress {
+    /// Called after each block of a download is written, with the number of bytes and blocks
+    /// written since the download started.
+    fn on_download(&mut self, bytes: usize, blocks: usize) {
+        let _ = (bytes, blocks);
+    }
+
+    /// Called when the host ends the download, before [`Handler::finish`] checks and completes it.
+    fn on_manifest_start(&mut self) {}
+
+    /// Called when a request fails, putting the DFU interface in the `dfuERROR` state with `status`.
+    fn on_error(&mut self, status: Status) {
+        let _ = status;
+    }
+}
+
+/// No progress reporting.
+impl DfuProgress for () {}
+
+impl<T: DfuProgress + ?Sized> DfuProgress for &mut T {
+    fn on_download(&mut self, bytes: usize, blocks: usize) {
+        T::on_download(self, bytes, blocks)
+    }
+
+    fn on_manifest_start(&mut self) {
+        T::on_manifest_start(self)
+    }
+
+    fn on_error(&mut self, status: Status) {
+        T::on_error(self, status)
+    }
+}
+
+/// A DFU target, exposed as an alternate setting of the DFU interface.
+///
+/// Devices with multiple targets, for instance an application image and a resources image, let the
+/// host select the target to download to with `dfu-util -a N`.
+pub struct DfuTarget {
+    name: Option<&'static str>,
+    attrs: DfuAttributes,
+}
+
+impl DfuTarget {
+    /// Create a new target. `name` is reported as the alternate setting's interface string.
+    pub const fn new(name: &'static str, attrs: DfuAttributes) -> Self {
+        Self {
+            name: Some(name),
+            attrs,
+        }
+    }
+}
+
+/// Handler dispatching to one handler per target, for use with [`DfuState::new_multi`].
+///
+/// Selecting a target without a handler fails with `errTARGET`, and so do the transfers until
+/// another target is selected.
+pub struct TargetHandlers<'h, const N: usize> {
+    handlers: [&'h mut dyn Handler; N],
+    current: Option<usize>,
+}
+
+impl<'h, const N: usize> TargetHandlers<'h, N> {
+    /// Create a new handler, with the handler of each target in target order.
+    pub fn new(handlers: [&'h mut dyn Handler; N]) -> Self {
+        assert!(N >= 1, "DFU needs at least 1 target");
+        Self {
+            handlers,
+            current: Some(0),
+        }
+    }
+
+    fn handler(&mut self) -> Result<&mut dyn Handler, Status> {
+        match self.current {
+            Some(target) => Ok(&mut *self.handlers[target]),
+            None => Err(Status::ErrTarget),
+        }
+    }
+}
+
+impl<const N: usize> Handler for TargetHandlers<'_, N> {
+    fn start(&mut self) {
+        if let Ok(handler) = self.handler() {
+            handler.start()
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Status> {
+        self.handler()?.write(data)
+    }
+
+    fn finish(&mut self) -> Result<(), Status> {
+        self.handler()?.finish()
+    }
+
+    fn system_reset(&mut self) {
+        if let Ok(handler) = self.handler() {
+            handler.system_reset()
+        }
+    }
+
+    fn upload(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize, Status> {
+        self.handler()?.upload(offset, buf)
+    }
+
+    fn abort(&mut self) {
+        if let Ok(handler) = self.handler() {
+            handler.abort()
+        }
+    }
+
+    fn poll_timeout_ms(&self) -> u32 {
+        match self.current {
+            Some(target) => self.handlers[target].poll_timeout_ms(),
+            None => 0,
+        }
+    }
+
+    fn select_target(&mut self, target: u8) -> Result<(), Status> {
+        if target as usize >= N {
+            warn!("No handler for DFU target {}", target);
+            self.current = None;
+            return Err(Status::ErrTarget);
+        }
+        self.current = Some(target as usize);
+        self.handlers[target as usize].select_target(target)
+    }
 }
 
 /// Internal state for USB DFU
-pub struct DfuState<H: Handler> {
+///
+/// `N` is the number of targets, and `P` the observer of the download progress.
+pub struct DfuState<H: Handler, const N: usize = 1, P: DfuProgress = ()> {
     handler: H,
-    attrs: DfuAttributes,
+    progress: P,
+    targets: [DfuTarget; N],
+    current: usize,
+    iface: InterfaceNumber,
+    strings: [Option<StringIndex>; N],
     state: State,
     status: Status,
     next_block_num: usize,
+    upload_offset: usize,
+    download_len: usize,
 }
 
-impl<'d, H: Handler> DfuState<H> {
+impl<H: Handler> DfuState<H> {
     /// Create a new DFU instance to handle DFU transfers.
     pub fn new(handler: H, attrs: DfuAttributes) -> Self {
+        Self::new_multi(handler, [DfuTarget { name: None, attrs }])
+    }
+}
+
+impl<H: Handler, const N: usize> DfuState<H, N> {
+    /// Create a new DFU instance to handle DFU transfers to multiple targets.
+    ///
+    /// Each target is exposed as an alternate setting of the DFU interface, in order. Selecting
+    /// another alternate setting resets the download state and calls [`Handler::select_target`].
+    /// See [`TargetHandlers`] for a handler dispatching to one handler per target.
+    pub fn new_multi(handler: H, targets: [DfuTarget; N]) -> Self {
+        assert!((1..=u8::MAX as usize).contains(&N), "DFU needs 1 to 255 targets");
         Self {
             handler,
-            attrs,
+            progress: (),
+            targets,
+            current: 0,
+            iface: InterfaceNumber::new(0),
+            strings: [None; N],
             state: State::DfuIdle,
             status: Status::Ok,
             next_block_num: 0,
+            upload_offset: 0,
+            download_len: 0,
+        }
+    }
+
+    /// Report the progress of downloads to `progress`.
+    pub fn with_progress<P: DfuProgress>(self, progress: P) -> DfuState<H, N, P> {
+        DfuState {
+            handler: self.handler,
+            progress,
+            targets: self.targets,
+            current: self.current,
+            iface: self.iface,
+            strings: self.strings,
+            state: self.state,
+            status: self.status,
+            next_block_num: self.next_block_num,
+            upload_offset: self.upload_offset,
+            download_len: self.download_len,
         }
     }
+}
+
+impl<H: Handler, const N: usize, P: DfuProgress> DfuState<H, N, P> {
+    fn attrs(&self) -> &DfuAttributes {
+        &self.targets[self.current].attrs
+    }
 
     fn reset_state(&mut self) {
         self.next_block_num = 0;
+        self.upload_offset = 0;
+        self.download_len = 0;
         self.state = State::DfuIdle;
         self.status = Status::Ok;
     }
+
+    /// Abandon the current transfer, if any, and go back to dfuIDLE.
+    fn abort_transfer(&mut self) {
+        if self.state != State::DfuIdle {
+            self.handler.abort();
+        }
+        self.reset_state();
+    }
+
+    /// Select `target`, abandoning the current transfer.
+    fn select_target(&mut self, target: u8) {
+        debug!("Target {} selected", target);
+        self.abort_transfer();
+        self.current = target as usize;
+        if let Err(status) = self.handler.select_target(target) {
+            self.fail(status);
+        }
+    }
+
+    /// Go to dfuERROR with `status`.
+    fn fail(&mut self, status: Status) {
+        self.state = State::Error;
+        self.status = status;
+        self.progress.on_error(status);
+    }
 }
 
-impl<H: Handler> crate::Handler for DfuState<H> {
+impl<H: Handler, const N: usize, P: DfuProgress> crate::Handler for DfuState<H, N, P> {
     fn reset(&mut self) {
         if matches!(self.state, State::ManifestSync | State::ManifestWaitReset) {
             self.handler.system_reset();
+        } else {
+            // A host that gave up on a transfer resets the bus before starting over.
+            self.select_target(0);
+        }
+    }
+
+    fn set_alternate_setting(&mut self, iface: InterfaceNumber, alternate_setting: u8) {
+        if iface != self.iface || alternate_setting as usize >= N {
+            return;
         }
+        self.select_target(alternate_setting);
+    }
+
+    fn get_string(&mut self, index: StringIndex, _lang_id: u16) -> Option<&str> {
+        let target = self.strings.iter().position(|s| *s == Some(index))?;
+        self.targets[target].name
     }
 
     fn control_out(&mut self, req: ControlRequest, data: &[u8]) -> Option<OutResponse> {
@@ -79,14 +345,19 @@ impl<H: Handler> crate::Handler for DfuState<H> {
         match Request::try_from(req.request) {
             Ok(Request::Abort) => {
                 info!("Abort requested");
-                self.reset_state();
+                self.abort_transfer();
                 Some(OutResponse::Accepted)
             }
-            Ok(Request::Dnload) if self.attrs.contains(DfuAttributes::CAN_DOWNLOAD) => {
+            Ok(Request::Dnload) if self.attrs().contains(DfuAttributes::CAN_DOWNLOAD) => {
+                // A failed download must be acknowledged with DFU_CLRSTATUS before starting over.
+                if self.state == State::Error {
+                    error!("Unexpected DNLOAD in the error state");
+                    return Some(OutResponse::Rejected);
+                }
+
                 if req.value as usize != self.next_block_num {
                     error!("expected next block num {}, got {}", self.next_block_num, req.value);
-                    self.state = State::Error;
-                    self.status = Status::ErrUnknown;
+                    self.fail(Status::ErrUnknown);
                     return Some(OutResponse::Rejected);
                 }
 
@@ -96,21 +367,18 @@ impl<H: Handler> crate::Handler for DfuState<H> {
                 }
 
                 if req.length == 0 {
+                    self.progress.on_manifest_start();
                     match self.handler.finish() {
                         Ok(_) => {
                             self.status = Status::Ok;
                             self.state = State::ManifestSync;
                         }
-                        Err(e) => {
-                            self.state = State::Error;
-                            self.status = e;
-                        }
+                        Err(e) => self.fail(e),
                     }
                 } else {
                     if self.state != State::Download {
                         error!("Unexpected DNLOAD while chip is waiting for a GETSTATUS");
-                        self.status = Status::ErrUnknown;
-                        self.state = State::Error;
+                        self.fail(Status::ErrUnknown);
                         return Some(OutResponse::Rejected);
                     }
                     match self.handler.write(data) {
@@ -118,20 +386,23 @@ impl<H: Handler> crate::Handler for DfuState<H> {
                             self.status = Status::Ok;
                             self.state = State::DlSync;
                             self.next_block_num += 1;
+                            self.download_len += data.len();
+                            self.progress.on_download(self.download_len, self.next_block_num);
                         }
-                        Err(e) => {
-                            self.state = State::Error;
-                            self.status = e;
-                        }
+                        Err(e) => self.fail(e),
                     }
                 }
 
                 Some(OutResponse::Accepted)
             }
-            Ok(Request::Detach) => Some(OutResponse::Accepted), // Device is already in DFU mode
+            Ok(Request::Detach) => {
+                // Device is already in DFU mode, leave the state alone.
+                debug!("Ignoring detach in DFU mode");
+                Some(OutResponse::Accepted)
+            }
             Ok(Request::ClrStatus) => {
                 info!("Clear status requested");
-                self.reset_state();
+                self.abort_transfer();
                 Some(OutResponse::Accepted)
             }
             _ => {
@@ -147,8 +418,16 @@ impl<H: Handler> crate::Handler for DfuState<H> {
         }
         match Request::try_from(req.request) {
             Ok(Request::GetStatus) => {
-                //TODO: Configurable poll timeout, ability to add string for Vendor error
-                buf[0..6].copy_from_slice(&[self.status as u8, 0x32, 0x00, 0x00, self.state as u8, 0x00]);
+                //TODO: Ability to add string for Vendor error
+                let timeout = self.handler.poll_timeout_ms().min(0xFF_FFFF).to_le_bytes();
+                buf[0..6].copy_from_slice(&[
+                    self.status as u8,
+                    timeout[0],
+                    timeout[1],
+                    timeout[2],
+                    self.state as u8,
+                    0x00,
+                ]);
                 match self.state {
                     State::DlSync => self.state = State::Download,
                     State::ManifestSync => self.state = State::ManifestWaitReset,
@@ -161,9 +440,43 @@ impl<H: Handler> crate::Handler for DfuState<H> {
                 buf[0] = self.state as u8;
                 Some(InResponse::Accepted(&buf[0..1]))
             }
-            Ok(Request::Upload) if self.attrs.contains(DfuAttributes::CAN_UPLOAD) => {
-                //TODO: FirmwareUpdater does not provide a way of reading the active partition, can't upload.
-                Some(InResponse::Rejected)
+            Ok(Request::Upload) if self.attrs().contains(DfuAttributes::CAN_UPLOAD) => {
+                if self.state == State::DfuIdle {
+                    info!("Upload starting");
+                    self.reset_state();
+                    self.state = State::UploadIdle;
+                }
+
+                if self.state != State::UploadIdle {
+                    error!("Unexpected UPLOAD while not idle");
+                    self.fail(Status::ErrStalledPkt);
+                    return Some(InResponse::Rejected);
+                }
+
+                if req.value as usize != self.next_block_num {
+                    error!("expected next block num {}, got {}", self.next_block_num, req.value);
+                    self.fail(Status::ErrUnknown);
+                    return Some(InResponse::Rejected);
+                }
+
+                let len = buf.len().min(req.length as usize);
+                match self.handler.upload(self.upload_offset, &mut buf[..len]) {
+                    Ok(n) => {
+                        let n = n.min(len);
+                        self.upload_offset += n;
+                        self.next_block_num += 1;
+                        // A short packet ends the upload.
+                        if n < len {
+                            info!("Upload complete, {} bytes", self.upload_offset);
+                            self.reset_state();
+                        }
+                        Some(InResponse::Accepted(&buf[..n]))
+                    }
+                    Err(e) => {
+                        self.fail(e);
+                        Some(InResponse::Rejected)
+                    }
+                }
             }
             _ => {
                 debug!("Unknown IN request {:?}", req);
@@ -176,37 +489,255 @@ impl<H: Handler> crate::Handler for DfuState<H> {
 /// An implementation of the USB DFU 1.1 protocol
 ///
 /// This function will add a DFU interface descriptor to the provided Builder, and register the provided Control as a handler for the USB device
-/// The handler is responsive to DFU GetState, GetStatus, Abort, and ClrStatus commands, as well as Download if configured by the user.
+/// The handler is responsive to DFU GetState, GetStatus, Abort, and ClrStatus commands, as well as Download and Upload if configured by the user.
 ///
 /// Once the host has initiated a DFU download operation, the chunks sent by the host will be written to the DFU partition.
 /// Once the final sync in the manifestation phase has been received, the handler will trigger a system reset to swap the new firmware.
-pub fn usb_dfu<'d, D: Driver<'d>, H: Handler>(
+/// Upload requests are served by [`Handler::upload`].
+///
+/// A state with multiple targets, created with [`DfuState::new_multi`], adds one alternate setting per target.
+pub fn usb_dfu<'d, D: Driver<'d>, H: Handler, const N: usize, P: DfuProgress>(
     builder: &mut Builder<'d, D>,
-    state: &'d mut DfuState<H>,
+    state: &'d mut DfuState<H, N, P>,
     max_write_size: usize,
     func_modifier: impl Fn(&mut FunctionBuilder<'_, 'd, D>),
 ) {
-    let mut func = builder.function(0x00, 0x00, 0x00);
+    let mut func = builder.function(USB_CLASS_APPN_SPEC, APPN_SPEC_SUBCLASS_DFU, DFU_PROTOCOL_DFU);
 
     // Here we give users the opportunity to add their own function level MSOS headers for instance.
     // This is useful when DFU functionality is part of a composite USB device.
     func_modifier(&mut func);
 
     let mut iface = func.interface();
-    let mut alt = iface.alt_setting(USB_CLASS_APPN_SPEC, APPN_SPEC_SUBCLASS_DFU, DFU_PROTOCOL_DFU, None);
-    alt.descriptor(
-        DESC_DFU_FUNCTIONAL,
-        &[
-            state.attrs.bits(),
-            0xc4,
-            0x09, // 2500ms timeout, doesn't affect operation as DETACH not necessary in bootloader code
-            (max_write_size & 0xff) as u8,
-            ((max_write_size & 0xff00) >> 8) as u8,
-            0x10,
-            0x01, // DFU 1.1
-        ],
-    );
+    state.iface = iface.interface_number();
+    for (target, string) in state.targets.iter().zip(state.strings.iter_mut()) {
+        *string = target.name.map(|_| iface.string());
+        let mut alt = iface.alt_setting(USB_CLASS_APPN_SPEC, APPN_SPEC_SUBCLASS_DFU, DFU_PROTOCOL_DFU, *string);
+        alt.descriptor(
+            DESC_DFU_FUNCTIONAL,
+            // 2500ms timeout, doesn't affect operation as DETACH not necessary in bootloader code
+            &super::functional_descriptor(&target.attrs, Duration::from_millis(2500), max_write_size),
+        );
+    }
 
     drop(func);
     builder.handler(state);
 }
+
+/// An implementation of the USB DFU 1.1 protocol, with MS OS 2.0 descriptors for WinUSB.
+///
+/// Same as [`usb_dfu`], but also adds the MS OS 2.0 descriptors that make Windows bind the WinUSB
+/// driver to the DFU interface automatically, so users don't have to do this manually using a tool
+/// like Zadig. `guid` is the device interface GUID clients on Windows use to find the device, such as
+/// `"{EAA9A5DC-30BA-44BC-9232-606CDC875321}"`.
+///
+/// The descriptors are only added if the builder was created with a non-empty MS OS descriptor buffer.
+/// If no MS OS descriptor set was started yet, the set header and the device level descriptors are
+/// added as well, using [`MSOS_VENDOR_CODE`](super::MSOS_VENDOR_CODE).
+pub fn usb_dfu_with_msos<'d, D: Driver<'d>, H: Handler, const N: usize, P: DfuProgress>(
+    builder: &mut Builder<'d, D>,
+    state: &'d mut DfuState<H, N, P>,
+    max_write_size: usize,
+    guid: &str,
+) {
+    let msos = super::winusb_device_msos(builder, guid);
+    usb_dfu(builder, state, max_write_size, |func| {
+        if msos {
+            super::winusb_function_msos(func, guid);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Handler as _;
+    use crate::driver::Direction;
+
+    struct NullHandler;
+
+    impl Handler for NullHandler {
+        fn start(&mut self) {}
+
+        fn write(&mut self, data: &[u8]) -> Result<(), Status> {
+            if data[0] == 0xEE { Err(Status::ErrWrite) } else { Ok(()) }
+        }
+
+        fn finish(&mut self) -> Result<(), Status> {
+            Ok(())
+        }
+
+        fn system_reset(&mut self) {}
+    }
+
+    /// Handler recording the selected target.
+    #[derive(Default)]
+    struct TargetRecorder {
+        selected: Option<u8>,
+    }
+
+    impl Handler for TargetRecorder {
+        fn start(&mut self) {}
+
+        fn write(&mut self, _data: &[u8]) -> Result<(), Status> {
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<(), Status> {
+            Ok(())
+        }
+
+        fn system_reset(&mut self) {}
+
+        fn select_target(&mut self, target: u8) -> Result<(), Status> {
+            self.selected = Some(target);
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        downloaded: (usize, usize),
+        manifest: bool,
+        error: Option<Status>,
+    }
+
+    impl DfuProgress for Recorder {
+        fn on_download(&mut self, bytes: usize, blocks: usize) {
+            self.downloaded = (bytes, blocks);
+        }
+
+        fn on_manifest_start(&mut self) {
+            self.manifest = true;
+        }
+
+        fn on_error(&mut self, status: Status) {
+            self.error = Some(status);
+        }
+    }
+
+    fn request(direction: Direction, request: Request, value: u16, length: u16) -> ControlRequest {
+        ControlRequest {
+            direction,
+            request_type: RequestType::Class,
+            recipient: Recipient::Interface,
+            request: request as u8,
+            value,
+            index: 0,
+            length,
+        }
+    }
+
+    /// Download a block and return the status and state reported by the following DFU_GETSTATUS.
+    fn download<P: DfuProgress>(state: &mut DfuState<NullHandler, 1, P>, block: u16, data: &[u8]) -> (u8, u8) {
+        let req = request(Direction::Out, Request::Dnload, block, data.len() as u16);
+        state.control_out(req, data);
+        let mut buf = [0; 6];
+        state.control_in(request(Direction::In, Request::GetStatus, 0, 6), &mut buf);
+        (buf[0], buf[4])
+    }
+
+    #[test]
+    fn reports_download_progress() {
+        let mut recorder = Recorder::default();
+        let mut state = DfuState::new(NullHandler, DfuAttributes::CAN_DOWNLOAD).with_progress(&mut recorder);
+        download(&mut state, 0, &[0; 64]);
+        download(&mut state, 1, &[0; 10]);
+        download(&mut state, 2, &[]);
+
+        assert_eq!(recorder.downloaded, (74, 2));
+        assert!(recorder.manifest);
+        assert!(recorder.error.is_none());
+    }
+
+    #[test]
+    fn reports_download_errors() {
+        let mut recorder = Recorder::default();
+        let mut state = DfuState::new(NullHandler, DfuAttributes::CAN_DOWNLOAD).with_progress(&mut recorder);
+        download(&mut state, 0, &[0; 64]);
+        download(&mut state, 1, &[0xEE; 64]);
+
+        assert_eq!(recorder.downloaded, (64, 1));
+        assert!(!recorder.manifest);
+        assert!(matches!(recorder.error, Some(Status::ErrWrite)));
+    }
+
+    #[test]
+    fn write_errors_stay_until_clrstatus() {
+        let mut state = DfuState::new(NullHandler, DfuAttributes::CAN_DOWNLOAD);
+        assert_eq!(
+            download(&mut state, 0, &[0; 64]),
+            (Status::Ok as u8, State::DlSync as u8)
+        );
+        assert_eq!(
+            download(&mut state, 1, &[0xEE; 64]),
+            (Status::ErrWrite as u8, State::Error as u8)
+        );
+
+        // Downloads are rejected until the error is cleared, even from the start.
+        let req = request(Direction::Out, Request::Dnload, 0, 64);
+        assert!(matches!(state.control_out(req, &[0; 64]), Some(OutResponse::Rejected)));
+        let mut buf = [0; 6];
+        state.control_in(request(Direction::In, Request::GetStatus, 0, 6), &mut buf);
+        assert_eq!((buf[0], buf[4]), (Status::ErrWrite as u8, State::Error as u8));
+
+        state.control_out(request(Direction::Out, Request::ClrStatus, 0, 0), &[]);
+        let mut buf = [0; 1];
+        state.control_in(request(Direction::In, Request::GetState, 0, 1), &mut buf);
+        assert_eq!(buf[0], State::DfuIdle as u8);
+        assert_eq!(
+            download(&mut state, 0, &[0; 64]),
+            (Status::Ok as u8, State::DlSync as u8)
+        );
+    }
+
+    fn get_status<H: Handler, const N: usize>(state: &mut DfuState<H, N>) -> (u8, u8) {
+        let mut buf = [0; 6];
+        state.control_in(request(Direction::In, Request::GetStatus, 0, 6), &mut buf);
+        (buf[0], buf[4])
+    }
+
+    #[test]
+    fn reset_selects_target_0() {
+        let mut state = DfuState::new_multi(
+            TargetRecorder::default(),
+            [
+                DfuTarget::new("app", DfuAttributes::CAN_DOWNLOAD),
+                DfuTarget::new("data", DfuAttributes::empty()),
+            ],
+        );
+        state.set_alternate_setting(InterfaceNumber::new(0), 1);
+        assert_eq!(state.handler.selected, Some(1));
+
+        state.reset();
+        assert_eq!(state.current, 0);
+        assert_eq!(state.handler.selected, Some(0));
+        // Target 0 can download again, target 1 couldn't.
+        let req = request(Direction::Out, Request::Dnload, 0, 4);
+        assert!(matches!(state.control_out(req, &[0; 4]), Some(OutResponse::Accepted)));
+    }
+
+    #[test]
+    fn selecting_a_target_without_handler_fails() {
+        let mut app = TargetRecorder::default();
+        let mut state = DfuState::new_multi(
+            TargetHandlers::new([&mut app]),
+            [
+                DfuTarget::new("app", DfuAttributes::CAN_DOWNLOAD),
+                DfuTarget::new("data", DfuAttributes::CAN_DOWNLOAD),
+            ],
+        );
+        state.set_alternate_setting(InterfaceNumber::new(0), 1);
+        assert_eq!(get_status(&mut state), (Status::ErrTarget as u8, State::Error as u8));
+
+        // Downloads to the missing target keep failing after the error is cleared.
+        state.control_out(request(Direction::Out, Request::ClrStatus, 0, 0), &[]);
+        state.control_out(request(Direction::Out, Request::Dnload, 0, 4), &[0; 4]);
+        assert_eq!(get_status(&mut state), (Status::ErrTarget as u8, State::Error as u8));
+
+        state.set_alternate_setting(InterfaceNumber::new(0), 0);
+        assert_eq!(get_status(&mut state), (Status::Ok as u8, State::DfuIdle as u8));
+        state.control_out(request(Direction::Out, Request::Dnload, 0, 4), &[0; 4]);
+        assert_eq!(get_status(&mut state), (Status::Ok as u8, State::DlSync as u8));
+    }
+}
@@ -4,9 +4,118 @@
 //! - `app_mode`: Runtime mode for applications to support detach requests
 //! - `dfu_mode`: Bootloader mode for handling firmware downloads
 
+use embassy_time::Duration;
+use embassy_usb_driver::Driver;
+
+use self::consts::DfuAttributes;
+use crate::msos::{self, CompatibleIdFeatureDescriptor, PropertyData, RegistryPropertyFeatureDescriptor};
+use crate::{Builder, FunctionBuilder};
+
 pub mod consts;
 
 /// DFU runtime mode (application side).
 pub mod app_mode;
 /// DFU bootloader mode (firmware download).
 pub mod dfu_mode;
+
+/// Vendor request code of the MS OS 2.0 descriptor set written by `usb_dfu_with_msos`.
+pub const MSOS_VENDOR_CODE: u8 = 0x02;
+
+/// Add the device level MS OS 2.0 descriptors binding WinUSB, if no descriptor set was started yet.
+///
+/// Returns `false` if the builder has no MS OS descriptor buffer, in which case nothing is written.
+fn winusb_device_msos<'d, D: Driver<'d>>(builder: &mut Builder<'d, D>, guid: &str) -> bool {
+    let writer = builder.msos_writer();
+    if !writer.is_enabled() {
+        return false;
+    }
+
+    // Windows only picks up the function level descriptors if the device level ones are present
+    // too. If the application already wrote a descriptor set, it's responsible for those.
+    if writer.is_empty() {
+        writer.header(msos::windows_version::WIN8_1, MSOS_VENDOR_CODE);
+        writer.device_feature(CompatibleIdFeatureDescriptor::new("WINUSB", ""));
+        writer.device_feature(RegistryPropertyFeatureDescriptor::new(
+            "DeviceInterfaceGUIDs",
+            PropertyData::RegMultiSz(&[guid]),
+        ));
+    }
+    true
+}
+
+/// Start the MS OS 2.0 descriptor set, without device level descriptors, if it wasn't started yet.
+///
+/// Returns `false` if the builder has no MS OS descriptor buffer, in which case nothing is written.
+fn winusb_header_msos<'d, D: Driver<'d>>(builder: &mut Builder<'d, D>) -> bool {
+    let writer = builder.msos_writer();
+    if !writer.is_enabled() {
+        return false;
+    }
+
+    // A device level compatible ID would make Windows bind WinUSB to the whole device, instead of
+    // its composite driver.
+    if writer.is_empty() {
+        writer.header(msos::windows_version::WIN8_1, MSOS_VENDOR_CODE);
+    }
+    true
+}
+
+/// Add the function level MS OS 2.0 descriptors binding WinUSB to the DFU interface.
+fn winusb_function_msos<'d, D: Driver<'d>>(func: &mut FunctionBuilder<'_, 'd, D>, guid: &str) {
+    func.msos_feature(CompatibleIdFeatureDescriptor::new("WINUSB", ""));
+    func.msos_feature(RegistryPropertyFeatureDescriptor::new(
+        "DeviceInterfaceGUIDs",
+        PropertyData::RegMultiSz(&[guid]),
+    ));
+}
+
+/// Body of the DFU 1.1 functional descriptor, after its length and type.
+///
+/// `bmAttributes` holds exactly the bits of `attrs`, and `wDetachTimeOut` is `detach_timeout`,
+/// clamped to the 65535 ms it can hold. Panics if `transfer_size` doesn't fit `wTransferSize`.
+fn functional_descriptor(attrs: &DfuAttributes, detach_timeout: Duration, transfer_size: usize) -> [u8; 7] {
+    let detach_timeout = detach_timeout.as_millis().min(u16::MAX as u64) as u16;
+    let transfer_size = u16::try_from(transfer_size).expect("wTransferSize must fit in 16 bits");
+    let [timeout_lo, timeout_hi] = detach_timeout.to_le_bytes();
+    let [size_lo, size_hi] = transfer_size.to_le_bytes();
+    [
+        attrs.bits(),
+        timeout_lo,
+        timeout_hi,
+        size_lo,
+        size_hi,
+        0x10,
+        0x01, // DFU 1.1
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functional_descriptor_fields() {
+        let attrs = DfuAttributes::CAN_DOWNLOAD | DfuAttributes::MANIFESTATION_TOLERANT | DfuAttributes::WILL_DETACH;
+        let desc = functional_descriptor(&attrs, Duration::from_millis(2500), 4096);
+
+        assert_eq!(desc[0], 0b0000_1101);
+        assert_eq!(u16::from_le_bytes([desc[1], desc[2]]), 2500);
+        assert_eq!(u16::from_le_bytes([desc[3], desc[4]]), 4096);
+        assert_eq!(u16::from_le_bytes([desc[5], desc[6]]), 0x0110);
+    }
+
+    #[test]
+    fn functional_descriptor_clamps_timeout() {
+        let desc = functional_descriptor(&DfuAttributes::empty(), Duration::from_secs(100), 64);
+
+        assert_eq!(desc[0], 0);
+        assert_eq!(u16::from_le_bytes([desc[1], desc[2]]), u16::MAX);
+        assert_eq!(u16::from_le_bytes([desc[3], desc[4]]), 64);
+    }
+
+    #[test]
+    #[should_panic]
+    fn functional_descriptor_transfer_size_too_large() {
+        functional_descriptor(&DfuAttributes::CAN_DOWNLOAD, Duration::from_millis(0), 65536);
+    }
+}
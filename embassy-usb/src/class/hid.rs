@@ -1,9 +1,15 @@
 //! USB HID (Human Interface Device) class implementation.
 
+use core::cell::RefCell;
+use core::future::poll_fn;
 use core::mem::MaybeUninit;
 use core::ops::Range;
 use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Poll;
 
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_sync::waitqueue::WakerRegistration;
 #[cfg(feature = "usbd-hid")]
 use ssmarshal::serialize;
 #[cfg(feature = "usbd-hid")]
@@ -29,6 +35,11 @@ const HID_REQ_SET_REPORT: u8 = 0x09;
 const HID_REQ_GET_PROTOCOL: u8 = 0x03;
 const HID_REQ_SET_PROTOCOL: u8 = 0x0b;
 
+/// Maximum length of an output report sent over the control pipe (SET_REPORT) to a [`HidReader`].
+///
+/// Longer output reports are passed to [`RequestHandler::set_report()`].
+pub const MAX_CONTROL_OUT_REPORT_LEN: usize = 64;
+
 /// Get/Set Protocol mapping
 /// See (7.2.5 and 7.2.6): <https://www.usb.org/sites/default/files/hid1_11.pdf>
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -93,6 +104,13 @@ pub struct Config<'d> {
     /// Max packet size for both the IN and OUT endpoints.
     pub max_packet_size: u16,
 
+    /// Whether [`HidReaderWriter::new`] allocates an interrupt OUT endpoint for output reports.
+    ///
+    /// Without it, the host sends output reports over the control pipe, which is enough for a few
+    /// small reports, such as the LED state of a keyboard. [`HidReader::read`] receives output reports
+    /// from both pipes. Ignored by [`HidWriter::new`].
+    pub out_endpoint: bool,
+
     /// The HID subclass of this interface
     pub hid_subclass: HidSubclass,
 
@@ -127,6 +145,7 @@ impl ReportId {
 pub struct State<'d> {
     control: MaybeUninit<Control<'d>>,
     out_report_offset: AtomicUsize,
+    control_out_report: ControlOutReport,
 }
 
 impl<'d> Default for State<'d> {
@@ -141,10 +160,82 @@ impl<'d> State<'d> {
         State {
             control: MaybeUninit::uninit(),
             out_report_offset: AtomicUsize::new(0),
+            control_out_report: ControlOutReport::new(),
         }
     }
 }
 
+/// Output report received over the control pipe, waiting for the reader.
+struct ControlOutReport {
+    inner: CriticalSectionMutex<RefCell<ControlOutReportInner>>,
+}
+
+struct ControlOutReportInner {
+    buf: [u8; MAX_CONTROL_OUT_REPORT_LEN],
+    len: Option<usize>,
+    waker: WakerRegistration,
+}
+
+impl ControlOutReport {
+    const fn new() -> Self {
+        ControlOutReport {
+            inner: CriticalSectionMutex::new(RefCell::new(ControlOutReportInner {
+                buf: [0; MAX_CONTROL_OUT_REPORT_LEN],
+                len: None,
+                waker: WakerRegistration::new(),
+            })),
+        }
+    }
+
+    /// Stores `data` for the reader, unless the previous report wasn't read yet.
+    fn put(&self, data: &[u8]) -> OutResponse {
+        self.inner.lock(|inner| {
+            let mut inner = inner.borrow_mut();
+            if inner.len.is_some() {
+                warn!("HID output report rejected, the previous one wasn't read yet");
+                return OutResponse::Rejected;
+            }
+            inner.buf[..data.len()].copy_from_slice(data);
+            inner.len = Some(data.len());
+            inner.waker.wake();
+            OutResponse::Accepted
+        })
+    }
+
+    fn clear(&self) {
+        self.inner.lock(|inner| inner.borrow_mut().len = None);
+    }
+
+    /// Waits for a report to be stored.
+    async fn wait(&self) {
+        poll_fn(|cx| {
+            self.inner.lock(|inner| {
+                let mut inner = inner.borrow_mut();
+                if inner.len.is_some() {
+                    Poll::Ready(())
+                } else {
+                    inner.waker.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Moves the stored report into `buf`.
+    fn take(&self, buf: &mut [u8]) -> Result<usize, ReadError> {
+        self.inner.lock(|inner| {
+            let mut inner = inner.borrow_mut();
+            let len = unwrap!(inner.len.take());
+            if len > buf.len() {
+                return Err(ReadError::BufferOverflow);
+            }
+            buf[..len].copy_from_slice(&inner.buf[..len]);
+            Ok(len)
+        })
+    }
+}
+
 /// USB HID reader/writer.
 pub struct HidReaderWriter<'d, D: Driver<'d>, const READ_N: usize, const WRITE_N: usize> {
     reader: HidReader<'d, D, READ_N>,
@@ -155,8 +246,13 @@ fn build<'d, D: Driver<'d>>(
     builder: &mut Builder<'d, D>,
     state: &'d mut State<'d>,
     config: Config<'d>,
-    with_out_endpoint: bool,
-) -> (Option<D::EndpointOut>, D::EndpointIn, &'d AtomicUsize) {
+    with_reader: bool,
+) -> (
+    Option<D::EndpointOut>,
+    D::EndpointIn,
+    &'d AtomicUsize,
+    &'d ControlOutReport,
+) {
     let len = config.report_descriptor.len();
 
     let mut func = builder.function(USB_CLASS_HID, config.hid_subclass as u8, config.hid_boot_protocol as u8);
@@ -189,7 +285,7 @@ fn build<'d, D: Driver<'d>>(
     );
 
     let ep_in = alt.endpoint_interrupt_in(None, config.max_packet_size, config.poll_ms);
-    let ep_out = if with_out_endpoint {
+    let ep_out = if with_reader && config.out_endpoint {
         Some(alt.endpoint_interrupt_out(None, config.max_packet_size, config.poll_ms))
     } else {
         None
@@ -202,25 +298,28 @@ fn build<'d, D: Driver<'d>>(
         config.report_descriptor,
         config.request_handler,
         &state.out_report_offset,
+        with_reader.then_some(&state.control_out_report),
     ));
     builder.handler(control);
 
-    (ep_out, ep_in, &state.out_report_offset)
+    (ep_out, ep_in, &state.out_report_offset, &state.control_out_report)
 }
 
 impl<'d, D: Driver<'d>, const READ_N: usize, const WRITE_N: usize> HidReaderWriter<'d, D, READ_N, WRITE_N> {
     /// Creates a new `HidReaderWriter`.
     ///
-    /// This will allocate one IN and one OUT endpoints. If you only need writing (sending)
-    /// HID reports, consider using [`HidWriter::new`] instead, which allocates an IN endpoint only.
+    /// This will allocate one IN endpoint, and one OUT endpoint if [`Config::out_endpoint`] is set.
+    /// If you only need writing (sending) HID reports, consider using [`HidWriter::new`] instead,
+    /// which allocates an IN endpoint only.
     ///
     pub fn new(builder: &mut Builder<'d, D>, state: &'d mut State<'d>, config: Config<'d>) -> Self {
-        let (ep_out, ep_in, offset) = build(builder, state, config, true);
+        let (ep_out, ep_in, offset, control_out_report) = build(builder, state, config, true);
 
         Self {
             reader: HidReader {
-                ep_out: ep_out.unwrap(),
+                ep_out,
                 offset,
+                control_out_report,
             },
             writer: HidWriter { ep_in },
         }
@@ -248,7 +347,7 @@ impl<'d, D: Driver<'d>, const READ_N: usize, const WRITE_N: usize> HidReaderWrit
         self.writer.write(report).await
     }
 
-    /// Reads an output report from the Interrupt Out pipe.
+    /// Reads an output report from the Interrupt Out pipe or the control pipe.
     ///
     /// See [`HidReader::read`].
     pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ReadError> {
@@ -267,8 +366,9 @@ pub struct HidWriter<'d, D: Driver<'d>, const N: usize> {
 ///
 /// You can obtain a `HidReader` using [`HidReaderWriter::split`].
 pub struct HidReader<'d, D: Driver<'d>, const N: usize> {
-    ep_out: D::EndpointOut,
+    ep_out: Option<D::EndpointOut>,
     offset: &'d AtomicUsize,
+    control_out_report: &'d ControlOutReport,
 }
 
 /// Error when reading a HID report.
@@ -304,7 +404,7 @@ impl<'d, D: Driver<'d>, const N: usize> HidWriter<'d, D, N> {
     /// of CPU on the device & bandwidth on the bus. A value of 10 is reasonable for
     /// high performance uses, and a value of 255 is good for best-effort usecases.
     pub fn new(builder: &mut Builder<'d, D>, state: &'d mut State<'d>, config: Config<'d>) -> Self {
-        let (ep_out, ep_in, _offset) = build(builder, state, config, false);
+        let (ep_out, ep_in, _offset, _control_out_report) = build(builder, state, config, false);
 
         assert!(ep_out.is_none());
 
@@ -346,11 +446,15 @@ impl<'d, D: Driver<'d>, const N: usize> HidWriter<'d, D, N> {
 
 impl<'d, D: Driver<'d>, const N: usize> HidReader<'d, D, N> {
     /// Waits for the interrupt out endpoint to be enabled.
+    ///
+    /// Returns right away if there is no interrupt out endpoint.
     pub async fn ready(&mut self) {
-        self.ep_out.wait_enabled().await;
+        if let Some(ep_out) = self.ep_out.as_mut() {
+            ep_out.wait_enabled().await;
+        }
     }
 
-    /// Delivers output reports from the Interrupt Out pipe to `handler`.
+    /// Delivers output reports from the Interrupt Out pipe and the control pipe to `handler`.
     ///
     /// If `use_report_ids` is true, the first byte of the report will be used as
     /// the `ReportId` value. Otherwise the `ReportId` value will be 0.
@@ -368,18 +472,19 @@ impl<'d, D: Driver<'d>, const N: usize> HidReader<'d, D, N> {
                     "Host sent output report larger than the configured maximum output report length ({})",
                     N
                 ),
-                Err(ReadError::Disabled) => self.ep_out.wait_enabled().await,
+                Err(ReadError::Disabled) => self.ready().await,
                 Err(ReadError::Sync(_)) => unreachable!(),
             }
         }
     }
 
-    /// Reads an output report from the Interrupt Out pipe.
+    /// Reads an output report from the Interrupt Out pipe or the control pipe.
     ///
-    /// **Note:** Any reports sent from the host over the control pipe will be
-    /// passed to [`RequestHandler::set_report()`] for handling. The application
-    /// is responsible for ensuring output reports from both pipes are handled
-    /// correctly.
+    /// Output reports sent over the control pipe (SET_REPORT) are returned by this method as
+    /// well, instead of being passed to [`RequestHandler::set_report()`], unless they are longer
+    /// than [`MAX_CONTROL_OUT_REPORT_LEN`]. Reports are returned as sent by the host, starting with
+    /// their report ID if the report descriptor uses report IDs. While a report from the control
+    /// pipe isn't read, the next one is rejected.
     ///
     /// **Note:** If `N` > the maximum packet size of the endpoint (i.e. output
     /// reports may be split across multiple packets) and this method's future
@@ -392,33 +497,56 @@ impl<'d, D: Driver<'d>, const N: usize> HidReader<'d, D, N> {
         assert!(N != 0);
         assert!(buf.len() >= N);
 
+        let Some(ep_out) = self.ep_out.as_mut() else {
+            self.control_out_report.wait().await;
+            return self.control_out_report.take(&mut buf[..N]);
+        };
+
         // Read packets from the endpoint
-        let max_packet_size = usize::from(self.ep_out.info().max_packet_size);
+        let max_packet_size = usize::from(ep_out.info().max_packet_size);
         let starting_offset = self.offset.load(Ordering::Acquire);
         let mut total = starting_offset;
-        loop {
-            for chunk in buf[starting_offset..N].chunks_mut(max_packet_size) {
-                match self.ep_out.read(chunk).await {
-                    Ok(size) => {
-                        total += size;
+        if starting_offset == 0 {
+            // Wait for the first packet of a report, or for a report from the control pipe. Once the
+            // first packet is read, the rest of the report is read from the endpoint only.
+            loop {
+                let first = &mut buf[..N.min(max_packet_size)];
+                match select(ep_out.read(first), self.control_out_report.wait()).await {
+                    Either::First(Ok(size)) => {
+                        total = size;
                         if size < max_packet_size || total == N {
-                            self.offset.store(0, Ordering::Release);
-                            break;
+                            // Some hosts may send ZLPs even when not required by the HID spec, so
+                            // we'll loop as long as total == 0.
+                            if total > 0 {
+                                return Ok(total);
+                            }
+                            continue;
                         }
                         self.offset.store(total, Ordering::Release);
+                        break;
                     }
-                    Err(err) => {
-                        self.offset.store(0, Ordering::Release);
-                        return Err(err.into());
-                    }
+                    Either::First(Err(err)) => return Err(err.into()),
+                    Either::Second(()) => return self.control_out_report.take(&mut buf[..N]),
                 }
             }
+        }
 
-            // Some hosts may send ZLPs even when not required by the HID spec, so we'll loop as long as total == 0.
-            if total > 0 {
-                break;
+        for chunk in buf[total..N].chunks_mut(max_packet_size) {
+            match ep_out.read(chunk).await {
+                Ok(size) => {
+                    total += size;
+                    if size < max_packet_size || total == N {
+                        break;
+                    }
+                    self.offset.store(total, Ordering::Release);
+                }
+                Err(err) => {
+                    self.offset.store(0, Ordering::Release);
+                    return Err(err.into());
+                }
             }
         }
+        self.offset.store(0, Ordering::Release);
 
         if starting_offset > 0 {
             Err(ReadError::Sync(starting_offset..total))
@@ -485,6 +613,7 @@ struct Control<'d> {
     report_descriptor: &'d [u8],
     request_handler: Option<&'d mut dyn RequestHandler>,
     out_report_offset: &'d AtomicUsize,
+    control_out_report: Option<&'d ControlOutReport>,
     hid_descriptor: [u8; 9],
 }
 
@@ -494,12 +623,14 @@ impl<'d> Control<'d> {
         report_descriptor: &'d [u8],
         request_handler: Option<&'d mut dyn RequestHandler>,
         out_report_offset: &'d AtomicUsize,
+        control_out_report: Option<&'d ControlOutReport>,
     ) -> Self {
         Control {
             if_num,
             report_descriptor,
             request_handler,
             out_report_offset,
+            control_out_report,
             hid_descriptor: [
                 // Length of buf inclusive of size prefix
                 9,
@@ -525,6 +656,9 @@ impl<'d> Control<'d> {
 impl<'d> Handler for Control<'d> {
     fn reset(&mut self) {
         self.out_report_offset.store(0, Ordering::Release);
+        if let Some(control_out_report) = self.control_out_report {
+            control_out_report.clear();
+        }
     }
 
     fn control_out(&mut self, req: Request, data: &[u8]) -> Option<OutResponse> {
@@ -550,6 +684,12 @@ impl<'d> Handler for Control<'d> {
                 Some(OutResponse::Accepted)
             }
             HID_REQ_SET_REPORT => match (ReportId::try_from(req.value), self.request_handler.as_mut()) {
+                // Output reports go to the reader, if there is one
+                (Ok(ReportId::Out(_)), _)
+                    if self.control_out_report.is_some() && data.len() <= MAX_CONTROL_OUT_REPORT_LEN =>
+                {
+                    self.control_out_report.map(|report| report.put(data))
+                }
                 (Ok(id), Some(handler)) => Some(handler.set_report(id, data)),
                 _ => Some(OutResponse::Rejected),
             },
@@ -629,3 +769,163 @@ impl<'d> Handler for Control<'d> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::pin::pin;
+
+    use embassy_futures::poll_once;
+
+    use super::*;
+    use crate::driver::EndpointType;
+    use crate::test_driver::{self, Packets};
+
+    fn new_writer<const N: usize>(max_packet_size: u16) -> (HidWriter<'static, test_driver::Driver, N>, Packets) {
+        let (ep_in, host_in) = test_driver::EndpointIn::new(1, EndpointType::Interrupt, max_packet_size);
+        (HidWriter { ep_in }, host_in)
+    }
+
+    fn new_reader<'d, const N: usize>(
+        state: &'d State<'d>,
+        max_packet_size: Option<u16>,
+    ) -> (HidReader<'d, test_driver::Driver, N>, Packets) {
+        let (ep_out, host_out) =
+            test_driver::EndpointOut::new(1, EndpointType::Interrupt, max_packet_size.unwrap_or(8));
+        let reader = HidReader {
+            ep_out: max_packet_size.map(|_| ep_out),
+            offset: &state.out_report_offset,
+            control_out_report: &state.control_out_report,
+        };
+        (reader, host_out)
+    }
+
+    fn set_report(control: &mut Control<'_>, data: &[u8]) -> Option<OutResponse> {
+        let req = Request::parse(&[0x21, HID_REQ_SET_REPORT, 0, 2, 0, 0, data.len() as u8, 0]);
+        control.control_out(req, data)
+    }
+
+    #[test]
+    fn writes_reports_in_packets() {
+        let (mut writer, host_in) = new_writer::<20>(8);
+
+        embassy_futures::block_on(writer.write(&[1; 20])).unwrap();
+        assert_eq!(host_in.take(), [[1; 8].to_vec(), [1; 8].to_vec(), [1; 4].to_vec()]);
+
+        // A report shorter than `N` ending on a packet boundary is ended by a zero length packet.
+        embassy_futures::block_on(writer.write(&[2; 16])).unwrap();
+        assert_eq!(host_in.take(), [[2; 8].to_vec(), [2; 8].to_vec(), [].to_vec()]);
+
+        embassy_futures::block_on(writer.write(&[3; 8])).unwrap();
+        assert_eq!(host_in.take(), [[3; 8].to_vec(), [].to_vec()]);
+
+        // A report of `N` bytes ends by itself.
+        let (mut writer, host_in) = new_writer::<8>(8);
+        embassy_futures::block_on(writer.write(&[4; 8])).unwrap();
+        assert_eq!(host_in.take(), [[4; 8].to_vec()]);
+    }
+
+    #[test]
+    fn reads_reports_spanning_packets() {
+        let state = State::new();
+        let (mut reader, host_out) = new_reader::<20>(&state, Some(8));
+        let mut buf = [0; 20];
+
+        for packet in [&[1; 8][..], &[2; 8], &[3; 4]] {
+            host_out.push(packet);
+        }
+        assert_eq!(embassy_futures::block_on(reader.read(&mut buf)), Ok(20));
+        assert_eq!(buf[..8], [1; 8]);
+        assert_eq!(buf[8..16], [2; 8]);
+        assert_eq!(buf[16..], [3; 4]);
+
+        // A report shorter than `N` ending on a packet boundary, ended by a zero length packet.
+        for packet in [&[4; 8][..], &[5; 8], &[]] {
+            host_out.push(packet);
+        }
+        assert_eq!(embassy_futures::block_on(reader.read(&mut buf)), Ok(16));
+        assert_eq!(buf[..16], [[4; 8], [5; 8]].concat()[..]);
+
+        // A single packet report, ended by a zero length packet. Zero length packets before a report
+        // are skipped.
+        for packet in [&[][..], &[6; 8], &[]] {
+            host_out.push(packet);
+        }
+        assert_eq!(embassy_futures::block_on(reader.read(&mut buf)), Ok(8));
+        assert_eq!(buf[..8], [6; 8]);
+        assert!(host_out.is_empty());
+    }
+
+    #[test]
+    fn reads_reports_of_one_packet() {
+        let state = State::new();
+        let (mut reader, host_out) = new_reader::<8>(&state, Some(8));
+        let mut buf = [0; 8];
+
+        // A full packet is a whole report when `N` is the max packet size.
+        host_out.push(&[1; 8]);
+        host_out.push(&[2; 3]);
+        assert_eq!(embassy_futures::block_on(reader.read(&mut buf)), Ok(8));
+        assert_eq!(buf, [1; 8]);
+        assert_eq!(embassy_futures::block_on(reader.read(&mut buf)), Ok(3));
+        assert_eq!(buf[..3], [2; 3]);
+    }
+
+    #[test]
+    fn resyncs_after_a_dropped_read() {
+        let state = State::new();
+        let (mut reader, host_out) = new_reader::<20>(&state, Some(8));
+        let mut buf = [0; 20];
+
+        host_out.push(&[1; 8]);
+        {
+            let read = pin!(reader.read(&mut buf));
+            assert!(poll_once(read).is_pending());
+        }
+        host_out.push(&[2; 8]);
+        host_out.push(&[3; 4]);
+        assert_eq!(
+            embassy_futures::block_on(reader.read(&mut buf)),
+            Err(ReadError::Sync(8..20))
+        );
+        assert_eq!(buf[..8], [1; 8]);
+        assert_eq!(buf[8..16], [2; 8]);
+    }
+
+    #[test]
+    fn reads_reports_from_the_control_pipe() {
+        let state = State::new();
+        let mut control = Control::new(
+            InterfaceNumber(0),
+            &[],
+            None,
+            &state.out_report_offset,
+            Some(&state.control_out_report),
+        );
+
+        // Without an OUT endpoint.
+        let (mut reader, _) = new_reader::<8>(&state, None);
+        let mut buf = [0; 8];
+        assert!(matches!(set_report(&mut control, &[1, 2]), Some(OutResponse::Accepted)));
+        // The next report is rejected until the first one is read.
+        assert!(matches!(set_report(&mut control, &[3]), Some(OutResponse::Rejected)));
+        assert_eq!(embassy_futures::block_on(reader.read(&mut buf)), Ok(2));
+        assert_eq!(buf[..2], [1, 2]);
+
+        // With an OUT endpoint, reports come from both pipes.
+        let (mut reader, host_out) = new_reader::<8>(&state, Some(8));
+        assert!(matches!(set_report(&mut control, &[3]), Some(OutResponse::Accepted)));
+        assert_eq!(embassy_futures::block_on(reader.read(&mut buf)), Ok(1));
+        assert_eq!(buf[..1], [3]);
+        host_out.push(&[4; 5]);
+        assert_eq!(embassy_futures::block_on(reader.read(&mut buf)), Ok(5));
+        assert_eq!(buf[..5], [4; 5]);
+
+        // Reports longer than the control buffer go to the request handler, rejected without one.
+        assert!(matches!(
+            set_report(&mut control, &[0; MAX_CONTROL_OUT_REPORT_LEN + 1]),
+            Some(OutResponse::Rejected)
+        ));
+    }
+}
@@ -0,0 +1,869 @@
+//! USB Mass Storage class implementation, using the Bulk-Only Transport and the SCSI transparent
+//! command set.
+//!
+//! The class exposes a single logical unit, backed by a [`BlockDevice`] with 512 byte blocks.
+
+use core::convert::Infallible;
+use core::future::{pending, poll_fn};
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+
+use aligned::Aligned;
+use block_device_driver::{BlockDevice, blocks_to_slice, blocks_to_slice_mut};
+use embassy_futures::select::{Either, select};
+use embassy_sync::waitqueue::AtomicWaker;
+
+use crate::control::{InResponse, OutResponse, Recipient, Request, RequestType};
+use crate::driver::{Driver, Endpoint, EndpointError, EndpointIn, EndpointOut};
+use crate::types::InterfaceNumber;
+use crate::{Builder, EndpointHalt, Handler};
+
+const USB_CLASS_MSC: u8 = 0x08;
+const MSC_SUBCLASS_SCSI: u8 = 0x06;
+const MSC_PROTOCOL_BULK_ONLY: u8 = 0x50;
+
+const REQ_GET_MAX_LUN: u8 = 0xfe;
+const REQ_BULK_ONLY_MASS_STORAGE_RESET: u8 = 0xff;
+
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CBW_LEN: usize = 31;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+const CSW_LEN: usize = 13;
+
+const SCSI_TEST_UNIT_READY: u8 = 0x00;
+const SCSI_REQUEST_SENSE: u8 = 0x03;
+const SCSI_INQUIRY: u8 = 0x12;
+const SCSI_MODE_SENSE_6: u8 = 0x1a;
+const SCSI_PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1e;
+const SCSI_READ_CAPACITY_10: u8 = 0x25;
+const SCSI_READ_10: u8 = 0x28;
+const SCSI_WRITE_10: u8 = 0x2a;
+
+const SENSE_KEY_NO_SENSE: u8 = 0x00;
+const SENSE_KEY_MEDIUM_ERROR: u8 = 0x03;
+const SENSE_KEY_ILLEGAL_REQUEST: u8 = 0x05;
+
+const ASC_UNRECOVERED_READ_ERROR: u8 = 0x11;
+const ASC_WRITE_ERROR: u8 = 0x0c;
+const ASC_INVALID_COMMAND_OPERATION_CODE: u8 = 0x20;
+const ASC_LBA_OUT_OF_RANGE: u8 = 0x21;
+const ASC_INVALID_FIELD_IN_CDB: u8 = 0x24;
+
+const INQUIRY_LEN: usize = 36;
+const REQUEST_SENSE_LEN: usize = 18;
+const MODE_SENSE_6_LEN: usize = 4;
+const READ_CAPACITY_10_LEN: usize = 8;
+
+/// Size of the blocks of the [`BlockDevice`] served by [`MscClass`], in bytes.
+pub const BLOCK_SIZE: usize = 512;
+
+/// Configuration for the mass storage class.
+pub struct Config<'d> {
+    /// Vendor identification reported to the host. At most 8 ASCII characters are used.
+    pub vendor: &'d str,
+
+    /// Product identification reported to the host. At most 16 ASCII characters are used.
+    pub product: &'d str,
+
+    /// Product revision reported to the host. At most 4 ASCII characters are used.
+    pub revision: &'d str,
+
+    /// Max packet size for both the IN and OUT endpoints. At most 512.
+    pub max_packet_size: u16,
+}
+
+/// Internal state for the mass storage class.
+pub struct State<'d> {
+    control: MaybeUninit<Control<'d>>,
+    shared: ControlShared,
+    halt_in: EndpointHalt,
+    halt_out: EndpointHalt,
+}
+
+impl<'d> Default for State<'d> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'d> State<'d> {
+    /// Create a new `State`.
+    pub const fn new() -> Self {
+        Self {
+            control: MaybeUninit::uninit(),
+            shared: ControlShared::new(),
+            halt_in: EndpointHalt::new(),
+            halt_out: EndpointHalt::new(),
+        }
+    }
+}
+
+/// Shared data between Control and MscClass
+struct ControlShared {
+    reset: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl ControlShared {
+    const fn new() -> Self {
+        Self {
+            reset: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    fn reset(&self) {
+        self.reset.store(true, Ordering::Relaxed);
+        self.waker.wake();
+    }
+
+    async fn wait_reset(&self) {
+        poll_fn(|cx| {
+            self.waker.register(cx.waker());
+            if self.reset.load(Ordering::Relaxed) {
+                self.reset.store(false, Ordering::Relaxed);
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+struct Control<'d> {
+    if_num: InterfaceNumber,
+    shared: &'d ControlShared,
+    halt_in: &'d EndpointHalt,
+    halt_out: &'d EndpointHalt,
+}
+
+impl<'d> Handler for Control<'d> {
+    fn reset(&mut self) {
+        self.shared.reset();
+    }
+
+    fn control_out(&mut self, req: Request, _data: &[u8]) -> Option<OutResponse> {
+        if (req.request_type, req.recipient, req.index)
+            != (RequestType::Class, Recipient::Interface, self.if_num.0 as u16)
+        {
+            return None;
+        }
+
+        match req.request {
+            REQ_BULK_ONLY_MASS_STORAGE_RESET if req.value == 0 && req.length == 0 => {
+                debug!("msc: bulk-only mass storage reset");
+                // Part of the reset recovery: the host can now clear the halts of the endpoints.
+                self.halt_in.release();
+                self.halt_out.release();
+                self.shared.reset();
+                Some(OutResponse::Accepted)
+            }
+            _ => Some(OutResponse::Rejected),
+        }
+    }
+
+    fn control_in<'a>(&'a mut self, req: Request, buf: &'a mut [u8]) -> Option<InResponse<'a>> {
+        if (req.request_type, req.recipient, req.index)
+            != (RequestType::Class, Recipient::Interface, self.if_num.0 as u16)
+        {
+            return None;
+        }
+
+        match req.request {
+            REQ_GET_MAX_LUN if req.value == 0 && req.length == 1 => {
+                buf[0] = 0;
+                Some(InResponse::Accepted(&buf[..1]))
+            }
+            _ => Some(InResponse::Rejected),
+        }
+    }
+}
+
+/// Command Block Wrapper, sent by the host to start a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cbw {
+    tag: u32,
+    data_len: u32,
+    dir_in: bool,
+    cb: [u8; 16],
+}
+
+impl Cbw {
+    /// Parse a CBW, returning `None` if it's not valid and meaningful.
+    fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() != CBW_LEN || u32::from_le_bytes(buf[0..4].try_into().unwrap()) != CBW_SIGNATURE {
+            return None;
+        }
+        let lun = buf[13] & 0x0f;
+        let cb_len = (buf[14] & 0x1f) as usize;
+        if lun != 0 || !(1..=16).contains(&cb_len) {
+            return None;
+        }
+        let mut cb = [0; 16];
+        cb[..cb_len].copy_from_slice(&buf[15..15 + cb_len]);
+        Some(Self {
+            tag: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+            data_len: u32::from_le_bytes(buf[8..12].try_into().unwrap()),
+            dir_in: buf[12] & 0x80 != 0,
+            cb,
+        })
+    }
+}
+
+/// Status of a command, reported to the host in the Command Status Wrapper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Status {
+    Passed = 0,
+    Failed = 1,
+    PhaseError = 2,
+}
+
+fn csw(tag: u32, residue: u32, status: Status) -> [u8; CSW_LEN] {
+    let mut buf = [0; CSW_LEN];
+    buf[0..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+    buf[4..8].copy_from_slice(&tag.to_le_bytes());
+    buf[8..12].copy_from_slice(&residue.to_le_bytes());
+    buf[12] = status as u8;
+    buf
+}
+
+/// Sense data of the last command, reported by REQUEST SENSE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sense {
+    key: u8,
+    asc: u8,
+}
+
+impl Sense {
+    const NO_SENSE: Self = Self::new(SENSE_KEY_NO_SENSE, 0);
+
+    const fn new(key: u8, asc: u8) -> Self {
+        Self { key, asc }
+    }
+
+    fn to_bytes(self) -> [u8; REQUEST_SENSE_LEN] {
+        let mut buf = [0; REQUEST_SENSE_LEN];
+        buf[0] = 0x70; // current error, fixed format
+        buf[2] = self.key;
+        buf[7] = (REQUEST_SENSE_LEN - 8) as u8;
+        buf[12] = self.asc;
+        buf
+    }
+}
+
+fn inquiry_data(config: &Config<'_>) -> [u8; INQUIRY_LEN] {
+    let mut buf = [b' '; INQUIRY_LEN];
+    buf[0] = 0x00; // direct access block device
+    buf[1] = 0x80; // removable medium
+    buf[2] = 0x04; // SPC-2
+    buf[3] = 0x02; // response data format
+    buf[4] = (INQUIRY_LEN - 5) as u8;
+    buf[5..8].fill(0);
+    for (range, s) in [
+        (8..16, config.vendor),
+        (16..32, config.product),
+        (32..36, config.revision),
+    ] {
+        let n = s.len().min(range.len());
+        buf[range][..n].copy_from_slice(&s.as_bytes()[..n]);
+    }
+    buf
+}
+
+/// Data transfer of a command, as requested by the host.
+struct Transfer {
+    dir_in: bool,
+    len: u32,
+    done: u32,
+    /// Whether the host ended the transfer early with a short packet.
+    ended: bool,
+}
+
+impl Transfer {
+    fn remaining(&self) -> u32 {
+        self.len - self.done
+    }
+
+    /// Whether the host expects at least `len` bytes in the given direction.
+    fn expects(&self, dir_in: bool, len: u32) -> bool {
+        self.len >= len && (self.dir_in == dir_in || self.len == 0)
+    }
+}
+
+/// USB Mass Storage class, exposing a block device to the host as a removable disk.
+pub struct MscClass<'d, D: Driver<'d>> {
+    read_ep: D::EndpointOut,
+    write_ep: D::EndpointIn,
+    shared: &'d ControlShared,
+    halt_in: &'d EndpointHalt,
+    halt_out: &'d EndpointHalt,
+    inquiry: [u8; INQUIRY_LEN],
+    sense: Sense,
+}
+
+impl<'d, D: Driver<'d>> MscClass<'d, D> {
+    /// Creates a new MscClass with the provided UsbBus and config.
+    pub fn new(builder: &mut Builder<'d, D>, state: &'d mut State<'d>, config: Config<'_>) -> Self {
+        assert!(config.max_packet_size as usize <= BLOCK_SIZE);
+
+        let mut func = builder.function(USB_CLASS_MSC, MSC_SUBCLASS_SCSI, MSC_PROTOCOL_BULK_ONLY);
+        let mut iface = func.interface();
+        let if_num = iface.interface_number();
+        let mut alt = iface.alt_setting(USB_CLASS_MSC, MSC_SUBCLASS_SCSI, MSC_PROTOCOL_BULK_ONLY, None);
+        let read_ep = alt.endpoint_bulk_out(None, config.max_packet_size);
+        let write_ep = alt.endpoint_bulk_in(None, config.max_packet_size);
+        drop(func);
+
+        let control = state.control.write(Control {
+            if_num,
+            shared: &state.shared,
+            halt_in: &state.halt_in,
+            halt_out: &state.halt_out,
+        });
+        builder.handler(control);
+        builder.endpoint_halt(read_ep.info().addr, &state.halt_out);
+        builder.endpoint_halt(write_ep.info().addr, &state.halt_in);
+
+        Self {
+            read_ep,
+            write_ep,
+            shared: &state.shared,
+            halt_in: &state.halt_in,
+            halt_out: &state.halt_out,
+            inquiry: inquiry_data(&config),
+            sense: Sense::NO_SENSE,
+        }
+    }
+
+    /// Serve `device` to the host.
+    ///
+    /// Blocks are read and written through `buf`, which must hold at least one block. A larger
+    /// buffer lets the class read and write several blocks at once.
+    pub async fn run<B: BlockDevice<BLOCK_SIZE>>(
+        &mut self,
+        device: &mut B,
+        buf: &mut [Aligned<B::Align, [u8; BLOCK_SIZE]>],
+    ) -> ! {
+        assert!(!buf.is_empty());
+
+        let shared = self.shared;
+        loop {
+            self.read_ep.wait_enabled().await;
+            shared.reset.store(false, Ordering::Relaxed);
+            self.sense = Sense::NO_SENSE;
+            match select(self.serve(device, buf), shared.wait_reset()).await {
+                Either::First(Err(e)) => debug!("msc: endpoint error {:?}", e),
+                Either::Second(()) => debug!("msc: reset"),
+            }
+        }
+    }
+
+    async fn serve<B: BlockDevice<BLOCK_SIZE>>(
+        &mut self,
+        device: &mut B,
+        buf: &mut [Aligned<B::Align, [u8; BLOCK_SIZE]>],
+    ) -> Result<Infallible, EndpointError> {
+        let mps = self.read_ep.info().max_packet_size as usize;
+        loop {
+            let block = blocks_to_slice_mut(&mut buf[..1]);
+            let n = self.read_ep.read(&mut block[..mps]).await?;
+            let Some(cbw) = Cbw::parse(&block[..n]) else {
+                // Invalid CBW: stall both endpoints until the host performs a reset recovery, a
+                // Bulk-Only Mass Storage Reset followed by clearing the halts.
+                warn!("msc: invalid CBW");
+                self.halt_in.halt_until_released();
+                self.halt_out.halt_until_released();
+                return pending().await;
+            };
+
+            let mut xfer = Transfer {
+                dir_in: cbw.dir_in,
+                len: cbw.data_len,
+                done: 0,
+                ended: false,
+            };
+            let status = self.command(&cbw.cb, &mut xfer, device, buf).await?;
+            trace!("msc: command {:02x} status {}", cbw.cb[0], status as u8);
+
+            // The data drained below isn't processed, so it's part of the residue.
+            let residue = xfer.remaining();
+            // The device must not stop in the middle of the data the host expects.
+            if xfer.remaining() > 0 && !xfer.ended {
+                if xfer.dir_in {
+                    self.halt_in.halt();
+                    self.halt_in.wait_cleared().await;
+                } else {
+                    self.drain(&mut xfer, blocks_to_slice_mut(&mut buf[..1])).await?;
+                }
+            }
+
+            self.write_ep.write(&csw(cbw.tag, residue, status)).await?;
+        }
+    }
+
+    async fn command<B: BlockDevice<BLOCK_SIZE>>(
+        &mut self,
+        cb: &[u8; 16],
+        xfer: &mut Transfer,
+        device: &mut B,
+        buf: &mut [Aligned<B::Align, [u8; BLOCK_SIZE]>],
+    ) -> Result<Status, EndpointError> {
+        if cb[0] != SCSI_REQUEST_SENSE {
+            self.sense = Sense::NO_SENSE;
+        }
+
+        match cb[0] {
+            SCSI_TEST_UNIT_READY | SCSI_PREVENT_ALLOW_MEDIUM_REMOVAL => Ok(Status::Passed),
+            SCSI_REQUEST_SENSE => {
+                let data = self.sense.to_bytes();
+                self.sense = Sense::NO_SENSE;
+                self.respond(xfer, &data, cb[4] as usize).await
+            }
+            SCSI_INQUIRY => {
+                if cb[1] & 0x01 != 0 {
+                    // Vital product data pages aren't supported.
+                    return Ok(self.fail(Sense::new(SENSE_KEY_ILLEGAL_REQUEST, ASC_INVALID_FIELD_IN_CDB)));
+                }
+                let data = self.inquiry;
+                let alloc_len = u16::from_be_bytes([cb[3], cb[4]]) as usize;
+                self.respond(xfer, &data, alloc_len).await
+            }
+            SCSI_MODE_SENSE_6 => {
+                let mut data = [0; MODE_SENSE_6_LEN];
+                data[0] = (MODE_SENSE_6_LEN - 1) as u8;
+                self.respond(xfer, &data, cb[4] as usize).await
+            }
+            SCSI_READ_CAPACITY_10 => {
+                let Some(block_count) = self.block_count(device, ASC_UNRECOVERED_READ_ERROR).await else {
+                    return Ok(Status::Failed);
+                };
+                let mut data = [0; READ_CAPACITY_10_LEN];
+                data[0..4].copy_from_slice(&block_count.saturating_sub(1).to_be_bytes());
+                data[4..8].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+                self.respond(xfer, &data, READ_CAPACITY_10_LEN).await
+            }
+            SCSI_READ_10 => self.read_10(cb, xfer, device, buf).await,
+            SCSI_WRITE_10 => self.write_10(cb, xfer, device, buf).await,
+            op => {
+                debug!("msc: unsupported command {:02x}", op);
+                Ok(self.fail(Sense::new(
+                    SENSE_KEY_ILLEGAL_REQUEST,
+                    ASC_INVALID_COMMAND_OPERATION_CODE,
+                )))
+            }
+        }
+    }
+
+    async fn read_10<B: BlockDevice<BLOCK_SIZE>>(
+        &mut self,
+        cb: &[u8; 16],
+        xfer: &mut Transfer,
+        device: &mut B,
+        buf: &mut [Aligned<B::Align, [u8; BLOCK_SIZE]>],
+    ) -> Result<Status, EndpointError> {
+        let (mut lba, mut count) = match self.block_range(cb, device, ASC_UNRECOVERED_READ_ERROR).await {
+            Ok(range) => range,
+            Err(status) => return Ok(status),
+        };
+        if !xfer.expects(true, count * BLOCK_SIZE as u32) {
+            return Ok(Status::PhaseError);
+        }
+
+        while count > 0 {
+            let n = buf.len().min(count as usize);
+            if device.read(lba, &mut buf[..n]).await.is_err() {
+                warn!("msc: read error at block {}", lba);
+                return Ok(self.fail(Sense::new(SENSE_KEY_MEDIUM_ERROR, ASC_UNRECOVERED_READ_ERROR)));
+            }
+            let data = blocks_to_slice(&buf[..n]);
+            self.write_ep.write_transfer(data, false).await?;
+            xfer.done += data.len() as u32;
+            lba += n as u32;
+            count -= n as u32;
+        }
+        Ok(Status::Passed)
+    }
+
+    async fn write_10<B: BlockDevice<BLOCK_SIZE>>(
+        &mut self,
+        cb: &[u8; 16],
+        xfer: &mut Transfer,
+        device: &mut B,
+        buf: &mut [Aligned<B::Align, [u8; BLOCK_SIZE]>],
+    ) -> Result<Status, EndpointError> {
+        let (mut lba, mut count) = match self.block_range(cb, device, ASC_WRITE_ERROR).await {
+            Ok(range) => range,
+            Err(status) => return Ok(status),
+        };
+        if !xfer.expects(false, count * BLOCK_SIZE as u32) {
+            return Ok(Status::PhaseError);
+        }
+
+        while count > 0 {
+            let n = buf.len().min(count as usize);
+            let data = blocks_to_slice_mut(&mut buf[..n]);
+            self.receive(xfer, data).await?;
+            if xfer.ended {
+                return Ok(Status::PhaseError);
+            }
+            if device.write(lba, &buf[..n]).await.is_err() {
+                warn!("msc: write error at block {}", lba);
+                return Ok(self.fail(Sense::new(SENSE_KEY_MEDIUM_ERROR, ASC_WRITE_ERROR)));
+            }
+            lba += n as u32;
+            count -= n as u32;
+        }
+        Ok(Status::Passed)
+    }
+
+    /// Get the block range of a READ(10) or WRITE(10) command, checking it against the device size.
+    async fn block_range<B: BlockDevice<BLOCK_SIZE>>(
+        &mut self,
+        cb: &[u8; 16],
+        device: &mut B,
+        asc: u8,
+    ) -> Result<(u32, u32), Status> {
+        let lba = u32::from_be_bytes(cb[2..6].try_into().unwrap());
+        let count = u16::from_be_bytes([cb[7], cb[8]]) as u32;
+        let block_count = self.block_count(device, asc).await.ok_or(Status::Failed)?;
+        if lba.checked_add(count).is_none_or(|end| end > block_count) {
+            return Err(self.fail(Sense::new(SENSE_KEY_ILLEGAL_REQUEST, ASC_LBA_OUT_OF_RANGE)));
+        }
+        Ok((lba, count))
+    }
+
+    async fn block_count<B: BlockDevice<BLOCK_SIZE>>(&mut self, device: &mut B, asc: u8) -> Option<u32> {
+        match device.size().await {
+            Ok(size) => Some((size / BLOCK_SIZE as u64).min(u32::MAX as u64) as u32),
+            Err(_) => {
+                warn!("msc: failed to get the device size");
+                self.fail(Sense::new(SENSE_KEY_MEDIUM_ERROR, asc));
+                None
+            }
+        }
+    }
+
+    fn fail(&mut self, sense: Sense) -> Status {
+        self.sense = sense;
+        Status::Failed
+    }
+
+    /// Send the response of a command, truncated to the allocation length of the command.
+    async fn respond(&mut self, xfer: &mut Transfer, data: &[u8], alloc_len: usize) -> Result<Status, EndpointError> {
+        let data = &data[..data.len().min(alloc_len)];
+        if !xfer.expects(true, data.len() as u32) {
+            return Ok(Status::PhaseError);
+        }
+        self.write_ep.write_transfer(data, false).await?;
+        xfer.done += data.len() as u32;
+        Ok(Status::Passed)
+    }
+
+    /// Receive `data.len()` bytes of the data sent by the host, or less if the host ends the
+    /// transfer early.
+    async fn receive(&mut self, xfer: &mut Transfer, data: &mut [u8]) -> Result<(), EndpointError> {
+        let mps = self.read_ep.info().max_packet_size as usize;
+        for chunk in data.chunks_mut(mps) {
+            let n = self.read_ep.read(chunk).await?;
+            xfer.done += n as u32;
+            if n < chunk.len() {
+                xfer.ended = true;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive and discard the rest of the data sent by the host.
+    async fn drain(&mut self, xfer: &mut Transfer, block: &mut [u8]) -> Result<(), EndpointError> {
+        let mps = self.read_ep.info().max_packet_size as usize;
+        while xfer.remaining() > 0 {
+            let len = mps.min(xfer.remaining() as usize);
+            let n = self.read_ep.read(&mut block[..len]).await?;
+            xfer.done += n as u32;
+            if n < len {
+                xfer.ended = true;
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::pin::pin;
+    use std::vec::Vec;
+
+    use aligned::A4;
+    use embassy_futures::poll_once;
+
+    use super::*;
+    use crate::driver::EndpointType;
+    use crate::test_driver::{self, Packets};
+
+    /// A device of 4 blocks, each filled with its index.
+    struct Ram([[u8; BLOCK_SIZE]; 4]);
+
+    impl Ram {
+        fn new() -> Self {
+            Self(core::array::from_fn(|i| [i as u8; BLOCK_SIZE]))
+        }
+    }
+
+    impl BlockDevice<BLOCK_SIZE> for Ram {
+        type Error = ();
+        type Align = A4;
+
+        async fn read(&mut self, lba: u32, data: &mut [Aligned<A4, [u8; BLOCK_SIZE]>]) -> Result<(), ()> {
+            for (i, block) in data.iter_mut().enumerate() {
+                **block = self.0[lba as usize + i];
+            }
+            Ok(())
+        }
+
+        async fn write(&mut self, lba: u32, data: &[Aligned<A4, [u8; BLOCK_SIZE]>]) -> Result<(), ()> {
+            for (i, block) in data.iter().enumerate() {
+                self.0[lba as usize + i] = **block;
+            }
+            Ok(())
+        }
+
+        async fn size(&mut self) -> Result<u64, ()> {
+            Ok((self.0.len() * BLOCK_SIZE) as u64)
+        }
+    }
+
+    /// A class on the test driver, with the queues of the packets from and to the host.
+    fn class<'d>(state: &'d State<'d>) -> (MscClass<'d, test_driver::Driver>, Packets, Packets) {
+        let (read_ep, host_out) = test_driver::EndpointOut::new(1, EndpointType::Bulk, 64);
+        let (write_ep, host_in) = test_driver::EndpointIn::new(1, EndpointType::Bulk, 64);
+        let class = MscClass {
+            read_ep,
+            write_ep,
+            shared: &state.shared,
+            halt_in: &state.halt_in,
+            halt_out: &state.halt_out,
+            inquiry: inquiry(),
+            sense: Sense::NO_SENSE,
+        };
+        (class, host_out, host_in)
+    }
+
+    fn inquiry() -> [u8; INQUIRY_LEN] {
+        inquiry_data(&Config {
+            vendor: "Embassy",
+            product: "Test",
+            revision: "1.0",
+            max_packet_size: 64,
+        })
+    }
+
+    fn csw_bytes(residue: u32, status: Status) -> Vec<u8> {
+        csw(0x1234_5678, residue, status).to_vec()
+    }
+
+    fn read_10(lba: u32, count: u16) -> [u8; 10] {
+        let mut cb = [0; 10];
+        cb[0] = SCSI_READ_10;
+        cb[2..6].copy_from_slice(&lba.to_be_bytes());
+        cb[7..9].copy_from_slice(&count.to_be_bytes());
+        cb
+    }
+
+    fn write_10(lba: u32, count: u16) -> [u8; 10] {
+        let mut cb = read_10(lba, count);
+        cb[0] = SCSI_WRITE_10;
+        cb
+    }
+
+    fn cbw_bytes(data_len: u32, flags: u8, cb: &[u8]) -> [u8; CBW_LEN] {
+        let mut buf = [0; CBW_LEN];
+        buf[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        buf[4..8].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        buf[8..12].copy_from_slice(&data_len.to_le_bytes());
+        buf[12] = flags;
+        buf[14] = cb.len() as u8;
+        buf[15..15 + cb.len()].copy_from_slice(cb);
+        buf
+    }
+
+    #[test]
+    fn parses_cbw() {
+        let cbw = Cbw::parse(&cbw_bytes(36, 0x80, &[SCSI_INQUIRY, 0, 0, 0, 36, 0])).unwrap();
+        assert_eq!(cbw.tag, 0x1234_5678);
+        assert_eq!(cbw.data_len, 36);
+        assert!(cbw.dir_in);
+        assert_eq!(cbw.cb[..6], [SCSI_INQUIRY, 0, 0, 0, 36, 0]);
+        assert!(cbw.cb[6..].iter().all(|&b| b == 0));
+
+        let mut bad_signature = cbw_bytes(0, 0, &[SCSI_TEST_UNIT_READY; 6]);
+        bad_signature[0] ^= 0xff;
+        assert_eq!(Cbw::parse(&bad_signature), None);
+
+        let mut bad_lun = cbw_bytes(0, 0, &[SCSI_TEST_UNIT_READY; 6]);
+        bad_lun[13] = 1;
+        assert_eq!(Cbw::parse(&bad_lun), None);
+
+        assert_eq!(Cbw::parse(&cbw_bytes(0, 0, &[])), None);
+        assert_eq!(Cbw::parse(&cbw_bytes(0, 0, &[SCSI_TEST_UNIT_READY; 6])[..30]), None);
+    }
+
+    #[test]
+    fn builds_inquiry_data() {
+        let data = inquiry_data(&Config {
+            vendor: "Embassy",
+            product: "A rather long product name",
+            revision: "1.0",
+            max_packet_size: 64,
+        });
+        assert_eq!(data[..8], [0x00, 0x80, 0x04, 0x02, 31, 0, 0, 0]);
+        assert_eq!(&data[8..16], b"Embassy ");
+        assert_eq!(&data[16..32], b"A rather long pr");
+        assert_eq!(&data[32..36], b"1.0 ");
+    }
+
+    #[test]
+    fn fails_unsupported_commands() {
+        let state = State::new();
+        let (mut class, host_out, host_in) = class(&state);
+        let mut device = Ram::new();
+        let mut buf = [Aligned([0; BLOCK_SIZE])];
+        let mut serve = pin!(class.serve(&mut device, &mut buf));
+
+        host_out.push(&cbw_bytes(0, 0, &[0xff; 6]));
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert_eq!(host_in.take(), [csw_bytes(0, Status::Failed)]);
+
+        host_out.push(&cbw_bytes(
+            REQUEST_SENSE_LEN as u32,
+            0x80,
+            &[SCSI_REQUEST_SENSE, 0, 0, 0, 18, 0],
+        ));
+        assert!(poll_once(serve.as_mut()).is_pending());
+        let packets = host_in.take();
+        assert_eq!(packets[0][2], SENSE_KEY_ILLEGAL_REQUEST);
+        assert_eq!(packets[0][12], ASC_INVALID_COMMAND_OPERATION_CODE);
+        assert_eq!(packets[1], csw_bytes(0, Status::Passed));
+
+        // The data the host wants to send is drained, and reported as not processed.
+        host_out.push(&cbw_bytes(100, 0, &[0xff; 6]));
+        host_out.push(&[0; 64]);
+        host_out.push(&[0; 36]);
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert!(host_out.is_empty());
+        assert_eq!(host_in.take(), [csw_bytes(100, Status::Failed)]);
+
+        // The data the host wants to receive is cut short by halting the IN endpoint.
+        host_out.push(&cbw_bytes(100, 0x80, &[0xff; 6]));
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert!(state.halt_in.is_halted());
+        assert!(host_in.is_empty());
+        state.halt_in.clear();
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert_eq!(host_in.take(), [csw_bytes(100, Status::Failed)]);
+    }
+
+    #[test]
+    fn reports_the_residue_of_short_transfers() {
+        let state = State::new();
+        let (mut class, host_out, host_in) = class(&state);
+        let mut device = Ram::new();
+        let mut buf = [Aligned([0; BLOCK_SIZE])];
+        let mut serve = pin!(class.serve(&mut device, &mut buf));
+
+        // The host asks for more than the inquiry data.
+        host_out.push(&cbw_bytes(64, 0x80, &[SCSI_INQUIRY, 0, 0, 0, 64, 0]));
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert_eq!(host_in.take().concat(), &inquiry()[..]);
+        assert!(state.halt_in.is_halted());
+        state.halt_in.clear();
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert_eq!(host_in.take(), [csw_bytes(64 - INQUIRY_LEN as u32, Status::Passed)]);
+
+        // The allocation length cuts the inquiry data short.
+        host_out.push(&cbw_bytes(8, 0x80, &[SCSI_INQUIRY, 0, 0, 0, 8, 0]));
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert_eq!(host_in.take(), [inquiry()[..8].to_vec(), csw_bytes(0, Status::Passed)]);
+        assert!(!state.halt_in.is_halted());
+
+        // The host ends the data of a write early.
+        host_out.push(&cbw_bytes(BLOCK_SIZE as u32, 0, &write_10(1, 1)));
+        host_out.push(&[0xaa; 64]);
+        host_out.push(&[0xaa; 16]);
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert_eq!(host_in.take(), [csw_bytes(BLOCK_SIZE as u32 - 80, Status::PhaseError)]);
+
+        // Nothing was written.
+        host_out.push(&cbw_bytes(BLOCK_SIZE as u32, 0x80, &read_10(1, 1)));
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert_eq!(host_in.take()[..BLOCK_SIZE / 64].concat(), [1; BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn reports_phase_errors() {
+        let state = State::new();
+        let (mut class, host_out, host_in) = class(&state);
+        let mut device = Ram::new();
+        let mut buf = [Aligned([0; BLOCK_SIZE])];
+        let mut serve = pin!(class.serve(&mut device, &mut buf));
+
+        // The host expects less data than the blocks read.
+        host_out.push(&cbw_bytes(0, 0x80, &read_10(2, 1)));
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert_eq!(host_in.take(), [csw_bytes(0, Status::PhaseError)]);
+
+        // The host sends the data of a read.
+        host_out.push(&cbw_bytes(BLOCK_SIZE as u32, 0, &read_10(2, 1)));
+        for _ in 0..BLOCK_SIZE / 64 {
+            host_out.push(&[0; 64]);
+        }
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert!(host_out.is_empty());
+        assert_eq!(host_in.take(), [csw_bytes(BLOCK_SIZE as u32, Status::PhaseError)]);
+
+        // A well-formed read.
+        host_out.push(&cbw_bytes(BLOCK_SIZE as u32, 0x80, &read_10(2, 1)));
+        assert!(poll_once(serve.as_mut()).is_pending());
+        let packets = host_in.take();
+        assert_eq!(packets[..BLOCK_SIZE / 64].concat(), [2; BLOCK_SIZE]);
+        assert_eq!(packets[BLOCK_SIZE / 64..], [csw_bytes(0, Status::Passed)]);
+    }
+
+    #[test]
+    fn stays_halted_until_reset_after_an_invalid_cbw() {
+        let state = State::new();
+        let (mut class, host_out, host_in) = class(&state);
+        let mut control = Control {
+            if_num: InterfaceNumber(0),
+            shared: &state.shared,
+            halt_in: &state.halt_in,
+            halt_out: &state.halt_out,
+        };
+        let mut device = Ram::new();
+        let mut buf = [Aligned([0; BLOCK_SIZE])];
+        let mut serve = pin!(class.serve(&mut device, &mut buf));
+
+        host_out.push(&cbw_bytes(0, 0, &[SCSI_TEST_UNIT_READY; 6])[..30]);
+        assert!(poll_once(serve.as_mut()).is_pending());
+        assert!(host_in.is_empty());
+        for halt in [&state.halt_in, &state.halt_out] {
+            assert!(halt.is_halted());
+            assert!(halt.is_held());
+        }
+
+        let reset = Request::parse(&[0x21, REQ_BULK_ONLY_MASS_STORAGE_RESET, 0, 0, 0, 0, 0, 0]);
+        assert!(matches!(control.control_out(reset, &[]), Some(OutResponse::Accepted)));
+        // The host can now clear the halts.
+        for halt in [&state.halt_in, &state.halt_out] {
+            assert!(halt.is_halted());
+            assert!(!halt.is_held());
+        }
+        assert!(state.shared.reset.load(Ordering::Relaxed));
+    }
+}
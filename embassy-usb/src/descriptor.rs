@@ -321,7 +321,7 @@ impl<'a> DescriptorWriter<'a> {
 ///
 /// All device descriptors are always 18 bytes, so there's no need for
 /// a variable-length buffer or DescriptorWriter.
-pub(crate) fn device_descriptor(config: &Config) -> [u8; 18] {
+pub(crate) fn device_descriptor(config: &Config, serial_number: bool) -> [u8; 18] {
     [
         18,   // bLength
         0x01, // bDescriptorType
@@ -336,11 +336,11 @@ pub(crate) fn device_descriptor(config: &Config) -> [u8; 18] {
         config.product_id as u8,
         (config.product_id >> 8) as u8, // idProduct
         config.device_release as u8,
-        (config.device_release >> 8) as u8,    // bcdDevice
-        config.manufacturer.map_or(0, |_| 1),  // iManufacturer
-        config.product.map_or(0, |_| 2),       // iProduct
-        config.serial_number.map_or(0, |_| 3), // iSerialNumber
-        1,                                     // bNumConfigurations
+        (config.device_release >> 8) as u8,   // bcdDevice
+        config.manufacturer.map_or(0, |_| 1), // iManufacturer
+        config.product.map_or(0, |_| 2),      // iProduct
+        if serial_number { 3 } else { 0 },    // iSerialNumber
+        1,                                    // bNumConfigurations
     ]
 }
 
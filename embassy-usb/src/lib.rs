@@ -14,6 +14,8 @@ pub mod control;
 pub mod descriptor;
 mod descriptor_reader;
 pub mod msos;
+#[cfg(test)]
+mod test_driver;
 pub mod types;
 
 mod config {
@@ -21,11 +23,16 @@ mod config {
     include!(concat!(env!("OUT_DIR"), "/config.rs"));
 }
 
-use embassy_futures::select::{Either, select};
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+
+use embassy_futures::select::{Either3, select3};
+use embassy_sync::waitqueue::AtomicWaker;
 use heapless::Vec;
 
 pub use crate::builder::{Builder, Config, FunctionBuilder, InterfaceAltBuilder, InterfaceBuilder, UsbVersion};
-use crate::config::{MAX_HANDLER_COUNT, MAX_INTERFACE_COUNT};
+use crate::config::{MAX_ENDPOINT_HALT_COUNT, MAX_HANDLER_COUNT, MAX_INTERFACE_COUNT, MAX_STRING_PROVIDER_COUNT};
 use crate::control::{InResponse, OutResponse, Recipient, Request, RequestType};
 use crate::descriptor::{descriptor_type, lang_id};
 use crate::descriptor_reader::foreach_endpoint;
@@ -160,6 +167,112 @@ pub trait Handler {
     }
 }
 
+/// Callback producing a string descriptor at runtime.
+///
+/// It is called with the language ID of the GET_DESCRIPTOR request and a scratch buffer, carved out
+/// of the control buffer, that the string can be formatted into. It returns the string to send, which
+/// may borrow from the scratch buffer. See [`Builder::string_with`].
+pub type StringProvider<'d> = dyn FnMut(u16, &mut [u8]) -> &str + 'd;
+
+/// Request to halt an endpoint, made by a class and carried out by the [`UsbDevice`].
+///
+/// Halting an endpoint goes through the bus, which classes don't have access to. Instead, a class
+/// registers an `EndpointHalt` for its endpoint with [`Builder::endpoint_halt`], and calls
+/// [`halt`](Self::halt). The endpoint stays halted until the host clears the halt with a
+/// CLEAR_FEATURE(ENDPOINT_HALT) request, or resets the bus.
+///
+/// With [`halt_until_released`](Self::halt_until_released), CLEAR_FEATURE(ENDPOINT_HALT) requests
+/// don't clear the halt until the class calls [`release`](Self::release), for protocols where
+/// the host must recover with a class specific request first.
+pub struct EndpointHalt {
+    requested: AtomicBool,
+    halted: AtomicBool,
+    held: AtomicBool,
+    device_waker: AtomicWaker,
+    class_waker: AtomicWaker,
+}
+
+impl Default for EndpointHalt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EndpointHalt {
+    /// Create a new `EndpointHalt`.
+    pub const fn new() -> Self {
+        Self {
+            requested: AtomicBool::new(false),
+            halted: AtomicBool::new(false),
+            held: AtomicBool::new(false),
+            device_waker: AtomicWaker::new(),
+            class_waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Halt the endpoint.
+    ///
+    /// The endpoint is halted once the [`UsbDevice`] runs.
+    pub fn halt(&self) {
+        self.halted.store(true, Ordering::Release);
+        self.requested.store(true, Ordering::Release);
+        self.device_waker.wake();
+    }
+
+    /// Halt the endpoint, ignoring CLEAR_FEATURE(ENDPOINT_HALT) requests until
+    /// [`release`](Self::release) is called.
+    ///
+    /// A bus reset still clears the halt.
+    pub fn halt_until_released(&self) {
+        self.held.store(true, Ordering::Release);
+        self.halt();
+    }
+
+    /// Let the host clear a halt made by [`halt_until_released`](Self::halt_until_released).
+    ///
+    /// The endpoint stays halted until the host clears the halt.
+    pub fn release(&self) {
+        self.held.store(false, Ordering::Release);
+    }
+
+    /// Returns whether the endpoint is halted, or about to be.
+    pub fn is_halted(&self) -> bool {
+        self.halted.load(Ordering::Acquire)
+    }
+
+    /// Waits for the host to clear the halt.
+    ///
+    /// Returns right away if the endpoint isn't halted.
+    pub async fn wait_cleared(&self) {
+        poll_fn(|cx| {
+            self.class_waker.register(cx.waker());
+            if self.is_halted() {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await
+    }
+
+    fn take_request(&self) -> bool {
+        let requested = self.requested.load(Ordering::Acquire);
+        self.requested.store(false, Ordering::Release);
+        requested
+    }
+
+    fn is_held(&self) -> bool {
+        self.held.load(Ordering::Acquire)
+    }
+
+    fn clear(&self) {
+        self.held.store(false, Ordering::Release);
+        self.requested.store(false, Ordering::Release);
+        self.halted.store(false, Ordering::Release);
+        self.class_waker.wake();
+    }
+}
+
 struct Interface {
     current_alt_setting: u8,
     num_alt_settings: u8,
@@ -210,6 +323,8 @@ struct Inner<'d, D: Driver<'d>> {
 
     interfaces: Vec<Interface, MAX_INTERFACE_COUNT>,
     handlers: Vec<&'d mut dyn Handler, MAX_HANDLER_COUNT>,
+    string_providers: Vec<(u8, &'d mut StringProvider<'d>), MAX_STRING_PROVIDER_COUNT>,
+    endpoint_halts: Vec<(EndpointAddress, &'d EndpointHalt), MAX_ENDPOINT_HALT_COUNT>,
 }
 
 impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
@@ -217,6 +332,8 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
         driver: D,
         config: Config<'d>,
         handlers: Vec<&'d mut dyn Handler, MAX_HANDLER_COUNT>,
+        string_providers: Vec<(u8, &'d mut StringProvider<'d>), MAX_STRING_PROVIDER_COUNT>,
+        endpoint_halts: Vec<(EndpointAddress, &'d EndpointHalt), MAX_ENDPOINT_HALT_COUNT>,
         config_descriptor: &'d [u8],
         bos_descriptor: &'d [u8],
         msos_descriptor: crate::msos::MsOsDescriptorSet<'d>,
@@ -226,7 +343,11 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
         // Start the USB bus.
         // This prevent further allocation by consuming the driver.
         let (bus, control) = driver.start(config.max_packet_size_0 as u16);
-        let device_descriptor = descriptor::device_descriptor(&config);
+        let serial_number = config.serial_number.is_some()
+            || string_providers
+                .iter()
+                .any(|(index, _)| *index == STRING_INDEX_SERIAL_NUMBER);
+        let device_descriptor = descriptor::device_descriptor(&config, serial_number);
         let device_qualifier_descriptor = descriptor::device_qualifier_descriptor(&config);
 
         Self {
@@ -249,6 +370,8 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
                 set_address_pending: false,
                 interfaces,
                 handlers,
+                string_providers,
+                endpoint_halts,
             },
         }
     }
@@ -288,9 +411,23 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
         while !self.inner.suspended {
             let control_fut = self.control.setup();
             let bus_fut = self.inner.bus.poll();
-            match select(bus_fut, control_fut).await {
-                Either::First(evt) => self.inner.handle_bus_event(evt).await,
-                Either::Second(req) => self.handle_control(req).await,
+            let endpoint_halts = &self.inner.endpoint_halts;
+            let halt_fut = poll_fn(|cx| {
+                for (ep_addr, halt) in endpoint_halts {
+                    halt.device_waker.register(cx.waker());
+                    if halt.take_request() {
+                        return Poll::Ready(*ep_addr);
+                    }
+                }
+                Poll::Pending
+            });
+            match select3(bus_fut, control_fut, halt_fut).await {
+                Either3::First(evt) => self.inner.handle_bus_event(evt).await,
+                Either3::Second(req) => self.handle_control(req).await,
+                Either3::Third(ep_addr) => {
+                    trace!("usb: halting endpoint {:?}", ep_addr);
+                    self.inner.bus.endpoint_set_stalled(ep_addr, true);
+                }
             }
         }
     }
@@ -321,8 +458,9 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
 
     /// Initiates a device remote wakeup on the USB bus.
     ///
-    /// If the bus is not suspended or remote wakeup is not enabled, an error
-    /// will be returned.
+    /// Remote wakeup must be enabled by the host with a SET_FEATURE request, which is only accepted
+    /// if [`Config::supports_remote_wakeup`] is set. If the bus is not suspended or remote wakeup is
+    /// not enabled, an error will be returned.
     ///
     /// This future may leave the bus in an inconsistent state if dropped.
     /// After dropping the future, [`UsbDevice::disable()`] should be called
@@ -451,6 +589,10 @@ impl<'d, D: Driver<'d>> Inner<'d, D> {
                 self.remote_wakeup_enabled = false;
                 self.address = 0;
 
+                for (_, halt) in &self.endpoint_halts {
+                    halt.clear();
+                }
+
                 for h in &mut self.handlers {
                     h.reset();
                 }
@@ -511,7 +653,7 @@ impl<'d, D: Driver<'d>> Inner<'d, D> {
                     }
                     OutResponse::Accepted
                 }
-                (Request::SET_FEATURE, Request::FEATURE_DEVICE_REMOTE_WAKEUP) => {
+                (Request::SET_FEATURE, Request::FEATURE_DEVICE_REMOTE_WAKEUP) if self.config.supports_remote_wakeup => {
                     self.remote_wakeup_enabled = true;
                     for h in &mut self.handlers {
                         h.remote_wakeup_enabled(true);
@@ -610,7 +752,15 @@ impl<'d, D: Driver<'d>> Inner<'d, D> {
                 }
                 (Request::CLEAR_FEATURE, Request::FEATURE_ENDPOINT_HALT) => {
                     let ep_addr = ((req.index as u8) & 0x8f).into();
+                    let halts = self.endpoint_halts.iter().filter(|(addr, _)| *addr == ep_addr);
+                    if halts.clone().any(|(_, halt)| halt.is_held()) {
+                        trace!("usb: endpoint {:?} stays halted until released", ep_addr);
+                        return OutResponse::Accepted;
+                    }
                     self.bus.endpoint_set_stalled(ep_addr, false);
+                    for (_, halt) in halts {
+                        halt.clear();
+                    }
                     OutResponse::Accepted
                 }
                 _ => OutResponse::Rejected,
@@ -730,6 +880,8 @@ impl<'d, D: Driver<'d>> Inner<'d, D> {
                     buf[2] = lang_id::ENGLISH_US as u8;
                     buf[3] = (lang_id::ENGLISH_US >> 8) as u8;
                     InResponse::Accepted(&buf[..4])
+                } else if let Some((_, provider)) = self.string_providers.iter_mut().find(|(i, _)| *i == index) {
+                    provide_string_descriptor(provider, req.index, buf)
                 } else {
                     let s = match index {
                         STRING_INDEX_MANUFACTURER => self.config.manufacturer,
@@ -750,19 +902,10 @@ impl<'d, D: Driver<'d>> Inner<'d, D> {
                     };
 
                     if let Some(s) = s {
-                        assert!(buf.len() >= 2, "control buffer too small");
-
-                        buf[1] = descriptor_type::STRING;
-                        let mut pos = 2;
-                        for c in s.encode_utf16() {
-                            assert!(pos + 2 < buf.len(), "control buffer too small");
-
-                            buf[pos..pos + 2].copy_from_slice(&c.to_le_bytes());
-                            pos += 2;
+                        match write_string_descriptor(buf, s) {
+                            Some(len) => InResponse::Accepted(&buf[..len]),
+                            None => panic!("control buffer too small"),
                         }
-
-                        buf[0] = pos as u8;
-                        InResponse::Accepted(&buf[..pos])
                     } else {
                         InResponse::Rejected
                     }
@@ -774,6 +917,40 @@ impl<'d, D: Driver<'d>> Inner<'d, D> {
     }
 }
 
+/// Produce a string descriptor with `provider`, using the control buffer both as scratch space for the
+/// provider and for the encoded descriptor.
+fn provide_string_descriptor<'a>(provider: &mut StringProvider<'_>, lang_id: u16, buf: &'a mut [u8]) -> InResponse<'a> {
+    // Each byte of UTF-8 takes at most 2 bytes once encoded as UTF-16, so give the provider at most a
+    // third of the buffer (minus the descriptor header), and no more than fits in a descriptor.
+    let scratch_len = (buf.len().saturating_sub(2) / 3).min((u8::MAX as usize - 2) / 2);
+    let (out, scratch) = buf.split_at_mut(buf.len() - scratch_len);
+
+    let s = provider(lang_id, scratch);
+    match write_string_descriptor(out, s) {
+        Some(len) => InResponse::Accepted(&out[..len]),
+        None => {
+            warn!("string descriptor does not fit in the control buffer");
+            InResponse::Rejected
+        }
+    }
+}
+
+/// Encode `s` as a string descriptor into `buf`, returning the descriptor length,
+/// or `None` if it does not fit.
+fn write_string_descriptor(buf: &mut [u8], s: &str) -> Option<usize> {
+    let len = 2 + 2 * s.encode_utf16().count();
+    if len > buf.len() || len > u8::MAX as usize {
+        return None;
+    }
+
+    buf[0] = len as u8;
+    buf[1] = descriptor_type::STRING;
+    for (i, c) in s.encode_utf16().enumerate() {
+        buf[2 + 2 * i..4 + 2 * i].copy_from_slice(&c.to_le_bytes());
+    }
+    Some(len)
+}
+
 fn first_last<T: Iterator>(iter: T) -> impl Iterator<Item = (bool, bool, T::Item)> {
     let mut iter = iter.peekable();
     let mut first = true;
@@ -75,6 +75,11 @@ impl<'d> MsOsDescriptorWriter<'d> {
         }
     }
 
+    /// Returns `true` if a non-empty buffer was provided for the MS OS descriptor set
+    pub fn is_enabled(&self) -> bool {
+        !self.buf.is_empty()
+    }
+
     /// Returns `true` if the MS OS descriptor header has not yet been written
     pub fn is_empty(&self) -> bool {
         self.position == 0
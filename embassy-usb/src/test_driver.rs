@@ -0,0 +1,227 @@
+//! Driver for the tests of the classes, whose endpoints exchange packets with the test through queues.
+
+extern crate std;
+
+use core::future::poll_fn;
+use core::task::Poll;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::vec::Vec;
+
+use crate::driver::{
+    self, Direction, EndpointAddress, EndpointAllocError, EndpointError, EndpointInfo, EndpointType, Event, Unsupported,
+};
+
+/// Packets sent over an endpoint, shared between the endpoint and the test.
+#[derive(Clone, Default)]
+pub(crate) struct Packets(Rc<RefCell<VecDeque<Vec<u8>>>>);
+
+impl Packets {
+    /// Queue a packet.
+    pub(crate) fn push(&self, packet: &[u8]) {
+        self.0.borrow_mut().push_back(packet.to_vec());
+    }
+
+    /// Take the oldest packet.
+    pub(crate) fn pop(&self) -> Option<Vec<u8>> {
+        self.0.borrow_mut().pop_front()
+    }
+
+    /// Take all the packets.
+    pub(crate) fn take(&self) -> Vec<Vec<u8>> {
+        self.0.borrow_mut().drain(..).collect()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+}
+
+/// An OUT endpoint, reading the packets queued by the test.
+pub(crate) struct EndpointOut {
+    info: EndpointInfo,
+    packets: Packets,
+}
+
+impl EndpointOut {
+    /// Create an endpoint, returning the queue the packets of the host go to.
+    pub(crate) fn new(index: usize, ep_type: EndpointType, max_packet_size: u16) -> (Self, Packets) {
+        let packets = Packets::default();
+        let ep = Self {
+            info: info(index, Direction::Out, ep_type, max_packet_size),
+            packets: packets.clone(),
+        };
+        (ep, packets)
+    }
+}
+
+impl driver::Endpoint for EndpointOut {
+    fn info(&self) -> &EndpointInfo {
+        &self.info
+    }
+
+    async fn wait_enabled(&mut self) {}
+}
+
+impl driver::EndpointOut for EndpointOut {
+    /// Waits for the test to queue a packet.
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, EndpointError> {
+        let packet = poll_fn(|_| self.packets.pop().map_or(Poll::Pending, Poll::Ready)).await;
+        assert!(
+            packet.len() <= self.info.max_packet_size as usize,
+            "packet longer than the max packet size"
+        );
+        let buf = buf.get_mut(..packet.len()).ok_or(EndpointError::BufferOverflow)?;
+        buf.copy_from_slice(&packet);
+        Ok(packet.len())
+    }
+}
+
+/// An IN endpoint, queueing the packets it writes for the test.
+pub(crate) struct EndpointIn {
+    info: EndpointInfo,
+    packets: Packets,
+}
+
+impl EndpointIn {
+    /// Create an endpoint, returning the queue the packets for the host go to.
+    pub(crate) fn new(index: usize, ep_type: EndpointType, max_packet_size: u16) -> (Self, Packets) {
+        let packets = Packets::default();
+        let ep = Self {
+            info: info(index, Direction::In, ep_type, max_packet_size),
+            packets: packets.clone(),
+        };
+        (ep, packets)
+    }
+}
+
+impl driver::Endpoint for EndpointIn {
+    fn info(&self) -> &EndpointInfo {
+        &self.info
+    }
+
+    async fn wait_enabled(&mut self) {}
+}
+
+impl driver::EndpointIn for EndpointIn {
+    async fn write(&mut self, buf: &[u8]) -> Result<(), EndpointError> {
+        if buf.len() > self.info.max_packet_size as usize {
+            return Err(EndpointError::BufferOverflow);
+        }
+        self.packets.push(buf);
+        Ok(())
+    }
+}
+
+fn info(index: usize, dir: Direction, ep_type: EndpointType, max_packet_size: u16) -> EndpointInfo {
+    EndpointInfo {
+        addr: EndpointAddress::from_parts(index, dir),
+        ep_type,
+        max_packet_size,
+        interval_ms: 1,
+    }
+}
+
+/// Driver handing out [`EndpointOut`] and [`EndpointIn`]. It can't be started.
+#[derive(Default)]
+pub(crate) struct Driver {
+    next_index: usize,
+}
+
+impl<'a> driver::Driver<'a> for Driver {
+    type EndpointOut = EndpointOut;
+    type EndpointIn = EndpointIn;
+    type ControlPipe = ControlPipe;
+    type Bus = Bus;
+
+    fn alloc_endpoint_out(
+        &mut self,
+        ep_type: EndpointType,
+        _ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        _interval_ms: u8,
+    ) -> Result<EndpointOut, EndpointAllocError> {
+        self.next_index += 1;
+        Ok(EndpointOut::new(self.next_index, ep_type, max_packet_size).0)
+    }
+
+    fn alloc_endpoint_in(
+        &mut self,
+        ep_type: EndpointType,
+        _ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        _interval_ms: u8,
+    ) -> Result<EndpointIn, EndpointAllocError> {
+        self.next_index += 1;
+        Ok(EndpointIn::new(self.next_index, ep_type, max_packet_size).0)
+    }
+
+    fn start(self, _control_max_packet_size: u16) -> (Bus, ControlPipe) {
+        unimplemented!()
+    }
+}
+
+pub(crate) enum Bus {}
+
+impl driver::Bus for Bus {
+    async fn enable(&mut self) {
+        match *self {}
+    }
+
+    async fn disable(&mut self) {
+        match *self {}
+    }
+
+    async fn poll(&mut self) -> Event {
+        match *self {}
+    }
+
+    fn endpoint_set_enabled(&mut self, _ep_addr: EndpointAddress, _enabled: bool) {
+        match *self {}
+    }
+
+    fn endpoint_set_stalled(&mut self, _ep_addr: EndpointAddress, _stalled: bool) {
+        match *self {}
+    }
+
+    fn endpoint_is_stalled(&mut self, _ep_addr: EndpointAddress) -> bool {
+        match *self {}
+    }
+
+    async fn remote_wakeup(&mut self) -> Result<(), Unsupported> {
+        match *self {}
+    }
+}
+
+pub(crate) enum ControlPipe {}
+
+impl driver::ControlPipe for ControlPipe {
+    fn max_packet_size(&self) -> usize {
+        match *self {}
+    }
+
+    async fn setup(&mut self) -> [u8; 8] {
+        match *self {}
+    }
+
+    async fn data_out(&mut self, _buf: &mut [u8], _first: bool, _last: bool) -> Result<usize, EndpointError> {
+        match *self {}
+    }
+
+    async fn data_in(&mut self, _data: &[u8], _first: bool, _last: bool) -> Result<(), EndpointError> {
+        match *self {}
+    }
+
+    async fn accept(&mut self) {
+        match *self {}
+    }
+
+    async fn reject(&mut self) {
+        match *self {}
+    }
+
+    async fn accept_set_address(&mut self, _addr: u8) {
+        match *self {}
+    }
+}
@@ -6,7 +6,8 @@ MEMORY
   BOOTLOADER_STATE                  : ORIGIN = 0x00056000, LENGTH = 4K
   FLASH                             : ORIGIN = 0x00057000, LENGTH = 64K
   DFU                               : ORIGIN = 0x00067000, LENGTH = 68K
-  RAM                         (rwx) : ORIGIN = 0x20018000, LENGTH = 32K
+  RAM                         (rwx) : ORIGIN = 0x20018000, LENGTH = 32K - 64
+  BOOTLOADER_INFO             (rw)  : ORIGIN = 0x2001FFC0, LENGTH = 64
 }
 
 __bootloader_state_start = ORIGIN(BOOTLOADER_STATE);
@@ -14,3 +15,6 @@ __bootloader_state_end = ORIGIN(BOOTLOADER_STATE) + LENGTH(BOOTLOADER_STATE);
 
 __bootloader_dfu_start = ORIGIN(DFU);
 __bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU);
+
+__bootloader_info_start = ORIGIN(BOOTLOADER_INFO);
+__bootloader_info_end = ORIGIN(BOOTLOADER_INFO) + LENGTH(BOOTLOADER_INFO);
@@ -5,15 +5,18 @@
 #[cfg(feature = "defmt")]
 use defmt_rtt as _;
 use embassy_boot::State;
-use embassy_boot_nrf::{FirmwareUpdater, FirmwareUpdaterConfig};
+use embassy_boot_nrf::{BootInfo, FirmwareUpdater, FirmwareUpdaterConfig, RunningWatchdogFlash};
 use embassy_embedded_hal::adapter::BlockingAsync;
 use embassy_executor::Spawner;
 use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pull};
 use embassy_nrf::nvmc::Nvmc;
-use embassy_nrf::wdt::{self, Watchdog};
+use embassy_nrf::wdt::Watchdog;
 use embassy_sync::mutex::Mutex;
 use panic_reset as _;
 
+#[path = "../../../../shared/nrf.rs"]
+mod shared;
+
 #[cfg(feature = "skip-include")]
 static APP_B: &[u8] = &[0, 1, 2, 3];
 #[cfg(not(feature = "skip-include"))]
@@ -23,6 +26,11 @@ static APP_B: &[u8] = include_bytes!("../../b.bin");
 async fn main(_spawner: Spawner) {
     let p = embassy_nrf::init(Default::default());
 
+    // Information left behind by the bootloader, if it provides any.
+    let _boot_info = BootInfo::read();
+    #[cfg(feature = "defmt")]
+    defmt::info!("Boot info: {}", _boot_info);
+
     #[cfg(not(feature = "nrf54"))]
     let mut button = Input::new(p.P0_11, Pull::Up);
     #[cfg(not(feature = "nrf54"))]
@@ -47,19 +55,21 @@ async fn main(_spawner: Spawner) {
 
     // The following code block illustrates how to obtain a watchdog that is configured
     // as per the existing watchdog. Ordinarily, we'd use the handle returned to "pet" the
-    // watchdog periodically. If we don't, and we're not going to for this example, then
-    // the watchdog will cause the device to reset as per its configured timeout in the bootloader.
+    // watchdog periodically. If we don't, and we only do so while writing an update in this
+    // example, then the watchdog will cause the device to reset as per its configured timeout
+    // in the bootloader.
     // This helps is avoid a situation where new firmware might be bad and block our executor.
     // If firmware is bad in this way then the bootloader will revert to any previous version.
     #[cfg(feature = "nrf54")]
     let wdt = p.WDT0;
     #[cfg(not(feature = "nrf54"))]
     let wdt = p.WDT;
-    let wdt_config = wdt::Config::try_new(&wdt).unwrap();
-    let (_wdt, [_wdt_handle]) = match Watchdog::try_new(wdt, wdt_config) {
+    embassy_nrf::assert_wdt_handles!(shared::WDT, 1);
+    let (_wdt, [wdt_handle]) = match Watchdog::try_new_shared(wdt, &shared::WDT) {
         Ok(x) => x,
         Err(_) => {
-            // Watchdog already active with the wrong number of handles, waiting for it to timeout...
+            // Watchdog started with another configuration than the shared one, which is
+            // logged, waiting for it to timeout...
             loop {
                 cortex_m::asm::wfe();
             }
@@ -71,7 +81,9 @@ async fn main(_spawner: Spawner) {
     let nvmc = Nvmc::new(p.RRAMC);
     #[cfg(not(feature = "nrf54"))]
     let nvmc = Nvmc::new(p.NVMC);
-    let nvmc = Mutex::new(BlockingAsync::new(nvmc));
+    // Pet the watchdog on every flash access, so that it can't reset the device half way
+    // through writing the update.
+    let nvmc = Mutex::new(BlockingAsync::new(RunningWatchdogFlash::new(nvmc, wdt_handle)));
 
     let config = FirmwareUpdaterConfig::from_linkerfile(&nvmc, &nvmc);
     let mut magic = [0; 16];
@@ -2,14 +2,86 @@
 #![no_main]
 #![macro_use]
 
+use embassy_boot_nrf::{FirmwareState, FirmwareUpdaterConfig, SelfTestGate};
+use embassy_embedded_hal::adapter::BlockingAsync;
 use embassy_executor::Spawner;
 use embassy_nrf::gpio::{Level, Output, OutputDrive};
+use embassy_nrf::nvmc::Nvmc;
+#[cfg(feature = "usb")]
+use embassy_nrf::usb::Driver;
+#[cfg(feature = "usb")]
+use embassy_nrf::usb::vbus_detect::HardwareVbusDetect;
+use embassy_nrf::wdt::Watchdog;
+#[cfg(feature = "usb")]
+use embassy_nrf::{Peri, bind_interrupts, peripherals, usb};
+#[cfg(feature = "usb")]
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+#[cfg(feature = "usb")]
+use embassy_sync::signal::Signal;
 use embassy_time::Timer;
 use panic_reset as _;
 
+#[path = "../../../../shared/nrf.rs"]
+mod shared;
+
+#[cfg(feature = "usb")]
+bind_interrupts!(struct Irqs {
+    USBD => usb::InterruptHandler<peripherals::USBD>;
+    CLOCK_POWER => usb::vbus_detect::InterruptHandler;
+});
+
+#[cfg(feature = "usb")]
+static USB_CONFIGURED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+#[cfg(feature = "usb")]
+struct ConfiguredHandler;
+
+#[cfg(feature = "usb")]
+impl embassy_usb::Handler for ConfiguredHandler {
+    fn configured(&mut self, configured: bool) {
+        if configured {
+            USB_CONFIGURED.signal(());
+        }
+    }
+}
+
+#[cfg(feature = "usb")]
+#[embassy_executor::task]
+async fn usb_task(usbd: Peri<'static, peripherals::USBD>) {
+    let driver = Driver::new(usbd, Irqs, HardwareVbusDetect::new(Irqs));
+
+    let mut config = embassy_usb::Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("Embassy");
+    config.product = Some("Bootloader example B");
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut msos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut handler = ConfiguredHandler;
+
+    let mut builder = embassy_usb::Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut msos_descriptor,
+        &mut control_buf,
+    );
+    builder.handler(&mut handler);
+    builder.build().run().await;
+}
+
 #[embassy_executor::main]
-async fn main(_spawner: Spawner) {
-    let p = embassy_nrf::init(Default::default());
+async fn main(spawner: Spawner) {
+    #[allow(unused_mut)]
+    let mut config = embassy_nrf::config::Config::default();
+    #[cfg(feature = "usb")]
+    {
+        config.hfclk_source = embassy_nrf::config::HfclkSource::ExternalXtal;
+    }
+    let p = embassy_nrf::init(config);
     #[cfg(not(feature = "nrf54"))]
     let mut led = Output::new(p.P0_13, Level::Low, OutputDrive::Standard);
     // let mut led = Output::new(p.P1_10, Level::Low, OutputDrive::Standard);
@@ -20,10 +92,66 @@ async fn main(_spawner: Spawner) {
     #[cfg(feature = "nrf54")]
     let mut led = Output::new(p.P1_10, Level::Low, OutputDrive::Standard);
 
+    // Take over the watchdog started by the bootloader, so that a self-test that hangs
+    // still causes a reset and thus a revert to the previous firmware.
+    #[cfg(feature = "nrf54")]
+    let wdt = p.WDT0;
+    #[cfg(not(feature = "nrf54"))]
+    let wdt = p.WDT;
+    embassy_nrf::assert_wdt_handles!(shared::WDT, 1);
+    let (_wdt, [mut wdt_handle]) = match Watchdog::try_new_shared(wdt, &shared::WDT) {
+        Ok(x) => x,
+        Err(_) => {
+            // Watchdog started with another configuration than the shared one, which is
+            // logged, waiting for it to timeout...
+            loop {
+                cortex_m::asm::wfe();
+            }
+        }
+    };
+
+    // RRAMC for nRF54
+    #[cfg(feature = "nrf54")]
+    let nvmc = Nvmc::new(p.RRAMC);
+    #[cfg(not(feature = "nrf54"))]
+    let nvmc = Nvmc::new(p.NVMC);
+    let nvmc = Mutex::new(BlockingAsync::new(nvmc));
+
+    let config = FirmwareUpdaterConfig::from_linkerfile(&nvmc, &nvmc);
+    #[cfg(feature = "nrf54")]
+    let mut magic = [0; 16];
+    #[cfg(not(feature = "nrf54"))]
+    let mut magic = [0; 4];
+    let mut state = FirmwareState::from_config(config, &mut magic);
+
+    // The self-test: USB must enumerate within 5 seconds. Replace this with checks that make
+    // sense for your application.
+    #[cfg(feature = "usb")]
+    let self_test = {
+        spawner.spawn(usb_task(p.USBD).unwrap());
+        async {
+            USB_CONFIGURED.wait().await;
+            true
+        }
+    };
+    #[cfg(not(feature = "usb"))]
+    let self_test = {
+        let _ = spawner;
+        async { true }
+    };
+
+    // Only confirm the new firmware once the self-test passed. On failure or timeout the device
+    // is reset and the bootloader reverts to the previous firmware.
+    SelfTestGate::new(&mut state, || wdt_handle.pet(), cortex_m::peripheral::SCB::sys_reset)
+        .run(self_test, Timer::after_secs(5))
+        .await
+        .unwrap();
+
     loop {
         led.set_high();
         Timer::after_millis(300).await;
         led.set_low();
         Timer::after_millis(300).await;
+        wdt_handle.pet();
     }
 }
@@ -0,0 +1,137 @@
+//! A serial console and a DFU runtime interface in one composite USB device.
+//!
+//! The console binds the usual CDC-ACM serial driver, and the DFU interface binds WinUSB on
+//! Windows, so that `dfu-util` can ask the device to detach to its bootloader. Everything typed
+//! into the console is echoed back.
+//!
+//! The DFU interface announces that it detaches by itself: once `dfu-util` sends its detach
+//! request, the device waits for the detach timeout, marks the DFU state and resets. The bootloader
+//! must then re-enumerate as its own DFU device, as the stm32wb-dfu bootloader example does, for
+//! `dfu-util` to complete the update.
+
+#![no_std]
+#![no_main]
+
+use core::cell::RefCell;
+
+use embassy_boot_nrf::{AlignedBuffer, BlockingFirmwareState, FirmwareUpdaterConfig};
+use embassy_executor::Spawner;
+use embassy_futures::join::join3;
+use embassy_nrf::nvmc::Nvmc;
+use embassy_nrf::usb::Driver;
+use embassy_nrf::usb::vbus_detect::HardwareVbusDetect;
+use embassy_nrf::wdt::{Watchdog, WatchdogHandle};
+use embassy_nrf::{bind_interrupts, peripherals, usb};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Timer};
+use embassy_usb::Builder;
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::EndpointError;
+use embassy_usb_dfu::ResetImmediate;
+use embassy_usb_dfu::application::{Detach, DfuAttributes, DfuState, usb_dfu_composite_with_msos};
+use panic_reset as _;
+
+#[path = "../../../../shared/nrf.rs"]
+mod shared;
+
+bind_interrupts!(struct Irqs {
+    USBD => usb::InterruptHandler<peripherals::USBD>;
+    CLOCK_POWER => usb::vbus_detect::InterruptHandler;
+});
+
+// This is a randomly generated GUID to allow clients on Windows to find your device.
+//
+// N.B. update to a custom GUID for your own device!
+const DEVICE_INTERFACE_GUID: &str = "{EAA9A5DC-30BA-44BC-9232-606CDC875321}";
+
+#[embassy_executor::task]
+async fn watchdog_task(mut handle: WatchdogHandle) {
+    loop {
+        handle.pet();
+        Timer::after_secs(1).await;
+    }
+}
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let mut config = embassy_nrf::config::Config::default();
+    config.hfclk_source = embassy_nrf::config::HfclkSource::ExternalXtal;
+    let p = embassy_nrf::init(config);
+
+    // Take over the watchdog started by the bootloader.
+    embassy_nrf::assert_wdt_handles!(shared::WDT, 1);
+    let (_wdt, [wdt_handle]) = match Watchdog::try_new_shared(p.WDT, &shared::WDT) {
+        Ok(x) => x,
+        Err(_) => {
+            // Watchdog started with another configuration than the shared one, which is
+            // logged, waiting for it to timeout...
+            loop {
+                cortex_m::asm::wfe();
+            }
+        }
+    };
+    spawner.spawn(watchdog_task(wdt_handle).unwrap());
+
+    let flash = Mutex::new(RefCell::new(Nvmc::new(p.NVMC)));
+    let config = FirmwareUpdaterConfig::from_linkerfile_blocking(&flash, &flash);
+    let mut magic = AlignedBuffer([0; 4]);
+    let mut firmware_state = BlockingFirmwareState::from_config(config, &mut magic.0);
+    firmware_state.mark_booted().expect("Failed to mark booted");
+
+    let driver = Driver::new(p.USBD, Irqs, HardwareVbusDetect::new(Irqs));
+    let mut config = embassy_usb::Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("Embassy");
+    config.product = Some("Console and DFU example");
+    config.serial_number = Some("12345678");
+    // Each function is announced by an interface association descriptor. This is the
+    // default, only shown here because a composite device doesn't enumerate without it.
+    config.composite_with_iads = true;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut msos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+
+    let mut cdc_state = State::new();
+    let detach = Detach::new(firmware_state, ResetImmediate);
+    let mut dfu_state = DfuState::new(
+        detach.handler(),
+        DfuAttributes::CAN_DOWNLOAD | DfuAttributes::WILL_DETACH,
+        Duration::from_millis(1000),
+    );
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut msos_descriptor,
+        &mut control_buf,
+    );
+
+    let mut class = CdcAcmClass::new(&mut builder, &mut cdc_state, 64);
+    // Unlike `usb_dfu_with_msos`, this only binds WinUSB to the DFU interface, so that Windows
+    // still binds the serial driver to the console.
+    usb_dfu_composite_with_msos(&mut builder, &mut dfu_state, DEVICE_INTERFACE_GUID);
+
+    let mut usb = builder.build();
+
+    let echo_fut = async {
+        loop {
+            class.wait_connection().await;
+            let _ = echo(&mut class).await;
+        }
+    };
+
+    join3(usb.run(), echo_fut, detach.run()).await;
+}
+
+async fn echo<'d, V: usb::vbus_detect::VbusDetect + 'd>(
+    class: &mut CdcAcmClass<'d, Driver<'d, V>>,
+) -> Result<(), EndpointError> {
+    let mut buf = [0; 64];
+    loop {
+        let n = class.read_packet(&mut buf).await?;
+        class.write_packet(&buf[..n]).await?;
+    }
+}
@@ -13,8 +13,8 @@ use embassy_stm32::usb::{self, Driver};
 use embassy_stm32::{bind_interrupts, peripherals};
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_time::Duration;
-use embassy_usb::{Builder, msos};
-use embassy_usb_dfu::application::{DfuAttributes, DfuState, Handler, usb_dfu};
+use embassy_usb::Builder;
+use embassy_usb_dfu::application::{DfuAttributes, DfuState, Handler, usb_dfu_with_msos};
 use panic_reset as _;
 
 bind_interrupts!(struct Irqs {
@@ -24,7 +24,7 @@ bind_interrupts!(struct Irqs {
 // This is a randomly generated GUID to allow clients on Windows to find your device.
 //
 // N.B. update to a custom GUID for your own device!
-const DEVICE_INTERFACE_GUIDS: &[&str] = &["{EAA9A5DC-30BA-44BC-9232-606CDC875321}"];
+const DEVICE_INTERFACE_GUID: &str = "{EAA9A5DC-30BA-44BC-9232-606CDC875321}";
 
 struct DfuHandler<'d, FLASH: embedded_storage::nor_flash::NorFlash> {
     firmware_state: BlockingFirmwareState<'d, FLASH>,
@@ -58,6 +58,7 @@ async fn main(_spawner: Spawner) {
 
     let mut config_descriptor = [0; 256];
     let mut bos_descriptor = [0; 256];
+    let mut msos_descriptor = [0; 256];
     let mut control_buf = [0; 64];
 
     let handler = DfuHandler { firmware_state };
@@ -68,32 +69,13 @@ async fn main(_spawner: Spawner) {
         config,
         &mut config_descriptor,
         &mut bos_descriptor,
-        &mut [],
+        &mut msos_descriptor,
         &mut control_buf,
     );
 
-    // We add MSOS headers so that the device automatically gets assigned the WinUSB driver on Windows.
+    // This adds MSOS headers so that the device automatically gets assigned the WinUSB driver on Windows.
     // Otherwise users need to do this manually using a tool like Zadig.
-    //
-    // It seems these always need to be at added at the device level for this to work and for
-    // composite devices they also need to be added on the function level (as shown later).
-    //
-    builder.msos_descriptor(msos::windows_version::WIN8_1, 2);
-    builder.msos_feature(msos::CompatibleIdFeatureDescriptor::new("WINUSB", ""));
-    builder.msos_feature(msos::RegistryPropertyFeatureDescriptor::new(
-        "DeviceInterfaceGUIDs",
-        msos::PropertyData::RegMultiSz(DEVICE_INTERFACE_GUIDS),
-    ));
-
-    usb_dfu(&mut builder, &mut state, |func| {
-        // You likely don't have to add these function level headers if your USB device is not composite
-        // (i.e. if your device does not expose another interface in addition to DFU)
-        func.msos_feature(msos::CompatibleIdFeatureDescriptor::new("WINUSB", ""));
-        func.msos_feature(msos::RegistryPropertyFeatureDescriptor::new(
-            "DeviceInterfaceGUIDs",
-            msos::PropertyData::RegMultiSz(DEVICE_INTERFACE_GUIDS),
-        ));
-    });
+    usb_dfu_with_msos(&mut builder, &mut state, DEVICE_INTERFACE_GUID);
 
     let mut dev = builder.build();
     dev.run().await
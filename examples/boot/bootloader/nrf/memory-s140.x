@@ -7,7 +7,8 @@ MEMORY
   DFU                               : ORIGIN = 0x0008F000, LENGTH = 430080
   FLASH                             : ORIGIN = 0x000f9000, LENGTH = 24K
   BOOTLOADER_STATE                  : ORIGIN = 0x000ff000, LENGTH = 4K
-  RAM                         (rwx) : ORIGIN = 0x20000008, LENGTH = 0x2fff8
+  RAM                         (rwx) : ORIGIN = 0x20000008, LENGTH = 0x2fff8 - 64
+  BOOTLOADER_INFO              (rw) : ORIGIN = 0x2002FFC0, LENGTH = 64
   uicr_bootloader_start_address (r) : ORIGIN = 0x10001014, LENGTH = 0x4
 }
 
@@ -20,6 +21,9 @@ __bootloader_active_end = ORIGIN(ACTIVE) + LENGTH(ACTIVE);
 __bootloader_dfu_start = ORIGIN(DFU);
 __bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU);
 
+__bootloader_info_start = ORIGIN(BOOTLOADER_INFO);
+__bootloader_info_end = ORIGIN(BOOTLOADER_INFO) + LENGTH(BOOTLOADER_INFO);
+
 __bootloader_start = ORIGIN(FLASH);
 
 SECTIONS
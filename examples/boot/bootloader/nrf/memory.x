@@ -5,7 +5,8 @@ MEMORY
   BOOTLOADER_STATE                  : ORIGIN = 0x00006000, LENGTH = 4K
   ACTIVE                            : ORIGIN = 0x00007000, LENGTH = 64K
   DFU                               : ORIGIN = 0x00017000, LENGTH = 68K
-  RAM                         (rwx) : ORIGIN = 0x20000000, LENGTH = 32K
+  RAM                         (rwx) : ORIGIN = 0x20000000, LENGTH = 32K - 64
+  BOOTLOADER_INFO             (rw)  : ORIGIN = 0x20007FC0, LENGTH = 64
 }
 
 __bootloader_state_start = ORIGIN(BOOTLOADER_STATE);
@@ -16,3 +17,6 @@ __bootloader_active_end = ORIGIN(ACTIVE) + LENGTH(ACTIVE);
 
 __bootloader_dfu_start = ORIGIN(DFU);
 __bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU);
+
+__bootloader_info_start = ORIGIN(BOOTLOADER_INFO);
+__bootloader_info_end = ORIGIN(BOOTLOADER_INFO) + LENGTH(BOOTLOADER_INFO);
@@ -8,9 +8,13 @@ use cortex_m_rt::{entry, exception};
 use defmt_rtt as _;
 use embassy_boot_nrf::*;
 use embassy_nrf::nvmc::Nvmc;
-use embassy_nrf::wdt::{self, HaltConfig, SleepConfig};
 use embassy_sync::blocking_mutex::Mutex;
 
+#[path = "../../../shared/nrf.rs"]
+mod shared;
+
+const BOOTLOADER_VERSION: u32 = 1;
+
 #[entry]
 fn main() -> ! {
     let p = embassy_nrf::init(Default::default());
@@ -23,21 +27,22 @@ fn main() -> ! {
         }
     */
 
-    let mut wdt_config = wdt::Config::default();
-    wdt_config.timeout_ticks = 32768 * 5; // timeout seconds
-    wdt_config.action_during_sleep = SleepConfig::RUN;
-    wdt_config.action_during_debug_halt = HaltConfig::PAUSE;
-
+    // The application takes the watchdog over with the same configuration.
+    embassy_nrf::assert_wdt_handles!(shared::WDT, 1);
     #[cfg(not(feature = "nrf54"))]
-    let flash = WatchdogFlash::start(Nvmc::new(p.NVMC), p.WDT, wdt_config);
+    let flash = WatchdogFlash::<_, 1>::start_shared(Nvmc::new(p.NVMC), p.WDT, &shared::WDT);
     #[cfg(feature = "nrf54")]
-    let flash = WatchdogFlash::start(Nvmc::new(p.RRAMC), p.WDT0, wdt_config);
+    let flash = WatchdogFlash::<_, 1>::start_shared(Nvmc::new(p.RRAMC), p.WDT0, &shared::WDT);
     let flash = Mutex::new(RefCell::new(flash));
 
     let config = BootLoaderConfig::from_linkerfile_blocking(&flash, &flash, &flash);
     let active_offset = config.active.offset();
     let bl: BootLoader = BootLoader::prepare(config);
 
+    // Let the application know how it was booted.
+    let info = bl.boot_info(BOOTLOADER_VERSION, active_offset);
+    unsafe { info.store() };
+
     unsafe { bl.load(active_offset) }
 }
 
@@ -11,9 +11,9 @@ use embassy_stm32::flash::{BANK1_REGION, Flash, WRITE_SIZE};
 use embassy_stm32::usb::Driver;
 use embassy_stm32::{Config, bind_interrupts, peripherals, usb};
 use embassy_sync::blocking_mutex::Mutex;
-use embassy_usb::{Builder, msos};
+use embassy_usb::Builder;
 use embassy_usb_dfu::consts::DfuAttributes;
-use embassy_usb_dfu::{ResetImmediate, new_state, usb_dfu};
+use embassy_usb_dfu::{ResetImmediate, new_state, usb_dfu_with_msos};
 
 bind_interrupts!(struct Irqs {
     USB_OTG_HS => usb::InterruptHandler<peripherals::USB_OTG_HS>;
@@ -22,7 +22,7 @@ bind_interrupts!(struct Irqs {
 // This is a randomly generated GUID to allow clients on Windows to find your device.
 //
 // N.B. update to a custom GUID for your own device!
-const DEVICE_INTERFACE_GUIDS: &[&str] = &["{EAA9A5DC-30BA-44BC-9232-606CDC875321}"];
+const DEVICE_INTERFACE_GUID: &str = "{EAA9A5DC-30BA-44BC-9232-606CDC875321}";
 
 // This is a randomly generated example key.
 //
@@ -91,6 +91,7 @@ fn main() -> ! {
 
         let mut config_descriptor = [0; 256];
         let mut bos_descriptor = [0; 256];
+        let mut msos_descriptor = [0; 256];
         let mut control_buf = [0; 4096];
 
         #[cfg(not(feature = "verify"))]
@@ -104,32 +105,13 @@ fn main() -> ! {
             config,
             &mut config_descriptor,
             &mut bos_descriptor,
-            &mut [],
+            &mut msos_descriptor,
             &mut control_buf,
         );
 
-        // We add MSOS headers so that the device automatically gets assigned the WinUSB driver on Windows.
+        // This adds MSOS headers so that the device automatically gets assigned the WinUSB driver on Windows.
         // Otherwise users need to do this manually using a tool like Zadig.
-        //
-        // It seems these always need to be at added at the device level for this to work and for
-        // composite devices they also need to be added on the function level (as shown later).
-
-        builder.msos_descriptor(msos::windows_version::WIN8_1, 2);
-        builder.msos_feature(msos::CompatibleIdFeatureDescriptor::new("WINUSB", ""));
-        builder.msos_feature(msos::RegistryPropertyFeatureDescriptor::new(
-            "DeviceInterfaceGUIDs",
-            msos::PropertyData::RegMultiSz(DEVICE_INTERFACE_GUIDS),
-        ));
-
-        usb_dfu::<_, _, _, _, 4096>(&mut builder, &mut state, |func| {
-            // You likely don't have to add these function level headers if your USB device is not composite
-            // (i.e. if your device does not expose another interface in addition to DFU)
-            func.msos_feature(msos::CompatibleIdFeatureDescriptor::new("WINUSB", ""));
-            func.msos_feature(msos::RegistryPropertyFeatureDescriptor::new(
-                "DeviceInterfaceGUIDs",
-                msos::PropertyData::RegMultiSz(DEVICE_INTERFACE_GUIDS),
-            ));
-        });
+        usb_dfu_with_msos::<_, _, _, _, 4096, _, _>(&mut builder, &mut state, DEVICE_INTERFACE_GUID);
 
         let mut dev = builder.build();
         embassy_futures::block_on(dev.run());
@@ -0,0 +1,8 @@
+//! Configuration shared by the nRF bootloader and application examples.
+//!
+//! Both include this file, so they can't get out of sync.
+
+use embassy_nrf::wdt::{self, HaltConfig, SharedConfig, SleepConfig};
+
+/// The watchdog the bootloader starts and the application takes over, with one handle.
+pub const WDT: SharedConfig = SharedConfig::new(wdt::ticks_from_hz(5_000), SleepConfig::RUN, HaltConfig::PAUSE, 1);
@@ -0,0 +1,50 @@
+//! Lights an LED while VDD is below 2.2 V.
+//!
+//! VDD is halved by a resistor divider into P0_02, and compared to the internal 1.2 V
+//! reference. The CPU sleeps between crossings.
+
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_nrf::comp::{self, Comp, Config, Output, Reference, Thresholds};
+use embassy_nrf::gpio::{Level, Output as LedOutput, OutputDrive};
+use embassy_nrf::{bind_interrupts, peripherals};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    COMP_LPCOMP => comp::InterruptHandler<peripherals::COMP>;
+});
+
+#[embassy_executor::main]
+async fn main(_p: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let mut led = LedOutput::new(p.P0_13, Level::High, OutputDrive::Standard);
+
+    // Down below 59/64 of 1.2 V at the pin, 2.21 V of VDD, and back up above 2.29 V.
+    let thresholds = Thresholds { down: 58, up: 60 };
+    let mut comp = Comp::new_single_ended(
+        p.COMP,
+        Irqs,
+        p.P0_02,
+        Reference::Internal1V2,
+        thresholds,
+        Config::default(),
+    );
+
+    let mut output = comp.sample();
+    loop {
+        match output {
+            Output::Below => {
+                info!("VDD low");
+                led.set_low();
+            }
+            Output::Above => {
+                info!("VDD ok");
+                led.set_high();
+            }
+        }
+        output = comp.wait_for_cross().await;
+    }
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pull};
+use embassy_nrf::gpiote::PortInput;
+use embassy_time::Timer;
+use {defmt_rtt as _, panic_probe as _};
+
+// Reads a 4x4 key matrix without using any GPIOTE channel.
+//
+// The rows are open drain outputs, driven low while idle, and the columns are
+// inputs with pull-ups. Pressing a key pulls its column low, which fires the
+// PORT event. The rows are then scanned one at a time to find which keys are
+// down. Rows are on P1 and columns on P0.
+
+const KEYS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+async fn scan(rows: &mut [Output<'_>; 4], cols: &PortInput<'_, 4>) -> u16 {
+    let mut keys = 0;
+    for r in 0..4 {
+        for (i, row) in rows.iter_mut().enumerate() {
+            row.set_level(if i == r { Level::Low } else { Level::High });
+        }
+        // Let the pull-ups charge the column lines.
+        Timer::after_micros(10).await;
+        for c in 0..4 {
+            if cols.pin(c).is_low() {
+                keys |= 1 << (r * 4 + c);
+            }
+        }
+    }
+
+    for row in rows.iter_mut() {
+        row.set_low();
+    }
+    keys
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    info!("Starting!");
+
+    let mut rows = [
+        Output::new(p.P1_10, Level::Low, OutputDrive::Standard0Disconnect1),
+        Output::new(p.P1_11, Level::Low, OutputDrive::Standard0Disconnect1),
+        Output::new(p.P1_12, Level::Low, OutputDrive::Standard0Disconnect1),
+        Output::new(p.P1_13, Level::Low, OutputDrive::Standard0Disconnect1),
+    ];
+    let mut cols = PortInput::new([
+        Input::new(p.P0_03, Pull::Up),
+        Input::new(p.P0_04, Pull::Up),
+        Input::new(p.P0_28, Pull::Up),
+        Input::new(p.P0_29, Pull::Up),
+    ]);
+
+    let mut pressed: u16 = 0;
+    loop {
+        let changed = cols.wait_for_port_event().await;
+        info!("columns changed: {=u32:04b}", changed);
+
+        // Debounce.
+        Timer::after_millis(10).await;
+
+        let keys = scan(&mut rows, &cols).await;
+        for i in 0..16 {
+            let key = KEYS[i / 4][i % 4];
+            let mask = 1 << i;
+            if keys & mask != 0 && pressed & mask == 0 {
+                info!("key {} pressed", key);
+            } else if keys & mask == 0 && pressed & mask != 0 {
+                info!("key {} released", key);
+            }
+        }
+        pressed = keys;
+    }
+}
@@ -0,0 +1,42 @@
+//! Measure the frequency and duty cycle of a signal on P0.11, such as a fan tachometer.
+//!
+//! Connect P0.11 to P0.13, which outputs a 1 kHz test signal with a 25% duty cycle.
+
+#![no_std]
+#![no_main]
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_nrf::input_capture::{self, InputCapture};
+use embassy_nrf::pwm::{DutyCycle, Prescaler, SimplePwm};
+use embassy_nrf::timer::Frequency;
+use embassy_time::{Duration, with_timeout};
+use {defmt_rtt as _, panic_probe as _};
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    // 16 MHz / 16000 = 1 kHz test signal.
+    let mut pwm = SimplePwm::new_1ch(p.PWM0, p.P0_13, &Default::default());
+    pwm.set_prescaler(Prescaler::Div1);
+    pwm.set_max_duty(16000);
+    pwm.set_duty(0, DutyCycle::inverted(4000));
+
+    let mut config = input_capture::Config::default();
+    config.frequency = Frequency::F16MHz;
+    let mut capture = InputCapture::new(p.TIMER1, p.GPIOTE_CH0, p.P0_11, p.PPI_CH0, config);
+
+    let tick_hz = capture.tick_hz();
+    let mut cycles = capture.stream();
+    loop {
+        match with_timeout(Duration::from_millis(500), cycles.next()).await {
+            Ok(m) => info!(
+                "{} Hz, {}% duty",
+                tick_hz / m.period,
+                100 * u64::from(m.high_time) / u64::from(m.period)
+            ),
+            Err(_) => warn!("no signal"),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use defmt::{info, unwrap};
+use embassy_executor::Spawner;
+use embassy_executor::raw::Executor;
+use embassy_nrf::config::HfclkSource;
+use embassy_nrf::low_power::{self, HfxoHook, SleepHook};
+use embassy_nrf::saadc::{ChannelConfig, Saadc, SaadcSleepHook};
+use embassy_nrf::uarte::{Uarte, UarteSleepHook};
+use embassy_nrf::{bind_interrupts, peripherals, saadc, uarte};
+use embassy_time::{Duration, Ticker};
+use static_cell::StaticCell;
+use {defmt_rtt as _, panic_probe as _};
+
+// Samples P0.02 every 10 seconds, and reports it on the UART.
+//
+// Between samples, the UARTE and the SAADC are disabled and the HFXO is stopped,
+// leaving only the RTC running. Measure the current with a power profiler, and
+// compare with the `sleep` hooks not registered.
+//
+// Run with `cargo run --release --bin low_power --features low-power`.
+
+bind_interrupts!(struct Irqs {
+    UARTE0 => uarte::InterruptHandler<peripherals::UARTE0>;
+    SAADC => saadc::InterruptHandler;
+});
+
+#[embassy_executor::task]
+async fn report(mut uart: Uarte<'static>, mut saadc: Saadc<'static, 1>) {
+    saadc.calibrate().await;
+
+    let mut ticker = Ticker::every(Duration::from_secs(10));
+    loop {
+        let mut buf = [0; 1];
+        saadc.sample(&mut buf).await;
+
+        info!("sample: {}", buf[0]);
+        unwrap!(uart.write(b"sampled\r\n").await);
+
+        ticker.next().await;
+    }
+}
+
+static UARTE_HOOK: StaticCell<UarteSleepHook<'static>> = StaticCell::new();
+static SAADC_HOOK: StaticCell<SaadcSleepHook<'static>> = StaticCell::new();
+static HFXO_HOOK: StaticCell<HfxoHook> = StaticCell::new();
+
+fn init(spawner: Spawner) {
+    let mut config = embassy_nrf::config::Config::default();
+    config.hfclk_source = HfclkSource::ExternalXtal;
+    let p = embassy_nrf::init(config);
+
+    let mut uart_config = uarte::Config::default();
+    uart_config.baudrate = uarte::Baudrate::BAUD115200;
+    let uart = Uarte::new(p.UARTE0, p.P0_08, p.P0_06, Irqs, uart_config);
+
+    let channel_config = ChannelConfig::single_ended(p.P0_02);
+    let saadc = Saadc::new(p.SAADC, Irqs, saadc::Config::default(), [channel_config]);
+
+    // The HFXO hook goes last, so the others run with the crystal running.
+    let hooks: [&'static mut dyn SleepHook; 3] = [
+        UARTE_HOOK.init(uart.sleep_hook()),
+        SAADC_HOOK.init(saadc.sleep_hook()),
+        HFXO_HOOK.init(HfxoHook::new()),
+    ];
+    for hook in hooks {
+        low_power::register(hook);
+    }
+
+    spawner.spawn(unwrap!(report(uart, saadc)));
+}
+
+static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+
+#[entry]
+fn main() -> ! {
+    // `usize::MAX` is the context of the cortex-m thread-mode executor, for which
+    // waking a task executes `SEV`.
+    let executor: &'static Executor = EXECUTOR.init(Executor::new(usize::MAX as *mut ()));
+    init(executor.spawner());
+
+    loop {
+        unsafe { executor.poll() };
+        // Not worth powering down for less than 5 ms.
+        low_power::sleep(Duration::from_millis(5));
+    }
+}
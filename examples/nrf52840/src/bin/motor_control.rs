@@ -0,0 +1,56 @@
+//! Motor speed control loop running at 1 kHz, logging when the loop overruns its period.
+//!
+//! The motor driver is driven with a 20 kHz PWM on P0.13. Every 2 seconds the loop does some
+//! extra work which takes longer than its period, and the overrun is logged. Logs are throttled,
+//! so that a loop overrunning at every tick doesn't flood them.
+
+#![no_std]
+#![no_main]
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_nrf::pwm::{DutyCycle, Prescaler, SimplePwm};
+use embassy_time::{Duration, Throttle, TickStatus, Ticker, block_for};
+use {defmt_rtt as _, panic_probe as _};
+
+/// 16 MHz / 800 = 20 kHz PWM.
+const MAX_DUTY: u16 = 800;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let mut pwm = SimplePwm::new_1ch(p.PWM0, p.P0_13, &Default::default());
+    pwm.set_prescaler(Prescaler::Div1);
+    pwm.set_max_duty(MAX_DUTY);
+
+    let mut ticker = Ticker::every(Duration::from_millis(1));
+    let mut log_throttle = Throttle::new(Duration::from_secs(1));
+    let mut overruns = 0u32;
+
+    let setpoint = MAX_DUTY as i32 * 3 / 4;
+    let mut duty = 0i32;
+    let mut iteration = 0u32;
+
+    loop {
+        if let TickStatus::Missed { late_by } = ticker.next_with_status().await {
+            overruns += 1;
+            if log_throttle.try_ready() {
+                warn!(
+                    "control loop overrun by {} us, {} overruns so far",
+                    late_by.as_micros(),
+                    overruns
+                );
+            }
+        }
+
+        // Proportional ramp towards the setpoint.
+        duty += (setpoint - duty) / 16;
+        pwm.set_duty(0, DutyCycle::normal(duty as u16));
+
+        // Simulate a slow computation, which doesn't fit in the loop period.
+        iteration += 1;
+        if iteration.is_multiple_of(2000) {
+            block_for(Duration::from_micros(1500));
+        }
+    }
+}
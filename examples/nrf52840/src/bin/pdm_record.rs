@@ -0,0 +1,63 @@
+//! Records one second of audio from a PDM microphone into RAM, then prints its level.
+//!
+//! The samples are copied out of the double buffers as they fill up, so that the recording is
+//! gapless. If copying ever took longer than filling a buffer, the recording would be stopped
+//! with `Error::Overrun` instead of holding corrupted audio.
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, unwrap};
+use embassy_executor::Spawner;
+use embassy_nrf::pdm::{self, Config, Frequency, OperationMode, Pdm, Ratio, SamplerState};
+use embassy_nrf::{bind_interrupts, peripherals};
+use fixed::types::I7F1;
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    PDM => pdm::InterruptHandler<peripherals::PDM>;
+});
+
+const SAMPLE_RATE: usize = 16_000;
+
+#[embassy_executor::main]
+async fn main(_p: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let mut config = Config::default();
+    // Pins are correct for the onboard microphone on the Feather nRF52840 Sense.
+    config.frequency = Frequency::_1280K; // 16 kHz sample rate
+    config.ratio = Ratio::RATIO80;
+    config.operation_mode = OperationMode::Mono;
+    config.gain_left = I7F1::from_bits(5); // 2.5 dB
+    let mut pdm = Pdm::new(p.PDM, Irqs, p.P0_00, p.P0_01, config);
+
+    let mut recording = [0i16; SAMPLE_RATE];
+    let mut bufs = [[0; 500]; 2];
+
+    loop {
+        let mut len = 0;
+        unwrap!(
+            pdm.run_task_sampler(&mut bufs, |buf| {
+                recording[len..len + buf.len()].copy_from_slice(buf);
+                len += buf.len();
+                if len == recording.len() {
+                    SamplerState::Stopped
+                } else {
+                    SamplerState::Sampled
+                }
+            })
+            .await
+        );
+
+        let mean = recording.iter().map(|v| i64::from(*v)).sum::<i64>() / recording.len() as i64;
+        let rms =
+            (recording.iter().map(|v| (i64::from(*v) - mean).pow(2)).sum::<i64>() / recording.len() as i64).isqrt();
+        info!(
+            "recorded {} samples, min {=i16}, max {=i16}, AC RMS {}",
+            len,
+            recording.iter().min().unwrap(),
+            recording.iter().max().unwrap(),
+            rms
+        );
+    }
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_nrf::pwm::audio::{AudioConfig, AudioPwm, Waveform};
+use embassy_nrf::{bind_interrupts, peripherals, pwm};
+use embassy_time::{Duration, Timer};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    PWM0 => pwm::InterruptHandler<peripherals::PWM0>;
+});
+
+// Notes of the startup jingle as (frequency in Hz, length in ms). A frequency of 0 is a rest.
+const JINGLE: [(u32, u64); 6] = [(523, 120), (659, 120), (784, 120), (0, 60), (1047, 300), (784, 150)];
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    // Connect a piezo buzzer between P0.03 and GND.
+    let mut audio: AudioPwm = unwrap!(AudioPwm::new(p.PWM0, p.P0_03, Irqs, AudioConfig::default()));
+    info!(
+        "carrier {} Hz, sample rate {} Hz",
+        audio.carrier_freq(),
+        audio.sample_rate()
+    );
+
+    loop {
+        for (freq, ms) in JINGLE {
+            if freq == 0 {
+                Timer::after_millis(ms).await;
+            } else {
+                audio.play_tone(freq, Duration::from_millis(ms), Waveform::Square).await;
+            }
+        }
+
+        // The same jingle, quieter and with a softer sine tone.
+        audio.set_volume(64);
+        for (freq, ms) in JINGLE {
+            if freq == 0 {
+                Timer::after_millis(ms).await;
+            } else {
+                audio.play_tone(freq, Duration::from_millis(ms), Waveform::Sine).await;
+            }
+        }
+        audio.set_volume(255);
+
+        Timer::after_secs(5).await;
+    }
+}
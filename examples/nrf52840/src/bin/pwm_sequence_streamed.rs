@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_nrf::pwm::{Config, Prescaler, SequenceConfig, SequenceLoad, SequencePwm};
+use embassy_nrf::{bind_interrupts, peripherals, pwm};
+use embassy_time::Timer;
+use {defmt_rtt as _, panic_probe as _};
+
+// Drives a strip of 300 WS2812B LEDs with a moving rainbow. The whole frame
+// would take 7200 words of RAM, so it is encoded on the fly into two small
+// buffers of 8 LEDs each, which the PWM plays in turn. See the
+// pwm_sequence_ws2812b example for the encoding, and for notes on powering
+// the LEDs from an nRF52840-DK. The data line is assumed to be P1_05.
+//
+// The line idles low after each frame, which latches the colors.
+
+bind_interrupts!(struct Irqs {
+    PWM0 => pwm::InterruptHandler<peripherals::PWM0>;
+});
+
+const T1H: u16 = 0x8000 | 13; // Duty = 13/20 ticks (0.8us/1.25us) for a 1
+const T0H: u16 = 0x8000 | 7; // Duty 7/20 ticks (0.4us/1.25us) for a 0
+const RES: u16 = 0x8000;
+
+const LEDS: usize = 300;
+const LEDS_PER_BUF: usize = 8;
+
+fn wheel(pos: u8) -> (u8, u8, u8) {
+    match pos {
+        0..=84 => (255 - pos * 3, pos * 3, 0),
+        85..=169 => (0, 255 - (pos - 85) * 3, (pos - 85) * 3),
+        _ => ((pos - 170) * 3, 0, 255 - (pos - 170) * 3),
+    }
+}
+
+fn encode(words: &mut [u16], (r, g, b): (u8, u8, u8)) {
+    // Dimmed to keep the current of a long strip reasonable.
+    let grb = ((g as u32 / 8) << 16) | ((r as u32 / 8) << 8) | (b as u32 / 8);
+    for (i, word) in words.iter_mut().enumerate() {
+        *word = if grb & (1 << (23 - i)) != 0 { T1H } else { T0H };
+    }
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let mut config = Config::default();
+    config.sequence_load = SequenceLoad::Common;
+    config.prescaler = Prescaler::Div1;
+    config.max_duty = 20; // 1.25us (1s / 16Mhz * 20)
+    let mut pwm = unwrap!(SequencePwm::new_1ch(p.PWM0, p.P1_05, config));
+
+    let mut bufs = [[RES; LEDS_PER_BUF * 24]; 2];
+    let seq_config = SequenceConfig::default();
+    let mut offset: u8 = 0;
+
+    loop {
+        let mut led = 0;
+        let result = pwm
+            .run_streamed(Irqs, &mut bufs, &seq_config, async |buf: &mut [u16]| {
+                // Each buffer holds 192 words, playing for 240us, which is
+                // the time available to encode the next one.
+                let n = (LEDS - led).min(LEDS_PER_BUF);
+                for (i, words) in buf.chunks_mut(24).take(n).enumerate() {
+                    let pos = ((led + i) * 256 / LEDS) as u8;
+                    encode(words, wheel(pos.wrapping_add(offset)));
+                }
+                led += n;
+                n * 24
+            })
+            .await;
+
+        if let Err(e) = result {
+            warn!("frame failed: {}", e);
+        }
+
+        offset = offset.wrapping_add(2);
+        Timer::after_millis(20).await;
+    }
+}
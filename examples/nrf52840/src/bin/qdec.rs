@@ -1,7 +1,7 @@
 #![no_std]
 #![no_main]
 
-use defmt::info;
+use defmt::{info, unwrap};
 use embassy_executor::Spawner;
 use embassy_nrf::qdec::{self, Qdec};
 use embassy_nrf::{bind_interrupts, peripherals};
@@ -20,7 +20,7 @@ async fn main(_spawner: Spawner) {
     info!("Turn rotary encoder!");
     let mut value = 0;
     loop {
-        value += rotary_enc.read().await;
+        value += unwrap!(rotary_enc.read().await);
         info!("Value: {}", value);
     }
 }
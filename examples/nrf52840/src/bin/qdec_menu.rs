@@ -0,0 +1,54 @@
+//! Moves a menu cursor with an EC11 rotary encoder.
+//!
+//! The A and B contacts of the encoder go to P0_31 and P0_30, its common pin to ground.
+
+#![no_std]
+#![no_main]
+
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_nrf::qdec::{self, NumSamples, Qdec, SamplePeriod};
+use embassy_nrf::{bind_interrupts, peripherals};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    QDEC => qdec::InterruptHandler<peripherals::QDEC>;
+});
+
+const ITEMS: [&str; 4] = ["Volume", "Brightness", "Contrast", "Back"];
+
+/// Most EC11 encoders go through a full quadrature cycle, four steps, per detent.
+const STEPS_PER_DETENT: i32 = 4;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let mut config = qdec::Config::default();
+    // Mechanical contacts bounce: sample them slowly, through the debounce filter.
+    config.period = SamplePeriod::_1024us;
+    config.debounce = true;
+    // Report every 10 ms at most.
+    config.num_samples = NumSamples::_10smpl;
+    let mut encoder = Qdec::new(p.QDEC, Irqs, p.P0_31, p.P0_30, config);
+
+    let mut cursor = 0;
+    let mut steps = 0;
+    info!("> {}", ITEMS[cursor]);
+    loop {
+        match encoder.read().await {
+            Ok(delta) => steps += i32::from(delta),
+            Err(qdec::Error::Overflow) => {
+                warn!("encoder steps lost");
+                steps = 0;
+            }
+            Err(_) => {}
+        }
+
+        let detents = steps / STEPS_PER_DETENT;
+        if detents != 0 {
+            steps -= detents * STEPS_PER_DETENT;
+            cursor = (cursor as i32 + detents).rem_euclid(ITEMS.len() as i32) as usize;
+            info!("> {}", ITEMS[cursor]);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 #![no_std]
 #![no_main]
 
-use defmt::info;
+use defmt::{info, unwrap};
 use embassy_executor::Spawner;
 use embassy_nrf::saadc::{CallbackResult, ChannelConfig, Config, Saadc};
 use embassy_nrf::timer::Frequency;
@@ -38,33 +38,35 @@ async fn main(_p: Spawner) {
     let mut c = 0;
     let mut a: i32 = 0;
 
-    saadc
-        .run_task_sampler(
-            p.TIMER0.reborrow(),
-            p.PPI_CH0.reborrow(),
-            p.PPI_CH1.reborrow(),
-            Frequency::F1MHz,
-            1000, // We want to sample at 1KHz
-            &mut bufs,
-            move |buf| {
-                // NOTE: It is important that the time spent within this callback
-                // does not exceed the time taken to acquire the 1500 samples we
-                // have in this example, which would be 10us + 2us per
-                // sample * 1500 = 18ms. You need to measure the time taken here
-                // and set the sample buffer size accordingly. Exceeding this
-                // time can lead to the peripheral re-writing the other buffer.
-                for b in buf {
-                    a += b[0] as i32;
-                }
-                c += buf.len();
-                if c > 1000 {
-                    a = a / c as i32;
-                    info!("channel 1: {=i32}", a);
-                    c = 0;
-                    a = 0;
-                }
-                CallbackResult::Continue
-            },
-        )
-        .await;
+    unwrap!(
+        saadc
+            .run_task_sampler(
+                p.TIMER0.reborrow(),
+                p.PPI_CH0.reborrow(),
+                p.PPI_CH1.reborrow(),
+                Frequency::F1MHz,
+                1000, // We want to sample at 1KHz
+                &mut bufs,
+                move |buf| {
+                    // NOTE: It is important that the time spent within this callback
+                    // does not exceed the time taken to acquire the 1500 samples we
+                    // have in this example, which would be 10us + 2us per
+                    // sample * 1500 = 18ms. You need to measure the time taken here
+                    // and set the sample buffer size accordingly. Exceeding this
+                    // time makes the sampler stop and return `Error::Overrun`.
+                    for b in buf {
+                        a += b[0] as i32;
+                    }
+                    c += buf.len();
+                    if c > 1000 {
+                        a = a / c as i32;
+                        info!("channel 1: {=i32}", a);
+                        c = 0;
+                        a = 0;
+                    }
+                    CallbackResult::Continue
+                },
+            )
+            .await
+    );
 }
@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+use defmt::{info, unwrap};
+use embassy_executor::Spawner;
+use embassy_nrf::gpio::{Level, Output, OutputDrive};
+use embassy_nrf::{bind_interrupts, peripherals, spim};
+use embassy_time::Timer;
+use {defmt_rtt as _, panic_probe as _};
+
+// Fills an ST7789 240x240 display with a single color, using the DCX line of
+// SPIM3 for the data/command pin. Each write starts with one command byte,
+// sent with DCX low, followed by its parameters with DCX high.
+
+bind_interrupts!(struct Irqs {
+    SPIM3 => spim::InterruptHandler<peripherals::SPI3>;
+});
+
+const SWRESET: u8 = 0x01;
+const SLPOUT: u8 = 0x11;
+const INVON: u8 = 0x21;
+const DISPON: u8 = 0x29;
+const CASET: u8 = 0x2A;
+const RASET: u8 = 0x2B;
+const RAMWR: u8 = 0x2C;
+const COLMOD: u8 = 0x3A;
+
+const SIZE: usize = 240;
+const COLOR: u16 = 0x07E0; // Green, RGB565
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    info!("running!");
+
+    let mut config = spim::Config::default();
+    config.frequency = spim::Frequency::M32;
+    config.mode = spim::MODE_3;
+    config.dcx_cmd_bytes = 1;
+    let mut spim = spim::Spim::new_txonly_with_dcx(p.SPI3, Irqs, p.P0_29, p.P0_28, p.P0_30, config);
+    let mut ncs = Output::new(p.P0_31, Level::High, OutputDrive::Standard);
+
+    ncs.set_low();
+    for cmd in [&[SWRESET][..], &[SLPOUT], &[COLMOD, 0x55], &[INVON], &[DISPON]] {
+        unwrap!(spim.write(cmd).await);
+        Timer::after_millis(120).await;
+    }
+    unwrap!(spim.write(&[CASET, 0, 0, 0, SIZE as u8 - 1]).await);
+    unwrap!(spim.write(&[RASET, 0, 0, 0, SIZE as u8 - 1]).await);
+    unwrap!(spim.write(&[RAMWR]).await);
+
+    // The pixels that follow are all data.
+    spim.set_dcx_cmd_bytes(0);
+    let line = [COLOR.to_be_bytes(); SIZE];
+    for _ in 0..SIZE {
+        unwrap!(spim.write(line.as_flattened()).await);
+    }
+    ncs.set_high();
+
+    info!("done!");
+}
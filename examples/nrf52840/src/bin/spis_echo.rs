@@ -0,0 +1,47 @@
+//! SPIS echo device: each transaction answers with the bytes received in the previous one.
+//!
+//! Connect a SPI master to P0.31 (CS), P0.29 (SCK), P0.28 (MISO) and P0.30 (MOSI), in mode 0.
+
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_nrf::spis::{self, Spis};
+use embassy_nrf::{bind_interrupts, peripherals};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    SPI2 => spis::InterruptHandler<peripherals::SPI2>;
+});
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    let mut config = spis::Config::default();
+    // Sent once the previous bytes are all echoed.
+    config.orc = 0xFF;
+    let mut spis = Spis::new(p.SPI2, Irqs, p.P0_31, p.P0_29, p.P0_28, p.P0_30, config);
+
+    let mut rx_buf = [0u8; 64];
+    let mut tx_buf = [0u8; 64];
+    let mut tx_len = 0;
+
+    info!("Running!");
+    loop {
+        let Ok((n_rx, n_tx)) = spis.transfer(&mut rx_buf, &tx_buf[..tx_len]).await else {
+            continue;
+        };
+        info!("Received {:02x}, echoed {} bytes", rx_buf[..n_rx], n_tx);
+        if spis.is_overread() {
+            info!("Master clocked more than the echoed bytes");
+        }
+        if spis.is_overflow() {
+            info!("Master sent more than {} bytes", rx_buf.len());
+        }
+
+        tx_buf[..n_rx].copy_from_slice(&rx_buf[..n_rx]);
+        tx_len = n_rx;
+    }
+}
@@ -5,7 +5,7 @@ use defmt::info;
 use embassy_executor::Spawner;
 use embassy_nrf::temp::Temp;
 use embassy_nrf::{bind_interrupts, temp};
-use embassy_time::Timer;
+use embassy_time::Duration;
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs {
@@ -17,9 +17,9 @@ async fn main(_spawner: Spawner) {
     let p = embassy_nrf::init(Default::default());
     let mut temp = Temp::new(p.TEMP, Irqs);
 
+    let mut stream = temp.stream(Duration::from_secs(1));
     loop {
-        let value = temp.read().await;
-        info!("temperature: {}℃", value.to_num::<u16>());
-        Timer::after_secs(1).await;
+        let value = stream.next().await;
+        info!("temperature: {} m℃", value);
     }
 }
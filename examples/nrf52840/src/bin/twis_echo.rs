@@ -0,0 +1,70 @@
+//! TWIS echo device: reads return the bytes of the last write.
+//!
+//! The device answers to two addresses. Reads at 0x55 return the bytes as written, reads at 0x56
+//! return them reversed. From a Raspberry Pi wired to P0.03 (SDA) and P0.04 (SCL):
+//!
+//!     i2ctransfer -y 1 w3@0x55 1 2 3 r3@0x55
+//!     i2ctransfer -y 1 r3@0x56
+
+#![no_std]
+#![no_main]
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_nrf::twis::{self, Command, Twis};
+use embassy_nrf::{bind_interrupts, peripherals};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    TWISPI0 => twis::InterruptHandler<peripherals::TWISPI0>;
+});
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    let mut config = twis::Config::default();
+    config.address0 = 0x55;
+    config.address1 = Some(0x56);
+    // Sent to masters reading more than the last write.
+    config.orc = 0xFF;
+    let mut i2c = Twis::new(p.TWISPI0, Irqs, p.P0_03, p.P0_04, config);
+
+    let mut data = [0u8; 32];
+    let mut len = 0;
+    let mut response = [0u8; 32];
+
+    info!("Listening...");
+    loop {
+        let mut buf = [0u8; 32];
+        let command = match i2c.listen(&mut buf).await {
+            Ok(command) => command,
+            Err(e) => {
+                error!("{:?}", e);
+                continue;
+            }
+        };
+
+        let n = match command {
+            Command::Write(n) | Command::WriteRead(n) => n,
+            Command::Read => 0,
+        };
+        if n > 0 {
+            data[..n].copy_from_slice(&buf[..n]);
+            len = n;
+            info!("Received {:02x}", data[..len]);
+        }
+
+        if matches!(command, Command::Read | Command::WriteRead(_)) {
+            response[..len].copy_from_slice(&data[..len]);
+            if i2c.address_match_index() == 1 {
+                response[..len].reverse();
+            }
+            match i2c.respond_to_read(&response[..len]).await {
+                Ok(n) => info!("Sent {} bytes", n),
+                Err(twis::Error::OverRead) => info!("Sent {} bytes, then 0xFF", len),
+                Err(e) => error!("{:?}", e),
+            }
+        }
+    }
+}
@@ -1,3 +1,6 @@
+//! A boot keyboard typing `a` while the button 1 of the nRF52840-DK is pressed, and showing the
+//! caps lock state of the host with LED1.
+
 #![no_std]
 #![no_main]
 
@@ -7,7 +10,7 @@ use defmt::*;
 use embassy_executor::Spawner;
 use embassy_futures::join::join;
 use embassy_futures::select::{Either, select};
-use embassy_nrf::gpio::{Input, Pull};
+use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pull};
 use embassy_nrf::usb::Driver;
 use embassy_nrf::usb::vbus_detect::HardwareVbusDetect;
 use embassy_nrf::{bind_interrupts, pac, peripherals, usb};
@@ -30,6 +33,9 @@ static SUSPENDED: AtomicBool = AtomicBool::new(false);
 
 static HID_PROTOCOL_MODE: AtomicU8 = AtomicU8::new(HidProtocolMode::Boot as u8);
 
+/// Caps lock bit of the LED output report of a boot keyboard.
+const CAPS_LOCK: u8 = 1 << 1;
+
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
     let p = embassy_nrf::init(Default::default());
@@ -79,9 +85,11 @@ async fn main(_spawner: Spawner) {
     // Create classes on the builder.
     let config = embassy_usb::class::hid::Config {
         report_descriptor: KeyboardReport::desc(),
-        request_handler: None,
+        request_handler: Some(&mut request_handler),
         poll_ms: 60,
         max_packet_size: 64,
+        // The host sends the LED state over the control pipe.
+        out_endpoint: false,
         hid_subclass: HidSubclass::Boot,
         hid_boot_protocol: HidBootProtocol::Keyboard,
     };
@@ -98,7 +106,12 @@ async fn main(_spawner: Spawner) {
             usb.run_until_suspend().await;
             match select(usb.wait_resume(), remote_wakeup.wait()).await {
                 Either::First(_) => (),
-                Either::Second(_) => unwrap!(usb.remote_wakeup().await),
+                Either::Second(_) => {
+                    // Fails if the host didn't enable remote wakeup, the device then stays suspended.
+                    if let Err(e) = usb.remote_wakeup().await {
+                        warn!("Remote wakeup failed: {:?}", e);
+                    }
+                }
             }
         }
     };
@@ -156,8 +169,21 @@ async fn main(_spawner: Spawner) {
         }
     };
 
+    let mut caps_lock_led = Output::new(p.P0_13, Level::High, OutputDrive::Standard);
+
     let out_fut = async {
-        reader.run(false, &mut request_handler).await;
+        let mut reader = reader;
+        let mut leds = [0; 1];
+        loop {
+            match reader.read(&mut leds).await {
+                Ok(_) => {
+                    info!("LED report: {=u8:#04x}", leds[0]);
+                    // The LED is on when the pin is low.
+                    caps_lock_led.set_level(Level::from(leds[0] & CAPS_LOCK == 0));
+                }
+                Err(e) => warn!("Failed to read LED report: {:?}", e),
+            }
+        }
     };
 
     // Run everything concurrently.
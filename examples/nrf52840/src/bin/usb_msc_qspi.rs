@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+use aligned::Aligned;
+use defmt::{info, unwrap};
+use embassy_embedded_hal::flash::FlashBlockDevice;
+use embassy_embedded_hal::flash::partition::RmwPartition;
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_nrf::qspi::Frequency;
+use embassy_nrf::usb::Driver;
+use embassy_nrf::usb::vbus_detect::HardwareVbusDetect;
+use embassy_nrf::{bind_interrupts, pac, peripherals, qspi, usb};
+use embassy_usb::class::msc::{self, BLOCK_SIZE, MscClass, State};
+use embassy_usb::{Builder, Config};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    USBD => usb::InterruptHandler<peripherals::USBD>;
+    CLOCK_POWER => usb::vbus_detect::InterruptHandler;
+    QSPI => qspi::InterruptHandler<peripherals::QSPI>;
+});
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    info!("Enabling ext hfosc...");
+    pac::CLOCK.tasks_hfclkstart().write_value(1);
+    while pac::CLOCK.events_hfclkstarted().read() != 1 {}
+
+    // Config for the MX25R64 present in the nRF52840 DK
+    let mut qspi_config = qspi::Config::default();
+    qspi_config.capacity = 8 * 1024 * 1024; // 8 MB
+    qspi_config.frequency = Frequency::M32;
+    qspi_config.read_opcode = qspi::ReadOpcode::READ4IO;
+    qspi_config.write_opcode = qspi::WriteOpcode::PP4IO;
+    qspi_config.write_page_size = qspi::WritePageSize::_256BYTES;
+
+    let mut q = qspi::Qspi::new(
+        p.QSPI,
+        Irqs,
+        p.P0_19,
+        p.P0_17,
+        p.P0_20,
+        p.P0_21,
+        p.P0_22,
+        p.P0_23,
+        qspi_config,
+    );
+
+    // Enable quad mode in the status register
+    let mut status = [4; 1];
+    unwrap!(q.custom_instruction(0x05, &[], &mut status).await);
+    if status[0] & 0x40 == 0 {
+        status[0] |= 0x40;
+        unwrap!(q.custom_instruction(0x01, &status, &mut []).await);
+    }
+
+    // The flash erases 4K sectors, while the disk has 512 byte blocks. Writing a block reads the
+    // sector into this buffer, erases it, and writes it back with the block modified.
+    let mut sector = [0; 4096];
+    let mut disk = FlashBlockDevice::new(RmwPartition::<_, BLOCK_SIZE>::new(q, &mut sector));
+
+    // Create the driver, from the HAL.
+    let driver = Driver::new(p.USBD, Irqs, HardwareVbusDetect::new(Irqs));
+
+    // Create embassy-usb Config
+    let mut config = Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("Embassy");
+    config.product = Some("USB-MSC QSPI example");
+    config.serial_number = Some("12345678");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    // Create embassy-usb DeviceBuilder using the driver and config.
+    // It needs some buffers for building the descriptors.
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut msos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+
+    let mut state = State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut msos_descriptor,
+        &mut control_buf,
+    );
+
+    // Create classes on the builder.
+    let msc_config = msc::Config {
+        vendor: "Embassy",
+        product: "QSPI flash",
+        revision: "1.0",
+        max_packet_size: 64,
+    };
+    let mut class = MscClass::new(&mut builder, &mut state, msc_config);
+
+    // Build the builder.
+    let mut usb = builder.build();
+
+    // Run the USB device.
+    let usb_fut = usb.run();
+
+    // Serve the flash.
+    let mut buf = [Aligned([0; BLOCK_SIZE]); 8];
+    let msc_fut = class.run(&mut disk, &mut buf);
+
+    // Run everything concurrently.
+    // If we had made everything `'static` above instead, we could do this using separate tasks instead.
+    join(usb_fut, msc_fut).await;
+}
@@ -0,0 +1,116 @@
+#![no_std]
+#![no_main]
+
+use core::convert::Infallible;
+
+use aligned::{A4, Aligned};
+use block_device_driver::BlockDevice;
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_nrf::usb::Driver;
+use embassy_nrf::usb::vbus_detect::HardwareVbusDetect;
+use embassy_nrf::{bind_interrupts, pac, peripherals, usb};
+use embassy_usb::class::msc::{self, BLOCK_SIZE, MscClass, State};
+use embassy_usb::{Builder, Config};
+use static_cell::ConstStaticCell;
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    USBD => usb::InterruptHandler<peripherals::USBD>;
+    CLOCK_POWER => usb::vbus_detect::InterruptHandler;
+});
+
+const BLOCK_COUNT: usize = 128;
+
+/// A disk stored in RAM, lost on reset. The host offers to format it when it's plugged in.
+struct RamDisk {
+    blocks: &'static mut [[u8; BLOCK_SIZE]; BLOCK_COUNT],
+}
+
+impl BlockDevice<BLOCK_SIZE> for RamDisk {
+    type Error = Infallible;
+    type Align = A4;
+
+    async fn read(&mut self, block_address: u32, data: &mut [Aligned<A4, [u8; BLOCK_SIZE]>]) -> Result<(), Infallible> {
+        for (i, block) in data.iter_mut().enumerate() {
+            block.copy_from_slice(&self.blocks[block_address as usize + i]);
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, block_address: u32, data: &[Aligned<A4, [u8; BLOCK_SIZE]>]) -> Result<(), Infallible> {
+        for (i, block) in data.iter().enumerate() {
+            self.blocks[block_address as usize + i].copy_from_slice(&block[..]);
+        }
+        Ok(())
+    }
+
+    async fn size(&mut self) -> Result<u64, Infallible> {
+        Ok((BLOCK_COUNT * BLOCK_SIZE) as u64)
+    }
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    info!("Enabling ext hfosc...");
+    pac::CLOCK.tasks_hfclkstart().write_value(1);
+    while pac::CLOCK.events_hfclkstarted().read() != 1 {}
+
+    // Create the driver, from the HAL.
+    let driver = Driver::new(p.USBD, Irqs, HardwareVbusDetect::new(Irqs));
+
+    // Create embassy-usb Config
+    let mut config = Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("Embassy");
+    config.product = Some("USB-MSC RAM disk example");
+    config.serial_number = Some("12345678");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    // Create embassy-usb DeviceBuilder using the driver and config.
+    // It needs some buffers for building the descriptors.
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut msos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+
+    let mut state = State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut msos_descriptor,
+        &mut control_buf,
+    );
+
+    // Create classes on the builder.
+    let msc_config = msc::Config {
+        vendor: "Embassy",
+        product: "RAM disk",
+        revision: "1.0",
+        max_packet_size: 64,
+    };
+    let mut class = MscClass::new(&mut builder, &mut state, msc_config);
+
+    // Build the builder.
+    let mut usb = builder.build();
+
+    // Run the USB device.
+    let usb_fut = usb.run();
+
+    // Serve the disk. The buffer lets the class read and write up to 4 blocks at once.
+    static BLOCKS: ConstStaticCell<[[u8; BLOCK_SIZE]; BLOCK_COUNT]> =
+        ConstStaticCell::new([[0; BLOCK_SIZE]; BLOCK_COUNT]);
+    let mut disk = RamDisk { blocks: BLOCKS.take() };
+    let mut buf = [Aligned([0; BLOCK_SIZE]); 4];
+    let msc_fut = class.run(&mut disk, &mut buf);
+
+    // Run everything concurrently.
+    // If we had made everything `'static` above instead, we could do this using separate tasks instead.
+    join(usb_fut, msc_fut).await;
+}
@@ -9,7 +9,7 @@ use embassy_nrf::usb::vbus_detect::{HardwareVbusDetect, VbusDetect};
 use embassy_nrf::{bind_interrupts, pac, peripherals, usb};
 use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
 use embassy_usb::driver::EndpointError;
-use embassy_usb::{Builder, Config};
+use embassy_usb::{Builder, Config, StringProvider};
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs {
@@ -32,7 +32,6 @@ async fn main(_spawner: Spawner) {
     let mut config = Config::new(0xc0de, 0xcafe);
     config.manufacturer = Some("Embassy");
     config.product = Some("USB-serial example");
-    config.serial_number = Some("12345678");
     config.max_power = 100;
     config.max_packet_size_0 = 64;
 
@@ -45,6 +44,10 @@ async fn main(_spawner: Spawner) {
 
     let mut state = State::new();
 
+    // Use the factory-programmed device ID as serial number.
+    let device_id = (pac::FICR.deviceid(1).read() as u64) << 32 | pac::FICR.deviceid(0).read() as u64;
+    let serial_number: &mut StringProvider = &mut move |_, buf| format_hex(device_id, buf);
+
     let mut builder = Builder::new(
         driver,
         config,
@@ -54,6 +57,8 @@ async fn main(_spawner: Spawner) {
         &mut control_buf,
     );
 
+    builder.serial_number_with(serial_number);
+
     // Create classes on the builder.
     let mut class = CdcAcmClass::new(&mut builder, &mut state, 64);
 
@@ -98,3 +103,13 @@ async fn echo<'d, V: VbusDetect + 'd>(class: &mut CdcAcmClass<'d, Driver<'d, V>>
         class.write_packet(data).await?;
     }
 }
+
+fn format_hex(value: u64, buf: &mut [u8]) -> &str {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    let buf = &mut buf[..16];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = HEX[(value >> (60 - 4 * i)) as usize & 0xF];
+    }
+    core::str::from_utf8(buf).unwrap()
+}
@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::entry;
+use defmt::{info, unwrap};
+use embassy_executor::Spawner;
+use embassy_executor::raw::Executor;
+use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pull};
+use embassy_nrf::wake_stats;
+use embassy_time::{Duration, Ticker, Timer};
+use static_cell::StaticCell;
+use {defmt_rtt as _, panic_probe as _};
+
+// Prints the interrupts that wake the chip most often, every 60 seconds.
+//
+// Instead of the usual `#[embassy_executor::main]`, the thread-mode executor
+// loop is written out, to tell `wake_stats` when the chip goes to sleep and
+// when it wakes up. Press button 1 to add GPIOTE wake-ups to the mix.
+//
+// Run with `cargo run --release --bin wake_stats --features wake-stats`.
+
+#[embassy_executor::task]
+async fn blink(mut led: Output<'static>) {
+    let mut ticker = Ticker::every(Duration::from_millis(250));
+    loop {
+        led.toggle();
+        ticker.next().await;
+    }
+}
+
+#[embassy_executor::task]
+async fn button(mut button: Input<'static>) {
+    loop {
+        button.wait_for_falling_edge().await;
+        info!("button pressed");
+    }
+}
+
+#[embassy_executor::task]
+async fn report() {
+    loop {
+        Timer::after_secs(60).await;
+
+        let stats = wake_stats::wake_stats();
+        info!(
+            "{} wake-ups, asleep {}.{}% of the time",
+            stats.total_wakes(),
+            stats.asleep_permille() / 10,
+            stats.asleep_permille() % 10
+        );
+        for (irq, count) in stats.top_wakes::<5>() {
+            if count > 0 {
+                info!(
+                    "  irq {=u16}: {} wake-ups, {} interrupts",
+                    irq, count, stats.interrupts[irq as usize]
+                );
+            }
+        }
+        if stats.unattributed_wakes > 0 {
+            info!("  unattributed: {} wake-ups", stats.unattributed_wakes);
+        }
+
+        wake_stats::clear();
+    }
+}
+
+fn init(spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let led = Output::new(p.P0_13, Level::High, OutputDrive::Standard);
+    let btn = Input::new(p.P0_11, Pull::Up);
+
+    spawner.spawn(unwrap!(blink(led)));
+    spawner.spawn(unwrap!(button(btn)));
+    spawner.spawn(unwrap!(report()));
+}
+
+static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+
+#[entry]
+fn main() -> ! {
+    // `usize::MAX` is the context of the cortex-m thread-mode executor, for which
+    // waking a task executes `SEV`.
+    let executor: &'static Executor = EXECUTOR.init(Executor::new(usize::MAX as *mut ()));
+    init(executor.spawner());
+
+    loop {
+        unsafe { executor.poll() };
+        wake_stats::on_idle();
+        cortex_m::asm::wfe();
+        wake_stats::on_wake();
+    }
+}
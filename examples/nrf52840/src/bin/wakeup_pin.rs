@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+use defmt::info;
+use embassy_executor::Spawner;
+use embassy_nrf::gpio::{self, Input, Level, Pull};
+use embassy_nrf::power;
+use embassy_time::Timer;
+use {defmt_rtt as _, panic_probe as _};
+
+// Enters System OFF after 5 seconds, and wakes up when button 1 or button 2 of the DK is pressed,
+// reporting which one woke the chip.
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    match gpio::wakeup_source() {
+        Some(11) => info!("Woken up by button 1"),
+        Some(12) => info!("Woken up by button 2"),
+        Some(pin) => info!("Woken up by pin {}", pin),
+        None => info!("Not woken up by a pin"),
+    }
+
+    let mut button1 = Input::new(p.P0_11, Pull::Up);
+    let mut button2 = Input::new(p.P0_12, Pull::Up);
+
+    Timer::after_secs(5).await;
+
+    info!("Entering System OFF");
+    button1.enable_sense_wakeup(Level::Low);
+    button2.enable_sense_wakeup(Level::Low);
+    gpio::clear_latch();
+    power::set_system_off();
+
+    // Only reached in debug interface mode, where System OFF is emulated.
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
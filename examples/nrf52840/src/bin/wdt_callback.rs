@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_nrf::gpio::{Input, Pull};
+use embassy_nrf::interrupt::Priority;
+use embassy_nrf::wdt::{self, Config, HaltConfig, Watchdog};
+use embassy_nrf::{bind_interrupts, pac, peripherals};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    WDT => wdt::InterruptHandler<peripherals::WDT>;
+});
+
+/// Marker left in GPREGRET by the timeout callback, which survives the watchdog reset.
+const TIMED_OUT: u8 = 0x5A;
+
+fn on_timeout() {
+    pac::POWER.gpregret().write(|w| w.set_gpregret(TIMED_OUT));
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    info!("Hello World!");
+
+    if pac::POWER.gpregret().read().gpregret() == TIMED_OUT {
+        info!("Reset by the watchdog");
+        pac::POWER.gpregret().write(|w| w.set_gpregret(0));
+    }
+
+    let mut config = Config::default();
+    config.timeout_ticks = 32768 * 3; // 3 seconds
+    config.action_during_debug_halt = HaltConfig::PAUSE;
+
+    wdt::set_timeout_callback::<peripherals::WDT>(on_timeout);
+    let (_wdt, [mut handle]) = match Watchdog::try_new_with_interrupt(p.WDT, Irqs, config, Priority::P0) {
+        Ok(x) => x,
+        Err(_) => {
+            info!("Watchdog already active with wrong config, waiting for it to timeout...");
+            loop {}
+        }
+    };
+
+    let mut button = Input::new(p.P0_11, Pull::Up);
+
+    info!("Watchdog started, press button 1 to pet it or I'll reset in 3 seconds!");
+
+    loop {
+        button.wait_for_high().await;
+        button.wait_for_low().await;
+        info!("Button pressed, petting watchdog!");
+        handle.pet();
+    }
+}
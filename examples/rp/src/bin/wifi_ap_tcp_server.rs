@@ -1,5 +1,6 @@
 //! This example uses the RP Pico W board Wifi chip (cyw43).
-//! Creates an Access point Wifi network and creates a TCP endpoint on port 1234.
+//! Creates an Access point Wifi network, hands out addresses to its clients with a DHCP server,
+//! and creates a TCP endpoint on port 1234.
 
 #![no_std]
 #![no_main]
@@ -11,8 +12,10 @@ use cyw43::aligned_bytes;
 use cyw43_pio::{DEFAULT_CLOCK_DIVIDER, PioSpi};
 use defmt::*;
 use embassy_executor::Spawner;
+use embassy_net::dhcp_server::{self, DhcpServer, Lease};
 use embassy_net::tcp::TcpSocket;
-use embassy_net::{Config, StackResources};
+use embassy_net::udp::PacketMetadata;
+use embassy_net::{Config, Ipv4Address, Stack, StackResources};
 use embassy_rp::clocks::RoscRng;
 use embassy_rp::gpio::{Level, Output};
 use embassy_rp::peripherals::{DMA_CH0, PIO0};
@@ -38,6 +41,31 @@ async fn net_task(mut runner: embassy_net::Runner<'static, cyw43::NetDriver<'sta
     runner.run().await
 }
 
+#[embassy_executor::task]
+async fn dhcp_task(stack: Stack<'static>) -> ! {
+    // Hand out 192.168.4.100 to 192.168.4.107, with the device as router.
+    let mut config = dhcp_server::Config::new(Ipv4Address::new(192, 168, 4, 100));
+    config.router = Some(Ipv4Address::new(192, 168, 4, 1));
+
+    let mut leases = [Lease::EMPTY; 8];
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 1024];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; 1024];
+    let mut buf = [0; 576];
+    let mut server = DhcpServer::new(
+        stack,
+        config,
+        &mut leases,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+        &mut buf,
+    );
+    server.run().await
+}
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     info!("Hello World!");
@@ -80,9 +108,9 @@ async fn main(spawner: Spawner) {
         .set_power_management(cyw43::PowerManagementMode::PowerSave)
         .await;
 
-    // Use a link-local address for communication without DHCP server
+    // The access point has a static address, its clients get theirs from the DHCP server.
     let config = Config::ipv4_static(embassy_net::StaticConfigV4 {
-        address: embassy_net::Ipv4Cidr::new(embassy_net::Ipv4Address::new(169, 254, 1, 1), 16),
+        address: embassy_net::Ipv4Cidr::new(Ipv4Address::new(192, 168, 4, 1), 24),
         dns_servers: heapless::Vec::new(),
         gateway: None,
     });
@@ -91,10 +119,11 @@ async fn main(spawner: Spawner) {
     let seed = rng.next_u64();
 
     // Init network stack
-    static RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
+    static RESOURCES: StaticCell<StackResources<4>> = StaticCell::new();
     let (stack, runner) = embassy_net::new(net_device, config, RESOURCES.init(StackResources::new()), seed);
 
     spawner.spawn(unwrap!(net_task(runner)));
+    spawner.spawn(unwrap!(dhcp_task(stack)));
 
     //control.start_ap_open("cyw43", 5).await;
     control.start_ap_wpa2("cyw43", "password", 5).await;
@@ -54,6 +54,8 @@ async fn ppp_task(stack: Stack<'static>, mut runner: Runner<'static>, port: Seri
 
     let r = runner
         .run(port, config, |ipv4| {
+            // Called on each IPCP negotiation, the address and DNS servers may change.
+            info!("IPCP up: {:?}", ipv4);
             let Some(addr) = ipv4.address else {
                 warn!("PPP did not provide an IP address.");
                 return;
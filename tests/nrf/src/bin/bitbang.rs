@@ -0,0 +1,96 @@
+// required-features: nrf52840
+#![no_std]
+#![no_main]
+
+#[path = "../common.rs"]
+mod common;
+
+use defmt::{assert, assert_eq, info, unwrap};
+use embassy_embedded_hal::SetConfig;
+use embassy_embedded_hal::bitbang::{i2c, spi};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_nrf::gpio::{Flex, Input, Level, Output, OutputDrive, Pull};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_async::i2c::I2c as _;
+use embedded_hal_async::spi::{MODE_0, MODE_1, MODE_2, MODE_3, SpiBus};
+use {defmt_rtt as _, panic_probe as _};
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let mut p = embassy_nrf::init(Default::default());
+
+    // SPI, with MOSI looped back to MISO through PIN_A/PIN_B.
+    {
+        let sck = Output::new(peri!(p, PIN_X).reborrow(), Level::Low, OutputDrive::Standard);
+        let mosi = Output::new(peri!(p, PIN_A).reborrow(), Level::Low, OutputDrive::Standard);
+        let miso = Input::new(peri!(p, PIN_B).reborrow(), Pull::None);
+        let mut spi = spi::Spi::new(sck, mosi, miso, spi::Config::default());
+
+        for mode in [MODE_0, MODE_1, MODE_2, MODE_3] {
+            let mut config = spi::Config::default();
+            config.mode = mode;
+            unwrap!(spi.set_config(&config));
+
+            let mut buf = [0xA5, 0x3C, 0xFF, 0x00, 0x81];
+            unwrap!(spi.transfer_in_place(&mut buf).await);
+            assert_eq!(buf, [0xA5, 0x3C, 0xFF, 0x00, 0x81]);
+        }
+
+        // 4 ticks of the 32768 Hz RTC per half period, 64 per byte.
+        let mut config = spi::Config::default();
+        config.frequency = 4096;
+        unwrap!(spi.set_config(&config));
+        let mut buf = [0x55; 32];
+        let start = Instant::now();
+        unwrap!(spi.transfer_in_place(&mut buf).await);
+        let ticks = start.elapsed().as_ticks();
+        assert_eq!(buf, [0x55; 32]);
+        info!("32 bytes at 4096 Hz: {} ticks, {} Hz", ticks, 32 * 8 * 32_768 / ticks);
+        assert!(ticks >= 32 * 64 && ticks <= 32 * 64 + 64);
+    }
+
+    // I2C without a device, SCL looped back through PIN_A/PIN_B, where PIN_B stands in
+    // for a device stretching the clock.
+    {
+        let mut sda = Flex::new(peri!(p, PIN_X).reborrow());
+        sda.set_high();
+        sda.set_as_input_output(Pull::Up, OutputDrive::Standard0Disconnect1);
+        let mut scl = Flex::new(peri!(p, PIN_A).reborrow());
+        scl.set_high();
+        scl.set_as_input_output(Pull::Up, OutputDrive::Standard0Disconnect1);
+        let mut stretch = Output::new(
+            peri!(p, PIN_B).reborrow(),
+            Level::High,
+            OutputDrive::Standard0Disconnect1,
+        );
+
+        let mut config = i2c::Config::default();
+        config.stretch_timeout = Duration::from_millis(5);
+        let mut i2c = i2c::I2c::new(sda, scl, config);
+
+        assert_eq!(i2c.write(0x42, &[]).await, Err(i2c::Error::AddressNack));
+
+        // Hold SCL low for 2 ms at the start.
+        stretch.set_low();
+        let start = Instant::now();
+        let (res, _) = join(i2c.write(0x42, &[]), async {
+            Timer::after_millis(2).await;
+            stretch.set_high();
+        })
+        .await;
+        assert_eq!(res, Err(i2c::Error::AddressNack));
+        assert!(start.elapsed().as_millis() >= 2);
+
+        stretch.set_low();
+        assert_eq!(i2c.write(0x42, &[]).await, Err(i2c::Error::Timeout));
+        stretch.set_high();
+
+        let (sda, scl) = i2c.release();
+        assert!(sda.is_high());
+        assert!(scl.is_high());
+    }
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
@@ -0,0 +1,108 @@
+// required-features: easydma
+#![no_std]
+#![no_main]
+
+#[path = "../common.rs"]
+mod common;
+
+use defmt::{panic, *};
+use embassy_executor::Spawner;
+use embassy_nrf::buffered_uarte::{self, BufferedUarte};
+use embassy_nrf::{peripherals, uarte};
+use embassy_time::Timer;
+use embedded_io_async::Write;
+use {defmt_rtt as _, panic_probe as _};
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let mut config = uarte::Config::default();
+    config.parity = uarte::Parity::EXCLUDED;
+    config.baudrate = uarte::Baudrate::BAUD115200;
+
+    let mut tx_buffer = [0u8; 256];
+    let mut rx_buffer = [0u8; 256];
+
+    let mut u = BufferedUarte::new(
+        peri!(p, UART0),
+        p.TIMER0,
+        p.PPI_CH0,
+        p.PPI_CH1,
+        p.PPI_GROUP0,
+        peri!(p, PIN_A),
+        peri!(p, PIN_B),
+        irqs!(UART0_BUFFERED),
+        config.clone(),
+        &mut rx_buffer,
+        &mut tx_buffer,
+    );
+
+    info!("uarte initialized!");
+
+    // Bytes left unread in the RX buffer across each baud rate change. They must be kept, and the
+    // reception must resume right after them.
+    const LEFT: usize = 37;
+
+    let mut sent = 0;
+    let mut received = 0;
+    for baudrate in [
+        uarte::Baudrate::BAUD1M,
+        uarte::Baudrate::BAUD9600,
+        uarte::Baudrate::BAUD115200,
+        uarte::Baudrate::BAUD1M,
+        uarte::Baudrate::BAUD115200,
+    ] {
+        info!("baudrate {}", baudrate.to_bits());
+        unwrap!(u.set_baudrate(baudrate));
+
+        let mut buf = [0; 100];
+        for (j, b) in buf.iter_mut().enumerate() {
+            *b = (sent + j) as u8;
+        }
+        unwrap!(u.write_all(&buf).await);
+        unwrap!(u.flush().await);
+        sent += buf.len();
+
+        // Changing the rate while data is waiting to be transmitted must be refused.
+        let mut buf = [0; 50];
+        for (j, b) in buf.iter_mut().enumerate() {
+            *b = (sent + j) as u8;
+        }
+        unwrap!(u.write_all(&buf).await);
+        if u.set_baudrate(baudrate) != Err(buffered_uarte::Error::Busy) {
+            panic!("baud rate changed with data waiting to be transmitted");
+        }
+        unwrap!(u.flush().await);
+        sent += buf.len();
+
+        // 150 bytes take 156 ms at 9600 baud.
+        Timer::after_millis(200).await;
+
+        while received < sent - LEFT {
+            let buf = unwrap!(u.fill_buf().await);
+            let n = buf.len().min(sent - LEFT - received);
+            for &b in &buf[..n] {
+                if b != received as u8 {
+                    panic!("mismatch {} vs {}, index {}", b, received as u8, received);
+                }
+                received += 1;
+            }
+            u.consume(n);
+        }
+    }
+
+    while received < sent {
+        let buf = unwrap!(u.fill_buf().await);
+        let n = buf.len().min(sent - received);
+        for &b in &buf[..n] {
+            if b != received as u8 {
+                panic!("mismatch {} vs {}, index {}", b, received as u8, received);
+            }
+            received += 1;
+        }
+        u.consume(n);
+    }
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
@@ -0,0 +1,66 @@
+// required-features: nrf52840
+#![no_std]
+#![no_main]
+
+#[path = "../common.rs"]
+mod common;
+
+use defmt::{assert, info};
+use embassy_executor::Spawner;
+use embassy_nrf::rng::Rng;
+use embassy_nrf::{bind_interrupts, peripherals, rng};
+use embassy_time::{Instant, Timer};
+use static_cell::StaticCell;
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    RNG => rng::InterruptHandler<peripherals::RNG>;
+});
+
+static CACHE: StaticCell<[u8; 256]> = StaticCell::new();
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let mut rng = Rng::new(p.RNG, Irqs);
+    rng.set_bias_correction(true);
+
+    // One interrupt per byte.
+    let mut buf = [0; 64];
+    let start = Instant::now();
+    rng.fill_bytes(&mut buf).await;
+    let direct = start.elapsed().as_micros();
+    assert!(buf.iter().any(|&b| b != 0));
+
+    let mut background = rng.fill_background(CACHE.init([0; 256]));
+
+    // Wait for the cache to fill up.
+    while background.available() < 256 {
+        Timer::after_millis(1).await;
+    }
+
+    // Served from the cache.
+    let mut buf = [0; 64];
+    let start = Instant::now();
+    background.read(&mut buf).await;
+    let cached = start.elapsed().as_micros();
+    assert!(buf.iter().any(|&b| b != 0));
+
+    info!("64 bytes: {} us direct, {} us cached", direct, cached);
+    assert!(cached < direct);
+
+    // More than the cache holds, which has to wait for the peripheral.
+    let mut buf = [0; 512];
+    background.read(&mut buf).await;
+    assert!(buf[256..].iter().any(|&b| b != 0));
+
+    // As used by the `rand_core` traits.
+    let mut x = [0; 8];
+    let mut y = [0; 8];
+    background.blocking_read(&mut x);
+    background.blocking_read(&mut y);
+    assert!(x != y);
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
@@ -0,0 +1,93 @@
+// required-features: nrf52840
+#![no_std]
+#![no_main]
+
+#[path = "../common.rs"]
+mod common;
+
+use defmt::{assert, assert_eq, info, unwrap};
+use embassy_embedded_hal::flash::spi_nor::{EraseType, SpiNor};
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice;
+use embassy_executor::Spawner;
+use embassy_nrf::gpio::{Level, Output, OutputDrive};
+use embassy_nrf::spim::{self, Spim};
+use embassy_nrf::{bind_interrupts, peripherals};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::Instant;
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    SPIM3 => spim::InterruptHandler<peripherals::SPI3>;
+});
+
+// From 28 kB to 132 kB: a 4 kB, a 32 kB, a 64 kB and a 4 kB erase.
+const START: u32 = 0x7000;
+const END: u32 = 0x21000;
+
+// The MX25R6435F of the nRF52840-DK, on the QSPI pins, driven by SPIM3.
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    let mut config = spim::Config::default();
+    config.frequency = spim::Frequency::M8;
+    let spim = Spim::new(p.SPI3, Irqs, p.P0_19, p.P0_21, p.P0_20, config);
+    let cs = Output::new(p.P0_17, Level::High, OutputDrive::Standard);
+    // WP# and HOLD#, as the chip is in SPI mode.
+    let _wp = Output::new(p.P0_22, Level::High, OutputDrive::Standard);
+    let _hold = Output::new(p.P0_23, Level::High, OutputDrive::Standard);
+    let bus = Mutex::<NoopRawMutex, _>::new(spim);
+    let spi = SpiDevice::new(&bus, cs);
+
+    let mut flash = unwrap!(SpiNor::new(spi).await);
+    let id = flash.jedec_id();
+    info!("JEDEC ID: {}", id);
+    assert_eq!((id.manufacturer, id.memory_type, id.capacity), (0xC2, 0x28, 0x17));
+    assert_eq!(flash.capacity(), 8 * 1024 * 1024);
+    let geometry = *flash.geometry();
+    info!("geometry: {}", geometry);
+    for (size, opcode) in [(4096, 0x20), (32768, 0x52), (65536, 0xD8)] {
+        assert!(geometry.erase_types.contains(&Some(EraseType { size, opcode })));
+    }
+
+    let start = Instant::now();
+    unwrap!(flash.erase(START, END).await);
+    info!(
+        "erased {} kB in {} ms",
+        (END - START) / 1024,
+        start.elapsed().as_millis()
+    );
+
+    let mut buf = [0; 256];
+    for addr in (START..END).step_by(buf.len()) {
+        unwrap!(flash.read(addr, &mut buf).await);
+        assert!(buf.iter().all(|b| *b == 0xFF));
+    }
+
+    // Across page boundaries.
+    let mut data = [0; 600];
+    for (i, b) in data.iter_mut().enumerate() {
+        *b = (i * 7) as u8;
+    }
+    let start = Instant::now();
+    unwrap!(flash.write(START + 0xF0, &data).await);
+    info!("wrote {} bytes in {} us", data.len(), start.elapsed().as_micros());
+
+    let mut read = [0; 600];
+    unwrap!(flash.read(START + 0xF0, &mut read).await);
+    assert_eq!(read, data);
+
+    unwrap!(flash.power_down().await);
+    unwrap!(flash.wake_up().await);
+    read.fill(0);
+    unwrap!(flash.read(START + 0xF0, &mut read).await);
+    assert_eq!(read, data);
+
+    // Leave the flash erased.
+    unwrap!(flash.erase(START, END).await);
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
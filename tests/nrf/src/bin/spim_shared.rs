@@ -0,0 +1,80 @@
+// required-features: nrf52840
+#![no_std]
+#![no_main]
+
+#[path = "../common.rs"]
+mod common;
+
+use defmt::{assert, assert_eq, *};
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDeviceWithConfig;
+use embassy_executor::Spawner;
+use embassy_nrf::gpio::{Level, Output, OutputDrive};
+use embassy_nrf::spim::Spim;
+use embassy_nrf::{peripherals, spim};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Instant};
+use embedded_hal_async::spi::SpiDevice;
+use {defmt_rtt as _, panic_probe as _};
+
+// Two devices on the loopback bus, at 125 kHz and 8 MHz. Their chip selects are LED1 and LED2
+// of the nRF52840-DK.
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+    let mut config = spim::Config::default();
+    config.frequency = spim::Frequency::M1;
+    let spim = Spim::new(
+        peri!(p, SPIM0),
+        irqs!(SPIM0),
+        peri!(p, PIN_X),
+        peri!(p, PIN_A), // MISO
+        peri!(p, PIN_B), // MOSI
+        config.clone(),
+    );
+    let bus = Mutex::<NoopRawMutex, _>::new(spim);
+
+    let mut slow_config = config.clone();
+    slow_config.frequency = spim::Frequency::K125;
+    let mut slow = SpiDeviceWithConfig::new(
+        &bus,
+        Output::new(p.P0_13, Level::High, OutputDrive::Standard),
+        slow_config,
+    );
+    let mut fast_config = config;
+    fast_config.frequency = spim::Frequency::M8;
+    let mut fast = SpiDeviceWithConfig::new(
+        &bus,
+        Output::new(p.P0_14, Level::High, OutputDrive::Standard),
+        fast_config,
+    );
+
+    let mut data = [0u8; 64];
+    for (i, b) in data.iter_mut().enumerate() {
+        *b = i as u8 ^ 0x5a;
+    }
+    let mut buf = [0u8; 64];
+
+    // 512 bits take about 4 ms at 125 kHz, and 64 us at 8 MHz. Alternate between the devices,
+    // so that each applies its own frequency.
+    for _ in 0..2 {
+        buf.fill(0);
+        let start = Instant::now();
+        unwrap!(slow.transfer(&mut buf, &data).await);
+        let elapsed = start.elapsed();
+        assert_eq!(data, buf);
+        info!("slow device: {} us", elapsed.as_micros());
+        assert!(elapsed > Duration::from_millis(3));
+
+        buf.fill(0);
+        let start = Instant::now();
+        unwrap!(fast.transfer(&mut buf, &data).await);
+        let elapsed = start.elapsed();
+        assert_eq!(data, buf);
+        info!("fast device: {} us", elapsed.as_micros());
+        assert!(elapsed < Duration::from_millis(1));
+    }
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
@@ -0,0 +1,119 @@
+// required-features: nrf52840
+#![no_std]
+#![no_main]
+
+#[path = "../common.rs"]
+mod common;
+
+use defmt::{assert_eq, *};
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_nrf::twim::{self, Twim};
+use embassy_nrf::twis::{self, Command, Twis};
+use embassy_nrf::{bind_interrupts, peripherals};
+use embedded_hal_async::i2c::Operation;
+use {defmt_rtt as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    TWISPI0 => twim::InterruptHandler<peripherals::TWISPI0>;
+    TWISPI1 => twis::InterruptHandler<peripherals::TWISPI1>;
+});
+
+const ADDRESS: u8 = 0x55;
+
+// The TWIS of the same chip is the device. SDA goes through the PIN_A/PIN_B
+// loopback, and both peripherals share PIN_X as SCL.
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    let mut config = twim::Config::default();
+    config.sda_pullup = true;
+    config.scl_pullup = true;
+    let mut ram_buffer = [0; 16];
+    let mut twim = Twim::new(
+        p.TWISPI0,
+        Irqs,
+        peri!(p, PIN_A),
+        peri!(p, PIN_X),
+        config,
+        &mut ram_buffer,
+    );
+
+    let mut config = twis::Config::default();
+    config.address0 = ADDRESS;
+    config.sda_pullup = true;
+    config.scl_pullup = true;
+    let scl = unsafe { common::peris::PIN_X::steal() };
+    let mut twis = Twis::new(p.TWISPI1, Irqs, peri!(p, PIN_B), scl, config);
+
+    // Probing: an empty write sends the address only.
+    assert_eq!(
+        twim.blocking_transaction(ADDRESS + 1, &mut [Operation::Write(&[])]),
+        Err(twim::Error::AddressNack)
+    );
+    let (res, cmd) = join(twim.write(ADDRESS, &[]), twis.listen(&mut [0; 8])).await;
+    unwrap!(res);
+    assert_eq!(unwrap!(cmd), Command::Write(0));
+
+    // Consecutive writes and consecutive reads are merged, with a repeated start in between.
+    let mut rd_a = [0; 2];
+    let mut rd_b = [0; 3];
+    let mut rx = [0; 8];
+    let (res, device) = join(
+        twim.transaction(
+            ADDRESS,
+            &mut [
+                Operation::Write(&[1, 2]),
+                Operation::Write(&[3]),
+                Operation::Read(&mut rd_a),
+                Operation::Read(&mut rd_b),
+            ],
+        ),
+        async {
+            let cmd = unwrap!(twis.listen(&mut rx).await);
+            let n = unwrap!(twis.respond_to_read(&[10, 11, 12, 13, 14]).await);
+            (cmd, n)
+        },
+    )
+    .await;
+    unwrap!(res);
+    assert_eq!(device, (Command::WriteRead(3), 5));
+    assert_eq!(rx[..3], [1, 2, 3]);
+    assert_eq!(rd_a, [10, 11]);
+    assert_eq!(rd_b, [12, 13, 14]);
+
+    // Reads only.
+    let mut rd_a = [0; 1];
+    let mut rd_b = [0; 3];
+    let (res, device) = join(
+        twim.transaction(ADDRESS, &mut [Operation::Read(&mut rd_a), Operation::Read(&mut rd_b)]),
+        async {
+            let cmd = unwrap!(twis.listen(&mut rx).await);
+            let n = unwrap!(twis.respond_to_read(&[20, 21, 22, 23]).await);
+            (cmd, n)
+        },
+    )
+    .await;
+    unwrap!(res);
+    assert_eq!(device, (Command::Read, 4));
+    assert_eq!(rd_a, [20]);
+    assert_eq!(rd_b, [21, 22, 23]);
+
+    // Rejected before touching the bus.
+    let mut rd = [0; 1];
+    assert_eq!(
+        twim.transaction(ADDRESS, &mut [Operation::Read(&mut rd), Operation::Write(&[])])
+            .await,
+        Err(twim::Error::UnsupportedTransaction)
+    );
+    let (mut rd_a, mut rd_b) = ([0; 9], [0; 8]);
+    assert_eq!(
+        twim.transaction(ADDRESS, &mut [Operation::Read(&mut rd_a), Operation::Read(&mut rd_b)])
+            .await,
+        Err(twim::Error::RAMBufferTooSmall)
+    );
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
@@ -0,0 +1,93 @@
+// required-features: nrf52840
+#![no_std]
+#![no_main]
+
+#[path = "../common.rs"]
+mod common;
+
+use core::cell::RefCell;
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of, addr_of_mut};
+
+use defmt::{assert, assert_eq, info, unwrap};
+use embassy_executor::{InterruptExecutor, Spawner};
+use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pull};
+use embassy_nrf::interrupt::{InterruptExt, Priority};
+use embassy_nrf::wdt::{self, HaltConfig, SleepConfig, Watchdog};
+use embassy_nrf::{bind_interrupts, interrupt, peripherals};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+bind_interrupts!(struct Irqs {
+    WDT => wdt::InterruptHandler<peripherals::WDT>;
+});
+
+const BREADCRUMB_MAGIC: u32 = 0x5AFE_D06E;
+
+// Not initialized at startup, so it would survive the reset.
+#[unsafe(link_section = ".uninit.BREADCRUMB")]
+static mut BREADCRUMB: MaybeUninit<u32> = MaybeUninit::uninit();
+
+static SAFE_PIN: Mutex<CriticalSectionRawMutex, RefCell<Option<Output<'static>>>> = Mutex::new(RefCell::new(None));
+
+static EXECUTOR_HIGH: InterruptExecutor = InterruptExecutor::new();
+
+#[interrupt]
+unsafe fn EGU0_SWI0() {
+    unsafe { EXECUTOR_HIGH.on_interrupt() }
+}
+
+fn on_timeout() {
+    unsafe { addr_of_mut!(BREADCRUMB).write_volatile(MaybeUninit::new(BREADCRUMB_MAGIC)) };
+    SAFE_PIN.lock(|pin| {
+        if let Some(pin) = pin.borrow_mut().as_mut() {
+            pin.set_high();
+        }
+    });
+}
+
+// Teleprobe runs the test from RAM, so nothing of it is left to run after the
+// reset. Instead, check what the callback left behind from a task woken by the
+// timeout, in the 61 microseconds before the reset.
+#[embassy_executor::task]
+async fn wait_for_timeout(mut watchdog: Watchdog, input: Input<'static>) {
+    watchdog.wait_timeout().await;
+
+    let latched = input.is_high();
+    let breadcrumb = unsafe { addr_of!(BREADCRUMB).read_volatile().assume_init() };
+    assert!(latched);
+    assert_eq!(breadcrumb, BREADCRUMB_MAGIC);
+
+    info!("Test OK");
+    cortex_m::asm::bkpt();
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_nrf::init(Default::default());
+
+    unsafe { addr_of_mut!(BREADCRUMB).write_volatile(MaybeUninit::new(0)) };
+
+    let input = Input::new(peri!(p, PIN_B), Pull::Down);
+    let output = Output::new(peri!(p, PIN_A), Level::Low, OutputDrive::Standard);
+    SAFE_PIN.lock(|pin| *pin.borrow_mut() = Some(output));
+    embassy_time::Timer::after_millis(10).await;
+    assert!(input.is_low());
+
+    wdt::set_timeout_callback::<peripherals::WDT>(on_timeout);
+
+    interrupt::EGU0_SWI0.set_priority(Priority::P1);
+    let spawner = EXECUTOR_HIGH.start(interrupt::EGU0_SWI0);
+
+    // The debugger halts the CPU at the breakpoint, the watchdog must not reset it meanwhile.
+    let config = wdt::Config::const_new(wdt::ticks_from_hz(100), SleepConfig::RUN, HaltConfig::PAUSE);
+    let (watchdog, _handles) = match Watchdog::try_new_with_interrupt::<_, 1>(p.WDT, Irqs, config, Priority::P0) {
+        Ok(x) => x,
+        Err(_) => defmt::panic!("watchdog already running with a different config"),
+    };
+    info!("watchdog started, not petting it");
+
+    spawner.spawn(unwrap!(wait_for_timeout(watchdog, input)));
+
+    core::future::pending::<()>().await;
+}